@@ -1,15 +1,24 @@
 use bevy::{
-    asset::{io::Reader, Asset, AssetLoader, AsyncReadExt, LoadContext},
+    asset::Handle,
+    asset::{
+        io::Reader, Asset, AssetLoader, AsyncReadExt, LoadContext, LoadDirectError,
+        ParseAssetPathError,
+    },
+    log::{error, info, warn},
+    math::{Quat, Vec3},
     reflect::Reflect,
+    transform::components::Transform,
     utils::{default, thiserror::Error, BoxedFuture, HashSet},
 };
-use serde::{Deserialize, Serialize};
-use std::ops::Deref;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{ops::Deref, path::Path};
 
 use crate::{
-    modifier::{Modifier, RenderModifier},
+    modifier::{BoxedModifier, Modifier, RenderModifier, TrailModifier},
+    process::PregeneratedShaders,
+    validate::DiagnosticSeverity,
     ExprHandle, GroupedModifier, ModifierContext, Module, ParticleGroupSet, ParticleLayout,
-    Property, PropertyLayout, SimulationSpace, Spawner,
+    Property, PropertyLayout, SimulationSpace, Spawner, SpawnerParam, ToWgslString, Value,
 };
 
 /// Type of motion integration applied to the particles of a system.
@@ -37,6 +46,18 @@ pub enum MotionIntegration {
     PostUpdate,
 }
 
+/// Sub-stepping of the particle update for fast-moving particles.
+///
+/// See [`EffectAsset::with_motion_substeps()`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct MotionSubsteps {
+    /// Maximum distance a particle travels during a single sub-step, in
+    /// simulation space units.
+    pub max_step_distance: f32,
+    /// Maximum number of sub-steps per simulation update.
+    pub max_substeps: u32,
+}
+
 /// Simulation condition for an effect.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
 pub enum SimulationCondition {
@@ -53,8 +74,9 @@ pub enum SimulationCondition {
     /// [`CompiledParticleEffect`], even when it's not visible and even when
     /// that variant is selected.
     ///
-    /// Note also that AABB culling is not currently available. Only boolean
-    /// ON/OFF visibility is used.
+    /// Note also that AABB culling is only available for effects computing
+    /// their bounds on GPU, see [`EffectAsset::with_gpu_bounds()`]. Otherwise
+    /// only boolean ON/OFF visibility is used.
     ///
     /// [`Visibility`]: bevy::render::view::Visibility
     /// [`InheritedVisibility`]: bevy::render::view::InheritedVisibility
@@ -162,6 +184,211 @@ pub enum AlphaMode {
     Mask(ExprHandle),
 }
 
+/// Surface properties of the particles written into the G-buffer of the Bevy
+/// deferred renderer.
+///
+/// With the deferred renderer, the opaque geometry is first written into a
+/// G-buffer, then lit in a single full-screen pass. By default particle effects
+/// are rendered in the forward passes, after the deferred lighting, so they're
+/// never lit. Effects rendered with [`AlphaMode::Mask`] can instead be written
+/// into the G-buffer with these surface properties, to be lit and shadowed like
+/// the rest of the opaque geometry. See [`EffectAsset::with_deferred()`] for
+/// details.
+///
+/// The properties match the ones of the Bevy `StandardMaterial`. The base
+/// color of each particle is its color, after all render modifiers applied.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct DeferredMaterial {
+    /// Perceived roughness of the particle surface, in \[0.089:1\].
+    pub perceptual_roughness: f32,
+    /// Metallic-ness of the particle surface, in \[0:1\].
+    pub metallic: f32,
+    /// Specular intensity of non-metallic surfaces, in \[0:1\].
+    pub reflectance: f32,
+    /// Write the particle color as emissive instead of being lit.
+    ///
+    /// This is useful for particles which only need to occlude and be
+    /// occluded like opaque geometry, and be seen by the screen-space effects
+    /// reading the G-buffer.
+    pub unlit: bool,
+}
+
+impl Default for DeferredMaterial {
+    fn default() -> Self {
+        // Same defaults as the Bevy StandardMaterial
+        Self {
+            perceptual_roughness: 0.5,
+            metallic: 0.,
+            reflectance: 0.5,
+            unlit: false,
+        }
+    }
+}
+
+impl DeferredMaterial {
+    /// Generate the WGSL code of the material, packed in a `vec4<f32>` with
+    /// the unlit flag in the last component.
+    pub(crate) fn to_wgsl_string(self) -> String {
+        format!(
+            "vec4<f32>({}, {}, {}, {})",
+            self.perceptual_roughness.clamp(0.089, 1.).to_wgsl_string(),
+            self.metallic.clamp(0., 1.).to_wgsl_string(),
+            self.reflectance.clamp(0., 1.).to_wgsl_string(),
+            if self.unlit { "1.0" } else { "0.0" },
+        )
+    }
+}
+
+/// Soft shadow drawn on a plane under each particle.
+///
+/// Blob shadows are a cheap alternative to casting the particles into the
+/// shadow maps of the lights, for stylized games. Each particle draws a dark
+/// soft disc where its center projects along [`direction`] onto the plane,
+/// stretched into an ellipse when the direction is oblique to the plane. See
+/// [`EffectAsset::with_blob_shadow()`] for details.
+///
+/// [`direction`]: BlobShadow::direction
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct BlobShadow {
+    /// Normal of the plane receiving the shadows, in world space.
+    pub plane_normal: Vec3,
+    /// Signed distance from the origin to the plane along its normal, in
+    /// world space.
+    pub plane_distance: f32,
+    /// Direction the particles are projected along onto the plane, in world
+    /// space, like the direction of a directional light.
+    pub direction: Vec3,
+    /// Radius of the shadow, relative to half the size of the particle.
+    pub scale: f32,
+    /// Opacity of the center of the shadow, in \[0:1\]. This is multiplied by
+    /// the alpha of the particle.
+    pub opacity: f32,
+    /// Fraction of the radius over which the shadow fades out toward its edge,
+    /// in \[0:1\].
+    pub softness: f32,
+}
+
+impl Default for BlobShadow {
+    fn default() -> Self {
+        Self {
+            plane_normal: Vec3::Y,
+            plane_distance: 0.,
+            direction: Vec3::NEG_Y,
+            scale: 1.,
+            opacity: 0.5,
+            softness: 0.5,
+        }
+    }
+}
+
+impl BlobShadow {
+    /// Generate the WGSL code declaring the shadow parameters as constants.
+    pub(crate) fn to_wgsl_string(self) -> String {
+        let normal = self.plane_normal.try_normalize().unwrap_or(Vec3::Y);
+        let direction = self.direction.try_normalize().unwrap_or(-normal);
+        format!(
+            "const blob_shadow_normal = {};\nconst blob_shadow_distance = {};\nconst blob_shadow_direction = {};\nconst blob_shadow_scale = {};\nconst blob_shadow_opacity = {};\nconst blob_shadow_softness = {};\n",
+            normal.to_wgsl_string(),
+            self.plane_distance.to_wgsl_string(),
+            direction.to_wgsl_string(),
+            self.scale.max(0.).to_wgsl_string(),
+            self.opacity.clamp(0., 1.).to_wgsl_string(),
+            self.softness.clamp(1e-3, 1.).to_wgsl_string(),
+        )
+    }
+}
+
+/// Additional emitter of an [`EffectAsset`].
+///
+/// An emitter spawns particles into a single particle group, according to its
+/// own [`Spawner`]. The particles it spawns are initialized by the init
+/// modifiers targeting that group. See [`EffectAsset::with_emitter()`] for
+/// details.
+///
+/// [`EffectAsset::with_emitter()`]: crate::EffectAsset::with_emitter
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct EffectEmitter {
+    /// Index of the particle group the emitter spawns particles into.
+    pub group_index: u32,
+    /// Spawner defining when particles are emitted.
+    pub spawner: Spawner,
+}
+
+/// Serialization of a [`Transform`], which doesn't implement serde traits.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Transform")]
+struct TransformDef {
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+
+/// Child effect of an [`EffectAsset`], composed by reference.
+///
+/// Each instance of the parent effect spawns an instance of the child effect
+/// as a child entity, with a transform relative to the parent instance. See
+/// [`EffectAsset::with_child()`] for details.
+///
+/// [`EffectAsset::with_child()`]: crate::EffectAsset::with_child
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct EffectChild {
+    /// Path of the child effect asset, relative to the parent asset.
+    ///
+    /// This is only used when the parent asset is loaded from a file, to load
+    /// the child asset into [`handle`].
+    ///
+    /// [`handle`]: crate::EffectChild::handle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Handle of the child effect asset.
+    #[serde(skip)]
+    pub handle: Handle<EffectAsset>,
+    /// Transform of the child effect instance, relative to the parent one.
+    #[serde(default, with = "TransformDef")]
+    pub transform: Transform,
+    /// Delay after which the child effect instance is spawned, in seconds of
+    /// simulation time of the parent instance.
+    #[serde(default)]
+    pub delay: f32,
+}
+
+impl EffectChild {
+    /// Create a new child effect from the handle of its asset.
+    pub fn new(handle: Handle<EffectAsset>) -> Self {
+        Self {
+            handle,
+            ..default()
+        }
+    }
+
+    /// Create a new child effect from the path of its asset, relative to the
+    /// parent asset.
+    ///
+    /// This is only useful to create assets which are saved to file, as the
+    /// child asset is loaded from that path only when the parent asset is
+    /// loaded itself.
+    pub fn from_path(path: impl Into<String>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..default()
+        }
+    }
+
+    /// Set the transform of the child effect instance, relative to the parent
+    /// one.
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Set the delay after which the child effect instance is spawned, in
+    /// seconds.
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay.max(0.);
+        self
+    }
+}
+
 /// Asset describing a visual effect.
 ///
 /// The effect can be instanciated with a [`ParticleEffect`] component, or a
@@ -172,6 +399,17 @@ pub enum AlphaMode {
 #[derive(Asset, Default, Clone, Reflect, Serialize, Deserialize)]
 #[reflect(from_reflect = false)]
 pub struct EffectAsset {
+    /// Version of the serialization format the asset was deserialized from.
+    ///
+    /// This is always serialized as the current [`FORMAT_VERSION`], and is
+    /// zero for assets saved before the format was versioned. See
+    /// [`migrate()`] for details.
+    ///
+    /// [`FORMAT_VERSION`]: crate::EffectAsset::FORMAT_VERSION
+    /// [`migrate()`]: crate::EffectAsset::migrate
+    #[serde(default = "FormatVersion::unversioned")]
+    #[reflect(ignore)]
+    format_version: FormatVersion,
     /// Display name of the effect.
     ///
     /// This has no internal use, and is mostly for the user to identify an
@@ -195,7 +433,12 @@ pub struct EffectAsset {
     /// with different Z values cannot be batched together, which may
     /// negatively affect performance.
     ///
+    /// The layer is shared by all particles of the effect, unless they're split
+    /// into several layers by a [`ZLayer2dModifier`].
+    ///
     /// Ignored for 3D rendering.
+    ///
+    /// [`ZLayer2dModifier`]: crate::ZLayer2dModifier
     pub z_layer_2d: f32,
     /// Particle simulation space.
     pub simulation_space: SimulationSpace,
@@ -219,8 +462,192 @@ pub struct EffectAsset {
     module: Module,
     /// Alpha mode.
     pub alpha_mode: AlphaMode,
+    /// Pre-warming duration, in seconds.
+    ///
+    /// When non-zero, the effect is fast-forwarded by that duration when it
+    /// activates, so that it appears already in a steady state. This is mostly
+    /// useful for looping effects like a waterfall or a campfire, which would
+    /// otherwise visibly "start" when a level loads. See [`with_prewarm()`]
+    /// for details.
+    ///
+    /// [`with_prewarm()`]: crate::EffectAsset::with_prewarm
+    #[serde(default)]
+    pub prewarm: f32,
+    /// Maximum number of particles which can be injected from the CPU per
+    /// frame, or zero to disable injection.
+    ///
+    /// See [`with_injection_capacity()`] for details.
+    ///
+    /// [`with_injection_capacity()`]: crate::EffectAsset::with_injection_capacity
+    #[serde(default)]
+    pub injection_capacity: u32,
+    /// Compute the bounds of the effect instances on GPU, for culling.
+    ///
+    /// See [`with_gpu_bounds()`] for details.
+    ///
+    /// [`with_gpu_bounds()`]: crate::EffectAsset::with_gpu_bounds
+    #[serde(default)]
+    pub gpu_bounds: bool,
+    /// Bounding radius of the particles culled individually against the
+    /// frustum of each view, or `None` to disable per-particle culling.
+    ///
+    /// See [`with_particle_culling()`] for details.
+    ///
+    /// [`with_particle_culling()`]: crate::EffectAsset::with_particle_culling
+    #[serde(default)]
+    pub particle_culling: Option<f32>,
+    /// Sub-stepping of the particle update for fast-moving particles, or
+    /// `None` to update the particles once per simulation update.
+    ///
+    /// See [`with_motion_substeps()`] for details.
+    ///
+    /// [`with_motion_substeps()`]: crate::EffectAsset::with_motion_substeps
+    #[serde(default)]
+    pub motion_substeps: Option<MotionSubsteps>,
+    /// Names of the particle groups, by group index, or empty if the groups
+    /// are not named.
+    ///
+    /// See [`with_group_names()`] for details.
+    ///
+    /// [`with_group_names()`]: crate::EffectAsset::with_group_names
+    #[serde(default)]
+    group_names: Vec<String>,
+    /// Alpha mode overrides per particle group, by group index.
+    ///
+    /// See [`with_group_alpha_mode()`] for details.
+    ///
+    /// [`with_group_alpha_mode()`]: crate::EffectAsset::with_group_alpha_mode
+    #[serde(default)]
+    group_alpha_modes: Vec<Option<AlphaMode>>,
+    /// Surface properties of the particles written into the G-buffer of the
+    /// deferred renderer, or `None` to render them in the forward passes.
+    ///
+    /// See [`with_deferred()`] for details.
+    ///
+    /// [`with_deferred()`]: crate::EffectAsset::with_deferred
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deferred: Option<DeferredMaterial>,
+    /// Apply the fog of the view to the particles.
+    ///
+    /// See [`with_fog()`] for details.
+    ///
+    /// [`with_fog()`]: crate::EffectAsset::with_fog
+    #[serde(default)]
+    pub fog: bool,
+    /// Soft shadow drawn on a plane under each particle, or `None` to draw no
+    /// shadow.
+    ///
+    /// See [`with_blob_shadow()`] for details.
+    ///
+    /// [`with_blob_shadow()`]: crate::EffectAsset::with_blob_shadow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_shadow: Option<BlobShadow>,
+    /// Allow toggling the init and update modifiers per effect instance.
+    ///
+    /// See [`with_modifier_toggles()`] for details.
+    ///
+    /// [`with_modifier_toggles()`]: crate::EffectAsset::with_modifier_toggles
+    #[serde(default)]
+    pub modifier_toggles: bool,
+    /// Additional emitters, each spawning into a particle group with its own
+    /// spawner.
+    ///
+    /// See [`with_emitter()`] for details.
+    ///
+    /// [`with_emitter()`]: crate::EffectAsset::with_emitter
+    #[serde(default)]
+    emitters: Vec<EffectEmitter>,
+    /// Child effects composed by reference, spawned along with each instance of
+    /// the effect.
+    ///
+    /// See [`with_child()`] for details.
+    ///
+    /// [`with_child()`]: crate::EffectAsset::with_child
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<EffectChild>,
+    /// Parameters of the [`spawner`] driven by a property, and the name of
+    /// that property.
+    ///
+    /// See [`with_spawner_property()`] for details.
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    /// [`with_spawner_property()`]: crate::EffectAsset::with_spawner_property
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    spawner_properties: Vec<(SpawnerParam, String)>,
+    /// Properties of the effect, for assets saved before the properties moved
+    /// to the [`Module`]. This is only used by [`migrate()`].
+    ///
+    /// [`migrate()`]: crate::EffectAsset::migrate
+    #[serde(default, rename = "properties", skip_serializing)]
+    #[reflect(ignore)]
+    legacy_properties: Vec<Property>,
+    /// Shaders generated ahead of time when the asset was processed, if any.
+    /// See [`pregenerate_shaders()`].
+    ///
+    /// [`pregenerate_shaders()`]: crate::EffectAsset::pregenerate_shaders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[reflect(ignore)]
+    pregenerated_shaders: Option<PregeneratedShaders>,
 }
 
+/// Version of the serialization format of an [`EffectAsset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+struct FormatVersion(u32);
+
+impl FormatVersion {
+    /// Version of the assets saved before the format was versioned.
+    fn unversioned() -> Self {
+        Self(0)
+    }
+}
+
+impl Default for FormatVersion {
+    fn default() -> Self {
+        Self(EffectAsset::FORMAT_VERSION)
+    }
+}
+
+impl Serialize for FormatVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Assets are always saved with the current version, whatever the version
+        // they were deserialized from
+        serializer.serialize_u32(EffectAsset::FORMAT_VERSION)
+    }
+}
+
+/// Error migrating an [`EffectAsset`] with [`EffectAsset::migrate()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum EffectAssetMigrationError {
+    /// The asset was saved with a more recent format version than the one
+    /// this version of Hanabi supports.
+    #[error("The effect asset format version {version} is more recent than the supported version {supported}. Upgrade bevy_hanabi to load it.")]
+    UnsupportedVersion {
+        /// Format version of the asset.
+        version: u32,
+        /// Most recent format version supported.
+        supported: u32,
+    },
+}
+
+/// Migration of an [`EffectAsset`] from a format version to the next one.
+type Migration = fn(&mut EffectAsset);
+
+/// Migrations of [`EffectAsset`], where the migration at index `N` migrates an
+/// asset from format version `N` to version `N + 1`.
+const MIGRATIONS: [Migration; EffectAsset::FORMAT_VERSION as usize] = [
+    // 0 -> 1: the properties moved from the asset to its module
+    |asset| {
+        for property in std::mem::take(&mut asset.legacy_properties) {
+            if asset.module.get_property_by_name(property.name()).is_none() {
+                asset
+                    .module
+                    .add_property(property.name(), *property.default_value());
+            }
+        }
+    },
+];
+
 impl EffectAsset {
     /// Create a new effect asset.
     ///
@@ -234,9 +661,10 @@ impl EffectAsset {
     /// consumption of the effect, which will allocate some buffers to store
     /// that many particles for as long as the effect exists. The capacities of
     /// an effect are immutable. See also [`capacities()`] for more details.
-    /// - The [`Spawner`], which defines when particles are emitted. All
-    /// spawners spawn particles into group 0. (To add particles to other
-    /// groups, use the [`crate::modifier::clone::CloneModifier`].)
+    /// - The [`Spawner`], which defines when particles are emitted. This
+    /// spawner spawns particles into group 0. (To add particles to other
+    /// groups, add more emitters with [`with_emitter()`], or use the
+    /// [`crate::modifier::clone::CloneModifier`].)
     ///
     /// Additionally, if any modifier added to this effect uses some [`Expr`] to
     /// customize its behavior, then those [`Expr`] are stored into a [`Module`]
@@ -273,6 +701,7 @@ impl EffectAsset {
     /// ```
     ///
     /// [`capacities()`]: crate::EffectAsset::capacities
+    /// [`with_emitter()`]: crate::EffectAsset::with_emitter
     /// [`Expr`]: crate::graph::expr::Expr
     pub fn new(capacities: Vec<u32>, spawner: Spawner, module: Module) -> Self {
         Self {
@@ -283,6 +712,59 @@ impl EffectAsset {
         }
     }
 
+    /// Current version of the serialization format of effect assets.
+    ///
+    /// The version is incremented each time the format changes in a way
+    /// which requires a migration of the assets saved with a previous version.
+    /// See [`migrate()`] for details.
+    ///
+    /// [`migrate()`]: crate::EffectAsset::migrate
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Get the version of the serialization format the asset was deserialized
+    /// from.
+    ///
+    /// This is the [`FORMAT_VERSION`] for assets created in code or already
+    /// migrated, and zero for assets saved before the format was versioned.
+    ///
+    /// [`FORMAT_VERSION`]: crate::EffectAsset::FORMAT_VERSION
+    pub fn format_version(&self) -> u32 {
+        self.format_version.0
+    }
+
+    /// Migrate an asset deserialized from a previous version of the
+    /// serialization format to the current [`FORMAT_VERSION`].
+    ///
+    /// Each change of the format which can't be handled by the deserialization
+    /// alone, like a setting moving to a different place, comes with a
+    /// migration applied by this method, so assets saved with a previous
+    /// version of Hanabi keep working instead of silently losing some of their
+    /// settings. The migrations are applied in sequence from the version the
+    /// asset was saved with, and the asset is then serialized with the current
+    /// version.
+    ///
+    /// This is called automatically when loading a `.effect` file, so only
+    /// needs to be called after deserializing an asset manually. Calling it
+    /// on an asset which is already up to date does nothing. Returns the
+    /// version the asset was migrated from, or an error if the asset was saved
+    /// with a more recent version than this version of Hanabi supports.
+    ///
+    /// [`FORMAT_VERSION`]: crate::EffectAsset::FORMAT_VERSION
+    pub fn migrate(&mut self) -> Result<u32, EffectAssetMigrationError> {
+        let version = self.format_version.0;
+        if version > Self::FORMAT_VERSION {
+            return Err(EffectAssetMigrationError::UnsupportedVersion {
+                version,
+                supported: Self::FORMAT_VERSION,
+            });
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(self);
+        }
+        self.format_version = FormatVersion::default();
+        Ok(version)
+    }
+
     /// Get the capacities of the effect, in number of particles per group.
     ///
     /// For example, if this function returns `&[256, 512]`, then this effect
@@ -314,6 +796,38 @@ impl EffectAsset {
         &self.module
     }
 
+    /// Get mutable access to the expression module of this effect, to add new
+    /// expressions.
+    pub(crate) fn module_mut(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    /// Get the shaders generated ahead of time when the asset was processed,
+    /// if any.
+    pub(crate) fn pregenerated_shaders(&self) -> Option<&PregeneratedShaders> {
+        self.pregenerated_shaders.as_ref()
+    }
+
+    /// Set the shaders generated ahead of time.
+    pub(crate) fn set_pregenerated_shaders(&mut self, shaders: Option<PregeneratedShaders>) {
+        self.pregenerated_shaders = shaders;
+    }
+
+    /// Get mutable access to the module and to all the modifiers of this
+    /// effect at once, for editing them together.
+    #[cfg(feature = "editor")]
+    pub(crate) fn module_and_modifiers_mut(
+        &mut self,
+    ) -> (&mut Module, impl Iterator<Item = &mut GroupedModifier>) {
+        (
+            &mut self.module,
+            self.init_modifiers
+                .iter_mut()
+                .chain(self.update_modifiers.iter_mut())
+                .chain(self.render_modifiers.iter_mut()),
+        )
+    }
+
     /// Set the effect name.
     ///
     /// The effect name is used when serializing the effect.
@@ -322,6 +836,101 @@ impl EffectAsset {
         self
     }
 
+    /// Set the names of the particle groups, in group index order.
+    ///
+    /// Named groups can be referenced by name instead of by index, with
+    /// [`group_set()`] or [`CloneModifier::with_destination_group_name()`],
+    /// so that reordering groups doesn't silently break the effect. Names are
+    /// also used in diagnostic messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of names is not equal to the number of groups, as
+    /// defined by the [`capacities()`], or if two groups have the same name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// let spawner = Spawner::once(32.0.into(), true);
+    /// let asset = EffectAsset::new(vec![256, 1024], spawner, Module::default())
+    ///     .with_group_names(["rain", "splash"]);
+    /// assert_eq!(asset.group_index("splash"), Some(1));
+    /// ```
+    ///
+    /// [`group_set()`]: crate::EffectAsset::group_set
+    /// [`CloneModifier::with_destination_group_name()`]: crate::CloneModifier::with_destination_group_name
+    /// [`capacities()`]: crate::EffectAsset::capacities
+    pub fn with_group_names(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        assert_eq!(
+            names.len(),
+            self.capacities.len(),
+            "Effect '{}' has {} groups but {} group names were specified.",
+            self.name,
+            self.capacities.len(),
+            names.len()
+        );
+        for (index, name) in names.iter().enumerate() {
+            assert!(
+                !names[..index].contains(name),
+                "Duplicate group name '{}' in effect '{}'.",
+                name,
+                self.name
+            );
+        }
+        self.group_names = names;
+        self
+    }
+
+    /// Get the names of the particle groups, in group index order.
+    ///
+    /// This is empty if the groups were not named with
+    /// [`with_group_names()`].
+    ///
+    /// [`with_group_names()`]: crate::EffectAsset::with_group_names
+    pub fn group_names(&self) -> &[String] {
+        &self.group_names
+    }
+
+    /// Get the index of the particle group with the given name, if any.
+    pub fn group_index(&self, name: &str) -> Option<u32> {
+        self.group_names
+            .iter()
+            .position(|group_name| group_name == name)
+            .map(|index| index as u32)
+    }
+
+    /// Get the name of the particle group with the given index, if any.
+    pub fn group_name(&self, group_index: u32) -> Option<&str> {
+        self.group_names
+            .get(group_index as usize)
+            .map(String::as_str)
+    }
+
+    /// Build a set of particle groups from their names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the names is not the name of a group of this effect.
+    pub fn group_set(&self, names: &[&str]) -> ParticleGroupSet {
+        names.iter().fold(ParticleGroupSet::none(), |set, name| {
+            let Some(group_index) = self.group_index(name) else {
+                panic!("Unknown group '{}' in effect '{}'.", name, self.name);
+            };
+            set.with_group(group_index)
+        })
+    }
+
+    /// Get a label identifying a particle group in diagnostic messages,
+    /// containing its index and its name if any.
+    pub(crate) fn group_label(&self, group_index: u32) -> String {
+        match self.group_name(group_index) {
+            Some(name) => format!("#{} '{}'", group_index, name),
+            None => format!("#{}", group_index),
+        }
+    }
+
     /// Set the effect's simulation condition.
     pub fn with_simulation_condition(mut self, simulation_condition: SimulationCondition) -> Self {
         self.simulation_condition = simulation_condition;
@@ -340,6 +949,447 @@ impl EffectAsset {
         self
     }
 
+    /// Set the alpha mode of a single particle group, overriding the alpha
+    /// mode of the effect for that group.
+    ///
+    /// Together with assigning a different [`ParticleTextureModifier`] and
+    /// [`FlipbookModifier`] to each group with [`render_groups()`], this
+    /// allows each group to look completely different, for example rendering
+    /// alpha-masked raindrops and alpha-blended splashes from a single effect.
+    ///
+    /// [`ParticleTextureModifier`]: crate::ParticleTextureModifier
+    /// [`FlipbookModifier`]: crate::FlipbookModifier
+    /// [`render_groups()`]: crate::EffectAsset::render_groups
+    pub fn with_group_alpha_mode(mut self, group_index: u32, alpha_mode: AlphaMode) -> Self {
+        let group_index = group_index as usize;
+        if self.group_alpha_modes.len() <= group_index {
+            self.group_alpha_modes.resize(group_index + 1, None);
+        }
+        self.group_alpha_modes[group_index] = Some(alpha_mode);
+        self
+    }
+
+    /// Write the particles into the G-buffer of the deferred renderer.
+    ///
+    /// In 3D views using the Bevy deferred renderer, that is with a
+    /// `DeferredPrepass` component on the camera, the particle groups rendered
+    /// with [`AlphaMode::Mask`] are written into the G-buffer with the given
+    /// surface properties instead of being drawn in the forward alpha mask
+    /// pass, so they're lit like the opaque meshes. Their normal is the
+    /// [`Attribute::AXIS_Z`] of each particle, so this works best with
+    /// particles oriented by an [`OrientModifier`]. The other particle groups,
+    /// and all the groups in views using the forward renderer, are rendered in
+    /// the forward passes as usual.
+    ///
+    /// The deferred renderer requires MSAA to be disabled.
+    ///
+    /// [`Attribute::AXIS_Z`]: crate::Attribute::AXIS_Z
+    /// [`OrientModifier`]: crate::OrientModifier
+    pub fn with_deferred(mut self, material: DeferredMaterial) -> Self {
+        self.deferred = Some(material);
+        self
+    }
+
+    /// Apply the fog of the view to the particles.
+    ///
+    /// When enabled, the `FogSettings` of the camera rendering the effect fade
+    /// the particles into the fog color with the distance to the camera, like
+    /// the meshes of the scene, so distant smoke or snow doesn't stand out of
+    /// the fog. All the fog falloff modes are supported; the directional light
+    /// inscattering of the fog is ignored. The particles written into the
+    /// G-buffer with [`with_deferred()`] are fogged by the deferred lighting
+    /// pass instead.
+    ///
+    /// This requires the `fog` feature, which enables the `bevy_pbr` feature
+    /// of Bevy. Without it, or in views without fog, the particles are
+    /// unaffected.
+    /// Effects simulated on CPU are never fogged.
+    ///
+    /// [`with_deferred()`]: crate::EffectAsset::with_deferred
+    pub fn with_fog(mut self, fog: bool) -> Self {
+        self.fog = fog;
+        self
+    }
+
+    /// Allow toggling the init and update modifiers per effect instance.
+    ///
+    /// When enabled, the code of each of the first 32 init and update
+    /// modifiers is guarded by a check of a per-instance bitfield, so the
+    /// modifiers can be enabled and disabled at runtime on each instance with
+    /// a [`ModifierToggles`] component, without compiling new shaders. This
+    /// adds a uniform branch per modifier to the shaders, and the expressions
+    /// shared by several modifiers are evaluated once per modifier.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::prelude::*;
+    /// # let spawner = Spawner::rate(32_f32.into());
+    /// let mut module = Module::default();
+    /// let accel = module.lit(Vec3::Y * -9.81);
+    /// let effect = EffectAsset::new(vec![4096], spawner, module)
+    ///     .update(AccelModifier::new(accel))
+    ///     .with_modifier_toggles(true);
+    ///
+    /// // Later, on an instance of the effect
+    /// let toggles = ModifierToggles::new().with_disabled_by_name("AccelModifier");
+    /// ```
+    ///
+    /// [`ModifierToggles`]: crate::ModifierToggles
+    pub fn with_modifier_toggles(mut self, modifier_toggles: bool) -> Self {
+        self.modifier_toggles = modifier_toggles;
+        self
+    }
+
+    /// Draw a soft shadow on a plane under each particle.
+    ///
+    /// Each particle of the groups rendered with [`AlphaMode::Blend`] draws a
+    /// dark soft ellipse where it projects onto the plane of the shadow,
+    /// blended like the particle itself, just before drawing the particle.
+    /// This is much cheaper than casting real shadows, and doesn't need any
+    /// light. Particles behind the plane, relative to the projection
+    /// direction, cast no shadow.
+    ///
+    /// The groups rendered with a trail or with [`AlphaMode::Mask`] draw no
+    /// shadow. The shadows are drawn with the particles, so they disappear
+    /// with them when the effect or the particle is culled, even if the
+    /// shadow itself is visible.
+    pub fn with_blob_shadow(mut self, shadow: BlobShadow) -> Self {
+        self.blob_shadow = Some(shadow);
+        self
+    }
+
+    /// Get the alpha mode of a particle group.
+    ///
+    /// This is the alpha mode set with [`with_group_alpha_mode()`] if any, or
+    /// the alpha mode of the effect otherwise.
+    ///
+    /// [`with_group_alpha_mode()`]: crate::EffectAsset::with_group_alpha_mode
+    pub fn group_alpha_mode(&self, group_index: u32) -> AlphaMode {
+        self.group_alpha_modes
+            .get(group_index as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(self.alpha_mode)
+    }
+
+    /// Add an emitter spawning particles into a particle group.
+    ///
+    /// The [`spawner`] of the effect always spawns particles into group 0.
+    /// Each additional emitter spawns particles into the group `group_index`
+    /// with its own [`Spawner`], and those particles are initialized by the
+    /// init modifiers targeting that group (see [`init_groups()`]). Together
+    /// with the per-group update and render modifiers, this allows a single
+    /// effect asset to bundle several particle systems sharing the same
+    /// [`Module`] and properties, which are then spawned and controlled as a
+    /// single [`ParticleEffect`] instance.
+    ///
+    /// All emitters share the playback state of the effect instance (see
+    /// [`EffectSpawner::play()`] and related functions), but otherwise tick
+    /// independently.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `group_index` is not a valid group index, that is if it's
+    /// greater than or equal to the number of [`capacities()`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # let mut module = Module::default();
+    /// # let flames_lifetime = module.lit(0.5);
+    /// # let smoke_lifetime = module.lit(3.);
+    /// // Campfire with flames in group 0, and smoke in group 1
+    /// let effect = EffectAsset::new(vec![256, 128], Spawner::rate(50.0.into()), module)
+    ///     .with_emitter(1, Spawner::rate(10.0.into()))
+    ///     .init(SetAttributeModifier::new(Attribute::LIFETIME, flames_lifetime))
+    ///     .init_groups(
+    ///         SetAttributeModifier::new(Attribute::LIFETIME, smoke_lifetime),
+    ///         ParticleGroupSet::single(1),
+    ///     );
+    /// ```
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    /// [`init_groups()`]: crate::EffectAsset::init_groups
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    /// [`EffectSpawner::play()`]: crate::EffectSpawner::play
+    /// [`capacities()`]: crate::EffectAsset::capacities
+    pub fn with_emitter(mut self, group_index: u32, spawner: Spawner) -> Self {
+        assert!(
+            (group_index as usize) < self.capacities.len(),
+            "Invalid group index {} for emitter of effect '{}' with {} group(s).",
+            group_index,
+            self.name,
+            self.capacities.len()
+        );
+        self.emitters.push(EffectEmitter {
+            group_index,
+            spawner,
+        });
+        self
+    }
+
+    /// Get the additional emitters of the effect.
+    ///
+    /// This doesn't include the [`spawner`] of the effect itself.
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    pub fn emitters(&self) -> &[EffectEmitter] {
+        &self.emitters
+    }
+
+    /// Add a child effect composed by reference.
+    ///
+    /// Each instance of this effect spawns an instance of the child effect,
+    /// as a child entity with the [`transform`] of the child relative to the
+    /// parent instance. Once the parent instance simulated for the [`delay`]
+    /// of the child, as measured by [`EffectSpawner::elapsed()`], the child
+    /// instance is spawned. This composes complex effects from existing ones,
+    /// like a "boss death" effect made of an explosion, delayed shockwaves,
+    /// and lingering smoke, all spawned with a single [`ParticleEffectBundle`].
+    ///
+    /// Child effects can have children of their own, but must not form a
+    /// cycle. The child instances are spawned only once per parent instance,
+    /// even if the parent is restarted, and are tracked by the
+    /// [`EffectComposition`] component of the parent instance. Despawning the
+    /// parent instance recursively despawns its children.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_hanabi::*;
+    /// # fn f(explosion: Handle<EffectAsset>, smoke: Handle<EffectAsset>) {
+    /// let boss_death = EffectAsset::new(vec![16], Spawner::once(0.0.into(), true), Module::default())
+    ///     .with_child(EffectChild::new(explosion))
+    ///     .with_child(
+    ///         EffectChild::new(smoke)
+    ///             .with_transform(Transform::from_xyz(0., 1., 0.))
+    ///             .with_delay(0.5),
+    ///     );
+    /// # }
+    /// ```
+    ///
+    /// [`transform`]: crate::EffectChild::transform
+    /// [`delay`]: crate::EffectChild::delay
+    /// [`EffectSpawner::elapsed()`]: crate::EffectSpawner::elapsed
+    /// [`ParticleEffectBundle`]: crate::ParticleEffectBundle
+    /// [`EffectComposition`]: crate::EffectComposition
+    pub fn with_child(mut self, child: EffectChild) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Get the child effects composed by reference.
+    ///
+    /// See [`with_child()`] for details.
+    ///
+    /// [`with_child()`]: crate::EffectAsset::with_child
+    pub fn children(&self) -> &[EffectChild] {
+        &self.children
+    }
+
+    /// Drive a parameter of the [`spawner`] with a property.
+    ///
+    /// Each frame, before ticking the [`EffectSpawner`] of an instance, the
+    /// parameter is set to the current value of the property for that
+    /// instance. The value is resolved the same way as on GPU: from the
+    /// [`GlobalProperties`] if a global property of the same name and type
+    /// exists, otherwise from the [`EffectProperties`] of the instance, and
+    /// otherwise from the default value of the property. This allows varying
+    /// for example the spawn rate of a single instance at runtime, without
+    /// mutating the shared asset nor the [`EffectSpawner`] directly.
+    ///
+    /// The property must be declared in the [`Module`] of the effect with
+    /// [`Module::add_property()`], and be of type `f32`; otherwise the binding
+    /// is ignored. Changes to [`SpawnerParam::Count`] take effect immediately,
+    /// while changes to [`SpawnerParam::SpawnTime`] and
+    /// [`SpawnerParam::Period`] take effect at the start of the next spawn
+    /// cycle. Only the main spawner is affected, not the spawners of any
+    /// additional [emitter].
+    ///
+    /// Note that the lifetime of the particles is not a spawner parameter, but
+    /// is set by an expression with a [`SetAttributeModifier`] on
+    /// [`Attribute::LIFETIME`], which can read a property directly with
+    /// [`Module::prop()`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// let mut module = Module::default();
+    /// module.add_property("rate", 30_f32.into());
+    /// let asset = EffectAsset::new(vec![1024], Spawner::rate(30.0.into()), module)
+    ///     .with_spawner_property(SpawnerParam::Count, "rate");
+    /// ```
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    /// [`EffectSpawner`]: crate::EffectSpawner
+    /// [`GlobalProperties`]: crate::GlobalProperties
+    /// [`EffectProperties`]: crate::EffectProperties
+    /// [emitter]: crate::EffectAsset::with_emitter
+    /// [`SetAttributeModifier`]: crate::SetAttributeModifier
+    /// [`Attribute::LIFETIME`]: crate::Attribute::LIFETIME
+    pub fn with_spawner_property(mut self, param: SpawnerParam, name: impl Into<String>) -> Self {
+        let name = name.into();
+        if let Some(binding) = self.spawner_properties.iter_mut().find(|b| b.0 == param) {
+            binding.1 = name;
+        } else {
+            self.spawner_properties.push((param, name));
+        }
+        self
+    }
+
+    /// Get the parameters of the [`spawner`] driven by a property.
+    ///
+    /// See [`with_spawner_property()`] for details.
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    /// [`with_spawner_property()`]: crate::EffectAsset::with_spawner_property
+    pub fn spawner_properties(&self) -> &[(SpawnerParam, String)] {
+        &self.spawner_properties
+    }
+
+    /// Set the pre-warming duration, in seconds.
+    ///
+    /// When the effect activates, its [`EffectSpawner`] is fast-forwarded by
+    /// `prewarm` seconds, and all particles spawned during that interval are
+    /// emitted on the first frame. Each of those particles is then aged by a
+    /// random duration in `[0:prewarm]`, and if the particle layout contains
+    /// both a position and a velocity, moved along its initial velocity by
+    /// that same duration. Particles older than their lifetime die on the
+    /// first update. This analytic fast-forward ignores any update modifier
+    /// (forces, drag, ...), so works best for effects emitting continuously
+    /// with mostly ballistic particles, like ambient effects.
+    ///
+    /// Pre-warming is only applied if the particle layout contains the
+    /// [`Attribute::AGE`] attribute. The number of pre-warmed particles is
+    /// capped by the capacity of the effect, like for any other spawning.
+    ///
+    /// A value of zero (the default) disables pre-warming.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # let spawner = Spawner::rate(30_f32.into());
+    /// # let module = Module::default();
+    /// // Waterfall appearing already in steady state after 5 seconds
+    /// let effect = EffectAsset::new(vec![4096], spawner, module).with_prewarm(5.);
+    /// ```
+    ///
+    /// [`EffectSpawner`]: crate::EffectSpawner
+    /// [`Attribute::AGE`]: crate::Attribute::AGE
+    pub fn with_prewarm(mut self, prewarm: f32) -> Self {
+        self.prewarm = prewarm.max(0.);
+        self
+    }
+
+    /// Set the maximum number of particles which can be injected from the CPU
+    /// per frame.
+    ///
+    /// A non-zero capacity allocates a GPU buffer of that many particles,
+    /// allowing the instances of the effect to receive explicit particles
+    /// pushed with an [`EffectInjector`]. A capacity of zero (the default)
+    /// disables injection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # let spawner = Spawner::once(0_f32.into(), false);
+    /// # let module = Module::default();
+    /// // Effect only spawning particles injected from the CPU, up to 256 per frame
+    /// let effect = EffectAsset::new(vec![4096], spawner, module).with_injection_capacity(256);
+    /// ```
+    ///
+    /// [`EffectInjector`]: crate::EffectInjector
+    pub fn with_injection_capacity(mut self, injection_capacity: u32) -> Self {
+        self.injection_capacity = injection_capacity;
+        self
+    }
+
+    /// Enable or disable the computation of the bounds of the effect instances
+    /// on GPU.
+    ///
+    /// When enabled, the update pass accumulates the axis-aligned bounding box
+    /// of the alive particles of each instance, which is read back from GPU
+    /// and inserted as an [`Aabb`] component on the entity holding the
+    /// [`ParticleEffect`]. Bevy's visibility system then culls the instance
+    /// from any view its bounds are not in, and instances simulated
+    /// [`SimulationCondition::WhenVisible`] are neither simulated nor rendered
+    /// while culled from all views.
+    ///
+    /// The bounds are read back with a latency of a few frames, so may lag
+    /// behind fast-moving particles. Until the first readback completes, the
+    /// instance is never culled. This requires the particles to have an
+    /// [`Attribute::POSITION`]; otherwise no bounds are computed. Disabled by
+    /// default.
+    ///
+    /// [`Aabb`]: bevy::render::primitives::Aabb
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    /// [`Attribute::POSITION`]: crate::Attribute::POSITION
+    pub fn with_gpu_bounds(mut self, gpu_bounds: bool) -> Self {
+        self.gpu_bounds = gpu_bounds;
+        self
+    }
+
+    /// Enable culling individual particles against the frustum of each view.
+    ///
+    /// When enabled, a compute pass running after the update pass builds, for
+    /// each view, the list of the particles inside the view frustum, and only
+    /// those particles are drawn into that view. This is mostly useful for
+    /// effects spanning a large part of the world, like weather effects, of
+    /// which only a fraction is visible at any time. For small effects, prefer
+    /// culling the entire effect with [`with_gpu_bounds()`].
+    ///
+    /// The `radius` is the bounding radius of a single particle, in simulation
+    /// space units. A particle is culled from a view if its position is farther
+    /// than this radius outside of the view frustum, so the radius needs to
+    /// account for the size of the rendered particles to prevent them from
+    /// popping at the edges of the view. This requires the particles to have
+    /// an [`Attribute::POSITION`]; otherwise no culling occurs.
+    ///
+    /// Note that this allocates one list of particle indices per view, of the
+    /// size of the capacity of the effect.
+    ///
+    /// [`with_gpu_bounds()`]: crate::EffectAsset::with_gpu_bounds
+    /// [`Attribute::POSITION`]: crate::Attribute::POSITION
+    pub fn with_particle_culling(mut self, radius: f32) -> Self {
+        self.particle_culling = Some(radius.max(0.));
+        self
+    }
+
+    /// Enable sub-stepping of the particle update for fast-moving particles.
+    ///
+    /// With a single update per frame, fast particles like sparks travel a
+    /// long distance each frame. They can then tunnel through collision
+    /// planes, and their trails show visibly discrete segments. With
+    /// sub-stepping, any particle which would travel more than
+    /// `max_step_distance` during a simulation update is instead updated
+    /// several times with a fraction of the delta time, so that it travels at
+    /// most that distance per sub-step, up to `max_substeps` sub-steps.
+    ///
+    /// All the update modifiers and the motion integration run on each
+    /// sub-step, while the particle age and lifetime are updated once per
+    /// simulation update, and the trail history is recorded once at the end of
+    /// it. Particles moving slower than the threshold are updated once, so the
+    /// extra cost is limited to fast particles. This requires the particles to
+    /// have both an [`Attribute::POSITION`] and an [`Attribute::VELOCITY`];
+    /// otherwise no sub-stepping occurs.
+    ///
+    /// [`Attribute::POSITION`]: crate::Attribute::POSITION
+    /// [`Attribute::VELOCITY`]: crate::Attribute::VELOCITY
+    pub fn with_motion_substeps(mut self, max_step_distance: f32, max_substeps: u32) -> Self {
+        self.motion_substeps = Some(MotionSubsteps {
+            max_step_distance,
+            max_substeps,
+        });
+        self
+    }
+
     /// Get the list of existing properties.
     ///
     /// This is a shortcut for `self.module().properties()`.
@@ -350,14 +1400,18 @@ impl EffectAsset {
     /// Add an initialization modifier to the effect.
     ///
     /// Initialization modifiers only apply to particles that are freshly
-    /// spawned. Currently, spawners can only spawn into group 0. Consequently,
-    /// the initialization modifiers will only affect particles in group 0.
+    /// spawned. This modifier only affects the particles spawned into group 0
+    /// by the [`spawner`] of the effect. To initialize the particles of other
+    /// emitters, use [`init_groups()`].
     ///
     /// # Panics
     ///
     /// Panics if the modifier doesn't support the init context (that is,
     /// `modifier.context()` returns a flag which doesn't include
     /// [`ModifierContext::Init`]).
+    ///
+    /// [`spawner`]: crate::EffectAsset::spawner
+    /// [`init_groups()`]: crate::EffectAsset::init_groups
     #[inline]
     pub fn init<M>(mut self, modifier: M) -> Self
     where
@@ -371,6 +1425,32 @@ impl EffectAsset {
         self
     }
 
+    /// Add an initialization modifier to the effect targeting only a subset of
+    /// groups.
+    ///
+    /// The modifier initializes the particles spawned into any of the `groups`
+    /// by the emitters of the effect (see [`with_emitter()`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the modifier doesn't support the init context (that is,
+    /// `modifier.context()` returns a flag which doesn't include
+    /// [`ModifierContext::Init`]).
+    ///
+    /// [`with_emitter()`]: crate::EffectAsset::with_emitter
+    #[inline]
+    pub fn init_groups<M>(mut self, modifier: M, groups: ParticleGroupSet) -> Self
+    where
+        M: Modifier + Send + Sync,
+    {
+        assert!(modifier.context().contains(ModifierContext::Init));
+        self.init_modifiers.push(GroupedModifier {
+            modifier: Box::new(modifier),
+            groups,
+        });
+        self
+    }
+
     /// Add an update modifier to the effect.
     ///
     /// # Panics
@@ -538,6 +1618,14 @@ impl EffectAsset {
         self
     }
 
+    /// Get the index of a modifier of this effect in [`modifiers()`], if any.
+    ///
+    /// [`modifiers()`]: crate::EffectAsset::modifiers
+    pub(crate) fn modifier_index(&self, modifier: &dyn Modifier) -> Option<usize> {
+        self.modifiers()
+            .position(|m| std::ptr::addr_eq(m, modifier))
+    }
+
     /// Get a list of all the modifiers of this effect.
     pub fn modifiers(&self) -> impl Iterator<Item = &dyn Modifier> {
         self.init_modifiers
@@ -571,6 +1659,29 @@ impl EffectAsset {
         })
     }
 
+    /// Get a list of all the init modifiers initializing the particles spawned
+    /// into a single group.
+    ///
+    /// This is a filtered list of all modifiers, retaining only modifiers
+    /// executing in the [`ModifierContext::Init`] context and affecting the
+    /// specified group.
+    ///
+    /// [`ModifierContext::Init`]: crate::ModifierContext::Init
+    pub fn init_modifiers_for_group(
+        &self,
+        group_index: u32,
+    ) -> impl Iterator<Item = &dyn Modifier> {
+        self.init_modifiers.iter().filter_map(move |gm| {
+            if gm.groups.contains(group_index)
+                && gm.modifier.context().contains(ModifierContext::Init)
+            {
+                Some(gm.modifier.deref())
+            } else {
+                None
+            }
+        })
+    }
+
     /// Get a list of all the update modifiers of this effect.
     ///
     /// This is a filtered list of all modifiers, retaining only modifiers
@@ -619,6 +1730,11 @@ impl EffectAsset {
         self.render_modifiers.iter().filter_map(|m| m.as_render())
     }
 
+    /// Get mutable access to all the render modifiers of this effect.
+    pub(crate) fn render_modifiers_mut(&mut self) -> impl Iterator<Item = &mut BoxedModifier> {
+        self.render_modifiers.iter_mut().map(|m| &mut m.modifier)
+    }
+
     /// Get a list of all the render modifiers of this effect that affect a
     /// specific group.
     ///
@@ -640,6 +1756,32 @@ impl EffectAsset {
         })
     }
 
+    /// Get the length of the trail of each particle group, or zero for groups
+    /// not rendered with a [`TrailModifier`].
+    ///
+    /// The result contains one entry per group.
+    ///
+    /// [`TrailModifier`]: crate::TrailModifier
+    pub fn trail_lengths(&self) -> Vec<u32> {
+        (0..self.capacities.len() as u32)
+            .map(|group_index| {
+                self.render_modifiers_for_group(group_index)
+                    .filter_map(|m| m.as_modifier().as_any().downcast_ref::<TrailModifier>())
+                    .map(|trail| trail.clamped_length())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Get the number of positions recorded per particle in the trail buffer
+    /// of the effect, or zero if the effect doesn't render any trail.
+    ///
+    /// This is the longest trail of all groups.
+    pub fn trail_stride(&self) -> u32 {
+        self.trail_lengths().into_iter().max().unwrap_or(0)
+    }
+
     /// Build the particle layout of the asset based on its modifiers.
     ///
     /// This method calculates the particle layout of the effect based on the
@@ -672,9 +1814,32 @@ impl EffectAsset {
     }
 }
 
+/// Log the issues found by [`EffectAsset::validate()`] in a loaded asset.
+fn log_diagnostics(path: &Path, asset: &EffectAsset) {
+    for diagnostic in asset.validate() {
+        match diagnostic.severity() {
+            DiagnosticSeverity::Warning => {
+                warn!("Effect asset '{}': {}", path.display(), diagnostic)
+            }
+            DiagnosticSeverity::Error => {
+                error!("Effect asset '{}': {}", path.display(), diagnostic)
+            }
+        }
+    }
+}
+
 /// Asset loader for [`EffectAsset`].
 ///
-/// Effet assets take the `.effect` extension.
+/// Effet assets take the `.effect` extension, and are serialized in the RON
+/// format. Assets saved with a previous version of the format are migrated
+/// with [`EffectAsset::migrate()`]. The loaded assets are then checked with
+/// [`EffectAsset::validate()`], and any issue found is logged.
+///
+/// When the asset is hot-reloaded, for example with the `file_watcher` feature
+/// of Bevy, the changes propagate to all the existing instances of the effect,
+/// without respawning them: their shaders are recompiled, their spawners and
+/// properties are updated from the new asset, and their GPU resources are
+/// reallocated, which clears any alive particle.
 #[derive(Default)]
 pub struct EffectAssetLoader;
 
@@ -688,6 +1853,14 @@ pub enum EffectAssetLoaderError {
     /// Error during RON format parsing.
     #[error("A RON format error occurred during loading of a particle effect")]
     Ron(#[from] ron::error::SpannedError),
+
+    /// Error migrating the asset from a previous format version.
+    #[error("Failed to migrate a particle effect: {0}")]
+    Migration(#[from] EffectAssetMigrationError),
+
+    /// The path to a child effect asset is invalid.
+    #[error("Invalid path to a child effect of a particle effect: {0}")]
+    InvalidChildPath(#[from] ParseAssetPathError),
 }
 
 impl AssetLoader for EffectAssetLoader {
@@ -701,12 +1874,28 @@ impl AssetLoader for EffectAssetLoader {
         &'a self,
         reader: &'a mut Reader,
         _settings: &'a Self::Settings,
-        _load_context: &'a mut LoadContext,
+        load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
         Box::pin(async move {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
-            let custom_asset = ron::de::from_bytes::<EffectAsset>(&bytes)?;
+            let mut custom_asset = ron::de::from_bytes::<EffectAsset>(&bytes)?;
+            let version = custom_asset.migrate()?;
+            for child in &mut custom_asset.children {
+                if let Some(path) = &child.path {
+                    let path = load_context.asset_path().resolve_embed(path)?;
+                    child.handle = load_context.load(path);
+                }
+            }
+            if version < EffectAsset::FORMAT_VERSION {
+                info!(
+                    "Migrated effect asset '{}' from format version {} to {}. Save it again to upgrade the file.",
+                    load_context.path().display(),
+                    version,
+                    EffectAsset::FORMAT_VERSION
+                );
+            }
+            log_diagnostics(load_context.path(), &custom_asset);
             Ok(custom_asset)
         })
     }
@@ -716,6 +1905,243 @@ impl AssetLoader for EffectAssetLoader {
     }
 }
 
+/// Overrides applied to a base [`EffectAsset`] to derive a variant of it.
+///
+/// Variants allow deriving several effects from a same base effect, like a
+/// small, medium, and large explosion, without duplicating the entire base
+/// asset. Only the overridden fields differ from the base, so the variants
+/// don't drift apart when the base is later modified.
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// # let base = EffectAsset::new(vec![256], Spawner::rate(30.0.into()), Module::default());
+/// let large = EffectVariant::default()
+///     .with_name("large_explosion")
+///     .with_capacities(vec![1024])
+///     .with_spawner(Spawner::once(500.0.into(), true))
+///     .apply(&base);
+/// ```
+///
+/// Modifiers can be overridden too, by replacing all the modifiers of the same
+/// type in the base asset, for example to change the gradient of a
+/// [`ColorOverLifetimeModifier`] or the texture of a
+/// [`ParticleTextureModifier`]. The overriding modifiers can only reference the
+/// expressions of the [`Module`] of the base asset, so this is mostly useful
+/// for modifiers without any expression.
+///
+/// Variants can also be stored in `.effect_variant` files in the RON format,
+/// which are loaded as the [`EffectAsset`] derived from their base asset. Such
+/// files reference their base asset by path with the [`base`] field, and are
+/// reloaded when their base asset is hot-reloaded.
+///
+/// [`ColorOverLifetimeModifier`]: crate::ColorOverLifetimeModifier
+/// [`ParticleTextureModifier`]: crate::ParticleTextureModifier
+/// [`base`]: crate::EffectVariant::base
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct EffectVariant {
+    /// Path to the base asset, relative to the variant file, or relative to
+    /// the root of the asset source if starting with a `/`.
+    ///
+    /// This is only used when loading a `.effect_variant` file, and ignored by
+    /// [`apply()`]. The base asset can itself be a variant.
+    ///
+    /// [`apply()`]: crate::EffectVariant::apply
+    #[serde(default)]
+    pub base: String,
+    /// Name of the variant, replacing the name of the base asset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Capacities of the particle groups, replacing the ones of the base
+    /// asset. The number of groups must match the one of the base asset.
+    #[serde(default)]
+    pub capacities: Option<Vec<u32>>,
+    /// Spawner of the effect, replacing the one of the base asset.
+    #[serde(default)]
+    pub spawner: Option<Spawner>,
+    /// Default values of properties, by property name, replacing the ones of
+    /// the base asset. The properties must exist in the base asset, with the
+    /// same value type.
+    #[serde(default)]
+    pub property_defaults: Vec<(String, Value)>,
+    /// Modifiers replacing the modifiers of the same type in the base asset.
+    #[serde(default)]
+    pub modifiers: Vec<BoxedModifier>,
+}
+
+impl EffectVariant {
+    /// Create a new variant of the base asset at the given path.
+    ///
+    /// See [`base`] for details.
+    ///
+    /// [`base`]: crate::EffectVariant::base
+    pub fn new(base: impl Into<String>) -> Self {
+        Self {
+            base: base.into(),
+            ..default()
+        }
+    }
+
+    /// Override the name of the base asset.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Override the capacities of the base asset.
+    pub fn with_capacities(mut self, capacities: Vec<u32>) -> Self {
+        self.capacities = Some(capacities);
+        self
+    }
+
+    /// Override the spawner of the base asset.
+    pub fn with_spawner(mut self, spawner: Spawner) -> Self {
+        self.spawner = Some(spawner);
+        self
+    }
+
+    /// Override the default value of a property of the base asset.
+    pub fn with_property_default(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.property_defaults.push((name.into(), value));
+        self
+    }
+
+    /// Override the modifiers of the same type as `modifier` in the base
+    /// asset.
+    pub fn with_modifier(mut self, modifier: BoxedModifier) -> Self {
+        self.modifiers.push(modifier);
+        self
+    }
+
+    /// Derive a variant of a base asset by applying the overrides.
+    ///
+    /// Invalid overrides are ignored, and a warning is logged.
+    pub fn apply(&self, base: &EffectAsset) -> EffectAsset {
+        let mut asset = base.clone();
+
+        if let Some(name) = &self.name {
+            asset.name = name.clone();
+        }
+
+        if let Some(capacities) = &self.capacities {
+            if capacities.len() == asset.capacities.len() {
+                asset.capacities = capacities.clone();
+            } else {
+                warn!(
+                    "Variant of effect '{}' overrides the capacities of {} groups, but the base asset has {} groups. The capacities are not overridden.",
+                    base.name,
+                    capacities.len(),
+                    asset.capacities.len()
+                );
+            }
+        }
+
+        if let Some(spawner) = self.spawner {
+            asset.spawner = spawner;
+        }
+
+        for (name, value) in &self.property_defaults {
+            if !asset.module.set_property_default_value(name, *value) {
+                warn!(
+                    "Variant of effect '{}' overrides the default value of property '{}' with a value of type {:?}, but the base asset has no such property of that type. The property is not overridden.",
+                    base.name,
+                    name,
+                    value.value_type()
+                );
+            }
+        }
+
+        for modifier in &self.modifiers {
+            let type_path = modifier.reflect_type_path();
+            let mut replaced = false;
+            for grouped in asset
+                .init_modifiers
+                .iter_mut()
+                .chain(asset.update_modifiers.iter_mut())
+                .chain(asset.render_modifiers.iter_mut())
+                .filter(|grouped| grouped.modifier.reflect_type_path() == type_path)
+            {
+                grouped.modifier = modifier.clone();
+                replaced = true;
+            }
+            if !replaced {
+                warn!(
+                    "Variant of effect '{}' overrides modifier {}, but the base asset has no such modifier. The modifier is not added.",
+                    base.name, type_path
+                );
+            }
+        }
+
+        asset
+    }
+}
+
+/// Asset loader for [`EffectVariant`]s.
+///
+/// Variant files take the `.effect_variant` extension, and are serialized in
+/// the RON format. The loader loads the base asset referenced by the variant,
+/// and produces the [`EffectAsset`] derived from it. Hot-reloading the base
+/// asset also reloads all its variants.
+#[derive(Default)]
+pub struct EffectVariantLoader;
+
+/// Error for the [`EffectVariantLoader`] loading an [`EffectVariant`].
+#[derive(Error, Debug)]
+pub enum EffectVariantLoaderError {
+    /// I/O error reading the asset source.
+    #[error("An IO error occurred during loading of a particle effect variant")]
+    Io(#[from] std::io::Error),
+
+    /// Error during RON format parsing.
+    #[error("A RON format error occurred during loading of a particle effect variant")]
+    Ron(#[from] ron::error::SpannedError),
+
+    /// The path to the base asset is invalid.
+    #[error("Invalid path to the base asset of a particle effect variant: {0}")]
+    InvalidBasePath(#[from] ParseAssetPathError),
+
+    /// The base asset failed to load.
+    #[error("Failed to load the base asset of a particle effect variant: {0}")]
+    LoadBase(#[from] LoadDirectError),
+
+    /// The base asset is not an [`EffectAsset`].
+    #[error("The base asset '{0}' of a particle effect variant is not an effect asset")]
+    InvalidBase(String),
+}
+
+impl AssetLoader for EffectVariantLoader {
+    type Asset = EffectAsset;
+
+    type Settings = ();
+
+    type Error = EffectVariantLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let variant = ron::de::from_bytes::<EffectVariant>(&bytes)?;
+            let base_path = load_context.asset_path().resolve_embed(&variant.base)?;
+            let base = load_context
+                .load_direct(base_path)
+                .await?
+                .take::<EffectAsset>()
+                .ok_or_else(|| EffectVariantLoaderError::InvalidBase(variant.base.clone()))?;
+            let asset = variant.apply(&base);
+            log_diagnostics(load_context.path(), &asset);
+            Ok(asset)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["effect_variant"]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ron::ser::PrettyConfig;
@@ -830,6 +2256,161 @@ mod tests {
         // assert_eq!(effect.render_layout, render_layout);
     }
 
+    #[test]
+    fn trail_lengths() {
+        let module = Module::default();
+        let asset = EffectAsset::new(vec![32, 32, 32], Spawner::default(), module);
+        assert_eq!(asset.trail_lengths(), vec![0, 0, 0]);
+        assert_eq!(asset.trail_stride(), 0);
+
+        let asset = asset
+            .render_groups(TrailModifier::new(8), ParticleGroupSet::single(1))
+            .render_groups(
+                TrailModifier {
+                    length: 1000,
+                    ..TrailModifier::new(2)
+                },
+                ParticleGroupSet::single(2),
+            );
+        assert_eq!(asset.trail_lengths(), vec![0, 8, TrailModifier::MAX_LENGTH]);
+        assert_eq!(asset.trail_stride(), TrailModifier::MAX_LENGTH);
+    }
+
+    #[test]
+    fn group_names() {
+        let module = Module::default();
+        let asset = EffectAsset::new(vec![32, 32, 32], Spawner::default(), module);
+        assert!(asset.group_names().is_empty());
+        assert_eq!(asset.group_index("rain"), None);
+        assert_eq!(asset.group_label(1), "#1");
+
+        let asset = asset.with_group_names(["rain", "splash", "mist"]);
+        assert_eq!(asset.group_index("splash"), Some(1));
+        assert_eq!(asset.group_index("snow"), None);
+        assert_eq!(asset.group_name(2), Some("mist"));
+        assert_eq!(asset.group_name(3), None);
+        assert_eq!(asset.group_label(1), "#1 'splash'");
+        assert_eq!(
+            asset.group_set(&["rain", "mist"]),
+            ParticleGroupSet::single(0).with_group(2)
+        );
+    }
+
+    #[test]
+    fn group_alpha_mode() {
+        let mut module = Module::default();
+        let cutoff = module.lit(0.5);
+        let asset = EffectAsset::new(vec![32, 32, 32], Spawner::default(), module)
+            .with_alpha_mode(AlphaMode::Mask(cutoff))
+            .with_group_alpha_mode(1, AlphaMode::Blend);
+        assert_eq!(asset.group_alpha_mode(0), AlphaMode::Mask(cutoff));
+        assert_eq!(asset.group_alpha_mode(1), AlphaMode::Blend);
+        assert_eq!(asset.group_alpha_mode(2), AlphaMode::Mask(cutoff));
+    }
+
+    #[test]
+    fn emitters() {
+        let mut module = Module::default();
+        let one = module.lit(1.);
+        let two = module.lit(2.);
+        let asset = EffectAsset::new(vec![32, 32], Spawner::rate(8.0.into()), module)
+            .with_emitter(1, Spawner::rate(4.0.into()))
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, one))
+            .init_groups(
+                SetAttributeModifier::new(Attribute::AGE, two),
+                ParticleGroupSet::single(1),
+            );
+        assert_eq!(
+            asset.emitters(),
+            &[EffectEmitter {
+                group_index: 1,
+                spawner: Spawner::rate(4.0.into()),
+            }]
+        );
+        assert_eq!(asset.init_modifiers().count(), 2);
+        assert_eq!(asset.init_modifiers_for_group(0).count(), 1);
+        assert_eq!(asset.init_modifiers_for_group(1).count(), 1);
+        assert_eq!(
+            asset
+                .init_modifiers_for_group(1)
+                .next()
+                .unwrap()
+                .attributes(),
+            &[Attribute::AGE]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn emitter_invalid_group() {
+        let _ = EffectAsset::new(vec![32], Spawner::default(), Module::default())
+            .with_emitter(1, Spawner::default());
+    }
+
+    #[test]
+    #[should_panic]
+    fn group_names_duplicate() {
+        let module = Module::default();
+        let _ = EffectAsset::new(vec![32, 32], Spawner::default(), module)
+            .with_group_names(["rain", "rain"]);
+    }
+
+    #[test]
+    fn variant() {
+        let mut module = Module::default();
+        module.add_property("size", 1.0.into());
+        let base = EffectAsset::new(vec![256], Spawner::rate(30.0.into()), module)
+            .with_name("explosion")
+            .render(ColorOverLifetimeModifier {
+                gradient: Gradient::constant(Vec4::ONE),
+            });
+
+        let gradient = Gradient::linear(Vec4::ONE, Vec4::ZERO);
+        let large = EffectVariant::default()
+            .with_name("large_explosion")
+            .with_capacities(vec![1024])
+            .with_spawner(Spawner::rate(100.0.into()))
+            .with_property_default("size", 3.0.into())
+            .with_modifier(Box::new(ColorOverLifetimeModifier {
+                gradient: gradient.clone(),
+            }))
+            .apply(&base);
+        assert_eq!(large.name, "large_explosion");
+        assert_eq!(large.capacities(), &[1024]);
+        assert_eq!(large.spawner.count(), CpuValue::Single(100.));
+        assert_eq!(large.properties()[0].default_value(), &Value::from(3.0_f32));
+        let color = large
+            .render_modifiers()
+            .next()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<ColorOverLifetimeModifier>()
+            .unwrap();
+        assert_eq!(color.gradient, gradient);
+
+        // Invalid overrides are ignored
+        let invalid = EffectVariant::default()
+            .with_capacities(vec![1024, 1024])
+            .with_property_default("size", Vec3::ONE.into())
+            .with_property_default("unknown", 3.0.into())
+            .with_modifier(Box::new(SizeOverLifetimeModifier::default()))
+            .apply(&base);
+        assert_eq!(invalid.name, "explosion");
+        assert_eq!(invalid.capacities(), &[256]);
+        assert_eq!(invalid.properties().len(), 1);
+        assert_eq!(
+            invalid.properties()[0].default_value(),
+            &Value::from(1.0_f32)
+        );
+        assert_eq!(invalid.modifiers().count(), 1);
+
+        // Variant files reference their base asset by path
+        let variant: EffectVariant =
+            ron::de::from_str(r#"(base: "explosion.effect", capacities: Some([512]))"#).unwrap();
+        assert_eq!(variant.base, "explosion.effect");
+        assert_eq!(variant.apply(&base).capacities(), &[512]);
+    }
+
     #[test]
     fn test_serde_ron() {
         let w = ExprWriter::new();
@@ -842,7 +2423,15 @@ mod tests {
         let mut module = w.finish();
         let prop = module.add_property("my_prop", Vec3::new(1.2, -2.3, 55.32).into());
         let prop = module.prop(prop);
-        let _ = module.abs(prop);
+        let abs = module.abs(prop);
+        module.set_meta(
+            abs,
+            Some(ExprMeta {
+                position: Vec2::new(120., 40.),
+                label: Some("abs".into()),
+                ..default()
+            }),
+        );
 
         let effect = EffectAsset {
             name: "Effect".into(),
@@ -859,6 +2448,7 @@ mod tests {
         assert_eq!(
             s,
             r#"(
+    format_version: 1,
     name: "Effect",
     capacities: [
         4096,
@@ -869,6 +2459,10 @@ mod tests {
         period: Single(1.0),
         starts_active: true,
         starts_immediately: true,
+        probability: None,
+        loop_delay: None,
+        loop_count: None,
+        spawn_scaling: Disabled,
     ),
     z_layer_2d: 0.0,
     simulation_space: Global,
@@ -888,31 +2482,48 @@ mod tests {
     render_modifiers: [],
     motion_integration: PostUpdate,
     module: (
-        expressions: [
-            Literal(Vector(Vec3((1.2, -3.45, 87.54485)))),
-            Literal(Vector(BVec2((false, true)))),
-            Binary(
+        expressions: {
+            1: Literal(Vector(Vec3((1.2, -3.45, 87.54485)))),
+            2: Literal(Vector(BVec2((false, true)))),
+            3: Binary(
                 op: Add,
                 left: 2,
                 right: 1,
             ),
-            Property(1),
-            Unary(
+            4: Property(1),
+            5: Unary(
                 op: Abs,
                 expr: 4,
             ),
-        ],
+        },
         properties: [
             (
                 name: "my_prop",
                 default_value: Vector(Vec3((1.2, -2.3, 55.32))),
             ),
         ],
+        meta: {
+            5: (
+                position: (120.0, 40.0),
+                label: Some("abs"),
+            ),
+        },
     ),
     alpha_mode: Blend,
+    prewarm: 0.0,
+    injection_capacity: 0,
+    gpu_bounds: false,
+    particle_culling: None,
+    motion_substeps: None,
+    group_names: [],
+    group_alpha_modes: [],
+    fog: false,
+    modifier_toggles: false,
+    emitters: [],
 )"#
         );
         let effect_serde: EffectAsset = ron::from_str(&s).unwrap();
+        assert_eq!(effect_serde.format_version(), EffectAsset::FORMAT_VERSION);
         assert_eq!(effect.name, effect_serde.name);
         assert_eq!(effect.capacities, effect_serde.capacities);
         assert_eq!(effect.spawner, effect_serde.spawner);
@@ -924,7 +2535,13 @@ mod tests {
         );
         assert_eq!(effect.motion_integration, effect_serde.motion_integration);
         assert_eq!(effect.module, effect_serde.module);
+        assert_eq!(effect.module.meta(abs), effect_serde.module.meta(abs));
         assert_eq!(effect.alpha_mode, effect_serde.alpha_mode);
+        assert_eq!(effect.prewarm, effect_serde.prewarm);
+        assert_eq!(effect.injection_capacity, effect_serde.injection_capacity);
+        assert_eq!(effect.gpu_bounds, effect_serde.gpu_bounds);
+        assert_eq!(effect.particle_culling, effect_serde.particle_culling);
+        assert_eq!(effect.motion_substeps, effect_serde.motion_substeps);
         assert_eq!(
             effect.init_modifiers().count(),
             effect_serde.init_modifiers().count()
@@ -938,4 +2555,73 @@ mod tests {
             effect_serde.render_modifiers().count()
         );
     }
+
+    #[test]
+    fn children() {
+        let asset = EffectAsset::default().with_child(
+            EffectChild::from_path("smoke.effect")
+                .with_transform(Transform::from_xyz(0., 1., 0.))
+                .with_delay(0.5),
+        );
+        let s = ron::ser::to_string(&asset).unwrap();
+        let asset_serde: EffectAsset = ron::from_str(&s).unwrap();
+        assert_eq!(asset_serde.children(), asset.children());
+        assert_eq!(
+            asset_serde.children()[0].path.as_deref(),
+            Some("smoke.effect")
+        );
+        assert_eq!(
+            asset_serde.children()[0].transform,
+            Transform::from_xyz(0., 1., 0.)
+        );
+
+        // Negative delays are clamped
+        assert_eq!(EffectChild::default().with_delay(-1.).delay, 0.);
+    }
+
+    #[test]
+    fn migrate() {
+        let effect = EffectAsset::new(vec![32], Spawner::rate(30.0.into()), Module::default());
+        assert_eq!(effect.format_version(), EffectAsset::FORMAT_VERSION);
+        let s = ron::to_string(&effect).unwrap();
+        assert!(s.starts_with("(format_version:1,"));
+
+        // Assets saved before the format was versioned had their properties in the
+        // asset instead of the module
+        let legacy = s.replace(
+            "format_version:1,",
+            r#"properties:[(name:"my_prop",default_value:Vector(Vec3((1.0,2.0,3.0))))],"#,
+        );
+        let mut effect: EffectAsset = ron::from_str(&legacy).unwrap();
+        assert_eq!(effect.format_version(), 0);
+        assert!(effect.module().properties().is_empty());
+        assert_eq!(effect.migrate(), Ok(0));
+        assert_eq!(effect.format_version(), EffectAsset::FORMAT_VERSION);
+        assert_eq!(
+            effect.module().properties(),
+            &[Property::new("my_prop", Value::from(Vec3::new(1., 2., 3.)))]
+        );
+        assert_eq!(
+            ron::to_string(&effect).unwrap(),
+            s.replace(
+                "properties:[]",
+                r#"properties:[(name:"my_prop",default_value:Vector(Vec3((1.0,2.0,3.0))))]"#,
+            )
+        );
+
+        // Migrating an up-to-date asset does nothing
+        assert_eq!(effect.migrate(), Ok(EffectAsset::FORMAT_VERSION));
+        assert_eq!(effect.module().properties().len(), 1);
+
+        // Assets from a more recent version are rejected
+        let future = s.replace("format_version:1,", "format_version:1000,");
+        let mut effect: EffectAsset = ron::from_str(&future).unwrap();
+        assert_eq!(
+            effect.migrate(),
+            Err(EffectAssetMigrationError::UnsupportedVersion {
+                version: 1000,
+                supported: EffectAsset::FORMAT_VERSION
+            })
+        );
+    }
 }
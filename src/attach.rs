@@ -0,0 +1,218 @@
+use bevy::{prelude::*, render::mesh::skinning::SkinnedMesh};
+
+/// Component attaching an effect instance to a named joint of an animated
+/// hierarchy.
+///
+/// Add this component to the same entity as a [`ParticleEffect`] to emit the
+/// particles from a joint of a character, like a hand or a weapon tip. Each
+/// frame, after the transform propagation, the [`GlobalTransform`] of the
+/// effect entity is overwritten with the one of the joint combined with
+/// [`offset`], so the emitter follows the animation without a custom system.
+///
+/// The joint is searched by [`Name`] among the descendants of [`target`], and
+/// the joints of any [`SkinnedMesh`] found there. The target is typically the
+/// root of a glTF scene; since scenes are spawned asynchronously, the search
+/// is retried each frame until the joint is found, and again if the joint
+/// entity is despawned. Until then, the effect keeps its own transform.
+///
+/// The effect entity should not be a descendant of the joint, and its own
+/// children aren't updated; attach each effect entity individually.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn attach_sparkles(
+///     mut commands: Commands,
+///     character: Query<Entity, Added<Character>>,
+///     effect: Res<MySparkles>,
+/// ) {
+///     for character in &character {
+///         commands.spawn((
+///             ParticleEffectBundle::new(effect.0.clone()),
+///             JointAttachment::new(character, "mixamorig:RightHand")
+///                 .with_offset(Transform::from_xyz(0., 0.1, 0.)),
+///         ));
+///     }
+/// }
+/// # #[derive(Component)]
+/// # struct Character;
+/// # #[derive(Resource)]
+/// # struct MySparkles(Handle<EffectAsset>);
+/// ```
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`offset`]: JointAttachment::offset
+/// [`target`]: JointAttachment::target
+#[derive(Debug, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct JointAttachment {
+    /// Root entity of the hierarchy containing the joint.
+    pub target: Entity,
+    /// Name of the joint.
+    pub joint: String,
+    /// Transform of the effect relative to the joint.
+    pub offset: Transform,
+    /// Joint entity, once found.
+    joint_entity: Option<Entity>,
+}
+
+impl Default for JointAttachment {
+    fn default() -> Self {
+        Self {
+            target: Entity::PLACEHOLDER,
+            joint: String::new(),
+            offset: Transform::IDENTITY,
+            joint_entity: None,
+        }
+    }
+}
+
+impl JointAttachment {
+    /// Create a new attachment to the joint with the given name, among the
+    /// descendants of the target entity.
+    pub fn new(target: Entity, joint: impl Into<String>) -> Self {
+        Self {
+            target,
+            joint: joint.into(),
+            ..default()
+        }
+    }
+
+    /// Set the transform of the effect relative to the joint.
+    pub fn with_offset(mut self, offset: Transform) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Get the joint entity the effect is attached to, if found.
+    pub fn joint_entity(&self) -> Option<Entity> {
+        self.joint_entity
+    }
+}
+
+/// Find an entity by name among the descendants of an entity, including the
+/// entity itself and the joints of the skinned meshes.
+fn find_joint(
+    root: Entity,
+    name: &str,
+    names: &Query<&Name>,
+    children: &Query<&Children>,
+    skinned_meshes: &Query<&SkinnedMesh>,
+) -> Option<Entity> {
+    let mut stack = vec![root];
+    let mut visited = vec![];
+    while let Some(entity) = stack.pop() {
+        if visited.contains(&entity) {
+            continue;
+        }
+        visited.push(entity);
+        if names.get(entity).is_ok_and(|n| n.as_str() == name) {
+            return Some(entity);
+        }
+        if let Ok(children) = children.get(entity) {
+            stack.extend(children.iter().copied());
+        }
+        if let Ok(skinned_mesh) = skinned_meshes.get(entity) {
+            stack.extend(skinned_mesh.joints.iter().copied());
+        }
+    }
+    None
+}
+
+/// Update the [`GlobalTransform`] of the effects with a [`JointAttachment`]
+/// from their joint.
+pub(crate) fn update_joint_attachments(
+    mut attachments: Query<(&mut JointAttachment, &mut GlobalTransform)>,
+    joints: Query<&GlobalTransform, Without<JointAttachment>>,
+    names: Query<&Name>,
+    children: Query<&Children>,
+    skinned_meshes: Query<&SkinnedMesh>,
+) {
+    for (mut attachment, mut transform) in &mut attachments {
+        // Find the joint on first use, or after it was despawned
+        let joint_transform = match attachment.joint_entity.map(|joint| joints.get(joint)) {
+            Some(Ok(joint_transform)) => joint_transform,
+            _ => {
+                let joint = find_joint(
+                    attachment.target,
+                    &attachment.joint,
+                    &names,
+                    &children,
+                    &skinned_meshes,
+                );
+                if attachment.joint_entity != joint {
+                    attachment.joint_entity = joint;
+                }
+                match joint.map(|joint| joints.get(joint)) {
+                    Some(Ok(joint_transform)) => joint_transform,
+                    _ => continue,
+                }
+            }
+        };
+
+        *transform = joint_transform.mul_transform(attachment.offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::transform::{TransformPlugin, TransformSystem};
+
+    use super::*;
+
+    #[test]
+    fn attach() {
+        let mut app = App::new();
+        app.add_plugins(TransformPlugin).add_systems(
+            PostUpdate,
+            update_joint_attachments.after(TransformSystem::TransformPropagate),
+        );
+
+        let hand = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0., 1., 0.)),
+                Name::new("hand"),
+            ))
+            .id();
+        let root = app
+            .world
+            .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                2., 0., 0.,
+            )))
+            .add_child(hand)
+            .id();
+        let effect = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                JointAttachment::new(root, "hand").with_offset(Transform::from_xyz(0., 0., 3.)),
+            ))
+            .id();
+        let missing = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                JointAttachment::new(root, "foot"),
+            ))
+            .id();
+
+        app.update();
+        let attachment = app.world.get::<JointAttachment>(effect).unwrap();
+        assert_eq!(attachment.joint_entity(), Some(hand));
+        let transform = app.world.get::<GlobalTransform>(effect).unwrap();
+        assert_eq!(transform.translation(), Vec3::new(2., 1., 3.));
+        let attachment = app.world.get::<JointAttachment>(missing).unwrap();
+        assert_eq!(attachment.joint_entity(), None);
+        let transform = app.world.get::<GlobalTransform>(missing).unwrap();
+        assert_eq!(transform.translation(), Vec3::ZERO);
+
+        // The effect follows the joint
+        app.world.get_mut::<Transform>(hand).unwrap().translation.y = 2.;
+        app.update();
+        let transform = app.world.get::<GlobalTransform>(effect).unwrap();
+        assert_eq!(transform.translation(), Vec3::new(2., 2., 3.));
+    }
+}
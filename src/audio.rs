@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use bevy::{
+    audio::{
+        AudioSink, AudioSinkPlayback, AudioSource, CpalSample, Decodable, PlaybackMode, Source,
+    },
+    prelude::*,
+    utils::{Entry, HashMap},
+};
+
+use crate::GlobalProperties;
+
+/// Cutoff frequency between the bass and mid bands, in Hertz.
+const BASS_CUTOFF: f32 = 250.;
+
+/// Cutoff frequency between the mid and treble bands, in Hertz.
+const TREBLE_CUTOFF: f32 = 2000.;
+
+/// Plugin analyzing the audio played by entities with an [`AudioAnalysis`],
+/// and exposing the result as global properties.
+///
+/// Each frame, the loudness of the audio recently played by each entity with
+/// an [`AudioAnalysis`] component is measured, globally and in three frequency
+/// bands, and written into the [`GlobalProperties`]. Any effect declaring a
+/// property of the same name reacts to the audio, which allows building music
+/// visualizers without any external bridge crate. See [`AudioAnalysis`] for
+/// the names of the properties.
+///
+/// The analysis is performed on CPU from the decoded [`AudioSource`], at the
+/// playback position tracked from the [`AudioSink`], so doesn't include the
+/// effects applied on the output, like the volume or the spatialization. The
+/// bands are separated with simple one-pole filters, which is cheap but much
+/// less selective than a spectrum analysis.
+///
+/// This plugin requires the `audio` feature of this crate, which enables the
+/// `bevy_audio` feature of Bevy, as well as the [`AudioPlugin`], which is part
+/// of the Bevy default plugins.
+///
+/// [`AudioPlugin`]: bevy::audio::AudioPlugin
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AudioAnalysisPlugin;
+
+impl Plugin for AudioAnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GlobalProperties>()
+            .register_type::<AudioAnalysis>()
+            .register_type::<AudioLevels>()
+            .add_systems(PreUpdate, analyze_audio);
+    }
+}
+
+/// Loudness of some audio, globally and per frequency band.
+///
+/// Each level is the root mean square (RMS) of the samples, in the band, over
+/// the analysis window. A full-scale sine wave has a level of about `0.707`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub struct AudioLevels {
+    /// Level of the entire signal.
+    pub rms: f32,
+    /// Level of the frequencies below 250 Hz.
+    pub bass: f32,
+    /// Level of the frequencies between 250 Hz and 2 kHz.
+    pub mid: f32,
+    /// Level of the frequencies above 2 kHz.
+    pub treble: f32,
+}
+
+impl AudioLevels {
+    /// Linearly interpolate between two levels.
+    fn lerp(self, rhs: Self, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| (b - a).mul_add(t, a);
+        Self {
+            rms: lerp(self.rms, rhs.rms),
+            bass: lerp(self.bass, rhs.bass),
+            mid: lerp(self.mid, rhs.mid),
+            treble: lerp(self.treble, rhs.treble),
+        }
+    }
+}
+
+/// Component analyzing the audio played by its entity for the
+/// [`AudioAnalysisPlugin`].
+///
+/// Insert this component on an entity playing some audio, typically spawned
+/// with an [`AudioBundle`], to have its levels written each frame into the
+/// following `f32` global properties:
+/// - `<prefix>_rms` for [`AudioLevels::rms`];
+/// - `<prefix>_bass` for [`AudioLevels::bass`];
+/// - `<prefix>_mid` for [`AudioLevels::mid`];
+/// - `<prefix>_treble` for [`AudioLevels::treble`].
+///
+/// The levels are zero until the playback starts, and while it's paused. Use
+/// a distinct prefix for each analyzed entity.
+///
+/// # Example
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+///     commands.spawn((
+///         AudioBundle {
+///             source: asset_server.load("music.ogg"),
+///             settings: PlaybackSettings::LOOP,
+///         },
+///         AudioAnalysis::new("music").with_gain(2.),
+///     ));
+///
+///     // Effects read the levels from properties of the same name
+///     let mut module = Module::default();
+///     let bass = module.add_property("music_bass", 0.0.into());
+/// }
+/// ```
+///
+/// [`AudioBundle`]: bevy::audio::AudioBundle
+#[derive(Debug, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct AudioAnalysis {
+    /// Prefix of the names of the global properties the levels are written
+    /// into.
+    pub prefix: String,
+    /// Duration of the analyzed audio before the playback position, in
+    /// seconds. Defaults to 1/20th of a second.
+    pub window: f32,
+    /// Smoothing of the levels over time, in `[0:1)`. Zero uses the levels of
+    /// the current window only, and values closer to one make the levels
+    /// decay slower. Defaults to `0.5`.
+    pub smoothing: f32,
+    /// Factor applied to the levels. Defaults to `1`.
+    pub gain: f32,
+    /// Levels of the last analysis.
+    levels: AudioLevels,
+    /// Playback position, in seconds.
+    position: f32,
+}
+
+impl AudioAnalysis {
+    /// Create a new analysis writing the global properties with the given
+    /// prefix.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            window: 0.05,
+            smoothing: 0.5,
+            gain: 1.,
+            levels: AudioLevels::default(),
+            position: 0.,
+        }
+    }
+
+    /// Set the duration of the analysis window, in seconds.
+    pub fn with_window(mut self, window: f32) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set the smoothing of the levels over time.
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Set the factor applied to the levels.
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Get the levels of the last analysis, after smoothing and gain.
+    pub fn levels(&self) -> AudioLevels {
+        self.levels
+    }
+
+    /// Get the playback position of the audio, in seconds.
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+}
+
+/// Audio decoded into mono samples.
+struct DecodedAudio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl DecodedAudio {
+    /// Decode an audio source, averaging all channels.
+    fn decode(source: &AudioSource) -> Self {
+        let decoder = source.decoder();
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate();
+        let interleaved: Vec<f32> = decoder.map(|sample| sample.to_sample::<f32>()).collect();
+        let samples = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Get the duration of the audio, in seconds.
+    fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate.max(1) as f32
+    }
+
+    /// Analyze the samples in a window ending at the given position.
+    fn analyze(&self, position: f32, window: f32) -> AudioLevels {
+        let rate = self.sample_rate as f32;
+        let end = ((position * rate) as usize).min(self.samples.len());
+        let start = end.saturating_sub((window * rate) as usize);
+        analyze_samples(&self.samples[start..end], self.sample_rate)
+    }
+}
+
+/// Compute the levels of some mono samples.
+fn analyze_samples(samples: &[f32], sample_rate: u32) -> AudioLevels {
+    if samples.is_empty() || sample_rate == 0 {
+        return AudioLevels::default();
+    }
+
+    // Split the bands with two one-pole low-pass filters
+    let coef = |cutoff: f32| 1. - (-std::f32::consts::TAU * cutoff / sample_rate as f32).exp();
+    let (bass_coef, treble_coef) = (coef(BASS_CUTOFF), coef(TREBLE_CUTOFF));
+    let (mut below_bass, mut below_treble) = (0f32, 0f32);
+    let mut sums = [0f32; 4];
+    for &x in samples {
+        below_bass = bass_coef.mul_add(x - below_bass, below_bass);
+        below_treble = treble_coef.mul_add(x - below_treble, below_treble);
+        let bands = [x, below_bass, below_treble - below_bass, x - below_treble];
+        for (sum, band) in sums.iter_mut().zip(bands) {
+            *sum = band.mul_add(band, *sum);
+        }
+    }
+
+    let rms = |sum: f32| (sum / samples.len() as f32).sqrt();
+    AudioLevels {
+        rms: rms(sums[0]),
+        bass: rms(sums[1]),
+        mid: rms(sums[2]),
+        treble: rms(sums[3]),
+    }
+}
+
+/// Analyze the audio of the entities with an [`AudioAnalysis`], and write
+/// their levels into the [`GlobalProperties`].
+fn analyze_audio(
+    time: Res<Time>,
+    sources: Res<Assets<AudioSource>>,
+    mut globals: ResMut<GlobalProperties>,
+    mut decoded: Local<HashMap<AssetId<AudioSource>, Arc<DecodedAudio>>>,
+    mut query: Query<(
+        &mut AudioAnalysis,
+        &Handle<AudioSource>,
+        Option<&AudioSink>,
+        Option<&PlaybackSettings>,
+    )>,
+) {
+    for (mut analysis, handle, sink, settings) in &mut query {
+        // The sink is inserted once the playback started
+        let playing = sink.is_some_and(|sink| !sink.is_paused() && !sink.empty());
+        let mut levels = AudioLevels::default();
+        if let (true, Some(sink)) = (playing, sink) {
+            let audio = match decoded.entry(handle.id()) {
+                Entry::Occupied(entry) => Some(entry.get().clone()),
+                Entry::Vacant(entry) => sources
+                    .get(handle)
+                    .map(|source| entry.insert(Arc::new(DecodedAudio::decode(source))).clone()),
+            };
+            if let Some(audio) = audio {
+                analysis.position += time.delta_seconds() * sink.speed();
+                let duration = audio.duration();
+                if duration > 0.
+                    && settings.is_some_and(|settings| matches!(settings.mode, PlaybackMode::Loop))
+                {
+                    analysis.position %= duration;
+                }
+                let raw = audio.analyze(analysis.position, analysis.window);
+                levels = AudioLevels {
+                    rms: raw.rms * analysis.gain,
+                    bass: raw.bass * analysis.gain,
+                    mid: raw.mid * analysis.gain,
+                    treble: raw.treble * analysis.gain,
+                };
+            }
+        }
+
+        let smoothing = analysis.smoothing.clamp(0., 1.);
+        analysis.levels = analysis.levels.lerp(levels, 1. - smoothing);
+        let levels = analysis.levels;
+        for (suffix, value) in [
+            ("rms", levels.rms),
+            ("bass", levels.bass),
+            ("mid", levels.mid),
+            ("treble", levels.treble),
+        ] {
+            globals.set(&format!("{}_{}", analysis.prefix, suffix), value.into());
+        }
+    }
+
+    // Forget about the audio not analyzed anymore
+    decoded.retain(|id, _| query.iter().any(|(_, handle, _, _)| handle.id() == *id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32) -> Vec<f32> {
+        (0..sample_rate / 10)
+            .map(|i| (std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn analyze() {
+        assert_eq!(analyze_samples(&[], 44100), AudioLevels::default());
+
+        let levels = analyze_samples(&sine(60., 44100), 44100);
+        assert!((levels.rms - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+        assert!(levels.bass > levels.mid);
+        assert!(levels.bass > levels.treble);
+
+        let levels = analyze_samples(&sine(8000., 44100), 44100);
+        assert!(levels.treble > levels.mid);
+        assert!(levels.treble > levels.bass);
+
+        let audio = DecodedAudio {
+            samples: sine(60., 44100),
+            sample_rate: 44100,
+        };
+        assert!((audio.duration() - 0.1).abs() < 1e-6);
+        assert_eq!(audio.analyze(0., 0.05), AudioLevels::default());
+        assert!(audio.analyze(0.1, 0.05).rms > 0.5);
+    }
+
+    #[test]
+    fn lerp() {
+        let a = AudioLevels::default();
+        let b = AudioLevels {
+            rms: 1.,
+            bass: 2.,
+            mid: 3.,
+            treble: 4.,
+        };
+        assert_eq!(a.lerp(b, 0.), a);
+        assert_eq!(a.lerp(b, 1.), b);
+        assert_eq!(a.lerp(b, 0.5).mid, 1.5);
+    }
+}
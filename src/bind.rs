@@ -0,0 +1,178 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::{graph::Value, tick_spawners, EffectProperties, EffectSystems};
+
+/// Function extracting the value of a property from a component.
+type BindingFn<T> = Box<dyn Fn(&T) -> Value + Send + Sync + 'static>;
+
+/// Bind some properties of an effect instance to a component on the same
+/// entity.
+///
+/// Each frame, the [`PropertyBindingPlugin`] for the component type `T` calls
+/// the binding functions with the component of the entity, and writes the
+/// returned values to the [`EffectProperties`] of the effect instance. This
+/// avoids writing a dedicated system for each piece of gameplay state an
+/// effect reacts to.
+///
+/// A single [`BindProperty`] can bind any number of properties to the same
+/// component type, with [`with_binding()`]. Properties are only marked as
+/// changed when their value actually changed, so unchanged components don't
+/// trigger a GPU re-upload.
+///
+/// # Example
+///
+/// Drive the intensity of a fire effect from the health of its entity:
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// #[derive(Component)]
+/// struct Health(f32);
+///
+/// fn setup(mut commands: Commands, fire: Handle<EffectAsset>) {
+///     commands.spawn((
+///         ParticleEffectBundle::new(fire),
+///         Health(100.),
+///         BindProperty::<Health>::new("intensity", |health| {
+///             (1. - health.0 / 100.).into()
+///         }),
+///     ));
+/// }
+///
+/// App::new().add_plugins(PropertyBindingPlugin::<Health>::default());
+/// ```
+///
+/// # Panics
+///
+/// The binding panics if the value returned by a binding function has a
+/// different type than the value already stored for that property, like
+/// [`EffectProperties::set()`] does.
+///
+/// [`with_binding()`]: BindProperty::with_binding
+#[derive(Component)]
+pub struct BindProperty<T: Component> {
+    bindings: Vec<(String, BindingFn<T>)>,
+}
+
+impl<T: Component> BindProperty<T> {
+    /// Create a new binding of a property to the component `T`.
+    pub fn new(
+        name: impl Into<String>,
+        binding: impl Fn(&T) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            bindings: vec![(name.into(), Box::new(binding))],
+        }
+    }
+
+    /// Bind an additional property to the component `T`.
+    pub fn with_binding(
+        mut self,
+        name: impl Into<String>,
+        binding: impl Fn(&T) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        self.bindings.push((name.into(), Box::new(binding)));
+        self
+    }
+
+    /// Get the names of the bound properties.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.bindings.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+impl<T: Component> std::fmt::Debug for BindProperty<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BindProperty")
+            .field("names", &self.names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Plugin updating the properties bound to the component `T` with a
+/// [`BindProperty<T>`].
+///
+/// Add one instance of this plugin per component type used with
+/// [`BindProperty`]. The bound properties are updated in the
+/// [`EffectSystems::TickSpawners`] set, before [`tick_spawners()`], so they
+/// are extracted to the render world the same frame.
+pub struct PropertyBindingPlugin<T: Component> {
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Component> Default for PropertyBindingPlugin<T> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Component> Plugin for PropertyBindingPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            bind_properties::<T>
+                .in_set(EffectSystems::TickSpawners)
+                .before(tick_spawners),
+        );
+    }
+}
+
+/// Update the properties bound to the component `T` with a
+/// [`BindProperty<T>`].
+///
+/// This system is added by the [`PropertyBindingPlugin<T>`].
+pub fn bind_properties<T: Component>(
+    mut query: Query<(&T, &BindProperty<T>, &mut EffectProperties)>,
+) {
+    trace!("bind_properties<{}>", std::any::type_name::<T>());
+
+    for (component, bind, mut properties) in query.iter_mut() {
+        for (name, binding) in &bind.bindings {
+            let value = binding(component);
+            properties = EffectProperties::set_if_changed(properties, name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component)]
+    struct Health(f32);
+
+    #[test]
+    fn bind_property() {
+        let mut app = App::new();
+        app.add_plugins(PropertyBindingPlugin::<Health>::default());
+
+        let entity = app
+            .world
+            .spawn((
+                Health(25.),
+                EffectProperties::default(),
+                BindProperty::<Health>::new("intensity", |health| (health.0 / 100.).into())
+                    .with_binding("alive", |health| (health.0 > 0.).into()),
+            ))
+            .id();
+        app.update();
+
+        let properties = app.world.get::<EffectProperties>(entity).unwrap();
+        assert_eq!(
+            properties.get_stored("intensity"),
+            Some(Value::from(0.25_f32))
+        );
+        assert_eq!(properties.get_stored("alive"), Some(Value::from(true)));
+
+        app.world.get_mut::<Health>(entity).unwrap().0 = 0.;
+        app.update();
+
+        let properties = app.world.get::<EffectProperties>(entity).unwrap();
+        assert_eq!(properties.get_stored("intensity"), Some(Value::from(0_f32)));
+        assert_eq!(properties.get_stored("alive"), Some(Value::from(false)));
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+
+use crate::{render::ReadbackChannel, ParticleEffect};
+
+/// Bounds of a single effect instance read back from GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BoundsReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    pub entity: Entity,
+    /// Minimum and maximum corners of the bounds of the alive particles, in
+    /// the local space of the effect, or `None` if no particle is alive.
+    pub bounds: Option<(Vec3, Vec3)>,
+}
+
+/// Decode an unsigned integer written by `order_f32()` in `vfx_update.wgsl`
+/// back into the float it was mapped from.
+fn unorder_f32(value: u32) -> f32 {
+    if value & 0x8000_0000 != 0 {
+        f32::from_bits(value & 0x7FFF_FFFF)
+    } else {
+        f32::from_bits(!value)
+    }
+}
+
+/// Decode the bounds of a single effect instance, as accumulated on GPU by
+/// `accumulate_bounds()` in `vfx_update.wgsl`.
+///
+/// Returns the minimum and maximum corners of the bounds, or `None` if no
+/// position was accumulated.
+pub(crate) fn decode_bounds(words: &[u32; 8]) -> Option<(Vec3, Vec3)> {
+    // The maximum of any accumulated position is non-zero
+    if words[4] == 0 {
+        return None;
+    }
+    let min = Vec3::new(
+        unorder_f32(!words[0]),
+        unorder_f32(!words[1]),
+        unorder_f32(!words[2]),
+    );
+    let max = Vec3::new(
+        unorder_f32(words[4]),
+        unorder_f32(words[5]),
+        unorder_f32(words[6]),
+    );
+    Some((min, max))
+}
+
+/// Update the [`Aabb`] of the effect instances computing their bounds on GPU.
+///
+/// The [`Aabb`] encloses all the alive particles as well as the origin of the
+/// effect, where new particles are generally spawned, so that an instance
+/// doesn't stay culled once it has no particle left. Instances without any
+/// alive particle have their [`Aabb`] removed, and are never culled.
+///
+/// See [`EffectAsset::with_gpu_bounds()`] for details.
+///
+/// [`EffectAsset::with_gpu_bounds()`]: crate::EffectAsset::with_gpu_bounds
+pub(crate) fn update_gpu_bounds(
+    mut commands: Commands,
+    channel: Res<ReadbackChannel<BoundsReport>>,
+    effects: Query<(), With<ParticleEffect>>,
+) {
+    for report in channel.take() {
+        // The effect may have been despawned since the readback was issued
+        if !effects.contains(report.entity) {
+            continue;
+        }
+        match report.bounds {
+            Some((min, max)) => {
+                let aabb = Aabb::from_min_max(min.min(Vec3::ZERO), max.max(Vec3::ZERO));
+                commands.entity(report.entity).insert(aabb);
+            }
+            None => {
+                commands.entity(report.entity).remove::<Aabb>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CPU mirror of `order_f32()` in `vfx_update.wgsl`.
+    fn order_f32(value: f32) -> u32 {
+        let bits = value.to_bits();
+        if bits & 0x8000_0000 != 0 {
+            !bits
+        } else {
+            bits | 0x8000_0000
+        }
+    }
+
+    /// CPU mirror of `accumulate_bounds()` in `vfx_update.wgsl`.
+    fn accumulate_bounds(words: &mut [u32; 8], position: Vec3) {
+        for (i, value) in position.to_array().into_iter().enumerate() {
+            words[i] = words[i].max(!order_f32(value));
+            words[4 + i] = words[4 + i].max(order_f32(value));
+        }
+    }
+
+    #[test]
+    fn order() {
+        let values = [f32::NEG_INFINITY, -3.5, -1e-8, -0., 0., 1e-8, 2., f32::MAX];
+        for pair in values.windows(2) {
+            assert!(order_f32(pair[0]) <= order_f32(pair[1]));
+        }
+        for value in values {
+            assert_eq!(unorder_f32(order_f32(value)), value);
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let mut words = [0; 8];
+        assert_eq!(decode_bounds(&words), None);
+
+        accumulate_bounds(&mut words, Vec3::new(1., -2., 3.));
+        accumulate_bounds(&mut words, Vec3::new(-4., 5., 0.5));
+        assert_eq!(
+            decode_bounds(&words),
+            Some((Vec3::new(-4., -2., 0.5), Vec3::new(1., 5., 3.)))
+        );
+    }
+
+    #[test]
+    fn update_aabb() {
+        let mut app = App::new();
+        app.init_resource::<ReadbackChannel<BoundsReport>>()
+            .add_systems(Update, update_gpu_bounds);
+
+        let entity = app.world.spawn(ParticleEffect::default()).id();
+
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<BoundsReport>>()
+            .clone();
+        channel.send([BoundsReport {
+            entity,
+            bounds: Some((Vec3::new(1., -2., 3.), Vec3::new(4., 5., 6.))),
+        }]);
+        app.update();
+        let aabb = app.world.get::<Aabb>(entity).unwrap();
+        assert_eq!(
+            *aabb,
+            Aabb::from_min_max(Vec3::new(0., -2., 0.), Vec3::new(4., 5., 6.))
+        );
+
+        channel.send([BoundsReport {
+            entity,
+            bounds: None,
+        }]);
+        app.update();
+        assert!(app.world.get::<Aabb>(entity).is_none());
+    }
+}
@@ -0,0 +1,276 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{EffectAsset, EffectSimulation, EffectSpawner, ParticleEffect};
+
+/// Priority class of an effect instance relative to the global particle
+/// budget.
+///
+/// When the [`ParticleBudget`] is exceeded, the spawn counts of effects are
+/// scaled down starting from the lowest priority class, until the estimated
+/// number of live particles fits the budget again. Effects with a
+/// [`BudgetPriority::Critical`] priority are never scaled down.
+///
+/// Add this component to the same entity as the [`ParticleEffect`] to change
+/// its priority. Effect instances without this component use the default
+/// [`BudgetPriority::Medium`] priority.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Component, Reflect)]
+#[reflect(Component)]
+pub enum BudgetPriority {
+    /// Lowest priority, scaled down first. Use for purely cosmetic effects.
+    Low,
+    /// Default priority.
+    #[default]
+    Medium,
+    /// High priority, scaled down only if scaling down all lower priorities
+    /// was not enough.
+    High,
+    /// Critical priority, never scaled down. Use for effects conveying
+    /// gameplay information.
+    Critical,
+}
+
+impl BudgetPriority {
+    /// All priority classes, from lowest to highest.
+    pub const ALL: [BudgetPriority; 4] = [
+        BudgetPriority::Low,
+        BudgetPriority::Medium,
+        BudgetPriority::High,
+        BudgetPriority::Critical,
+    ];
+}
+
+/// Global budget of live particles across all effects.
+///
+/// The number of live particles is only known on GPU, so the budget works from
+/// a CPU estimate built from the spawn count of each effect instance. The
+/// estimate decays each frame according to [`particle_lifetime`], which should
+/// be set to the typical lifetime of particles in the application, and is
+/// capped by the capacity of each effect.
+///
+/// When the estimated total, including the particles about to be spawned this
+/// frame, exceeds [`max_particles`], the spawn count of each [`EffectSpawner`]
+/// is scaled down by priority class (see [`BudgetPriority`]) during the
+/// [`EffectSystems::TickSpawners`] set, after [`tick_spawners()`] calculated
+/// it. The whole excess is removed from the particles spawned this frame, as
+/// far as possible without scaling down [`BudgetPriority::Critical`] effects;
+/// the particles already alive are not affected.
+///
+/// By default the budget is unlimited.
+///
+/// [`particle_lifetime`]: crate::ParticleBudget::particle_lifetime
+/// [`max_particles`]: crate::ParticleBudget::max_particles
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+/// [`tick_spawners()`]: crate::tick_spawners
+#[derive(Debug, Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct ParticleBudget {
+    /// Maximum number of live particles across all effects.
+    pub max_particles: u32,
+    /// Typical lifetime of particles, in seconds, used to estimate the number
+    /// of live particles from the spawn counts.
+    pub particle_lifetime: f32,
+    /// Estimated number of live particles per effect instance.
+    #[reflect(ignore)]
+    estimates: HashMap<Entity, f32>,
+    /// Spawn scale applied during the last frame, per priority class.
+    scales: [f32; 4],
+}
+
+impl Default for ParticleBudget {
+    fn default() -> Self {
+        Self {
+            max_particles: u32::MAX,
+            particle_lifetime: 1.,
+            estimates: default(),
+            scales: [1.; 4],
+        }
+    }
+}
+
+impl ParticleBudget {
+    /// Create a new budget with the given maximum number of live particles.
+    pub fn new(max_particles: u32) -> Self {
+        Self {
+            max_particles,
+            ..default()
+        }
+    }
+
+    /// Set the typical particle lifetime used to estimate the number of live
+    /// particles, in seconds.
+    pub fn with_particle_lifetime(mut self, particle_lifetime: f32) -> Self {
+        self.particle_lifetime = particle_lifetime;
+        self
+    }
+
+    /// Get the estimated total number of live particles across all effects.
+    pub fn estimated_particles(&self) -> u32 {
+        self.estimates.values().sum::<f32>() as u32
+    }
+
+    /// Get the spawn scale applied during the last frame to all the effects
+    /// of the given priority class.
+    ///
+    /// A value of `1.0` means the spawn counts were not scaled down, while a
+    /// value of `0.0` means no particle was spawned.
+    pub fn scale(&self, priority: BudgetPriority) -> f32 {
+        self.scales[priority as usize]
+    }
+
+    /// Calculate the spawn scale of each priority class given the estimated
+    /// number of live particles and the number of particles to spawn this
+    /// frame per class.
+    ///
+    /// Only the spawn counts can be scaled down, so the excess is removed from
+    /// them, starting from the lowest priority class.
+    fn calc_scales(&mut self, class_alive: &[f32; 4], class_spawn: &[f32; 4]) {
+        self.scales = [1.; 4];
+        let total: f32 = class_alive.iter().chain(class_spawn.iter()).sum();
+        let mut excess = total - self.max_particles as f32;
+        for priority in BudgetPriority::ALL {
+            if excess <= 0. || priority == BudgetPriority::Critical {
+                break;
+            }
+            let spawn = class_spawn[priority as usize];
+            if spawn <= 0. {
+                continue;
+            }
+            let reduction = excess.min(spawn);
+            self.scales[priority as usize] = 1. - reduction / spawn;
+            excess -= reduction;
+        }
+    }
+}
+
+/// Scale down the spawn count of all effects to enforce the global
+/// [`ParticleBudget`].
+///
+/// This system runs in the [`EffectSystems::TickSpawners`] set, after
+/// [`tick_spawners()`].
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+/// [`tick_spawners()`]: crate::tick_spawners
+pub fn apply_particle_budget(
+    mut budget: ResMut<ParticleBudget>,
+    time: Res<Time<EffectSimulation>>,
+    effects: Res<Assets<EffectAsset>>,
+    mut query: Query<(
+        Entity,
+        &ParticleEffect,
+        &mut EffectSpawner,
+        Option<&BudgetPriority>,
+    )>,
+) {
+    trace!("apply_particle_budget");
+
+    if budget.max_particles == u32::MAX {
+        return;
+    }
+
+    let dt = time.delta_seconds();
+    let decay = if budget.particle_lifetime > 0. {
+        (-dt / budget.particle_lifetime).exp()
+    } else {
+        0.
+    };
+
+    // Decay the estimates from the previous frame, and drop removed effects
+    budget.estimates.retain(|entity, estimate| {
+        *estimate *= decay;
+        query.contains(*entity)
+    });
+
+    // Accumulate the number of live particles and of particles to spawn this frame
+    // per class
+    let mut class_alive = [0_f32; 4];
+    let mut class_spawn = [0_f32; 4];
+    for (entity, _, spawner, maybe_priority) in query.iter() {
+        let priority = maybe_priority.copied().unwrap_or_default();
+        class_alive[priority as usize] += budget.estimates.get(&entity).copied().unwrap_or(0.);
+        class_spawn[priority as usize] += total_spawn_count(spawner) as f32;
+    }
+
+    budget.calc_scales(&class_alive, &class_spawn);
+
+    // Scale the spawn counts, and update the estimates
+    for (entity, effect, mut spawner, maybe_priority) in query.iter_mut() {
+        let priority = maybe_priority.copied().unwrap_or_default();
+        let scale = budget.scale(priority);
+        if scale < 1. {
+            scale_spawn_counts(&mut spawner, scale);
+        }
+        let capacity = effects
+            .get(&effect.handle)
+            .map_or(u32::MAX, |asset| asset.capacities().iter().sum())
+            as f32;
+        let estimate = budget.estimates.entry(entity).or_insert(0.);
+        *estimate = (*estimate + total_spawn_count(&spawner) as f32).min(capacity);
+    }
+}
+
+/// Total number of particles an effect spawns this frame, including the ones
+/// spawned by its additional emitters.
+fn total_spawn_count(spawner: &EffectSpawner) -> u32 {
+    spawner.spawn_count
+        + spawner
+            .emitters()
+            .iter()
+            .map(|emitter| emitter.spawn_count)
+            .sum::<u32>()
+}
+
+/// Scale down the spawn count of an effect and of all its additional emitters.
+fn scale_spawn_counts(spawner: &mut EffectSpawner, scale: f32) {
+    spawner.spawn_count = (spawner.spawn_count as f32 * scale).floor() as u32;
+    for emitter in spawner.emitters_mut() {
+        emitter.spawn_count = (emitter.spawn_count as f32 * scale).floor() as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Module, Spawner};
+
+    #[test]
+    fn calc_scales() {
+        let mut budget = ParticleBudget::new(100);
+        budget.calc_scales(&[10., 40., 20., 10.], &[0., 10., 0., 0.]);
+        assert_eq!(budget.scales, [1.; 4]);
+
+        // Excess of 20, taken from the 40 particles spawned by Low
+        budget.calc_scales(&[30., 40., 10., 0.], &[40., 0., 0., 0.]);
+        assert_eq!(budget.scale(BudgetPriority::Low), 0.5);
+        assert_eq!(budget.scale(BudgetPriority::Medium), 1.);
+
+        // Excess of 20, all 10 particles spawned by Low, then half of the 20
+        // spawned by Medium
+        budget.calc_scales(&[30., 40., 20., 0.], &[10., 20., 0., 0.]);
+        assert_eq!(budget.scale(BudgetPriority::Low), 0.);
+        assert_eq!(budget.scale(BudgetPriority::Medium), 0.5);
+        assert_eq!(budget.scale(BudgetPriority::High), 1.);
+
+        // The live particles alone exceed the budget; nothing is spawned
+        budget.calc_scales(&[200., 0., 0., 0.], &[10., 10., 10., 10.]);
+        assert_eq!(budget.scales, [0., 0., 0., 1.]);
+
+        // Critical is never scaled
+        budget.calc_scales(&[0., 0., 0., 0.], &[0., 0., 0., 500.]);
+        assert_eq!(budget.scales, [1.; 4]);
+    }
+
+    #[test]
+    fn emitter_spawn_counts() {
+        let asset = EffectAsset::new(vec![256, 256], Spawner::rate(5.0.into()), Module::default())
+            .with_emitter(1, Spawner::rate(10.0.into()));
+        let mut spawner = EffectSpawner::new(&asset);
+        spawner.spawn_count = 5;
+        spawner.emitters_mut()[0].spawn_count = 10;
+        assert_eq!(total_spawn_count(&spawner), 15);
+
+        scale_spawn_counts(&mut spawner, 0.5);
+        assert_eq!(spawner.spawn_count, 2);
+        assert_eq!(spawner.emitters()[0].spawn_count, 5);
+        assert_eq!(total_spawn_count(&spawner), 7);
+    }
+}
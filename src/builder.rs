@@ -0,0 +1,278 @@
+use std::marker::PhantomData;
+
+use crate::{
+    Attribute, EffectAsset, ExprHandle, Modifier, ParticleGroupSet, RenderModifier,
+    SetAttributeModifier,
+};
+
+/// Stage of an [`EffectAssetBuilder`] adding init modifiers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InitStage;
+
+/// Stage of an [`EffectAssetBuilder`] adding update modifiers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateStage;
+
+/// Stage of an [`EffectAssetBuilder`] adding render modifiers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderStage;
+
+/// State of an [`EffectAssetBuilder`] which didn't initialize the lifetime of
+/// the particles yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LifetimeUnset;
+
+/// State of an [`EffectAssetBuilder`] which initialized the lifetime of the
+/// particles, or explicitly opted out of it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LifetimeSet;
+
+/// Typed builder for the modifiers of an [`EffectAsset`].
+///
+/// The builder adds the modifiers of an effect in distinct stages, in the order
+/// they execute: first the init modifiers, then the update modifiers, and
+/// finally the render modifiers. Each stage only exposes the methods adding
+/// the modifiers of that stage, and a stage can't be reopened once the builder
+/// moved to the next one, so the modifiers can't be misordered.
+///
+/// The builder also checks at compile time that the lifetime of the particles
+/// is initialized, with [`init_lifetime()`], before the asset can be built.
+/// Effects whose particles never die, or are only killed by some modifier, need
+/// to opt out explicitly with [`immortal()`].
+///
+/// Modifiers can be scoped to some particle groups with [`in_groups()`]; all
+/// modifiers added inside the scope only apply to those groups.
+///
+/// ```
+/// # use bevy::math::{Vec3, Vec4};
+/// # use bevy_hanabi::*;
+/// let mut module = Module::default();
+/// let lifetime = module.lit(2.);
+/// let accel = module.lit(Vec3::new(0., -9.81, 0.));
+/// let trail_lifetime = module.lit(0.2);
+///
+/// let asset = EffectAssetBuilder::new(
+///     EffectAsset::new(vec![256, 4096], Spawner::rate(30.0.into()), module)
+///         .with_name("comet"),
+/// )
+/// .init_lifetime(lifetime)
+/// .update_stage()
+/// .in_groups(ParticleGroupSet::single(0), |stage| {
+///     stage.update(AccelModifier::new(accel)).update(CloneModifier::new(0.05, 1))
+/// })
+/// .in_groups(ParticleGroupSet::single(1), |stage| {
+///     stage.update(SetAttributeModifier::new(Attribute::LIFETIME, trail_lifetime))
+/// })
+/// .render_stage()
+/// .render(SetColorModifier {
+///     color: Vec4::ONE.into(),
+/// })
+/// .build();
+/// ```
+///
+/// Forgetting to initialize the lifetime fails to compile:
+///
+/// ```compile_fail
+/// # use bevy_hanabi::*;
+/// let asset = EffectAssetBuilder::new(EffectAsset::default())
+///     .render_stage()
+///     .build();
+/// ```
+///
+/// [`init_lifetime()`]: crate::EffectAssetBuilder::init_lifetime
+/// [`immortal()`]: crate::EffectAssetBuilder::immortal
+/// [`in_groups()`]: crate::EffectAssetBuilder::in_groups
+pub struct EffectAssetBuilder<S = InitStage, L = LifetimeUnset> {
+    /// The asset being built.
+    asset: EffectAsset,
+    /// Particle groups the modifiers are added to.
+    groups: ParticleGroupSet,
+    /// Typed state of the builder.
+    state: PhantomData<(S, L)>,
+}
+
+impl EffectAssetBuilder {
+    /// Create a new builder adding modifiers to an existing asset.
+    ///
+    /// The asset is usually created with [`EffectAsset::new()`], and configured
+    /// with the various `with_*()` methods of [`EffectAsset`], before being
+    /// passed to the builder. Any modifier it already contains is kept, before
+    /// the modifiers added by the builder.
+    pub fn new(asset: EffectAsset) -> Self {
+        Self {
+            asset,
+            groups: ParticleGroupSet::all(),
+            state: PhantomData,
+        }
+    }
+}
+
+impl<S, L> EffectAssetBuilder<S, L> {
+    /// Move the builder to another typed state.
+    fn into_state<S2, L2>(self) -> EffectAssetBuilder<S2, L2> {
+        EffectAssetBuilder {
+            asset: self.asset,
+            groups: self.groups,
+            state: PhantomData,
+        }
+    }
+
+    /// Add modifiers to a subset of the particle groups only.
+    ///
+    /// The modifiers added by `f` to the builder it receives only apply to the
+    /// given `groups`. Scopes can't be nested; an inner scope replaces the
+    /// groups of the outer one.
+    pub fn in_groups(self, groups: ParticleGroupSet, f: impl FnOnce(Self) -> Self) -> Self {
+        let all = self.groups;
+        let mut builder = f(Self { groups, ..self });
+        builder.groups = all;
+        builder
+    }
+
+    /// Move to the render stage, skipping any intermediate stage.
+    pub fn render_stage(self) -> EffectAssetBuilder<RenderStage, L> {
+        self.into_state()
+    }
+}
+
+impl<L> EffectAssetBuilder<InitStage, L> {
+    /// Add an init modifier.
+    ///
+    /// See [`EffectAsset::init_groups()`].
+    pub fn init<M>(self, modifier: M) -> Self
+    where
+        M: Modifier + Send + Sync,
+    {
+        let groups = self.groups;
+        Self {
+            asset: self.asset.init_groups(modifier, groups),
+            ..self
+        }
+    }
+
+    /// Initialize the lifetime of the particles, in seconds.
+    ///
+    /// This adds a [`SetAttributeModifier`] initializing the
+    /// [`Attribute::LIFETIME`]. If no modifier initializes the
+    /// [`Attribute::AGE`] of the particles by the time the asset is built, the
+    /// age is initialized to zero.
+    pub fn init_lifetime(self, lifetime: ExprHandle) -> EffectAssetBuilder<InitStage, LifetimeSet> {
+        self.init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+            .into_state()
+    }
+
+    /// Move to the update stage.
+    pub fn update_stage(self) -> EffectAssetBuilder<UpdateStage, L> {
+        self.into_state()
+    }
+}
+
+impl EffectAssetBuilder<InitStage, LifetimeUnset> {
+    /// Opt out of initializing the lifetime of the particles.
+    ///
+    /// The particles never die, unless killed by some modifier like the
+    /// [`KillAabbModifier`].
+    ///
+    /// [`KillAabbModifier`]: crate::KillAabbModifier
+    pub fn immortal(self) -> EffectAssetBuilder<InitStage, LifetimeSet> {
+        self.into_state()
+    }
+}
+
+impl<L> EffectAssetBuilder<UpdateStage, L> {
+    /// Add an update modifier.
+    ///
+    /// See [`EffectAsset::update_groups()`].
+    pub fn update<M>(self, modifier: M) -> Self
+    where
+        M: Modifier + Send + Sync,
+    {
+        let groups = self.groups;
+        Self {
+            asset: self.asset.update_groups(modifier, groups),
+            ..self
+        }
+    }
+}
+
+impl<L> EffectAssetBuilder<RenderStage, L> {
+    /// Add a render modifier.
+    ///
+    /// See [`EffectAsset::render_groups()`].
+    pub fn render<M>(self, modifier: M) -> Self
+    where
+        M: RenderModifier + Send + Sync,
+    {
+        let groups = self.groups;
+        Self {
+            asset: self.asset.render_groups(modifier, groups),
+            ..self
+        }
+    }
+}
+
+impl<S> EffectAssetBuilder<S, LifetimeSet> {
+    /// Build the asset.
+    pub fn build(self) -> EffectAsset {
+        let mut asset = self.asset;
+        let inits = |asset: &EffectAsset, attr: Attribute| {
+            asset
+                .init_modifiers()
+                .any(|modifier| modifier.attributes().contains(&attr))
+        };
+        if inits(&asset, Attribute::LIFETIME) && !inits(&asset, Attribute::AGE) {
+            let age = asset.module_mut().lit(0.);
+            asset = asset.init_groups(
+                SetAttributeModifier::new(Attribute::AGE, age),
+                ParticleGroupSet::all(),
+            );
+        }
+        asset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec3, Vec4};
+
+    use super::*;
+    use crate::{AccelModifier, Module, SetColorModifier, Spawner};
+
+    #[test]
+    fn build() {
+        let mut module = Module::default();
+        let lifetime = module.lit(2.);
+        let accel = module.lit(Vec3::Y);
+        let asset = EffectAssetBuilder::new(EffectAsset::new(
+            vec![32, 32],
+            Spawner::rate(5.0.into()),
+            module,
+        ))
+        .init_lifetime(lifetime)
+        .update_stage()
+        .in_groups(ParticleGroupSet::single(1), |stage| {
+            stage.update(AccelModifier::new(accel))
+        })
+        .render_stage()
+        .render(SetColorModifier {
+            color: Vec4::ONE.into(),
+        })
+        .build();
+
+        assert_eq!(asset.init_modifiers_for_group(0).count(), 2);
+        assert_eq!(asset.init_modifiers_for_group(1).count(), 2);
+        assert_eq!(asset.update_modifiers_for_group(0).count(), 0);
+        assert_eq!(asset.update_modifiers_for_group(1).count(), 1);
+        assert_eq!(asset.render_modifiers_for_group(0).count(), 1);
+        assert_eq!(asset.render_modifiers_for_group(1).count(), 1);
+        let layout = asset.particle_layout();
+        assert!(layout.contains(Attribute::AGE));
+        assert!(layout.contains(Attribute::LIFETIME));
+
+        // Immortal particles don't get an age
+        let asset = EffectAssetBuilder::new(EffectAsset::default())
+            .immortal()
+            .build();
+        assert_eq!(asset.modifiers().count(), 0);
+    }
+}
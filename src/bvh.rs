@@ -0,0 +1,312 @@
+use bevy::{
+    ecs::event::ManualEventReader,
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+/// Width in texels of the texture the collision BVH is stored into.
+const BVH_TEXTURE_WIDTH: usize = 1024;
+
+/// Maximum number of triangles in a leaf node of the collision BVH.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// Maximum depth of the collision BVH. This must be less than half the size of
+/// the traversal stack of `raycast_bvh()` in `vfx_update.wgsl`.
+const MAX_DEPTH: usize = 15;
+
+/// Marker component for the meshes particles collide with via the
+/// [`CollideMeshModifier`].
+///
+/// The triangles of all the entities with this component, a [`Handle<Mesh>`],
+/// and a [`GlobalTransform`] are gathered in world space into a single bounding
+/// volume hierarchy (BVH), which is uploaded to GPU for the update pass to
+/// raycast particles against. Only meshes with the
+/// [`PrimitiveTopology::TriangleList`] topology are supported.
+///
+/// The BVH is rebuilt on CPU each time a collider is added, removed, moved, or
+/// its mesh changes. This is intended for a few static or rarely moving meshes
+/// of moderate complexity, like the level geometry around some precise impact
+/// effects; prefer a simplified collision mesh over the render mesh.
+///
+/// [`CollideMeshModifier`]: crate::CollideMeshModifier
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct ParticleCollider;
+
+/// Collision BVH built from the [`ParticleCollider`] meshes.
+///
+/// This resource is managed automatically. The BVH is stored into a float
+/// image, which is uploaded to GPU like any other image. It starts with a
+/// header texel containing the number of nodes and triangles, followed by two
+/// texels per node and three texels per triangle.
+#[derive(Debug, Default, Clone, Resource, ExtractResource)]
+pub struct CollisionBvh {
+    /// The image storing the BVH, if any collider was found.
+    pub(crate) image: Option<Handle<Image>>,
+    /// The number of triangles in the BVH.
+    triangle_count: usize,
+}
+
+impl CollisionBvh {
+    /// The number of triangles in the BVH, from all colliders.
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+}
+
+/// A triangle of the collision BVH, in world space.
+type Triangle = [Vec3; 3];
+
+/// Node of the collision BVH being built.
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    /// Index of the right child for an internal node, whose left child
+    /// immediately follows it, or index of the first triangle for a leaf.
+    index: usize,
+    /// Number of triangles of a leaf, or zero for an internal node.
+    count: usize,
+}
+
+/// Recursively build the BVH nodes of a range of triangles, splitting them at
+/// the median of the longest axis of their centroids.
+fn build_node(triangles: &mut [Triangle], first: usize, depth: usize, nodes: &mut Vec<BvhNode>) {
+    let (min, max) = triangles.iter().flatten().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), &v| (min.min(v), max.max(v)),
+    );
+    let node_index = nodes.len();
+    nodes.push(BvhNode {
+        min,
+        max,
+        index: first,
+        count: triangles.len(),
+    });
+    if triangles.len() <= MAX_LEAF_TRIANGLES || depth >= MAX_DEPTH {
+        return;
+    }
+
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let mid = triangles.len() / 2;
+    triangles.select_nth_unstable_by(mid, |a, b| {
+        let ca = a[0][axis] + a[1][axis] + a[2][axis];
+        let cb = b[0][axis] + b[1][axis] + b[2][axis];
+        ca.total_cmp(&cb)
+    });
+    let (left, right) = triangles.split_at_mut(mid);
+    build_node(left, first, depth + 1, nodes);
+    nodes[node_index].index = nodes.len();
+    nodes[node_index].count = 0;
+    build_node(right, first + mid, depth + 1, nodes);
+}
+
+/// Build the texels of the BVH of a set of triangles.
+///
+/// See [`CollisionBvh`] for the layout.
+fn build_bvh(mut triangles: Vec<Triangle>) -> Vec<[f32; 4]> {
+    let mut nodes = vec![];
+    if !triangles.is_empty() {
+        build_node(&mut triangles, 0, 0, &mut nodes);
+    }
+
+    let mut texels = Vec::with_capacity(1 + nodes.len() * 2 + triangles.len() * 3);
+    texels.push([nodes.len() as f32, triangles.len() as f32, 0., 0.]);
+    for node in &nodes {
+        texels.push([node.min.x, node.min.y, node.min.z, node.index as f32]);
+        texels.push([node.max.x, node.max.y, node.max.z, node.count as f32]);
+    }
+    for triangle in &triangles {
+        for v in triangle {
+            texels.push([v.x, v.y, v.z, 0.]);
+        }
+    }
+    texels
+}
+
+/// Append the triangles of a mesh, transformed into world space.
+fn append_triangles(mesh: &Mesh, transform: &GlobalTransform, triangles: &mut Vec<Triangle>) {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let affine = transform.affine();
+    let vertex = |index: usize| affine.transform_point3(Vec3::from(positions[index]));
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    triangles.extend(
+        indices
+            .chunks_exact(3)
+            .filter(|tri| tri.iter().all(|&i| i < positions.len()))
+            .map(|tri| [vertex(tri[0]), vertex(tri[1]), vertex(tri[2])]),
+    );
+}
+
+/// Rebuild the [`CollisionBvh`] when the [`ParticleCollider`] entities or
+/// their meshes change.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_collision_bvh(
+    mut bvh: ResMut<CollisionBvh>,
+    images: Option<ResMut<Assets<Image>>>,
+    meshes: Option<Res<Assets<Mesh>>>,
+    mesh_events: Option<Res<Events<AssetEvent<Mesh>>>>,
+    mut mesh_event_reader: Local<ManualEventReader<AssetEvent<Mesh>>>,
+    mut removed: RemovedComponents<ParticleCollider>,
+    changed: Query<
+        (),
+        (
+            With<ParticleCollider>,
+            Or<(
+                Changed<ParticleCollider>,
+                Changed<GlobalTransform>,
+                Changed<Handle<Mesh>>,
+            )>,
+        ),
+    >,
+    colliders: Query<(&Handle<Mesh>, &GlobalTransform), With<ParticleCollider>>,
+) {
+    let (Some(mut images), Some(meshes)) = (images, meshes) else {
+        return;
+    };
+
+    // Check if any collider mesh was loaded or modified, consuming all events
+    let mut mesh_changed = false;
+    if let Some(events) = mesh_events {
+        for event in mesh_event_reader.read(&events) {
+            if let AssetEvent::Added { id }
+            | AssetEvent::Modified { id }
+            | AssetEvent::LoadedWithDependencies { id } = event
+            {
+                mesh_changed |= colliders.iter().any(|(handle, _)| handle.id() == *id);
+            }
+        }
+    }
+    let removed = removed.read().count() > 0;
+    if !mesh_changed && !removed && changed.is_empty() {
+        return;
+    }
+
+    let mut triangles = vec![];
+    for (handle, transform) in &colliders {
+        if let Some(mesh) = meshes.get(handle) {
+            append_triangles(mesh, transform, &mut triangles);
+        }
+    }
+    trace!(
+        "Rebuilding collision BVH with {} triangles",
+        triangles.len()
+    );
+    bvh.triangle_count = triangles.len();
+    if triangles.is_empty() {
+        if let Some(image) = bvh.image.take() {
+            images.remove(&image);
+        }
+        return;
+    }
+
+    let mut texels = build_bvh(triangles);
+    let height = texels.len().div_ceil(BVH_TEXTURE_WIDTH);
+    texels.resize(height * BVH_TEXTURE_WIDTH, [0.; 4]);
+    let image = Image::new(
+        Extent3d {
+            width: BVH_TEXTURE_WIDTH as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        bytemuck::cast_slice(&texels).to_vec(),
+        TextureFormat::Rgba32Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    match &bvh.image {
+        Some(handle) => {
+            images.insert(handle, image);
+        }
+        None => bvh.image = Some(images.add(image)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        assert_eq!(build_bvh(vec![]), vec![[0., 0., 0., 0.]]);
+
+        // A row of 10 unit triangles along X
+        let triangles: Vec<Triangle> = (0..10)
+            .map(|i| {
+                let x = i as f32;
+                [
+                    Vec3::new(x, 0., 0.),
+                    Vec3::new(x + 1., 0., 0.),
+                    Vec3::new(x, 1., 0.),
+                ]
+            })
+            .collect();
+        let texels = build_bvh(triangles);
+        let node_count = texels[0][0] as usize;
+        assert_eq!(texels[0][1], 10.);
+        assert_eq!(texels.len(), 1 + node_count * 2 + 30);
+
+        // The root encloses all triangles
+        assert_eq!(texels[1], [0., 0., 0., texels[1][3]]);
+        assert_eq!(texels[2], [10., 1., 0., 0.]);
+
+        // Each triangle is referenced by exactly one leaf, which encloses it
+        let mut referenced = [0; 10];
+        for node in 0..node_count {
+            let (min, max) = (texels[1 + node * 2], texels[2 + node * 2]);
+            let count = max[3] as usize;
+            for t in min[3] as usize..min[3] as usize + count {
+                referenced[t] += 1;
+                for v in 0..3 {
+                    let p = texels[1 + node_count * 2 + t * 3 + v];
+                    assert!((0..3).all(|k| p[k] >= min[k] && p[k] <= max[k]));
+                }
+            }
+        }
+        assert!(referenced.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn append() {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [1., 1., 0.]],
+        );
+        mesh.insert_indices(Indices::U16(vec![0, 1, 2, 1, 3, 2]));
+
+        let mut triangles = vec![];
+        let transform = GlobalTransform::from_translation(Vec3::Z);
+        append_triangles(&mesh, &transform, &mut triangles);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(
+            triangles[1],
+            [Vec3::new(1., 0., 1.), Vec3::ONE, Vec3::new(0., 1., 1.)]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use bevy::{prelude::*, render::settings::WgpuLimits, utils::thiserror::Error};
+use wgpu::DownlevelFlags;
+
+use crate::render::BASE_STORAGE_BUFFER_COUNT;
+
+/// Number of bind groups used by the simulation and rendering passes.
+const REQUIRED_BIND_GROUPS: u32 = 4;
+
+/// Size of the largest compute workgroup, used by the particle sorting passes.
+const REQUIRED_WORKGROUP_SIZE: u32 = 256;
+
+/// A GPU capability required to simulate effects on GPU, and missing on the
+/// current device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+pub enum MissingCapability {
+    /// The device doesn't support compute shaders, like WebGL2 or some OpenGL
+    /// ES 3.0 devices.
+    #[error("compute shaders are not supported")]
+    ComputeShaders,
+    /// The device supports less bind groups than required
+    /// (`Limits::max_bind_groups`).
+    #[error("{supported} bind groups are supported, but {required} are required (Limits::max_bind_groups)")]
+    BindGroups {
+        /// Number of bind groups required.
+        required: u32,
+        /// Number of bind groups supported by the device.
+        supported: u32,
+    },
+    /// The device supports less storage buffers per shader stage than required
+    /// (`Limits::max_storage_buffers_per_shader_stage`). This includes devices
+    /// which can't read storage buffers from a vertex shader.
+    #[error("{supported} storage buffers per shader stage are supported, but {required} are required (Limits::max_storage_buffers_per_shader_stage)")]
+    StorageBuffers {
+        /// Number of storage buffers required.
+        required: u32,
+        /// Number of storage buffers supported by the device.
+        supported: u32,
+    },
+    /// The device supports smaller compute workgroups than required
+    /// (`Limits::max_compute_workgroup_size_x` and
+    /// `Limits::max_compute_invocations_per_workgroup`).
+    #[error("compute workgroups of {supported} invocations are supported, but {required} are required (Limits::max_compute_invocations_per_workgroup)")]
+    WorkgroupSize {
+        /// Number of invocations per workgroup required.
+        required: u32,
+        /// Number of invocations per workgroup supported by the device.
+        supported: u32,
+    },
+    /// The device can't read storage buffers from a vertex shader
+    /// (`DownlevelFlags::VERTEX_STORAGE`), like WebGL2. The render pass reads
+    /// the particles from storage buffers.
+    ///
+    /// The render pass never writes to those storage buffers, so doesn't
+    /// require `Features::VERTEX_WRITABLE_STORAGE`, which WebGPU doesn't
+    /// support.
+    #[error(
+        "storage buffers are not supported in vertex shaders (DownlevelFlags::VERTEX_STORAGE)"
+    )]
+    VertexStorage,
+    /// There's no render device at all, like in a headless app without the
+    /// `RenderPlugin`, or with no rendering backend enabled.
+    #[error("no render device is available")]
+    RenderDevice,
+}
+
+/// Detect the capabilities required to simulate effects on GPU which are
+/// missing from a device with the given limits and downlevel flags.
+///
+/// Devices without compute shader support report all their compute limits as
+/// zero.
+pub(crate) fn detect_missing_capabilities(
+    limits: &WgpuLimits,
+    downlevel_flags: DownlevelFlags,
+) -> Vec<MissingCapability> {
+    let mut missing = vec![];
+    if limits.max_compute_workgroups_per_dimension == 0
+        || limits.max_compute_invocations_per_workgroup == 0
+    {
+        missing.push(MissingCapability::ComputeShaders);
+    } else {
+        let supported = limits
+            .max_compute_invocations_per_workgroup
+            .min(limits.max_compute_workgroup_size_x);
+        if supported < REQUIRED_WORKGROUP_SIZE {
+            missing.push(MissingCapability::WorkgroupSize {
+                required: REQUIRED_WORKGROUP_SIZE,
+                supported,
+            });
+        }
+    }
+    if limits.max_bind_groups < REQUIRED_BIND_GROUPS {
+        missing.push(MissingCapability::BindGroups {
+            required: REQUIRED_BIND_GROUPS,
+            supported: limits.max_bind_groups,
+        });
+    }
+    if limits.max_storage_buffers_per_shader_stage < BASE_STORAGE_BUFFER_COUNT {
+        missing.push(MissingCapability::StorageBuffers {
+            required: BASE_STORAGE_BUFFER_COUNT,
+            supported: limits.max_storage_buffers_per_shader_stage,
+        });
+    }
+    if !downlevel_flags.contains(DownlevelFlags::VERTEX_STORAGE) {
+        missing.push(MissingCapability::VertexStorage);
+    }
+    missing
+}
+
+/// Fallback used when the GPU device doesn't support simulating effects on
+/// GPU.
+///
+/// Insert this resource before adding the [`HanabiPlugin`] to opt into a
+/// fallback. The capabilities of the device are detected when the plugin
+/// finishes building, and the outcome is available from the
+/// [`GpuCapabilities`] resource.
+///
+/// [`HanabiPlugin`]: crate::HanabiPlugin
+#[derive(Debug, Default, Clone, Copy, PartialEq, Resource, Reflect)]
+#[reflect(Resource)]
+pub enum SimulationFallback {
+    /// Don't simulate nor render any effect. This is the default.
+    #[default]
+    Disabled,
+    /// Simulate effects on CPU.
+    ///
+    /// The CPU simulation only supports a subset of the effect features, and is
+    /// intended for simple effects with small capacities:
+    /// - only the first particle group of an effect is simulated, with its
+    ///   capacity clamped to `max_particles`;
+    /// - expressions can only use floating-point values, and the built-in
+    ///   time, delta time, and random operators;
+    /// - the supported init and update modifiers are
+    ///   [`SetAttributeModifier`], [`SetPositionSphereModifier`],
+    ///   [`SetVelocitySphereModifier`], [`AccelModifier`], and
+    ///   [`LinearDragModifier`];
+    /// - the supported render modifiers are [`SetColorModifier`],
+    ///   [`ColorOverLifetimeModifier`], [`HdrColorOverLifetimeModifier`],
+    ///   [`SetSizeModifier`], [`SizeOverLifetimeModifier`], and
+    ///   [`SizeOverLifetimeCurveModifier`];
+    /// - particles are rendered as camera-facing untextured quads.
+    ///
+    /// Any other modifier is ignored, with a warning logged once per effect
+    /// asset.
+    ///
+    /// This fallback also applies to headless apps without any render device,
+    /// where effects are simulated but not rendered. The particles of each
+    /// effect instance can be read back from its [`CpuParticles`] component.
+    /// To simulate an effect outside of any app, see [`CpuSimulation`].
+    ///
+    /// [`SetAttributeModifier`]: crate::SetAttributeModifier
+    /// [`SetPositionSphereModifier`]: crate::SetPositionSphereModifier
+    /// [`SetVelocitySphereModifier`]: crate::SetVelocitySphereModifier
+    /// [`AccelModifier`]: crate::AccelModifier
+    /// [`LinearDragModifier`]: crate::LinearDragModifier
+    /// [`SetColorModifier`]: crate::SetColorModifier
+    /// [`ColorOverLifetimeModifier`]: crate::ColorOverLifetimeModifier
+    /// [`HdrColorOverLifetimeModifier`]: crate::HdrColorOverLifetimeModifier
+    /// [`SetSizeModifier`]: crate::SetSizeModifier
+    /// [`SizeOverLifetimeModifier`]: crate::SizeOverLifetimeModifier
+    /// [`SizeOverLifetimeCurveModifier`]: crate::SizeOverLifetimeCurveModifier
+    /// [`CpuParticles`]: crate::CpuParticles
+    /// [`CpuSimulation`]: crate::CpuSimulation
+    Cpu {
+        /// Maximum number of particles simulated per effect instance.
+        max_particles: u32,
+    },
+}
+
+/// Backend simulating the particle effects.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum SimulationBackend {
+    /// Effects are simulated and rendered on GPU.
+    #[default]
+    Gpu,
+    /// Effects are simulated on CPU, because the GPU device doesn't support
+    /// the GPU simulation. See [`SimulationFallback::Cpu`].
+    Cpu,
+    /// Effects are neither simulated nor rendered, because the GPU device
+    /// doesn't support the GPU simulation and no fallback was enabled.
+    Disabled,
+}
+
+/// Capabilities of the GPU device relative to the simulation of effects.
+///
+/// This resource is inserted when the [`HanabiPlugin`] finishes building, once
+/// the GPU device is known.
+///
+/// [`HanabiPlugin`]: crate::HanabiPlugin
+#[derive(Debug, Default, Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct GpuCapabilities {
+    adapter_name: String,
+    #[reflect(ignore)]
+    missing: Vec<MissingCapability>,
+    backend: SimulationBackend,
+}
+
+impl GpuCapabilities {
+    pub(crate) fn new(
+        adapter_name: impl Into<String>,
+        missing: Vec<MissingCapability>,
+        fallback: SimulationFallback,
+    ) -> Self {
+        let backend = match (missing.is_empty(), fallback) {
+            (true, _) => SimulationBackend::Gpu,
+            (false, SimulationFallback::Cpu { .. }) => SimulationBackend::Cpu,
+            (false, SimulationFallback::Disabled) => SimulationBackend::Disabled,
+        };
+        Self {
+            adapter_name: adapter_name.into(),
+            missing,
+            backend,
+        }
+    }
+
+    /// Name of the GPU adapter.
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    /// Capabilities required to simulate effects on GPU which the device is
+    /// missing, if any.
+    pub fn missing(&self) -> &[MissingCapability] {
+        &self.missing
+    }
+
+    /// Check if the device supports simulating effects on GPU.
+    pub fn supports_gpu_simulation(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Backend simulating the effects.
+    pub fn backend(&self) -> SimulationBackend {
+        self.backend
+    }
+
+    /// Get a diagnostic message describing the missing capabilities and the
+    /// fallback in use, if any capability is missing.
+    pub(crate) fn diagnostic(&self) -> Option<String> {
+        if self.missing.is_empty() {
+            return None;
+        }
+        let mut message = format!(
+            "The GPU adapter '{}' doesn't support simulating particle effects on GPU:",
+            self.adapter_name
+        );
+        for missing in &self.missing {
+            message += &format!("\n- {}", missing);
+        }
+        message += match self.backend {
+            SimulationBackend::Cpu => "\nFalling back to simulating effects on CPU, with a reduced feature set.",
+            _ => "\nParticle effects are disabled. Insert a SimulationFallback::Cpu resource before adding the HanabiPlugin to simulate simple effects on CPU instead.",
+        };
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert!(
+            detect_missing_capabilities(&WgpuLimits::default(), DownlevelFlags::all()).is_empty()
+        );
+
+        // Typical of older mobile devices
+        assert_eq!(
+            detect_missing_capabilities(&WgpuLimits::downlevel_defaults(), DownlevelFlags::all()),
+            vec![MissingCapability::StorageBuffers {
+                required: BASE_STORAGE_BUFFER_COUNT,
+                supported: 4
+            }]
+        );
+
+        let missing = detect_missing_capabilities(
+            &WgpuLimits::downlevel_webgl2_defaults(),
+            DownlevelFlags::compliant() - DownlevelFlags::VERTEX_STORAGE,
+        );
+        assert_eq!(missing[0], MissingCapability::ComputeShaders);
+        assert!(missing.contains(&MissingCapability::StorageBuffers {
+            required: BASE_STORAGE_BUFFER_COUNT,
+            supported: 0
+        }));
+        assert!(missing.contains(&MissingCapability::VertexStorage));
+
+        let limits = WgpuLimits {
+            max_compute_invocations_per_workgroup: 128,
+            max_bind_groups: 3,
+            ..default()
+        };
+        assert_eq!(
+            detect_missing_capabilities(&limits, DownlevelFlags::all()),
+            vec![
+                MissingCapability::WorkgroupSize {
+                    required: 256,
+                    supported: 128
+                },
+                MissingCapability::BindGroups {
+                    required: 4,
+                    supported: 3
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn vertex_storage_read_only() {
+        // The render pass only reads storage buffers from the vertex stage, so runs
+        // on devices without Features::VERTEX_WRITABLE_STORAGE, like WebGPU
+        for source in [
+            include_str!("render/vfx_render.wgsl"),
+            include_str!("render/vfx_cpu.wgsl"),
+        ] {
+            assert!(source.contains("@vertex"));
+            assert!(!source.contains("var<storage, read_write>"));
+        }
+    }
+
+    #[test]
+    fn backend() {
+        let caps = GpuCapabilities::new("gpu", vec![], SimulationFallback::Disabled);
+        assert!(caps.supports_gpu_simulation());
+        assert_eq!(caps.backend(), SimulationBackend::Gpu);
+        assert!(caps.diagnostic().is_none());
+
+        let missing = vec![MissingCapability::ComputeShaders];
+        let caps = GpuCapabilities::new("gpu", missing.clone(), SimulationFallback::Disabled);
+        assert!(!caps.supports_gpu_simulation());
+        assert_eq!(caps.backend(), SimulationBackend::Disabled);
+
+        let caps = GpuCapabilities::new(
+            "gpu",
+            missing,
+            SimulationFallback::Cpu { max_particles: 64 },
+        );
+        assert_eq!(caps.backend(), SimulationBackend::Cpu);
+        let diagnostic = caps.diagnostic().unwrap();
+        assert!(diagnostic.contains("compute shaders are not supported"));
+        assert!(diagnostic.contains("CPU"));
+    }
+}
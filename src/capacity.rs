@@ -0,0 +1,825 @@
+use bevy::{prelude::*, render::extract_resource::ExtractResource, utils::HashMap};
+
+use crate::{render::ReadbackChannel, EffectAsset, ParticleEffect};
+
+/// Occupancy of a particle group of an effect instance.
+///
+/// The occupancy is read back from GPU with a latency of a few frames, so is
+/// only an approximation of the current state of the group.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct GroupOccupancy {
+    /// Capacity of the group, in number of particles.
+    pub capacity: u32,
+    /// Number of alive particles in the group.
+    pub alive_count: u32,
+}
+
+impl GroupOccupancy {
+    /// Get the ratio of alive particles to the group capacity, in `[0:1]`.
+    pub fn ratio(&self) -> f32 {
+        if self.capacity == 0 {
+            0.
+        } else {
+            self.alive_count as f32 / self.capacity as f32
+        }
+    }
+
+    /// Check if the group is full, that is no more particle can be spawned
+    /// into it until some existing particle dies.
+    pub fn is_full(&self) -> bool {
+        self.alive_count >= self.capacity
+    }
+}
+
+/// Configuration of the diagnostics of particle groups running at full
+/// capacity.
+///
+/// When a particle group is full, any particle spawned into it is silently
+/// dropped, which often manifests as an effect looking sparser than authored.
+/// To help diagnose such cases, the number of alive particles of each group is
+/// periodically read back from GPU, and a [`CapacityExceededEvent`] is emitted
+/// for each group found full during [`full_readback_count`] consecutive
+/// readbacks. The event lists the occupancy of all groups of the effect
+/// instance, to help adjusting the capacities passed to [`EffectAsset::new()`].
+///
+/// The readback is asynchronous, and only one readback is in flight at any
+/// time, so the occupancy is sampled every few frames only.
+///
+/// [`full_readback_count`]: crate::CapacityDiagnostics::full_readback_count
+/// [`EffectAsset::new()`]: crate::EffectAsset::new
+#[derive(Debug, Clone, Copy, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct CapacityDiagnostics {
+    /// Enable reading back the group occupancy from GPU. Defaults to `true`.
+    pub enabled: bool,
+    /// Number of consecutive readbacks a group needs to be found full before a
+    /// [`CapacityExceededEvent`] is emitted. Defaults to `8`.
+    pub full_readback_count: u32,
+    /// Log a warning in addition to emitting a [`CapacityExceededEvent`] or a
+    /// [`SpawnDroppedEvent`]. Defaults to `true`.
+    pub log_warning: bool,
+    /// Minimum number of frames between two [`SpawnDroppedEvent`] for the same
+    /// group. The particles dropped in between are accumulated into the next
+    /// event. Defaults to `300`.
+    pub drop_warning_interval: u32,
+}
+
+impl Default for CapacityDiagnostics {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            full_readback_count: 8,
+            log_warning: true,
+            drop_warning_interval: 300,
+        }
+    }
+}
+
+/// Event emitted when a particle group consistently runs at full capacity.
+///
+/// See [`CapacityDiagnostics`] for details.
+#[derive(Debug, Clone, PartialEq, Event)]
+pub struct CapacityExceededEvent {
+    /// Entity holding the [`ParticleEffect`] instance.
+    pub entity: Entity,
+    /// Index of the full group.
+    pub group_index: u32,
+    /// Name of the full group, if the asset names its groups.
+    pub group_name: Option<String>,
+    /// Number of consecutive readbacks the group was found full.
+    pub full_readback_count: u32,
+    /// Occupancy of all the groups of the effect instance, by group index.
+    pub occupancy: Vec<GroupOccupancy>,
+}
+
+/// Event emitted when some particles couldn't be spawned into a full group.
+///
+/// The particles spawned into a group in excess of its free capacity are
+/// dropped, and counted on GPU. The event is emitted from the readback of that
+/// counter, and is throttled per group by the
+/// [`CapacityDiagnostics::drop_warning_interval`]; the counts are accumulated
+/// since the previous event for the group.
+#[derive(Debug, Clone, PartialEq, Event)]
+pub struct SpawnDroppedEvent {
+    /// Entity holding the [`ParticleEffect`] instance.
+    pub entity: Entity,
+    /// Index of the group the particles were spawned into.
+    pub group_index: u32,
+    /// Name of the group, if the asset names its groups.
+    pub group_name: Option<String>,
+    /// Number of particles requested to be spawned into the group, including
+    /// the dropped ones.
+    pub requested: u32,
+    /// Number of particles dropped.
+    pub dropped: u32,
+    /// Occupancy of the group at the last readback. Its free capacity is the
+    /// number of particles available for spawning at that time.
+    pub occupancy: GroupOccupancy,
+}
+
+/// Statistics of an effect instance, read back asynchronously from GPU.
+///
+/// Insert this component on an entity holding a [`ParticleEffect`] to have
+/// the number of alive particles and the number of spawned particles of the
+/// effect periodically read back from GPU. This is useful to drive gameplay
+/// logic, like despawning an explosion effect once all its particles died, or
+/// for budgeting and debugging purpose.
+///
+/// The readback is asynchronous and never stalls the GPU, so the statistics
+/// lag a few frames behind the simulation. Like for the
+/// [`CapacityDiagnostics`], only one readback is in flight at any time, so the
+/// statistics are only updated every few frames. Use [`frame`] to determine
+/// their age, and [`is_valid()`] to check if any readback completed since the
+/// component was inserted.
+///
+/// The spawn statistics are counted on GPU, so include the particles spawned
+/// from the events of a parent effect, which are unknown to the CPU.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::EffectStats;
+/// fn despawn_finished_effects(mut commands: Commands, query: Query<(Entity, &EffectStats)>) {
+///     for (entity, stats) in &query {
+///         if stats.is_finished() {
+///             commands.entity(entity).despawn_recursive();
+///         }
+///     }
+/// }
+/// ```
+///
+/// [`frame`]: crate::EffectStats::frame
+/// [`is_valid()`]: crate::EffectStats::is_valid
+#[derive(Debug, Default, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectStats {
+    /// Occupancy of each group of the effect, by group index.
+    pub groups: Vec<GroupOccupancy>,
+    /// Total number of particles spawned by the effect since its GPU resources
+    /// were allocated, in all groups. This wraps around on overflow.
+    pub spawned_count: u32,
+    /// Number of particles spawned by the effect between the previous readback
+    /// and the current one, in all groups.
+    pub spawned_since_last_readback: u32,
+    /// Total number of particles the effect couldn't spawn because their group
+    /// was full, since its GPU resources were allocated, in all groups. This
+    /// wraps around on overflow.
+    pub dropped_count: u32,
+    /// Number of particles the effect couldn't spawn because their group was
+    /// full, between the previous readback and the current one, in all groups.
+    pub dropped_since_last_readback: u32,
+    /// Value of the [`FrameCount`] of the frame the statistics were sampled
+    /// on GPU.
+    ///
+    /// [`FrameCount`]: bevy::core::FrameCount
+    pub frame: u32,
+    /// Number of frames between the previous readback and the current one, or
+    /// zero if there's no previous readback.
+    pub readback_interval: u32,
+    /// Number of readbacks received since the component was inserted.
+    pub readback_count: u32,
+}
+
+impl EffectStats {
+    /// Check if the statistics contain data read back from GPU.
+    ///
+    /// This is `false` until the first readback completes, a few frames after
+    /// the component was inserted.
+    pub fn is_valid(&self) -> bool {
+        self.readback_count > 0
+    }
+
+    /// Get the total number of alive particles, in all groups.
+    pub fn alive_count(&self) -> u32 {
+        self.groups.iter().map(|group| group.alive_count).sum()
+    }
+
+    /// Get the total capacity of the effect, in all groups.
+    pub fn capacity(&self) -> u32 {
+        self.groups.iter().map(|group| group.capacity).sum()
+    }
+
+    /// Get the average number of particles spawned per frame between the
+    /// previous readback and the current one, in all groups.
+    pub fn spawned_per_frame(&self) -> f32 {
+        if self.readback_interval == 0 {
+            0.
+        } else {
+            self.spawned_since_last_readback as f32 / self.readback_interval as f32
+        }
+    }
+
+    /// Check if the effect spawned some particles, and all of them died since.
+    ///
+    /// This is typically used to detect the end of one-shot effects like
+    /// explosions. Note that a finished effect may spawn again later, for
+    /// example if its [`EffectSpawner`] is reset.
+    ///
+    /// [`EffectSpawner`]: crate::EffectSpawner
+    pub fn is_finished(&self) -> bool {
+        self.is_valid() && self.spawned_count > 0 && self.alive_count() == 0
+    }
+
+    /// Update the statistics from a new readback.
+    fn update(&mut self, report: &OccupancyReport) {
+        let spawned_count = report
+            .spawned_counts
+            .iter()
+            .fold(0u32, |acc, &count| acc.wrapping_add(count));
+        let dropped_count = report
+            .dropped_counts
+            .iter()
+            .fold(0u32, |acc, &count| acc.wrapping_add(count));
+        (
+            self.spawned_since_last_readback,
+            self.dropped_since_last_readback,
+            self.readback_interval,
+        ) = if self.is_valid() {
+            (
+                spawned_count.wrapping_sub(self.spawned_count),
+                dropped_count.wrapping_sub(self.dropped_count),
+                report.frame.wrapping_sub(self.frame),
+            )
+        } else {
+            (0, 0, 0)
+        };
+        self.spawned_count = spawned_count;
+        self.dropped_count = dropped_count;
+        self.groups.clone_from(&report.groups);
+        self.frame = report.frame;
+        self.readback_count += 1;
+    }
+}
+
+/// Statistics of all the effect instances, for HUD overlays and profiling
+/// tools.
+///
+/// Insert this resource into the app to have it refreshed every frame:
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::HanabiStats;
+/// # let mut app = App::new();
+/// app.init_resource::<HanabiStats>();
+/// ```
+///
+/// The statistics of each effect instance are read back asynchronously from
+/// the GPU counters, like the [`EffectStats`] component, so lag a few frames
+/// behind the simulation and are only updated every few frames. The render
+/// statistics, like the number of batches and the memory usage, are known on
+/// the CPU, and are updated every frame with the values of the previous frame.
+/// Effects simulated by the CPU fallback are not included.
+#[derive(Debug, Default, Clone, PartialEq, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct HanabiStats {
+    /// Statistics of each effect instance, by entity.
+    pub effects: HashMap<Entity, EffectStats>,
+    /// Number of effect batches simulated during the last rendered frame.
+    pub batch_count: u32,
+    /// Number of draw batches queued during the last rendered frame, one per
+    /// particle group of each effect batch. Each draw batch issues one draw
+    /// call per view it's visible in.
+    pub draw_batch_count: u32,
+    /// Size of the GPU buffers allocated to store particles, in bytes. This
+    /// includes the particle attributes, the indirection indices, the
+    /// properties, the injected particles, and the trails.
+    pub buffer_memory: u64,
+    /// Number of render and compute pipelines of Hanabi compiled and ready to
+    /// use in the pipeline cache.
+    pub pipeline_count: u32,
+}
+
+impl HanabiStats {
+    /// Get the number of alive particles, in all effect instances.
+    pub fn alive_count(&self) -> u32 {
+        self.effects.values().map(EffectStats::alive_count).sum()
+    }
+
+    /// Get the total capacity of all effect instances.
+    pub fn capacity(&self) -> u32 {
+        self.effects.values().map(EffectStats::capacity).sum()
+    }
+
+    /// Get the ratio of alive particles to the total capacity of all effect
+    /// instances, in `[0:1]`.
+    pub fn utilization(&self) -> f32 {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            0.
+        } else {
+            self.alive_count() as f32 / capacity as f32
+        }
+    }
+
+    /// Get the total number of particles dropped because their group was full,
+    /// in all effect instances, between their last two readbacks.
+    pub fn dropped_since_last_readback(&self) -> u32 {
+        self.effects
+            .values()
+            .map(|stats| stats.dropped_since_last_readback)
+            .sum()
+    }
+
+    /// Get the average number of particles spawned per frame in all effect
+    /// instances, between their last two readbacks.
+    pub fn spawned_per_frame(&self) -> f32 {
+        self.effects
+            .values()
+            .map(EffectStats::spawned_per_frame)
+            .sum()
+    }
+}
+
+/// Render statistics of a single frame, sent by the render world to the main
+/// world.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct RenderStatsReport {
+    /// Number of effect batches.
+    pub batch_count: u32,
+    /// Number of draw batches.
+    pub draw_batch_count: u32,
+    /// Size of the particle buffers, in bytes.
+    pub buffer_memory: u64,
+    /// Number of pipelines compiled and ready.
+    pub pipeline_count: u32,
+}
+
+/// Occupancy of the groups of a single effect instance read back from GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OccupancyReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    pub entity: Entity,
+    /// Occupancy of each group of the effect.
+    pub groups: Vec<GroupOccupancy>,
+    /// Total number of particles spawned into each group, wrapping on
+    /// overflow.
+    pub spawned_counts: Vec<u32>,
+    /// Total number of particles dropped because each group was full, wrapping
+    /// on overflow.
+    pub dropped_counts: Vec<u32>,
+    /// Frame the occupancy was sampled on GPU.
+    pub frame: u32,
+}
+
+/// Particles dropped by a group not reported yet.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DropTracker {
+    /// Total spawn counter of the group when the previous event was emitted.
+    spawned_count: u32,
+    /// Total drop counter of the group when the previous event was emitted.
+    dropped_count: u32,
+    /// Frame the previous event was emitted on, if any.
+    last_event_frame: Option<u32>,
+}
+
+/// Update the [`EffectStats`] of all effect instances and the [`HanabiStats`],
+/// emit a [`CapacityExceededEvent`] for each group consistently running at
+/// full capacity, and a throttled [`SpawnDroppedEvent`] for each group which
+/// dropped some spawned particles.
+///
+/// This system consumes the occupancy reports read back from GPU. See
+/// [`EffectStats`] and [`CapacityDiagnostics`] for details.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn report_group_occupancy(
+    diagnostics: Res<CapacityDiagnostics>,
+    channel: Res<ReadbackChannel<OccupancyReport>>,
+    render_stats_channel: Option<Res<ReadbackChannel<RenderStatsReport>>>,
+    mut hanabi_stats: Option<ResMut<HanabiStats>>,
+    assets: Res<Assets<EffectAsset>>,
+    effects: Query<&ParticleEffect>,
+    mut stats: Query<&mut EffectStats>,
+    mut full_counts: Local<HashMap<(Entity, u32), u32>>,
+    mut drop_trackers: Local<HashMap<(Entity, u32), DropTracker>>,
+    mut events: EventWriter<CapacityExceededEvent>,
+    mut drop_events: EventWriter<SpawnDroppedEvent>,
+) {
+    let reports = channel.take();
+    for report in &reports {
+        if let Ok(mut stats) = stats.get_mut(report.entity) {
+            stats.update(report);
+        }
+    }
+
+    if let Some(hanabi_stats) = hanabi_stats.as_mut() {
+        for report in &reports {
+            if effects.contains(report.entity) {
+                hanabi_stats
+                    .effects
+                    .entry(report.entity)
+                    .or_default()
+                    .update(report);
+            }
+        }
+        hanabi_stats
+            .effects
+            .retain(|entity, _| effects.contains(*entity));
+        if let Some(report) = render_stats_channel.and_then(|channel| channel.take().pop()) {
+            hanabi_stats.batch_count = report.batch_count;
+            hanabi_stats.draw_batch_count = report.draw_batch_count;
+            hanabi_stats.buffer_memory = report.buffer_memory;
+            hanabi_stats.pipeline_count = report.pipeline_count;
+        }
+    }
+
+    if !diagnostics.enabled {
+        full_counts.clear();
+        drop_trackers.clear();
+        return;
+    }
+
+    for report in reports {
+        // The effect may have been despawned since the readback was issued
+        let Ok(effect) = effects.get(report.entity) else {
+            continue;
+        };
+        let asset = assets.get(&effect.handle);
+        let labels = |group_index: u32| {
+            asset
+                .map(|asset| (asset.name.as_str(), asset.group_label(group_index)))
+                .unwrap_or(("<unknown>", format!("#{}", group_index)))
+        };
+        let group_name = |group_index: u32| {
+            asset
+                .and_then(|asset| asset.group_name(group_index))
+                .map(|name| name.to_string())
+        };
+
+        for (group_index, occupancy) in report.groups.iter().enumerate() {
+            let group_index = group_index as u32;
+            let key = (report.entity, group_index);
+
+            // Report the dropped particles, at most once per interval. The drop
+            // counter starts at zero when the group is allocated.
+            let spawned_count = report.spawned_counts[group_index as usize];
+            let dropped_count = report.dropped_counts[group_index as usize];
+            let tracker = drop_trackers.entry(key).or_default();
+            let dropped = dropped_count.wrapping_sub(tracker.dropped_count);
+            let throttled = tracker.last_event_frame.is_some_and(|frame| {
+                report.frame.wrapping_sub(frame) < diagnostics.drop_warning_interval
+            });
+            if dropped == 0 {
+                tracker.spawned_count = spawned_count;
+            } else if !throttled {
+                let requested = spawned_count
+                    .wrapping_sub(tracker.spawned_count)
+                    .wrapping_add(dropped);
+                *tracker = DropTracker {
+                    spawned_count,
+                    dropped_count,
+                    last_event_frame: Some(report.frame),
+                };
+
+                if diagnostics.log_warning {
+                    let (asset_name, group_label) = labels(group_index);
+                    warn!(
+                        "Group {} of effect '{}' on entity {:?} dropped {} of {} requested particles because it was full (capacity: {}, available: {}). Consider increasing its capacity.",
+                        group_label,
+                        asset_name,
+                        report.entity,
+                        dropped,
+                        requested,
+                        occupancy.capacity,
+                        occupancy.capacity.saturating_sub(occupancy.alive_count),
+                    );
+                }
+
+                drop_events.send(SpawnDroppedEvent {
+                    entity: report.entity,
+                    group_index,
+                    group_name: group_name(group_index),
+                    requested,
+                    dropped,
+                    occupancy: *occupancy,
+                });
+            }
+
+            if !occupancy.is_full() {
+                full_counts.remove(&key);
+                continue;
+            }
+
+            let full_count = full_counts.entry(key).or_insert(0);
+            *full_count += 1;
+            if *full_count < diagnostics.full_readback_count {
+                continue;
+            }
+            let full_readback_count = std::mem::take(full_count);
+
+            if diagnostics.log_warning {
+                let (asset_name, group_label) = labels(group_index);
+                warn!(
+                    "Group {} of effect '{}' on entity {:?} was full during {} consecutive readbacks ({} particles), and may have dropped spawned particles. Consider increasing its capacity. Occupancy of all groups: {:?}",
+                    group_label,
+                    asset_name,
+                    report.entity,
+                    full_readback_count,
+                    occupancy.capacity,
+                    report.groups,
+                );
+            }
+
+            events.send(CapacityExceededEvent {
+                entity: report.entity,
+                group_index,
+                group_name: group_name(group_index),
+                full_readback_count,
+                occupancy: report.groups.clone(),
+            });
+        }
+    }
+
+    // Forget about despawned effects
+    full_counts.retain(|(entity, _), _| effects.contains(*entity));
+    drop_trackers.retain(|(entity, _), _| effects.contains(*entity));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Module, Spawner};
+
+    use super::*;
+
+    #[test]
+    fn group_occupancy() {
+        let occupancy = GroupOccupancy {
+            capacity: 32,
+            alive_count: 8,
+        };
+        assert_eq!(occupancy.ratio(), 0.25);
+        assert!(!occupancy.is_full());
+
+        let occupancy = GroupOccupancy {
+            capacity: 32,
+            alive_count: 32,
+        };
+        assert_eq!(occupancy.ratio(), 1.);
+        assert!(occupancy.is_full());
+
+        assert_eq!(GroupOccupancy::default().ratio(), 0.);
+    }
+
+    #[test]
+    fn report_full_groups() {
+        let mut app = App::new();
+        app.init_resource::<Assets<EffectAsset>>()
+            .add_event::<CapacityExceededEvent>()
+            .add_event::<SpawnDroppedEvent>()
+            .insert_resource(CapacityDiagnostics {
+                full_readback_count: 2,
+                log_warning: false,
+                ..default()
+            })
+            .init_resource::<ReadbackChannel<OccupancyReport>>()
+            .add_systems(Update, report_group_occupancy);
+
+        let asset = EffectAsset::new(vec![32, 16], Spawner::default(), Module::default())
+            .with_group_names(vec!["drops".to_string(), "splash".to_string()]);
+        let handle = app.world.resource_mut::<Assets<EffectAsset>>().add(asset);
+        let entity = app.world.spawn(ParticleEffect::new(handle)).id();
+
+        let report = OccupancyReport {
+            entity,
+            groups: vec![
+                GroupOccupancy {
+                    capacity: 32,
+                    alive_count: 12,
+                },
+                GroupOccupancy {
+                    capacity: 16,
+                    alive_count: 16,
+                },
+            ],
+            spawned_counts: vec![12, 16],
+            dropped_counts: vec![0, 0],
+            frame: 0,
+        };
+
+        // A single full readback is not enough
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<OccupancyReport>>()
+            .clone();
+        channel.send([report.clone()]);
+        app.update();
+        let events = app.world.resource::<Events<CapacityExceededEvent>>();
+        assert!(events.is_empty());
+
+        // Two consecutive full readbacks raise an event
+        channel.send([report.clone()]);
+        app.update();
+        let events = app.world.resource::<Events<CapacityExceededEvent>>();
+        let mut reader = events.get_reader();
+        let events: Vec<_> = reader.read(events).collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, entity);
+        assert_eq!(events[0].group_index, 1);
+        assert_eq!(events[0].group_name.as_deref(), Some("splash"));
+        assert_eq!(events[0].full_readback_count, 2);
+        assert_eq!(events[0].occupancy, report.groups);
+    }
+
+    #[test]
+    fn report_dropped_spawns() {
+        let mut app = App::new();
+        app.init_resource::<Assets<EffectAsset>>()
+            .add_event::<CapacityExceededEvent>()
+            .add_event::<SpawnDroppedEvent>()
+            .insert_resource(CapacityDiagnostics {
+                log_warning: false,
+                drop_warning_interval: 10,
+                ..default()
+            })
+            .init_resource::<ReadbackChannel<OccupancyReport>>()
+            .add_systems(Update, report_group_occupancy);
+
+        let entity = app.world.spawn(ParticleEffect::default()).id();
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<OccupancyReport>>()
+            .clone();
+        let report = |spawned: u32, dropped: u32, frame: u32| OccupancyReport {
+            entity,
+            groups: vec![GroupOccupancy {
+                capacity: 32,
+                alive_count: 30,
+            }],
+            spawned_counts: vec![spawned],
+            dropped_counts: vec![dropped],
+            frame,
+        };
+        let read_events = |app: &mut App| -> Vec<SpawnDroppedEvent> {
+            app.world
+                .resource_mut::<Events<SpawnDroppedEvent>>()
+                .drain()
+                .collect()
+        };
+
+        // No drop, no event
+        channel.send([report(30, 0, 0)]);
+        app.update();
+        assert!(read_events(&mut app).is_empty());
+
+        // The first drop is reported immediately
+        channel.send([report(32, 6, 4)]);
+        app.update();
+        let events = read_events(&mut app);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, entity);
+        assert_eq!(events[0].group_index, 0);
+        assert_eq!(events[0].requested, 8);
+        assert_eq!(events[0].dropped, 6);
+
+        // Further drops are accumulated until the interval elapsed
+        channel.send([report(34, 9, 8)]);
+        app.update();
+        assert!(read_events(&mut app).is_empty());
+        channel.send([report(36, 10, 14)]);
+        app.update();
+        let events = read_events(&mut app);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].requested, 8);
+        assert_eq!(events[0].dropped, 4);
+    }
+
+    #[test]
+    fn update_stats() {
+        let mut app = App::new();
+        app.init_resource::<Assets<EffectAsset>>()
+            .add_event::<CapacityExceededEvent>()
+            .add_event::<SpawnDroppedEvent>()
+            .insert_resource(CapacityDiagnostics {
+                enabled: false,
+                ..default()
+            })
+            .init_resource::<ReadbackChannel<OccupancyReport>>()
+            .add_systems(Update, report_group_occupancy);
+
+        let entity = app
+            .world
+            .spawn((ParticleEffect::default(), EffectStats::default()))
+            .id();
+        let stats = app.world.get::<EffectStats>(entity).unwrap();
+        assert!(!stats.is_valid());
+        assert!(!stats.is_finished());
+
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<OccupancyReport>>()
+            .clone();
+        channel.send([OccupancyReport {
+            entity,
+            groups: vec![
+                GroupOccupancy {
+                    capacity: 32,
+                    alive_count: 12,
+                },
+                GroupOccupancy {
+                    capacity: 16,
+                    alive_count: 3,
+                },
+            ],
+            spawned_counts: vec![20, u32::MAX],
+            dropped_counts: vec![0, 4],
+            frame: 5,
+        }]);
+        app.update();
+        let stats = app.world.get::<EffectStats>(entity).unwrap();
+        assert!(stats.is_valid());
+        assert_eq!(stats.alive_count(), 15);
+        assert_eq!(stats.capacity(), 48);
+        assert_eq!(stats.spawned_count, 19);
+        assert_eq!(stats.spawned_since_last_readback, 0);
+        assert_eq!(stats.dropped_count, 4);
+        assert_eq!(stats.dropped_since_last_readback, 0);
+        assert_eq!(stats.frame, 5);
+        assert!(!stats.is_finished());
+
+        // All particles died, and the spawn counter wrapped around
+        channel.send([OccupancyReport {
+            entity,
+            groups: vec![
+                GroupOccupancy {
+                    capacity: 32,
+                    alive_count: 0,
+                },
+                GroupOccupancy {
+                    capacity: 16,
+                    alive_count: 0,
+                },
+            ],
+            spawned_counts: vec![24, 1],
+            dropped_counts: vec![2, 5],
+            frame: 9,
+        }]);
+        app.update();
+        let stats = app.world.get::<EffectStats>(entity).unwrap();
+        assert_eq!(stats.spawned_count, 25);
+        assert_eq!(stats.spawned_since_last_readback, 6);
+        assert_eq!(stats.dropped_count, 7);
+        assert_eq!(stats.dropped_since_last_readback, 3);
+        assert_eq!(stats.readback_interval, 4);
+        assert_eq!(stats.spawned_per_frame(), 1.5);
+        assert_eq!(stats.readback_count, 2);
+        assert!(stats.is_finished());
+    }
+
+    #[test]
+    fn update_hanabi_stats() {
+        let mut app = App::new();
+        app.init_resource::<Assets<EffectAsset>>()
+            .add_event::<CapacityExceededEvent>()
+            .add_event::<SpawnDroppedEvent>()
+            .insert_resource(CapacityDiagnostics {
+                enabled: false,
+                ..default()
+            })
+            .init_resource::<ReadbackChannel<OccupancyReport>>()
+            .init_resource::<ReadbackChannel<RenderStatsReport>>()
+            .init_resource::<HanabiStats>()
+            .add_systems(Update, report_group_occupancy);
+
+        let entity = app.world.spawn(ParticleEffect::default()).id();
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<OccupancyReport>>()
+            .clone();
+        channel.send([OccupancyReport {
+            entity,
+            groups: vec![GroupOccupancy {
+                capacity: 32,
+                alive_count: 8,
+            }],
+            spawned_counts: vec![8],
+            dropped_counts: vec![0],
+            frame: 3,
+        }]);
+        let render_stats_channel = app
+            .world
+            .resource::<ReadbackChannel<RenderStatsReport>>()
+            .clone();
+        render_stats_channel.send([RenderStatsReport {
+            batch_count: 1,
+            draw_batch_count: 2,
+            buffer_memory: 4096,
+            pipeline_count: 5,
+        }]);
+        app.update();
+
+        let stats = app.world.resource::<HanabiStats>();
+        assert_eq!(stats.effects.len(), 1);
+        assert_eq!(stats.effects[&entity].alive_count(), 8);
+        assert_eq!(stats.alive_count(), 8);
+        assert_eq!(stats.capacity(), 32);
+        assert_eq!(stats.utilization(), 0.25);
+        assert_eq!(stats.batch_count, 1);
+        assert_eq!(stats.draw_batch_count, 2);
+        assert_eq!(stats.buffer_memory, 4096);
+        assert_eq!(stats.pipeline_count, 5);
+
+        // Despawned effects are removed
+        app.world.despawn(entity);
+        app.update();
+        let stats = app.world.resource::<HanabiStats>();
+        assert!(stats.effects.is_empty());
+        assert_eq!(stats.utilization(), 0.);
+    }
+}
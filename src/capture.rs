@@ -0,0 +1,295 @@
+use bevy::prelude::*;
+
+use crate::{
+    render::ReadbackChannel, Attribute, ParticleLayout, ScalarType, ScalarValue, ScalarValueMut,
+    Value, ValueType, VectorValue,
+};
+
+/// A single alive particle read back from GPU by a [`ParticleCapture`].
+///
+/// The particle stores the values of all the attributes of the particle layout
+/// of the effect, in simulation space.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CapturedParticle {
+    values: Vec<(Attribute, Value)>,
+}
+
+impl CapturedParticle {
+    /// Get the value of an attribute, if part of the particle layout.
+    pub fn value(&self, attribute: Attribute) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(attr, _)| *attr == attribute)
+            .map(|(_, value)| value)
+    }
+
+    /// Get the value of an attribute as a Rust type, if part of the particle
+    /// layout and of a type convertible to `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_hanabi::*;
+    /// # fn check(particle: &CapturedParticle) {
+    /// let position: Option<Vec3> = particle.get(Attribute::POSITION);
+    /// # }
+    /// ```
+    pub fn get<T>(&self, attribute: Attribute) -> Option<T>
+    where
+        Value: TryInto<T>,
+    {
+        (*self.value(attribute)?).try_into().ok()
+    }
+
+    /// Get the [`Attribute::POSITION`] of the particle, if any.
+    pub fn position(&self) -> Option<Vec3> {
+        self.get(Attribute::POSITION)
+    }
+
+    /// Get the [`Attribute::VELOCITY`] of the particle, if any.
+    pub fn velocity(&self) -> Option<Vec3> {
+        self.get(Attribute::VELOCITY)
+    }
+
+    /// Get the [`Attribute::AGE`] of the particle, if any.
+    pub fn age(&self) -> Option<f32> {
+        self.get(Attribute::AGE)
+    }
+
+    /// Get the [`Attribute::LIFETIME`] of the particle, if any.
+    pub fn lifetime(&self) -> Option<f32> {
+        self.get(Attribute::LIFETIME)
+    }
+
+    /// Iterate over the attributes of the particle and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (Attribute, &Value)> {
+        self.values.iter().map(|(attr, value)| (*attr, value))
+    }
+}
+
+/// Component reading back the particles of an effect instance from GPU.
+///
+/// Insert this component on the entity of a [`ParticleEffect`] to capture the
+/// content of its particle buffer once the given number of frames elapsed.
+/// This is intended for integration tests asserting on the behavior of
+/// modifiers, like checking that all particles below a plane were killed, and
+/// stalls nothing but is not cheap; don't leave it on effects in production.
+///
+/// The frame count starts when the component is inserted. Once it elapsed, the
+/// alive particles of all groups are copied into a staging buffer at the end of
+/// the next rendered frame, which is mapped asynchronously. The result is
+/// available through [`particles()`] a few frames later. Insert the component
+/// again to take another capture.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn check_floor(captures: Query<&ParticleCapture>) {
+///     for capture in &captures {
+///         let Some(particles) = capture.particles() else {
+///             continue;
+///         };
+///         assert!(particles
+///             .iter()
+///             .all(|p| p.position().map_or(true, |pos| pos.y >= 0.)));
+///     }
+/// }
+/// ```
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`particles()`]: ParticleCapture::particles
+#[derive(Debug, Default, Clone, Component)]
+pub struct ParticleCapture {
+    /// Number of frames left before the capture is requested.
+    frames_remaining: u32,
+    /// Captured particles, once read back.
+    particles: Option<Vec<CapturedParticle>>,
+}
+
+impl ParticleCapture {
+    /// Capture the particles after the given number of frames.
+    ///
+    /// A value of zero captures the particles of the next rendered frame.
+    pub fn after_frames(frames: u32) -> Self {
+        Self {
+            frames_remaining: frames,
+            particles: None,
+        }
+    }
+
+    /// Check if the particles were read back.
+    pub fn is_ready(&self) -> bool {
+        self.particles.is_some()
+    }
+
+    /// Get the captured alive particles, or `None` if not read back yet.
+    ///
+    /// The particles of all groups are returned, in no particular order.
+    pub fn particles(&self) -> Option<&[CapturedParticle]> {
+        self.particles.as_deref()
+    }
+
+    /// Check if the capture is waiting for a readback from the render world.
+    pub(crate) fn is_requested(&self) -> bool {
+        self.frames_remaining == 0 && self.particles.is_none()
+    }
+}
+
+/// Particles of a single effect instance read back from GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CaptureReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Alive particles of all groups.
+    pub particles: Vec<CapturedParticle>,
+}
+
+/// Decode a single scalar stored as a 32-bit word.
+fn decode_scalar(scalar_type: ScalarType, word: u32) -> ScalarValue {
+    match scalar_type {
+        ScalarType::Bool => ScalarValue::Bool(word != 0),
+        ScalarType::Float => ScalarValue::Float(f32::from_bits(word)),
+        ScalarType::Int => ScalarValue::Int(word as i32),
+        ScalarType::Uint => ScalarValue::Uint(word),
+    }
+}
+
+/// Decode a single particle stored with the given layout in a GPU buffer.
+///
+/// Matrix attributes aren't supported, and are skipped.
+pub(crate) fn decode_particle(layout: &ParticleLayout, data: &[u8]) -> CapturedParticle {
+    let values = layout
+        .attributes()
+        .iter()
+        .filter_map(|attr_layout| {
+            let offset = attr_layout.offset as usize;
+            let size = attr_layout.attribute.size();
+            let words: Vec<u32> = data[offset..offset + size]
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            let value = match attr_layout.attribute.value_type() {
+                ValueType::Scalar(scalar_type) => {
+                    Value::Scalar(decode_scalar(scalar_type, words[0]))
+                }
+                ValueType::Vector(vector_type) => {
+                    let elem_type = vector_type.elem_type();
+                    let mut vector = VectorValue::splat(
+                        &decode_scalar(elem_type, words[0]),
+                        vector_type.count() as u8,
+                    );
+                    for (index, &word) in words.iter().enumerate().skip(1) {
+                        match vector.value_mut(index) {
+                            ScalarValueMut::Bool(dst) => *dst = word != 0,
+                            ScalarValueMut::Float(dst) => *dst = f32::from_bits(word),
+                            ScalarValueMut::Int(dst) => *dst = word as i32,
+                            ScalarValueMut::Uint(dst) => *dst = word,
+                        }
+                    }
+                    Value::Vector(vector)
+                }
+                ValueType::Matrix(_) => return None,
+            };
+            Some((attr_layout.attribute, value))
+        })
+        .collect();
+    CapturedParticle { values }
+}
+
+/// Count down the frames of the pending [`ParticleCapture`]s, and store the
+/// particles read back from GPU.
+pub(crate) fn update_particle_captures(
+    channel: Res<ReadbackChannel<CaptureReport>>,
+    mut captures: Query<&mut ParticleCapture>,
+) {
+    for report in channel.take() {
+        // The effect may have been despawned, or the capture replaced, since the
+        // readback was issued
+        let Ok(mut capture) = captures.get_mut(report.entity) else {
+            continue;
+        };
+        if capture.is_requested() {
+            capture.particles = Some(report.particles);
+        }
+    }
+
+    for mut capture in &mut captures {
+        if capture.frames_remaining > 0 {
+            capture.frames_remaining -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::AGE)
+            .append(Attribute::SPRITE_INDEX)
+            .build();
+        let mut data = vec![0u8; layout.min_binding_size().get() as usize];
+        for attr_layout in layout.attributes() {
+            let value = match attr_layout.attribute {
+                attr if attr == Attribute::POSITION => Value::from(Vec3::new(1., -2., 3.5)),
+                attr if attr == Attribute::AGE => Value::from(0.25),
+                _ => Value::from(7_i32),
+            };
+            let offset = attr_layout.offset as usize;
+            let bytes = value.as_bytes();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        let particle = decode_particle(&layout, &data);
+        assert_eq!(particle.position(), Some(Vec3::new(1., -2., 3.5)));
+        assert_eq!(particle.age(), Some(0.25));
+        assert_eq!(particle.lifetime(), None);
+        assert_eq!(
+            particle.value(Attribute::SPRITE_INDEX),
+            Some(&Value::from(7_i32))
+        );
+        assert_eq!(particle.iter().count(), 3);
+    }
+
+    #[test]
+    fn update_capture() {
+        let mut app = App::new();
+        app.init_resource::<ReadbackChannel<CaptureReport>>()
+            .add_systems(Update, update_particle_captures);
+
+        let entity = app.world.spawn(ParticleCapture::after_frames(2)).id();
+        let report = CaptureReport {
+            entity,
+            particles: vec![CapturedParticle::default()],
+        };
+
+        // Reports received before the frames elapsed are ignored
+        app.world
+            .resource::<ReadbackChannel<CaptureReport>>()
+            .send([report.clone()]);
+        app.update();
+        assert!(!app.world.get::<ParticleCapture>(entity).unwrap().is_ready());
+        app.update();
+        assert!(app
+            .world
+            .get::<ParticleCapture>(entity)
+            .unwrap()
+            .is_requested());
+
+        app.world
+            .resource::<ReadbackChannel<CaptureReport>>()
+            .send([report]);
+        app.update();
+        let capture = app.world.get::<ParticleCapture>(entity).unwrap();
+        assert!(capture.is_ready());
+        assert_eq!(capture.particles().unwrap().len(), 1);
+    }
+}
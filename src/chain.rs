@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+/// Kind of particle event of a parent effect driving the spawning of a child
+/// effect.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum ParentEvent {
+    /// A particle of the parent effect died, either because it reached the end
+    /// of its lifetime or because it was killed by some modifier.
+    #[default]
+    Death,
+    /// A particle of the parent effect collided with a surface, as detected by
+    /// a collision modifier like the [`CollidePlaneModifier`].
+    ///
+    /// [`CollidePlaneModifier`]: crate::CollidePlaneModifier
+    Collision,
+}
+
+impl ParentEvent {
+    /// Number of event kinds, which is the number of GPU event counters
+    /// allocated per parent effect.
+    pub(crate) const COUNT: u32 = 2;
+
+    /// Index of the GPU event counter for this event kind, relative to the
+    /// first counter of the parent effect.
+    pub(crate) fn counter_index(&self) -> u32 {
+        match self {
+            ParentEvent::Death => 0,
+            ParentEvent::Collision => 1,
+        }
+    }
+}
+
+/// Component chaining an effect instance to a parent effect instance.
+///
+/// Add this component to the same entity as a [`ParticleEffect`] to spawn
+/// particles each time a particle of the parent effect instance triggers some
+/// [`ParentEvent`], like dying or colliding. This allows composing complex
+/// effects from several [`EffectAsset`], for example to emit a smoke puff
+/// wherever a firework spark burns out.
+///
+/// The events are counted on GPU by the update pass of the parent effect, and
+/// consumed by the init pass of the child effect on the next frame, without
+/// any readback to CPU. For each event, [`particles_per_event`] particles are
+/// spawned in addition to any particle spawned by the [`EffectSpawner`] of the
+/// child effect, typically configured with a zero spawn rate. The child
+/// particles are initialized by the init modifiers of the child effect like
+/// any other particle; they don't inherit any attribute from the parent
+/// particle which emitted the event.
+///
+/// Because the number of events is only known on GPU, the init pass of the
+/// child effect is dispatched indirectly. A small compute pass running before
+/// it writes the workgroup count of the init pass from the number of particles
+/// spawned on CPU plus the number of events counted during the previous frame,
+/// so the cost of the init pass scales with the actual number of spawned
+/// particles rather than with the capacity of the child effect.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`EffectAsset`]: crate::EffectAsset
+/// [`particles_per_event`]: crate::EffectParent::particles_per_event
+/// [`EffectSpawner`]: crate::EffectSpawner
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectParent {
+    /// Entity holding the parent [`ParticleEffect`].
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Kind of event of the parent effect driving the spawning.
+    pub event: ParentEvent,
+    /// Number of particles spawned per event.
+    pub particles_per_event: u32,
+}
+
+impl Default for EffectParent {
+    fn default() -> Self {
+        Self {
+            entity: Entity::PLACEHOLDER,
+            event: ParentEvent::default(),
+            particles_per_event: 1,
+        }
+    }
+}
+
+impl EffectParent {
+    /// Create a new component chaining to the parent effect on the given
+    /// entity, spawning one particle per [`ParentEvent::Death`] event.
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            ..default()
+        }
+    }
+
+    /// Set the kind of event of the parent effect driving the spawning.
+    pub fn with_event(mut self, event: ParentEvent) -> Self {
+        self.event = event;
+        self
+    }
+
+    /// Set the number of particles spawned per event.
+    pub fn with_particles_per_event(mut self, particles_per_event: u32) -> Self {
+        self.particles_per_event = particles_per_event;
+        self
+    }
+}
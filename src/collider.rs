@@ -0,0 +1,230 @@
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+/// A simple static collider, for the [`CollideStaticModifier`].
+///
+/// The 3D colliders are defined in world space. The 2D colliders are defined
+/// in the XY plane of the world, and ignore the Z coordinate of the particles,
+/// like 2D physics colliders.
+///
+/// [`CollideStaticModifier`]: crate::CollideStaticModifier
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub enum StaticCollider {
+    /// A 2D circle in the XY plane.
+    Circle {
+        /// The circle center.
+        center: Vec2,
+        /// The circle radius.
+        radius: f32,
+    },
+    /// A 2D axis-aligned rectangle in the XY plane.
+    Rect {
+        /// The rectangle center.
+        center: Vec2,
+        /// The rectangle half size, along each axis.
+        half_size: Vec2,
+    },
+    /// A 2D segment in the XY plane, with a thickness.
+    ///
+    /// This is also known as a 2D capsule. A zero radius makes a thin segment,
+    /// for example an edge of a polyline collider.
+    Segment {
+        /// The first end of the segment.
+        start: Vec2,
+        /// The second end of the segment.
+        end: Vec2,
+        /// The radius of the segment, that is half its thickness.
+        radius: f32,
+    },
+    /// A 3D sphere.
+    Sphere {
+        /// The sphere center.
+        center: Vec3,
+        /// The sphere radius.
+        radius: f32,
+    },
+    /// A 3D axis-aligned box.
+    Box {
+        /// The box center.
+        center: Vec3,
+        /// The box half size, along each axis.
+        half_size: Vec3,
+    },
+    /// A 3D capsule, that is a segment with a thickness.
+    Capsule {
+        /// The first end of the capsule segment.
+        start: Vec3,
+        /// The second end of the capsule segment.
+        end: Vec3,
+        /// The radius of the capsule.
+        radius: f32,
+    },
+}
+
+impl StaticCollider {
+    /// Get the minimum and maximum corners of the world-space bounds of the
+    /// collider.
+    ///
+    /// This is useful to keep only the colliders near the effects using them.
+    /// The bounds of the 2D colliders are infinite along the Z axis, since
+    /// they ignore the Z coordinate of the particles.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let flat =
+            |min: Vec2, max: Vec2| (min.extend(f32::NEG_INFINITY), max.extend(f32::INFINITY));
+        match *self {
+            StaticCollider::Circle { center, radius } => flat(center - radius, center + radius),
+            StaticCollider::Rect { center, half_size } => {
+                flat(center - half_size, center + half_size)
+            }
+            StaticCollider::Segment { start, end, radius } => {
+                flat(start.min(end) - radius, start.max(end) + radius)
+            }
+            StaticCollider::Sphere { center, radius } => (center - radius, center + radius),
+            StaticCollider::Box { center, half_size } => (center - half_size, center + half_size),
+            StaticCollider::Capsule { start, end, radius } => {
+                (start.min(end) - radius, start.max(end) + radius)
+            }
+        }
+    }
+}
+
+/// List of the static colliders the [`CollideStaticModifier`] collides
+/// particles with.
+///
+/// The list is shared by all effects, and uploaded to a GPU storage buffer each
+/// frame it changes. It's typically filled each frame by a bridge system
+/// converting the static colliders of a physics world near the camera into
+/// [`StaticCollider`] primitives, for example only the ones overlapping the
+/// bounds of the effects using them. The colliders are in world space.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn update_colliders(mut colliders: ResMut<StaticColliders>) {
+///     colliders.colliders.clear();
+///     // The ground, and a round bumper
+///     colliders.colliders.push(StaticCollider::Rect {
+///         center: Vec2::new(0., -100.),
+///         half_size: Vec2::new(400., 20.),
+///     });
+///     colliders.colliders.push(StaticCollider::Circle {
+///         center: Vec2::new(50., 0.),
+///         radius: 16.,
+///     });
+/// }
+/// ```
+///
+/// [`CollideStaticModifier`]: crate::CollideStaticModifier
+#[derive(Debug, Default, Clone, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct StaticColliders {
+    /// The colliders.
+    pub colliders: Vec<StaticCollider>,
+}
+
+/// GPU representation of a [`StaticCollider`], matching the `StaticCollider`
+/// struct of `vfx_update.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub(crate) struct GpuStaticCollider {
+    /// Kind of collider, or zero for an unused entry.
+    pub kind: u32,
+    /// Radius of a circle, a segment, a sphere, or a capsule.
+    pub radius: f32,
+    pub __pad0: [u32; 2],
+    /// Center of a circle, a rectangle, a sphere, or a box, or first end of a
+    /// segment or a capsule.
+    pub a: [f32; 3],
+    pub __pad1: u32,
+    /// Half size of a rectangle or a box, or second end of a segment or a
+    /// capsule.
+    pub b: [f32; 3],
+    pub __pad2: u32,
+}
+
+impl From<&StaticCollider> for GpuStaticCollider {
+    fn from(collider: &StaticCollider) -> Self {
+        let (kind, radius, a, b) = match *collider {
+            StaticCollider::Circle { center, radius } => (1, radius, center.extend(0.), Vec3::ZERO),
+            StaticCollider::Rect { center, half_size } => {
+                (2, 0., center.extend(0.), half_size.extend(0.))
+            }
+            StaticCollider::Segment { start, end, radius } => {
+                (3, radius, start.extend(0.), end.extend(0.))
+            }
+            StaticCollider::Sphere { center, radius } => (4, radius, center, Vec3::ZERO),
+            StaticCollider::Box { center, half_size } => (5, 0., center, half_size),
+            StaticCollider::Capsule { start, end, radius } => (6, radius, start, end),
+        };
+        Self {
+            kind,
+            radius,
+            a: a.into(),
+            b: b.into(),
+            ..default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_static_collider() {
+        // Must match the size of the WGSL struct, aligned to its vec3 fields
+        assert_eq!(size_of::<GpuStaticCollider>(), 48);
+
+        let gpu = GpuStaticCollider::from(&StaticCollider::Segment {
+            start: Vec2::new(1., 2.),
+            end: Vec2::new(3., 4.),
+            radius: 0.5,
+        });
+        assert_eq!(gpu.kind, 3);
+        assert_eq!(gpu.radius, 0.5);
+        assert_eq!(gpu.a, [1., 2., 0.]);
+        assert_eq!(gpu.b, [3., 4., 0.]);
+
+        let gpu = GpuStaticCollider::from(&StaticCollider::Rect {
+            center: Vec2::new(-1., 0.),
+            half_size: Vec2::ONE,
+        });
+        assert_eq!(gpu.kind, 2);
+        assert_eq!(gpu.a, [-1., 0., 0.]);
+        assert_eq!(gpu.b, [1., 1., 0.]);
+
+        let gpu = GpuStaticCollider::from(&StaticCollider::Capsule {
+            start: Vec3::new(1., 2., 3.),
+            end: Vec3::new(4., 5., 6.),
+            radius: 0.25,
+        });
+        assert_eq!(gpu.kind, 6);
+        assert_eq!(gpu.radius, 0.25);
+        assert_eq!(gpu.a, [1., 2., 3.]);
+        assert_eq!(gpu.b, [4., 5., 6.]);
+    }
+
+    #[test]
+    fn bounds() {
+        let (min, max) = StaticCollider::Segment {
+            start: Vec2::new(3., -1.),
+            end: Vec2::new(1., 2.),
+            radius: 0.5,
+        }
+        .bounds();
+        assert_eq!(min.truncate(), Vec2::new(0.5, -1.5));
+        assert_eq!(max.truncate(), Vec2::new(3.5, 2.5));
+        assert_eq!(min.z, f32::NEG_INFINITY);
+        assert_eq!(max.z, f32::INFINITY);
+
+        let bounds = StaticCollider::Box {
+            center: Vec3::new(1., 2., 3.),
+            half_size: Vec3::new(1., 0.5, 2.),
+        }
+        .bounds();
+        assert_eq!(bounds, (Vec3::new(0., 1.5, 1.), Vec3::new(2., 2.5, 5.)));
+    }
+}
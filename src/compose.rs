@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use crate::{EffectAsset, EffectSpawner, ParticleEffect, ParticleEffectBundle};
+
+/// Runtime component tracking the child effect instances spawned for an
+/// effect instance whose asset is composed of child effects.
+///
+/// This component is automatically added to the entity of the parent
+/// [`ParticleEffect`] by [`spawn_effect_children()`], once its first child
+/// effect instance is spawned. See [`EffectAsset::with_child()`] for details.
+///
+/// [`EffectAsset::with_child()`]: crate::EffectAsset::with_child
+#[derive(Debug, Default, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectComposition {
+    /// Entity of each child effect instance, in the order of
+    /// [`EffectAsset::children()`], or `None` if not spawned yet.
+    children: Vec<Option<Entity>>,
+}
+
+impl EffectComposition {
+    /// Get the entity of a child effect instance, by index of the child in
+    /// [`EffectAsset::children()`].
+    ///
+    /// Returns `None` if the child effect instance is not spawned yet, because
+    /// its delay didn't elapse.
+    pub fn child(&self, index: usize) -> Option<Entity> {
+        self.children.get(index).copied().flatten()
+    }
+
+    /// Iterate over the entities of the child effect instances spawned so far.
+    pub fn children(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.children.iter().filter_map(|entity| *entity)
+    }
+}
+
+/// Spawn the child effect instances of all composed effect instances.
+///
+/// For each [`ParticleEffect`] whose [`EffectAsset`] has child effects, this
+/// system spawns a child entity with a [`ParticleEffectBundle`] for each child
+/// effect whose delay elapsed, as measured by [`EffectSpawner::elapsed()`],
+/// and records it in the [`EffectComposition`] of the parent entity.
+///
+/// This system runs in the [`PostUpdate`] schedule, in the
+/// [`EffectSystems::TickSpawners`] set, after [`tick_spawners()`].
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+/// [`tick_spawners()`]: crate::tick_spawners
+pub fn spawn_effect_children(
+    mut commands: Commands,
+    effects: Res<Assets<EffectAsset>>,
+    mut query: Query<(
+        Entity,
+        &ParticleEffect,
+        &EffectSpawner,
+        Option<&mut EffectComposition>,
+    )>,
+) {
+    for (entity, effect, spawner, composition) in query.iter_mut() {
+        let Some(asset) = effects.get(&effect.handle) else {
+            continue;
+        };
+        let children = asset.children();
+        let spawned = composition
+            .as_ref()
+            .map(|composition| composition.children.as_slice())
+            .unwrap_or_default();
+        let is_pending = |index: usize| spawned.get(index).copied().flatten().is_none();
+        if !children
+            .iter()
+            .enumerate()
+            .any(|(index, child)| is_pending(index) && child.delay <= spawner.elapsed())
+        {
+            continue;
+        }
+
+        let mut spawned = spawned.to_vec();
+        spawned.resize(children.len(), None);
+        for (child, slot) in children.iter().zip(spawned.iter_mut()) {
+            if slot.is_some() || child.delay > spawner.elapsed() {
+                continue;
+            }
+            trace!(
+                "Spawning child effect {:?} of effect instance {:?}",
+                child.handle,
+                entity
+            );
+            let child_entity = commands
+                .spawn(ParticleEffectBundle {
+                    transform: child.transform,
+                    ..ParticleEffectBundle::new(child.handle.clone())
+                })
+                .set_parent(entity)
+                .id();
+            *slot = Some(child_entity);
+        }
+
+        match composition {
+            Some(mut composition) => composition.children = spawned,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(EffectComposition { children: spawned });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spawn::new_rng, EffectChild, Module, Spawner};
+
+    #[test]
+    fn spawn_children() {
+        let mut world = World::new();
+        world.init_resource::<Assets<EffectAsset>>();
+        let mut assets = world.resource_mut::<Assets<EffectAsset>>();
+        let child = assets.add(EffectAsset::new(
+            vec![16],
+            Spawner::rate(5.0.into()),
+            Module::default(),
+        ));
+        let asset = EffectAsset::new(vec![16], Spawner::rate(5.0.into()), Module::default())
+            .with_child(EffectChild::new(child.clone()))
+            .with_child(
+                EffectChild::new(child.clone())
+                    .with_transform(Transform::from_xyz(1., 2., 3.))
+                    .with_delay(1.),
+            );
+        let mut spawner = EffectSpawner::new(&asset);
+        let handle = assets.add(asset);
+        let mut rng = new_rng();
+        spawner.tick(0.5, &mut rng);
+        let parent = world
+            .spawn((ParticleEffect::new(handle), spawner.clone()))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(spawn_effect_children);
+        schedule.run(&mut world);
+
+        // Only the child without delay is spawned
+        let composition = world.get::<EffectComposition>(parent).unwrap().clone();
+        assert_eq!(composition.children().count(), 1);
+        assert!(composition.child(1).is_none());
+        let first = composition.child(0).unwrap();
+        assert_eq!(world.get::<Parent>(first).unwrap().get(), parent);
+        assert_eq!(world.get::<ParticleEffect>(first).unwrap().handle, child);
+
+        // The delayed child is spawned once the parent simulated long enough
+        spawner.tick(0.5, &mut rng);
+        *world.get_mut::<EffectSpawner>(parent).unwrap() = spawner;
+        schedule.run(&mut world);
+        let composition = world.get::<EffectComposition>(parent).unwrap().clone();
+        assert_eq!(composition.child(0), Some(first));
+        let second = composition.child(1).unwrap();
+        assert_eq!(
+            *world.get::<Transform>(second).unwrap(),
+            Transform::from_xyz(1., 2., 3.)
+        );
+        assert_eq!(world.get::<Children>(parent).unwrap().len(), 2);
+
+        // Children are spawned only once
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Children>(parent).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,1337 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashSet};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::{
+    graph::{expr::TernaryOperator, BinaryOperator, BuiltInOperator, Expr, ExprError, ExprHandle},
+    modifier::{Modifier, ShapeDimension},
+    render::GpuCpuParticle,
+    AccelModifier, AlphaMode, Attribute, ColorOverLifetimeModifier, EffectAsset, EffectProperties,
+    EffectSimulation, EffectSpawner, GlobalProperties, HdrColorOverLifetimeModifier,
+    LinearDragModifier, Module, MotionIntegration, ParticleEffect, Random, ScalarValue,
+    SetAttributeModifier, SetColorModifier, SetPositionSphereModifier, SetSizeModifier,
+    SetVelocitySphereModifier, SimulationFallback, SimulationSpace, SizeOverLifetimeCurveModifier,
+    SizeOverLifetimeModifier, UnaryOperator, Value,
+};
+
+/// A single particle simulated on CPU.
+///
+/// See [`CpuSimulation`] for details.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuParticle {
+    /// Position of the particle, in simulation space.
+    pub position: Vec3,
+    /// Velocity of the particle, in simulation space.
+    pub velocity: Vec3,
+    /// Age of the particle, in seconds.
+    pub age: f32,
+    /// Lifetime of the particle, or infinite if the effect doesn't assign
+    /// [`Attribute::LIFETIME`].
+    pub lifetime: f32,
+    /// Color of the particle, as assigned by the init and update modifiers.
+    /// The render modifiers are not applied.
+    pub color: Vec4,
+    /// Size of the particle, as assigned by the init and update modifiers.
+    /// The render modifiers are not applied.
+    pub size: Vec2,
+    /// Random value in `[0:1]` drawn at spawn time, to sample random render
+    /// values consistently across frames.
+    pub seed: f32,
+}
+
+impl Default for CpuParticle {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            age: 0.,
+            lifetime: f32::INFINITY,
+            color: Vec4::ONE,
+            size: Vec2::ONE,
+            seed: 0.,
+        }
+    }
+}
+
+/// Particles of an effect instance simulated on CPU.
+///
+/// This component is inserted on the effect instances when the effects are
+/// simulated on CPU. See [`SimulationFallback::Cpu`] for details.
+///
+/// The particles can be read back from this component, for example to check
+/// the state of an effect in a headless test.
+#[derive(Debug, Default, Component)]
+pub struct CpuParticles {
+    /// Alive particles, in simulation space.
+    particles: Vec<CpuParticle>,
+    /// Simulation space of the alive particles.
+    simulation_space: SimulationSpace,
+    /// Quads of the alive particles to render, in world space.
+    pub(crate) instances: Vec<GpuCpuParticle>,
+    /// Are the particles rendered with alpha masking?
+    pub(crate) use_alpha_mask: bool,
+    /// Z layer used to sort the effect when rendering in 2D.
+    #[cfg(feature = "2d")]
+    pub(crate) z_layer_2d: f32,
+}
+
+impl CpuParticles {
+    /// Get the alive particles, in simulation space.
+    pub fn particles(&self) -> &[CpuParticle] {
+        &self.particles
+    }
+
+    /// Number of alive particles.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Check if there's no alive particle.
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+}
+
+/// Value of an expression evaluated on CPU.
+///
+/// The CPU evaluation only supports floating-point scalars and vectors, which
+/// are all stored as a [`Vec4`] with `rank` significant components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CpuExprValue {
+    value: Vec4,
+    rank: u8,
+}
+
+impl CpuExprValue {
+    fn scalar(value: f32) -> Self {
+        Self {
+            value: Vec4::splat(value),
+            rank: 1,
+        }
+    }
+
+    fn vector(value: Vec4, rank: u8) -> Self {
+        Self { value, rank }
+    }
+
+    fn from_value(value: &Value) -> Result<Self, ExprError> {
+        match value {
+            Value::Scalar(s) => Ok(Self::scalar(s.as_f32())),
+            Value::Vector(v) => {
+                let rank = v.vector_type().count();
+                let mut value = Vec4::ZERO;
+                for i in 0..rank {
+                    value[i] = v.value(i).as_f32();
+                }
+                Ok(Self::vector(value, rank as u8))
+            }
+            Value::Matrix(_) => Err(unsupported("matrix values")),
+        }
+    }
+
+    fn to_value(self) -> Value {
+        match self.rank {
+            1 => Value::Scalar(self.value.x.into()),
+            2 => Value::Vector(self.value.truncate().truncate().into()),
+            3 => Value::Vector(self.value.truncate().into()),
+            _ => Value::Vector(self.value.into()),
+        }
+    }
+
+    fn x(&self) -> f32 {
+        self.value.x
+    }
+
+    fn vec2(&self) -> Vec2 {
+        if self.rank == 1 {
+            Vec2::splat(self.value.x)
+        } else {
+            self.value.truncate().truncate()
+        }
+    }
+
+    fn vec3(&self) -> Vec3 {
+        if self.rank == 1 {
+            Vec3::splat(self.value.x)
+        } else {
+            self.value.truncate()
+        }
+    }
+
+    fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Self::vector(
+            Vec4::new(
+                f(self.value.x),
+                f(self.value.y),
+                f(self.value.z),
+                f(self.value.w),
+            ),
+            self.rank,
+        )
+    }
+
+    /// Apply a component-wise binary function, broadcasting scalars.
+    fn zip(self, other: Self, mut f: impl FnMut(f32, f32) -> f32) -> Self {
+        let rank = self.rank.max(other.rank);
+        let (a, b) = (self.broadcast(), other.broadcast());
+        Self::vector(
+            Vec4::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z), f(a.w, b.w)),
+            rank,
+        )
+    }
+
+    fn broadcast(&self) -> Vec4 {
+        if self.rank == 1 {
+            Vec4::splat(self.value.x)
+        } else {
+            self.value
+        }
+    }
+
+    fn dot(&self, other: &Self) -> f32 {
+        let product = self.value * other.value;
+        (0..self.rank as usize).map(|i| product[i]).sum()
+    }
+
+    fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+}
+
+fn unsupported(what: &str) -> ExprError {
+    ExprError::GraphEvalError(format!("{} are not supported by the CPU simulation", what))
+}
+
+/// Context to evaluate expressions on CPU for a single particle.
+struct CpuEvalContext<'a> {
+    module: &'a Module,
+    properties: Option<&'a EffectProperties>,
+    globals: Option<&'a GlobalProperties>,
+    time: f32,
+    delta_time: f32,
+    particle: CpuParticle,
+    rng: &'a mut Pcg32,
+}
+
+impl<'a> CpuEvalContext<'a> {
+    fn eval(&mut self, handle: ExprHandle) -> Result<CpuExprValue, ExprError> {
+        let expr = *self.module.try_get(handle)?;
+        match expr {
+            Expr::Literal(literal) => CpuExprValue::from_value(literal.value()),
+            Expr::Property(property) => {
+                let property = self.module.get_property(property.property()).ok_or(
+                    ExprError::PropertyError(format!(
+                        "Unknown property handle {:?} in evaluation module.",
+                        property.property()
+                    )),
+                )?;
+                let value = self
+                    .globals
+                    .and_then(|globals| globals.get_for(property))
+                    .or_else(|| {
+                        self.properties
+                            .and_then(|properties| properties.get_stored(property.name()))
+                    })
+                    .unwrap_or(*property.default_value());
+                CpuExprValue::from_value(&value)
+            }
+            Expr::BuiltIn(builtin) => match builtin.operator() {
+                BuiltInOperator::Time => Ok(CpuExprValue::scalar(self.time)),
+                BuiltInOperator::DeltaTime => Ok(CpuExprValue::scalar(self.delta_time)),
+                BuiltInOperator::Rand(value_type) => {
+                    let value = Vec4::new(
+                        self.rng.gen(),
+                        self.rng.gen(),
+                        self.rng.gen(),
+                        self.rng.gen(),
+                    );
+                    let rank = match value_type {
+                        crate::ValueType::Scalar(_) => 1,
+                        crate::ValueType::Vector(vector_type) => vector_type.count() as u8,
+                        crate::ValueType::Matrix(_) => return Err(unsupported("matrix values")),
+                    };
+                    Ok(CpuExprValue::vector(value, rank))
+                }
+                op => Err(unsupported(&format!("built-in operator '{}'", op.name()))),
+            },
+            Expr::Attribute(attr) => self.attribute(attr.attr()),
+            Expr::Unary { op, expr } => {
+                let value = self.eval(expr)?;
+                Ok(match op {
+                    UnaryOperator::Abs => value.map(f32::abs),
+                    UnaryOperator::Ceil => value.map(f32::ceil),
+                    UnaryOperator::Cos => value.map(f32::cos),
+                    UnaryOperator::Exp => value.map(f32::exp),
+                    UnaryOperator::Floor => value.map(f32::floor),
+                    UnaryOperator::Fract => value.map(|x| x - x.floor()),
+                    UnaryOperator::Length => CpuExprValue::scalar(value.length()),
+                    UnaryOperator::Normalize => {
+                        let length = value.length();
+                        if length > 0. {
+                            value.map(|x| x / length)
+                        } else {
+                            value
+                        }
+                    }
+                    UnaryOperator::Saturate => value.map(|x| x.clamp(0., 1.)),
+                    UnaryOperator::Sign => value.map(|x| {
+                        if x > 0. {
+                            1.
+                        } else if x < 0. {
+                            -1.
+                        } else {
+                            0.
+                        }
+                    }),
+                    UnaryOperator::Sin => value.map(f32::sin),
+                    UnaryOperator::Sqrt => value.map(f32::sqrt),
+                    UnaryOperator::Tan => value.map(f32::tan),
+                    UnaryOperator::X => CpuExprValue::scalar(value.value.x),
+                    UnaryOperator::Y => CpuExprValue::scalar(value.value.y),
+                    UnaryOperator::Z => CpuExprValue::scalar(value.value.z),
+                    UnaryOperator::W => CpuExprValue::scalar(value.value.w),
+                    op => return Err(unsupported(&format!("unary operator '{:?}'", op))),
+                })
+            }
+            Expr::Binary { op, left, right } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                Ok(match op {
+                    BinaryOperator::Add => left.zip(right, |a, b| a + b),
+                    BinaryOperator::Sub => left.zip(right, |a, b| a - b),
+                    BinaryOperator::Mul => left.zip(right, |a, b| a * b),
+                    BinaryOperator::Div => left.zip(right, |a, b| a / b),
+                    BinaryOperator::Min => left.zip(right, f32::min),
+                    BinaryOperator::Max => left.zip(right, f32::max),
+                    BinaryOperator::Remainder => left.zip(right, |a, b| a % b),
+                    BinaryOperator::Step => left.zip(right, |a, b| if a <= b { 1. } else { 0. }),
+                    BinaryOperator::Dot => CpuExprValue::scalar(left.dot(&right)),
+                    BinaryOperator::Distance => {
+                        CpuExprValue::scalar(left.zip(right, |a, b| a - b).length())
+                    }
+                    BinaryOperator::Cross => {
+                        CpuExprValue::vector(left.vec3().cross(right.vec3()).extend(0.), 3)
+                    }
+                    BinaryOperator::UniformRand => {
+                        let rng = &mut *self.rng;
+                        left.zip(right, |a, b| (b - a).mul_add(rng.gen::<f32>(), a))
+                    }
+                    BinaryOperator::Vec2 => {
+                        CpuExprValue::vector(Vec4::new(left.x(), right.x(), 0., 0.), 2)
+                    }
+                    op => return Err(unsupported(&format!("binary operator '{:?}'", op))),
+                })
+            }
+            Expr::Ternary {
+                op,
+                first,
+                second,
+                third,
+            } => {
+                let first = self.eval(first)?;
+                let second = self.eval(second)?;
+                let third = self.eval(third)?;
+                Ok(match op {
+                    TernaryOperator::Mix => {
+                        let t = third.broadcast();
+                        let (a, b) = (first.broadcast(), second.broadcast());
+                        CpuExprValue::vector(a + (b - a) * t, first.rank.max(second.rank))
+                    }
+                    TernaryOperator::SmoothStep => {
+                        let (e0, e1, x) =
+                            (first.broadcast(), second.broadcast(), third.broadcast());
+                        let t = ((x - e0) / (e1 - e0)).clamp(Vec4::ZERO, Vec4::ONE);
+                        CpuExprValue::vector(t * t * (3. - 2. * t), third.rank)
+                    }
+                    TernaryOperator::Vec3 => {
+                        CpuExprValue::vector(Vec4::new(first.x(), second.x(), third.x(), 0.), 3)
+                    }
+                })
+            }
+            Expr::Cast(cast) => {
+                let value = self.eval(cast.inner())?;
+                match cast.value_type() {
+                    crate::ValueType::Scalar(_) => Ok(CpuExprValue::scalar(value.x())),
+                    crate::ValueType::Vector(vector_type) => {
+                        let rank = vector_type.count() as u8;
+                        Ok(CpuExprValue::vector(value.broadcast(), rank))
+                    }
+                    crate::ValueType::Matrix(_) => Err(unsupported("matrix values")),
+                }
+            }
+        }
+    }
+
+    fn attribute(&self, attr: Attribute) -> Result<CpuExprValue, ExprError> {
+        let p = &self.particle;
+        Ok(if attr == Attribute::POSITION {
+            CpuExprValue::vector(p.position.extend(0.), 3)
+        } else if attr == Attribute::VELOCITY {
+            CpuExprValue::vector(p.velocity.extend(0.), 3)
+        } else if attr == Attribute::AGE {
+            CpuExprValue::scalar(p.age)
+        } else if attr == Attribute::LIFETIME {
+            CpuExprValue::scalar(p.lifetime)
+        } else if attr == Attribute::HDR_COLOR {
+            CpuExprValue::vector(p.color, 4)
+        } else if attr == Attribute::SIZE {
+            CpuExprValue::scalar(p.size.x)
+        } else if attr == Attribute::SIZE2 {
+            CpuExprValue::vector(p.size.extend(0.).extend(0.), 2)
+        } else {
+            return Err(unsupported(&format!("attribute '{}'", attr.name())));
+        })
+    }
+}
+
+/// Assign the value of an expression to an attribute of a particle.
+fn set_attribute(
+    particle: &mut CpuParticle,
+    attr: Attribute,
+    value: CpuExprValue,
+    module: &Module,
+    handle: ExprHandle,
+) -> Result<(), ExprError> {
+    if attr == Attribute::POSITION {
+        particle.position = value.vec3();
+    } else if attr == Attribute::VELOCITY {
+        particle.velocity = value.vec3();
+    } else if attr == Attribute::AGE {
+        particle.age = value.x();
+    } else if attr == Attribute::LIFETIME {
+        particle.lifetime = value.x();
+    } else if attr == Attribute::HDR_COLOR {
+        particle.color = value.value;
+    } else if attr == Attribute::COLOR {
+        // Packed colors are generally literals, which lose precision once converted
+        // to a float, so are unpacked from the original literal value.
+        particle.color = match module.get(handle) {
+            Some(Expr::Literal(literal)) => match literal.value() {
+                Value::Scalar(ScalarValue::Uint(packed)) => {
+                    Vec4::from_array(packed.to_le_bytes().map(|c| c as f32 / 255.))
+                }
+                _ => value.value,
+            },
+            _ => return Err(unsupported("non-literal packed colors")),
+        };
+    } else if attr == Attribute::SIZE {
+        particle.size = Vec2::splat(value.x());
+    } else if attr == Attribute::SIZE2 {
+        particle.size = value.vec2();
+    } else {
+        return Err(unsupported(&format!("attribute '{}'", attr.name())));
+    }
+    Ok(())
+}
+
+/// Check if a modifier is supported by the CPU simulation.
+fn is_supported(modifier: &dyn Modifier) -> bool {
+    let any = modifier.as_any();
+    any.is::<SetAttributeModifier>()
+        || any.is::<SetPositionSphereModifier>()
+        || any.is::<SetVelocitySphereModifier>()
+        || any.is::<AccelModifier>()
+        || any.is::<LinearDragModifier>()
+        || any.is::<SetColorModifier>()
+        || any.is::<ColorOverLifetimeModifier>()
+        || any.is::<HdrColorOverLifetimeModifier>()
+        || any.is::<SetSizeModifier>()
+        || any.is::<SizeOverLifetimeModifier>()
+        || any.is::<SizeOverLifetimeCurveModifier>()
+}
+
+/// Apply an init or update modifier to a single particle.
+fn apply_modifier(
+    modifier: &dyn Modifier,
+    ctx: &mut CpuEvalContext,
+    particle: &mut CpuParticle,
+) -> Result<(), ExprError> {
+    ctx.particle = *particle;
+    let any = modifier.as_any();
+    if let Some(m) = any.downcast_ref::<SetAttributeModifier>() {
+        let value = ctx.eval(m.value)?;
+        set_attribute(particle, m.attribute, value, ctx.module, m.value)?;
+    } else if let Some(m) = any.downcast_ref::<SetPositionSphereModifier>() {
+        let center = ctx.eval(m.center)?.vec3();
+        let mut radius = ctx.eval(m.radius)?.x();
+        if m.dimension == ShapeDimension::Volume {
+            radius *= ctx.rng.gen::<f32>().cbrt();
+        }
+        let theta = ctx.rng.gen::<f32>() * std::f32::consts::TAU;
+        let z = ctx.rng.gen::<f32>().mul_add(2., -1.);
+        let r = (1. - z * z).max(0.).sqrt();
+        let dir = Vec3::new(r * theta.cos(), r * theta.sin(), z);
+        particle.position = center + dir * radius;
+    } else if let Some(m) = any.downcast_ref::<SetVelocitySphereModifier>() {
+        let center = ctx.eval(m.center)?.vec3();
+        let speed = ctx.eval(m.speed)?.x();
+        particle.velocity = (particle.position - center).normalize_or_zero() * speed;
+    } else if let Some(m) = any.downcast_ref::<AccelModifier>() {
+        let accel = ctx.eval(m.accel())?.vec3();
+        particle.velocity += accel * ctx.delta_time;
+    } else if let Some(m) = any.downcast_ref::<LinearDragModifier>() {
+        let drag = ctx.eval(m.drag)?.x();
+        particle.velocity *= drag.mul_add(-ctx.delta_time, 1.).max(0.);
+    }
+    Ok(())
+}
+
+/// Apply a render modifier to the color and size of a single particle.
+fn apply_render_modifier(
+    modifier: &dyn Modifier,
+    particle: &CpuParticle,
+    color: &mut Vec4,
+    size: &mut Vec2,
+) {
+    let any = modifier.as_any();
+    let ratio = if particle.lifetime.is_finite() && particle.lifetime > 0. {
+        particle.age / particle.lifetime
+    } else {
+        0.
+    };
+    if let Some(m) = any.downcast_ref::<SetColorModifier>() {
+        *color = match m.color {
+            crate::CpuValue::Single(c) => c,
+            crate::CpuValue::Uniform((a, b)) => a.lerp(b, particle.seed),
+        };
+    } else if let Some(m) = any.downcast_ref::<ColorOverLifetimeModifier>() {
+        *color = m.gradient.sample(ratio);
+    } else if let Some(m) = any.downcast_ref::<HdrColorOverLifetimeModifier>() {
+        if !m.gradient.is_empty() {
+            *color = m.gradient.sample(ratio).to_linear_vec4();
+        }
+    } else if let Some(m) = any.downcast_ref::<SetSizeModifier>() {
+        *size = match m.size {
+            crate::CpuValue::Single(s) => s,
+            crate::CpuValue::Uniform((a, b)) => a.lerp(b, particle.seed),
+        };
+    } else if let Some(m) = any.downcast_ref::<SizeOverLifetimeModifier>() {
+        *size = m.gradient.sample(ratio);
+    } else if let Some(m) = any.downcast_ref::<SizeOverLifetimeCurveModifier>() {
+        if !m.curve.is_empty() {
+            *size = m.curve.sample(ratio);
+        }
+    }
+}
+
+/// Simulate the particles of a single effect instance for one frame.
+#[allow(clippy::too_many_arguments)]
+fn simulate(
+    particles: &mut CpuParticles,
+    asset: &EffectAsset,
+    simulation_space: SimulationSpace,
+    properties: Option<&EffectProperties>,
+    globals: Option<&GlobalProperties>,
+    transform: &GlobalTransform,
+    spawn_count: u32,
+    capacity: u32,
+    time: &Time<EffectSimulation>,
+    time_scale: f32,
+    rng: &mut Pcg32,
+) -> Result<(), ExprError> {
+    let module = asset.module();
+    let delta_time = time.delta_seconds() * time_scale;
+    let mut ctx = CpuEvalContext {
+        module,
+        properties,
+        globals,
+        time: time.elapsed_seconds_wrapped(),
+        delta_time,
+        particle: CpuParticle::default(),
+        rng,
+    };
+
+    // Convert the existing particles if the effect switched simulation space
+    if particles.simulation_space != simulation_space {
+        let matrix = match simulation_space {
+            SimulationSpace::Global => transform.compute_matrix(),
+            SimulationSpace::Local => transform.compute_matrix().inverse(),
+        };
+        for particle in &mut particles.particles {
+            particle.position = matrix.transform_point3(particle.position);
+            particle.velocity = matrix.transform_vector3(particle.velocity);
+        }
+        particles.simulation_space = simulation_space;
+    }
+
+    // Update the existing particles
+    let integrate = |particle: &mut CpuParticle| {
+        particle.position += particle.velocity * delta_time;
+    };
+    let mut result = Ok(());
+    particles.particles.retain_mut(|particle| {
+        particle.age += delta_time;
+        if asset.motion_integration == MotionIntegration::PreUpdate {
+            integrate(particle);
+        }
+        for modifier in asset.update_modifiers_for_group(0) {
+            if let Err(err) = apply_modifier(modifier, &mut ctx, particle) {
+                result = Err(err);
+            }
+        }
+        if asset.motion_integration == MotionIntegration::PostUpdate {
+            integrate(particle);
+        }
+        particle.age < particle.lifetime
+    });
+    result?;
+
+    // Spawn new particles, up to the capacity
+    let spawn_count = spawn_count.min(capacity.saturating_sub(particles.particles.len() as u32));
+    for _ in 0..spawn_count {
+        let mut particle = CpuParticle {
+            seed: ctx.rng.gen(),
+            ..default()
+        };
+        for modifier in asset.init_modifiers_for_group(0) {
+            apply_modifier(modifier, &mut ctx, &mut particle)?;
+        }
+        if simulation_space == SimulationSpace::Global {
+            particle.position += transform.translation();
+        }
+        particles.particles.push(particle);
+    }
+
+    // Build the quads to render
+    let alpha_cutoff = match asset.group_alpha_mode(0) {
+        AlphaMode::Mask(cutoff) => {
+            ctx.particle = CpuParticle::default();
+            Some(ctx.eval(cutoff)?.x())
+        }
+        _ => None,
+    };
+    particles.use_alpha_mask = alpha_cutoff.is_some();
+    particles.instances.clear();
+    for particle in &particles.particles {
+        let mut color = particle.color;
+        let mut size = particle.size;
+        for modifier in asset.render_modifiers_for_group(0) {
+            apply_render_modifier(modifier.as_modifier(), particle, &mut color, &mut size);
+        }
+        if let Some(cutoff) = alpha_cutoff {
+            color.w = if color.w >= cutoff { 1. } else { 0. };
+        }
+        let position = if simulation_space == SimulationSpace::Local {
+            transform.transform_point(particle.position)
+        } else {
+            particle.position
+        };
+        particles.instances.push(GpuCpuParticle {
+            position: position.to_array(),
+            size: size.to_array(),
+            color: color.to_array(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Simulate on CPU the particles of all effect instances.
+///
+/// This system only runs when the effects are simulated on CPU, after the
+/// spawners were ticked. See [`SimulationFallback::Cpu`] for details.
+#[allow(clippy::type_complexity)]
+pub(crate) fn simulate_cpu_particles(
+    mut commands: Commands,
+    fallback: Res<SimulationFallback>,
+    time: Res<Time<EffectSimulation>>,
+    effects: Res<Assets<EffectAsset>>,
+    globals: Res<GlobalProperties>,
+    mut rng: ResMut<Random>,
+    mut checked_assets: Local<HashSet<AssetId<EffectAsset>>>,
+    mut failed_assets: Local<HashSet<AssetId<EffectAsset>>>,
+    mut query: Query<(
+        Entity,
+        &ParticleEffect,
+        &mut EffectSpawner,
+        Option<&EffectProperties>,
+        Option<&GlobalTransform>,
+        Option<&mut CpuParticles>,
+    )>,
+) {
+    let SimulationFallback::Cpu { max_particles } = *fallback else {
+        return;
+    };
+
+    for (entity, effect, mut spawner, properties, transform, cpu_particles) in query.iter_mut() {
+        let Some(asset) = effects.get(&effect.handle) else {
+            continue;
+        };
+
+        // Warn once per asset about the features the CPU simulation doesn't support
+        if checked_assets.insert(effect.handle.id()) {
+            let unsupported: Vec<_> = asset
+                .modifiers()
+                .filter(|modifier| !is_supported(*modifier))
+                .map(|modifier| modifier.reflect_short_type_path().to_string())
+                .collect();
+            if !unsupported.is_empty() {
+                warn!(
+                    "Effect '{}' uses modifiers not supported by the CPU simulation, which are ignored: {}",
+                    asset.name,
+                    unsupported.join(", ")
+                );
+            }
+            if asset.capacities().len() > 1 {
+                warn!(
+                    "Effect '{}' has {} particle groups, but the CPU simulation only simulates the first one.",
+                    asset.name,
+                    asset.capacities().len()
+                );
+            }
+        }
+
+        let capacity = asset.capacities()[0].min(max_particles);
+        let transform = transform.copied().unwrap_or_default();
+        let mut new_particles = None;
+        let particles = match cpu_particles {
+            Some(particles) => particles.into_inner(),
+            None => new_particles.insert(CpuParticles::default()),
+        };
+        #[cfg(feature = "2d")]
+        {
+            let z_layer_2d = effect.z_layer_2d.unwrap_or(asset.z_layer_2d);
+            particles.z_layer_2d = match effect.y_sort_2d {
+                Some(scale) => (-transform.translation().y).mul_add(scale, z_layer_2d),
+                None => z_layer_2d,
+            };
+        }
+        let spawn_count = spawner.spawn_count;
+        let time_scale = spawner.time_scale();
+        // Effects with a fixed seed draw from their own generator to stay
+        // deterministic
+        let rng = spawner.rng_mut().unwrap_or(&mut rng.0);
+        if let Err(err) = simulate(
+            particles,
+            asset,
+            effect.simulation_space.unwrap_or(asset.simulation_space),
+            properties,
+            Some(&globals),
+            &transform,
+            spawn_count,
+            capacity,
+            &time,
+            time_scale,
+            rng,
+        ) {
+            if failed_assets.insert(effect.handle.id()) {
+                warn!("Failed to simulate effect '{}' on CPU: {}", asset.name, err);
+            }
+        }
+        if let Some(particles) = new_particles {
+            commands.entity(entity).insert(particles);
+        }
+    }
+}
+
+/// Reference simulation of an effect on CPU, outside of any app.
+///
+/// This runs the same simulation as the [`SimulationFallback::Cpu`] backend
+/// step by step, from an [`EffectAsset`] alone, without any GPU device nor
+/// Bevy app. It's intended for headless contexts like CI, and for unit tests
+/// checking the values produced by modifiers and expressions against known
+/// values. The limitations of [`SimulationFallback::Cpu`] apply; in
+/// particular, only the first particle group is simulated, and unsupported
+/// modifiers are ignored.
+///
+/// The simulation is seeded explicitly, so is deterministic: the same asset
+/// stepped with the same delta times produces the same particles. This allows
+/// seeking the simulation to any time with [`seek()`], including backward by
+/// re-simulating from the start, for example to scrub the timeline of an effect
+/// in an editor or a cinematic tool.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// let mut module = Module::default();
+/// let lifetime = module.lit(2.);
+/// let velocity = module.lit(Vec3::Y);
+/// let asset = EffectAsset::new(vec![16], Spawner::once(4.0.into(), true), module)
+///     .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+///     .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity));
+///
+/// let mut sim = CpuSimulation::new(&asset, 42);
+/// sim.step(&asset, None, 0.5).unwrap();
+/// assert_eq!(sim.particles().len(), 4);
+/// assert_eq!(sim.particles()[0].velocity, Vec3::Y);
+/// ```
+///
+/// [`seek()`]: CpuSimulation::seek
+pub struct CpuSimulation {
+    /// Spawner of the simulated effect.
+    spawner: EffectSpawner,
+    /// Alive particles.
+    particles: CpuParticles,
+    /// Simulation clock.
+    time: Time<EffectSimulation>,
+    /// Transform of the simulated effect instance.
+    transform: GlobalTransform,
+    /// Random number generator of the simulation.
+    rng: Pcg32,
+    /// Seed the simulation was created with, to replay it from the start.
+    seed: u32,
+    /// Fixed time step and number of steps of the last [`seek()`], if the
+    /// simulation was not stepped otherwise since, to resume seeking forward
+    /// without replaying from the start.
+    ///
+    /// [`seek()`]: CpuSimulation::seek
+    replay: Option<(f32, u32)>,
+}
+
+impl CpuSimulation {
+    /// Create a new simulation of an effect asset, seeded with the given
+    /// value.
+    pub fn new(asset: &EffectAsset, seed: u32) -> Self {
+        Self {
+            spawner: EffectSpawner::new(asset),
+            particles: CpuParticles {
+                simulation_space: asset.simulation_space,
+                ..default()
+            },
+            time: Time::default(),
+            transform: GlobalTransform::IDENTITY,
+            rng: Pcg32::seed_from_u64(seed as u64),
+            seed,
+            replay: None,
+        }
+    }
+
+    /// Restart the simulation from scratch, as if newly created with the same
+    /// seed.
+    ///
+    /// All particles are removed, and the simulation time is reset to zero.
+    /// The transform, and the time scale and seed of the spawner, are
+    /// preserved.
+    pub fn restart(&mut self, asset: &EffectAsset) {
+        self.spawner = EffectSpawner::new(asset)
+            .with_time_scale(self.spawner.time_scale())
+            .with_seed(self.spawner.seed());
+        self.particles = CpuParticles {
+            simulation_space: asset.simulation_space,
+            ..default()
+        };
+        self.time = Time::default();
+        self.rng = Pcg32::seed_from_u64(self.seed as u64);
+        self.replay = None;
+    }
+
+    /// Set the transform of the simulated effect instance.
+    pub fn with_transform(mut self, transform: GlobalTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Set the transform of the simulated effect instance.
+    ///
+    /// Like for an effect instance in an app, particles simulated in local
+    /// space follow the transform, while particles simulated in global space
+    /// are only spawned at its location.
+    pub fn set_transform(&mut self, transform: GlobalTransform) {
+        self.transform = transform;
+    }
+
+    /// Get the spawner of the simulated effect.
+    pub fn spawner(&self) -> &EffectSpawner {
+        &self.spawner
+    }
+
+    /// Get the spawner of the simulated effect, for example to reset it or
+    /// change its time scale.
+    pub fn spawner_mut(&mut self) -> &mut EffectSpawner {
+        &mut self.spawner
+    }
+
+    /// Get the simulation time elapsed since the simulation started, in
+    /// seconds.
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.time.elapsed_seconds()
+    }
+
+    /// Get the alive particles, in simulation space.
+    pub fn particles(&self) -> &[CpuParticle] {
+        &self.particles.particles
+    }
+
+    /// Get the quads of the alive particles to render, in world space, with
+    /// the render modifiers applied.
+    pub(crate) fn instances(&self) -> &[GpuCpuParticle] {
+        &self.particles.instances
+    }
+
+    /// Advance the simulation by `dt` seconds.
+    ///
+    /// This ticks the spawner, updates the existing particles, then spawns the
+    /// new ones, like a frame of the [`SimulationFallback::Cpu`] backend.
+    /// Returns the number of particles the spawner requested this step, which
+    /// may be more than the number of particles actually spawned if the
+    /// effect is at capacity.
+    ///
+    /// The `asset` must be the one the simulation was created with.
+    pub fn step(
+        &mut self,
+        asset: &EffectAsset,
+        properties: Option<&EffectProperties>,
+        dt: f32,
+    ) -> Result<u32, ExprError> {
+        self.replay = None;
+        self.time.advance_by(Duration::from_secs_f32(dt));
+        let spawn_count = self.spawner.tick(dt, &mut self.rng);
+        simulate(
+            &mut self.particles,
+            asset,
+            asset.simulation_space,
+            properties,
+            None,
+            &self.transform,
+            spawn_count,
+            asset.capacities()[0],
+            &self.time,
+            self.spawner.time_scale(),
+            &mut self.rng,
+        )?;
+        Ok(spawn_count)
+    }
+
+    /// Seek the simulation to the given time, in seconds since the start.
+    ///
+    /// The simulation is replayed deterministically from the start, with
+    /// steps of `time_step` seconds, the last step being shortened to land
+    /// exactly on `time`. The particles after seeking to a given time are
+    /// therefore always the same, whatever the current time, and whether
+    /// seeking forward or backward. As an optimization, when seeking forward
+    /// from the time of a previous seek with the same `time_step` and no
+    /// shortened step, the simulation resumes from its current state instead
+    /// of replaying from the start.
+    ///
+    /// The cost of seeking backward is proportional to the target time, since
+    /// all steps from the start are simulated again.
+    ///
+    /// The `asset` must be the one the simulation was created with.
+    pub fn seek(
+        &mut self,
+        asset: &EffectAsset,
+        properties: Option<&EffectProperties>,
+        time: f32,
+        time_step: f32,
+    ) -> Result<(), ExprError> {
+        let time = time.max(0.);
+        let time_step = time_step.max(1e-4);
+
+        // Split the time into full steps and a remainder, tolerating rounding
+        // errors so that multiples of the time step don't produce a tiny
+        // extra step.
+        let step_count = (time / time_step + 1e-3).floor() as u32;
+        let remainder = (step_count as f32).mul_add(-time_step, time);
+
+        let resume_from = match self.replay {
+            Some((replay_step, replay_count))
+                if replay_step == time_step && replay_count <= step_count =>
+            {
+                replay_count
+            }
+            _ => {
+                self.restart(asset);
+                0
+            }
+        };
+        for _ in resume_from..step_count {
+            self.step(asset, properties, time_step)?;
+        }
+        if remainder > time_step * 1e-3 {
+            self.step(asset, properties, remainder)?;
+        } else {
+            self.replay = Some((time_step, step_count));
+        }
+        Ok(())
+    }
+
+    /// Step the simulation backward by `dt` seconds.
+    ///
+    /// This seeks the simulation to the current time minus `dt`, clamped to
+    /// zero. See [`seek()`] for details.
+    ///
+    /// [`seek()`]: CpuSimulation::seek
+    pub fn step_back(
+        &mut self,
+        asset: &EffectAsset,
+        properties: Option<&EffectProperties>,
+        dt: f32,
+        time_step: f32,
+    ) -> Result<(), ExprError> {
+        let time = self.elapsed_seconds() - dt;
+        self.seek(asset, properties, time, time_step)
+    }
+
+    /// Evaluate an expression on CPU for a particle, at the current time of
+    /// the simulation.
+    ///
+    /// Random expressions draw from the random number generator of the
+    /// simulation, so evaluating them changes the particles simulated by the
+    /// next steps.
+    pub fn eval(
+        &mut self,
+        module: &Module,
+        expr: ExprHandle,
+        particle: &CpuParticle,
+        properties: Option<&EffectProperties>,
+    ) -> Result<Value, ExprError> {
+        let mut ctx = CpuEvalContext {
+            module,
+            properties,
+            globals: None,
+            time: self.time.elapsed_seconds(),
+            delta_time: self.time.delta_seconds(),
+            particle: *particle,
+            rng: &mut self.rng,
+        };
+        ctx.eval(expr).map(CpuExprValue::to_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spawn::new_rng, Gradient, Spawner};
+
+    fn eval(module: &Module, handle: ExprHandle) -> CpuExprValue {
+        let mut rng = new_rng();
+        let mut ctx = CpuEvalContext {
+            module,
+            properties: None,
+            globals: None,
+            time: 2.,
+            delta_time: 0.5,
+            particle: CpuParticle {
+                age: 1.,
+                velocity: Vec3::new(1., 2., 3.),
+                ..default()
+            },
+            rng: &mut rng,
+        };
+        ctx.eval(handle).unwrap()
+    }
+
+    #[test]
+    fn eval_expr() {
+        let mut m = Module::default();
+        let a = m.lit(Vec3::new(1., 2., 3.));
+        let b = m.lit(2.);
+        let mul = m.mul(a, b);
+        assert_eq!(eval(&m, mul).vec3(), Vec3::new(2., 4., 6.));
+
+        let dt = m.builtin(BuiltInOperator::DeltaTime);
+        let vel = m.attr(Attribute::VELOCITY);
+        let step = m.mul(vel, dt);
+        assert_eq!(eval(&m, step).vec3(), Vec3::new(0.5, 1., 1.5));
+
+        let len = m.length(a);
+        assert_eq!(eval(&m, len).x(), 14_f32.sqrt());
+
+        let z = m.z(a);
+        assert_eq!(eval(&m, z), CpuExprValue::scalar(3.));
+
+        let lo = m.lit(4.);
+        let hi = m.lit(5.);
+        let rand = m.uniform(lo, hi);
+        let value = eval(&m, rand).x();
+        assert!((4. ..=5.).contains(&value));
+
+        let prop = m.add_property("speed", Value::Scalar(ScalarValue::Float(3.)));
+        let prop = m.prop(prop);
+        assert_eq!(eval(&m, prop).x(), 3.);
+    }
+
+    #[test]
+    fn simulate_particles() {
+        let mut module = Module::default();
+        let lifetime = module.lit(1.5);
+        let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
+        let velocity = module.lit(Vec3::X);
+        let init_velocity = SetAttributeModifier::new(Attribute::VELOCITY, velocity);
+        let accel = module.lit(Vec3::Y * 2.);
+        let mut gradient = Gradient::new();
+        gradient.add_key(0., Vec4::ONE);
+        gradient.add_key(1., Vec4::ZERO);
+        let asset = EffectAsset::new(vec![4], Spawner::once(10.0.into(), true), module)
+            .init(init_lifetime)
+            .init(init_velocity)
+            .update(AccelModifier::new(accel))
+            .render(ColorOverLifetimeModifier { gradient });
+
+        let mut time = Time::<EffectSimulation>::default();
+        time.advance_by(std::time::Duration::from_secs_f32(0.5));
+        let transform = GlobalTransform::from_translation(Vec3::Z);
+        let mut rng = new_rng();
+        let mut particles = CpuParticles::default();
+
+        // Spawn is capped to the capacity
+        simulate(
+            &mut particles,
+            &asset,
+            asset.simulation_space,
+            None,
+            None,
+            &transform,
+            10,
+            4,
+            &time,
+            1.,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(particles.len(), 4);
+        assert_eq!(particles.instances.len(), 4);
+        assert_eq!(particles.instances[0].position, [0., 0., 1.]);
+        assert_eq!(particles.instances[0].color, [1.; 4]);
+
+        // Particles are accelerated and integrated
+        simulate(
+            &mut particles,
+            &asset,
+            asset.simulation_space,
+            None,
+            None,
+            &transform,
+            0,
+            4,
+            &time,
+            1.,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(particles.len(), 4);
+        assert_eq!(particles.particles[0].velocity, Vec3::new(1., 1., 0.));
+        assert_eq!(particles.particles[0].position, Vec3::new(0.5, 0.5, 1.));
+        assert_eq!(particles.instances[0].position, [0.5, 0.5, 1.]);
+        assert!(particles.instances[0].color[0] < 1.);
+
+        // Particles die once their age reaches their lifetime
+        for _ in 0..2 {
+            simulate(
+                &mut particles,
+                &asset,
+                asset.simulation_space,
+                None,
+                None,
+                &transform,
+                0,
+                4,
+                &time,
+                1.,
+                &mut rng,
+            )
+            .unwrap();
+        }
+        assert_eq!(particles.len(), 0);
+        assert!(particles.instances.is_empty());
+    }
+
+    #[test]
+    fn switch_simulation_space() {
+        let mut module = Module::default();
+        let lifetime = module.lit(10.);
+        let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
+        let asset =
+            EffectAsset::new(vec![4], Spawner::once(1.0.into(), true), module).init(init_lifetime);
+
+        let mut time = Time::<EffectSimulation>::default();
+        time.advance_by(std::time::Duration::from_secs_f32(0.5));
+        let mut rng = new_rng();
+        let mut particles = CpuParticles::default();
+
+        let transform = GlobalTransform::from_translation(Vec3::Z);
+        simulate(
+            &mut particles,
+            &asset,
+            SimulationSpace::Global,
+            None,
+            None,
+            &transform,
+            1,
+            4,
+            &time,
+            1.,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(particles.particles[0].position, Vec3::Z);
+
+        // Switching to local space converts the particle without moving it
+        simulate(
+            &mut particles,
+            &asset,
+            SimulationSpace::Local,
+            None,
+            None,
+            &transform,
+            0,
+            4,
+            &time,
+            1.,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(particles.particles[0].position, Vec3::ZERO);
+        assert_eq!(particles.instances[0].position, [0., 0., 1.]);
+
+        // Once in local space, the particle follows the effect
+        let transform = GlobalTransform::from_translation(Vec3::Z * 2.);
+        simulate(
+            &mut particles,
+            &asset,
+            SimulationSpace::Local,
+            None,
+            None,
+            &transform,
+            0,
+            4,
+            &time,
+            1.,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(particles.instances[0].position, [0., 0., 2.]);
+
+        // Switching back to global space detaches it again
+        for transform in [transform, GlobalTransform::IDENTITY] {
+            simulate(
+                &mut particles,
+                &asset,
+                SimulationSpace::Global,
+                None,
+                None,
+                &transform,
+                0,
+                4,
+                &time,
+                1.,
+                &mut rng,
+            )
+            .unwrap();
+            assert_eq!(particles.instances[0].position, [0., 0., 2.]);
+        }
+    }
+
+    #[test]
+    fn cpu_simulation() {
+        let mut module = Module::default();
+        let lifetime = module.lit(1.);
+        let velocity = module.lit(Vec3::X * 2.);
+        let accel = module.lit(Vec3::Y);
+        let asset = EffectAsset::new(vec![8], Spawner::once(2.0.into(), true), module)
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+            .update(AccelModifier::new(accel));
+
+        let mut sim = CpuSimulation::new(&asset, 0);
+        assert_eq!(sim.step(&asset, None, 0.5).unwrap(), 2);
+        assert_eq!(sim.particles().len(), 2);
+        assert_eq!(sim.particles()[0].position, Vec3::ZERO);
+        assert_eq!(sim.particles()[0].velocity, Vec3::new(2., 0., 0.));
+
+        // Accelerated, then integrated
+        assert_eq!(sim.step(&asset, None, 0.5).unwrap(), 0);
+        assert_eq!(sim.elapsed_seconds(), 1.);
+        let particle = sim.particles()[0];
+        assert_eq!(particle.age, 0.5);
+        assert_eq!(particle.velocity, Vec3::new(2., 0.5, 0.));
+        assert_eq!(particle.position, Vec3::new(1., 0.25, 0.));
+
+        // Dies once its age reaches its lifetime
+        sim.step(&asset, None, 0.5).unwrap();
+        assert!(sim.particles().is_empty());
+    }
+
+    #[test]
+    fn cpu_simulation_deterministic() {
+        let mut module = Module::default();
+        let center = module.lit(Vec3::ZERO);
+        let radius = module.lit(1.);
+        let asset = EffectAsset::new(vec![8], Spawner::once(8.0.into(), true), module).init(
+            SetPositionSphereModifier {
+                center,
+                radius,
+                dimension: ShapeDimension::Volume,
+            },
+        );
+
+        let positions = |seed: u32| {
+            let mut sim = CpuSimulation::new(&asset, seed);
+            sim.step(&asset, None, 0.1).unwrap();
+            sim.particles()
+                .iter()
+                .map(|particle| particle.position)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(3).len(), 8);
+        assert_eq!(positions(3), positions(3));
+        assert_ne!(positions(3), positions(4));
+    }
+
+    #[test]
+    fn cpu_simulation_seek() {
+        let mut module = Module::default();
+        let center = module.lit(Vec3::ZERO);
+        let radius = module.lit(1.);
+        let speed = module.lit(2.);
+        let lifetime = module.lit(1.);
+        let asset = EffectAsset::new(vec![64], Spawner::rate(20.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+            .init(SetPositionSphereModifier {
+                center,
+                radius,
+                dimension: ShapeDimension::Volume,
+            })
+            .init(SetVelocitySphereModifier { center, speed });
+        let step = 0.05;
+
+        let mut reference = CpuSimulation::new(&asset, 7);
+        for _ in 0..10 {
+            reference.step(&asset, None, step).unwrap();
+        }
+
+        // Seeking forward replays the same steps
+        let mut sim = CpuSimulation::new(&asset, 7);
+        sim.seek(&asset, None, 0.25, step).unwrap();
+        sim.seek(&asset, None, 0.5, step).unwrap();
+        assert_eq!(sim.elapsed_seconds(), reference.elapsed_seconds());
+        assert_eq!(sim.particles(), reference.particles());
+
+        // Seeking backward replays from the start, and lands on the same state
+        // as seeking directly
+        sim.step_back(&asset, None, 0.3, step).unwrap();
+        let mut direct = CpuSimulation::new(&asset, 7);
+        direct.seek(&asset, None, 0.2, step).unwrap();
+        assert_eq!(sim.particles(), direct.particles());
+        assert!(!sim.particles().is_empty());
+
+        sim.seek(&asset, None, 0., step).unwrap();
+        assert_eq!(sim.elapsed_seconds(), 0.);
+        assert!(sim.particles().is_empty());
+    }
+
+    #[test]
+    fn cpu_simulation_eval() {
+        let mut module = Module::default();
+        let one = module.lit(Vec3::ONE);
+        let age = module.attr(Attribute::AGE);
+        let expr = module.mul(one, age);
+        let asset = EffectAsset::new(vec![8], Spawner::once(1.0.into(), true), module);
+
+        let mut sim = CpuSimulation::new(&asset, 0);
+        let particle = CpuParticle {
+            age: 2.,
+            ..default()
+        };
+        assert_eq!(
+            sim.eval(asset.module(), expr, &particle, None).unwrap(),
+            Value::Vector(Vec3::splat(2.).into())
+        );
+        assert_eq!(
+            sim.eval(asset.module(), age, &particle, None).unwrap(),
+            Value::Scalar(2.0.into())
+        );
+    }
+}
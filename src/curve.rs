@@ -0,0 +1,312 @@
+use std::{
+    hash::{Hash, Hasher},
+    ops::{Add, Mul},
+};
+
+use bevy::{
+    math::{Vec2, Vec3, Vec4},
+    reflect::{FromReflect, Reflect},
+    utils::FloatOrd,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{spawn::FloatHash, ToWgslString};
+
+/// Describes a type that can be interpolated by a [`Curve`].
+///
+/// This is implemented for `f32`, [`Vec2`], [`Vec3`], and [`Vec4`].
+pub trait CurveValue:
+    Copy
+    + Default
+    + PartialEq
+    + Add<Output = Self>
+    + Mul<f32, Output = Self>
+    + FloatHash
+    + ToWgslString
+    + FromReflect
+{
+}
+
+impl CurveValue for f32 {}
+impl CurveValue for Vec2 {}
+impl CurveValue for Vec3 {}
+impl CurveValue for Vec4 {}
+
+/// A single key point for a [`Curve`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct CurveKey<T: CurveValue> {
+    /// Ratio in \[0:1\] where the key is located.
+    ratio: f32,
+
+    /// Value associated with the key.
+    pub value: T,
+
+    /// Tangent of the curve arriving at the key, as a rate of change of the
+    /// value per unit of ratio.
+    pub in_tangent: T,
+
+    /// Tangent of the curve leaving the key, as a rate of change of the value
+    /// per unit of ratio.
+    pub out_tangent: T,
+}
+
+impl<T: CurveValue> CurveKey<T> {
+    /// Get the ratio where the key point is located, in \[0:1\].
+    pub fn ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    /// Evaluate the cubic Hermite segment between this key and the next one,
+    /// at a ratio in between both keys.
+    #[allow(clippy::suboptimal_flops)]
+    fn interpolate(&self, next: &Self, ratio: f32) -> T {
+        let dt = next.ratio - self.ratio;
+        let s = (ratio - self.ratio) / dt;
+        let s2 = s * s;
+        let s3 = s2 * s;
+        self.value * (2. * s3 - 3. * s2 + 1.)
+            + self.out_tangent * ((s3 - 2. * s2 + s) * dt)
+            + next.value * (3. * s2 - 2. * s3)
+            + next.in_tangent * ((s3 - s2) * dt)
+    }
+}
+
+impl<T: CurveValue> Hash for CurveKey<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        FloatOrd(self.ratio).hash(state);
+        self.value.hash_f32(state);
+        self.in_tangent.hash_f32(state);
+        self.out_tangent.hash_f32(state);
+    }
+}
+
+/// A smooth curve made of keypoints with tangent handles.
+///
+/// Unlike a [`Gradient`], which interpolates linearly between its keys, the
+/// curve interpolates between two keys with a cubic Hermite spline, whose
+/// shape is controlled by the tangent leaving the first key and the tangent
+/// arriving at the second one. This allows authoring pops, overshoots, and
+/// ease-outs, which look mechanical with piecewise-linear ramps. This is the
+/// same kind of curve as the animation curves of many VFX and game engine
+/// tools.
+///
+/// The curve can be sampled anywhere. Sampling before the first key or after
+/// the last one returns the value of that key.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::Curve;
+/// // Grow quickly, overshoot, then settle at 1.0
+/// let curve = Curve::<f32>::new()
+///     .with_key_tangents(0., 0., 0., 6.)
+///     .with_key(0.3, 1.2)
+///     .with_key(1., 1.);
+/// assert_eq!(curve.sample(0.3), 1.2);
+/// assert_eq!(curve.sample(1.), 1.);
+/// ```
+///
+/// [`Gradient`]: crate::Gradient
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct Curve<T: CurveValue> {
+    keys: Vec<CurveKey<T>>,
+}
+
+impl<T: CurveValue> Hash for Curve<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.keys.hash(state);
+    }
+}
+
+impl<T: CurveValue> Curve<T> {
+    /// Create a new empty curve.
+    pub const fn new() -> Self {
+        Self { keys: vec![] }
+    }
+
+    /// Create a constant curve.
+    ///
+    /// The curve contains `value` at key 0.0 and nothing else.
+    pub fn constant(value: T) -> Self {
+        Self::new().with_key(0., value)
+    }
+
+    /// Create a linear curve between two values.
+    ///
+    /// The curve contains the `start` value at key 0.0 and the `end` value at
+    /// key 1.0, with tangents making the curve a straight line.
+    pub fn linear(start: T, end: T) -> Self {
+        let tangent = end + start * -1.;
+        Self::new()
+            .with_key_tangents(0., start, tangent, tangent)
+            .with_key_tangents(1., end, tangent, tangent)
+    }
+
+    /// Returns `true` if the curve contains no key points.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the number of key points in the curve.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Add a key point with flat tangents to the curve.
+    ///
+    /// Flat (zero) tangents make the curve ease in and out of the key.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    pub fn with_key(mut self, ratio: f32, value: T) -> Self {
+        self.add_key(ratio, value);
+        self
+    }
+
+    /// Add a key point with explicit tangents to the curve.
+    ///
+    /// The tangents are expressed as a rate of change of the value per unit
+    /// of ratio. Using the same tangent for `in_tangent` and `out_tangent`
+    /// makes the curve smooth at the key, while different tangents produce a
+    /// sharp corner.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    pub fn with_key_tangents(
+        mut self,
+        ratio: f32,
+        value: T,
+        in_tangent: T,
+        out_tangent: T,
+    ) -> Self {
+        self.add_key_tangents(ratio, value, in_tangent, out_tangent);
+        self
+    }
+
+    /// Add a key point with flat tangents to the curve.
+    ///
+    /// If one or more keys already exist with the same ratio, append the new
+    /// key after them.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    pub fn add_key(&mut self, ratio: f32, value: T) {
+        self.add_key_tangents(ratio, value, T::default(), T::default());
+    }
+
+    /// Add a key point with explicit tangents to the curve.
+    ///
+    /// If one or more keys already exist with the same ratio, append the new
+    /// key after them. See [`with_key_tangents()`] for details about the
+    /// tangents.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    ///
+    /// [`with_key_tangents()`]: crate::Curve::with_key_tangents
+    pub fn add_key_tangents(&mut self, ratio: f32, value: T, in_tangent: T, out_tangent: T) {
+        assert!(ratio >= 0.0);
+        assert!(ratio <= 1.0);
+        let index = self.keys.partition_point(|k| k.ratio <= ratio);
+        self.keys.insert(
+            index,
+            CurveKey {
+                ratio,
+                value,
+                in_tangent,
+                out_tangent,
+            },
+        );
+    }
+
+    /// Get the curve keys.
+    pub fn keys(&self) -> &[CurveKey<T>] {
+        &self.keys[..]
+    }
+
+    /// Get mutable access to the curve keys.
+    pub fn keys_mut(&mut self) -> &mut [CurveKey<T>] {
+        &mut self.keys[..]
+    }
+
+    /// Sample the curve at the given ratio.
+    ///
+    /// If the ratio is exactly equal to those of one or more keys, sample the
+    /// first key in the collection. If the ratio is before the first key or
+    /// after the last one, return the first and last value, respectively.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the curve is empty (has no key point).
+    pub fn sample(&self, ratio: f32) -> T {
+        assert!(!self.keys.is_empty());
+        let index = self.keys.partition_point(|k| k.ratio < ratio);
+        if index == 0 {
+            self.keys[0].value
+        } else if index == self.keys.len() {
+            self.keys[index - 1].value
+        } else if self.keys[index].ratio == ratio {
+            self.keys[index].value
+        } else {
+            self.keys[index - 1].interpolate(&self.keys[index], ratio)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn sample() {
+        let curve = Curve::<f32>::new().with_key(0.5, 2.);
+        assert_eq!(curve.sample(0.), 2.);
+        assert_eq!(curve.sample(1.), 2.);
+
+        // Flat tangents ease in and out
+        let curve = Curve::<f32>::new().with_key(0., 0.).with_key(1., 1.);
+        assert_eq!(curve.sample(0.), 0.);
+        assert_approx_eq!(curve.sample(0.25), 0.15625);
+        assert_approx_eq!(curve.sample(0.5), 0.5);
+        assert_eq!(curve.sample(1.), 1.);
+
+        // Linear curves are straight lines
+        let curve = Curve::linear(Vec2::ZERO, Vec2::new(2., 4.));
+        assert_approx_eq!(curve.sample(0.25), Vec2::new(0.5, 1.));
+        assert_approx_eq!(curve.sample(0.75), Vec2::new(1.5, 3.));
+
+        // Tangents overshoot
+        let curve = Curve::<f32>::new()
+            .with_key_tangents(0., 0., 0., 8.)
+            .with_key(0.5, 1.);
+        assert!((0..50).any(|i| curve.sample(i as f32 / 100.) > 1.));
+        assert_eq!(curve.sample(0.5), 1.);
+        assert_eq!(curve.sample(0.75), 1.);
+    }
+
+    #[test]
+    fn keys() {
+        let mut curve = Curve::<f32>::new().with_key(0.8, 1.).with_key(0.2, 2.);
+        curve.add_key(0.8, 3.);
+        assert_eq!(curve.len(), 3);
+        let ratios: Vec<_> = curve.keys().iter().map(|k| k.ratio()).collect();
+        assert_eq!(ratios, vec![0.2, 0.8, 0.8]);
+        assert_eq!(curve.keys()[1].value, 1.);
+        assert_eq!(curve.sample(0.8), 1.);
+    }
+
+    #[test]
+    fn serde() {
+        let curve = Curve::new()
+            .with_key_tangents(0., Vec2::ZERO, Vec2::ONE, Vec2::X)
+            .with_key(1., Vec2::ONE);
+        let s = ron::to_string(&curve).unwrap();
+        let curve_serde: Curve<Vec2> = ron::from_str(&s).unwrap();
+        assert_eq!(curve, curve_serde);
+    }
+}
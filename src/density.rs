@@ -0,0 +1,118 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+};
+
+/// Component accumulating the density of the particles of the effect
+/// instances with a [`ParticleDensitySource`] into a 3D image.
+///
+/// Insert this component on an entity with a [`GlobalTransform`]; the volume
+/// is the unit cube centered on the entity, from `-0.5` to `0.5` along each
+/// axis of its local space, so scale the transform to size the volume. The
+/// volume is divided into as many cells as the texels of the [`image`], and
+/// each frame, once all the effects were simulated and drawn, the image is
+/// overwritten with the sum of the density of the particles inside each cell.
+///
+/// The image is meant to be sampled by the volumetric fog or lighting shaders
+/// of the application, so that the smoke of an effect occludes and scatters
+/// the light like the rest of the participating media. It's written at the
+/// end of the frame, so it's one frame behind when sampled. The image must be
+/// a 3D `R32Float` image usable as a storage texture, like the ones created
+/// with [`ParticleDensityVolume::create_image()`]; other images are ignored.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, effect: Res<MySmoke>) {
+///     // A 20x10x20 volume of 64x32x64 cells above the origin
+///     let image = images.add(ParticleDensityVolume::create_image(UVec3::new(64, 32, 64)));
+///     commands.spawn((
+///         ParticleDensityVolume::new(image),
+///         TransformBundle::from_transform(
+///             Transform::from_xyz(0., 5., 0.).with_scale(Vec3::new(20., 10., 20.)),
+///         ),
+///     ));
+///
+///     // Smoke contributing to the volume
+///     commands.spawn((
+///         ParticleEffectBundle::new(effect.0.clone()),
+///         ParticleDensitySource::new(0.2),
+///     ));
+/// }
+/// # #[derive(Resource)]
+/// # struct MySmoke(Handle<EffectAsset>);
+/// ```
+///
+/// [`image`]: ParticleDensityVolume::image
+#[derive(Debug, Default, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct ParticleDensityVolume {
+    /// Image the density is written into.
+    pub image: Handle<Image>,
+}
+
+impl ParticleDensityVolume {
+    /// Accumulate the density into the given image.
+    pub fn new(image: Handle<Image>) -> Self {
+        Self { image }
+    }
+
+    /// Create an image with one texel per cell of the volume, which can be
+    /// both written by the particles and sampled.
+    ///
+    /// The image is cleared to zero density, and only exists on GPU.
+    pub fn create_image(resolution: UVec3) -> Image {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: resolution.x.max(1),
+                height: resolution.y.max(1),
+                depth_or_array_layers: resolution.z.max(1),
+            },
+            TextureDimension::D3,
+            &[0; 4],
+            TextureFormat::R32Float,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_DST
+            | TextureUsages::STORAGE_BINDING;
+        image
+    }
+}
+
+/// Component making the particles of an effect instance contribute to the
+/// [`ParticleDensityVolume`]s.
+///
+/// Each alive particle adds its [`density`] to the cell of each volume its
+/// [`Attribute::POSITION`] falls into. Effects without a position attribute
+/// don't contribute.
+///
+/// [`density`]: ParticleDensitySource::density
+/// [`Attribute::POSITION`]: crate::Attribute::POSITION
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct ParticleDensitySource {
+    /// Density added by each particle to the cell it's in.
+    ///
+    /// The density is accumulated in fixed point, with a precision of
+    /// `1/256`, so smaller values are rounded to zero.
+    pub density: f32,
+}
+
+impl Default for ParticleDensitySource {
+    fn default() -> Self {
+        Self { density: 1. }
+    }
+}
+
+impl ParticleDensitySource {
+    /// Make the particles contribute the given density each.
+    pub fn new(density: f32) -> Self {
+        Self { density }
+    }
+}
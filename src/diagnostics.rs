@@ -0,0 +1,122 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+
+use crate::HanabiStats;
+
+/// Plugin registering the global statistics of Hanabi as Bevy diagnostics.
+///
+/// The values of the [`HanabiStats`] resource, which this plugin inserts, are
+/// added each frame to the [`DiagnosticsStore`] under the paths defined by the
+/// associated constants, so they can be displayed or logged like the frame
+/// time, for example with the `LogDiagnosticsPlugin`.
+///
+/// Like the [`HanabiStats`], the particle counts are read back asynchronously
+/// from GPU and lag a few frames behind, while the render statistics are those
+/// of the previous frame.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::{diagnostic::LogDiagnosticsPlugin, prelude::*};
+/// # use bevy_hanabi::*;
+/// App::new()
+///     .add_plugins(HanabiDiagnosticsPlugin)
+///     .add_plugins(LogDiagnosticsPlugin::filtered(vec![
+///         HanabiDiagnosticsPlugin::ALIVE_PARTICLES,
+///     ]));
+/// ```
+///
+/// [`DiagnosticsStore`]: bevy::diagnostic::DiagnosticsStore
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HanabiDiagnosticsPlugin;
+
+impl HanabiDiagnosticsPlugin {
+    /// Number of alive particles in all effect instances simulated on GPU.
+    pub const ALIVE_PARTICLES: DiagnosticPath = DiagnosticPath::const_new("hanabi/alive_particles");
+    /// Number of effect instances simulated on GPU.
+    pub const EFFECTS: DiagnosticPath = DiagnosticPath::const_new("hanabi/effects");
+    /// Number of effect batches simulated.
+    pub const BATCHES: DiagnosticPath = DiagnosticPath::const_new("hanabi/batches");
+    /// Number of draw batches queued.
+    pub const DRAW_BATCHES: DiagnosticPath = DiagnosticPath::const_new("hanabi/draw_batches");
+    /// Size of the GPU buffers storing the particles, in bytes.
+    pub const BUFFER_MEMORY: DiagnosticPath = DiagnosticPath::const_new("hanabi/buffer_memory");
+    /// Number of pipelines compiled since the previous frame.
+    pub const PIPELINES_COMPILED: DiagnosticPath =
+        DiagnosticPath::const_new("hanabi/pipelines_compiled");
+}
+
+impl Plugin for HanabiDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HanabiStats>()
+            .register_diagnostic(Diagnostic::new(Self::ALIVE_PARTICLES))
+            .register_diagnostic(Diagnostic::new(Self::EFFECTS))
+            .register_diagnostic(Diagnostic::new(Self::BATCHES))
+            .register_diagnostic(Diagnostic::new(Self::DRAW_BATCHES))
+            .register_diagnostic(Diagnostic::new(Self::BUFFER_MEMORY).with_suffix(" B"))
+            .register_diagnostic(Diagnostic::new(Self::PIPELINES_COMPILED))
+            .add_systems(PostUpdate, report_hanabi_diagnostics);
+    }
+}
+
+/// Add the current [`HanabiStats`] to the diagnostics.
+fn report_hanabi_diagnostics(
+    stats: Res<HanabiStats>,
+    mut diagnostics: Diagnostics,
+    mut last_pipeline_count: Local<u32>,
+) {
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::ALIVE_PARTICLES, || {
+        stats.alive_count() as f64
+    });
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::EFFECTS, || {
+        stats.effects.len() as f64
+    });
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::BATCHES, || {
+        stats.batch_count as f64
+    });
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::DRAW_BATCHES, || {
+        stats.draw_batch_count as f64
+    });
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::BUFFER_MEMORY, || {
+        stats.buffer_memory as f64
+    });
+
+    // Pipelines are never removed from the cache, so the difference with the
+    // previous count is the number of newly compiled pipelines
+    let compiled = stats.pipeline_count.saturating_sub(*last_pipeline_count);
+    *last_pipeline_count = stats.pipeline_count;
+    diagnostics.add_measurement(&HanabiDiagnosticsPlugin::PIPELINES_COMPILED, || {
+        compiled as f64
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::diagnostic::DiagnosticsStore;
+
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut app = App::new();
+        app.add_plugins(HanabiDiagnosticsPlugin);
+
+        app.world.resource_mut::<HanabiStats>().pipeline_count = 3;
+        app.update();
+        app.world.resource_mut::<HanabiStats>().buffer_memory = 1024;
+        app.update();
+
+        let store = app.world.resource::<DiagnosticsStore>();
+        let value = |path: DiagnosticPath| store.get(&path).and_then(Diagnostic::value);
+        assert_eq!(value(HanabiDiagnosticsPlugin::BUFFER_MEMORY), Some(1024.));
+        assert_eq!(value(HanabiDiagnosticsPlugin::ALIVE_PARTICLES), Some(0.));
+        assert_eq!(value(HanabiDiagnosticsPlugin::PIPELINES_COMPILED), Some(0.));
+        let compiled = store
+            .get(&HanabiDiagnosticsPlugin::PIPELINES_COMPILED)
+            .unwrap();
+        assert_eq!(compiled.history_len(), 2);
+        assert_eq!(compiled.values().next(), Some(&3.));
+    }
+}
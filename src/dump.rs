@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use crate::{
+    graph::EvalContext, modifier::ShaderWriter, process::EffectAssetProcessError,
+    render::ShaderTemplates, EffectAsset, EffectShaderSource, ExprHandle, ModifierContext,
+};
+
+/// Kind of a shader generated for an effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderKind {
+    /// Compute shader initializing the particles spawned by the main emitter.
+    Init,
+    /// Compute shader initializing the particles spawned by an additional
+    /// emitter.
+    EmitterInit,
+    /// Compute shader updating the particles of a group.
+    Update,
+    /// Render shader drawing the particles of a group.
+    Render,
+}
+
+/// A single WGSL shader generated for an effect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedShader {
+    /// Kind of shader.
+    pub kind: ShaderKind,
+    /// Index of the group for the update and render shaders, or of the
+    /// additional emitter for the emitter init shaders. Always zero for the
+    /// init shader.
+    pub index: u32,
+    /// WGSL source code of the shader.
+    ///
+    /// The code is configured but not specialized; the conditional directives
+    /// like `#ifdef` are still present.
+    pub code: String,
+}
+
+impl GeneratedShader {
+    /// Get the file name of the shader when dumped to disk.
+    pub fn file_name(&self) -> String {
+        match self.kind {
+            ShaderKind::Init => "init.wgsl".to_string(),
+            ShaderKind::EmitterInit => format!("emitter_init_{}.wgsl", self.index),
+            ShaderKind::Update => format!("update_{}.wgsl", self.index),
+            ShaderKind::Render => format!("render_{}.wgsl", self.index),
+        }
+    }
+}
+
+/// A line of a [`GeneratedShader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderLine {
+    /// Index of the shader in [`EffectShaderDump::shaders`].
+    pub shader: usize,
+    /// Line number in the shader, starting at 1.
+    pub line: usize,
+}
+
+/// Location of the code of an expression in the generated shaders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprLines {
+    /// The expression.
+    pub expr: ExprHandle,
+    /// WGSL code the expression evaluates to.
+    pub code: String,
+    /// All the shader lines containing the code of the expression.
+    pub lines: Vec<ShaderLine>,
+}
+
+/// WGSL code generated for an [`EffectAsset`], for debugging purpose.
+///
+/// This contains the final code of all the shaders of the effect, and maps the
+/// expressions of its [`Module`] to the shader lines they appear in, so that a
+/// shader validation error or an unexpected behavior can be traced back to the
+/// expressions and modifiers of the effect.
+///
+/// The mapping is computed by searching for the code of each expression in
+/// the shaders, so short expressions like literals can match unrelated lines.
+/// Expressions with side effects, like random values, are stored in local
+/// variables, and are not mapped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use bevy_hanabi::*;
+/// # let asset = EffectAsset::new(vec![32], Spawner::rate(8.0.into()), Module::default());
+/// let dump = asset.generate_shaders().unwrap();
+/// dump.write_to_dir("target/shaders/my_effect").unwrap();
+/// ```
+///
+/// [`Module`]: crate::Module
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectShaderDump {
+    /// The generated shaders.
+    pub shaders: Vec<GeneratedShader>,
+    /// The shader lines of each expression.
+    pub expressions: Vec<ExprLines>,
+}
+
+impl EffectShaderDump {
+    /// Get the generated shader of the given kind and index, if any.
+    pub fn shader(&self, kind: ShaderKind, index: u32) -> Option<&GeneratedShader> {
+        self.shaders
+            .iter()
+            .find(|shader| shader.kind == kind && shader.index == index)
+    }
+
+    /// Get the shader lines containing the code of an expression.
+    ///
+    /// Returns an empty slice if the expression is unknown, has side effects,
+    /// or doesn't appear in any shader.
+    pub fn lines_of(&self, expr: ExprHandle) -> &[ShaderLine] {
+        self.expressions
+            .iter()
+            .find(|expr_lines| expr_lines.expr == expr)
+            .map(|expr_lines| &expr_lines.lines[..])
+            .unwrap_or_default()
+    }
+
+    /// Write all the shaders into the given directory, along with an
+    /// `expressions.txt` file listing the shader lines of each expression.
+    ///
+    /// The directory is created if needed, and existing files are overwritten.
+    pub fn write_to_dir(&self, dir: impl AsRef<Path>) -> std::io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for shader in &self.shaders {
+            std::fs::write(dir.join(shader.file_name()), &shader.code)?;
+        }
+        let mut mapping = String::new();
+        for expr_lines in &self.expressions {
+            mapping += &format!("expr {}: {}\n", expr_lines.expr.index(), expr_lines.code);
+            for line in &expr_lines.lines {
+                mapping += &format!(
+                    "    {}:{}\n",
+                    self.shaders[line.shader].file_name(),
+                    line.line
+                );
+            }
+        }
+        std::fs::write(dir.join("expressions.txt"), mapping)
+    }
+}
+
+impl EffectAsset {
+    /// Generate the WGSL code of all the shaders of the effect, for debugging
+    /// purpose.
+    ///
+    /// The shaders are generated from the default shader templates, for the
+    /// simulation space of the asset and without any LOD tier, like the shaders
+    /// of an instance without any override. See [`EffectShaderDump`] for
+    /// details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shaders fail to generate.
+    pub fn generate_shaders(&self) -> Result<EffectShaderDump, EffectAssetProcessError> {
+        let templates = ShaderTemplates::default();
+        let source = EffectShaderSource::generate(&templates, self, self.simulation_space, None)
+            .map_err(|err| EffectAssetProcessError::Generate(err.to_string()))?;
+
+        let mut shaders = vec![GeneratedShader {
+            kind: ShaderKind::Init,
+            index: 0,
+            code: source.init,
+        }];
+        for (kind, codes) in [
+            (ShaderKind::EmitterInit, source.emitter_init),
+            (ShaderKind::Update, source.update),
+            (ShaderKind::Render, source.render),
+        ] {
+            shaders.extend(
+                codes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, code)| GeneratedShader {
+                        kind,
+                        index: index as u32,
+                        code,
+                    }),
+            );
+        }
+
+        // Evaluate each expression on its own, and search for its code
+        let module = self.module();
+        let property_layout = self.property_layout();
+        let particle_layout = self.particle_layout();
+        let expressions = module
+            .iter()
+            .filter(|(expr, _)| !module.has_side_effect(*expr))
+            .filter_map(|(expr, _)| {
+                let mut writer =
+                    ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+                let code = writer.eval(module, expr).ok()?;
+                let lines = shaders
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(shader_index, shader)| {
+                        shader
+                            .code
+                            .lines()
+                            .enumerate()
+                            .filter(|(_, line)| line.contains(&code))
+                            .map(move |(line, _)| ShaderLine {
+                                shader: shader_index,
+                                line: line + 1,
+                            })
+                    })
+                    .collect();
+                Some(ExprLines { expr, code, lines })
+            })
+            .collect();
+
+        Ok(EffectShaderDump {
+            shaders,
+            expressions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::{Attribute, ExprWriter, SetAttributeModifier, Spawner};
+
+    #[test]
+    fn generate() {
+        let writer = ExprWriter::new();
+        let position = writer.lit(Vec3::ZERO).expr();
+        let velocity = (writer.lit(Vec3::Y) * writer.lit(4.25)).expr();
+        let asset = EffectAsset::new(vec![32], Spawner::rate(8.0.into()), writer.finish())
+            .init(SetAttributeModifier::new(Attribute::POSITION, position))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity));
+
+        let dump = asset.generate_shaders().unwrap();
+        assert_eq!(dump.shaders.len(), 3);
+        let init = dump.shader(ShaderKind::Init, 0).unwrap();
+        assert!(dump.shader(ShaderKind::Update, 0).is_some());
+        assert!(dump.shader(ShaderKind::Render, 0).is_some());
+        assert!(dump.shader(ShaderKind::Update, 1).is_none());
+
+        // The dumped init shader contains the code of both init modifiers
+        assert!(init
+            .code
+            .contains("particle.position = vec3<f32>(0.,0.,0.);"));
+        assert!(init
+            .code
+            .contains("particle.velocity = (vec3<f32>(0.,1.,0.)) * (4.25);"));
+
+        // The velocity expression is found in the init shader only
+        let lines = dump.lines_of(velocity);
+        assert!(!lines.is_empty());
+        for line in lines {
+            assert_eq!(line.shader, 0);
+            let code = init.code.lines().nth(line.line - 1).unwrap();
+            assert!(code.contains("particle.velocity"));
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use std::path::PathBuf;
+
+use bevy::{prelude::*, reflect::ReflectMut};
+use bevy_egui::{
+    egui::{self, Ui},
+    EguiContexts, EguiPlugin,
+};
+
+use crate::{
+    EffectAsset, EffectSpawner, Expr, ExprHandle, Gradient, Module, ParticleEffect, ScalarValue,
+    ScalarValueMut, Value,
+};
+
+/// Plugin adding an egui window to inspect and edit the effects at runtime.
+///
+/// The window lists all the [`ParticleEffect`] instances, and for the selected
+/// one allows controlling the playback of its [`EffectSpawner`], and editing
+/// its [`EffectAsset`]: the spawner settings, the default value of the
+/// properties, the gradients, and the parameters of the modifiers. Parameters
+/// stored as literal expressions can be edited directly.
+///
+/// The edits are written back to the asset, which updates all the instances of
+/// the effect like hot-reloading it would, and recompiles the effect shaders
+/// if needed. Assets loaded from a file can then be saved back to that file,
+/// which is looked up in [`asset_dir`].
+///
+/// This plugin requires the `editor` feature. It adds the [`EguiPlugin`] if
+/// not already added.
+///
+/// [`asset_dir`]: EffectEditorPlugin::asset_dir
+#[derive(Debug, Clone)]
+pub struct EffectEditorPlugin {
+    /// Directory the asset paths are relative to, used to save the edited
+    /// assets. This is generally the same as the file path of the
+    /// [`AssetPlugin`]. Defaults to `assets`.
+    pub asset_dir: PathBuf,
+}
+
+impl Default for EffectEditorPlugin {
+    fn default() -> Self {
+        Self {
+            asset_dir: PathBuf::from("assets"),
+        }
+    }
+}
+
+impl Plugin for EffectEditorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.insert_resource(EffectEditorConfig {
+            asset_dir: self.asset_dir.clone(),
+        })
+        .add_systems(Update, effect_editor_ui);
+    }
+}
+
+/// Configuration of the [`EffectEditorPlugin`].
+#[derive(Debug, Resource)]
+struct EffectEditorConfig {
+    asset_dir: PathBuf,
+}
+
+/// State of the editor window.
+#[derive(Debug, Default)]
+struct EffectEditorState {
+    /// Entity of the selected effect instance.
+    selected: Option<Entity>,
+    /// Result of the last save, displayed in the window.
+    status: String,
+}
+
+/// Draw the editor window, and apply the edits to the selected effect.
+fn effect_editor_ui(
+    mut contexts: EguiContexts,
+    config: Res<EffectEditorConfig>,
+    asset_server: Option<Res<AssetServer>>,
+    mut assets: ResMut<Assets<EffectAsset>>,
+    mut effects: Query<(
+        Entity,
+        Option<&Name>,
+        &ParticleEffect,
+        Option<&mut EffectSpawner>,
+    )>,
+    mut state: Local<EffectEditorState>,
+) {
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Hanabi effects").show(ctx, |ui| {
+        egui::ScrollArea::vertical()
+            .id_source("hanabi_effect_list")
+            .max_height(150.)
+            .show(ui, |ui| {
+                for (entity, name, effect, _) in effects.iter() {
+                    let label = match name {
+                        Some(name) => format!("{} ({:?})", name, entity),
+                        None => {
+                            let asset_name = assets
+                                .get(&effect.handle)
+                                .map(|asset| asset.name.as_str())
+                                .unwrap_or_default();
+                            format!("{} ({:?})", asset_name, entity)
+                        }
+                    };
+                    if ui
+                        .selectable_label(state.selected == Some(entity), label)
+                        .clicked()
+                    {
+                        state.selected = Some(entity);
+                        state.status.clear();
+                    }
+                }
+            });
+        ui.separator();
+
+        let Some((_, _, effect, spawner)) = state
+            .selected
+            .and_then(|entity| effects.get_mut(entity).ok())
+        else {
+            ui.label("Select an effect to edit it.");
+            return;
+        };
+
+        if let Some(mut spawner) = spawner {
+            ui.horizontal(|ui| {
+                if ui.button("Play").clicked() {
+                    spawner.play();
+                }
+                if ui.button("Pause").clicked() {
+                    spawner.pause();
+                }
+                if ui.button("Restart").clicked() {
+                    spawner.restart();
+                }
+            });
+        }
+
+        // Edit a copy, to only modify the asset, and recompile the effect, when
+        // something actually changed.
+        let id = effect.handle.id();
+        let Some(mut asset) = assets.get(id).cloned() else {
+            ui.label("The effect asset is not loaded.");
+            return;
+        };
+        let changed = egui::ScrollArea::vertical()
+            .id_source("hanabi_effect_asset")
+            .show(ui, |ui| asset_ui(ui, &mut asset))
+            .inner;
+
+        let path = asset_server
+            .as_ref()
+            .and_then(|asset_server| asset_server.get_path(id));
+        ui.separator();
+        ui.horizontal(|ui| {
+            if let Some(path) = &path {
+                if ui.button("Save").clicked() {
+                    let file = config.asset_dir.join(path.path());
+                    state.status = match save_asset(&asset, &file) {
+                        Ok(()) => format!("Saved to {}", file.display()),
+                        Err(err) => format!("Failed to save to {}: {}", file.display(), err),
+                    };
+                }
+            } else {
+                ui.label("The asset was not loaded from a file, and cannot be saved.");
+            }
+            ui.label(state.status.as_str());
+        });
+
+        if changed {
+            if let Some(dst) = assets.get_mut(id) {
+                *dst = asset;
+            }
+        }
+    });
+}
+
+/// Serialize an asset to a file, in the format of the [`EffectAssetLoader`].
+///
+/// [`EffectAssetLoader`]: crate::asset::EffectAssetLoader
+fn save_asset(asset: &EffectAsset, file: &std::path::Path) -> anyhow::Result<()> {
+    let s = ron::ser::to_string_pretty(asset, ron::ser::PrettyConfig::default())?;
+    std::fs::write(file, s)?;
+    Ok(())
+}
+
+/// Draw the editable parts of an asset, and return `true` if any changed.
+fn asset_ui(ui: &mut Ui, asset: &mut EffectAsset) -> bool {
+    let mut changed = false;
+
+    egui::CollapsingHeader::new("Spawner")
+        .default_open(true)
+        .show(ui, |ui| {
+            let spawner = &mut asset.spawner;
+            let mut count = spawner.count();
+            let mut spawn_time = spawner.spawn_time();
+            let mut period = spawner.period();
+            let mut starts_active = spawner.starts_active();
+            if cpu_value_ui(ui, "Count", &mut count) {
+                spawner.set_count(count);
+                changed = true;
+            }
+            if cpu_value_ui(ui, "Spawn time", &mut spawn_time) {
+                spawner.set_spawn_time(spawn_time);
+                changed = true;
+            }
+            if cpu_value_ui(ui, "Period", &mut period) {
+                spawner.set_period(period);
+                changed = true;
+            }
+            if ui.checkbox(&mut starts_active, "Starts active").changed() {
+                spawner.set_starts_active(starts_active);
+                changed = true;
+            }
+        });
+
+    let (module, modifiers) = asset.module_and_modifiers_mut();
+
+    if !module.properties().is_empty() {
+        egui::CollapsingHeader::new("Properties")
+            .default_open(true)
+            .show(ui, |ui| {
+                let properties = module
+                    .properties()
+                    .iter()
+                    .map(|prop| (prop.name().to_string(), *prop.default_value()))
+                    .collect::<Vec<_>>();
+                for (name, mut value) in properties {
+                    if labeled(ui, &name, |ui| value_ui(ui, &mut value)) {
+                        module.set_property_default_value(&name, value);
+                        changed = true;
+                    }
+                }
+            });
+    }
+
+    egui::CollapsingHeader::new("Modifiers")
+        .default_open(true)
+        .show(ui, |ui| {
+            for (index, grouped) in modifiers.enumerate() {
+                let name = grouped.modifier.reflect_short_type_path().to_string();
+                ui.push_id(index, |ui| {
+                    changed |= reflect_ui(ui, &name, grouped.modifier.as_reflect_mut(), module);
+                });
+            }
+        });
+
+    changed
+}
+
+/// Draw a widget with a label on the same line, and return its result.
+fn labeled(ui: &mut Ui, label: &str, add_contents: impl FnOnce(&mut Ui) -> bool) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        add_contents(ui)
+    })
+    .inner
+}
+
+fn cpu_value_ui(ui: &mut Ui, label: &str, value: &mut crate::CpuValue<f32>) -> bool {
+    labeled(ui, label, |ui| match value {
+        crate::CpuValue::Single(x) => ui.add(egui::DragValue::new(x).speed(0.01)).changed(),
+        crate::CpuValue::Uniform((a, b)) => {
+            ui.add(egui::DragValue::new(a).speed(0.01)).changed()
+                | ui.add(egui::DragValue::new(b).speed(0.01)).changed()
+        }
+    })
+}
+
+fn scalar_ui(ui: &mut Ui, value: ScalarValueMut) -> bool {
+    match value {
+        ScalarValueMut::Bool(b) => ui.checkbox(b, "").changed(),
+        ScalarValueMut::Float(f) => ui.add(egui::DragValue::new(f).speed(0.01)).changed(),
+        ScalarValueMut::Int(i) => ui.add(egui::DragValue::new(i)).changed(),
+        ScalarValueMut::Uint(u) => ui.add(egui::DragValue::new(u)).changed(),
+    }
+}
+
+/// Draw the widgets editing a value, and return `true` if it changed.
+fn value_ui(ui: &mut Ui, value: &mut Value) -> bool {
+    match value {
+        Value::Scalar(scalar) => scalar_ui(
+            ui,
+            match scalar {
+                ScalarValue::Bool(b) => ScalarValueMut::Bool(b),
+                ScalarValue::Float(f) => ScalarValueMut::Float(f),
+                ScalarValue::Int(i) => ScalarValueMut::Int(i),
+                ScalarValue::Uint(u) => ScalarValueMut::Uint(u),
+            },
+        ),
+        Value::Vector(vector) => {
+            let mut changed = false;
+            for index in 0..vector.vector_type().count() {
+                changed |= scalar_ui(ui, vector.value_mut(index));
+            }
+            changed
+        }
+        Value::Matrix(matrix) => {
+            let mut changed = false;
+            let matrix_type = matrix.matrix_type();
+            for row in 0..matrix_type.rows() {
+                for col in 0..matrix_type.cols() {
+                    changed |= ui
+                        .add(egui::DragValue::new(matrix.value_mut(row, col)).speed(0.01))
+                        .changed();
+                }
+            }
+            changed
+        }
+    }
+}
+
+/// Draw the widgets editing a reflected value, recursively, and return `true`
+/// if it changed.
+///
+/// Expressions are editable only if they're literal; other expressions are
+/// displayed but can't be edited, since they're generally shared with other
+/// expressions.
+fn reflect_ui(ui: &mut Ui, name: &str, value: &mut dyn Reflect, module: &mut Module) -> bool {
+    if let Some(handle) = value.downcast_ref::<ExprHandle>().copied() {
+        return labeled(ui, name, |ui| match module.get_mut(handle) {
+            Some(Expr::Literal(literal)) => value_ui(ui, literal.value_mut()),
+            Some(expr) => {
+                ui.label(format!("{:?}", expr));
+                false
+            }
+            None => {
+                ui.label("(invalid expression)");
+                false
+            }
+        });
+    }
+    if let Some(gradient) = value.downcast_mut::<Gradient<Vec4>>() {
+        return egui::CollapsingHeader::new(name)
+            .show(ui, |ui| {
+                let mut changed = false;
+                for key in gradient.keys_mut() {
+                    let mut color = key.value.to_array();
+                    if labeled(ui, &format!("{:.2}", key.ratio()), |ui| {
+                        ui.color_edit_button_rgba_unmultiplied(&mut color).changed()
+                    }) {
+                        key.value = Vec4::from_array(color);
+                        changed = true;
+                    }
+                }
+                changed
+            })
+            .body_returned
+            .unwrap_or(false);
+    }
+    if let Some(f) = value.downcast_mut::<f32>() {
+        return labeled(ui, name, |ui| {
+            ui.add(egui::DragValue::new(f).speed(0.01)).changed()
+        });
+    }
+    if let Some(u) = value.downcast_mut::<u32>() {
+        return labeled(ui, name, |ui| ui.add(egui::DragValue::new(u)).changed());
+    }
+    if let Some(b) = value.downcast_mut::<bool>() {
+        return ui.checkbox(b, name).changed();
+    }
+
+    match value.reflect_mut() {
+        ReflectMut::Struct(s) => egui::CollapsingHeader::new(name)
+            .show(ui, |ui| {
+                let mut changed = false;
+                for index in 0..s.field_len() {
+                    let field_name = s.name_at(index).unwrap_or_default().to_string();
+                    if let Some(field) = s.field_at_mut(index) {
+                        changed |= reflect_ui(ui, &field_name, field, module);
+                    }
+                }
+                changed
+            })
+            .body_returned
+            .unwrap_or(false),
+        ReflectMut::TupleStruct(s) => {
+            let mut changed = false;
+            for index in 0..s.field_len() {
+                if let Some(field) = s.field_mut(index) {
+                    changed |= reflect_ui(ui, name, field, module);
+                }
+            }
+            changed
+        }
+        ReflectMut::List(list) => egui::CollapsingHeader::new(name)
+            .show(ui, |ui| {
+                let mut changed = false;
+                for index in 0..list.len() {
+                    if let Some(item) = list.get_mut(index) {
+                        changed |= reflect_ui(ui, &index.to_string(), item, module);
+                    }
+                }
+                changed
+            })
+            .body_returned
+            .unwrap_or(false),
+        ReflectMut::Enum(e) if e.field_len() > 0 => {
+            egui::CollapsingHeader::new(format!("{}: {}", name, e.variant_name()))
+                .show(ui, |ui| {
+                    let mut changed = false;
+                    for index in 0..e.field_len() {
+                        let field_name = e
+                            .name_at(index)
+                            .map_or_else(|| index.to_string(), ToString::to_string);
+                        if let Some(field) = e.field_at_mut(index) {
+                            changed |= reflect_ui(ui, &field_name, field, module);
+                        }
+                    }
+                    changed
+                })
+                .body_returned
+                .unwrap_or(false)
+        }
+        _ => {
+            ui.label(format!("{}: {:?}", name, value));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColorOverLifetimeModifier, LinearDragModifier};
+
+    #[test]
+    fn save_asset() {
+        let mut module = Module::default();
+        let drag = module.lit(2.);
+        let asset = EffectAsset::new(vec![32], crate::Spawner::rate(5.0.into()), module)
+            .update(LinearDragModifier::new(drag))
+            .render(ColorOverLifetimeModifier {
+                gradient: Gradient::linear(Vec4::ONE, Vec4::ZERO),
+            });
+
+        let file = std::env::temp_dir().join("hanabi_editor_save_asset.effect");
+        super::save_asset(&asset, &file).unwrap();
+        let s = std::fs::read_to_string(&file).unwrap();
+        let _ = std::fs::remove_file(&file);
+        let asset_serde: EffectAsset = ron::from_str(&s).unwrap();
+        assert_eq!(asset_serde.spawner, asset.spawner);
+        assert_eq!(asset_serde.module(), asset.module());
+        assert_eq!(asset_serde.modifiers().count(), 2);
+    }
+}
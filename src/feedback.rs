@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+
+use crate::{render::ReadbackChannel, CompiledParticleEffect, SimulationSpace};
+
+/// Scale of the fixed-point values the momentum of particles is accumulated
+/// with on GPU, as floats can't be added atomically. This must match the scale
+/// used by `accumulate_impulse()` in `vfx_update.wgsl`.
+pub(crate) const IMPULSE_SCALE: f32 = 1024.;
+
+/// Impulse transferred by the particles of an effect instance absorbed by a
+/// [`ForceReceiverModifier`], read back from GPU.
+///
+/// This component is inserted and updated automatically on the entities of
+/// the effect instances using a [`ForceReceiverModifier`]. The impulse is the
+/// total momentum of the particles absorbed during a single simulation frame,
+/// in world space. GPU readbacks are asynchronous, so the value is generally a
+/// few frames late, and not all simulation frames are read back. To push a
+/// rigid body continuously, prefer applying the average [`force()`] over the
+/// following frames rather than the impulse itself.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// #[derive(Component)]
+/// struct ExternalForce(Vec3);
+///
+/// // Push the body the water jet effect is aiming at
+/// fn push_body(jets: Query<&ParticleImpulse>, mut bodies: Query<&mut ExternalForce>) {
+///     let Ok(impulse) = jets.get_single() else { return };
+///     for mut force in bodies.iter_mut() {
+///         force.0 = impulse.force();
+///     }
+/// }
+/// ```
+///
+/// [`ForceReceiverModifier`]: crate::ForceReceiverModifier
+/// [`force()`]: ParticleImpulse::force
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct ParticleImpulse {
+    /// Total momentum of the particles absorbed during the simulation frame,
+    /// in world space.
+    pub impulse: Vec3,
+    /// Number of particles absorbed during the simulation frame.
+    pub count: u32,
+    /// Duration of the simulation frame, in seconds.
+    pub delta_time: f32,
+}
+
+impl ParticleImpulse {
+    /// Get the average force applied by the absorbed particles during the
+    /// simulation frame, in world space.
+    ///
+    /// This is the impulse divided by the duration of the frame, or zero if
+    /// that duration is zero.
+    pub fn force(&self) -> Vec3 {
+        if self.delta_time > 0. {
+            self.impulse / self.delta_time
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+/// Impulse of a single effect instance read back from GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImpulseReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Impulse in simulation space, and number of absorbed particles.
+    pub impulse: (Vec3, u32),
+    /// Duration of the simulation frame, in seconds.
+    pub delta_time: f32,
+}
+
+/// Decode the impulse of a single effect instance, as accumulated on GPU by
+/// `accumulate_impulse()` in `vfx_update.wgsl`.
+///
+/// Returns the impulse in simulation space, and the number of particles
+/// accumulated.
+pub(crate) fn decode_impulse(words: &[u32; 4]) -> (Vec3, u32) {
+    let impulse = Vec3::new(
+        words[0] as i32 as f32,
+        words[1] as i32 as f32,
+        words[2] as i32 as f32,
+    ) / IMPULSE_SCALE;
+    (impulse, words[3])
+}
+
+/// Update the [`ParticleImpulse`] of the effect instances using a
+/// [`ForceReceiverModifier`].
+///
+/// [`ForceReceiverModifier`]: crate::ForceReceiverModifier
+pub(crate) fn update_particle_impulses(
+    mut commands: Commands,
+    channel: Res<ReadbackChannel<ImpulseReport>>,
+    mut effects: Query<(
+        &CompiledParticleEffect,
+        Option<&GlobalTransform>,
+        Option<&mut ParticleImpulse>,
+    )>,
+) {
+    for report in channel.take() {
+        // The effect may have been despawned since the readback was issued
+        let Ok((compiled, transform, impulse)) = effects.get_mut(report.entity) else {
+            continue;
+        };
+
+        // Convert the momentum of particles simulated in local space to world space
+        let (mut value, count) = report.impulse;
+        if compiled.simulation_space == SimulationSpace::Local {
+            if let Some(transform) = transform {
+                value = transform.affine().transform_vector3(value);
+            }
+        }
+
+        let new_impulse = ParticleImpulse {
+            impulse: value,
+            count,
+            delta_time: report.delta_time,
+        };
+        match impulse {
+            Some(mut impulse) => *impulse = new_impulse,
+            None => {
+                commands.entity(report.entity).insert(new_impulse);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CPU mirror of `accumulate_impulse()` in `vfx_update.wgsl`.
+    fn accumulate_impulse(words: &mut [u32; 4], momentum: Vec3) {
+        let fixed = (momentum * IMPULSE_SCALE).round();
+        for (i, value) in fixed.to_array().into_iter().enumerate() {
+            words[i] = words[i].wrapping_add(value as i32 as u32);
+        }
+        words[3] += 1;
+    }
+
+    #[test]
+    fn decode() {
+        let mut words = [0; 4];
+        assert_eq!(decode_impulse(&words), (Vec3::ZERO, 0));
+
+        accumulate_impulse(&mut words, Vec3::new(1.5, -2., 0.25));
+        accumulate_impulse(&mut words, Vec3::new(-4., 0.5, 0.25));
+        assert_eq!(decode_impulse(&words), (Vec3::new(-2.5, -1.5, 0.5), 2));
+    }
+
+    #[test]
+    fn force() {
+        let impulse = ParticleImpulse {
+            impulse: Vec3::new(1., 0., -2.),
+            count: 3,
+            delta_time: 0.5,
+        };
+        assert_eq!(impulse.force(), Vec3::new(2., 0., -4.));
+        assert_eq!(ParticleImpulse::default().force(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn update_impulse() {
+        let mut app = App::new();
+        app.init_resource::<ReadbackChannel<ImpulseReport>>()
+            .add_systems(Update, update_particle_impulses);
+
+        let entity = app
+            .world
+            .spawn((
+                CompiledParticleEffect::default(),
+                GlobalTransform::from_translation(Vec3::X),
+            ))
+            .id();
+
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<ImpulseReport>>()
+            .clone();
+        channel.send([ImpulseReport {
+            entity,
+            impulse: (Vec3::new(1., 2., 3.), 4),
+            delta_time: 0.25,
+        }]);
+        app.update();
+        let impulse = app.world.get::<ParticleImpulse>(entity).unwrap();
+        assert_eq!(impulse.impulse, Vec3::new(1., 2., 3.));
+        assert_eq!(impulse.count, 4);
+        assert_eq!(impulse.force(), Vec3::new(4., 8., 12.));
+
+        channel.send([ImpulseReport {
+            entity,
+            impulse: (Vec3::ZERO, 0),
+            delta_time: 0.25,
+        }]);
+        app.update();
+        let impulse = app.world.get::<ParticleImpulse>(entity).unwrap();
+        assert_eq!(impulse.impulse, Vec3::ZERO);
+        assert_eq!(impulse.count, 0);
+    }
+}
@@ -0,0 +1,365 @@
+use bevy::{
+    prelude::*,
+    render::{primitives::Aabb, view::VisibilitySystems},
+    transform::TransformSystem,
+};
+
+use crate::{
+    ConformToSphereModifier, EffectAsset, EffectProperties, Expr, ExprHandle, KillAabbModifier,
+    KillSphereModifier, Module, ParticleEffect, RadialAccelModifier, SetPositionCircleModifier,
+    SetPositionCone3dModifier, SetPositionSphereModifier, SimulationSpace, TangentAccelModifier,
+    Value,
+};
+
+/// Plugin drawing debug gizmos for the spatial settings of effects.
+///
+/// For each effect instance with a [`ShowEffectGizmos`] component, this draws
+/// the outline of the emission shapes of its position modifiers, the kill
+/// zones, the origin of the attractors and other forces, and the bounds of the
+/// instance. This helps authoring spatial modifiers, whose values are
+/// otherwise only visible through the particles they affect.
+///
+/// The bounds drawn are the [`Aabb`] used for visibility culling. An instance
+/// culled from all views this frame has its bounds drawn in a different color
+/// and crossed out, which helps diagnosing bounds too small for the particles,
+/// making the effect disappear when its origin leaves the screen.
+///
+/// Only the values of literal and property expressions can be drawn; the
+/// properties use the current value of the instance. Modifiers whose values
+/// are computed by more complex expressions are skipped. The colors and a
+/// global toggle are configured with the [`EffectGizmoConfig`] resource.
+///
+/// This plugin requires the `gizmos` feature of this crate, which enables the
+/// `bevy_gizmos` feature of Bevy, as well as the [`GizmoPlugin`], which is
+/// part of the Bevy default plugins.
+///
+/// [`GizmoPlugin`]: bevy::gizmos::GizmoPlugin
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EffectGizmosPlugin;
+
+impl Plugin for EffectGizmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EffectGizmoConfig>()
+            .register_type::<EffectGizmoConfig>()
+            .register_type::<ShowEffectGizmos>()
+            .add_systems(
+                PostUpdate,
+                draw_effect_gizmos
+                    .after(TransformSystem::TransformPropagate)
+                    .after(VisibilitySystems::CheckVisibility),
+            );
+    }
+}
+
+/// Configuration of the gizmos drawn by the [`EffectGizmosPlugin`].
+#[derive(Debug, Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct EffectGizmoConfig {
+    /// Draw the gizmos. Defaults to `true`.
+    pub enabled: bool,
+    /// Color of the emission shapes. Defaults to cyan.
+    pub emitter_color: Color,
+    /// Color of the kill zones. Defaults to red.
+    pub kill_color: Color,
+    /// Color of the attractors and other forces. Defaults to yellow.
+    pub force_color: Color,
+    /// Color of the effect bounds. Defaults to green.
+    pub bounds_color: Color,
+    /// Color of the bounds of the effects culled from all views. Defaults to
+    /// grey.
+    pub culled_color: Color,
+}
+
+impl Default for EffectGizmoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            emitter_color: Color::CYAN,
+            kill_color: Color::RED,
+            force_color: Color::YELLOW,
+            bounds_color: Color::GREEN,
+            culled_color: Color::GRAY,
+        }
+    }
+}
+
+/// Marker component for the effect instances to draw the gizmos of.
+///
+/// Insert this component on the entity of a [`ParticleEffect`] to select it
+/// for the [`EffectGizmosPlugin`], and remove it to hide its gizmos.
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct ShowEffectGizmos;
+
+/// Evaluator of the expressions of an effect instance on CPU.
+struct GizmoEval<'a> {
+    module: &'a Module,
+    properties: Option<&'a EffectProperties>,
+}
+
+impl<'a> GizmoEval<'a> {
+    /// Get the value of a literal or property expression.
+    fn value(&self, expr: ExprHandle) -> Option<Value> {
+        match self.module.get(expr)? {
+            Expr::Literal(literal) => Some(*literal.value()),
+            Expr::Property(property) => {
+                let property = self.module.get_property(property.property())?;
+                self.properties
+                    .and_then(|properties| properties.get_stored(property.name()))
+                    .or(Some(*property.default_value()))
+            }
+            _ => None,
+        }
+    }
+
+    fn f32(&self, expr: ExprHandle) -> Option<f32> {
+        self.value(expr)?.try_into().ok()
+    }
+
+    fn vec3(&self, expr: ExprHandle) -> Option<Vec3> {
+        self.value(expr)?.try_into().ok()
+    }
+}
+
+/// Draw the outline of a truncated cone along the Y axis, with its base at
+/// the origin.
+fn draw_cone(
+    gizmos: &mut Gizmos,
+    transform: &GlobalTransform,
+    height: f32,
+    base_radius: f32,
+    top_radius: f32,
+    color: Color,
+) {
+    let scale = transform.compute_transform().scale.max_element();
+    let normal =
+        Direction3d::new(transform.affine().transform_vector3(Vec3::Y)).unwrap_or(Direction3d::Y);
+    let top = Vec3::Y * height;
+    gizmos.circle(transform.translation(), normal, base_radius * scale, color);
+    gizmos.circle(
+        transform.transform_point(top),
+        normal,
+        top_radius * scale,
+        color,
+    );
+    for dir in [Vec3::X, Vec3::Z, Vec3::NEG_X, Vec3::NEG_Z] {
+        gizmos.line(
+            transform.transform_point(dir * base_radius),
+            transform.transform_point(top + dir * top_radius),
+            color,
+        );
+    }
+}
+
+/// Draw a small cross marking a point.
+fn draw_cross(gizmos: &mut Gizmos, position: Vec3, color: Color) {
+    const SIZE: f32 = 0.1;
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+        gizmos.line(position - axis * SIZE, position + axis * SIZE, color);
+    }
+}
+
+/// Draw the gizmos of the effect instances with a [`ShowEffectGizmos`].
+fn draw_effect_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<EffectGizmoConfig>,
+    assets: Res<Assets<EffectAsset>>,
+    effects: Query<
+        (
+            &ParticleEffect,
+            &GlobalTransform,
+            Option<&EffectProperties>,
+            Option<&Aabb>,
+            Option<&ViewVisibility>,
+        ),
+        With<ShowEffectGizmos>,
+    >,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    for (effect, transform, properties, aabb, visibility) in &effects {
+        if let Some(aabb) = aabb {
+            let bounds = transform.mul_transform(
+                Transform::from_translation(aabb.center.into())
+                    .with_scale(Vec3::from(aabb.half_extents) * 2.),
+            );
+            let culled = visibility.is_some_and(|visibility| !visibility.get());
+            if culled {
+                // Cross out the bounds of culled effects
+                gizmos.cuboid(bounds, config.culled_color);
+                for corner in [
+                    Vec3::new(-0.5, -0.5, -0.5),
+                    Vec3::new(0.5, -0.5, -0.5),
+                    Vec3::new(-0.5, 0.5, -0.5),
+                    Vec3::new(0.5, 0.5, -0.5),
+                ] {
+                    gizmos.line(
+                        bounds.transform_point(corner),
+                        bounds.transform_point(-corner),
+                        config.culled_color,
+                    );
+                }
+            } else {
+                gizmos.cuboid(bounds, config.bounds_color);
+            }
+        }
+
+        let Some(asset) = assets.get(&effect.handle) else {
+            continue;
+        };
+        let eval = GizmoEval {
+            module: asset.module(),
+            properties,
+        };
+        let scale = transform.compute_transform().scale.max_element();
+
+        // Emission shapes are relative to the emitter
+        for modifier in asset.init_modifiers() {
+            let modifier = modifier.as_any();
+            if let Some(circle) = modifier.downcast_ref::<SetPositionCircleModifier>() {
+                let (Some(center), Some(axis), Some(radius)) = (
+                    eval.vec3(circle.center),
+                    eval.vec3(circle.axis),
+                    eval.f32(circle.radius),
+                ) else {
+                    continue;
+                };
+                let normal = Direction3d::new(transform.affine().transform_vector3(axis))
+                    .unwrap_or(Direction3d::Y);
+                gizmos.circle(
+                    transform.transform_point(center),
+                    normal,
+                    radius * scale,
+                    config.emitter_color,
+                );
+            } else if let Some(sphere) = modifier.downcast_ref::<SetPositionSphereModifier>() {
+                let (Some(center), Some(radius)) =
+                    (eval.vec3(sphere.center), eval.f32(sphere.radius))
+                else {
+                    continue;
+                };
+                gizmos.sphere(
+                    transform.transform_point(center),
+                    transform.compute_transform().rotation,
+                    radius * scale,
+                    config.emitter_color,
+                );
+            } else if let Some(cone) = modifier.downcast_ref::<SetPositionCone3dModifier>() {
+                let (Some(height), Some(base_radius), Some(top_radius)) = (
+                    eval.f32(cone.height),
+                    eval.f32(cone.base_radius),
+                    eval.f32(cone.top_radius),
+                ) else {
+                    continue;
+                };
+                draw_cone(
+                    &mut gizmos,
+                    transform,
+                    height,
+                    base_radius,
+                    top_radius,
+                    config.emitter_color,
+                );
+            }
+        }
+
+        // Update modifiers are in simulation space
+        let sim_transform = match asset.simulation_space {
+            SimulationSpace::Global => GlobalTransform::IDENTITY,
+            SimulationSpace::Local => *transform,
+        };
+        let sim_scale = sim_transform.compute_transform().scale.max_element();
+        for modifier in asset.update_modifiers() {
+            let modifier = modifier.as_any();
+            if let Some(kill) = modifier.downcast_ref::<KillAabbModifier>() {
+                let (Some(center), Some(half_size)) =
+                    (eval.vec3(kill.center), eval.vec3(kill.half_size))
+                else {
+                    continue;
+                };
+                let zone = sim_transform
+                    .mul_transform(Transform::from_translation(center).with_scale(half_size * 2.));
+                gizmos.cuboid(zone, config.kill_color);
+            } else if let Some(kill) = modifier.downcast_ref::<KillSphereModifier>() {
+                let (Some(center), Some(sqr_radius)) =
+                    (eval.vec3(kill.center), eval.f32(kill.sqr_radius))
+                else {
+                    continue;
+                };
+                gizmos.sphere(
+                    sim_transform.transform_point(center),
+                    sim_transform.compute_transform().rotation,
+                    sqr_radius.sqrt() * sim_scale,
+                    config.kill_color,
+                );
+            } else if let Some(accel) = modifier.downcast_ref::<RadialAccelModifier>() {
+                if let Some(origin) = eval.vec3(accel.origin()) {
+                    draw_cross(
+                        &mut gizmos,
+                        sim_transform.transform_point(origin),
+                        config.force_color,
+                    );
+                }
+            } else if let Some(accel) = modifier.downcast_ref::<TangentAccelModifier>() {
+                if let (Some(origin), Some(axis)) =
+                    (eval.vec3(accel.origin()), eval.vec3(accel.axis()))
+                {
+                    let origin = sim_transform.transform_point(origin);
+                    let axis = sim_transform
+                        .affine()
+                        .transform_vector3(axis.normalize_or_zero());
+                    draw_cross(&mut gizmos, origin, config.force_color);
+                    gizmos.arrow(origin, origin + axis, config.force_color);
+                }
+            } else if let Some(conform) = modifier.downcast_ref::<ConformToSphereModifier>() {
+                if let (Some(origin), Some(radius)) =
+                    (eval.vec3(conform.origin), eval.f32(conform.radius))
+                {
+                    let origin = sim_transform.transform_point(origin);
+                    draw_cross(&mut gizmos, origin, config.force_color);
+                    gizmos.sphere(
+                        origin,
+                        sim_transform.compute_transform().rotation,
+                        radius * sim_scale,
+                        config.force_color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExprWriter;
+
+    #[test]
+    fn eval() {
+        let writer = ExprWriter::new();
+        let lit = writer.lit(Vec3::ONE).expr();
+        let prop = writer.add_property("radius", 2.0.into());
+        let prop = writer.prop(prop).expr();
+        let sum = (writer.lit(1.) + writer.lit(2.)).expr();
+        let module = writer.finish();
+
+        let eval = GizmoEval {
+            module: &module,
+            properties: None,
+        };
+        assert_eq!(eval.vec3(lit), Some(Vec3::ONE));
+        assert_eq!(eval.f32(lit), None);
+        assert_eq!(eval.f32(prop), Some(2.));
+        assert_eq!(eval.f32(sum), None);
+
+        let properties =
+            EffectProperties::default().with_properties([("radius".to_string(), 5.0.into())]);
+        let eval = GizmoEval {
+            module: &module,
+            properties: Some(&properties),
+        };
+        assert_eq!(eval.f32(prop), Some(5.));
+    }
+}
@@ -1,11 +1,14 @@
 use bevy::{
     math::{Quat, Vec2, Vec3, Vec3A, Vec4},
     reflect::{FromReflect, Reflect},
-    utils::FloatOrd,
+    render::{color::Color, render_resource::TextureFormat, texture::Image},
+    utils::{thiserror::Error, FloatOrd},
 };
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
 
+use crate::HdrColor;
+
 /// Describes a type that can be linearly interpolated between two keys.
 ///
 /// This trait is used for values in a gradient, which are primitive types and
@@ -45,6 +48,18 @@ impl_lerp_vecn!(Vec3);
 impl_lerp_vecn!(Vec3A);
 impl_lerp_vecn!(Vec4);
 
+impl Lerp for HdrColor {
+    /// Interpolate the colors in linear space, and the intensities separately.
+    fn lerp(self, other: Self, ratio: f32) -> Self {
+        let start = Vec4::from(self.color.as_linear_rgba_f32());
+        let end = Vec4::from(other.color.as_linear_rgba_f32());
+        Self {
+            color: Color::rgba_linear_from_array(start.lerp(end, ratio)),
+            intensity: Lerp::lerp(self.intensity, other.intensity, ratio),
+        }
+    }
+}
+
 impl Lerp for Quat {
     fn lerp(self, other: Self, ratio: f32) -> Self {
         // We use slerp() instead of lerp() as conceptually we want a smooth
@@ -55,6 +70,82 @@ impl Lerp for Quat {
     }
 }
 
+/// Interpolation of the values of a [`Gradient`] between a key and the next
+/// one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum GradientInterpolation {
+    /// Linear interpolation toward the value of the next key.
+    #[default]
+    Linear,
+    /// Constant value equal to the value of the key, until the next key. This
+    /// produces hard steps, like color bands.
+    Constant,
+    /// Smooth cubic interpolation toward the value of the next key, easing in
+    /// and out of both keys. This uses the same Hermite curve as the WGSL
+    /// `smoothstep()` function.
+    Smooth,
+}
+
+impl GradientInterpolation {
+    /// Check if this is the default [`GradientInterpolation::Linear`].
+    fn is_linear(&self) -> bool {
+        *self == GradientInterpolation::Linear
+    }
+
+    /// Remap a linear interpolation factor in \[0:1\] according to the
+    /// interpolation mode.
+    #[allow(clippy::suboptimal_flops)]
+    fn remap(&self, t: f32) -> f32 {
+        match self {
+            GradientInterpolation::Linear => t,
+            GradientInterpolation::Constant => 0.,
+            GradientInterpolation::Smooth => t * t * (3. - 2. * t),
+        }
+    }
+
+    /// Generate the WGSL code returning the interpolated value between the
+    /// variables `{var}{index}` and `{var}{index+1}`, for ratios up to
+    /// `t{index+1}`.
+    pub(crate) fn segment_shader_code(&self, input: &str, var: &str, index: usize) -> String {
+        let next = index + 1;
+        self.segment_shader_code_with(input, index, |factor| match factor {
+            Some(factor) => format!("return mix({var}{index}, {var}{next}, {factor});"),
+            None => format!("return {var}{index};"),
+        })
+    }
+
+    /// Generate the WGSL code of the segment between the key `index` and the
+    /// next one, for ratios up to `t{index+1}`.
+    ///
+    /// The `body` function generates the statements returning the value of
+    /// the segment, from the expression of the interpolation factor, or `None`
+    /// if the segment is constant.
+    pub(crate) fn segment_shader_code_with(
+        &self,
+        input: &str,
+        index: usize,
+        body: impl Fn(Option<&str>) -> String,
+    ) -> String {
+        let next = index + 1;
+        match self {
+            GradientInterpolation::Linear => format!(
+                "else if ({input} <= t{next}) {{ {} }}\n",
+                body(Some(&format!(
+                    "({input} - t{index}) / (t{next} - t{index})"
+                )))
+            ),
+            // Sampling exactly at the next key returns the next value, like on CPU
+            GradientInterpolation::Constant => {
+                format!("else if ({input} < t{next}) {{ {} }}\n", body(None))
+            }
+            GradientInterpolation::Smooth => format!(
+                "else if ({input} <= t{next}) {{ {} }}\n",
+                body(Some(&format!("smoothstep(t{index}, t{next}, {input})")))
+            ),
+        }
+    }
+}
+
 /// A single key point for a [`Gradient`].
 #[derive(Debug, Default, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct GradientKey<T: Lerp + FromReflect> {
@@ -66,19 +157,41 @@ pub struct GradientKey<T: Lerp + FromReflect> {
     /// The value is uploaded as is to the render shader. For colors, this means
     /// the value does not imply any particular color space by itself.
     pub value: T,
+
+    /// Interpolation of the gradient values between this key and the next
+    /// one. This is ignored for the last key.
+    #[serde(default, skip_serializing_if = "GradientInterpolation::is_linear")]
+    pub interpolation: GradientInterpolation,
 }
 
 impl<T: Lerp + FromReflect> GradientKey<T> {
+    /// Create a new key with a linear interpolation toward the next key.
+    fn new(ratio: f32, value: T) -> Self {
+        Self {
+            ratio,
+            value,
+            interpolation: GradientInterpolation::Linear,
+        }
+    }
+
     /// Get the ratio where the key point is located, in \[0:1\].
     pub fn ratio(&self) -> f32 {
         self.ratio
     }
+
+    /// Interpolate between this key and the next one, at a ratio in between
+    /// both keys.
+    fn interpolate(&self, next: &Self, ratio: f32) -> T {
+        let t = (ratio - self.ratio) / (next.ratio - self.ratio);
+        self.value.lerp(next.value, self.interpolation.remap(t))
+    }
 }
 
 impl Hash for GradientKey<f32> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         FloatOrd(self.ratio).hash(state);
         FloatOrd(self.value).hash(state);
+        self.interpolation.hash(state);
     }
 }
 
@@ -87,6 +200,7 @@ impl Hash for GradientKey<Vec2> {
         FloatOrd(self.ratio).hash(state);
         FloatOrd(self.value.x).hash(state);
         FloatOrd(self.value.y).hash(state);
+        self.interpolation.hash(state);
     }
 }
 
@@ -96,6 +210,18 @@ impl Hash for GradientKey<Vec3> {
         FloatOrd(self.value.x).hash(state);
         FloatOrd(self.value.y).hash(state);
         FloatOrd(self.value.z).hash(state);
+        self.interpolation.hash(state);
+    }
+}
+
+impl Hash for GradientKey<HdrColor> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        FloatOrd(self.ratio).hash(state);
+        for c in self.value.color.as_rgba_f32() {
+            FloatOrd(c).hash(state);
+        }
+        FloatOrd(self.value.intensity).hash(state);
+        self.interpolation.hash(state);
     }
 }
 
@@ -106,15 +232,21 @@ impl Hash for GradientKey<Vec4> {
         FloatOrd(self.value.y).hash(state);
         FloatOrd(self.value.z).hash(state);
         FloatOrd(self.value.w).hash(state);
+        self.interpolation.hash(state);
     }
 }
 
 /// A gradient curve made of keypoints and associated values.
 ///
-/// The gradient can be sampled anywhere, and will return a linear interpolation
-/// of the values of its closest keys. Sampling before 0 or after 1 returns a
+/// The gradient can be sampled anywhere, and will return an interpolation of
+/// the values of its closest keys. Sampling before 0 or after 1 returns a
 /// constant value equal to the one of the closest bound.
 ///
+/// By default the values are interpolated linearly. Each key can select a
+/// different [`GradientInterpolation`] toward the next key, for example to
+/// produce hard color bands and smooth fades in the same gradient. See
+/// [`with_interpolated_key()`].
+///
 /// # Construction
 ///
 /// The most efficient constructors take the entirety of the key points upfront.
@@ -127,6 +259,7 @@ impl Hash for GradientKey<Vec4> {
 /// [`constant()`]: crate::Gradient::constant
 /// [`linear()`]: crate::Gradient::linear
 /// [`from_keys()`]: crate::Gradient::from_keys
+/// [`with_interpolated_key()`]: crate::Gradient::with_interpolated_key
 #[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct Gradient<T: Lerp + FromReflect> {
     keys: Vec<GradientKey<T>>,
@@ -174,7 +307,7 @@ impl<T: Lerp + FromReflect> Gradient<T> {
     /// ```
     pub fn constant(value: T) -> Self {
         Self {
-            keys: vec![GradientKey::<T> { ratio: 0., value }],
+            keys: vec![GradientKey::new(0., value)],
         }
     }
 
@@ -193,16 +326,7 @@ impl<T: Lerp + FromReflect> Gradient<T> {
     /// ```
     pub fn linear(start: T, end: T) -> Self {
         Self {
-            keys: vec![
-                GradientKey::<T> {
-                    ratio: 0.,
-                    value: start,
-                },
-                GradientKey::<T> {
-                    ratio: 1.,
-                    value: end,
-                },
-            ],
+            keys: vec![GradientKey::new(0., start), GradientKey::new(1., end)],
         }
     }
 
@@ -241,7 +365,7 @@ impl<T: Lerp + FromReflect> Gradient<T> {
         // the keys are kept in the correct order.
         let mut keys = keys
             .into_iter()
-            .map(|(ratio, value)| GradientKey { ratio, value })
+            .map(|(ratio, value)| GradientKey::new(ratio, value))
             .collect::<Vec<_>>();
         keys.sort_by(|a, b| FloatOrd(a.ratio).cmp(&FloatOrd(b.ratio)));
         Self { keys }
@@ -338,6 +462,40 @@ impl<T: Lerp + FromReflect> Gradient<T> {
         self
     }
 
+    /// Add a key point to the gradient, with a given interpolation toward the
+    /// next key point.
+    ///
+    /// This is similar to [`with_key()`], but sets the
+    /// [`GradientInterpolation`] of the new key instead of interpolating
+    /// linearly.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::{Gradient, GradientInterpolation};
+    /// let g = Gradient::new()
+    ///     .with_interpolated_key(0., 1., GradientInterpolation::Constant)
+    ///     .with_interpolated_key(0.5, 2., GradientInterpolation::Smooth)
+    ///     .with_key(1., 4.);
+    /// assert_eq!(g.sample(0.25), 1.);
+    /// assert_eq!(g.sample(0.75), 3.);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    ///
+    /// [`with_key()`]: crate::Gradient::with_key
+    pub fn with_interpolated_key(
+        mut self,
+        ratio: f32,
+        value: T,
+        interpolation: GradientInterpolation,
+    ) -> Self {
+        self.add_interpolated_key(ratio, value, interpolation);
+        self
+    }
+
     /// Add a key point to the gradient.
     ///
     /// If one or more duplicate ratios already exist, append the new key after
@@ -349,6 +507,25 @@ impl<T: Lerp + FromReflect> Gradient<T> {
     ///
     /// This method panics if `ratio` is not in the \[0:1\] range.
     pub fn add_key(&mut self, ratio: f32, value: T) {
+        self.add_interpolated_key(ratio, value, GradientInterpolation::Linear);
+    }
+
+    /// Add a key point to the gradient, with a given interpolation toward the
+    /// next key point.
+    ///
+    /// See [`with_interpolated_key()`] for details.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `ratio` is not in the \[0:1\] range.
+    ///
+    /// [`with_interpolated_key()`]: crate::Gradient::with_interpolated_key
+    pub fn add_interpolated_key(
+        &mut self,
+        ratio: f32,
+        value: T,
+        interpolation: GradientInterpolation,
+    ) {
         assert!(ratio >= 0.0);
         assert!(ratio <= 1.0);
         let index = match self
@@ -367,7 +544,14 @@ impl<T: Lerp + FromReflect> Gradient<T> {
             }
             Err(upper_index) => upper_index,
         };
-        self.keys.insert(index, GradientKey { ratio, value });
+        self.keys.insert(
+            index,
+            GradientKey {
+                ratio,
+                value,
+                interpolation,
+            },
+        );
     }
 
     /// Get the gradient keys.
@@ -384,7 +568,8 @@ impl<T: Lerp + FromReflect> Gradient<T> {
     ///
     /// If the ratio is exactly equal to those of one or more keys, sample the
     /// first key in the collection. If the ratio falls between two keys,
-    /// return a linear interpolation of their values. If the ratio is
+    /// return an interpolation of their values according to the
+    /// [`GradientInterpolation`] of the first key. If the ratio is
     /// before the first key or after the last one, return the first and
     /// last value, respectively.
     ///
@@ -410,8 +595,7 @@ impl<T: Lerp + FromReflect> Gradient<T> {
                     if upper_index < self.keys.len() {
                         let key0 = &self.keys[upper_index - 1];
                         let key1 = &self.keys[upper_index];
-                        let t = (ratio - key0.ratio) / (key1.ratio - key0.ratio);
-                        key0.value.lerp(key1.value, t)
+                        key0.interpolate(key1, ratio)
                     } else {
                         // post: sampling point located after the last key
                         self.keys[upper_index - 1].value
@@ -469,14 +653,130 @@ impl<T: Lerp + FromReflect> Gradient<T> {
             } else {
                 let k0 = &self.keys[ikey - 1];
                 let k1 = &self.keys[ikey];
-                let t = (ratio - k0.ratio) / (k1.ratio - k0.ratio);
-                dst[i] = k0.value.lerp(k1.value, t);
+                dst[i] = k0.interpolate(k1, ratio);
             }
             ratio += inc;
         }
     }
 }
 
+/// Error creating a [`Gradient`] from an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum GradientImageError {
+    /// The image doesn't contain any pixel.
+    #[error("The gradient image is empty.")]
+    Empty,
+    /// The format of the image is not supported.
+    #[error("Unsupported gradient image format {0:?}.")]
+    UnsupportedFormat(TextureFormat),
+}
+
+/// Decode a pixel of an image into a linear RGBA color.
+fn decode_pixel(format: TextureFormat, bytes: &[u8]) -> Vec4 {
+    let unorm = |index: usize| bytes[index] as f32 / 255.;
+    match format {
+        TextureFormat::Rgba8Unorm => Vec4::new(unorm(0), unorm(1), unorm(2), unorm(3)),
+        TextureFormat::Bgra8Unorm => Vec4::new(unorm(2), unorm(1), unorm(0), unorm(3)),
+        TextureFormat::Rgba8UnormSrgb => Color::rgba(unorm(0), unorm(1), unorm(2), unorm(3))
+            .as_linear_rgba_f32()
+            .into(),
+        TextureFormat::Bgra8UnormSrgb => Color::rgba(unorm(2), unorm(1), unorm(0), unorm(3))
+            .as_linear_rgba_f32()
+            .into(),
+        TextureFormat::Rgba32Float => {
+            let float = |index: usize| {
+                f32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap())
+            };
+            Vec4::new(float(0), float(1), float(2), float(3))
+        }
+        _ => unreachable!(),
+    }
+}
+
+impl Gradient<Vec4> {
+    /// Create a color gradient from a 1D lookup table (LUT) image.
+    ///
+    /// The image is a horizontal strip of pixels, like the color ramps
+    /// authored in many image and VFX tools, where the leftmost pixel is the
+    /// color at ratio 0.0 and the rightmost one the color at ratio 1.0. If
+    /// the image is taller than wide, it's read as a vertical strip instead,
+    /// from top to bottom. For images more than one pixel thick, the middle
+    /// row (or column) is read.
+    ///
+    /// The colors are converted to linear RGBA. To keep the generated shader
+    /// code small, pixels which can be linearly interpolated from their
+    /// neighbors, within `tolerance` on each color component, don't produce a
+    /// gradient key. A `tolerance` of zero keeps all the pixels which are not
+    /// exactly on a linear ramp.
+    ///
+    /// The image needs to be available on CPU, so must not be loaded with
+    /// [`RenderAssetUsages::RENDER_WORLD`] only. To sample the image at
+    /// render time instead of converting it into a gradient, use a
+    /// [`ColorOverLifetimeTextureModifier`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image is empty, or its format is not one of
+    /// [`TextureFormat::Rgba8Unorm`], [`TextureFormat::Rgba8UnormSrgb`],
+    /// [`TextureFormat::Bgra8Unorm`], [`TextureFormat::Bgra8UnormSrgb`], or
+    /// [`TextureFormat::Rgba32Float`].
+    ///
+    /// [`RenderAssetUsages::RENDER_WORLD`]: bevy::render::render_asset::RenderAssetUsages::RENDER_WORLD
+    /// [`ColorOverLifetimeTextureModifier`]: crate::ColorOverLifetimeTextureModifier
+    pub fn from_image(image: &Image, tolerance: f32) -> Result<Self, GradientImageError> {
+        let format = image.texture_descriptor.format;
+        let pixel_size = match format {
+            TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb => 4,
+            TextureFormat::Rgba32Float => 16,
+            format => return Err(GradientImageError::UnsupportedFormat(format)),
+        };
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        if width == 0 || height == 0 || image.data.len() < width * height * pixel_size {
+            return Err(GradientImageError::Empty);
+        }
+
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * width + x) * pixel_size;
+            decode_pixel(format, &image.data[offset..offset + pixel_size])
+        };
+        let colors: Vec<Vec4> = if width >= height {
+            (0..width).map(|x| pixel(x, height / 2)).collect()
+        } else {
+            (0..height).map(|y| pixel(width / 2, y)).collect()
+        };
+        if colors.len() == 1 {
+            return Ok(Self::constant(colors[0]));
+        }
+
+        // Check if all the colors between two pixels lie on the linear ramp
+        // between them
+        let is_linear = |start: usize, end: usize| {
+            (start + 1..end).all(|index| {
+                let ratio = (index - start) as f32 / (end - start) as f32;
+                let error = colors[start].lerp(colors[end], ratio) - colors[index];
+                error.abs().max_element() <= tolerance
+            })
+        };
+        let last = colors.len() - 1;
+        let ratio = |index: usize| index as f32 / last as f32;
+        let mut keys = vec![(0., colors[0])];
+        let mut start = 0;
+        while start < last {
+            let mut end = start + 1;
+            while end < last && is_linear(start, end + 1) {
+                end += 1;
+            }
+            keys.push((ratio(end), colors[end]));
+            start = end;
+        }
+        Ok(Self::from_keys(keys))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::hash_map::DefaultHasher;
@@ -533,6 +833,64 @@ mod tests {
         assert_approx_eq!(Lerp::lerp(e, s, 0.9), s.slerp(e, 0.1));
     }
 
+    #[test]
+    fn from_image() {
+        use bevy::render::{
+            render_asset::RenderAssetUsages,
+            render_resource::{Extent3d, TextureDimension},
+        };
+
+        let mut image = Image::new(
+            Extent3d {
+                width: 5,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            [
+                [255, 0, 0, 255],
+                [128, 0, 128, 255],
+                [0, 0, 255, 255],
+                [0, 0, 255, 255],
+                [0, 0, 255, 255],
+            ]
+            .concat(),
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::default(),
+        );
+
+        // Pixels on a linear ramp are merged
+        let grad = Gradient::from_image(&image, 1. / 255.).unwrap();
+        let keys = grad.keys();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0].ratio(), 0.);
+        assert_eq!(keys[0].value, RED);
+        assert_eq!(keys[1].ratio(), 0.5);
+        assert_eq!(keys[1].value, BLUE);
+        assert_eq!(keys[2].ratio(), 1.);
+        assert_eq!(keys[2].value, BLUE);
+
+        // A zero tolerance keeps the inexact middle pixel
+        let grad = Gradient::from_image(&image, 0.).unwrap();
+        assert_eq!(grad.keys().len(), 4);
+        assert_eq!(grad.keys()[1].ratio(), 0.25);
+
+        image.texture_descriptor.format = TextureFormat::Rgba16Float;
+        assert_eq!(
+            Gradient::from_image(&image, 0.),
+            Err(GradientImageError::UnsupportedFormat(
+                TextureFormat::Rgba16Float
+            ))
+        );
+
+        image.texture_descriptor.format = TextureFormat::Rgba8Unorm;
+        image.data.clear();
+        assert_eq!(
+            Gradient::from_image(&image, 0.),
+            Err(GradientImageError::Empty)
+        );
+    }
+
     #[test]
     fn constant() {
         let grad = Gradient::constant(3.0);
@@ -601,6 +959,59 @@ mod tests {
         assert_eq!(GREEN, g.sample(1.0));
     }
 
+    #[test]
+    fn interpolation() {
+        let g = Gradient::new()
+            .with_interpolated_key(0., 0., GradientInterpolation::Constant)
+            .with_interpolated_key(0.5, 1., GradientInterpolation::Smooth)
+            .with_key(1., 3.);
+        assert_eq!(g.keys()[0].interpolation, GradientInterpolation::Constant);
+        assert_eq!(g.keys()[2].interpolation, GradientInterpolation::Linear);
+
+        // Constant until the next key
+        assert_eq!(g.sample(0.), 0.);
+        assert_eq!(g.sample(0.499), 0.);
+        assert_eq!(g.sample(0.5), 1.);
+
+        // Smooth step
+        assert_approx_eq!(g.sample(0.75), 2.);
+        assert_approx_eq!(g.sample(0.625), 1.3125);
+        assert_eq!(g.sample(1.), 3.);
+
+        // Sampling in batch is consistent
+        let mut data = [0_f32; 16];
+        g.sample_by(0., 1. / 16., &mut data[..]);
+        for (i, &d) in data.iter().enumerate() {
+            assert_approx_eq!(d, g.sample(i as f32 / 16.));
+        }
+
+        // Only non-linear interpolations are serialized
+        let s = ron::to_string(&g).unwrap();
+        assert_eq!(s.matches("interpolation").count(), 2);
+        let g2: Gradient<f32> = ron::from_str(&s).unwrap();
+        assert_eq!(g, g2);
+    }
+
+    #[test]
+    fn hdr_color() {
+        let g = Gradient::linear(
+            HdrColor::new(Color::rgba_linear(1., 0., 0., 1.)),
+            HdrColor::new(Color::rgba_linear(0., 0., 1., 0.)).with_intensity(5.),
+        );
+        // Colors and intensities are interpolated separately
+        let mid = g.sample(0.5);
+        assert_approx_eq!(
+            Vec4::from(mid.color.as_linear_rgba_f32()),
+            Vec4::new(0.5, 0., 0.5, 0.5)
+        );
+        assert_approx_eq!(mid.intensity, 3.);
+        assert_approx_eq!(mid.to_linear_vec4(), Vec4::new(1.5, 0., 1.5, 0.5));
+
+        let s = ron::to_string(&g).unwrap();
+        let g_serde: Gradient<HdrColor> = ron::from_str(&s).unwrap();
+        assert_eq!(g, g_serde);
+    }
+
     #[test]
     fn sample_by() {
         let g = Gradient::from_keys([(0.5, RED), (0.8, BLUE)]);
@@ -102,10 +102,20 @@
 //! [`Modifier`]: crate::Modifier
 //! [`EffectAsset`]: crate::EffectAsset
 
-use std::{cell::RefCell, num::NonZeroU32, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    hash::{Hash, Hasher},
+    num::NonZeroU32,
+    rc::Rc,
+};
 
-use bevy::{reflect::Reflect, utils::thiserror::Error};
-use serde::{Deserialize, Serialize};
+use bevy::{math::Vec2, reflect::Reflect, utils::thiserror::Error};
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use crate::{
     Attribute, ModifierContext, ParticleLayout, Property, PropertyLayout, ScalarType, ToWgslString,
@@ -151,7 +161,7 @@ impl ExprHandle {
     }
 
     /// Get the zero-based index into the array of the module.
-    fn index(&self) -> usize {
+    pub(crate) fn index(&self) -> usize {
         (self.id.get() - 1) as usize
     }
 }
@@ -195,6 +205,24 @@ impl PropertyHandle {
     }
 }
 
+/// Editor metadata attached to an expression of a [`Module`].
+///
+/// The metadata is not used by Hanabi itself. It's stored and serialized along
+/// with the module, so that node-graph editors can save the layout of the
+/// graph of expressions inside the effect asset, and load it back unchanged.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExprMeta {
+    /// Position of the expression node in the editor.
+    pub position: Vec2,
+    /// Optional label of the node, displayed instead of the expression itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Any other editor-specific data, by key.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
 /// Container for expressions.
 ///
 /// A module represents a storage for a set of expressions used in a single
@@ -211,15 +239,112 @@ impl PropertyHandle {
 /// deleted. Modules are not designed to be used as editing structures, but as
 /// storage and serialization ones.
 ///
+/// # Serialization
+///
+/// The expressions are serialized as a map from their handle to their value, in
+/// the order they were written into the module, so that the references between
+/// expressions can be read from the serialized text, and that appending an
+/// expression only appends a line to it. Each expression can be annotated with
+/// some [`ExprMeta`] like the position of its node in an editor, which is
+/// serialized after the properties, and ignored when comparing or hashing
+/// modules. Modules serialized as a plain list of expressions by previous
+/// versions of Hanabi are still supported.
+///
 /// [`EffectAsset`]: crate::EffectAsset
 /// [`lit()`]: Module::lit
 /// [`attr()`]: Module::attr
-#[derive(Debug, Default, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Reflect, Serialize, Deserialize)]
 pub struct Module {
     /// Expressions defined in the module.
+    #[serde(
+        serialize_with = "serialize_expressions",
+        deserialize_with = "deserialize_expressions"
+    )]
     expressions: Vec<Expr>,
     /// Properties used as part of a [`PropertyExpr`].
     properties: Vec<Property>,
+    /// Editor metadata of the expressions, by handle.
+    #[reflect(ignore)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    meta: BTreeMap<ExprHandle, ExprMeta>,
+}
+
+impl PartialEq for Module {
+    fn eq(&self, other: &Self) -> bool {
+        // The editor metadata doesn't change the meaning of the module.
+        self.expressions == other.expressions && self.properties == other.properties
+    }
+}
+
+impl Hash for Module {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.expressions.hash(state);
+        self.properties.hash(state);
+    }
+}
+
+/// Serialize the expressions of a [`Module`] as a map from their handle.
+fn serialize_expressions<S: Serializer>(
+    expressions: &[Expr],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(expressions.iter().enumerate().map(|(index, expr)| {
+        // SAFETY - index + 1 is non-zero.
+        #[allow(unsafe_code)]
+        let handle = unsafe { ExprHandle::new_unchecked(index + 1) };
+        (handle, expr)
+    }))
+}
+
+/// Deserialize the expressions of a [`Module`], either from a map from their
+/// handle, or from a plain list.
+fn deserialize_expressions<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Expr>, D::Error> {
+    struct ExpressionsVisitor;
+
+    impl<'de> Visitor<'de> for ExpressionsVisitor {
+        type Value = Vec<Expr>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map of expressions by handle, or a list of expressions")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut expressions = vec![];
+            while let Some(expr) = seq.next_element()? {
+                expressions.push(expr);
+            }
+            Ok(expressions)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut expressions = BTreeMap::<ExprHandle, Expr>::new();
+            while let Some((handle, expr)) = map.next_entry()? {
+                if expressions.insert(handle, expr).is_some() {
+                    return Err(serde::de::Error::custom(format!(
+                        "duplicate expression handle {}",
+                        handle.id
+                    )));
+                }
+            }
+            // Handles are indices, so must be contiguous, starting at 1.
+            if let Some((index, handle)) = expressions
+                .keys()
+                .enumerate()
+                .find(|(index, handle)| handle.index() != *index)
+            {
+                return Err(serde::de::Error::custom(format!(
+                    "missing expression handle {} before handle {}",
+                    index + 1,
+                    handle.id
+                )));
+            }
+            Ok(expressions.into_values().collect())
+        }
+    }
+
+    deserializer.deserialize_any(ExpressionsVisitor)
 }
 
 macro_rules! impl_module_unary {
@@ -258,6 +383,7 @@ impl Module {
         Self {
             expressions: expr,
             properties: vec![],
+            meta: BTreeMap::new(),
         }
     }
 
@@ -313,6 +439,22 @@ impl Module {
         &self.properties
     }
 
+    /// Set the default value of an existing property.
+    ///
+    /// Returns `false` if the property doesn't exist, or if the value type
+    /// doesn't match the type of the property, in which case the property is
+    /// left unchanged.
+    pub(crate) fn set_property_default_value(&mut self, name: &str, value: Value) -> bool {
+        let Some(property) = self.properties.iter_mut().find(|prop| prop.name() == name) else {
+            return false;
+        };
+        if property.value_type() != value.value_type() {
+            return false;
+        }
+        *property = Property::new(name, value);
+        true
+    }
+
     /// Append a new expression to the module.
     fn push(&mut self, expr: impl Into<Expr>) -> ExprHandle {
         self.expressions.push(expr.into());
@@ -544,6 +686,36 @@ impl Module {
                 "Cannot find expression with handle {:?} in the current module. Check that the Module used to build the expression was the same used in the EvalContext or the original EffectAsset.", expr)))
     }
 
+    /// Iterate over all the expressions of the module, with their handle, in
+    /// the order they were written into the module.
+    pub fn iter(&self) -> impl Iterator<Item = (ExprHandle, &Expr)> {
+        self.expressions.iter().enumerate().map(|(index, expr)| {
+            // SAFETY - index + 1 is non-zero.
+            #[allow(unsafe_code)]
+            let handle = unsafe { ExprHandle::new_unchecked(index + 1) };
+            (handle, expr)
+        })
+    }
+
+    /// Get the editor metadata of an expression, if any.
+    pub fn meta(&self, expr: ExprHandle) -> Option<&ExprMeta> {
+        self.meta.get(&expr)
+    }
+
+    /// Set the editor metadata of an expression, or remove it if `None`.
+    ///
+    /// The metadata is ignored if `expr` doesn't refer to an expression of this
+    /// module.
+    pub fn set_meta(&mut self, expr: ExprHandle, meta: Option<ExprMeta>) {
+        if expr.index() >= self.expressions.len() {
+            return;
+        }
+        match meta {
+            Some(meta) => self.meta.insert(expr, meta),
+            None => self.meta.remove(&expr),
+        };
+    }
+
     /// Is the expression resulting in a compile-time constant which can be
     /// hard-coded into a shader's code?
     ///
@@ -616,6 +788,15 @@ pub enum ExprError {
     /// another [`ModifierContext`] was available.
     #[error("Invalid modifier context {0}, expected {1} instead.")]
     InvalidModifierContext(ModifierContext, ModifierContext),
+
+    /// Error resolving a particle group by name.
+    ///
+    /// A modifier referenced a group name which was not declared with
+    /// [`EffectAsset::with_group_names()`].
+    ///
+    /// [`EffectAsset::with_group_names()`]: crate::EffectAsset::with_group_names
+    #[error("Unknown particle group '{0}'.")]
+    UnknownGroupError(String),
 }
 
 /// Evaluation context for transforming expressions into WGSL code.
@@ -1060,6 +1241,16 @@ impl LiteralExpr {
         self.value.value_type()
     }
 
+    /// Get the constant value of the expression.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Get mutable access to the constant value of the expression.
+    pub fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
     /// Evaluate the expression in the given context.
     pub fn eval(&self, _context: &dyn EvalContext) -> Result<String, ExprError> {
         Ok(self.value.to_wgsl_string())
@@ -1110,6 +1301,11 @@ impl AttributeExpr {
         self.attr.value_type()
     }
 
+    /// Get the attribute the expression reads.
+    pub fn attr(&self) -> Attribute {
+        self.attr
+    }
+
     /// Evaluate the expression in the given context.
     pub fn eval(&self, context: &dyn EvalContext) -> Result<String, ExprError> {
         if context.is_attribute_pointer() {
@@ -1154,6 +1350,11 @@ impl PropertyExpr {
         Self { property }
     }
 
+    /// Get the handle of the property the expression reads.
+    pub fn property(&self) -> PropertyHandle {
+        self.property
+    }
+
     /// Is the expression resulting in a compile-time constant which can be
     /// hard-coded into a shader's code?
     fn is_const(&self) -> bool {
@@ -1203,6 +1404,11 @@ impl CastExpr {
         self.target
     }
 
+    /// Get the operand expression to cast.
+    pub fn inner(&self) -> ExprHandle {
+        self.inner
+    }
+
     /// Try to evaluate if the cast expression is valid.
     ///
     /// The evaluation fails if the value type of the operand cannot be
@@ -1392,6 +1598,11 @@ impl BuiltInExpr {
         matches!(self.operator, BuiltInOperator::Rand(_))
     }
 
+    /// Get the built-in operator of the expression.
+    pub fn operator(&self) -> BuiltInOperator {
+        self.operator
+    }
+
     /// Get the value type of the expression.
     ///
     /// The value type of the expression is the type of the value(s) that an
@@ -3465,6 +3676,62 @@ mod tests {
         assert_eq!(m.try_get_mut(x), Ok(&mut expected));
     }
 
+    #[test]
+    fn module_serde() {
+        let mut m = Module::default();
+        let x = m.lit(5.);
+        let y = m.lit(2u32);
+        let z = m.add(x, y);
+        assert_eq!(
+            m.iter().map(|(handle, _)| handle).collect::<Vec<_>>(),
+            vec![x, y, z]
+        );
+
+        let meta = ExprMeta {
+            position: Vec2::new(-3., 12.5),
+            label: Some("sum".to_string()),
+            extra: BTreeMap::from([("color".to_string(), "#ff0000".to_string())]),
+        };
+        m.set_meta(z, Some(meta.clone()));
+        assert_eq!(m.meta(z), Some(&meta));
+        assert_eq!(m.meta(x), None);
+
+        // Metadata doesn't change the module itself
+        let mut m2 = m.clone();
+        m2.set_meta(z, None);
+        assert_eq!(m2.meta(z), None);
+        assert_eq!(m, m2);
+
+        // Unknown handles are ignored
+        #[allow(unsafe_code)]
+        let unknown = unsafe { ExprHandle::new_unchecked(4) };
+        m2.set_meta(unknown, Some(meta.clone()));
+        assert_eq!(m2.meta(unknown), None);
+
+        let s = ron::to_string(&m).unwrap();
+        assert_eq!(
+            s,
+            "(expressions:{1:Literal(Scalar(Float(5.0))),2:Literal(Scalar(Uint(2))),3:Binary(op:Add,left:1,right:2)},properties:[],meta:{3:(position:(-3.0,12.5),label:Some(\"sum\"),extra:{\"color\":\"#ff0000\"})})"
+        );
+        let m_serde: Module = ron::from_str(&s).unwrap();
+        assert_eq!(m_serde, m);
+        assert_eq!(m_serde.meta(z), Some(&meta));
+
+        // Format of previous versions, as a plain list of expressions
+        let m_serde: Module = ron::from_str(
+            "(expressions:[Literal(Scalar(Float(5.0))),Literal(Scalar(Uint(2))),Binary(op:Add,left:1,right:2)],properties:[])",
+        )
+        .unwrap();
+        assert_eq!(m_serde, m);
+        assert_eq!(m_serde.meta(z), None);
+
+        // Handles must be contiguous
+        assert!(ron::from_str::<Module>(
+            "(expressions:{1:Literal(Scalar(Float(5.0))),3:Literal(Scalar(Uint(2)))},properties:[])"
+        )
+        .is_err());
+    }
+
     #[test]
     fn local_var() {
         let property_layout = PropertyLayout::default();
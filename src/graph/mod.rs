@@ -55,15 +55,17 @@ use crate::{MatrixType, ScalarType, ToWgslString, ValueType, VectorType};
 
 pub mod expr;
 pub mod node;
+mod parse;
 
 pub use expr::{
     AttributeExpr, BinaryOperator, BuiltInExpr, BuiltInOperator, EvalContext, Expr, ExprError,
-    ExprHandle, ExprWriter, LiteralExpr, Module, PropertyExpr, UnaryOperator, WriterExpr,
+    ExprHandle, ExprMeta, ExprWriter, LiteralExpr, Module, PropertyExpr, UnaryOperator, WriterExpr,
 };
 pub use node::{
     AddNode, AttributeNode, DivNode, Graph, MulNode, Node, NormalizeNode, Slot, SlotDir, SlotId,
     SubNode, TimeNode,
 };
+pub use parse::ParseError;
 
 /// Variant storage for a scalar value.
 #[derive(Debug)]
@@ -0,0 +1,702 @@
+//! Parser for the text representation of expressions.
+//!
+//! This allows writing expressions as formulas embedded in data files or typed
+//! in an editor, instead of building them node by node. See
+//! [`Module::parse()`] for the syntax.
+
+use std::ops::Range;
+
+use bevy::utils::thiserror::Error;
+
+use super::{
+    expr::{PropertyHandle, TernaryOperator},
+    BinaryOperator, BuiltInOperator, ExprHandle, Module, ScalarValue, UnaryOperator, Value,
+};
+use crate::{Attribute, ScalarType, ToWgslString, ValueType, VectorType};
+
+/// Error produced when parsing an expression from text fails.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at {}..{}", span.start, span.end)]
+pub struct ParseError {
+    /// Description of the error.
+    pub message: String,
+    /// Range of bytes of the source text where the error occurred.
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// Unary operators called with a functional syntax, like `abs(x)`.
+const UNARY_FUNCTIONS: [UnaryOperator; 23] = [
+    UnaryOperator::Abs,
+    UnaryOperator::All,
+    UnaryOperator::Any,
+    UnaryOperator::Ceil,
+    UnaryOperator::Cos,
+    UnaryOperator::Exp,
+    UnaryOperator::Exp2,
+    UnaryOperator::Floor,
+    UnaryOperator::Fract,
+    UnaryOperator::InvSqrt,
+    UnaryOperator::Length,
+    UnaryOperator::Log,
+    UnaryOperator::Log2,
+    UnaryOperator::Normalize,
+    UnaryOperator::Pack4x8snorm,
+    UnaryOperator::Pack4x8unorm,
+    UnaryOperator::Saturate,
+    UnaryOperator::Sign,
+    UnaryOperator::Sin,
+    UnaryOperator::Sqrt,
+    UnaryOperator::Tan,
+    UnaryOperator::Unpack4x8snorm,
+    UnaryOperator::Unpack4x8unorm,
+];
+
+/// Binary operators called with a functional syntax, like `min(a, b)`.
+const BINARY_FUNCTIONS: [BinaryOperator; 8] = [
+    BinaryOperator::Cross,
+    BinaryOperator::Distance,
+    BinaryOperator::Dot,
+    BinaryOperator::Max,
+    BinaryOperator::Min,
+    BinaryOperator::Step,
+    BinaryOperator::UniformRand,
+    BinaryOperator::Vec2,
+];
+
+/// Ternary operators, all called with a functional syntax.
+const TERNARY_FUNCTIONS: [TernaryOperator; 3] = [
+    TernaryOperator::Mix,
+    TernaryOperator::SmoothStep,
+    TernaryOperator::Vec3,
+];
+
+/// Built-in operators referenced by their bare name, like `time`.
+const BUILT_IN_VALUES: [BuiltInOperator; 7] = [
+    BuiltInOperator::Time,
+    BuiltInOperator::DeltaTime,
+    BuiltInOperator::VirtualTime,
+    BuiltInOperator::VirtualDeltaTime,
+    BuiltInOperator::RealTime,
+    BuiltInOperator::RealDeltaTime,
+    BuiltInOperator::AlphaCutoff,
+];
+
+/// Find the built-in random operator with the given name, like `frand3`.
+fn rand_operator(name: &str) -> Option<BuiltInOperator> {
+    [
+        ScalarType::Bool,
+        ScalarType::Float,
+        ScalarType::Int,
+        ScalarType::Uint,
+    ]
+    .into_iter()
+    .flat_map(|scalar_type| {
+        [
+            ValueType::Scalar(scalar_type),
+            VectorType::new(scalar_type, 2).into(),
+            VectorType::new(scalar_type, 3).into(),
+            VectorType::new(scalar_type, 4).into(),
+        ]
+    })
+    .map(BuiltInOperator::Rand)
+    .find(|op| op.name() == name)
+}
+
+/// Lexical token of the source text.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+/// Split the source text into tokens, with their span.
+fn tokenize(source: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    const PUNCTS: [&str; 13] = [
+        "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "(", ")", ",", ".",
+    ];
+
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let start = pos;
+        if c.is_ascii_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_digit() {
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+            let mut is_float = false;
+            // Don't consume the dot of a swizzle or attribute access
+            if pos < bytes.len()
+                && bytes[pos] == b'.'
+                && !bytes
+                    .get(pos + 1)
+                    .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_')
+            {
+                is_float = true;
+                pos += 1;
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+            }
+            if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+                is_float = true;
+                pos += 1;
+                if pos < bytes.len() && (bytes[pos] == b'+' || bytes[pos] == b'-') {
+                    pos += 1;
+                }
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+            }
+            let text = &source[start..pos];
+            let suffix = bytes.get(pos).copied().filter(u8::is_ascii_alphabetic);
+            if suffix.is_some() {
+                pos += 1;
+            }
+            let value: Option<Value> = match suffix {
+                None if is_float => text.parse::<f32>().ok().map(Into::into),
+                None | Some(b'i') if !is_float => text.parse::<i32>().ok().map(Into::into),
+                Some(b'u') if !is_float => text.parse::<u32>().ok().map(Into::into),
+                Some(b'f') => text.parse::<f32>().ok().map(Into::into),
+                _ => None,
+            };
+            let Some(value) = value else {
+                return Err(ParseError::new(
+                    format!("Invalid number literal '{}'", &source[start..pos]),
+                    start..pos,
+                ));
+            };
+            tokens.push((Token::Literal(value), start..pos));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            tokens.push((Token::Ident(source[start..pos].to_string()), start..pos));
+        } else if let Some(punct) = PUNCTS
+            .iter()
+            .find(|punct| source[start..].starts_with(**punct))
+        {
+            pos += punct.len();
+            tokens.push((Token::Punct(punct), start..pos));
+        } else {
+            let len = source[start..].chars().next().map_or(1, char::len_utf8);
+            return Err(ParseError::new(
+                format!("Unexpected character '{}'", &source[start..start + len]),
+                start..start + len,
+            ));
+        }
+    }
+    tokens.push((Token::End, source.len()..source.len()));
+    Ok(tokens)
+}
+
+/// Parsed expression, not yet added to a [`Module`].
+#[derive(Debug, Clone, PartialEq)]
+enum Ast {
+    Literal(Value),
+    Attribute(Attribute),
+    Property(PropertyHandle),
+    BuiltIn(BuiltInOperator),
+    Unary(UnaryOperator, Box<Ast>),
+    Binary(BinaryOperator, Box<Ast>, Box<Ast>),
+    Ternary(TernaryOperator, Box<Ast>, Box<Ast>, Box<Ast>),
+}
+
+impl Ast {
+    /// Get the scalar type of the value of the expression, if known without
+    /// evaluating it.
+    fn scalar_type(&self) -> Option<ScalarType> {
+        let value_type = match self {
+            Ast::Literal(value) => value.value_type(),
+            Ast::Attribute(attr) => attr.value_type(),
+            Ast::BuiltIn(op) => op.value_type(),
+            _ => return None,
+        };
+        match value_type {
+            ValueType::Scalar(scalar_type) => Some(scalar_type),
+            ValueType::Vector(vector_type) => Some(vector_type.elem_type()),
+            ValueType::Matrix(_) => Some(ScalarType::Float),
+        }
+    }
+
+    /// Add the expression to a module.
+    fn emit(self, module: &mut Module) -> ExprHandle {
+        match self {
+            Ast::Literal(value) => module.lit(value),
+            Ast::Attribute(attr) => module.attr(attr),
+            Ast::Property(property) => module.prop(property),
+            Ast::BuiltIn(op) => module.builtin(op),
+            Ast::Unary(op, inner) => {
+                let inner = inner.emit(module);
+                module.unary(op, inner)
+            }
+            Ast::Binary(op, left, right) => {
+                let left = left.emit(module);
+                let right = right.emit(module);
+                module.binary(op, left, right)
+            }
+            Ast::Ternary(op, first, second, third) => {
+                let first = first.emit(module);
+                let second = second.emit(module);
+                let third = third.emit(module);
+                module.ternary(op, first, second, third)
+            }
+        }
+    }
+}
+
+/// Recursive descent parser building an [`Ast`] from tokens.
+struct Parser<'a> {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    module: &'a Module,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.tokens[self.pos].1.clone()
+    }
+
+    fn next(&mut self) -> (Token, Range<usize>) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Consume the next token if it's the given punctuation.
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Token::Punct(p) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", punct)))
+        }
+    }
+
+    /// Make an error for the current token, which is not what was expected.
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let found = match self.peek() {
+            Token::End => "end of expression".to_string(),
+            Token::Punct(punct) => format!("'{}'", punct),
+            Token::Ident(ident) => format!("'{}'", ident),
+            Token::Literal(_) => "literal".to_string(),
+        };
+        ParseError::new(
+            format!("Expected {}, found {}", expected, found),
+            self.span(),
+        )
+    }
+
+    fn parse_expr(&mut self) -> Result<Ast, ParseError> {
+        let left = self.parse_additive()?;
+        let op = match self.peek() {
+            Token::Punct("<") => BinaryOperator::LessThan,
+            Token::Punct("<=") => BinaryOperator::LessThanOrEqual,
+            Token::Punct(">") => BinaryOperator::GreaterThan,
+            Token::Punct(">=") => BinaryOperator::GreaterThanOrEqual,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_additive()?;
+        Ok(Ast::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Ast, ParseError> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct("+") => BinaryOperator::Add,
+                Token::Punct("-") => BinaryOperator::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = Ast::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Ast, ParseError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct("*") => BinaryOperator::Mul,
+                Token::Punct("/") => BinaryOperator::Div,
+                Token::Punct("%") => BinaryOperator::Remainder,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = Ast::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Ast, ParseError> {
+        let start = self.span().start;
+        if !self.eat("-") {
+            return self.parse_postfix();
+        }
+        let inner = self.parse_unary()?;
+        let span = start..self.tokens[self.pos - 1].1.end;
+        // Fold the negation of literals, and otherwise multiply by -1 since there's
+        // no negation operator.
+        match inner {
+            Ast::Literal(Value::Scalar(ScalarValue::Float(f))) => Ok(Ast::Literal((-f).into())),
+            Ast::Literal(Value::Scalar(ScalarValue::Int(i))) => Ok(Ast::Literal((-i).into())),
+            inner => match inner.scalar_type() {
+                Some(ScalarType::Uint) | Some(ScalarType::Bool) => Err(ParseError::new(
+                    "Cannot negate an unsigned or boolean value",
+                    span,
+                )),
+                Some(ScalarType::Int) => Ok(Ast::Binary(
+                    BinaryOperator::Mul,
+                    Box::new(Ast::Literal(Value::from(-1_i32))),
+                    Box::new(inner),
+                )),
+                _ => Ok(Ast::Binary(
+                    BinaryOperator::Mul,
+                    Box::new(Ast::Literal(Value::from(-1_f32))),
+                    Box::new(inner),
+                )),
+            },
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Ast, ParseError> {
+        let mut expr = self.parse_primary()?;
+        while self.eat(".") {
+            let (token, span) = self.next();
+            let op = match &token {
+                Token::Ident(ident) => match ident.as_str() {
+                    "x" => UnaryOperator::X,
+                    "y" => UnaryOperator::Y,
+                    "z" => UnaryOperator::Z,
+                    "w" => UnaryOperator::W,
+                    _ => {
+                        return Err(ParseError::new(
+                            format!("Unknown vector component '{}'", ident),
+                            span,
+                        ))
+                    }
+                },
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("vector component"));
+                }
+            };
+            expr = Ast::Unary(op, Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Ast, ParseError> {
+        let (token, span) = self.next();
+        match token {
+            Token::Literal(value) => Ok(Ast::Literal(value)),
+            Token::Punct("(") => {
+                let expr = self.parse_expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Ident(ident) => self.parse_ident(ident, span),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("expression"))
+            }
+        }
+    }
+
+    fn parse_ident(&mut self, ident: String, span: Range<usize>) -> Result<Ast, ParseError> {
+        match ident.as_str() {
+            "true" => return Ok(Ast::Literal(true.into())),
+            "false" => return Ok(Ast::Literal(false.into())),
+            _ => {}
+        }
+
+        if self.eat("(") {
+            let mut args = vec![];
+            if !self.eat(")") {
+                loop {
+                    args.push(self.parse_expr()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            let call_span = span.start..self.tokens[self.pos - 1].1.end;
+            return self.make_call(&ident, span, call_span, args);
+        }
+
+        if matches!(ident.as_str(), "particle" | "p" | "properties") && self.eat(".") {
+            let (token, name_span) = self.next();
+            let Token::Ident(name) = token else {
+                self.pos -= 1;
+                return Err(self.unexpected("name"));
+            };
+            return if ident == "properties" {
+                self.property(&name, name_span)
+            } else {
+                Attribute::from_name(&name)
+                    .map(Ast::Attribute)
+                    .ok_or_else(|| {
+                        ParseError::new(format!("Unknown attribute '{}'", name), name_span)
+                    })
+            };
+        }
+
+        if let Some(op) = BUILT_IN_VALUES.iter().find(|op| op.name() == ident) {
+            return Ok(Ast::BuiltIn(*op));
+        }
+
+        self.property(&ident, span)
+    }
+
+    fn property(&self, name: &str, span: Range<usize>) -> Result<Ast, ParseError> {
+        self.module
+            .get_property_by_name(name)
+            .map(Ast::Property)
+            .ok_or_else(|| ParseError::new(format!("Unknown identifier '{}'", name), span))
+    }
+
+    fn make_call(
+        &self,
+        name: &str,
+        name_span: Range<usize>,
+        call_span: Range<usize>,
+        args: Vec<Ast>,
+    ) -> Result<Ast, ParseError> {
+        let (arity, make): (usize, Box<dyn FnOnce(Vec<Ast>) -> Ast>) =
+            if let Some(op) = rand_operator(name) {
+                (0, Box::new(move |_| Ast::BuiltIn(op)))
+            } else if let Some(op) = UNARY_FUNCTIONS
+                .iter()
+                .find(|op| op.to_wgsl_string() == name)
+                .copied()
+            {
+                (
+                    1,
+                    Box::new(move |args| {
+                        let mut args = args.into_iter();
+                        Ast::Unary(op, Box::new(args.next().unwrap()))
+                    }),
+                )
+            } else if let Some(op) = BINARY_FUNCTIONS
+                .iter()
+                .find(|op| op.to_wgsl_string() == name)
+                .copied()
+            {
+                (
+                    2,
+                    Box::new(move |args| {
+                        let mut args = args.into_iter();
+                        Ast::Binary(
+                            op,
+                            Box::new(args.next().unwrap()),
+                            Box::new(args.next().unwrap()),
+                        )
+                    }),
+                )
+            } else if let Some(op) = TERNARY_FUNCTIONS
+                .iter()
+                .find(|op| op.to_wgsl_string() == name)
+                .copied()
+            {
+                (
+                    3,
+                    Box::new(move |args| {
+                        let mut args = args.into_iter();
+                        Ast::Ternary(
+                            op,
+                            Box::new(args.next().unwrap()),
+                            Box::new(args.next().unwrap()),
+                            Box::new(args.next().unwrap()),
+                        )
+                    }),
+                )
+            } else {
+                return Err(ParseError::new(
+                    format!("Unknown function '{}'", name),
+                    name_span,
+                ));
+            };
+
+        if args.len() != arity {
+            return Err(ParseError::new(
+                format!(
+                    "Function '{}' takes {} argument(s), but {} were given",
+                    name,
+                    arity,
+                    args.len()
+                ),
+                call_span,
+            ));
+        }
+        Ok(make(args))
+    }
+}
+
+impl Module {
+    /// Parse an expression from its text representation, and add it to the
+    /// module.
+    ///
+    /// The syntax is close to the one of WGSL:
+    /// - Literals: `1.5`, `1e-3`, `2` (signed integer), `2u` (unsigned
+    ///   integer), `true`, `false`.
+    /// - Particle attributes: `particle.position`, or `p.position` for short,
+    ///   with the attribute [names].
+    /// - Properties: `properties.my_prop`, or just `my_prop` if no built-in
+    ///   value has the same name. The property must already exist in the
+    ///   module.
+    /// - Built-in values: `time`, `delta_time`, `virtual_time`,
+    ///   `virtual_delta_time`, `real_time`, `real_delta_time`, and
+    ///   `alpha_cutoff`.
+    /// - Random values: `frand()`, `frand3()`, `urand2()`, and so on, for all
+    ///   the scalar and vector types.
+    /// - Arithmetic operators `+`, `-`, `*`, `/`, `%`, the comparison operators
+    ///   `<`, `<=`, `>`, `>=`, and the negation `-x`, with the usual
+    ///   precedence.
+    /// - Vector components: `v.x`, `v.y`, `v.z`, `v.w`.
+    /// - Functions, with the names of the [`UnaryOperator`],
+    ///   [`BinaryOperator`], and [`TernaryOperator`] in WGSL: `abs(x)`,
+    ///   `normalize(v)`, `max(a, b)`, `mix(a, b, t)`, `vec3(x, y, z)`, and so
+    ///   on.
+    ///
+    /// On error, nothing is added to the module, and the returned
+    /// [`ParseError`] contains the range of bytes of `source` where the error
+    /// occurred.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// let mut module = Module::default();
+    /// let expr = module
+    ///     .parse("normalize(p.position) * (1.0 + sin(time * 2.0))")
+    ///     .unwrap();
+    /// let init_vel = SetAttributeModifier::new(Attribute::VELOCITY, expr);
+    ///
+    /// let err = module.parse("p.position * speed").unwrap_err();
+    /// assert_eq!(err.span, 13..18);
+    /// ```
+    ///
+    /// [names]: crate::Attribute::name
+    pub fn parse(&mut self, source: &str) -> Result<ExprHandle, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            module: self,
+        };
+        let ast = parser.parse_expr()?;
+        if *parser.peek() != Token::End {
+            return Err(parser.unexpected("operator or end of expression"));
+        }
+        Ok(ast.emit(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModifierContext, ParticleLayout, PropertyLayout, ShaderWriter};
+
+    fn eval(module: &Module, expr: ExprHandle) -> String {
+        let property_layout = PropertyLayout::new(module.properties().iter());
+        let particle_layout = ParticleLayout::default();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        module
+            .get(expr)
+            .unwrap()
+            .eval(module, &mut context)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let mut module = Module::default();
+        module.add_property("speed", Value::from(3_f32));
+
+        let expr = module
+            .parse("normalize(p.position) * (1.0 + sin(time*2.0))")
+            .unwrap();
+        assert_eq!(
+            eval(&module, expr),
+            "(normalize(particle.position)) * ((1.) + (sin((sim_params.time) * (2.))))"
+        );
+
+        // Precedence and associativity
+        let expr = module.parse("1 - 2 - 3 * 4u").unwrap();
+        assert_eq!(eval(&module, expr), "((1) - (2)) - ((3) * (4u))");
+
+        // Properties, vector components, negation, and functions; the random value
+        // is stored in a local variable since it has a side effect.
+        let expr = module
+            .parse("-speed * mix(particle.velocity.x, properties.speed, frand()) >= -2.5e1")
+            .unwrap();
+        assert_eq!(
+            eval(&module, expr),
+            "(((-1.) * (properties.speed)) * (mix(particle.velocity.x, properties.speed, var0))) >= (-25.)"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut module = Module::default();
+        let before = module.clone();
+        let cases = [
+            ("p.position * speed", 13..18, "Unknown identifier 'speed'"),
+            ("p.color2", 2..8, "Unknown attribute 'color2'"),
+            ("foo(1.)", 0..3, "Unknown function 'foo'"),
+            (
+                "min(1.)",
+                0..7,
+                "Function 'min' takes 2 argument(s), but 1 were given",
+            ),
+            ("(1. + 2.", 8..8, "Expected ')', found end of expression"),
+            (
+                "1. 2.",
+                3..5,
+                "Expected operator or end of expression, found literal",
+            ),
+            ("p.position.q", 11..12, "Unknown vector component 'q'"),
+            ("-2u", 0..3, "Cannot negate an unsigned or boolean value"),
+            ("1. # 2.", 3..4, "Unexpected character '#'"),
+            ("1.5u", 0..4, "Invalid number literal '1.5u'"),
+        ];
+        for (source, span, message) in cases {
+            let err = module.parse(source).unwrap_err();
+            assert_eq!(err, ParseError::new(message, span), "{}", source);
+        }
+        // Nothing was added to the module
+        assert_eq!(module, before);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{borrow::Cow, path::PathBuf, time::SystemTime};
+
+use bevy::{
+    prelude::*,
+    utils::{Duration, Instant},
+};
+
+use crate::{render::ShaderTemplates, ShaderCache};
+
+/// Source files of the WGSL templates, relative to the root of the crate, in
+/// the order of the fields of [`ShaderTemplates`].
+const TEMPLATE_FILES: [&str; 4] = [
+    "src/render/vfx_init.wgsl",
+    "src/render/vfx_update.wgsl",
+    "src/render/vfx_render.wgsl",
+    "src/render/vfx_cull.wgsl",
+];
+
+/// Source file of a WGSL template watched for modifications.
+#[derive(Debug)]
+struct WatchedTemplate {
+    /// Path to the source file.
+    path: PathBuf,
+    /// Last modification time of the file, or `None` if it doesn't exist.
+    modified: Option<SystemTime>,
+}
+
+/// Watcher of the source files of the WGSL templates the effect shaders are
+/// generated from.
+///
+/// This only exists in debug builds, when enabled with
+/// [`HanabiSettings::shader_hot_reload`]. The files are looked up in the source
+/// directory the crate was compiled from, so this is only useful when working
+/// on a local checkout of Hanabi.
+///
+/// [`HanabiSettings::shader_hot_reload`]: crate::HanabiSettings::shader_hot_reload
+#[derive(Debug, Resource)]
+pub(crate) struct ShaderTemplateWatcher {
+    /// Watched files, in the order of [`TEMPLATE_FILES`].
+    files: Vec<WatchedTemplate>,
+    /// Time of the last check of the files.
+    last_poll: Option<Instant>,
+}
+
+impl Default for ShaderTemplateWatcher {
+    fn default() -> Self {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        Self {
+            // The modification times are unknown until the first check, which
+            // picks any change made since the crate was compiled.
+            files: TEMPLATE_FILES
+                .iter()
+                .map(|file| WatchedTemplate {
+                    path: root.join(file),
+                    modified: None,
+                })
+                .collect(),
+            last_poll: None,
+        }
+    }
+}
+
+impl ShaderTemplateWatcher {
+    /// Interval between two checks of the modification time of the files.
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Update the modification times of the files, and return `true` if any
+    /// changed since the last call.
+    fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for file in &mut self.files {
+            let modified = std::fs::metadata(&file.path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if modified != file.modified {
+                file.modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Load the templates from their source files, keeping the current version
+    /// of any template which cannot be read.
+    fn load(&self, current: &ShaderTemplates) -> ShaderTemplates {
+        let mut templates = current.clone();
+        let fields = [
+            &mut templates.init,
+            &mut templates.update,
+            &mut templates.render,
+            &mut templates.cull,
+        ];
+        for (file, template) in self.files.iter().zip(fields) {
+            match std::fs::read_to_string(&file.path) {
+                Ok(source) => *template = Cow::Owned(source),
+                Err(err) => warn!(
+                    "Failed to reload shader template {}: {}",
+                    file.path.display(),
+                    err
+                ),
+            }
+        }
+        templates
+    }
+}
+
+/// Reload the WGSL templates the effect shaders are generated from when their
+/// source files are modified, which recompiles all effects.
+///
+/// This system runs in the [`EffectSystems::CompileEffects`] set, before the
+/// effects are compiled.
+///
+/// [`EffectSystems::CompileEffects`]: crate::EffectSystems::CompileEffects
+pub(crate) fn hot_reload_shader_templates(
+    mut watcher: ResMut<ShaderTemplateWatcher>,
+    mut shader_cache: ResMut<ShaderCache>,
+) {
+    let now = Instant::now();
+    if watcher
+        .last_poll
+        .is_some_and(|last_poll| now - last_poll < ShaderTemplateWatcher::POLL_INTERVAL)
+    {
+        return;
+    }
+    watcher.last_poll = Some(now);
+
+    if !watcher.poll() {
+        return;
+    }
+
+    let templates = watcher.load(shader_cache.templates());
+    if templates != *shader_cache.templates() {
+        info!("Reloaded shader templates, recompiling all effects.");
+        shader_cache.set_templates(templates);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_templates() {
+        let mut watcher = ShaderTemplateWatcher::default();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        // The source files are the ones built into the plugin
+        let templates = ShaderTemplates::default();
+        assert_eq!(watcher.load(&templates), templates);
+
+        // Missing files keep the current templates
+        watcher.files[1].path = PathBuf::from("does/not/exist.wgsl");
+        let mut current = templates.clone();
+        current.update = Cow::Borrowed("// current");
+        let loaded = watcher.load(&current);
+        assert_eq!(loaded.update, current.update);
+        assert_eq!(loaded.render, templates.render);
+    }
+}
@@ -0,0 +1,430 @@
+use bevy::{math::Affine3A, prelude::*, utils::HashMap};
+
+use crate::{next_multiple_of, Attribute, CompiledParticleEffect, ParticleLayout, Value};
+
+/// A single particle pushed from the CPU into an effect.
+///
+/// The particle stores explicit values for some of its attributes. On GPU, the
+/// particle is initialized like any other spawned particle by the init
+/// modifiers of the effect, then all the attributes explicitly set here
+/// override the values produced by those modifiers. Attributes not set keep
+/// their value from the init modifiers.
+///
+/// Values are in simulation space; for an effect simulated in
+/// [`SimulationSpace::Global`], the [`Attribute::POSITION`] is a world
+/// position, and is not offset by the emitter transform.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// # use bevy::prelude::*;
+/// let particle = InjectedParticle::new()
+///     .with(Attribute::POSITION, Vec3::new(1., 2., 3.))
+///     .with(Attribute::VELOCITY, Vec3::Y * 4.);
+/// ```
+///
+/// [`SimulationSpace::Global`]: crate::SimulationSpace::Global
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InjectedParticle {
+    values: HashMap<Attribute, Value>,
+}
+
+impl InjectedParticle {
+    /// Create a new particle without any explicit attribute value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of an attribute.
+    ///
+    /// The value type must match the type of the attribute, otherwise the
+    /// value is ignored when the particle is uploaded to GPU.
+    pub fn with(mut self, attribute: Attribute, value: impl Into<Value>) -> Self {
+        self.set(attribute, value);
+        self
+    }
+
+    /// Set the value of an attribute.
+    ///
+    /// The value type must match the type of the attribute, otherwise the
+    /// value is ignored when the particle is uploaded to GPU.
+    pub fn set(&mut self, attribute: Attribute, value: impl Into<Value>) {
+        self.values.insert(attribute, value.into());
+    }
+
+    /// Get the value of an attribute, if explicitly set.
+    pub fn get(&self, attribute: Attribute) -> Option<&Value> {
+        self.values.get(&attribute)
+    }
+
+    /// Serialize the particle into `dst` according to the given layout.
+    ///
+    /// The destination buffer holds an `InjectedParticle` WGSL struct, which is
+    /// made of the `Particle` struct followed by a `u32` bitfield of the
+    /// attributes explicitly set, indexed by their position in the layout.
+    fn serialize_into(&self, layout: &ParticleLayout, dst: &mut [u8]) {
+        let mut mask = 0u32;
+        for (index, attr_layout) in layout.attributes().iter().enumerate() {
+            let Some(value) = self.values.get(&attr_layout.attribute) else {
+                continue;
+            };
+            if value.value_type() != attr_layout.attribute.value_type() {
+                warn!(
+                    "Injected particle value for attribute {} has type {:?}, expected {:?}. Ignored.",
+                    attr_layout.attribute.name(),
+                    value.value_type(),
+                    attr_layout.attribute.value_type()
+                );
+                continue;
+            }
+            let offset = attr_layout.offset as usize;
+            let src = value.as_bytes();
+            dst[offset..offset + src.len()].copy_from_slice(src);
+            mask |= 1 << index;
+        }
+        let offset = layout.min_binding_size().get() as usize;
+        dst[offset..offset + 4].copy_from_slice(&mask.to_le_bytes());
+    }
+}
+
+/// A burst of particles pushed from the CPU into an effect, with overrides
+/// applied only to the particles of that burst.
+///
+/// Bursts let a single effect asset adapt to each trigger, like an impact
+/// effect tinted by the material of the surface hit, without one asset or
+/// property per variant. Each particle of the burst is initialized by the init
+/// modifiers of the effect, like any other injected particle, then the
+/// overrides of the burst are applied on top:
+/// - the color set with [`with_color()`] replaces the [`Attribute::COLOR`] or
+///   [`Attribute::HDR_COLOR`] of the particles, whichever the effect uses;
+/// - the offsets set with [`with_position_offsets()`] replace the
+///   [`Attribute::POSITION`] of the particles, cycling through the list if the
+///   burst has more particles than offsets;
+/// - any other attribute value set with [`with()`] is applied as is.
+///
+/// Offsets are expressed in the local space of the emitter, and converted to
+/// the simulation space of the effect when the burst is submitted.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// # use bevy::prelude::*;
+/// fn on_hit(mut injector: Query<&mut EffectInjector>) {
+///     let burst = EffectBurst::new(16)
+///         .with_color(Color::rgb(0.4, 0.3, 0.2))
+///         .with_position_offsets([Vec3::ZERO, Vec3::X * 0.1, Vec3::Z * 0.1]);
+///     injector.single_mut().burst(burst);
+/// }
+/// ```
+///
+/// [`with_color()`]: EffectBurst::with_color
+/// [`with_position_offsets()`]: EffectBurst::with_position_offsets
+/// [`with()`]: EffectBurst::with
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EffectBurst {
+    /// Number of particles of the burst.
+    pub count: u32,
+    /// Positions of the particles relative to the emitter, cycled through.
+    position_offsets: Vec<Vec3>,
+    /// Explicit attribute values shared by all particles of the burst.
+    values: HashMap<Attribute, Value>,
+}
+
+impl EffectBurst {
+    /// Create a new burst of the given number of particles, without any
+    /// override.
+    pub fn new(count: u32) -> Self {
+        Self { count, ..default() }
+    }
+
+    /// Override the color of the particles of the burst.
+    pub fn with_color(self, color: Color) -> Self {
+        self.with(Attribute::COLOR, color.as_linear_rgba_u32())
+            .with(Attribute::HDR_COLOR, Vec4::from(color.as_linear_rgba_f32()))
+    }
+
+    /// Override the position of the particles of the burst with a list of
+    /// offsets relative to the emitter.
+    ///
+    /// The particle #i of the burst is placed at the offset #(i % N), where N
+    /// is the number of offsets. An empty list keeps the positions produced by
+    /// the init modifiers.
+    pub fn with_position_offsets(mut self, offsets: impl IntoIterator<Item = Vec3>) -> Self {
+        self.position_offsets = offsets.into_iter().collect();
+        self
+    }
+
+    /// Override the value of an attribute for all particles of the burst.
+    ///
+    /// The value type must match the type of the attribute, otherwise the
+    /// value is ignored when the particles are uploaded to GPU. Attributes not
+    /// present in the effect are ignored.
+    pub fn with(mut self, attribute: Attribute, value: impl Into<Value>) -> Self {
+        self.values.insert(attribute, value.into());
+        self
+    }
+
+    /// Get the position offsets of the burst.
+    pub fn position_offsets(&self) -> &[Vec3] {
+        &self.position_offsets
+    }
+
+    /// Expand the burst into individual particles, converting the position
+    /// offsets from emitter space with the given transform.
+    fn particles(
+        &self,
+        emitter_to_simulation: Affine3A,
+    ) -> impl Iterator<Item = InjectedParticle> + '_ {
+        (0..self.count as usize).map(move |index| {
+            let mut particle = InjectedParticle {
+                values: self.values.clone(),
+            };
+            if !self.position_offsets.is_empty() {
+                let offset = self.position_offsets[index % self.position_offsets.len()];
+                particle.set(
+                    Attribute::POSITION,
+                    emitter_to_simulation.transform_point3(offset),
+                );
+            }
+            particle
+        })
+    }
+}
+
+/// Component to push explicit particles from the CPU into an effect.
+///
+/// Add this component to the same entity as a [`ParticleEffect`] to inject
+/// particles computed by gameplay code, like one particle per destroyed voxel.
+/// The particles pushed with [`inject()`] during a frame are uploaded to GPU
+/// and spawned at the end of that frame, in addition to any particle spawned
+/// by the [`EffectSpawner`].
+///
+/// Injection requires the [`EffectAsset`] to reserve some GPU storage with
+/// [`EffectAsset::with_injection_capacity()`]. Particles in excess of that
+/// capacity, or of the number of free particles in the effect, are dropped.
+/// This also applies to the particles of the bursts pushed with [`burst()`].
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`inject()`]: crate::EffectInjector::inject
+/// [`burst()`]: crate::EffectInjector::burst
+/// [`EffectSpawner`]: crate::EffectSpawner
+/// [`EffectAsset`]: crate::EffectAsset
+/// [`EffectAsset::with_injection_capacity()`]: crate::EffectAsset::with_injection_capacity
+#[derive(Debug, Default, Clone, Component)]
+pub struct EffectInjector {
+    /// Particles pushed this frame, not yet submitted.
+    pending: Vec<InjectedParticle>,
+    /// Bursts pushed this frame, not yet submitted.
+    pending_bursts: Vec<EffectBurst>,
+    /// Particles submitted for upload this frame.
+    submitted: Vec<InjectedParticle>,
+}
+
+impl EffectInjector {
+    /// Push a particle to be spawned this frame.
+    pub fn inject(&mut self, particle: InjectedParticle) {
+        self.pending.push(particle);
+    }
+
+    /// Push a batch of particles to be spawned this frame.
+    pub fn inject_batch(&mut self, particles: impl IntoIterator<Item = InjectedParticle>) {
+        self.pending.extend(particles);
+    }
+
+    /// Push a burst of particles to be spawned this frame.
+    ///
+    /// The burst is expanded into individual particles when submitted, at the
+    /// end of the [`EffectSystems::TickSpawners`] set.
+    ///
+    /// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+    pub fn burst(&mut self, burst: EffectBurst) {
+        self.pending_bursts.push(burst);
+    }
+
+    /// Get the number of particles pushed this frame and not yet submitted,
+    /// including the particles of the pending bursts.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+            + self
+                .pending_bursts
+                .iter()
+                .map(|burst| burst.count as usize)
+                .sum::<usize>()
+    }
+
+    /// Get the particles submitted for upload this frame.
+    pub(crate) fn submitted(&self) -> &[InjectedParticle] {
+        &self.submitted
+    }
+}
+
+/// Size in bytes of a single injected particle in the GPU injection buffer.
+pub(crate) fn injected_particle_stride(layout: &ParticleLayout) -> u64 {
+    let size = layout.min_binding_size().get() as usize + 4;
+    next_multiple_of(size, layout.align().max(4)) as u64
+}
+
+/// Serialize a list of injected particles into a binary blob ready for GPU
+/// upload, capped to `capacity` particles.
+///
+/// Returns the blob and the number of particles written into it.
+pub(crate) fn serialize_injected_particles(
+    particles: &[InjectedParticle],
+    layout: &ParticleLayout,
+    capacity: u32,
+) -> (Vec<u8>, u32) {
+    let count = particles.len().min(capacity as usize);
+    if count < particles.len() {
+        warn!(
+            "Dropped {} injected particle(s) in excess of the injection capacity {}.",
+            particles.len() - count,
+            capacity
+        );
+    }
+    let stride = injected_particle_stride(layout) as usize;
+    let mut data = vec![0; stride * count];
+    for (particle, dst) in particles[..count].iter().zip(data.chunks_exact_mut(stride)) {
+        particle.serialize_into(layout, dst);
+    }
+    (data, count as u32)
+}
+
+/// Submit the particles pushed into each [`EffectInjector`] during the frame.
+///
+/// This system runs in the [`EffectSystems::TickSpawners`] set. The pending
+/// bursts are expanded into particles, then the submitted particles are
+/// extracted into the render world, and discarded on next frame.
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+pub fn submit_injected_particles(
+    mut query: Query<(
+        &mut EffectInjector,
+        Option<&CompiledParticleEffect>,
+        Option<&GlobalTransform>,
+    )>,
+) {
+    for (mut injector, compiled, transform) in query.iter_mut() {
+        // Avoid triggering change detection if nothing to do
+        if injector.pending.is_empty()
+            && injector.pending_bursts.is_empty()
+            && injector.submitted.is_empty()
+        {
+            continue;
+        }
+        let injector = &mut *injector;
+        if !injector.pending_bursts.is_empty() {
+            let emitter_to_world = transform.map(GlobalTransform::affine).unwrap_or_default();
+            let emitter_to_simulation = compiled
+                .map(|compiled| compiled.simulation_to_world(transform).inverse())
+                .unwrap_or(Affine3A::IDENTITY)
+                * emitter_to_world;
+            for burst in injector.pending_bursts.drain(..) {
+                injector
+                    .pending
+                    .extend(burst.particles(emitter_to_simulation));
+            }
+        }
+        injector.submitted.clear();
+        std::mem::swap(&mut injector.pending, &mut injector.submitted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize() {
+        let layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::LIFETIME)
+            .build();
+        // vec3<f32> + f32 = 16 bytes, + mask = 20 bytes, aligned to 16 bytes
+        let stride = injected_particle_stride(&layout);
+        assert_eq!(stride, 32);
+
+        let particles = vec![
+            InjectedParticle::new().with(Attribute::POSITION, Vec3::new(1., 2., 3.)),
+            InjectedParticle::new().with(Attribute::LIFETIME, 5_f32),
+            // Invalid type, ignored
+            InjectedParticle::new().with(Attribute::LIFETIME, Vec3::ONE),
+        ];
+        let (data, count) = serialize_injected_particles(&particles, &layout, 2);
+        assert_eq!(count, 2);
+        assert_eq!(data.len(), 64);
+
+        let pos_offset = layout
+            .attributes()
+            .iter()
+            .position(|l| l.attribute == Attribute::POSITION)
+            .unwrap();
+        let lifetime_offset = 1 - pos_offset;
+
+        let mask0 = u32::from_le_bytes(data[16..20].try_into().unwrap());
+        assert_eq!(mask0, 1 << pos_offset);
+        let mask1 = u32::from_le_bytes(data[48..52].try_into().unwrap());
+        assert_eq!(mask1, 1 << lifetime_offset);
+
+        let (data, count) = serialize_injected_particles(&particles[2..], &layout, 2);
+        assert_eq!(count, 1);
+        let mask2 = u32::from_le_bytes(data[16..20].try_into().unwrap());
+        assert_eq!(mask2, 0);
+    }
+
+    #[test]
+    fn submit_burst() {
+        let mut app = App::new();
+        app.add_systems(Update, submit_injected_particles);
+
+        let mut injector = EffectInjector::default();
+        injector.inject(InjectedParticle::new());
+        injector.burst(
+            EffectBurst::new(3)
+                .with_color(Color::WHITE)
+                .with_position_offsets([Vec3::X, Vec3::Y]),
+        );
+        assert_eq!(injector.pending_count(), 4);
+        let entity = app
+            .world
+            .spawn((
+                injector,
+                CompiledParticleEffect::default(),
+                GlobalTransform::from_xyz(0., 0., 2.),
+            ))
+            .id();
+        app.update();
+
+        // Global simulation space; offsets are moved by the emitter transform
+        let injector = app.world.get::<EffectInjector>(entity).unwrap();
+        assert_eq!(injector.pending_count(), 0);
+        let submitted = injector.submitted();
+        assert_eq!(submitted.len(), 4);
+        assert_eq!(submitted[0], InjectedParticle::new());
+        let positions = [
+            Vec3::new(1., 0., 2.),
+            Vec3::new(0., 1., 2.),
+            Vec3::new(1., 0., 2.),
+        ];
+        for (particle, position) in submitted[1..].iter().zip(positions) {
+            assert_eq!(
+                particle.get(Attribute::POSITION),
+                Some(&Value::from(position))
+            );
+            assert_eq!(
+                particle.get(Attribute::COLOR),
+                Some(&Value::from(0xFFFFFFFF_u32))
+            );
+            assert_eq!(
+                particle.get(Attribute::HDR_COLOR),
+                Some(&Value::from(Vec4::ONE))
+            );
+        }
+
+        // Submitted particles are discarded on next frame
+        app.update();
+        let injector = app.world.get::<EffectInjector>(entity).unwrap();
+        assert!(injector.submitted().is_empty());
+    }
+}
@@ -25,11 +25,21 @@
 //! the GPU.
 //!
 //! _Note: This library makes heavy use of compute shaders to offload work to
-//! the GPU in a performant way. Support for compute shaders on the `wasm`
-//! target (WebAssembly) via WebGPU is only available in Bevy in general since
-//! the newly-released Bevy v0.11, and is not yet available in this library.
-//! See [#41](https://github.com/djeedai/bevy_hanabi/issues/41) for details on
-//! progress._
+//! the GPU in a performant way. On the `wasm` target (WebAssembly), this
+//! requires WebGPU, enabled with the `webgpu` feature of Bevy. WebGPU only
+//! guarantees 8 storage buffers per shader stage
+//! (`Limits::max_storage_buffers_per_shader_stage`); effects exceeding that
+//! limit are not simulated, and particle culling is disabled for effects which
+//! would exceed it only because of culling. See
+//! [#41](https://github.com/djeedai/bevy_hanabi/issues/41) for details._
+//!
+//! _Devices which can't simulate effects on GPU, like WebGL2 or older mobile
+//! GPUs, are detected when the plugin is built, and reported in the
+//! [`GpuCapabilities`] resource. By default effects are then disabled; insert a
+//! [`SimulationFallback::Cpu`] resource before adding the [`HanabiPlugin`] to
+//! simulate simple effects on CPU instead. The same fallback simulates effects
+//! without rendering them in headless apps, and [`CpuSimulation`] runs that
+//! simulation step by step outside of any app, for example in unit tests._
 //!
 //! # 2D vs. 3D
 //!
@@ -44,6 +54,11 @@
 //! bevy_hanabi = { version = "0.6", default-features = false, features = ["3d"] }
 //! ```
 //!
+//! In 2D, particles are rendered into the color target of the 2D transparent
+//! phase only, which the 2D lighting crates of the ecosystem don't light. Add a
+//! [`Lighting2dModifier`] to light them with the [`Lights2d`] instead, which a
+//! bridge system can fill from the lights of those crates.
+//!
 //! # Example
 //!
 //! Add the 🎆 Hanabi plugin to your app:
@@ -171,38 +186,149 @@
 #[cfg(feature = "2d")]
 use bevy::utils::FloatOrd;
 use bevy::{
+    math::Affine3A,
     prelude::*,
     utils::{thiserror::Error, HashSet},
 };
 use serde::{Deserialize, Serialize};
 use std::fmt::Write as _; // import without risk of name clashing
 
+use lod::LodShaderConfig;
+use render::{CompiledEffectShader, ShaderTemplates};
+
 mod asset;
+mod attach;
 pub mod attributes;
+#[cfg(feature = "audio")]
+mod audio;
+mod bind;
+mod bounds;
+mod budget;
+mod builder;
 mod bundle;
+mod bvh;
+mod capabilities;
+mod capacity;
+mod capture;
+mod chain;
+mod collider;
+mod compose;
+mod cpu_sim;
+mod curve;
+mod density;
+mod diagnostics;
+mod dump;
+#[cfg(feature = "editor")]
+mod editor;
+mod feedback;
+#[cfg(feature = "gizmos")]
+mod gizmos;
 mod gradient;
 pub mod graph;
+#[cfg(debug_assertions)]
+mod hot_reload;
+mod inject;
+mod light2d;
+mod lod;
 pub mod modifier;
+mod pick;
 mod plugin;
+mod pool;
+mod precompile;
+mod preview;
+mod process;
 pub mod properties;
+#[cfg(any(feature = "rapier2d", feature = "rapier3d"))]
+mod rapier;
 mod render;
+mod render_target;
+mod shared;
+mod shuriken;
+mod snapshot;
 mod spawn;
+mod stereo;
+pub mod templates;
+mod throttle;
+#[cfg(feature = "tilemap")]
+mod tilemap;
 mod time;
+mod timing;
+mod toggle;
+mod track;
+mod tween;
+mod validate;
 
 #[cfg(test)]
 mod test_utils;
 
-pub use asset::{AlphaMode, EffectAsset, MotionIntegration, SimulationCondition};
+pub use asset::{
+    AlphaMode, BlobShadow, DeferredMaterial, EffectAsset, EffectAssetMigrationError, EffectChild,
+    EffectEmitter, EffectVariant, MotionIntegration, MotionSubsteps, SimulationCondition,
+};
+pub use attach::JointAttachment;
 pub use attributes::*;
+#[cfg(feature = "audio")]
+pub use audio::{AudioAnalysis, AudioAnalysisPlugin, AudioLevels};
+pub use bind::{bind_properties, BindProperty, PropertyBindingPlugin};
+pub use budget::{apply_particle_budget, BudgetPriority, ParticleBudget};
+pub use builder::{
+    EffectAssetBuilder, InitStage, LifetimeSet, LifetimeUnset, RenderStage, UpdateStage,
+};
 pub use bundle::ParticleEffectBundle;
-pub use gradient::{Gradient, GradientKey};
+pub use bvh::{CollisionBvh, ParticleCollider};
+pub use capabilities::{GpuCapabilities, MissingCapability, SimulationBackend, SimulationFallback};
+pub use capacity::{
+    CapacityDiagnostics, CapacityExceededEvent, EffectStats, GroupOccupancy, HanabiStats,
+    SpawnDroppedEvent,
+};
+pub use capture::{CapturedParticle, ParticleCapture};
+pub use chain::{EffectParent, ParentEvent};
+pub use collider::{StaticCollider, StaticColliders};
+pub use compose::{spawn_effect_children, EffectComposition};
+pub use cpu_sim::{CpuParticle, CpuParticles, CpuSimulation};
+pub use curve::{Curve, CurveKey, CurveValue};
+pub use density::{ParticleDensitySource, ParticleDensityVolume};
+pub use diagnostics::HanabiDiagnosticsPlugin;
+pub use dump::{EffectShaderDump, ExprLines, GeneratedShader, ShaderKind, ShaderLine};
+#[cfg(feature = "editor")]
+pub use editor::EffectEditorPlugin;
+pub use feedback::ParticleImpulse;
+#[cfg(feature = "gizmos")]
+pub use gizmos::{EffectGizmoConfig, EffectGizmosPlugin, ShowEffectGizmos};
+pub use gradient::{Gradient, GradientImageError, GradientInterpolation, GradientKey};
 pub use graph::*;
+pub use inject::{submit_injected_particles, EffectBurst, EffectInjector, InjectedParticle};
+pub use light2d::{Lights2d, PointLight2d};
+pub use lod::{update_effect_lod, EffectLod, LodTier};
 pub use modifier::*;
-pub use plugin::{EffectSystems, HanabiPlugin};
+pub use pick::{ParticlePick, ParticlePickable, ParticlePicking};
+pub use plugin::{EffectSystems, HanabiPlugin, HanabiSettings};
+pub use pool::EffectPool;
+pub use precompile::{EffectPrecompiler, PrecompileState};
+pub use preview::EffectPreview;
+pub use process::{EffectAssetProcessError, EffectAssetSaver};
 pub use properties::*;
+#[cfg(any(feature = "rapier2d", feature = "rapier3d"))]
+pub use rapier::RapierCollidersPlugin;
 pub use render::{LayoutFlags, ShaderCache};
-pub use spawn::{tick_spawners, CpuValue, EffectSpawner, Random, Spawner};
+pub use render_target::{EffectRenderTarget, EffectRenderTargetSource};
+pub use shared::{update_shared_assets, ColorGradient, SizeCurve};
+pub use shuriken::{import_shuriken, ShurikenImportError};
+pub use snapshot::EffectSnapshot;
+pub use spawn::{
+    tick_spawners, CpuValue, EffectPlayback, EffectSpawner, EffectSpawnerSnapshot, Random,
+    SpawnScaling, Spawner, SpawnerParam,
+};
+pub use stereo::EffectStereoEye;
+pub use throttle::{update_offscreen_throttle, OffscreenThrottle, ThrottleMode};
+#[cfg(feature = "tilemap")]
+pub use tilemap::{TileEmitter, TileEmitterPlugin, TileGrid, TileGridKind};
 pub use time::{EffectSimulation, EffectSimulationTime};
+pub use timing::{GpuTimingDiagnostics, GpuTimingPass};
+pub use toggle::ModifierToggles;
+pub use track::{ParticleTracker, TrackedParticle};
+pub use tween::{tick_property_tweens, PropertyTween, TweenEasing};
+pub use validate::{DiagnosticSeverity, EffectDiagnostic};
 
 #[allow(missing_docs)]
 pub mod prelude {
@@ -481,7 +607,7 @@ impl ToWgslString for CpuValue<Vec4> {
 }
 
 /// Simulation space for the particles of an effect.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum SimulationSpace {
     /// Particles are simulated in global space.
@@ -628,10 +754,86 @@ pub struct ParticleEffect {
     /// negatively affect performance.
     ///
     /// Note that this value is shared by all particles of the effect instance.
+    /// To sort particles individually against a sprite, for example based on
+    /// their position, split them into several layers with a
+    /// [`ZLayer2dModifier`], or use [`y_sort_2d`] to sort the whole instance
+    /// by its position.
     ///
     /// This is only available with the `2d` feature.
+    ///
+    /// [`y_sort_2d`]: ParticleEffect::y_sort_2d
     #[cfg(feature = "2d")]
     pub z_layer_2d: Option<f32>,
+    /// For 2D rendering, derive the Z coordinate of the layer at which the
+    /// particles are rendered from the world Y position of the effect.
+    ///
+    /// When set, the Z coordinate used to sort the effect is offset by
+    /// `-y * scale`, where `y` is the Y coordinate of the [`GlobalTransform`]
+    /// of the effect, and `scale` the value of this field. This matches the
+    /// usual way Y-sorted sprites assign their Z coordinate in top-down games,
+    /// so the effect correctly goes behind sprites lower on screen, and in
+    /// front of sprites higher on screen, as it moves. The offset is added to
+    /// the base layer from [`z_layer_2d`].
+    ///
+    /// Note that this value is shared by all particles of the effect instance;
+    /// all particles of the effect are rendered at the same layer, which is
+    /// re-evaluated each frame. See [`ZLayer2dModifier`] to sort individual
+    /// particles above and below a sprite. Like [`z_layer_2d`], effects with
+    /// different Z values cannot be batched together.
+    ///
+    /// This is only available with the `2d` feature.
+    ///
+    /// [`z_layer_2d`]: ParticleEffect::z_layer_2d
+    #[cfg(feature = "2d")]
+    pub y_sort_2d: Option<f32>,
+    /// Fixed seed of the random number generator of the effect instance, to
+    /// make its simulation deterministic.
+    ///
+    /// By default the effect is seeded randomly. See
+    /// [`EffectSpawner::set_seed()`] for details.
+    pub seed: Option<u32>,
+    /// Override the simulation space of the effect asset.
+    ///
+    /// Changing the simulation space of a live effect instance converts its
+    /// existing particles into the new space, so they don't jump. This allows
+    /// for example a smoke emitter attached to a vehicle in local space to
+    /// keep its smoke trail in place once detached, by switching to global
+    /// space. The conversion uses the [`GlobalTransform`] of the effect at the
+    /// time of the switch. The trail history of particles is not converted.
+    ///
+    /// Switching requires new shaders for the effect, so the effect may be
+    /// frozen for a few frames until they're compiled, and no particle spawns
+    /// on the frame the conversion occurs.
+    pub simulation_space: Option<SimulationSpace>,
+    /// Override the particle texture of the effect asset.
+    ///
+    /// This replaces the texture of all particle groups of the asset sampling
+    /// a particle texture, for example with a [`ParticleTextureModifier`], so
+    /// a single asset can be reused with different textures. Groups without
+    /// any particle texture are unaffected. Since the texture is bound at
+    /// render time, changing it doesn't require new shaders; however effect
+    /// instances with different textures are not batched together.
+    pub texture: Option<Handle<Image>>,
+    /// Override the sprite grid size of the [`FlipbookModifier`] of the effect
+    /// asset.
+    ///
+    /// This allows reusing a single asset with flipbook textures of different
+    /// layouts, together with [`texture`]. The override only applies to assets
+    /// which already use a [`FlipbookModifier`]. The grid size is baked into
+    /// the shaders, so each distinct override requires its own shaders.
+    ///
+    /// [`texture`]: ParticleEffect::texture
+    pub sprite_grid_size: Option<UVec2>,
+    /// Debug view overriding the color of the particles, if any.
+    ///
+    /// When set, particles are drawn with a color derived from one of their
+    /// attributes, ignoring the color modifiers of the asset, to help
+    /// diagnosing why they behave unexpectedly. The [`DebugView::Overdraw`]
+    /// view instead draws how many particles overlap on each pixel, to find
+    /// the effects most expensive to fill. This can be toggled at runtime;
+    /// each change generates new render shaders for the instance, so this is
+    /// intended for debugging only.
+    pub debug_view: Option<DebugView>,
 }
 
 impl ParticleEffect {
@@ -641,9 +843,102 @@ impl ParticleEffect {
             handle,
             #[cfg(feature = "2d")]
             z_layer_2d: None,
+            #[cfg(feature = "2d")]
+            y_sort_2d: None,
+            seed: None,
+            simulation_space: None,
+            texture: None,
+            sprite_grid_size: None,
+            debug_view: None,
         }
     }
 
+    /// Set a fixed seed for the random number generator of the effect
+    /// instance, to make its simulation deterministic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::asset::Handle;
+    /// # let asset = Handle::<EffectAsset>::default();
+    /// // Replay the same particles on each run
+    /// let effect = ParticleEffect::new(asset).with_seed(42);
+    /// ```
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Override the simulation space of the effect asset for this instance.
+    ///
+    /// Setting the value to `None` reverts to the simulation space of the
+    /// asset. See [`ParticleEffect::simulation_space`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::asset::Handle;
+    /// # let asset = Handle::<EffectAsset>::default();
+    /// let effect = ParticleEffect::new(asset).with_simulation_space(Some(SimulationSpace::Local));
+    /// ```
+    pub fn with_simulation_space(mut self, simulation_space: Option<SimulationSpace>) -> Self {
+        self.simulation_space = simulation_space;
+        self
+    }
+
+    /// Override the particle texture of the effect asset for this instance.
+    ///
+    /// Setting the value to `None` reverts to the texture of the asset. See
+    /// [`ParticleEffect::texture`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::prelude::*;
+    /// # let asset = Handle::<EffectAsset>::default();
+    /// # let metal_decal = Handle::<Image>::default();
+    /// // Reuse a generic impact effect with a material-specific decal
+    /// let effect = ParticleEffect::new(asset)
+    ///     .with_texture(Some(metal_decal))
+    ///     .with_sprite_grid_size(Some(UVec2::new(4, 4)));
+    /// ```
+    pub fn with_texture(mut self, texture: Option<Handle<Image>>) -> Self {
+        self.texture = texture;
+        self
+    }
+
+    /// Override the sprite grid size of the [`FlipbookModifier`] of the effect
+    /// asset for this instance.
+    ///
+    /// Setting the value to `None` reverts to the grid size of the asset. See
+    /// [`ParticleEffect::sprite_grid_size`] for details.
+    pub fn with_sprite_grid_size(mut self, sprite_grid_size: Option<UVec2>) -> Self {
+        self.sprite_grid_size = sprite_grid_size;
+        self
+    }
+
+    /// Set the debug view overriding the color of the particles.
+    ///
+    /// Setting the value to `None` reverts to the normal rendering. See
+    /// [`ParticleEffect::debug_view`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::asset::Handle;
+    /// # let asset = Handle::<EffectAsset>::default();
+    /// // Check which particles are about to die
+    /// let effect = ParticleEffect::new(asset).with_debug_view(Some(DebugView::AgeFraction));
+    /// ```
+    pub fn with_debug_view(mut self, debug_view: Option<DebugView>) -> Self {
+        self.debug_view = debug_view;
+        self
+    }
+
     /// Set the value of the Z layer used when rendering in 2D mode.
     ///
     /// In 2D mode, the Bevy renderer sorts all render items according to their
@@ -669,6 +964,30 @@ impl ParticleEffect {
         self.z_layer_2d = z_layer_2d;
         self
     }
+
+    /// Configure the Y-sorting of the effect when rendering in 2D mode.
+    ///
+    /// See [`ParticleEffect::y_sort_2d`] for details. Set to `None` to disable
+    /// Y-sorting, and only use the fixed layer of [`z_layer_2d`].
+    ///
+    /// This function has no effect when rendering in 3D mode.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// # use bevy::asset::Handle;
+    /// # let asset = Handle::<EffectAsset>::default();
+    /// // Sort the effect with sprites using z = -y * 0.001
+    /// let effect = ParticleEffect::new(asset).with_y_sort_2d(Some(0.001));
+    /// ```
+    ///
+    /// [`z_layer_2d`]: ParticleEffect::z_layer_2d
+    #[cfg(feature = "2d")]
+    pub fn with_y_sort_2d(mut self, scale: Option<f32>) -> Self {
+        self.y_sort_2d = scale;
+        self
+    }
 }
 
 /// Effect shader.
@@ -677,6 +996,7 @@ impl ParticleEffect {
 #[derive(Debug, Default, Clone)]
 pub(crate) struct EffectShader {
     pub init: Handle<Shader>,
+    pub emitter_init: Vec<Handle<Shader>>,
     pub update: Vec<Handle<Shader>>,
     pub render: Vec<Handle<Shader>>,
 }
@@ -690,10 +1010,14 @@ pub(crate) struct EffectShader {
 #[derive(Debug)]
 struct EffectShaderSource {
     pub init: String,
+    pub emitter_init: Vec<String>,
     pub update: Vec<String>,
     pub render: Vec<String>,
     pub layout_flags: LayoutFlags,
-    pub particle_texture: Option<Handle<Image>>,
+    pub group_layout_flags: Vec<LayoutFlags>,
+    pub particle_textures: Vec<Option<Handle<Image>>>,
+    pub color_luts: Vec<Option<Handle<Image>>>,
+    pub z_layers_2d: Vec<Vec<f32>>,
 }
 
 /// Error resulting from the generating of the WGSL shader code of an
@@ -707,12 +1031,56 @@ enum ShaderGenerateError {
     Validate(String),
 }
 
+/// Apply an init or update modifier of an asset to a shader context.
+///
+/// If the asset allows toggling modifiers per instance, the code of the
+/// modifier is guarded by its bit in the `disabled_modifiers` bitfield of the
+/// spawner. See [`ModifierToggles`] for details.
+fn apply_modifier(
+    asset: &EffectAsset,
+    modifier: &dyn Modifier,
+    module: &mut Module,
+    context: &mut ShaderWriter,
+) -> Result<(), ExprError> {
+    let bit = if asset.modifier_toggles {
+        asset.modifier_index(modifier).filter(|index| *index < 32)
+    } else {
+        None
+    };
+    let Some(bit) = bit else {
+        return modifier.apply(module, context);
+    };
+    context.main_code.push_str(&format!(
+        "\nif ((spawner.disabled_modifiers & {}u) == 0u) {{\n",
+        1u32 << bit
+    ));
+    let result = modifier.apply(module, context);
+    context.main_code.push_str("\n}\n");
+    // The local variables declared by the modifier are scoped to the block
+    context.clear_expr_cache();
+    result
+}
+
 impl EffectShaderSource {
     /// Generate the effect shader WGSL source code.
     ///
     /// This takes a base asset effect and generate the WGSL code for the
-    /// various shaders (init/update/render).
-    pub fn generate(asset: &EffectAsset) -> Result<EffectShaderSource, ShaderGenerateError> {
+    /// various shaders (init/update/render), simulating the particles in the
+    /// given `simulation_space`, which overrides the one of the asset. If a LOD
+    /// tier applies to the effect instance, its size scale and disabled
+    /// modifiers are baked into the generated code. The shaders are generated
+    /// from the given `templates`.
+    pub fn generate(
+        templates: &ShaderTemplates,
+        asset: &EffectAsset,
+        simulation_space: SimulationSpace,
+        lod: Option<&LodShaderConfig>,
+    ) -> Result<EffectShaderSource, ShaderGenerateError> {
+        let is_enabled = |m: &dyn Modifier| match lod {
+            Some(lod) => lod.is_enabled(m),
+            None => true,
+        };
+
         let particle_layout = asset.particle_layout();
 
         // The particle layout cannot be empty currently because we always emit some
@@ -825,12 +1193,19 @@ impl EffectShaderSource {
         // modifiers to the contexts.
         let mut module = asset.module().clone();
 
-        // Generate the shader code for the initializing shader
-        let (init_code, init_extra, init_sim_space_transform_code) = {
+        // Generate the shader code for the initializing shader of an emitter, from the
+        // init modifiers of the group it spawns into
+        let generate_init_code = |module: &mut Module,
+                                  group_index: u32|
+         -> Result<(String, String, String), ShaderGenerateError> {
             let mut init_context =
-                ShaderWriter::new(ModifierContext::Init, &property_layout, &particle_layout);
-            for m in asset.init_modifiers() {
-                if let Err(err) = m.apply(&mut module, &mut init_context) {
+                ShaderWriter::new(ModifierContext::Init, &property_layout, &particle_layout)
+                    .with_group_names(asset.group_names());
+            for m in asset
+                .init_modifiers_for_group(group_index)
+                .filter(|m| is_enabled(*m))
+            {
+                if let Err(err) = apply_modifier(asset, m, module, &mut init_context) {
                     error!("Failed to compile effect, error in init context: {:?}", err);
                     return Err(ShaderGenerateError::Expr(err));
                 }
@@ -847,54 +1222,297 @@ impl EffectShaderSource {
                 }
             }
 
-            let sim_space_transform_code = match asset.simulation_space.eval(&init_context) {
+            // If the effect is pre-warmed or seeked, fast-forward the newly spawned
+            // particles by a random fraction of the fast-forward duration. The duration
+            // is zero outside of the single fast-forward frame, so this is a no-op most
+            // of the time. Any effect can be seeked at runtime, so the code is always
+            // emitted.
+            if particle_layout.contains(Attribute::AGE) {
+                let mut code = format!(
+                    "\nif (spawner.prewarm > 0.0) {{\n    let prewarm_age = frand() * spawner.prewarm;\n    particle.{} += prewarm_age;\n",
+                    Attribute::AGE.name()
+                );
+                if particle_layout.contains(Attribute::VELOCITY) {
+                    code += &format!(
+                        "    particle.{0} += particle.{1} * prewarm_age;\n",
+                        Attribute::POSITION.name(),
+                        Attribute::VELOCITY.name()
+                    );
+                }
+                code += "}\n";
+                init_context.main_code.push_str(&code);
+            }
+
+            let sim_space_transform_code = match simulation_space.eval(&init_context) {
                 Ok(s) => s,
                 Err(err) => {
                     error!("Failed to compile effect's simulation space: {:?}", err);
                     return Err(ShaderGenerateError::Expr(err));
                 }
             };
-            (
+            Ok((
                 init_context.main_code,
                 init_context.extra_code,
                 sim_space_transform_code,
+            ))
+        };
+        let init_codes = generate_init_code(&mut module, 0)?;
+        let emitter_init_codes = asset
+            .emitters()
+            .iter()
+            .map(|emitter| generate_init_code(&mut module, emitter.group_index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Generate the code overwriting the attributes of the particles injected from
+        // the CPU, if any. This runs after the simulation space transform, because
+        // injected values are already expressed in simulation space.
+        let (injection_binding_code, injection_code) = if asset.injection_capacity == 0 {
+            ("// (no injection)".to_string(), String::new())
+        } else {
+            let binding_code = r#"struct InjectedParticle {
+    particle: Particle,
+    mask: u32,
+}
+@group(1) @binding(4) var<storage, read> injected_particles : array<InjectedParticle>;"#
+                .to_string();
+            let mut code = "if (global_invocation_id.x < spawner.inject) {\n".to_string();
+            code += "    let injected = injected_particles[global_invocation_id.x];\n";
+            for (index, attr_layout) in particle_layout.attributes().iter().enumerate() {
+                code += &format!(
+                    "    if ((injected.mask & (1u << {1}u)) != 0u) {{ particle.{0} = injected.particle.{0}; }}\n",
+                    attr_layout.attribute.name(),
+                    index
+                );
+            }
+            code += "}\n";
+            (binding_code, code)
+        };
+
+        // Generate the code declaring the trail buffer if any group is rendered with a
+        // trail, and filling the trail history of newly spawned particles.
+        let trail_lengths = asset.trail_lengths();
+        let trail_stride = trail_lengths.iter().copied().max().unwrap_or(0);
+        let (trail_sim_binding, trail_render_binding, trail_init_code) = if trail_stride == 0 {
+            (
+                "// (no trail)".to_string(),
+                "// (no trail)".to_string(),
+                String::new(),
             )
+        } else {
+            let stride_code = format!("const trail_stride : u32 = {}u;\n", trail_stride);
+            let init_code = format!(
+                r##"// Fill the trail history with the spawn position
+    {{
+        let trail_base = index * trail_stride;
+        for (var i = 0u; i < trail_stride; i += 1u) {{
+            trail_buffer[trail_base + i] = vec4<f32>(particle.{0}, 0.0);
+        }}
+    }}"##,
+                Attribute::POSITION.name()
+            );
+            let sim_binding = stride_code.clone()
+                + "@group(1) @binding(5) var<storage, read_write> trail_buffer : array<vec4<f32>>;";
+            let render_binding = stride_code
+                + "@group(1) @binding(4) var<storage, read> trail_buffer : array<vec4<f32>>;";
+            (sim_binding, render_binding, init_code)
         };
 
         // Configure the init shader template, and make sure a corresponding shader
         // asset exists
-        let init_shader_source = PARTICLES_INIT_SHADER_TEMPLATE
-            .replace("{{ATTRIBUTES}}", &attributes_code)
-            .replace("{{INIT_CODE}}", &init_code)
-            .replace("{{INIT_EXTRA}}", &init_extra)
-            .replace("{{PROPERTIES}}", &properties_code)
-            .replace("{{PROPERTIES_BINDING}}", &properties_binding_code)
-            .replace(
-                "{{SIMULATION_SPACE_TRANSFORM_PARTICLE}}",
-                &init_sim_space_transform_code,
-            );
+        let configure_init_shader =
+            |(init_code, init_extra, sim_space_transform_code): &(String, String, String),
+             spawn_cap_code: &str,
+             recycle_code: &str| {
+                templates
+                    .init
+                    .replace("{{ATTRIBUTES}}", &attributes_code)
+                    .replace("{{INIT_CODE}}", init_code)
+                    .replace("{{INIT_EXTRA}}", init_extra)
+                    .replace("{{PROPERTIES}}", &properties_code)
+                    .replace("{{PROPERTIES_BINDING}}", &properties_binding_code)
+                    .replace("{{INJECTION_BINDING}}", &injection_binding_code)
+                    .replace("{{TRAIL_BINDING}}", &trail_sim_binding)
+                    .replace("{{SPAWN_CAP_CODE}}", spawn_cap_code)
+                    .replace("{{RECYCLE_CODE}}", recycle_code)
+                    .replace(
+                        "{{SIMULATION_SPACE_TRANSFORM_PARTICLE}}",
+                        sim_space_transform_code,
+                    )
+                    .replace("{{INJECTION_CODE}}", &injection_code)
+                    .replace("{{TRAIL_CODE}}", &trail_init_code)
+            };
+
+        // The spawner of the effect spawns into the first group, and is capped to the
+        // number of dead particles of that group.
+        let init_shader_source = configure_init_shader(
+            &init_codes,
+            r##"// Cap to max number of dead particles, copied from dead_count at the end of the
+    // previous iteration, and constant during this pass (unlike dead_count).
+    if (index >= render_effect_indirect.max_spawn) {
+        atomicAdd(&render_group_indirect.dropped_count, 1u);
+        return;
+    }"##,
+            r##"let base_index = particle_groups[0].effect_particle_offset;
+    let dead_index = atomicSub(&render_group_indirect.dead_count, 1u) - 1u;"##,
+        );
         trace!("Configured init shader:\n{}", init_shader_source);
 
+        // Additional emitters spawn into any group, whose dead particles count is not
+        // copied to a constant location, so detect when it goes negative instead.
+        let emitter_init_shader_sources: Vec<_> = asset
+            .emitters()
+            .iter()
+            .zip(emitter_init_codes.iter())
+            .map(|(emitter, init_codes)| {
+                let recycle_code = format!(
+                    r##"let base_index = particle_groups[{0}u].effect_particle_offset + particle_groups[{0}u].indirect_index;
+    let dead_index = atomicSub(&render_group_indirect.dead_count, 1u) - 1u;
+    // HACK - we have no limiter for dead_count, so could go negative (wrap around).
+    // Assume that any value above 2^31 is a wrap around, undo the atomic op and return.
+    if (dead_index >= 0xF0000000u) {{
+        atomicAdd(&render_group_indirect.dead_count, 1u);
+        atomicAdd(&render_group_indirect.dropped_count, 1u);
+        return;
+    }}"##,
+                    emitter.group_index
+                );
+                let source = configure_init_shader(
+                    init_codes,
+                    "// (no spawn cap; see recycling below)",
+                    &recycle_code,
+                );
+                trace!(
+                    "Configured init shader for emitter into group #{}:\n{}",
+                    emitter.group_index,
+                    source
+                );
+                source
+            })
+            .collect();
+
+        // Flags shared by all groups. The render-related flags are determined per
+        // group, from the render modifiers and alpha mode of each group.
         let mut layout_flags = LayoutFlags::NONE;
-        if asset.simulation_space == SimulationSpace::Local {
+        if simulation_space == SimulationSpace::Local {
             layout_flags |= LayoutFlags::LOCAL_SPACE_SIMULATION;
         }
-        if let AlphaMode::Mask(_) = &asset.alpha_mode {
-            layout_flags |= LayoutFlags::USE_ALPHA_MASK;
+        if trail_stride > 0 {
+            layout_flags |= LayoutFlags::TRAILS;
+        }
+        if asset
+            .update_modifiers()
+            .any(|modifier| modifier.as_any().is::<ForceReceiverModifier>())
+        {
+            layout_flags |= LayoutFlags::FORCE_FEEDBACK;
+        }
+        if asset
+            .update_modifiers()
+            .any(|modifier| modifier.as_any().is::<CollideStaticModifier>())
+        {
+            layout_flags |= LayoutFlags::STATIC_COLLIDERS;
         }
 
-        let mut effect_particle_texture = None;
+        // Configure the accumulation of the effect bounds by the update pass, in the
+        // local space of the effect.
+        let bounds_code = if asset.gpu_bounds && present_attributes.contains(&Attribute::POSITION) {
+            layout_flags |= LayoutFlags::GPU_BOUNDS;
+            let position = format!("particle.{}", Attribute::POSITION.name());
+            if simulation_space == SimulationSpace::Local {
+                format!("accumulate_bounds({});", position)
+            } else {
+                format!(
+                    "accumulate_bounds(vec4<f32>({}, 1.0) * spawner.inverse_transform);",
+                    position
+                )
+            }
+        } else {
+            String::new()
+        };
+
+        // Configure the conversion of the existing particles into the simulation
+        // space, on the frame the effect instance switches to it. The spawner
+        // tells which conversion to apply, if any.
+        let space_conversion_code = [(Attribute::POSITION, "1.0"), (Attribute::VELOCITY, "0.0")]
+            .into_iter()
+            .filter(|(attr, _)| present_attributes.contains(attr))
+            .map(|(attr, w)| {
+                format!(
+                    r##"if (spawner.space_conversion == 1u) {{
+        particle.{0} = vec4<f32>(particle.{0}, {1}) * spawner.transform;
+    }} else if (spawner.space_conversion == 2u) {{
+        particle.{0} = vec4<f32>(particle.{0}, {1}) * spawner.inverse_transform;
+    }}
+    "##,
+                    attr.name(),
+                    w
+                )
+            })
+            .collect::<String>();
+
+        // Configure the per-view culling of individual particles, which needs their
+        // world position.
+        let cull_code = match asset.particle_culling {
+            Some(radius) if present_attributes.contains(&Attribute::POSITION) => {
+                layout_flags |= LayoutFlags::CULL_PARTICLES;
+                let position = format!("particle.{}", Attribute::POSITION.name());
+                let world_position = if simulation_space == SimulationSpace::Local {
+                    format!("vec4<f32>({}, 1.0) * spawner.transform", position)
+                } else {
+                    position
+                };
+                templates
+                    .cull
+                    .replace("{{CULL_POSITION}}", &world_position)
+                    .replace("{{CULL_RADIUS}}", &radius.to_wgsl_string())
+            }
+            _ => String::new(),
+        };
+
+        // Surface properties of the particles written into the G-buffer, only used
+        // by the groups with LayoutFlags::DEFERRED
+        let deferred_material_code = asset.deferred.unwrap_or_default().to_wgsl_string();
+
+        // Parameters of the blob shadow, only used by the groups with
+        // LayoutFlags::BLOB_SHADOW
+        let blob_shadow_code = asset.blob_shadow.map_or_else(
+            || "// (no blob shadow)".to_string(),
+            BlobShadow::to_wgsl_string,
+        );
+
+        let mut group_layout_flags = vec![];
+        let mut particle_textures = vec![];
+        let mut color_luts = vec![];
+        let mut z_layers_2d = vec![];
 
         let (mut update_shader_sources, mut render_shader_sources) = (vec![], vec![]);
         for group_index in 0..(asset.capacities().len() as u32) {
+            let mut group_flags = layout_flags;
+            if asset.fog {
+                group_flags |= LayoutFlags::FOG;
+            }
+            let alpha_mode = asset.group_alpha_mode(group_index);
+            if let AlphaMode::Mask(_) = &alpha_mode {
+                group_flags |= LayoutFlags::USE_ALPHA_MASK;
+                if asset.deferred.is_some() {
+                    group_flags |= LayoutFlags::DEFERRED;
+                }
+            } else if asset.blob_shadow.is_some() && trail_lengths[group_index as usize] == 0 {
+                group_flags |= LayoutFlags::BLOB_SHADOW;
+            }
+
             // Generate the shader code for the update shader
             let (mut update_code, update_extra) = {
                 let mut update_context =
-                    ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
-                for m in asset.update_modifiers_for_group(group_index) {
-                    if let Err(err) = m.apply(&mut module, &mut update_context) {
+                    ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout)
+                        .with_group_names(asset.group_names());
+                for m in asset
+                    .update_modifiers_for_group(group_index)
+                    .filter(|m| is_enabled(*m))
+                {
+                    if let Err(err) = apply_modifier(asset, m, &mut module, &mut update_context) {
                         error!(
-                            "Failed to compile effect, error in update context: {:?}",
+                            "Failed to compile effect, error in update context of group {}: {:?}",
+                            asset.group_label(group_index),
                             err
                         );
                         return Err(ShaderGenerateError::Expr(err));
@@ -932,6 +1550,63 @@ impl EffectShaderSource {
                 }
             }
 
+            // Split the update into several sub-steps for fast particles. The age and
+            // lifetime are updated outside of the sub-steps, once per update.
+            if let Some(substeps) = asset.motion_substeps {
+                if has_position && has_velocity {
+                    update_code = format!(
+                        r##"
+{{
+    let frame_delta_time = sim_params.delta_time;
+    let step_distance = length(particle.{0}) * frame_delta_time;
+    let substep_count = clamp(u32(ceil(step_distance / {1})), 1u, {2}u);
+    sim_params.delta_time = frame_delta_time / f32(substep_count);
+    for (var substep = 0u; substep < substep_count; substep += 1u) {{
+        {3}
+        if (!is_alive) {{
+            break;
+        }}
+    }}
+    sim_params.delta_time = frame_delta_time;
+}}
+"##,
+                        Attribute::VELOCITY.name(),
+                        substeps.max_step_distance.max(1e-6).to_wgsl_string(),
+                        substeps.max_substeps.max(1),
+                        update_code
+                    );
+                } else {
+                    warn!(
+                        "Asset {} specifies motion sub-steps but is missing {}.",
+                        asset.name,
+                        if has_position {
+                            "Attribute::VELOCITY"
+                        } else {
+                            "Attribute::POSITION"
+                        }
+                    )
+                }
+            }
+
+            // Record the trail history of the particles of this group, shifting all
+            // previous positions by one slot.
+            let trail_length = trail_lengths[group_index as usize];
+            if trail_length > 0 {
+                update_code += &format!(
+                    r##"
+{{
+    let trail_base = index * trail_stride;
+    for (var i = {1}u; i > 0u; i -= 1u) {{
+        trail_buffer[trail_base + i] = trail_buffer[trail_base + i - 1u];
+    }}
+    trail_buffer[trail_base] = vec4<f32>(particle.{0}, 0.0);
+}}
+"##,
+                    Attribute::POSITION.name(),
+                    trail_length - 1
+                );
+            }
+
             // Generate the shader code for the render shader
             let (
                 vertex_code,
@@ -943,15 +1618,25 @@ impl EffectShaderSource {
                 image_sample_mapping_code,
             ) = {
                 let mut render_context = RenderContext::new(&property_layout, &particle_layout);
-                for m in asset.render_modifiers_for_group(group_index) {
+                for m in asset
+                    .render_modifiers_for_group(group_index)
+                    .filter(|m| is_enabled(m.as_modifier()))
+                {
                     m.apply_render(&mut module, &mut render_context);
                 }
 
+                // Scale the particle size after all modifiers, so it applies whichever
+                // modifier sets it.
+                if let Some(lod) = lod.filter(|lod| lod.size_scale != 1.) {
+                    render_context.vertex_code +=
+                        &format!("size *= {};\n", lod.size_scale.to_wgsl_string());
+                }
+
                 if render_context.needs_uv {
-                    layout_flags |= LayoutFlags::NEEDS_UV;
+                    group_flags |= LayoutFlags::NEEDS_UV;
                 }
 
-                let alpha_cutoff_code = if let AlphaMode::Mask(cutoff) = &asset.alpha_mode {
+                let alpha_cutoff_code = if let AlphaMode::Mask(cutoff) = &alpha_mode {
                     render_context.eval(&module, *cutoff).unwrap_or_else(|err| {
                         error!(
                             "Failed to evaluate the expression for AlphaMode::Mask, error: {:?}",
@@ -972,7 +1657,7 @@ impl EffectShaderSource {
 
                 let (flipbook_scale_code, flipbook_row_count_code) =
                     if let Some(grid_size) = render_context.sprite_grid_size {
-                        layout_flags |= LayoutFlags::FLIPBOOK;
+                        group_flags |= LayoutFlags::FLIPBOOK;
                         // Note: row_count needs to be i32, not u32, because of sprite_index
                         let flipbook_row_count_code = (grid_size.x as i32).to_wgsl_string();
                         let flipbook_scale_code =
@@ -983,10 +1668,18 @@ impl EffectShaderSource {
                         (String::new(), String::new())
                     };
 
-                // FIXME: What about multiple textures?
-                if let Some(particle_texture) = render_context.particle_texture {
-                    effect_particle_texture = Some(particle_texture);
+                if render_context.particle_texture.is_some() {
+                    group_flags |= LayoutFlags::PARTICLE_TEXTURE;
+                }
+                particle_textures.push(render_context.particle_texture);
+                if render_context.color_lut.is_some() {
+                    group_flags |= LayoutFlags::COLOR_LUT;
+                }
+                if render_context.lighting_2d {
+                    group_flags |= LayoutFlags::LIGHTING_2D;
                 }
+                color_luts.push(render_context.color_lut);
+                z_layers_2d.push(render_context.z_layers_2d);
 
                 (
                     render_context.vertex_code,
@@ -1035,30 +1728,36 @@ impl EffectShaderSource {
                 "".to_string()
             };
 
-            let group_index_code = format!("{}", group_index);
-
             // Configure the update shader template, and make sure a corresponding shader
             // asset exists
-            let update_shader_source = PARTICLES_UPDATE_SHADER_TEMPLATE
+            let update_shader_source = templates
+                .update
                 .replace("{{ATTRIBUTES}}", &attributes_code)
+                .replace("{{SPACE_CONVERSION_CODE}}", &space_conversion_code)
                 .replace("{{AGE_CODE}}", &age_code)
                 .replace("{{REAP_CODE}}", &reap_code)
+                .replace("{{BOUNDS_CODE}}", &bounds_code)
+                .replace("{{CULL_CODE}}", &cull_code)
                 .replace("{{UPDATE_CODE}}", &update_code)
                 .replace("{{UPDATE_EXTRA}}", &update_extra)
                 .replace("{{PROPERTIES}}", &properties_code)
                 .replace("{{PROPERTIES_BINDING}}", &properties_binding_code)
-                .replace("{{GROUP_INDEX}}", &group_index_code);
+                .replace("{{TRAIL_BINDING}}", &trail_sim_binding);
             trace!("Configured update shader:\n{}", update_shader_source);
 
             // Configure the render shader template, and make sure a corresponding shader
             // asset exists
-            let render_shader_source = PARTICLES_RENDER_SHADER_TEMPLATE
+            let render_shader_source = templates
+                .render
                 .replace("{{ATTRIBUTES}}", &attributes_code)
                 .replace("{{INPUTS}}", &inputs_code)
                 .replace("{{VERTEX_MODIFIERS}}", &vertex_code)
                 .replace("{{FRAGMENT_MODIFIERS}}", &fragment_code)
                 .replace("{{RENDER_EXTRA}}", &render_extra)
+                .replace("{{TRAIL_BINDING}}", &trail_render_binding)
                 .replace("{{ALPHA_CUTOFF}}", &alpha_cutoff_code)
+                .replace("{{DEFERRED_MATERIAL}}", &deferred_material_code)
+                .replace("{{BLOB_SHADOW}}", &blob_shadow_code)
                 .replace("{{FLIPBOOK_SCALE}}", &flipbook_scale_code)
                 .replace("{{FLIPBOOK_ROW_COUNT}}", &flipbook_row_count_code)
                 .replace(
@@ -1069,14 +1768,24 @@ impl EffectShaderSource {
 
             update_shader_sources.push(update_shader_source);
             render_shader_sources.push(render_shader_source);
+            group_layout_flags.push(group_flags);
         }
 
+        // The effect-wide flags are the union of the flags of all groups.
+        let layout_flags = group_layout_flags
+            .iter()
+            .fold(layout_flags, |acc, flags| acc | *flags);
+
         Ok(EffectShaderSource {
             init: init_shader_source,
+            emitter_init: emitter_init_shader_sources,
             update: update_shader_sources,
             render: render_shader_sources,
             layout_flags,
-            particle_texture: effect_particle_texture,
+            group_layout_flags,
+            particle_textures,
+            color_luts,
+            z_layers_2d,
         })
     }
 }
@@ -1104,13 +1813,38 @@ pub struct CompiledParticleEffect {
     simulation_condition: SimulationCondition,
     /// Handle to the effect shader for his effect instance, if configured.
     effect_shader: Option<EffectShader>,
-    /// Main particle texture.
-    particle_texture: Option<Handle<Image>>,
+    /// Particle texture of each group, if any.
+    particle_textures: Vec<Option<Handle<Image>>>,
+    /// Color over lifetime LUT texture of each group, if any.
+    color_luts: Vec<Option<Handle<Image>>>,
+    /// Z coordinates of the 2D layers of each group, if split by a
+    /// [`ZLayer2dModifier`].
+    z_layers_2d: Vec<Vec<f32>>,
     /// 2D layer for the effect instance.
     #[cfg(feature = "2d")]
     z_layer_2d: FloatOrd,
-    /// Layout flags.
+    /// Scale of the Y-sorting of the effect instance, if any.
+    #[cfg(feature = "2d")]
+    y_sort_2d: Option<f32>,
+    /// Layout flags, as the union of the layout flags of all groups.
     layout_flags: LayoutFlags,
+    /// Layout flags of each group.
+    group_layout_flags: Vec<LayoutFlags>,
+    /// Shader configuration of the LOD tier the shaders were compiled for, if
+    /// any.
+    lod: Option<LodShaderConfig>,
+    /// Simulation space the shaders were compiled for.
+    simulation_space: SimulationSpace,
+    /// Particle texture overriding the one of all groups, if any.
+    texture: Option<Handle<Image>>,
+    /// Flipbook sprite grid size the shaders were compiled for, if overridden.
+    sprite_grid_size: Option<UVec2>,
+    /// Debug view the shaders were compiled for, if any.
+    debug_view: Option<DebugView>,
+    /// Was the underlying asset modified this frame? The GPU resources of the
+    /// instance are then reallocated, as the capacities or layouts of the
+    /// asset may have changed.
+    asset_modified: bool,
 }
 
 impl Default for CompiledParticleEffect {
@@ -1119,10 +1853,21 @@ impl Default for CompiledParticleEffect {
             asset: default(),
             simulation_condition: SimulationCondition::default(),
             effect_shader: None,
-            particle_texture: None,
+            particle_textures: vec![],
+            color_luts: vec![],
+            z_layers_2d: vec![],
             #[cfg(feature = "2d")]
             z_layer_2d: FloatOrd(0.0),
+            #[cfg(feature = "2d")]
+            y_sort_2d: None,
             layout_flags: LayoutFlags::NONE,
+            group_layout_flags: vec![],
+            lod: None,
+            simulation_space: SimulationSpace::default(),
+            texture: None,
+            sprite_grid_size: None,
+            debug_view: None,
+            asset_modified: false,
         }
     }
 }
@@ -1132,7 +1877,48 @@ impl CompiledParticleEffect {
     pub(crate) fn clear(&mut self) {
         self.asset = Handle::default();
         self.effect_shader = None;
-        self.particle_texture = None;
+        self.particle_textures.clear();
+        self.color_luts.clear();
+        self.z_layers_2d.clear();
+        self.texture = None;
+    }
+
+    /// Get the transform from the simulation space of the effect instance to
+    /// world space, given the [`GlobalTransform`] of its entity, if any.
+    pub(crate) fn simulation_to_world(&self, transform: Option<&GlobalTransform>) -> Affine3A {
+        match (self.simulation_space, transform) {
+            (SimulationSpace::Local, Some(transform)) => transform.affine(),
+            _ => Affine3A::IDENTITY,
+        }
+    }
+
+    /// Get the particle texture of each group, if any, taking into account the
+    /// texture override of the effect instance.
+    pub(crate) fn particle_textures(&self) -> impl Iterator<Item = Option<&Handle<Image>>> {
+        self.particle_textures
+            .iter()
+            .map(|texture| texture.as_ref().map(|t| self.texture.as_ref().unwrap_or(t)))
+    }
+
+    /// Get the color over lifetime LUT texture of each group, if any.
+    pub(crate) fn color_luts(&self) -> impl Iterator<Item = Option<&Handle<Image>>> {
+        self.color_luts.iter().map(Option::as_ref)
+    }
+
+    /// Get the Z coordinates of the 2D layers of each group, empty for the
+    /// groups drawn at the layer of the effect instance.
+    pub(crate) fn z_layers_2d(&self) -> &[Vec<f32>] {
+        &self.z_layers_2d
+    }
+
+    /// Get the Z coordinate used to sort the effect instance when rendering in
+    /// 2D, taking into account its Y-sorting if any.
+    #[cfg(feature = "2d")]
+    pub(crate) fn z_sort_key_2d(&self, transform: &GlobalTransform) -> FloatOrd {
+        match self.y_sort_2d {
+            Some(scale) => FloatOrd((-transform.translation().y).mul_add(scale, self.z_layer_2d.0)),
+            None => self.z_layer_2d,
+        }
     }
 
     /// Update the compiled effect from its asset and instance.
@@ -1140,8 +1926,14 @@ impl CompiledParticleEffect {
         &mut self,
         rebuild: bool,
         #[cfg(feature = "2d")] z_layer_2d: FloatOrd,
+        #[cfg(feature = "2d")] y_sort_2d: Option<f32>,
         handle: Handle<EffectAsset>,
         asset: &EffectAsset,
+        simulation_space: SimulationSpace,
+        lod: Option<LodShaderConfig>,
+        texture: Option<Handle<Image>>,
+        sprite_grid_size: Option<UVec2>,
+        debug_view: Option<DebugView>,
         shaders: &mut ResMut<Assets<Shader>>,
         shader_cache: &mut ResMut<ShaderCache>,
     ) {
@@ -1165,6 +1957,7 @@ impl CompiledParticleEffect {
         // diff what may or may not have changed.
         self.asset = handle;
         self.simulation_condition = asset.simulation_condition;
+        self.texture = texture;
 
         // Check if the instance changed. If so, rebuild some data from this compiled
         // effect based on the new data of the effect instance.
@@ -1178,15 +1971,92 @@ impl CompiledParticleEffect {
             #[cfg(feature = "2d")]
             {
                 self.z_layer_2d = z_layer_2d;
+                self.y_sort_2d = y_sort_2d;
             }
         }
 
+        // The LOD tier bakes some of its settings into the shaders, so switching to a
+        // tier with different settings needs new shaders.
+        if self.lod != lod {
+            self.effect_shader = None;
+            self.lod = lod;
+        }
+        if self.simulation_space != simulation_space {
+            self.effect_shader = None;
+            self.simulation_space = simulation_space;
+        }
+        if self.sprite_grid_size != sprite_grid_size {
+            self.effect_shader = None;
+            self.sprite_grid_size = sprite_grid_size;
+        }
+        if self.debug_view != debug_view {
+            self.effect_shader = None;
+            self.debug_view = debug_view;
+        }
+
         // If the shaders are already compiled, there's nothing more to do
         if self.effect_shader.is_some() {
             return;
         }
 
-        let shader_source = match EffectShaderSource::generate(asset) {
+        // Reuse the shaders compiled for another instance of the same asset, LOD
+        // tier, simulation space, and flipbook layout, if any, to avoid generating
+        // the same shader code again. Debug views are rare enough to always
+        // generate their shaders.
+        if let Some(compiled) = shader_cache
+            .get_effect(
+                self.asset.id(),
+                self.simulation_space,
+                self.lod.as_ref(),
+                self.sprite_grid_size,
+            )
+            .filter(|_| self.debug_view.is_none())
+        {
+            self.effect_shader = Some(compiled.shader.clone());
+            self.layout_flags = compiled.layout_flags;
+            self.group_layout_flags = compiled.group_layout_flags.clone();
+            self.particle_textures = compiled.particle_textures.clone();
+            self.color_luts = compiled.color_luts.clone();
+            self.z_layers_2d = compiled.z_layers_2d.clone();
+            return;
+        }
+
+        // The flipbook layout override replaces the flipbook modifiers of the asset
+        let variant = self.sprite_grid_size.map(|sprite_grid_size| {
+            EffectVariant::default()
+                .with_modifier(Box::new(FlipbookModifier { sprite_grid_size }))
+                .apply(asset)
+        });
+        let asset = variant.as_ref().unwrap_or(asset);
+
+        // The debug view overrides the color after all other render modifiers
+        let debug_asset = self.debug_view.map(|view| {
+            (0..asset.capacities().len() as u32).fold(asset.clone(), |asset, group_index| {
+                asset.add_render_modifier_to_groups(
+                    Box::new(DebugViewModifier { view, group_index }),
+                    ParticleGroupSet::single(group_index),
+                )
+            })
+        });
+        let asset = debug_asset.as_ref().unwrap_or(asset);
+
+        // Use the shaders generated when the asset was processed, if still valid
+        let shader_source = match EffectShaderSource::pregenerated(
+            shader_cache.templates(),
+            asset,
+            self.simulation_space,
+            self.lod.as_ref(),
+        )
+        .filter(|_| variant.is_none() && debug_asset.is_none())
+        .map(Ok)
+        .unwrap_or_else(|| {
+            EffectShaderSource::generate(
+                shader_cache.templates(),
+                asset,
+                self.simulation_space,
+                self.lod.as_ref(),
+            )
+        }) {
             Ok(shader_source) => shader_source,
             Err(err) => {
                 error!(
@@ -1198,8 +2068,27 @@ impl CompiledParticleEffect {
         };
 
         self.layout_flags = shader_source.layout_flags;
+        self.group_layout_flags = shader_source.group_layout_flags;
+
+        // The overdraw view blends all particles additively, including the
+        // alpha-masked ones
+        if self.debug_view == Some(DebugView::Overdraw) {
+            for flags in self
+                .group_layout_flags
+                .iter_mut()
+                .chain(std::iter::once(&mut self.layout_flags))
+            {
+                flags.remove(LayoutFlags::USE_ALPHA_MASK | LayoutFlags::DEFERRED);
+                flags.insert(LayoutFlags::OVERDRAW);
+            }
+        }
 
         let init_shader = shader_cache.get_or_insert(&asset.name, &shader_source.init, shaders);
+        let emitter_init_shaders: Vec<_> = shader_source
+            .emitter_init
+            .iter()
+            .map(|init_source| shader_cache.get_or_insert(&asset.name, init_source, shaders))
+            .collect();
         let update_shaders: Vec<_> = shader_source
             .update
             .iter()
@@ -1212,12 +2101,13 @@ impl CompiledParticleEffect {
             .collect();
 
         trace!(
-            "CompiledParticleEffect::update(): init_shader={:?} update_shaders={:?} render_shaders={:?} has_image={} layout_flags={:?}",
+            "CompiledParticleEffect::update(): init_shader={:?} emitter_init_shaders={:?} update_shaders={:?} render_shaders={:?} particle_textures={:?} group_layout_flags={:?}",
             init_shader,
+            emitter_init_shaders,
             update_shaders,
             render_shaders,
-            shader_source.particle_texture.is_some(),
-            self.layout_flags,
+            shader_source.particle_textures,
+            self.group_layout_flags,
         );
 
         // TODO - Replace with Option<EffectShader { handle: Handle<Shader>, hash:
@@ -1227,13 +2117,33 @@ impl CompiledParticleEffect {
         // to avoid hash collisions, an index into a shader cache). The only
         // use is to be able to compare 2 instances and see if they can be
         // batched together.
-        self.effect_shader = Some(EffectShader {
+        let effect_shader = EffectShader {
             init: init_shader,
+            emitter_init: emitter_init_shaders,
             update: update_shaders,
             render: render_shaders,
-        });
+        };
+        if self.debug_view.is_none() {
+            shader_cache.insert_effect(
+                self.asset.id(),
+                self.simulation_space,
+                self.lod.clone(),
+                self.sprite_grid_size,
+                CompiledEffectShader {
+                    shader: effect_shader.clone(),
+                    layout_flags: self.layout_flags,
+                    group_layout_flags: self.group_layout_flags.clone(),
+                    particle_textures: shader_source.particle_textures.clone(),
+                    color_luts: shader_source.color_luts.clone(),
+                    z_layers_2d: shader_source.z_layers_2d.clone(),
+                },
+            );
+        }
+        self.effect_shader = Some(effect_shader);
 
-        self.particle_texture = shader_source.particle_texture;
+        self.particle_textures = shader_source.particle_textures;
+        self.color_luts = shader_source.color_luts;
+        self.z_layers_2d = shader_source.z_layers_2d;
     }
 
     /// Get the effect shader if configured, or `None` otherwise.
@@ -1242,17 +2152,13 @@ impl CompiledParticleEffect {
     }
 }
 
-const PARTICLES_INIT_SHADER_TEMPLATE: &str = include_str!("render/vfx_init.wgsl");
-const PARTICLES_UPDATE_SHADER_TEMPLATE: &str = include_str!("render/vfx_update.wgsl");
-const PARTICLES_RENDER_SHADER_TEMPLATE: &str = include_str!("render/vfx_render.wgsl");
-
 /// Trait to convert any data structure to its equivalent shader code.
 trait ShaderCode {
     /// Generate the shader code for the current state of the object.
     fn to_shader_code(&self, input: &str) -> String;
 }
 
-impl ShaderCode for Gradient<Vec2> {
+impl ShaderCode for Gradient<f32> {
     fn to_shader_code(&self, input: &str) -> String {
         if self.keys().is_empty() {
             return String::new();
@@ -1277,15 +2183,9 @@ impl ShaderCode for Gradient<Vec2> {
             let mut s = self
                 .keys()
                 .iter()
-                .skip(1)
+                .take(self.keys().len() - 1)
                 .enumerate()
-                .map(|(index, _key)| {
-                    format!(
-                        "else if ({input} <= t{1}) {{ return mix(v{0}, v{1}, ({input} - t{0}) / (t{1} - t{0})); }}\n",
-                        index,
-                        index + 1
-                    )
-                })
+                .map(|(index, key)| key.interpolation.segment_shader_code(input, "v", index))
                 .fold(s, |s, key| s + &key);
             let _ = writeln!(s, "else {{ return v{}; }}", self.keys().len() - 1);
             s
@@ -1293,7 +2193,7 @@ impl ShaderCode for Gradient<Vec2> {
     }
 }
 
-impl ShaderCode for Gradient<Vec4> {
+impl ShaderCode for Gradient<Vec2> {
     fn to_shader_code(&self, input: &str) -> String {
         if self.keys().is_empty() {
             return String::new();
@@ -1304,7 +2204,7 @@ impl ShaderCode for Gradient<Vec4> {
             .enumerate()
             .map(|(index, key)| {
                 format!(
-                    "let t{0} = {1};\nlet c{0} = {2};",
+                    "let t{0} = {1};\nlet v{0} = {2};",
                     index,
                     key.ratio().to_wgsl_string(),
                     key.value.to_wgsl_string()
@@ -1312,66 +2212,222 @@ impl ShaderCode for Gradient<Vec4> {
             })
             .fold("// Gradient\n".into(), |s, key| s + &key + "\n");
         if self.keys().len() == 1 {
-            s + "return c0;\n"
+            s + "return v0;\n"
         } else {
-            s += &format!("if ({input} <= t0) {{ return c0; }}\n");
+            s += &format!("if ({input} <= t0) {{ return v0; }}\n");
             let mut s = self
                 .keys()
                 .iter()
-                .skip(1)
+                .take(self.keys().len() - 1)
                 .enumerate()
-                .map(|(index, _key)| {
-                    format!(
-                        "else if ({input} <= t{1}) {{ return mix(c{0}, c{1}, ({input} - t{0}) / (t{1} - t{0})); }}\n",
-                        index,
-                        index + 1
-                    )
-                })
+                .map(|(index, key)| key.interpolation.segment_shader_code(input, "v", index))
                 .fold(s, |s, key| s + &key);
-            let _ = writeln!(s, "else {{ return c{}; }}", self.keys().len() - 1);
+            let _ = writeln!(s, "else {{ return v{}; }}", self.keys().len() - 1);
             s
         }
     }
 }
 
-/// Compile all the [`ParticleEffect`] components into a
-/// [`CompiledParticleEffect`].
-///
-/// This system runs in the [`EffectSystems::CompileEffects`] set of the
-/// [`PostUpdate`] schedule. It gathers all new instances of [`ParticleEffect`],
-/// as well as instances which changed, and (re)compile them into an optimized
-/// form saved into the [`CompiledParticleEffect`] component.
-///
-/// Hidden instances are compiled like visible ones, both to allow users to
-/// compile "in the background" by spawning a hidden effect, and also to prevent
-/// having mixed state where only some effects are compiled, and effects
-/// becoming visible later need to be special casing. If you want to avoid
+impl ShaderCode for Gradient<Vec4> {
+    fn to_shader_code(&self, input: &str) -> String {
+        if self.keys().is_empty() {
+            return String::new();
+        }
+        let mut s: String = self
+            .keys()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                format!(
+                    "let t{0} = {1};\nlet c{0} = {2};",
+                    index,
+                    key.ratio().to_wgsl_string(),
+                    key.value.to_wgsl_string()
+                )
+            })
+            .fold("// Gradient\n".into(), |s, key| s + &key + "\n");
+        if self.keys().len() == 1 {
+            s + "return c0;\n"
+        } else {
+            s += &format!("if ({input} <= t0) {{ return c0; }}\n");
+            let mut s = self
+                .keys()
+                .iter()
+                .take(self.keys().len() - 1)
+                .enumerate()
+                .map(|(index, key)| key.interpolation.segment_shader_code(input, "c", index))
+                .fold(s, |s, key| s + &key);
+            let _ = writeln!(s, "else {{ return c{}; }}", self.keys().len() - 1);
+            s
+        }
+    }
+}
+
+impl ShaderCode for Gradient<HdrColor> {
+    fn to_shader_code(&self, input: &str) -> String {
+        if self.keys().is_empty() {
+            return String::new();
+        }
+        // Colors and intensities are interpolated separately, then combined
+        let hdr = |color: &str, intensity: &str| {
+            format!("return vec4<f32>({color}.rgb * {intensity}, {color}.a);")
+        };
+        let mut s: String = self
+            .keys()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                format!(
+                    "let t{0} = {1};\nlet c{0} = {2};\nlet e{0} = {3};",
+                    index,
+                    key.ratio().to_wgsl_string(),
+                    Vec4::from(key.value.color.as_linear_rgba_f32()).to_wgsl_string(),
+                    key.value.intensity.to_wgsl_string()
+                )
+            })
+            .fold("// HDR gradient\n".into(), |s, key| s + &key + "\n");
+        if self.keys().len() == 1 {
+            s + &hdr("c0", "e0") + "\n"
+        } else {
+            s += &format!("if ({input} <= t0) {{ {} }}\n", hdr("c0", "e0"));
+            let mut s =
+                self.keys()
+                    .iter()
+                    .take(self.keys().len() - 1)
+                    .enumerate()
+                    .map(|(index, key)| {
+                        let next = index + 1;
+                        key.interpolation
+                            .segment_shader_code_with(input, index, |factor| match factor {
+                                Some(factor) => format!(
+                                    "let f = {factor}; let c = mix(c{index}, c{next}, f); {}",
+                                    hdr("c", &format!("mix(e{index}, e{next}, f)"))
+                                ),
+                                None => hdr(&format!("c{index}"), &format!("e{index}")),
+                            })
+                    })
+                    .fold(s, |s, key| s + &key);
+            let last = self.keys().len() - 1;
+            let _ = writeln!(
+                s,
+                "else {{ {} }}",
+                hdr(&format!("c{last}"), &format!("e{last}"))
+            );
+            s
+        }
+    }
+}
+
+impl<T: CurveValue> ShaderCode for Curve<T> {
+    fn to_shader_code(&self, input: &str) -> String {
+        if self.keys().is_empty() {
+            return String::new();
+        }
+        let mut s: String = self
+            .keys()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                format!(
+                    "let t{0} = {1};\nlet v{0} = {2};\nlet i{0} = {3};\nlet o{0} = {4};",
+                    index,
+                    key.ratio().to_wgsl_string(),
+                    key.value.to_wgsl_string(),
+                    key.in_tangent.to_wgsl_string(),
+                    key.out_tangent.to_wgsl_string()
+                )
+            })
+            .fold("// Curve\n".into(), |s, key| s + &key + "\n");
+        if self.keys().len() == 1 {
+            s + "return v0;\n"
+        } else {
+            s += &format!("if ({input} <= t0) {{ return v0; }}\n");
+            let mut s = (1..self.keys().len())
+                .map(|index| {
+                    format!(
+                        "else if ({input} <= t{1}) {{ let dt = t{1} - t{0}; let s = ({input} - t{0}) / dt; let s2 = s * s; let s3 = s2 * s; \
+                        return (2. * s3 - 3. * s2 + 1.) * v{0} + ((s3 - 2. * s2 + s) * dt) * o{0} + (3. * s2 - 2. * s3) * v{1} + ((s3 - s2) * dt) * i{1}; }}\n",
+                        index - 1,
+                        index
+                    )
+                })
+                .fold(s, |s, key| s + &key);
+            let _ = writeln!(s, "else {{ return v{}; }}", self.keys().len() - 1);
+            s
+        }
+    }
+}
+
+/// Compile all the [`ParticleEffect`] components into a
+/// [`CompiledParticleEffect`].
+///
+/// This system runs in the [`EffectSystems::CompileEffects`] set of the
+/// [`PostUpdate`] schedule. It gathers all new instances of [`ParticleEffect`],
+/// as well as instances which changed, and (re)compile them into an optimized
+/// form saved into the [`CompiledParticleEffect`] component.
+///
+/// Hidden instances are compiled like visible ones, both to allow users to
+/// compile "in the background" by spawning a hidden effect, and also to prevent
+/// having mixed state where only some effects are compiled, and effects
+/// becoming visible later need to be special casing. If you want to avoid
 /// compiling an effect, don't spawn it.
 fn compile_effects(
     effects: Res<Assets<EffectAsset>>,
+    mut asset_events: EventReader<AssetEvent<EffectAsset>>,
     mut shaders: ResMut<Assets<Shader>>,
     mut shader_cache: ResMut<ShaderCache>,
-    mut q_effects: Query<(Entity, Ref<ParticleEffect>, &mut CompiledParticleEffect)>,
+    mut q_effects: Query<(
+        Entity,
+        Ref<ParticleEffect>,
+        &mut CompiledParticleEffect,
+        Option<&EffectLod>,
+    )>,
 ) {
     trace!("compile_effects");
 
+    // Recompile all instances if the shader templates were hot-reloaded
+    let templates_modified = shader_cache.take_templates_modified();
+
+    // Forget the shaders compiled for modified assets, and recompile all their
+    // instances.
+    let mut modified_assets = HashSet::new();
+    for event in asset_events.read() {
+        match event {
+            AssetEvent::Modified { id } | AssetEvent::Removed { id } => {
+                shader_cache.invalidate_effect(*id);
+                modified_assets.insert(*id);
+            }
+            _ => {}
+        }
+    }
+
     // Loop over all existing effects to update them, including invisible ones
-    for (asset, entity, effect, mut compiled_effect) in
+    for (asset, entity, effect, mut compiled_effect, lod) in
         q_effects
             .iter_mut()
-            .filter_map(|(entity, effect, compiled_effect)| {
+            .filter_map(|(entity, effect, compiled_effect, lod)| {
                 // Check if asset is available, otherwise silently ignore as we can't check for
                 // changes, and conceptually it makes no sense to render a particle effect whose
                 // asset was unloaded.
                 let asset = effects.get(&effect.handle)?;
 
-                Some((asset, entity, effect, compiled_effect))
+                Some((asset, entity, effect, compiled_effect, lod))
             })
     {
+        // Flag the instances of modified assets for reallocation. Only mutate if
+        // needed to avoid triggering change detection.
+        let asset_modified = modified_assets.contains(&effect.handle.id());
+        if compiled_effect.asset_modified != asset_modified {
+            compiled_effect.asset_modified = asset_modified;
+        }
+
         // If the ParticleEffect didn't change, and the compiled one is for the correct
-        // asset, then there's nothing to do.
-        let need_rebuild = effect.is_changed();
-        if !need_rebuild && (compiled_effect.asset == effect.handle) {
+        // asset and LOD tier, then there's nothing to do.
+        let need_rebuild = templates_modified || effect.is_changed() || asset_modified;
+        let lod = lod
+            .and_then(EffectLod::current_tier)
+            .and_then(LodTier::shader_config);
+        if !need_rebuild && (compiled_effect.asset == effect.handle) && compiled_effect.lod == lod {
             continue;
         }
 
@@ -1390,15 +2446,22 @@ fn compile_effects(
             need_rebuild,
             #[cfg(feature = "2d")]
             z_layer_2d,
+            #[cfg(feature = "2d")]
+            effect.y_sort_2d,
             effect.handle.clone(),
             asset,
+            effect.simulation_space.unwrap_or(asset.simulation_space),
+            lod,
+            effect.texture.clone(),
+            effect.sprite_grid_size,
+            effect.debug_view,
             &mut shaders,
             &mut shader_cache,
         );
     }
 
     // Clear removed effects, to allow them to be released by the asset server
-    for (_, effect, mut compiled_effect) in q_effects.iter_mut() {
+    for (_, effect, mut compiled_effect, _) in q_effects.iter_mut() {
         if effects.get(&effect.handle).is_none() {
             compiled_effect.clear();
         }
@@ -1420,12 +2483,26 @@ fn compile_effects(
 /// compiling an effect, don't spawn it.
 fn update_properties_from_asset(
     assets: Res<Assets<EffectAsset>>,
-    mut q_effects: Query<(Ref<ParticleEffect>, &mut EffectProperties), Changed<ParticleEffect>>,
+    mut asset_events: EventReader<AssetEvent<EffectAsset>>,
+    mut q_effects: Query<(Ref<ParticleEffect>, &mut EffectProperties)>,
 ) {
     trace!("update_properties_from_asset");
 
+    let modified_assets: HashSet<AssetId<EffectAsset>> = asset_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
     // Loop over all existing effects, including invisible ones
-    for (effect, properties) in q_effects.iter_mut() {
+    for (effect, mut properties) in q_effects.iter_mut() {
+        let asset_modified = modified_assets.contains(&effect.handle.id());
+        if !effect.is_changed() && !asset_modified {
+            continue;
+        }
+
         // Check if the asset is available, otherwise silently ignore as we can't check
         // for changes, and conceptually it makes no sense to render a particle
         // effect whose asset was unloaded.
@@ -1433,6 +2510,12 @@ fn update_properties_from_asset(
             continue;
         };
 
+        // The GPU resources of the instances of a modified asset are reallocated, so
+        // their properties need to be uploaded again.
+        if asset_modified {
+            properties.set_changed();
+        }
+
         EffectProperties::update(properties, asset.properties(), effect.is_added());
     }
 }
@@ -1517,6 +2600,23 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "2d")]
+    #[test]
+    fn z_sort_key_2d() {
+        let transform = GlobalTransform::from_translation(Vec3::new(5., 200., 0.));
+        let mut compiled = CompiledParticleEffect {
+            z_layer_2d: FloatOrd(1.),
+            ..default()
+        };
+        assert_eq!(compiled.z_sort_key_2d(&transform), FloatOrd(1.));
+
+        // Effects higher on screen sort behind those lower on screen
+        compiled.y_sort_2d = Some(0.001);
+        assert!((compiled.z_sort_key_2d(&transform).0 - 0.8).abs() < 1e-5);
+        let lower = GlobalTransform::from_translation(Vec3::new(5., -100., 0.));
+        assert!(compiled.z_sort_key_2d(&lower) > compiled.z_sort_key_2d(&transform));
+    }
+
     #[test]
     fn to_wgsl_f32() {
         let s = 1.0_f32.to_wgsl_string();
@@ -1644,6 +2744,54 @@ let c1 = vec4<f32>(1.,0.,0.,1.);
 if (key <= t0) { return c0; }
 else if (key <= t1) { return mix(c0, c1, (key - t0) / (t1 - t0)); }
 else { return c1; }
+"#,
+            grad.to_shader_code("key")
+        );
+
+        let grad = Gradient::new()
+            .with_interpolated_key(0., 0., GradientInterpolation::Constant)
+            .with_interpolated_key(0.5, 1., GradientInterpolation::Smooth)
+            .with_key(1., 3.);
+        assert_eq!(
+            r#"// Gradient
+let t0 = 0.;
+let v0 = 0.;
+let t1 = 0.5;
+let v1 = 1.;
+let t2 = 1.;
+let v2 = 3.;
+if (key <= t0) { return v0; }
+else if (key < t1) { return v0; }
+else if (key <= t2) { return mix(v1, v2, smoothstep(t1, t2, key)); }
+else { return v2; }
+"#,
+            grad.to_shader_code("key")
+        );
+    }
+
+    #[test]
+    fn to_shader_code_hdr() {
+        let grad = Gradient::constant(HdrColor::new(Color::rgba_linear(1., 0., 0., 1.)));
+        assert_eq!(
+            "// HDR gradient\nlet t0 = 0.;\nlet c0 = vec4<f32>(1.,0.,0.,1.);\nlet e0 = 1.;\nreturn vec4<f32>(c0.rgb * e0, c0.a);\n",
+            grad.to_shader_code("key")
+        );
+
+        let grad = grad.with_key(
+            1.,
+            HdrColor::new(Color::rgba_linear(0., 0., 1., 1.)).with_intensity(4.),
+        );
+        assert_eq!(
+            r#"// HDR gradient
+let t0 = 0.;
+let c0 = vec4<f32>(1.,0.,0.,1.);
+let e0 = 1.;
+let t1 = 1.;
+let c1 = vec4<f32>(0.,0.,1.,1.);
+let e1 = 4.;
+if (key <= t0) { return vec4<f32>(c0.rgb * e0, c0.a); }
+else if (key <= t1) { let f = (key - t0) / (t1 - t0); let c = mix(c0, c1, f); return vec4<f32>(c.rgb * mix(e0, e1, f), c.a); }
+else { return vec4<f32>(c1.rgb * e1, c1.a); }
 "#,
             grad.to_shader_code("key")
         );
@@ -1742,6 +2890,70 @@ else { return c1; }
         }
     }
 
+    /// Compose a generated shader with its imports and the given shader defs,
+    /// and validate the resulting module with naga.
+    fn compose_and_validate(
+        name: &str,
+        code: &str,
+        shader_defs: std::collections::HashMap<String, ShaderDefValue>,
+    ) {
+        let mut composer = Composer::default();
+
+        // Import bevy_render::view for the render shader
+        {
+            // It's reasonably hard to retrieve the source code for view.wgsl in
+            // bevy_render. We use a few tricks to get a Shader that we can
+            // then convert into a composable module (which is how imports work in Bevy
+            // itself).
+            let mut dummy_app = App::new();
+            dummy_app.init_resource::<Assets<Shader>>();
+            dummy_app.add_plugins(bevy::render::view::ViewPlugin);
+            let shaders = dummy_app.world.get_resource::<Assets<Shader>>().unwrap();
+            let view_shader = shaders.get(bevy::render::view::VIEW_TYPE_HANDLE).unwrap();
+
+            let res = composer.add_composable_module(view_shader.into());
+            assert!(res.is_ok());
+        }
+
+        // Import bevy_hanabi::vfx_common
+        {
+            let min_storage_buffer_offset_alignment = 256;
+            let common_shader =
+                HanabiPlugin::make_common_shader(min_storage_buffer_offset_alignment);
+            let res = composer.add_composable_module((&common_shader).into());
+            assert!(res.is_ok());
+        }
+
+        match composer.make_naga_module(NagaModuleDescriptor {
+            source: code,
+            file_path: &format!("{}.wgsl", name),
+            shader_defs,
+            ..Default::default()
+        }) {
+            Ok(module) => {
+                // println!("shader: {:#?}", module);
+                let info = naga::valid::Validator::new(
+                    naga::valid::ValidationFlags::all(),
+                    naga::valid::Capabilities::default(),
+                )
+                .validate(&module)
+                .unwrap();
+                let wgsl = naga::back::wgsl::write_string(
+                    &module,
+                    &info,
+                    naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
+                )
+                .unwrap();
+                println!("Final wgsl from naga:\n\n{}", wgsl);
+                // Ok(module)
+            }
+            Err(e) => {
+                panic!("{}", e.emit_to_string(&composer));
+                // Err(e)
+            }
+        }
+    }
+
     #[test]
     fn test_effect_shader_source() {
         // Empty particle layout
@@ -1749,7 +2961,12 @@ else { return c1; }
         let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
             .with_simulation_space(SimulationSpace::Local);
         assert_eq!(asset.simulation_space, SimulationSpace::Local);
-        let res = EffectShaderSource::generate(&asset);
+        let res = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        );
         assert!(res.is_err());
         let err = res.err().unwrap();
         assert!(matches!(err, ShaderGenerateError::Validate(_)));
@@ -1760,7 +2977,12 @@ else { return c1; }
         let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
             .init(SetAttributeModifier::new(Attribute::VELOCITY, zero));
         assert!(asset.particle_layout().size() > 0);
-        let res = EffectShaderSource::generate(&asset);
+        let res = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        );
         assert!(res.is_err());
         let err = res.err().unwrap();
         assert!(matches!(err, ShaderGenerateError::Validate(_)));
@@ -1772,7 +2994,12 @@ else { return c1; }
             .with_simulation_space(SimulationSpace::Local)
             .init(SetAttributeModifier::new(Attribute::POSITION, zero));
         assert_eq!(asset.simulation_space, SimulationSpace::Local);
-        let res = EffectShaderSource::generate(&asset);
+        let res = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        );
         assert!(res.is_ok());
         let shader_source = res.unwrap();
         for (name, code) in iter::once(("Init", &shader_source.init))
@@ -1794,70 +3021,19 @@ else { return c1; }
             let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
             shader_defs.insert("LOCAL_SPACE_SIMULATION".into(), ShaderDefValue::Bool(true));
             shader_defs.insert("PARTICLE_TEXTURE".into(), ShaderDefValue::Bool(true));
+            shader_defs.insert("COLOR_LUT".into(), ShaderDefValue::Bool(true));
             shader_defs.insert("NEEDS_UV".into(), ShaderDefValue::Bool(true));
             shader_defs.insert("RENDER_NEEDS_SPAWNER".into(), ShaderDefValue::Bool(true));
             shader_defs.insert(
                 "PARTICLE_SCREEN_SPACE_SIZE".into(),
                 ShaderDefValue::Bool(true),
             );
+            shader_defs.insert("WORKGROUP_SIZE".into(), ShaderDefValue::UInt(64));
             if name == "Update" {
                 shader_defs.insert("REM_MAX_SPAWN_ATOMIC".into(), ShaderDefValue::Bool(true));
+                shader_defs.insert("GROUP_INDEX".into(), ShaderDefValue::UInt(0));
             }
-            let mut composer = Composer::default();
-
-            // Import bevy_render::view for the render shader
-            {
-                // It's reasonably hard to retrieve the source code for view.wgsl in
-                // bevy_render. We use a few tricks to get a Shader that we can
-                // then convert into a composable module (which is how imports work in Bevy
-                // itself).
-                let mut dummy_app = App::new();
-                dummy_app.init_resource::<Assets<Shader>>();
-                dummy_app.add_plugins(bevy::render::view::ViewPlugin);
-                let shaders = dummy_app.world.get_resource::<Assets<Shader>>().unwrap();
-                let view_shader = shaders.get(bevy::render::view::VIEW_TYPE_HANDLE).unwrap();
-
-                let res = composer.add_composable_module(view_shader.into());
-                assert!(res.is_ok());
-            }
-
-            // Import bevy_hanabi::vfx_common
-            {
-                let min_storage_buffer_offset_alignment = 256;
-                let common_shader =
-                    HanabiPlugin::make_common_shader(min_storage_buffer_offset_alignment);
-                let res = composer.add_composable_module((&common_shader).into());
-                assert!(res.is_ok());
-            }
-
-            match composer.make_naga_module(NagaModuleDescriptor {
-                source: code,
-                file_path: &format!("{}.wgsl", name),
-                shader_defs,
-                ..Default::default()
-            }) {
-                Ok(module) => {
-                    // println!("shader: {:#?}", module);
-                    let info = naga::valid::Validator::new(
-                        naga::valid::ValidationFlags::all(),
-                        naga::valid::Capabilities::default(),
-                    )
-                    .validate(&module)
-                    .unwrap();
-                    let wgsl = naga::back::wgsl::write_string(
-                        &module,
-                        &info,
-                        naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
-                    )
-                    .unwrap();
-                    println!("Final wgsl from naga:\n\n{}", wgsl);
-                    // Ok(module)
-                }
-                Err(e) => {
-                    panic!("{}", e.emit_to_string(&composer));
-                    // Err(e)
-                }
-            }
+            compose_and_validate(name, code, shader_defs);
 
             // let mut frontend = Frontend::new();
             // let res = frontend.parse(code);
@@ -1869,6 +3045,462 @@ else { return c1; }
         }
     }
 
+    #[test]
+    fn test_effect_shader_source_static_colliders() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, zero))
+            .update(CollideStaticModifier::new());
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(shader_source
+            .layout_flags
+            .contains(LayoutFlags::STATIC_COLLIDERS));
+
+        // The static colliders are only declared with the STATIC_COLLIDERS definition
+        let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
+        shader_defs.insert("WORKGROUP_SIZE".into(), ShaderDefValue::UInt(64));
+        shader_defs.insert("REM_MAX_SPAWN_ATOMIC".into(), ShaderDefValue::Bool(true));
+        shader_defs.insert("GROUP_INDEX".into(), ShaderDefValue::UInt(0));
+        shader_defs.insert("STATIC_COLLIDERS".into(), ShaderDefValue::Bool(true));
+        compose_and_validate("Update", &shader_source.update[0], shader_defs);
+
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero));
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(!shader_source
+            .layout_flags
+            .contains(LayoutFlags::STATIC_COLLIDERS));
+    }
+
+    #[test]
+    fn test_effect_shader_source_batched_update() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, zero))
+            .update_groups(CloneModifier::new(1.0, 1), ParticleGroupSet::single(0))
+            .update(CollideStaticModifier::new());
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // The update shader of each group is valid both for a single instance and for
+        // a batch of instances
+        for (group_index, update_source) in shader_source.update.iter().enumerate() {
+            for batched in [false, true] {
+                let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
+                shader_defs.insert("WORKGROUP_SIZE".into(), ShaderDefValue::UInt(64));
+                shader_defs.insert("REM_MAX_SPAWN_ATOMIC".into(), ShaderDefValue::Bool(true));
+                shader_defs.insert(
+                    "GROUP_INDEX".into(),
+                    ShaderDefValue::UInt(group_index as u32),
+                );
+                shader_defs.insert("STATIC_COLLIDERS".into(), ShaderDefValue::Bool(true));
+                if batched {
+                    shader_defs.insert("BATCHED_UPDATE".into(), ShaderDefValue::Bool(true));
+                    shader_defs.insert("SPAWNER_READ_ONLY".into(), ShaderDefValue::Bool(true));
+                    shader_defs.insert("SPAWNER_PADDING".into(), ShaderDefValue::Bool(true));
+                }
+                compose_and_validate("Update", update_source, shader_defs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_effect_shader_source_group_render() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let cutoff = module.lit(0.5);
+        let texture = Handle::<Image>::weak_from_u128(0x1234);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .with_group_alpha_mode(0, AlphaMode::Mask(cutoff))
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .render_groups(
+                ParticleTextureModifier {
+                    texture: texture.clone(),
+                    sample_mapping: ImageSampleMapping::Modulate,
+                },
+                ParticleGroupSet::single(1),
+            );
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(shader_source.group_layout_flags.len(), 2);
+        assert_eq!(
+            shader_source.group_layout_flags[0],
+            LayoutFlags::USE_ALPHA_MASK
+        );
+        assert_eq!(
+            shader_source.group_layout_flags[1],
+            LayoutFlags::PARTICLE_TEXTURE | LayoutFlags::NEEDS_UV
+        );
+        assert_eq!(
+            shader_source.layout_flags,
+            LayoutFlags::USE_ALPHA_MASK | LayoutFlags::PARTICLE_TEXTURE | LayoutFlags::NEEDS_UV
+        );
+        assert_eq!(shader_source.particle_textures, vec![None, Some(texture)]);
+    }
+
+    #[test]
+    fn test_effect_shader_source_fast_forward() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let age = module.lit(0.);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .init(SetAttributeModifier::new(Attribute::AGE, age));
+        assert_eq!(asset.prewarm, 0.);
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // Even without pre-warming, the effect can be fast-forwarded by a seek
+        assert!(shader_source.init.contains("if (spawner.prewarm > 0.0) {"));
+    }
+
+    #[test]
+    fn test_effect_shader_source_color_lut() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let texture = Handle::<Image>::weak_from_u128(0x1234);
+        let lut = Handle::<Image>::weak_from_u128(0x5678);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .render(ParticleTextureModifier {
+                texture: texture.clone(),
+                sample_mapping: ImageSampleMapping::Modulate,
+            })
+            .render(ColorOverLifetimeTextureModifier {
+                texture: lut.clone(),
+            });
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // The LUT doesn't replace the particle texture
+        assert_eq!(
+            shader_source.group_layout_flags[0],
+            LayoutFlags::PARTICLE_TEXTURE | LayoutFlags::NEEDS_UV | LayoutFlags::COLOR_LUT
+        );
+        assert_eq!(shader_source.particle_textures, vec![Some(texture)]);
+        assert_eq!(shader_source.color_luts, vec![Some(lut)]);
+        assert!(shader_source.render[0].contains("textureSampleLevel(color_lut_texture"));
+        assert!(shader_source.render[0].contains("textureSample(particle_texture"));
+    }
+
+    #[test]
+    fn test_effect_shader_source_deferred() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let cutoff = module.lit(0.5);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .init_groups(
+                SetAttributeModifier::new(Attribute::POSITION, zero),
+                ParticleGroupSet::all(),
+            )
+            .with_group_alpha_mode(0, AlphaMode::Mask(cutoff))
+            .with_deferred(DeferredMaterial {
+                metallic: 1.,
+                ..default()
+            });
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // Only the alpha-masked group is written into the G-buffer
+        assert_eq!(
+            shader_source.group_layout_flags,
+            vec![
+                LayoutFlags::USE_ALPHA_MASK | LayoutFlags::DEFERRED,
+                LayoutFlags::NONE
+            ]
+        );
+        assert!(shader_source.render[0].contains("vec4<f32>(0.5, 1., 0.5, 0.0)"));
+    }
+
+    #[test]
+    fn test_effect_shader_source_blob_shadow() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let cutoff = module.lit(0.5);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .init_groups(
+                SetAttributeModifier::new(Attribute::POSITION, zero),
+                ParticleGroupSet::all(),
+            )
+            .with_group_alpha_mode(1, AlphaMode::Mask(cutoff))
+            .with_blob_shadow(BlobShadow {
+                opacity: 0.25,
+                ..default()
+            });
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // Only the alpha-blended group draws a shadow
+        assert_eq!(
+            shader_source.group_layout_flags,
+            vec![LayoutFlags::BLOB_SHADOW, LayoutFlags::USE_ALPHA_MASK]
+        );
+        assert!(shader_source.render[0].contains("const blob_shadow_opacity = 0.25;"));
+
+        // The shadow quad of the blended group is valid WGSL
+        let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
+        shader_defs.insert("BLOB_SHADOW".into(), ShaderDefValue::Bool(true));
+        compose_and_validate("Render", &shader_source.render[0], shader_defs);
+    }
+
+    #[test]
+    fn test_effect_shader_source_z_layers_2d() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let position = module.attr(Attribute::POSITION);
+        let y = module.y(position);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .render_groups(
+                ZLayer2dModifier::new(y, [1., -1., f32::NAN, 0.]),
+                ParticleGroupSet::single(0),
+            );
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // Only the first group is split, into layers sorted by Z
+        assert_eq!(shader_source.z_layers_2d, vec![vec![-1., 0., 1.], vec![]]);
+        assert!(shader_source.render[0].contains("#{Z_LAYER_2D}"));
+        assert!(!shader_source.render[1].contains("#{Z_LAYER_2D}"));
+
+        // The render shader of each layer is valid WGSL, as is the one drawing all
+        // particles at once in 3D
+        for z_layer in [None, Some(0), Some(2)] {
+            let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
+            if let Some(z_layer) = z_layer {
+                shader_defs.insert("Z_LAYER_2D".into(), ShaderDefValue::UInt(z_layer));
+            }
+            compose_and_validate("Render", &shader_source.render[0], shader_defs);
+        }
+    }
+
+    #[test]
+    fn test_effect_shader_source_lighting_2d() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let emissive = module.lit(0.5);
+        let asset = EffectAsset::new(vec![256, 64], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .render_groups(
+                Lighting2dModifier::new(emissive),
+                ParticleGroupSet::single(1),
+            );
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+
+        // Only the second group is lit
+        assert_eq!(
+            shader_source.group_layout_flags,
+            vec![LayoutFlags::NONE, LayoutFlags::LIGHTING_2D]
+        );
+
+        // The lit group is valid WGSL
+        let mut shader_defs = std::collections::HashMap::<String, ShaderDefValue>::new();
+        shader_defs.insert("LIGHTING_2D".into(), ShaderDefValue::Bool(true));
+        compose_and_validate("Render", &shader_source.render[1], shader_defs);
+    }
+
+    #[test]
+    fn test_effect_shader_source_lod() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .render(ParticleTextureModifier {
+                texture: Handle::<Image>::weak_from_u128(0x1234),
+                sample_mapping: ImageSampleMapping::Modulate,
+            });
+
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(shader_source
+            .layout_flags
+            .contains(LayoutFlags::PARTICLE_TEXTURE));
+        assert!(!shader_source.render[0].contains("size *="));
+
+        let lod = LodTier::new(10.)
+            .with_size_scale(0.5)
+            .disable_modifier::<ParticleTextureModifier>()
+            .shader_config();
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            lod.as_ref(),
+        )
+        .unwrap();
+        assert!(!shader_source
+            .layout_flags
+            .contains(LayoutFlags::PARTICLE_TEXTURE));
+        assert!(shader_source.render[0].contains("size *= 0.5;"));
+    }
+
+    #[test]
+    fn test_effect_shader_source_simulation_space() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, zero));
+        assert_eq!(asset.simulation_space, SimulationSpace::Global);
+
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            SimulationSpace::Global,
+            None,
+        )
+        .unwrap();
+        assert!(!shader_source
+            .layout_flags
+            .contains(LayoutFlags::LOCAL_SPACE_SIMULATION));
+
+        // The instance override takes precedence over the asset
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            SimulationSpace::Local,
+            None,
+        )
+        .unwrap();
+        assert!(shader_source
+            .layout_flags
+            .contains(LayoutFlags::LOCAL_SPACE_SIMULATION));
+
+        // Both position and velocity are converted when switching space
+        assert!(shader_source.update[0].contains(
+            "particle.position = vec4<f32>(particle.position, 1.0) * spawner.inverse_transform;"
+        ));
+        assert!(shader_source.update[0].contains(
+            "particle.velocity = vec4<f32>(particle.velocity, 0.0) * spawner.transform;"
+        ));
+    }
+
+    #[test]
+    fn test_effect_shader_source_motion_substeps() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .init(SetAttributeModifier::new(Attribute::VELOCITY, zero));
+
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(!shader_source.update[0].contains("substep_count"));
+
+        let asset = asset.with_motion_substeps(0.5, 8);
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(shader_source.update[0].contains("ceil(step_distance / 0.5)), 1u, 8u)"));
+        assert!(shader_source.update[0].contains("sim_params.delta_time = frame_delta_time;"));
+    }
+
+    #[test]
+    fn test_effect_shader_source_modifier_toggles() {
+        let mut module = Module::default();
+        let zero = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, zero))
+            .update(AccelModifier::new(zero));
+
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(!shader_source.init.contains("disabled_modifiers &"));
+        assert!(!shader_source.update[0].contains("disabled_modifiers &"));
+
+        let asset = asset.with_modifier_toggles(true);
+        let shader_source = EffectShaderSource::generate(
+            &ShaderTemplates::default(),
+            &asset,
+            asset.simulation_space,
+            None,
+        )
+        .unwrap();
+        assert!(shader_source
+            .init
+            .contains("if ((spawner.disabled_modifiers & 1u) == 0u) {"));
+        assert!(shader_source.update[0].contains("if ((spawner.disabled_modifiers & 2u) == 0u) {"));
+    }
+
     // Regression test for #228
     #[test]
     fn test_compile_effect_changed() {
@@ -1959,6 +3591,101 @@ else { return c1; }
         }
     }
 
+    #[test]
+    fn test_compile_effect_shared_shaders() {
+        let mut app = make_test_app();
+
+        let world = &mut app.world;
+        let mut assets = world.resource_mut::<Assets<EffectAsset>>();
+        let mut module = Module::default();
+        let init_pos = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![64, 32], Spawner::once(32.0.into(), true), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, init_pos));
+        let handle = assets.add(asset.clone());
+        let other_handle = assets.add(asset);
+        for handle in [&handle, &handle, &other_handle] {
+            world.spawn((
+                ParticleEffect::new(handle.clone()),
+                CompiledParticleEffect::default(),
+            ));
+        }
+
+        app.update();
+
+        let world = &mut app.world;
+        let shaders: Vec<_> = world
+            .query::<&CompiledParticleEffect>()
+            .iter(world)
+            .map(|compiled| compiled.get_configured_shader().unwrap())
+            .collect();
+        assert_eq!(shaders.len(), 3);
+
+        // Both groups have the same update code, so share the same shader, and are
+        // only specialized by their group index.
+        assert_eq!(shaders[0].update.len(), 2);
+        assert_eq!(shaders[0].update[0], shaders[0].update[1]);
+
+        // Instances of the same asset, and of different assets producing the same
+        // code, share the same shaders.
+        for shader in &shaders[1..] {
+            assert_eq!(shader.init, shaders[0].init);
+            assert_eq!(shader.update, shaders[0].update);
+            assert_eq!(shader.render, shaders[0].render);
+        }
+    }
+
+    #[test]
+    fn test_compile_effect_overrides() {
+        let mut app = make_test_app();
+
+        let world = &mut app.world;
+        let mut assets = world.resource_mut::<Assets<EffectAsset>>();
+        let mut module = Module::default();
+        let init_pos = module.lit(Vec3::ZERO);
+        let asset = EffectAsset::new(vec![64], Spawner::once(32.0.into(), true), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, init_pos))
+            .render(ParticleTextureModifier::default())
+            .render(FlipbookModifier {
+                sprite_grid_size: UVec2::new(2, 2),
+            });
+        let handle = assets.add(asset);
+        let texture = Handle::<Image>::weak_from_u128(0x1234);
+        let base = world
+            .spawn((
+                ParticleEffect::new(handle.clone()),
+                CompiledParticleEffect::default(),
+            ))
+            .id();
+        let overridden = world
+            .spawn((
+                ParticleEffect::new(handle)
+                    .with_texture(Some(texture.clone()))
+                    .with_sprite_grid_size(Some(UVec2::new(4, 4))),
+                CompiledParticleEffect::default(),
+            ))
+            .id();
+
+        app.update();
+
+        let world = &mut app.world;
+        let base = world.get::<CompiledParticleEffect>(base).unwrap();
+        let overridden = world.get::<CompiledParticleEffect>(overridden).unwrap();
+        assert_eq!(
+            base.particle_textures().collect::<Vec<_>>(),
+            vec![Some(&Handle::default())]
+        );
+        assert_eq!(
+            overridden.particle_textures().collect::<Vec<_>>(),
+            vec![Some(&texture)]
+        );
+
+        // The flipbook layout is baked into the render shader
+        let base_shader = base.get_configured_shader().unwrap();
+        let overridden_shader = overridden.get_configured_shader().unwrap();
+        assert_eq!(base_shader.init, overridden_shader.init);
+        assert_ne!(base_shader.render, overridden_shader.render);
+    }
+
     #[test]
     fn test_compile_effect_visibility() {
         let spawner = Spawner::once(32.0.into(), true);
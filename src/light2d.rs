@@ -0,0 +1,170 @@
+use bevy::{
+    prelude::*,
+    render::{extract_resource::ExtractResource, render_resource::ShaderType},
+};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of [`PointLight2d`] lighting the particles. This must match
+/// the size of the `lights` array of the `Lights2d` struct of
+/// `vfx_render.wgsl`.
+pub(crate) const MAX_POINT_LIGHTS_2D: usize = 16;
+
+/// A 2D point light, lighting the particles of the effects using a
+/// [`Lighting2dModifier`].
+///
+/// The light is a disc in the XY plane of the world, and ignores the Z
+/// coordinate of the particles. Its intensity falls off smoothly from its
+/// center to zero at its radius.
+///
+/// [`Lighting2dModifier`]: crate::Lighting2dModifier
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct PointLight2d {
+    /// The light position, in world space.
+    pub position: Vec2,
+    /// The light color.
+    pub color: Color,
+    /// The light intensity, multiplying its color.
+    pub intensity: f32,
+    /// The radius beyond which the light has no effect.
+    pub radius: f32,
+}
+
+impl Default for PointLight2d {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            color: Color::WHITE,
+            intensity: 1.,
+            radius: 100.,
+        }
+    }
+}
+
+/// 2D lighting applied to the particles of the effects using a
+/// [`Lighting2dModifier`].
+///
+/// The lighting is shared by all effects, and uploaded to a GPU uniform buffer
+/// each frame it changes. The 2D lighting crates of the ecosystem render their
+/// own lights, generally in a pass of their own, which doesn't reach the
+/// particles. This resource is typically filled each frame by a bridge system
+/// copying the ambient light and the point lights of such a crate, so the
+/// particles receive the same lighting as the sprites around them. Only the
+/// first [`PointLight2d`]s are used, up to 16.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn update_lights(mut lights: ResMut<Lights2d>, torches: Query<&GlobalTransform>) {
+///     // A dark night, lit by torches
+///     lights.ambient = Color::rgb(0.05, 0.05, 0.1);
+///     lights.point_lights.clear();
+///     for transform in &torches {
+///         lights.point_lights.push(PointLight2d {
+///             position: transform.translation().truncate(),
+///             color: Color::ORANGE,
+///             intensity: 2.,
+///             radius: 150.,
+///         });
+///     }
+/// }
+/// ```
+///
+/// [`Lighting2dModifier`]: crate::Lighting2dModifier
+#[derive(Debug, Clone, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct Lights2d {
+    /// Ambient light, lighting all particles whatever their position. Defaults
+    /// to white, which leaves the particles unchanged without point lights.
+    pub ambient: Color,
+    /// The point lights.
+    pub point_lights: Vec<PointLight2d>,
+}
+
+impl Default for Lights2d {
+    fn default() -> Self {
+        Self {
+            ambient: Color::WHITE,
+            point_lights: vec![],
+        }
+    }
+}
+
+/// GPU representation of a [`PointLight2d`], matching the `PointLight2d` struct
+/// of `vfx_render.wgsl`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ShaderType)]
+pub(crate) struct GpuPointLight2d {
+    /// Light position in `xy`, and radius in `z`.
+    pub position_radius: Vec4,
+    /// Light color multiplied by its intensity, in linear RGB.
+    pub color: Vec4,
+}
+
+/// GPU representation of the [`Lights2d`], matching the `Lights2d` struct of
+/// `vfx_render.wgsl`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ShaderType)]
+pub(crate) struct GpuLights2d {
+    /// Ambient light, in linear RGB.
+    pub ambient: Vec4,
+    /// Number of valid entries in `point_lights`.
+    pub point_light_count: u32,
+    /// The point lights.
+    pub point_lights: [GpuPointLight2d; MAX_POINT_LIGHTS_2D],
+}
+
+impl From<&Lights2d> for GpuLights2d {
+    fn from(lights: &Lights2d) -> Self {
+        let mut gpu_lights = Self {
+            ambient: Vec4::from_array(lights.ambient.as_linear_rgba_f32()),
+            ..default()
+        };
+        for (gpu_light, light) in gpu_lights
+            .point_lights
+            .iter_mut()
+            .zip(lights.point_lights.iter())
+        {
+            *gpu_light = GpuPointLight2d {
+                position_radius: light.position.extend(light.radius.max(0.)).extend(0.),
+                color: Vec4::from_array(light.color.as_linear_rgba_f32()) * light.intensity,
+            };
+        }
+        gpu_lights.point_light_count = lights.point_lights.len().min(MAX_POINT_LIGHTS_2D) as u32;
+        gpu_lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_lights_2d() {
+        // The layout must match the Lights2d struct of the render shader
+        assert_eq!(
+            GpuLights2d::min_size().get(),
+            32 + 32 * MAX_POINT_LIGHTS_2D as u64
+        );
+
+        let lights = Lights2d {
+            ambient: Color::BLACK,
+            point_lights: vec![
+                PointLight2d {
+                    position: Vec2::new(1., 2.),
+                    color: Color::WHITE,
+                    intensity: 2.,
+                    radius: 10.,
+                };
+                MAX_POINT_LIGHTS_2D + 1
+            ],
+        };
+        let gpu = GpuLights2d::from(&lights);
+        assert_eq!(gpu.ambient, Vec4::new(0., 0., 0., 1.));
+        assert_eq!(gpu.point_light_count, MAX_POINT_LIGHTS_2D as u32);
+        assert_eq!(
+            gpu.point_lights[0].position_radius,
+            Vec4::new(1., 2., 10., 0.)
+        );
+        assert_eq!(gpu.point_lights[0].color, Vec4::splat(2.));
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::{prelude::*, reflect::TypePath};
+
+use crate::{modifier::Modifier, EffectSpawner};
+
+/// Level of detail applied to an effect instance beyond a given distance from
+/// the camera.
+///
+/// See [`EffectLod`] for details.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LodTier {
+    /// Distance from the nearest camera, in world units, from which this tier
+    /// applies.
+    pub min_distance: f32,
+    /// Scale applied to the number of particles spawned each frame.
+    pub spawn_scale: f32,
+    /// Scale applied to the size of the rendered particles.
+    pub size_scale: f32,
+    /// Type paths of the modifiers disabled while this tier applies.
+    pub disabled_modifiers: Vec<String>,
+}
+
+impl LodTier {
+    /// Create a new tier applying from the given distance, which doesn't
+    /// change anything until configured.
+    pub fn new(min_distance: f32) -> Self {
+        Self {
+            min_distance,
+            spawn_scale: 1.,
+            size_scale: 1.,
+            disabled_modifiers: vec![],
+        }
+    }
+
+    /// Set the scale applied to the number of particles spawned each frame.
+    pub fn with_spawn_scale(mut self, spawn_scale: f32) -> Self {
+        self.spawn_scale = spawn_scale;
+        self
+    }
+
+    /// Set the scale applied to the size of the rendered particles.
+    pub fn with_size_scale(mut self, size_scale: f32) -> Self {
+        self.size_scale = size_scale;
+        self
+    }
+
+    /// Disable all the modifiers of type `M` while this tier applies.
+    ///
+    /// This is generally used to disable expensive modifiers which don't
+    /// contribute much at a distance, like trails or noise. Disabling
+    /// modifiers requires compiling a separate set of shaders for this tier.
+    pub fn disable_modifier<M: Modifier + TypePath>(mut self) -> Self {
+        self.disabled_modifiers.push(M::type_path().to_string());
+        self
+    }
+
+    /// Get the shader configuration of this tier, or `None` if the tier uses
+    /// the same shaders as the effect without LOD.
+    pub(crate) fn shader_config(&self) -> Option<LodShaderConfig> {
+        if self.size_scale == 1. && self.disabled_modifiers.is_empty() {
+            None
+        } else {
+            Some(LodShaderConfig {
+                size_scale: self.size_scale,
+                disabled_modifiers: self.disabled_modifiers.clone(),
+            })
+        }
+    }
+}
+
+/// Part of a [`LodTier`] baked into the shaders of an effect instance.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LodShaderConfig {
+    pub size_scale: f32,
+    pub disabled_modifiers: Vec<String>,
+}
+
+// The size scale is only compared and hashed by value to look up the compiled
+// shaders, so NaN values are not a concern.
+impl Eq for LodShaderConfig {}
+
+impl std::hash::Hash for LodShaderConfig {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.size_scale.to_bits().hash(state);
+        self.disabled_modifiers.hash(state);
+    }
+}
+
+impl LodShaderConfig {
+    /// Check if a modifier is enabled with this configuration.
+    pub fn is_enabled(&self, modifier: &dyn Modifier) -> bool {
+        let type_path = modifier.reflect_type_path();
+        !self.disabled_modifiers.iter().any(|m| m == type_path)
+    }
+}
+
+/// Distance-based level of detail (LOD) of an effect instance.
+///
+/// Add this component to the same entity as the [`ParticleEffect`] to reduce
+/// the cost of the effect instance when it's far from the camera. Each frame,
+/// the distance from the effect to the nearest active camera selects the last
+/// [`LodTier`] whose [`min_distance`] is smaller than that distance, if any,
+/// which then:
+/// - scales the number of particles spawned this frame by [`spawn_scale`];
+/// - scales the size of the rendered particles by [`size_scale`];
+/// - disables the modifiers listed in [`disabled_modifiers`].
+///
+/// Spawn scaling is applied during the [`EffectSystems::TickSpawners`] set,
+/// after [`tick_spawners()`] calculated the spawn count and before the
+/// [`ParticleBudget`] is enforced. Fractional spawn counts are carried over to
+/// the next frames, so effects spawning a single particle per frame still
+/// spawn at a reduced rate instead of not at all.
+///
+/// Size scaling and disabled modifiers are baked into the shaders of the
+/// effect instance, so switching to a tier using different values than the
+/// current one compiles new shaders the first time it happens, and prevents
+/// batching with instances using another tier. Particles already alive keep
+/// their current state when the tier changes.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`min_distance`]: crate::LodTier::min_distance
+/// [`spawn_scale`]: crate::LodTier::spawn_scale
+/// [`size_scale`]: crate::LodTier::size_scale
+/// [`disabled_modifiers`]: crate::LodTier::disabled_modifiers
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+/// [`tick_spawners()`]: crate::tick_spawners
+/// [`ParticleBudget`]: crate::ParticleBudget
+#[derive(Debug, Default, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectLod {
+    /// LOD tiers, sorted by increasing distance.
+    tiers: Vec<LodTier>,
+    /// Index of the tier currently applied, if any.
+    current: Option<usize>,
+    /// Fractional number of particles not spawned yet due to spawn scaling.
+    spawn_remainder: f32,
+}
+
+impl EffectLod {
+    /// Create a new LOD component from a list of tiers, in any order.
+    pub fn new(tiers: impl IntoIterator<Item = LodTier>) -> Self {
+        let mut tiers: Vec<_> = tiers.into_iter().collect();
+        tiers.sort_by(|a, b| a.min_distance.total_cmp(&b.min_distance));
+        Self { tiers, ..default() }
+    }
+
+    /// Add a new tier.
+    pub fn with_tier(self, tier: LodTier) -> Self {
+        Self::new(self.tiers.into_iter().chain(std::iter::once(tier)))
+    }
+
+    /// Get the LOD tiers, sorted by increasing distance.
+    pub fn tiers(&self) -> &[LodTier] {
+        &self.tiers
+    }
+
+    /// Get the index of the tier currently applied, if any.
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Get the tier currently applied, if any.
+    pub fn current_tier(&self) -> Option<&LodTier> {
+        self.current.map(|index| &self.tiers[index])
+    }
+
+    /// Select the tier applying at the given distance from the camera.
+    fn select(&self, distance: f32) -> Option<usize> {
+        self.tiers
+            .iter()
+            .rposition(|tier| distance >= tier.min_distance)
+    }
+
+    /// Scale a spawn count by the spawn scale of the current tier, carrying
+    /// over the fractional part to the next frames.
+    fn scale_spawn_count(&mut self, spawn_count: u32) -> u32 {
+        let Some(spawn_scale) = self.current_tier().map(|tier| tier.spawn_scale) else {
+            self.spawn_remainder = 0.;
+            return spawn_count;
+        };
+        let count = (spawn_count as f32).mul_add(spawn_scale.max(0.), self.spawn_remainder);
+        let scaled = count.floor();
+        self.spawn_remainder = count - scaled;
+        scaled as u32
+    }
+}
+
+/// Select the LOD tier of all effect instances with an [`EffectLod`], and
+/// scale their spawn count accordingly.
+///
+/// This system runs in the [`EffectSystems::TickSpawners`] set, after
+/// [`tick_spawners()`].
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+/// [`tick_spawners()`]: crate::tick_spawners
+pub fn update_effect_lod(
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut query: Query<(&GlobalTransform, &mut EffectLod, Option<&mut EffectSpawner>)>,
+) {
+    trace!("update_effect_lod");
+
+    let camera_positions: Vec<Vec3> = cameras
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .map(|(_, transform)| transform.translation())
+        .collect();
+
+    for (transform, mut lod, spawner) in query.iter_mut() {
+        let position = transform.translation();
+        let current = camera_positions
+            .iter()
+            .map(|camera| camera.distance(position))
+            .min_by(f32::total_cmp)
+            .and_then(|distance| lod.select(distance));
+
+        // Only mutate the tier when it changes, as this triggers a recompiling of the
+        // effect shaders.
+        if lod.current != current {
+            lod.current = current;
+        }
+
+        if let Some(mut spawner) = spawner {
+            let lod = lod.bypass_change_detection();
+            spawner.spawn_count = lod.scale_spawn_count(spawner.spawn_count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrailModifier;
+
+    #[test]
+    fn select() {
+        let lod = EffectLod::new([
+            LodTier::new(50.).with_spawn_scale(0.),
+            LodTier::new(10.).with_spawn_scale(0.5),
+        ]);
+        assert_eq!(lod.tiers()[0].min_distance, 10.);
+        assert_eq!(lod.select(5.), None);
+        assert_eq!(lod.select(10.), Some(0));
+        assert_eq!(lod.select(49.), Some(0));
+        assert_eq!(lod.select(100.), Some(1));
+
+        let lod = EffectLod::default().with_tier(LodTier::new(20.));
+        assert_eq!(lod.tiers().len(), 1);
+        assert_eq!(lod.select(30.), Some(0));
+    }
+
+    #[test]
+    fn scale_spawn_count() {
+        let mut lod = EffectLod::new([LodTier::new(10.).with_spawn_scale(0.25)]);
+        assert_eq!(lod.scale_spawn_count(8), 8);
+
+        lod.current = Some(0);
+        assert_eq!(lod.scale_spawn_count(8), 2);
+        // Fractional counts are carried over
+        let total: u32 = (0..8).map(|_| lod.scale_spawn_count(1)).sum();
+        assert_eq!(total, 2);
+
+        lod.current = None;
+        assert_eq!(lod.scale_spawn_count(3), 3);
+        assert_eq!(lod.spawn_remainder, 0.);
+    }
+
+    #[test]
+    fn shader_config() {
+        assert!(LodTier::new(10.)
+            .with_spawn_scale(0.5)
+            .shader_config()
+            .is_none());
+
+        let config = LodTier::new(10.)
+            .with_size_scale(2.)
+            .disable_modifier::<TrailModifier>()
+            .shader_config()
+            .unwrap();
+        assert_eq!(config.size_scale, 2.);
+        assert!(!config.is_enabled(&TrailModifier::new(8)));
+    }
+}
@@ -46,6 +46,11 @@ impl AccelModifier {
         Self { accel }
     }
 
+    /// Get the acceleration expression.
+    pub fn accel(&self) -> ExprHandle {
+        self.accel
+    }
+
     /// Create a new modifier with an acceleration derived from a property.
     ///
     /// To create a new property, use [`Module::add_property()`].
@@ -145,6 +150,11 @@ impl RadialAccelModifier {
             accel: module.lit(acceleration),
         }
     }
+
+    /// Get the origin expression.
+    pub fn origin(&self) -> ExprHandle {
+        self.origin
+    }
 }
 
 #[typetag::serde]
@@ -265,6 +275,16 @@ impl TangentAccelModifier {
             accel: module.lit(acceleration),
         }
     }
+
+    /// Get the origin expression.
+    pub fn origin(&self) -> ExprHandle {
+        self.origin
+    }
+
+    /// Get the axis expression.
+    pub fn axis(&self) -> ExprHandle {
+        self.axis
+    }
 }
 
 #[typetag::serde]
@@ -6,26 +6,95 @@ use bevy::{prelude::*, utils::FloatOrd};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    calc_func_id, Attribute, BoxedModifier, EvalContext, ExprError, Modifier, ModifierContext,
-    Module, ShaderWriter,
+    calc_func_id, Attribute, BoxedModifier, EvalContext, ExprError, ExprHandle, Modifier,
+    ModifierContext, Module, ShaderWriter, ToWgslString,
 };
 
+/// How a particle duplicated by a [`CloneModifier`] initializes an attribute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum AttributeInheritance {
+    /// Copy the value of the attribute from the original particle.
+    #[default]
+    Inherit,
+    /// Reset the attribute to its default value.
+    ///
+    /// See [`Attribute::default_value()`].
+    Reset,
+    /// Assign the value of an expression.
+    ///
+    /// The expression is evaluated in the context of the original particle, so
+    /// any attribute it reads is the value of the original particle. This
+    /// allows transforming the inherited value, like scaling down the
+    /// velocity.
+    ///
+    /// Expression type: same as the attribute.
+    Expr(ExprHandle),
+}
+
+/// Rule defining how a particle duplicated by a [`CloneModifier`] initializes a
+/// single attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CloneAttributeRule {
+    /// The attribute the rule applies to.
+    pub attribute: Attribute,
+    /// How the attribute is initialized on the new particle.
+    pub inheritance: AttributeInheritance,
+}
+
 /// Duplicates a particle and places it in a group.
 ///
 /// Spawners always spawn particles into group 0, so this is the primary way to
 /// place particles into groups other than 0. Typical uses of this modifier are
 /// to create trails.
 ///
-/// All attributes are copied to the new particle, with the exception of
-/// [`Attribute::AGE`], which is reset to zero.
-#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+/// By default, all attributes are copied to the new particle, with the
+/// exception of [`Attribute::AGE`], which is reset to zero. This can be
+/// customized per attribute with [`with_attribute_inheritance()`], for example
+/// to inherit the position of the original particle but not its velocity.
+///
+/// [`with_attribute_inheritance()`]: crate::CloneModifier::with_attribute_inheritance
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct CloneModifier {
     /// How many seconds must elapse before the particle will be duplicated.
     ///
     /// If this is zero, particles will be duplicated every frame.
     pub spawn_period: f32,
+    /// Optional expression overriding [`spawn_period`] per particle.
+    ///
+    /// This allows the cloning rate to depend on some attributes of the
+    /// particle, like cloning more often when the particle moves faster. If
+    /// the period is zero or negative, the particle is duplicated every frame.
+    ///
+    /// Expression type: `f32`
+    ///
+    /// [`spawn_period`]: crate::CloneModifier::spawn_period
+    #[serde(default)]
+    pub spawn_period_expr: Option<ExprHandle>,
+    /// Optional probability for each duplication to occur, in `[0:1]`.
+    ///
+    /// If `None` (default), all duplications occur.
+    ///
+    /// Expression type: `f32`
+    #[serde(default)]
+    pub probability: Option<ExprHandle>,
     /// The group that the new particle will be spawned into.
     pub destination_group: u32,
+    /// Optional name of the group that the new particle will be spawned into,
+    /// overriding [`destination_group`].
+    ///
+    /// The name is resolved into a group index when the effect is compiled,
+    /// from the group names declared with [`EffectAsset::with_group_names()`].
+    ///
+    /// [`destination_group`]: crate::CloneModifier::destination_group
+    /// [`EffectAsset::with_group_names()`]: crate::EffectAsset::with_group_names
+    #[serde(default)]
+    pub destination_group_name: Option<String>,
+    /// Rules overriding how the new particle initializes some attributes.
+    ///
+    /// Attributes without a rule are inherited from the original particle,
+    /// except [`Attribute::AGE`] which is reset to zero.
+    #[serde(default)]
+    pub attribute_rules: Vec<CloneAttributeRule>,
 }
 
 #[typetag::serde]
@@ -39,25 +108,44 @@ impl Modifier for CloneModifier {
     }
 
     fn boxed_clone(&self) -> BoxedModifier {
-        Box::new(*self)
+        Box::new(self.clone())
     }
 
     fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
         let func_id = calc_func_id(self);
         let func_name = format!("duplicate_{0:016X}", func_id);
         let multiple_count_name = format!("multiple_count_{0:016X}", func_id);
+        let destination_group = match &self.destination_group_name {
+            Some(name) => context.group_index(name)?,
+            None => self.destination_group,
+        };
 
         context.make_fn(
             &func_name,
             "particle: ptr<function, Particle>, orig_index: u32",
             module,
-            &mut |_m: &mut Module, context: &mut dyn EvalContext| -> Result<String, ExprError> {
+            &mut |m: &mut Module, context: &mut dyn EvalContext| -> Result<String, ExprError> {
                 let age_reset_code = if context.particle_layout().contains(Attribute::AGE) {
                     format!("new_particle.{} = 0.0;", Attribute::AGE.name())
                 } else {
                     "".to_owned()
                 };
 
+                // Apply the attribute rules, after the age reset so that a rule can
+                // override it.
+                let mut attribute_rules_code = String::new();
+                for rule in &self.attribute_rules {
+                    if !context.particle_layout().contains(rule.attribute) {
+                        continue;
+                    }
+                    let value = match rule.inheritance {
+                        AttributeInheritance::Inherit => format!("(*particle).{}", rule.attribute.name()),
+                        AttributeInheritance::Reset => rule.attribute.default_value().to_wgsl_string(),
+                        AttributeInheritance::Expr(expr) => context.eval(m, expr)?,
+                    };
+                    attribute_rules_code += &format!("new_particle.{} = {};\n", rule.attribute.name(), value);
+                }
+
                 // If applicable, insert the particle into a linked list, either
                 // singly or doubly linked. This is typically used for ribbons.
 
@@ -96,14 +184,14 @@ impl Modifier for CloneModifier {
 
                 Ok(format!(
                     r##"
-                    let base_index = particle_groups[{dest}u].indirect_index;
+                    let base_index = particle_groups[particle_group_base + {dest}u].indirect_index;
 
                     // Recycle a dead particle.
-                    let dead_index = atomicSub(&render_group_indirect[{dest}u].dead_count, 1u) - 1u;
+                    let dead_index = atomicSub(&render_group_indirect[render_group_base + {dest}u].dead_count, 1u) - 1u;
                     // HACK - we have no limiter for dead_count, so could go negative (wrap around).
                     // Assume that any value above 2^31 is a wrap around, undo the atomic op and return.
                     if (dead_index >= 0xF0000000) {{
-                        atomicAdd(&render_group_indirect[{dest}u].dead_count, 1u);
+                        atomicAdd(&render_group_indirect[render_group_base + {dest}u].dead_count, 1u);
                         return;
                     }}
                     let new_index = indirect_buffer.indices[3u * (base_index + dead_index) + 2u];
@@ -111,6 +199,7 @@ impl Modifier for CloneModifier {
                     // Initialize the new particle.
                     var new_particle = *particle;
                     {age_reset_code}
+                    {attribute_rules_code}
 
                     // Insert the particle between us and our current `prev`
                     // node, if applicable.
@@ -122,20 +211,51 @@ impl Modifier for CloneModifier {
                     particle_buffer.particles[new_index] = new_particle;
 
                     // Mark it as alive.
-                    atomicAdd(&render_group_indirect[{dest}u].alive_count, 1u);
+                    atomicAdd(&render_group_indirect[render_group_base + {dest}u].alive_count, 1u);
 
                     // Add an instance.
-                    let ping = render_effect_indirect.ping;
-                    let indirect_index = atomicAdd(&render_group_indirect[{dest}u].instance_count, 1u);
+                    let indirect_index = atomicAdd(&render_group_indirect[render_group_base + {dest}u].instance_count, 1u);
                     indirect_buffer.indices[3u * (base_index + indirect_index) + ping] = new_index;
                 "##,
-                    dest = self.destination_group,
+                    dest = destination_group,
                 ))
             },
         )?;
 
-        if self.spawn_period <= 0.0 {
-            context.main_code += &format!("{func}(&particle);", func = func_name);
+        // Wrap the call into a random test if the cloning is probabilistic.
+        let call_code = if let Some(probability) = self.probability {
+            let probability = context.eval(module, probability)?;
+            format!(
+                "if (frand() < {probability}) {{ {func}(&particle, index); }}",
+                func = func_name
+            )
+        } else {
+            format!("{func}(&particle, index);", func = func_name)
+        };
+
+        if let Some(spawn_period) = self.spawn_period_expr {
+            // The period varies per particle, so is only known at runtime. Same as
+            // below, but fall back to cloning every frame if the period is not
+            // positive.
+            let period = context.eval(module, spawn_period)?;
+            context.main_code += &format!(
+                r##"
+                let {period_name} = {period};
+                var {multiple_count} = 1;
+                if ({period_name} > 0.0) {{
+                    {multiple_count} = max(0, i32(floor({b} / {period_name})) - i32(ceil(({b} - {delta}) / {period_name})) + 1);
+                }}
+                for (var i = 0; i < {multiple_count}; i += 1) {{
+                    {call_code}
+                }}
+            "##,
+                period_name = format!("clone_period_{0:016X}", func_id),
+                multiple_count = multiple_count_name,
+                b = "sim_params.time",
+                delta = "sim_params.delta_time",
+            );
+        } else if self.spawn_period <= 0.0 {
+            context.main_code += &call_code;
         } else {
             // Calculate the number of multiples of `spawn_period` that fall
             // between the last tick and this one, and spawn one particle for
@@ -146,10 +266,9 @@ impl Modifier for CloneModifier {
                 r##"
                 let {multiple_count} = max(0, i32(floor({b} / {m})) - i32(ceil(({b} - {delta}) / {m})) + 1);
                 for (var i = 0; i < {multiple_count}; i += 1) {{
-                    {func}(&particle, index);
+                    {call_code}
                 }}
             "##,
-                func = func_name,
                 multiple_count = multiple_count_name,
                 b = "sim_params.time",
                 delta = "sim_params.delta_time",
@@ -167,9 +286,83 @@ impl CloneModifier {
     pub fn new(spawn_period: f32, destination_group: u32) -> CloneModifier {
         CloneModifier {
             spawn_period,
+            spawn_period_expr: None,
+            probability: None,
             destination_group,
+            destination_group_name: None,
+            attribute_rules: vec![],
+        }
+    }
+
+    /// Set the name of the group that the new particle will be spawned into,
+    /// overriding the group index.
+    pub fn with_destination_group_name(mut self, name: impl Into<String>) -> Self {
+        self.destination_group_name = Some(name.into());
+        self
+    }
+
+    /// Set an expression overriding the spawn period per particle.
+    ///
+    /// Expression type: `f32`
+    pub fn with_spawn_period_expr(mut self, spawn_period: ExprHandle) -> Self {
+        self.spawn_period_expr = Some(spawn_period);
+        self
+    }
+
+    /// Set the probability for each duplication to occur.
+    ///
+    /// Expression type: `f32`
+    pub fn with_probability(mut self, probability: ExprHandle) -> Self {
+        self.probability = Some(probability);
+        self
+    }
+
+    /// Set how the new particle initializes an attribute.
+    ///
+    /// This replaces any rule previously set for the same attribute.
+    pub fn with_attribute_inheritance(
+        mut self,
+        attribute: Attribute,
+        inheritance: AttributeInheritance,
+    ) -> Self {
+        self.set_attribute_inheritance(attribute, inheritance);
+        self
+    }
+
+    /// Set how the new particle initializes an attribute.
+    ///
+    /// This replaces any rule previously set for the same attribute.
+    pub fn set_attribute_inheritance(
+        &mut self,
+        attribute: Attribute,
+        inheritance: AttributeInheritance,
+    ) {
+        if let Some(rule) = self
+            .attribute_rules
+            .iter_mut()
+            .find(|rule| rule.attribute == attribute)
+        {
+            rule.inheritance = inheritance;
+        } else {
+            self.attribute_rules.push(CloneAttributeRule {
+                attribute,
+                inheritance,
+            });
         }
     }
+
+    /// Get how the new particle initializes an attribute.
+    ///
+    /// Attributes without an explicit rule return
+    /// [`AttributeInheritance::Inherit`], even [`Attribute::AGE`] which is
+    /// always reset by default.
+    pub fn attribute_inheritance(&self, attribute: Attribute) -> AttributeInheritance {
+        self.attribute_rules
+            .iter()
+            .find(|rule| rule.attribute == attribute)
+            .map(|rule| rule.inheritance)
+            .unwrap_or_default()
+    }
 }
 
 impl Eq for CloneModifier {}
@@ -180,6 +373,112 @@ impl Hash for CloneModifier {
         H: Hasher,
     {
         FloatOrd(self.spawn_period).hash(state);
+        self.spawn_period_expr.hash(state);
+        self.probability.hash(state);
         self.destination_group.hash(state);
+        self.destination_group_name.hash(state);
+        self.attribute_rules.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParticleLayout, PropertyLayout};
+
+    use super::*;
+
+    #[test]
+    fn attribute_inheritance() {
+        let mut module = Module::default();
+        let half = module.lit(0.5);
+        let vel = module.attr(Attribute::VELOCITY);
+        let half_vel = module.mul(vel, half);
+        let modifier = CloneModifier::new(0., 1)
+            .with_attribute_inheritance(Attribute::COLOR, AttributeInheritance::Reset)
+            .with_attribute_inheritance(Attribute::VELOCITY, AttributeInheritance::Inherit)
+            .with_attribute_inheritance(Attribute::VELOCITY, AttributeInheritance::Expr(half_vel));
+        assert_eq!(modifier.attribute_rules.len(), 2);
+        assert_eq!(
+            modifier.attribute_inheritance(Attribute::POSITION),
+            AttributeInheritance::Inherit
+        );
+        assert_eq!(
+            modifier.attribute_inheritance(Attribute::VELOCITY),
+            AttributeInheritance::Expr(half_vel)
+        );
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .append(Attribute::COLOR)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.extra_code.contains(&format!(
+            "new_particle.{} = {};",
+            Attribute::COLOR.name(),
+            Attribute::COLOR.default_value().to_wgsl_string()
+        )));
+        assert!(context
+            .extra_code
+            .contains(&format!("new_particle.{} = (", Attribute::VELOCITY.name())));
+        assert!(!context
+            .extra_code
+            .contains(&format!("new_particle.{} =", Attribute::POSITION.name())));
+    }
+
+    #[test]
+    fn destination_group_name() {
+        let mut module = Module::default();
+        let modifier = CloneModifier::new(0., 0).with_destination_group_name("splash");
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new().append(Attribute::POSITION).build();
+        let group_names = vec!["rain".to_string(), "splash".to_string()];
+
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout)
+                .with_group_names(&group_names);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+        assert!(context
+            .extra_code
+            .contains("render_group_indirect[render_group_base + 1u]"));
+
+        // Unknown group name
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert_eq!(
+            modifier.apply(&mut module, &mut context),
+            Err(ExprError::UnknownGroupError("splash".to_string()))
+        );
+    }
+
+    #[test]
+    fn expr_rate() {
+        let mut module = Module::default();
+        let vel = module.attr(Attribute::VELOCITY);
+        let speed = module.length(vel);
+        let one = module.lit(1.);
+        let period = module.div(one, speed);
+        let probability = module.lit(0.25);
+        let modifier = CloneModifier::new(1., 1)
+            .with_spawn_period_expr(period)
+            .with_probability(probability);
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("let clone_period_"));
+        assert!(context.main_code.contains("if (frand() < "));
+        assert!(!context.main_code.contains("/ 1)"));
     }
 }
@@ -0,0 +1,933 @@
+//! Modifiers to collide particles with the environment.
+//!
+//! These modifiers detect particles hitting some surface, make them bounce off
+//! that surface, and optionally emit new particles at the collision point. The
+//! [`CollideVoxelModifier`] collides with the filled cells of the global
+//! [`VoxelGrid`], for worlds made of blocks where meshes are impractical, and
+//! the [`CollideMeshModifier`] raycasts particles against the triangles of the
+//! [`ParticleCollider`](crate::ParticleCollider) meshes, for precise impacts.
+//! The [`CollideStaticModifier`] collides with the simple shapes of the
+//! [`StaticColliders`](crate::StaticColliders) list, typically mirroring the
+//! static colliders of a physics world. The [`ForceReceiverModifier`] instead
+//! absorbs the particles entering a volume, to transfer their momentum to a
+//! physics object.
+
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calc_func_id, Attribute, BoxedModifier, EvalContext, ExprError, ExprHandle, Modifier,
+    ModifierContext, Module, ParentEvent, ShaderWriter,
+};
+
+/// Sub-emitter spawning particles into a group on collision.
+///
+/// Each time a particle collides, [`count`] new particles are spawned into
+/// [`destination_group`]. The new particles inherit all the attributes of the
+/// colliding particle, with the exception of [`Attribute::AGE`] which is reset
+/// to zero. In particular they inherit its position at the collision point, and
+/// its velocity after it bounced off the surface.
+///
+/// Like with the [`CloneModifier`], the new particles are recycled from the
+/// dead particles of the destination group, and no particle is spawned if that
+/// group is full.
+///
+/// [`count`]: crate::CollisionSubEmitter::count
+/// [`destination_group`]: crate::CollisionSubEmitter::destination_group
+/// [`CloneModifier`]: crate::CloneModifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollisionSubEmitter {
+    /// The group that the new particles are spawned into.
+    pub destination_group: u32,
+    /// The number of particles spawned per collision.
+    pub count: u32,
+}
+
+impl CollisionSubEmitter {
+    /// Create a new sub-emitter spawning `count` particles into the
+    /// `destination_group` on each collision.
+    pub fn new(destination_group: u32, count: u32) -> Self {
+        Self {
+            destination_group,
+            count,
+        }
+    }
+}
+
+/// Response of a particle to a collision, shared by all collision modifiers.
+///
+/// On collision, the normal component of the particle velocity is reflected
+/// and scaled by the [`restitution`] factor, while the tangent component is
+/// scaled down by the [`friction`] factor. The collision can optionally kill
+/// the colliding particle, and spawn new particles into another group via a
+/// [`CollisionSubEmitter`].
+///
+/// The default response produces perfectly elastic collisions without
+/// friction, and doesn't have any sub-emitter.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// let writer = ExprWriter::new();
+///
+/// // Rain drops dying on impact, and spawning 4 splashes into group #1
+/// let response = CollisionResponse::new()
+///     .with_restitution(writer.lit(0.).expr())
+///     .with_kill_on_collision(true)
+///     .with_sub_emitter(CollisionSubEmitter::new(1, 4));
+/// ```
+///
+/// [`restitution`]: crate::CollisionResponse::restitution
+/// [`friction`]: crate::CollisionResponse::friction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollisionResponse {
+    /// Restitution factor of the normal velocity on collision.
+    ///
+    /// A value of `1.0` produces a perfectly elastic bounce, while a value of
+    /// `0.0` makes particles stick to the surface. Defaults to `1.0`.
+    ///
+    /// Expression type: `f32`
+    pub restitution: Option<ExprHandle>,
+    /// Friction factor reducing the tangent velocity on collision, in `[0:1]`.
+    ///
+    /// Defaults to `0.0` (no friction).
+    ///
+    /// Expression type: `f32`
+    pub friction: Option<ExprHandle>,
+    /// If `true`, kill the particle when it collides.
+    pub kill_on_collision: bool,
+    /// Optional sub-emitter spawning particles on collision.
+    pub sub_emitter: Option<CollisionSubEmitter>,
+}
+
+impl CollisionResponse {
+    /// Create a new response producing perfectly elastic collisions without
+    /// friction, and without any sub-emitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the restitution factor of the normal velocity on collision.
+    pub fn with_restitution(mut self, restitution: impl Into<ExprHandle>) -> Self {
+        self.restitution = Some(restitution.into());
+        self
+    }
+
+    /// Set the friction factor reducing the tangent velocity on collision.
+    pub fn with_friction(mut self, friction: impl Into<ExprHandle>) -> Self {
+        self.friction = Some(friction.into());
+        self
+    }
+
+    /// Set whether particles are killed when they collide.
+    pub fn with_kill_on_collision(mut self, kill_on_collision: bool) -> Self {
+        self.kill_on_collision = kill_on_collision;
+        self
+    }
+
+    /// Set a sub-emitter spawning particles into another group on collision.
+    pub fn with_sub_emitter(mut self, sub_emitter: CollisionSubEmitter) -> Self {
+        self.sub_emitter = Some(sub_emitter);
+        self
+    }
+}
+
+/// A modifier colliding particles with an infinite plane.
+///
+/// Particles moving toward the back side of the plane, and about to cross it
+/// during the current simulation step, bounce off the plane. The normal
+/// component of their velocity is reflected and scaled by the [`restitution`]
+/// factor, while the tangent component is scaled down by the [`friction`]
+/// factor. Particles found behind the plane are projected back onto it.
+///
+/// Collisions can optionally [`kill`] the colliding particle, and spawn new
+/// particles into another group via a [`CollisionSubEmitter`], for example to
+/// emit impact sparks where rain drops hit the ground. Each collision also
+/// counts as a [`ParentEvent::Collision`] for the child effects of the effect,
+/// if any.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+/// - [`Attribute::VELOCITY`]
+///
+/// [`restitution`]: crate::CollisionResponse::restitution
+/// [`friction`]: crate::CollisionResponse::friction
+/// [`kill`]: crate::CollisionResponse::kill_on_collision
+/// [`ParentEvent::Collision`]: crate::ParentEvent::Collision
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollidePlaneModifier {
+    /// A point on the plane, in [simulation space](crate::SimulationSpace).
+    ///
+    /// Expression type: `Vec3`
+    pub origin: ExprHandle,
+    /// The plane normal, pointing toward the side particles can move freely
+    /// into. This doesn't need to be normalized.
+    ///
+    /// Expression type: `Vec3`
+    pub normal: ExprHandle,
+    /// Response of the particles to a collision.
+    pub response: CollisionResponse,
+}
+
+impl CollidePlaneModifier {
+    /// Create a new modifier from a point on the plane and the plane normal.
+    ///
+    /// The created instance produces perfectly elastic collisions without
+    /// friction, and doesn't have any sub-emitter.
+    pub fn new(origin: impl Into<ExprHandle>, normal: impl Into<ExprHandle>) -> Self {
+        Self {
+            origin: origin.into(),
+            normal: normal.into(),
+            response: default(),
+        }
+    }
+
+    /// Set the response of the particles to a collision.
+    pub fn with_response(mut self, response: CollisionResponse) -> Self {
+        self.response = response;
+        self
+    }
+}
+
+#[typetag::serde]
+impl Modifier for CollidePlaneModifier {
+    fn context(&self) -> ModifierContext {
+        ModifierContext::Update
+    }
+
+    fn attributes(&self) -> &[Attribute] {
+        &[Attribute::POSITION, Attribute::VELOCITY]
+    }
+
+    fn boxed_clone(&self) -> BoxedModifier {
+        Box::new(*self)
+    }
+
+    fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
+        let func_id = calc_func_id(self);
+        apply_collision(
+            module,
+            context,
+            &format!("collide_plane_{0:016X}", func_id),
+            &format!("collision_emit_{0:016X}", func_id),
+            &self.response,
+            &mut |m: &mut Module, ctx: &mut dyn EvalContext, attr_pos: &str| {
+                let origin = ctx.eval(m, self.origin)?;
+                let normal = ctx.eval(m, self.normal)?;
+                Ok(format!(
+                    r##"    let n = normalize({normal});
+    let dist = dot({attr_pos} - {origin}, n);
+"##
+                ))
+            },
+        )
+    }
+}
+
+/// Emit the code colliding particles with a surface.
+///
+/// The `surface` callback generates the code computing the signed distance
+/// `dist` of the particle position to the surface, negative inside the
+/// collider, and the unit normal `n` of the surface at the closest point,
+/// pointing outside.
+fn apply_collision(
+    module: &mut Module,
+    context: &mut ShaderWriter,
+    func_name: &str,
+    emit_func_name: &str,
+    response: &CollisionResponse,
+    surface: &mut dyn FnMut(&mut Module, &mut dyn EvalContext, &str) -> Result<String, ExprError>,
+) -> Result<(), ExprError> {
+    // Spawn function for the sub-emitter, if any
+    let sub_emitter_code = if let Some(sub_emitter) = &response.sub_emitter {
+        context.make_fn(
+            emit_func_name,
+            "particle: ptr<function, Particle>",
+            module,
+            &mut |_m: &mut Module, ctx: &mut dyn EvalContext| -> Result<String, ExprError> {
+                let age_reset_code = if ctx.particle_layout().contains(Attribute::AGE) {
+                    format!("new_particle.{} = 0.0;", Attribute::AGE.name())
+                } else {
+                    "".to_owned()
+                };
+
+                Ok(format!(
+                    r##"    let base_index = particle_groups[particle_group_base + {dest}u].indirect_index;
+
+    // Recycle a dead particle.
+    let dead_index = atomicSub(&render_group_indirect[render_group_base + {dest}u].dead_count, 1u) - 1u;
+    // Undo the atomic op and return if the destination group is full.
+    if (dead_index >= 0xF0000000) {{
+        atomicAdd(&render_group_indirect[render_group_base + {dest}u].dead_count, 1u);
+        return;
+    }}
+    let new_index = indirect_buffer.indices[3u * (base_index + dead_index) + 2u];
+
+    // Inherit all attributes from the colliding particle.
+    var new_particle = *particle;
+    {age_reset_code}
+    particle_buffer.particles[new_index] = new_particle;
+
+    // Mark it as alive.
+    atomicAdd(&render_group_indirect[render_group_base + {dest}u].alive_count, 1u);
+
+    // Add an instance.
+    let indirect_index = atomicAdd(&render_group_indirect[render_group_base + {dest}u].instance_count, 1u);
+    indirect_buffer.indices[3u * (base_index + indirect_index) + ping] = new_index;
+"##,
+                    dest = sub_emitter.destination_group,
+                ))
+            },
+        )?;
+
+        format!(
+            r##"
+        for (var i = 0u; i < {count}u; i += 1u) {{
+            {emit_func_name}(particle);
+        }}"##,
+            count = sub_emitter.count,
+        )
+    } else {
+        String::new()
+    };
+
+    let kill_code = if response.kill_on_collision {
+        "\n        *is_alive = false;"
+    } else {
+        ""
+    };
+
+    context.make_fn(
+        func_name,
+        "particle: ptr<function, Particle>, is_alive: ptr<function, bool>",
+        module,
+        &mut |m: &mut Module, ctx: &mut dyn EvalContext| -> Result<String, ExprError> {
+            let attr_pos = format!("(*particle).{}", Attribute::POSITION.name());
+            let attr_vel = format!("(*particle).{}", Attribute::VELOCITY.name());
+
+            let surface_code = surface(m, ctx, &attr_pos)?;
+            let restitution = if let Some(restitution) = response.restitution {
+                ctx.eval(m, restitution)?
+            } else {
+                "1.0".to_string()
+            };
+            let friction = if let Some(friction) = response.friction {
+                ctx.eval(m, friction)?
+            } else {
+                "0.0".to_string()
+            };
+
+            Ok(format!(
+                r##"{surface_code}    let normal_speed = dot({attr_vel}, n);
+    // Only collide particles moving toward the surface and about to cross it.
+    if (normal_speed >= 0.0 || dist + normal_speed * sim_params.delta_time >= 0.0) {{
+        return;
+    }}
+    // Project particles inside the collider back onto its surface.
+    {attr_pos} -= n * min(dist, 0.0);
+    // Reflect the normal velocity, and apply friction to the tangent one.
+    let normal_vel = n * normal_speed;
+    let tangent_vel = {attr_vel} - normal_vel;
+    {attr_vel} = tangent_vel * (1.0 - {friction}) - normal_vel * {restitution};
+    // Count a collision event for child effects.
+    emit_parent_event({collision_event}u);{sub_emitter_code}{kill_code}
+"##,
+                collision_event = ParentEvent::Collision.counter_index(),
+            ))
+        },
+    )?;
+
+    context.main_code += &format!("{}(&particle, &is_alive);\n", func_name);
+
+    Ok(())
+}
+
+/// A modifier colliding particles with the [`StaticColliders`].
+///
+/// Particles moving toward the closest collider of the list, and about to
+/// enter it during the current simulation step, bounce off its surface. The
+/// collision response is the same as the one of the [`CollidePlaneModifier`],
+/// and supports the same [`restitution`], [`friction`], [`kill`], and
+/// sub-emitter settings.
+///
+/// Particles found inside a collider are pushed back onto its surface. All the
+/// colliders are tested for each particle, so the list should be kept to the
+/// few dozens of colliders surrounding the camera. The colliders are in world
+/// space, so this modifier generally requires a [`SimulationSpace::Global`]
+/// effect.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// let writer = ExprWriter::new();
+///
+/// // Sparks bouncing off the level colliders, losing half their speed
+/// let collide = CollideStaticModifier::new()
+///     .with_response(CollisionResponse::new().with_restitution(writer.lit(0.5).expr()));
+/// ```
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+/// - [`Attribute::VELOCITY`]
+///
+/// [`StaticColliders`]: crate::StaticColliders
+/// [`restitution`]: crate::CollisionResponse::restitution
+/// [`friction`]: crate::CollisionResponse::friction
+/// [`kill`]: crate::CollisionResponse::kill_on_collision
+/// [`SimulationSpace::Global`]: crate::SimulationSpace::Global
+#[derive(Debug, Default, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollideStaticModifier {
+    /// Response of the particles to a collision.
+    pub response: CollisionResponse,
+}
+
+impl CollideStaticModifier {
+    /// Create a new modifier.
+    ///
+    /// The created instance produces perfectly elastic collisions without
+    /// friction, and doesn't have any sub-emitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the response of the particles to a collision.
+    pub fn with_response(mut self, response: CollisionResponse) -> Self {
+        self.response = response;
+        self
+    }
+}
+
+#[typetag::serde]
+impl Modifier for CollideStaticModifier {
+    fn context(&self) -> ModifierContext {
+        ModifierContext::Update
+    }
+
+    fn attributes(&self) -> &[Attribute] {
+        &[Attribute::POSITION, Attribute::VELOCITY]
+    }
+
+    fn boxed_clone(&self) -> BoxedModifier {
+        Box::new(*self)
+    }
+
+    fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
+        let func_id = calc_func_id(self);
+        apply_collision(
+            module,
+            context,
+            &format!("collide_static_{0:016X}", func_id),
+            &format!("collision_emit_{0:016X}", func_id),
+            &self.response,
+            &mut |_m: &mut Module, _ctx: &mut dyn EvalContext, attr_pos: &str| {
+                Ok(format!(
+                    r##"    let hit = closest_static_collider({attr_pos});
+    let n = hit.xyz;
+    let dist = hit.w;
+"##
+                ))
+            },
+        )
+    }
+}
+
+/// Occupancy grid the [`CollideVoxelModifier`] collides particles with.
+///
+/// The grid is a 3D image with the [`TextureFormat::R8Uint`] format, where
+/// each texel is a cell of the grid, filled if non-zero. This is typically
+/// built from the block data of the chunks of a voxel game surrounding the
+/// camera. The placement and size of the cells are defined per effect, by the
+/// [`origin`] and [`cell_size`] of the modifier.
+///
+/// The grid is shared by all effects. Update the image asset to reflect
+/// changes to the world; the new content is uploaded to GPU like any other
+/// image. When no grid is set, or the image is not loaded yet, all cells are
+/// empty and particles don't collide.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::*}};
+/// # use bevy_hanabi::*;
+/// fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+///     // A 16x16x16 chunk whose 4 first layers along Z are filled. The cell
+///     // (x, y, z) is stored at index x + 16 * (y + 16 * z).
+///     let mut data = vec![0u8; 16 * 16 * 16];
+///     data[..16 * 16 * 4].fill(1);
+///     let image = Image::new(
+///         Extent3d {
+///             width: 16,
+///             height: 16,
+///             depth_or_array_layers: 16,
+///         },
+///         TextureDimension::D3,
+///         data,
+///         TextureFormat::R8Uint,
+///         RenderAssetUsages::RENDER_WORLD,
+///     );
+///     commands.insert_resource(VoxelGrid {
+///         image: Some(images.add(image)),
+///     });
+/// }
+/// ```
+///
+/// [`TextureFormat::R8Uint`]: bevy::render::render_resource::TextureFormat::R8Uint
+/// [`origin`]: crate::CollideVoxelModifier::origin
+/// [`cell_size`]: crate::CollideVoxelModifier::cell_size
+#[derive(Debug, Default, Clone, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct VoxelGrid {
+    /// The occupancy image, or `None` to disable voxel collisions.
+    pub image: Option<Handle<Image>>,
+}
+
+/// A modifier colliding particles with the filled cells of the [`VoxelGrid`].
+///
+/// The grid cells are axis-aligned cubes of [`cell_size`], the first cell
+/// starting at [`origin`]. Particles about to enter a filled cell during the
+/// current simulation step bounce off the face of the cell they cross. The
+/// collision response is the same as the one of the [`CollidePlaneModifier`],
+/// and supports the same [`restitution`], [`friction`], [`kill`], and
+/// sub-emitter settings. Cells outside the grid are empty.
+///
+/// Only the cells adjacent to the current cell of a particle are tested, so
+/// particles moving more than one cell per simulation step can tunnel through
+/// thin walls.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+/// - [`Attribute::VELOCITY`]
+///
+/// [`cell_size`]: crate::CollideVoxelModifier::cell_size
+/// [`origin`]: crate::CollideVoxelModifier::origin
+/// [`restitution`]: crate::CollisionResponse::restitution
+/// [`friction`]: crate::CollisionResponse::friction
+/// [`kill`]: crate::CollisionResponse::kill_on_collision
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollideVoxelModifier {
+    /// The position of the minimum corner of the grid, in [simulation
+    /// space](crate::SimulationSpace).
+    ///
+    /// Expression type: `Vec3`
+    pub origin: ExprHandle,
+    /// The size of a grid cell.
+    ///
+    /// Expression type: `f32`
+    pub cell_size: ExprHandle,
+    /// Response of the particles to a collision.
+    pub response: CollisionResponse,
+}
+
+impl CollideVoxelModifier {
+    /// Create a new modifier from the origin of the grid and its cell size.
+    ///
+    /// The created instance produces perfectly elastic collisions without
+    /// friction, and doesn't have any sub-emitter.
+    pub fn new(origin: impl Into<ExprHandle>, cell_size: impl Into<ExprHandle>) -> Self {
+        Self {
+            origin: origin.into(),
+            cell_size: cell_size.into(),
+            response: default(),
+        }
+    }
+
+    /// Set the response of the particles to a collision.
+    pub fn with_response(mut self, response: CollisionResponse) -> Self {
+        self.response = response;
+        self
+    }
+}
+
+#[typetag::serde]
+impl Modifier for CollideVoxelModifier {
+    fn context(&self) -> ModifierContext {
+        ModifierContext::Update
+    }
+
+    fn attributes(&self) -> &[Attribute] {
+        &[Attribute::POSITION, Attribute::VELOCITY]
+    }
+
+    fn boxed_clone(&self) -> BoxedModifier {
+        Box::new(*self)
+    }
+
+    fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
+        let func_id = calc_func_id(self);
+        apply_collision(
+            module,
+            context,
+            &format!("collide_voxel_{0:016X}", func_id),
+            &format!("collision_emit_{0:016X}", func_id),
+            &self.response,
+            &mut |m: &mut Module, ctx: &mut dyn EvalContext, attr_pos: &str| {
+                let origin = ctx.eval(m, self.origin)?;
+                let cell_size = ctx.eval(m, self.cell_size)?;
+                // Find the first face crossed into a filled neighbor cell, if any. The
+                // distance is to the plane of that face, so is negative only after
+                // crossing it; otherwise it's large enough to never collide.
+                Ok(format!(
+                    r##"    let grid_origin = {origin};
+    let cell_size = {cell_size};
+    let vel = (*particle).{vel};
+    let cell = vec3<i32>(floor(({attr_pos} - grid_origin) / cell_size));
+    let next_cell = vec3<i32>(floor(({attr_pos} + vel * sim_params.delta_time - grid_origin) / cell_size));
+    var n = vec3<f32>(0.0, 1.0, 0.0);
+    var dist = 3.4e38;
+    for (var axis = 0; axis < 3; axis += 1) {{
+        if (next_cell[axis] == cell[axis]) {{
+            continue;
+        }}
+        let dir = select(-1, 1, next_cell[axis] > cell[axis]);
+        var neighbor = cell;
+        neighbor[axis] += dir;
+        if (is_voxel_filled(neighbor)) {{
+            let face = grid_origin[axis] + f32(cell[axis] + max(dir, 0)) * cell_size;
+            n = vec3<f32>(0.0);
+            n[axis] = -f32(dir);
+            dist = (face - {attr_pos}[axis]) * f32(dir);
+            break;
+        }}
+    }}
+"##,
+                    vel = Attribute::VELOCITY.name(),
+                ))
+            },
+        )
+    }
+}
+
+/// A modifier colliding particles with the triangles of the
+/// [`ParticleCollider`] meshes.
+///
+/// Each particle casts a ray along the path it travels during the current
+/// simulation step, against a bounding volume hierarchy (BVH) of the
+/// triangles of all the colliders. If the ray hits a triangle, the particle
+/// bounces off it. Triangles are two-sided. The collision response is the
+/// same as the one of the [`CollidePlaneModifier`], and supports the same
+/// [`restitution`], [`friction`], [`kill`], and sub-emitter settings. Setting
+/// both the restitution to `0.0` and the friction to `1.0` makes particles
+/// stick to the surface they hit, for example for blood splatters.
+///
+/// Raycasting is precise, but much more expensive than the other collision
+/// modifiers, so this is best used on effects with a low particle count, like
+/// bullet impacts. The colliders are in world space, so this modifier
+/// generally requires a [`SimulationSpace::Global`] effect.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+/// - [`Attribute::VELOCITY`]
+///
+/// [`ParticleCollider`]: crate::ParticleCollider
+/// [`restitution`]: crate::CollisionResponse::restitution
+/// [`friction`]: crate::CollisionResponse::friction
+/// [`kill`]: crate::CollisionResponse::kill_on_collision
+/// [`SimulationSpace::Global`]: crate::SimulationSpace::Global
+#[derive(Debug, Default, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct CollideMeshModifier {
+    /// Response of the particles to a collision.
+    pub response: CollisionResponse,
+}
+
+impl CollideMeshModifier {
+    /// Create a new modifier.
+    ///
+    /// The created instance produces perfectly elastic collisions without
+    /// friction, and doesn't have any sub-emitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the response of the particles to a collision.
+    pub fn with_response(mut self, response: CollisionResponse) -> Self {
+        self.response = response;
+        self
+    }
+}
+
+#[typetag::serde]
+impl Modifier for CollideMeshModifier {
+    fn context(&self) -> ModifierContext {
+        ModifierContext::Update
+    }
+
+    fn attributes(&self) -> &[Attribute] {
+        &[Attribute::POSITION, Attribute::VELOCITY]
+    }
+
+    fn boxed_clone(&self) -> BoxedModifier {
+        Box::new(*self)
+    }
+
+    fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
+        let func_id = calc_func_id(self);
+        apply_collision(
+            module,
+            context,
+            &format!("collide_mesh_{0:016X}", func_id),
+            &format!("collision_emit_{0:016X}", func_id),
+            &self.response,
+            &mut |_m: &mut Module, _ctx: &mut dyn EvalContext, attr_pos: &str| {
+                // The distance is to the plane of the hit triangle, so is negative
+                // only after crossing it.
+                Ok(format!(
+                    r##"    let delta = (*particle).{vel} * sim_params.delta_time;
+    let hit = raycast_bvh({attr_pos}, delta);
+    var n = vec3<f32>(0.0, 1.0, 0.0);
+    var dist = 3.4e38;
+    if (hit.w <= 1.0) {{
+        // Orient the triangle normal toward the particle
+        n = select(hit.xyz, -hit.xyz, dot(hit.xyz, delta) > 0.0);
+        dist = -hit.w * dot(delta, n);
+    }}
+"##,
+                    vel = Attribute::VELOCITY.name(),
+                ))
+            },
+        )
+    }
+}
+
+/// A modifier absorbing the particles entering a volume, and accumulating
+/// their momentum to push some physics object.
+///
+/// Particles inside the axis-aligned box described by [`center`] and
+/// [`half_size`] are killed, and their momentum, that is their velocity
+/// multiplied by their [`mass`], is accumulated on GPU. The total momentum
+/// absorbed each frame is read back asynchronously, and stored into the
+/// [`ParticleImpulse`] component of the effect instance, from which a game can
+/// push a rigid body of its physics world. This allows for example a dense
+/// water jet to push a crate.
+///
+/// The volume is expressed in [simulation space](crate::SimulationSpace), and
+/// generally follows the object receiving the force through some properties.
+/// Only one receiver is supported per effect; all the [`ForceReceiverModifier`]
+/// of an effect accumulate into the same [`ParticleImpulse`].
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+/// - [`Attribute::VELOCITY`]
+///
+/// [`center`]: crate::ForceReceiverModifier::center
+/// [`half_size`]: crate::ForceReceiverModifier::half_size
+/// [`mass`]: crate::ForceReceiverModifier::mass
+/// [`ParticleImpulse`]: crate::ParticleImpulse
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct ForceReceiverModifier {
+    /// The center of the receiver volume.
+    ///
+    /// Expression type: `Vec3`
+    pub center: ExprHandle,
+    /// The half size of the receiver volume, along each axis.
+    ///
+    /// Expression type: `Vec3`
+    pub half_size: ExprHandle,
+    /// The mass of each particle. Defaults to `1.0`.
+    ///
+    /// Expression type: `f32`
+    pub mass: Option<ExprHandle>,
+}
+
+impl ForceReceiverModifier {
+    /// Create a new modifier from the center and half size of the receiver
+    /// volume.
+    pub fn new(center: impl Into<ExprHandle>, half_size: impl Into<ExprHandle>) -> Self {
+        Self {
+            center: center.into(),
+            half_size: half_size.into(),
+            mass: None,
+        }
+    }
+
+    /// Set the mass of each particle.
+    pub fn with_mass(mut self, mass: impl Into<ExprHandle>) -> Self {
+        self.mass = Some(mass.into());
+        self
+    }
+}
+
+#[typetag::serde]
+impl Modifier for ForceReceiverModifier {
+    fn context(&self) -> ModifierContext {
+        ModifierContext::Update
+    }
+
+    fn attributes(&self) -> &[Attribute] {
+        &[Attribute::POSITION, Attribute::VELOCITY]
+    }
+
+    fn boxed_clone(&self) -> BoxedModifier {
+        Box::new(*self)
+    }
+
+    fn apply(&self, module: &mut Module, context: &mut ShaderWriter) -> Result<(), ExprError> {
+        let func_name = format!("force_receiver_{0:016X}", calc_func_id(self));
+        context.make_fn(
+            &func_name,
+            "particle: ptr<function, Particle>, is_alive: ptr<function, bool>",
+            module,
+            &mut |m: &mut Module, ctx: &mut dyn EvalContext| -> Result<String, ExprError> {
+                let center = ctx.eval(m, self.center)?;
+                let half_size = ctx.eval(m, self.half_size)?;
+                let mass = if let Some(mass) = self.mass {
+                    ctx.eval(m, mass)?
+                } else {
+                    "1.0".to_string()
+                };
+
+                Ok(format!(
+                    r##"    if (any(abs((*particle).{pos} - {center}) > {half_size})) {{
+        return;
+    }}
+    // Absorb the particle, and transfer its momentum to the receiver.
+    accumulate_impulse((*particle).{vel} * {mass});
+    *is_alive = false;
+"##,
+                    pos = Attribute::POSITION.name(),
+                    vel = Attribute::VELOCITY.name(),
+                ))
+            },
+        )?;
+
+        context.main_code += &format!("{}(&particle, &is_alive);\n", func_name);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParticleLayout, PropertyLayout};
+
+    use super::*;
+
+    #[test]
+    fn mod_collide_plane() {
+        let mut module = Module::default();
+        let origin = module.lit(Vec3::ZERO);
+        let normal = module.lit(Vec3::Y);
+        let modifier = CollidePlaneModifier::new(origin, normal).with_response(
+            CollisionResponse::new()
+                .with_kill_on_collision(true)
+                .with_sub_emitter(CollisionSubEmitter::new(1, 4)),
+        );
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("collide_plane_"));
+        assert!(context.extra_code.contains("*is_alive = false"));
+        assert!(context
+            .extra_code
+            .contains("render_group_indirect[render_group_base + 1u]"));
+        assert!(context.extra_code.contains("i < 4u"));
+    }
+
+    #[test]
+    fn mod_collide_static() {
+        let mut module = Module::default();
+        let modifier = CollideStaticModifier::new()
+            .with_response(CollisionResponse::new().with_kill_on_collision(true));
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("collide_static_"));
+        assert!(context.extra_code.contains("closest_static_collider("));
+        assert!(context.extra_code.contains("*is_alive = false"));
+    }
+
+    #[test]
+    fn mod_collide_voxel() {
+        let mut module = Module::default();
+        let origin = module.lit(Vec3::ZERO);
+        let cell_size = module.lit(0.5);
+        let modifier = CollideVoxelModifier::new(origin, cell_size)
+            .with_response(CollisionResponse::new().with_kill_on_collision(true));
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("collide_voxel_"));
+        assert!(context.extra_code.contains("is_voxel_filled(neighbor)"));
+        assert!(context.extra_code.contains("*is_alive = false"));
+    }
+
+    #[test]
+    fn mod_collide_mesh() {
+        let mut module = Module::default();
+        let zero = module.lit(0.);
+        let modifier = CollideMeshModifier::new().with_response(
+            CollisionResponse::new()
+                .with_restitution(zero)
+                .with_sub_emitter(CollisionSubEmitter::new(1, 2)),
+        );
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("collide_mesh_"));
+        assert!(context.extra_code.contains("raycast_bvh("));
+        assert!(context
+            .extra_code
+            .contains("render_group_indirect[render_group_base + 1u]"));
+    }
+
+    #[test]
+    fn mod_force_receiver() {
+        let mut module = Module::default();
+        let center = module.lit(Vec3::ZERO);
+        let half_size = module.lit(Vec3::ONE);
+        let mass = module.lit(0.1);
+        let modifier = ForceReceiverModifier::new(center, half_size).with_mass(mass);
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::POSITION)
+            .append(Attribute::VELOCITY)
+            .build();
+        let mut context =
+            ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+        assert!(modifier.apply(&mut module, &mut context).is_ok());
+
+        assert!(context.main_code.contains("force_receiver_"));
+        assert!(context.extra_code.contains("accumulate_impulse("));
+        assert!(context.extra_code.contains("*is_alive = false"));
+    }
+}
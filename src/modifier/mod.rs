@@ -37,21 +37,25 @@ use std::{
 pub mod accel;
 pub mod attr;
 pub mod clone;
+pub mod collision;
 pub mod force;
 pub mod kill;
 pub mod output;
 pub mod position;
 pub mod ribbon;
+pub mod trail;
 pub mod velocity;
 
 pub use accel::*;
 pub use attr::*;
 pub use clone::*;
+pub use collision::*;
 pub use force::*;
 pub use kill::*;
 pub use output::*;
 pub use position::*;
 pub use ribbon::*;
+pub use trail::*;
 pub use velocity::*;
 
 use crate::{
@@ -256,6 +260,8 @@ pub struct ShaderWriter<'a> {
     expr_cache: HashMap<ExprHandle, String>,
     /// Is the attribute struct a pointer?
     is_attribute_pointer: bool,
+    /// Names of the particle groups of the effect, by group index.
+    group_names: &'a [String],
 }
 
 impl<'a> ShaderWriter<'a> {
@@ -274,6 +280,7 @@ impl<'a> ShaderWriter<'a> {
             var_counter: 0,
             expr_cache: Default::default(),
             is_attribute_pointer: false,
+            group_names: &[],
         }
     }
 
@@ -282,6 +289,31 @@ impl<'a> ShaderWriter<'a> {
         self.is_attribute_pointer = true;
         self
     }
+
+    /// Set the names of the particle groups of the effect, by group index.
+    pub fn with_group_names(mut self, group_names: &'a [String]) -> Self {
+        self.group_names = group_names;
+        self
+    }
+
+    /// Get the index of the particle group with the given name.
+    ///
+    /// Returns an [`ExprError::UnknownGroupError`] if the effect doesn't have
+    /// any group with that name.
+    pub fn group_index(&self, name: &str) -> Result<u32, ExprError> {
+        self.group_names
+            .iter()
+            .position(|group_name| group_name == name)
+            .map(|index| index as u32)
+            .ok_or_else(|| ExprError::UnknownGroupError(name.to_string()))
+    }
+
+    /// Forget the expressions evaluated so far, so the next modifiers evaluate
+    /// them again instead of referencing local variables which may be out of
+    /// scope.
+    pub(crate) fn clear_expr_cache(&mut self) {
+        self.expr_cache.clear();
+    }
 }
 
 impl<'a> EvalContext for ShaderWriter<'a> {
@@ -377,14 +409,24 @@ pub struct RenderContext<'a> {
     /// WGSL code describing how to modulate the base color of the particle with
     /// the image texture sample, if any.
     pub image_sample_mapping_code: String,
+    /// LUT texture defining the particle color over its lifetime.
+    pub color_lut: Option<Handle<Image>>,
     /// Flipbook sprite sheet grid size, if any.
     pub sprite_grid_size: Option<UVec2>,
+    /// Z coordinates of the 2D layers the particles are split into, sorted in
+    /// increasing order, or empty if all particles share the layer of the
+    /// effect instance.
+    pub z_layers_2d: Vec<f32>,
     /// Color gradients.
     pub gradients: HashMap<u64, Gradient<Vec4>>,
     /// Size gradients.
     pub size_gradients: HashMap<u64, Gradient<Vec2>>,
     /// Needs uv
     pub needs_uv: bool,
+    /// The particles are lit by the [`Lights2d`].
+    ///
+    /// [`Lights2d`]: crate::Lights2d
+    pub lighting_2d: bool,
     /// Counter for unique variable names.
     var_counter: u32,
     /// Cache of evaluated expressions.
@@ -404,10 +446,13 @@ impl<'a> RenderContext<'a> {
             render_extra: String::new(),
             particle_texture: None,
             image_sample_mapping_code: String::new(),
+            color_lut: None,
             sprite_grid_size: None,
+            z_layers_2d: vec![],
             gradients: HashMap::new(),
             size_gradients: HashMap::new(),
             needs_uv: false,
+            lighting_2d: false,
             var_counter: 0,
             expr_cache: Default::default(),
             is_attribute_pointer: false,
@@ -422,6 +467,11 @@ impl<'a> RenderContext<'a> {
         self.needs_uv = true;
     }
 
+    /// Set the LUT texture defining the particle color over its lifetime.
+    fn set_color_lut(&mut self, handle: Handle<Image>) {
+        self.color_lut = Some(handle);
+    }
+
     /// Mark the rendering shader as needing UVs.
     fn set_needs_uv(&mut self) {
         self.needs_uv = true;
@@ -598,7 +648,9 @@ mod tests {
     use bevy::prelude::*;
     use naga::front::wgsl::Frontend;
 
-    use crate::{BuiltInOperator, ExprWriter, ScalarType};
+    use crate::{
+        BuiltInOperator, Curve, ExprWriter, Gradient, GradientInterpolation, HdrColor, ScalarType,
+    };
 
     use super::*;
 
@@ -782,6 +834,14 @@ fn main() {{
             &ConformToSphereModifier::new(origin, one, one, one, one),
             &LinearDragModifier::new(writer.lit(3.5).expr()),
             &KillAabbModifier::new(writer.lit(Vec3::ZERO).expr(), writer.lit(Vec3::ONE).expr()),
+            &CollidePlaneModifier::new(origin, y_axis)
+                .with_response(CollisionResponse::new().with_kill_on_collision(true)),
+            &CollideStaticModifier::new()
+                .with_response(CollisionResponse::new().with_restitution(one)),
+            &CollideVoxelModifier::new(origin, one)
+                .with_response(CollisionResponse::new().with_friction(one)),
+            &CollideMeshModifier::new()
+                .with_response(CollisionResponse::new().with_restitution(one)),
             &SetPositionCircleModifier {
                 center,
                 axis,
@@ -868,6 +928,21 @@ fn proj(u: vec3<f32>, v: vec3<f32>) -> vec3<f32> {{
     return dot(v, u) / dot(u,u) * u;
 }}
 
+fn emit_parent_event(kind: u32) {{
+}}
+
+fn is_voxel_filled(cell: vec3<i32>) -> bool {{
+    return false;
+}}
+
+fn raycast_bvh(origin: vec3<f32>, delta: vec3<f32>) -> vec4<f32> {{
+    return vec4<f32>(0.0, 0.0, 0.0, 2.0);
+}}
+
+fn closest_static_collider(position: vec3<f32>) -> vec4<f32> {{
+    return vec4<f32>(0.0, 0.0, 0.0, 3.4e38);
+}}
+
 {update_extra}
 
 @group(0) @binding(0) var<uniform> sim_params : SimParams;
@@ -902,7 +977,31 @@ fn main() {{
         let modifiers: &[&dyn RenderModifier] = &[
             &ParticleTextureModifier::default(),
             &ColorOverLifetimeModifier::default(),
+            &ColorOverLifetimeTextureModifier::default(),
+            &HdrColorOverLifetimeModifier {
+                gradient: Gradient::new()
+                    .with_key(0., HdrColor::new(Color::RED))
+                    .with_interpolated_key(
+                        0.3,
+                        HdrColor::new(Color::RED).with_intensity(4.),
+                        GradientInterpolation::Smooth,
+                    )
+                    .with_interpolated_key(
+                        0.6,
+                        HdrColor::new(Color::BLUE),
+                        GradientInterpolation::Constant,
+                    )
+                    .with_key(1., HdrColor::new(Color::BLUE).with_intensity(0.)),
+            },
             &SizeOverLifetimeModifier::default(),
+            &SizeOverLifetimeCurveModifier {
+                curve: Curve::linear(Vec2::ZERO, Vec2::ONE),
+            },
+            &TextureAtlasModifier::from_rects(
+                Vec2::new(64., 32.),
+                [Rect::new(0., 0., 32., 32.), Rect::new(32., 0., 64., 16.)],
+            ),
+            &PixelSnapModifier::new(4.),
             &OrientModifier::new(OrientMode::ParallelCameraDepthPlane),
             &OrientModifier::new(OrientMode::FaceCameraPosition),
             &OrientModifier::new(OrientMode::AlongVelocity),
@@ -938,6 +1037,10 @@ struct View {{
     height: f32,
 }};
 
+struct BillboardView {{
+    view: mat4x4<f32>,
+}};
+
 fn frand() -> f32 {{ return 0.0; }}
 fn get_camera_position_effect_space() -> vec3<f32> {{ return vec3<f32>(); }}
 fn get_camera_rotation_effect_space() -> mat3x3<f32> {{ return mat3x3<f32>(); }}
@@ -954,6 +1057,9 @@ struct VertexOutput {{
 }};
 
 @group(0) @binding(0) var<uniform> view: View;
+@group(0) @binding(3) var<uniform> billboard_view: BillboardView;
+@group(3) @binding(0) var color_lut_texture: texture_2d<f32>;
+@group(3) @binding(1) var color_lut_sampler: sampler;
 
 {render_extra}
 
@@ -967,6 +1073,7 @@ fn main() {{
     var axis_y = vec3<f32>(0.0, 1.0, 0.0);
     var axis_z = vec3<f32>(0.0, 0.0, 1.0);
     var color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    var uv = vec2<f32>(0.0, 0.0);
 {vertex_code}
     var out: VertexOutput;
     return out;
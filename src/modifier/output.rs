@@ -5,9 +5,10 @@ use serde::{Deserialize, Serialize};
 use std::hash::Hash;
 
 use crate::{
-    impl_mod_render, Attribute, BoxedModifier, CpuValue, EvalContext, ExprError, ExprHandle,
-    Gradient, Modifier, ModifierContext, Module, RenderContext, RenderModifier, ShaderCode,
-    ShaderWriter, ToWgslString,
+    calc_func_id, impl_mod_render, Attribute, BoxedModifier, BuiltInOperator, ColorGradient,
+    CpuValue, Curve, EvalContext, ExprError, ExprHandle, Gradient, HdrColor, Modifier,
+    ModifierContext, Module, RenderContext, RenderModifier, ScalarType, SetAttributeModifier,
+    ShaderCode, ShaderWriter, SizeCurve, ToWgslString,
 };
 
 /// Mapping of the sample read from a texture image to the base particle color.
@@ -174,6 +175,300 @@ impl RenderModifier for ColorOverLifetimeModifier {
     }
 }
 
+/// A modifier modulating each particle's color over its lifetime with a shared
+/// [`ColorGradient`] asset.
+///
+/// This is similar to [`ColorOverLifetimeModifier`], but references the
+/// gradient by handle instead of owning a copy of it. Editing the gradient
+/// asset updates all the effects using it, including the ones already
+/// spawned. Until the asset is loaded, the modifier has no effect.
+///
+/// Like textures, the handle is not serialized with the effect.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+///
+/// [`ColorGradient`]: crate::ColorGradient
+#[derive(Debug, Default, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct ColorOverLifetimeAssetModifier {
+    /// The shared color gradient defining the particle color based on its
+    /// lifetime.
+    #[serde(skip)]
+    pub gradient: Handle<ColorGradient>,
+    /// Content of the gradient asset, copied by [`update_shared_assets()`].
+    ///
+    /// [`update_shared_assets()`]: crate::update_shared_assets
+    #[serde(skip)]
+    #[reflect(ignore)]
+    pub(crate) resolved: Gradient<Vec4>,
+}
+
+impl ColorOverLifetimeAssetModifier {
+    /// Create a new instance of this modifier referencing a shared gradient.
+    pub fn new(gradient: Handle<ColorGradient>) -> Self {
+        Self {
+            gradient,
+            resolved: Gradient::new(),
+        }
+    }
+}
+
+impl_mod_render!(
+    ColorOverLifetimeAssetModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for ColorOverLifetimeAssetModifier {
+    fn apply_render(&self, module: &mut Module, context: &mut RenderContext) {
+        if !self.resolved.is_empty() {
+            ColorOverLifetimeModifier {
+                gradient: self.resolved.clone(),
+            }
+            .apply_render(module, context);
+        }
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// A modifier modulating each particle's color over its lifetime with an HDR
+/// gradient curve.
+///
+/// This is similar to [`ColorOverLifetimeModifier`], but the keys of the
+/// gradient are [`HdrColor`] values, made of a color and a separate intensity
+/// multiplier. Colors and intensities are interpolated independently, so a
+/// gradient can for example keep a constant hue while its brightness ramps up
+/// for bloom, or shift hue at a constant brightness. The final color is the
+/// linear RGB color scaled by the intensity, with the alpha of the color.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+///
+/// [`HdrColor`]: crate::HdrColor
+#[derive(Debug, Default, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct HdrColorOverLifetimeModifier {
+    /// The HDR color gradient defining the particle color based on its
+    /// lifetime.
+    pub gradient: Gradient<HdrColor>,
+}
+
+impl_mod_render!(
+    HdrColorOverLifetimeModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for HdrColorOverLifetimeModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        let func_name = format!("hdr_color_gradient_{0:016X}", calc_func_id(&self.gradient));
+        context.render_extra += &format!(
+            r#"fn {0}(key: f32) -> vec4<f32> {{
+    {1}
+}}
+
+"#,
+            func_name,
+            self.gradient.to_shader_code("key")
+        );
+
+        context.vertex_code += &format!(
+            "color = {0}(particle.{1} / particle.{2});\n",
+            func_name,
+            Attribute::AGE.name(),
+            Attribute::LIFETIME.name()
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// A modifier modulating each particle's color over its lifetime with a random
+/// blend between two gradients.
+///
+/// Each particle samples both gradients at its current age ratio, and blends
+/// the two colors with a per-particle factor in \[0:1\]. This gives natural
+/// per-particle color variations, like the "random between two gradients"
+/// mode of other VFX tools, without authoring multiple effects.
+///
+/// The `blend` expression is evaluated in the render shader, each frame. To
+/// keep the color of a particle stable over its lifetime, the blend factor is
+/// generally drawn randomly once when the particle spawns, stored into an
+/// attribute with a [`SetAttributeModifier`], then read back by the `blend`
+/// expression.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::math::Vec4;
+/// # use bevy_hanabi::*;
+/// let writer = ExprWriter::new();
+///
+/// // Draw a random blend factor once per particle
+/// let init_blend =
+///     SetAttributeModifier::new(Attribute::F32_0, writer.rand(ScalarType::Float).expr());
+///
+/// // Blend between a red-ish and a yellow-ish fire ramp
+/// let fire_red = Gradient::linear(Vec4::new(1., 0.2, 0., 1.), Vec4::new(0.2, 0., 0., 0.));
+/// let fire_yellow = Gradient::linear(Vec4::new(1., 0.8, 0., 1.), Vec4::new(0.4, 0.2, 0., 0.));
+/// let color = ColorOverLifetimeRandomModifier::new(
+///     fire_red,
+///     fire_yellow,
+///     writer.attr(Attribute::F32_0).expr(),
+/// );
+///
+/// let module = writer.finish();
+/// ```
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+///
+/// as well as any attribute read by the `blend` expression.
+///
+/// [`SetAttributeModifier`]: crate::SetAttributeModifier
+#[derive(Debug, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct ColorOverLifetimeRandomModifier {
+    /// The color gradient used when the blend factor is `0.0`.
+    pub gradient0: Gradient<Vec4>,
+    /// The color gradient used when the blend factor is `1.0`.
+    pub gradient1: Gradient<Vec4>,
+    /// The per-particle blend factor between the two gradients, as a single
+    /// `f32` value clamped to \[0:1\].
+    pub blend: ExprHandle,
+}
+
+impl ColorOverLifetimeRandomModifier {
+    /// Create a new instance of this modifier.
+    pub fn new(gradient0: Gradient<Vec4>, gradient1: Gradient<Vec4>, blend: ExprHandle) -> Self {
+        Self {
+            gradient0,
+            gradient1,
+            blend,
+        }
+    }
+}
+
+impl_mod_render!(
+    ColorOverLifetimeRandomModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for ColorOverLifetimeRandomModifier {
+    fn apply_render(&self, module: &mut Module, context: &mut RenderContext) {
+        let blend = context.eval(module, self.blend).unwrap();
+
+        let mut func_names = vec![];
+        for gradient in [&self.gradient0, &self.gradient1] {
+            let func_name = context.add_color_gradient(gradient.clone());
+            // Both gradients may be identical; only define the function once
+            if !func_names.contains(&func_name) {
+                context.render_extra += &format!(
+                    r#"fn {0}(key: f32) -> vec4<f32> {{
+    {1}
+}}
+
+"#,
+                    func_name,
+                    gradient.to_shader_code("key")
+                );
+            }
+            func_names.push(func_name);
+        }
+
+        context.vertex_code += &format!(
+            "color = mix({0}(particle.{2} / particle.{3}), {1}(particle.{2} / particle.{3}), saturate({4}));\n",
+            func_names[0],
+            func_names[1],
+            Attribute::AGE.name(),
+            Attribute::LIFETIME.name(),
+            blend
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// A modifier setting each particle's color over its lifetime by sampling a
+/// 1D lookup table (LUT) texture.
+///
+/// This is the runtime counterpart of [`Gradient::from_image()`]: instead of
+/// converting the LUT image into a gradient baked into the shader code, the
+/// texture is bound to the render shader and sampled in the vertex shader at
+/// the ratio of the particle's age over its lifetime, along the horizontal
+/// center line of the texture. This allows swapping the LUT without
+/// regenerating any shader.
+///
+/// The LUT is bound separately from the texture of the
+/// [`ParticleTextureModifier`], so both modifiers can be used together on the
+/// same particle group.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+///
+/// [`Gradient::from_image()`]: crate::Gradient::from_image
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct ColorOverLifetimeTextureModifier {
+    /// The LUT texture defining the particle color based on its lifetime.
+    #[serde(skip)]
+    pub texture: Handle<Image>,
+}
+
+impl_mod_render!(
+    ColorOverLifetimeTextureModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for ColorOverLifetimeTextureModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        context.set_color_lut(self.texture.clone());
+        context.vertex_code += &format!(
+            "color = textureSampleLevel(color_lut_texture, color_lut_sampler, vec2<f32>(particle.{0} / particle.{1}, 0.5), 0.0);\n",
+            Attribute::AGE.name(),
+            Attribute::LIFETIME.name()
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
 /// A modifier to set the size of all particles.
 ///
 /// This modifier assigns a _single_ size to all particles. That size can be
@@ -263,6 +558,125 @@ impl RenderModifier for SizeOverLifetimeModifier {
     }
 }
 
+/// A modifier modulating each particle's size over its lifetime with a smooth
+/// curve.
+///
+/// This is similar to [`SizeOverLifetimeModifier`], but uses a [`Curve`] with
+/// tangent handles instead of a piecewise-linear [`Gradient`], for pops and
+/// ease-outs which look natural.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+#[derive(Debug, Default, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct SizeOverLifetimeCurveModifier {
+    /// The size curve defining the particle size based on its lifetime.
+    pub curve: Curve<Vec2>,
+}
+
+impl_mod_render!(
+    SizeOverLifetimeCurveModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for SizeOverLifetimeCurveModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        let func_name = format!("size_curve_{0:016X}", calc_func_id(&self.curve));
+        context.render_extra += &format!(
+            r#"fn {0}(key: f32) -> vec2<f32> {{
+    {1}
+}}
+
+"#,
+            func_name,
+            self.curve.to_shader_code("key")
+        );
+
+        context.vertex_code += &format!(
+            "size = {0}(particle.{1} / particle.{2});\n",
+            func_name,
+            Attribute::AGE.name(),
+            Attribute::LIFETIME.name()
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// A modifier modulating each particle's size over its lifetime with a shared
+/// [`SizeCurve`] asset.
+///
+/// This is similar to [`SizeOverLifetimeCurveModifier`], but references the
+/// curve by handle instead of owning a copy of it. Editing the curve asset
+/// updates all the effects using it, including the ones already spawned.
+/// Until the asset is loaded, the modifier has no effect.
+///
+/// Like textures, the handle is not serialized with the effect.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::AGE`]
+/// - [`Attribute::LIFETIME`]
+///
+/// [`SizeCurve`]: crate::SizeCurve
+#[derive(Debug, Default, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct SizeOverLifetimeAssetModifier {
+    /// The shared size curve defining the particle size based on its lifetime.
+    #[serde(skip)]
+    pub curve: Handle<SizeCurve>,
+    /// Content of the curve asset, copied by [`update_shared_assets()`].
+    ///
+    /// [`update_shared_assets()`]: crate::update_shared_assets
+    #[serde(skip)]
+    #[reflect(ignore)]
+    pub(crate) resolved: Curve<Vec2>,
+}
+
+impl SizeOverLifetimeAssetModifier {
+    /// Create a new instance of this modifier referencing a shared curve.
+    pub fn new(curve: Handle<SizeCurve>) -> Self {
+        Self {
+            curve,
+            resolved: Curve::new(),
+        }
+    }
+}
+
+impl_mod_render!(
+    SizeOverLifetimeAssetModifier,
+    &[Attribute::AGE, Attribute::LIFETIME]
+);
+
+#[typetag::serde]
+impl RenderModifier for SizeOverLifetimeAssetModifier {
+    fn apply_render(&self, module: &mut Module, context: &mut RenderContext) {
+        if !self.resolved.is_empty() {
+            SizeOverLifetimeCurveModifier {
+                curve: self.resolved.clone(),
+            }
+            .apply_render(module, context);
+        }
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
 /// Mode of orientation of a particle's local frame.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
 pub enum OrientMode {
@@ -427,7 +841,7 @@ axis_z = cam_rot[2].xyz;
 let particle_rot_in_cam_space = {};
 let particle_rot_in_cam_space_cos = cos(particle_rot_in_cam_space);
 let particle_rot_in_cam_space_sin = sin(particle_rot_in_cam_space);
-let axis_x0 = normalize(cross(view.view[1].xyz, axis_z));
+let axis_x0 = normalize(cross(billboard_view.view[1].xyz, axis_z));
 let axis_y0 = cross(axis_z, axis_x0);
 axis_x = axis_x0 * particle_rot_in_cam_space_cos + axis_y0 * particle_rot_in_cam_space_sin;
 axis_y = axis_x0 * particle_rot_in_cam_space_sin - axis_y0 * particle_rot_in_cam_space_cos;
@@ -436,7 +850,7 @@ axis_y = axis_x0 * particle_rot_in_cam_space_sin - axis_y0 * particle_rot_in_cam
                     );
                 } else {
                     context.vertex_code += r#"axis_z = normalize(get_camera_position_effect_space() - position);
-axis_x = normalize(cross(view.view[1].xyz, axis_z));
+axis_x = normalize(cross(billboard_view.view[1].xyz, axis_z));
 axis_y = cross(axis_z, axis_x);
 "#;
                 }
@@ -565,9 +979,148 @@ impl RenderModifier for FlipbookModifier {
     }
 }
 
-/// A modifier to interpret the size of all particles in screen-space pixels.
+/// A modifier to render particles with a region of a texture atlas.
 ///
-/// This modifier assigns a pixel size to particles in screen space, ignoring
+/// This is the non-uniform counterpart of the [`FlipbookModifier`]: instead of
+/// slicing the particle texture into a regular grid, each sprite is an
+/// arbitrary rectangular region of the texture. This allows reusing the sprite
+/// sheets of a 2D game, generally described by a `TextureAtlasLayout`, to
+/// render particles. Like the flipbook, this requires a
+/// [`ParticleTextureModifier`] to specify the texture itself.
+///
+/// The renderer reads the [`Attribute::SPRITE_INDEX`] of each particle to
+/// select the region to render. Indices outside the list of regions are
+/// clamped to the first or last region. To select a random region for each
+/// particle, initialize the attribute with [`init_random_index()`].
+///
+/// Note that this modifier is incompatible with the [`FlipbookModifier`].
+/// Attempts to use them together will produce unexpected results.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// # let texture = Handle::<Image>::default();
+/// # let layout_size = Vec2::new(128., 64.);
+/// # let layout_textures = vec![Rect::new(0., 0., 64., 64.), Rect::new(64., 0., 128., 32.)];
+/// // From a TextureAtlasLayout, use the `size` and `textures` fields
+/// let atlas = TextureAtlasModifier::from_rects(layout_size, layout_textures);
+///
+/// let mut module = Module::default();
+/// let init_sprite = atlas.init_random_index(&mut module);
+///
+/// let asset = EffectAsset::new(vec![256], Spawner::rate(32.0.into()), module)
+///     .init(init_sprite)
+///     .render(ParticleTextureModifier {
+///         texture,
+///         sample_mapping: ImageSampleMapping::Modulate,
+///     })
+///     .render(atlas);
+/// ```
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::SPRITE_INDEX`]
+///
+/// [`init_random_index()`]: TextureAtlasModifier::init_random_index
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct TextureAtlasModifier {
+    /// The texture regions, in normalized UV coordinates.
+    ///
+    /// Each region is stored as `(min.x, min.y, max.x, max.y)`, with
+    /// coordinates in \[0:1\] relative to the size of the texture.
+    pub regions: Vec<Vec4>,
+}
+
+impl TextureAtlasModifier {
+    /// Create a new modifier from the pixel rectangles of the regions of a
+    /// texture atlas.
+    ///
+    /// The `size` is the size in pixels of the atlas texture, and `rects` the
+    /// rectangles in pixels of each region. These map respectively to the
+    /// `size` and `textures` fields of a `TextureAtlasLayout`.
+    pub fn from_rects(size: Vec2, rects: impl IntoIterator<Item = Rect>) -> Self {
+        let regions = rects
+            .into_iter()
+            .map(|rect| (rect.min / size, rect.max / size))
+            .map(|(min, max)| Vec4::new(min.x, min.y, max.x, max.y))
+            .collect();
+        Self { regions }
+    }
+
+    /// Get the number of regions in the atlas.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Returns `true` if the atlas contains no region.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Create an init modifier assigning a random region of this atlas to each
+    /// particle, via its [`Attribute::SPRITE_INDEX`].
+    pub fn init_random_index(&self, module: &mut Module) -> SetAttributeModifier {
+        let rand = module.builtin(BuiltInOperator::Rand(ScalarType::Float.into()));
+        let count = module.lit(self.regions.len() as f32);
+        let index = module.mul(rand, count);
+        let index = module.floor(index);
+        let index = module.cast(index, ScalarType::Int);
+        SetAttributeModifier::new(Attribute::SPRITE_INDEX, index)
+    }
+}
+
+impl_mod_render!(TextureAtlasModifier, &[Attribute::SPRITE_INDEX]);
+
+#[typetag::serde]
+impl RenderModifier for TextureAtlasModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        if self.regions.is_empty() {
+            return;
+        }
+        context.set_needs_uv();
+
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| r.to_wgsl_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let func_name = format!("atlas_region_{0:016X}", calc_func_id(&regions));
+        context.render_extra += &format!(
+            r#"fn {0}(index: i32) -> vec4<f32> {{
+    var regions = array<vec4<f32>, {1}>({2});
+    return regions[clamp(index, 0, {3})];
+}}
+
+"#,
+            func_name,
+            self.regions.len(),
+            regions,
+            self.regions.len() - 1
+        );
+
+        context.vertex_code += &format!(
+            "let atlas_region = {0}(particle.{1});\nuv = mix(atlas_region.xy, atlas_region.zw, uv);\n",
+            func_name,
+            Attribute::SPRITE_INDEX.name()
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// A modifier to interpret the size of all particles in screen-space pixels.
+///
+/// This modifier assigns a pixel size to particles in screen space, ignoring
 /// the distance to the camera and perspective. It effectively scales the
 /// existing [`Attribute::SIZE`] of each particle to negate the perspective
 /// correction usually applied to rendered objects based on their distance to
@@ -622,6 +1175,403 @@ impl RenderModifier for ScreenSpaceSizeModifier {
     }
 }
 
+/// A modifier to snap particles to a virtual pixel grid.
+///
+/// Low-resolution pixel-art games generally render the world with a fixed
+/// number of world units per screen pixel, or into a low-resolution render
+/// target upscaled to the window. Particles moving continuously then cover a
+/// varying fraction of a pixel each frame, which produces a shimmering of
+/// their edges. This modifier rounds the size of each particle to a whole
+/// number of virtual pixels, and snaps its corners to the pixel grid, in the
+/// vertex shader. The simulation itself is unaffected.
+///
+/// The snapping applies to the X and Y coordinates of the particles, and
+/// assumes particles are aligned with those axes, which is the case for 2D
+/// effects with the default orientation. The grid is expressed in simulation
+/// space; for effects simulated in [`SimulationSpace::Local`], the effect
+/// itself should be placed on the pixel grid.
+///
+/// Note that this modifier should generally be placed last in the stack, or at
+/// least after any modifier which might modify the particle position or its
+/// size. Otherwise the snapping will be incorrect.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+///
+/// [`SimulationSpace::Local`]: crate::SimulationSpace::Local
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct PixelSnapModifier {
+    /// Size of a single virtual pixel, in world units.
+    ///
+    /// For a 2D camera with the default projection and no upscaling, this is
+    /// `1.0`. For a game rendering at 320x180 upscaled to 1280x720 with a 2D
+    /// camera scale of `0.25`, this is `4.0`.
+    pub pixel_size: f32,
+}
+
+impl Default for PixelSnapModifier {
+    fn default() -> Self {
+        Self { pixel_size: 1. }
+    }
+}
+
+impl PixelSnapModifier {
+    /// Create a new modifier snapping to a grid of the given pixel size, in
+    /// world units.
+    pub fn new(pixel_size: f32) -> Self {
+        Self { pixel_size }
+    }
+}
+
+impl_mod_render!(PixelSnapModifier, &[Attribute::POSITION]);
+
+#[typetag::serde]
+impl RenderModifier for PixelSnapModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        if self.pixel_size <= 0. {
+            return;
+        }
+        // Round the size first, with at least one pixel so particles don't vanish,
+        // then snap the bottom-left corner of the particle to the grid. Snapping the
+        // center instead would leave particles with an odd pixel size straddling
+        // two pixels.
+        let pixel_size = self.pixel_size.to_wgsl_string();
+        context.vertex_code += &format!(
+            "size = max(round(size / {0}), vec2<f32>(1.0)) * {0};\n\
+            let snap_corner = position.xy - size * 0.5;\n\
+            position = vec3<f32>(round(snap_corner / {0}) * {0} + size * 0.5, position.z);\n",
+            pixel_size
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(*self)
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// Lights the particles with the [`Lights2d`], like the sprites lit by a 2D
+/// lighting crate.
+///
+/// The color of each particle is multiplied by the light it receives at its
+/// world position from the ambient light and the point lights of the
+/// [`Lights2d`], evaluated per particle in the vertex shader. The `emissive`
+/// expression acts as an emissive mask: it's the fraction of the particle
+/// color which ignores the lighting, so a torch flame can stay bright in a
+/// dark scene while its smoke is lit by the surrounding lights.
+///
+/// Particles don't contribute to the lighting themselves. To make an effect
+/// light its surroundings, add a light at its position to the 2D lighting
+/// crate, and to the [`Lights2d`] for the other effects.
+///
+/// Note that this modifier should generally be placed last in the stack, or at
+/// least after any modifier which might modify the particle color or position.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// let writer = ExprWriter::new();
+/// // Half of the particle color glows, whatever the lighting
+/// let modifier = Lighting2dModifier::new(writer.lit(0.5).expr());
+/// ```
+///
+/// [`Lights2d`]: crate::Lights2d
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct Lighting2dModifier {
+    /// Fraction of the particle color not affected by the lighting, from 0.0
+    /// for a fully lit particle to 1.0 for a fully emissive one.
+    ///
+    /// Expected type: `f32`.
+    pub emissive: ExprHandle,
+}
+
+impl Lighting2dModifier {
+    /// Create a new modifier with the given emissive fraction.
+    pub fn new(emissive: ExprHandle) -> Self {
+        Self { emissive }
+    }
+}
+
+impl_mod_render!(Lighting2dModifier, &[Attribute::POSITION]);
+
+#[typetag::serde]
+impl RenderModifier for Lighting2dModifier {
+    fn apply_render(&self, module: &mut Module, context: &mut RenderContext) {
+        let emissive = context.eval(module, self.emissive).unwrap_or_else(|err| {
+            error!(
+                "Failed to evaluate the expression for Lighting2dModifier, error: {:?}",
+                err
+            );
+            0_f32.to_wgsl_string()
+        });
+        context.lighting_2d = true;
+        context.vertex_code += &format!(
+            "{{\n\
+            let light_2d = lighting_2d(transform_position_simulation_to_world(position).xy);\n\
+            color = vec4<f32>(color.rgb * mix(light_2d, vec3<f32>(1.0), saturate({})), color.a);\n\
+            }}\n",
+            emissive
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(*self)
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// Splits the particles of a 2D effect into several layers, based on an
+/// expression evaluated per particle.
+///
+/// By default all particles of an effect instance are rendered at the single
+/// layer of [`ParticleEffect::z_layer_2d`], so a 2D effect is drawn entirely
+/// above or entirely below a sprite. This modifier instead evaluates the `z`
+/// expression for each particle, and renders the particle at the entry of
+/// `layers` nearest to the value obtained. Each layer is drawn by its own draw
+/// call, sorted at that Z coordinate with the other items of the 2D
+/// transparent phase, so particles can for example sort in front of or behind
+/// a character based on their Y position.
+///
+/// Each layer costs an extra draw call of all the particles of the group, where
+/// the vertex shader discards the particles belonging to other layers, so the
+/// list should be kept short. The modifier is ignored for 3D rendering, which
+/// uses the depth buffer instead.
+///
+/// Note that this modifier should generally be placed last in the stack, after
+/// any modifier which might modify the particle position. Only one such
+/// modifier is supported per particle group.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// let writer = ExprWriter::new();
+/// // Particles below the character at Y=0 are drawn in front of it, at Z=1, and
+/// // the particles above it behind it, at Z=-1.
+/// let z = (writer.attr(Attribute::POSITION).y() * writer.lit(-1.)).expr();
+/// let modifier = ZLayer2dModifier::new(z, [-1., 1.]);
+/// ```
+///
+/// [`ParticleEffect::z_layer_2d`]: crate::ParticleEffect::z_layer_2d
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct ZLayer2dModifier {
+    /// Expression of the Z coordinate of the particle, evaluated per particle
+    /// in the vertex shader.
+    ///
+    /// Expected type: `f32`.
+    pub z: ExprHandle,
+    /// Z coordinates of the layers the particles are split into.
+    ///
+    /// The order doesn't matter, and non-finite values are ignored.
+    pub layers: Vec<f32>,
+}
+
+impl ZLayer2dModifier {
+    /// Create a new modifier splitting the particles into the given layers.
+    pub fn new(z: ExprHandle, layers: impl Into<Vec<f32>>) -> Self {
+        Self {
+            z,
+            layers: layers.into(),
+        }
+    }
+
+    /// Get the finite Z coordinates of the layers, sorted in increasing order
+    /// and without duplicates. This is the order of the layer indices used by
+    /// the render shader.
+    pub(crate) fn sorted_layers(&self) -> Vec<f32> {
+        let mut layers = self
+            .layers
+            .iter()
+            .copied()
+            .filter(|z| z.is_finite())
+            .collect::<Vec<_>>();
+        layers.sort_by(f32::total_cmp);
+        layers.dedup();
+        layers
+    }
+}
+
+impl_mod_render!(ZLayer2dModifier, &[]);
+
+#[typetag::serde]
+impl RenderModifier for ZLayer2dModifier {
+    fn apply_render(&self, module: &mut Module, context: &mut RenderContext) {
+        let layers = self.sorted_layers();
+        if layers.is_empty() {
+            return;
+        }
+
+        // With a single layer, all particles are drawn there, and there's nothing
+        // to discard.
+        if layers.len() > 1 {
+            let z = context.eval(module, self.z).unwrap_or_else(|err| {
+                error!(
+                    "Failed to evaluate the expression for ZLayer2dModifier, error: {:?}",
+                    err
+                );
+                layers[0].to_wgsl_string()
+            });
+
+            // The layer of the particle is the nearest one, that is the number of
+            // midpoints between consecutive layers below its Z coordinate. The
+            // render pipeline drawing the layer Z_LAYER_2D discards the others.
+            let layer_index_code = layers
+                .windows(2)
+                .map(|pair| {
+                    format!(
+                        "select(0u, 1u, z_layer > {})",
+                        ((pair[0] + pair[1]) * 0.5).to_wgsl_string()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" + ");
+            context.vertex_code += &format!(
+                r##"#ifdef Z_LAYER_2D
+{{
+    let z_layer = {};
+    let z_layer_index = {};
+    if (z_layer_index != #{{Z_LAYER_2D}}u) {{
+        // Degenerate quad, discarded by the rasterizer
+        out.position = vec4<f32>(0.0);
+        return out;
+    }}
+}}
+#endif
+"##,
+                z, layer_index_code
+            );
+        }
+
+        context.z_layers_2d = layers;
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
+/// Attribute visualized by the debug view of an effect instance.
+///
+/// See [`ParticleEffect::debug_view`] for details.
+///
+/// [`ParticleEffect::debug_view`]: crate::ParticleEffect::debug_view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum DebugView {
+    /// Color particles by the fraction of their lifetime elapsed, from blue
+    /// for newborn particles to red for particles about to die.
+    ///
+    /// Requires the [`Attribute::AGE`] and [`Attribute::LIFETIME`] attributes.
+    AgeFraction,
+    /// Color particles by their speed, from blue for particles at rest to red
+    /// for fast ones. The scale is logarithmic, so that speeds of different
+    /// orders of magnitude remain distinguishable, and reaches red at 1000
+    /// units per second.
+    ///
+    /// Requires the [`Attribute::VELOCITY`] attribute.
+    Speed,
+    /// Color particles with a distinct color per particle group.
+    Group,
+    /// Color particles with a distinct color per sprite index.
+    ///
+    /// Requires the [`Attribute::SPRITE_INDEX`] attribute.
+    SpriteIndex,
+    /// Draw the number of particle fragments rasterized per pixel as a
+    /// heatmap, to visualize the fill-rate cost of the effect.
+    ///
+    /// Each fragment of a particle adds a constant color, whatever its texture
+    /// and alpha, so pixels go from black to red after 8 overlapping layers,
+    /// yellow after 24, and white after 64. Alpha-masked particles are blended
+    /// too. The heatmap is added to the scene behind the effect, so is best
+    /// read over a dark background, and HDR cameras tonemap it.
+    Overdraw,
+}
+
+/// Render modifier overriding the particle color for a [`DebugView`].
+///
+/// This is added by the [`CompiledParticleEffect`] of the instances with a
+/// debug view, and placed after all other render modifiers of the group.
+/// Particles without the attributes required by the view are drawn in grey.
+///
+/// [`CompiledParticleEffect`]: crate::CompiledParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub(crate) struct DebugViewModifier {
+    /// The visualized attribute.
+    pub view: DebugView,
+    /// The index of the particle group the modifier applies to.
+    pub group_index: u32,
+}
+
+impl_mod_render!(DebugViewModifier, &[]);
+
+#[typetag::serde]
+impl RenderModifier for DebugViewModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        // The overdraw color is output by the render pipeline itself
+        if self.view == DebugView::Overdraw {
+            return;
+        }
+
+        let has = |attr: Attribute| context.particle_layout.contains(attr);
+        let value_code = match self.view {
+            DebugView::AgeFraction if has(Attribute::AGE) && has(Attribute::LIFETIME) => format!(
+                "debug_ramp(particle.{} / particle.{})",
+                Attribute::AGE.name(),
+                Attribute::LIFETIME.name()
+            ),
+            DebugView::Speed if has(Attribute::VELOCITY) => format!(
+                "debug_ramp(log2(1.0 + length(particle.{})) / log2(1001.0))",
+                Attribute::VELOCITY.name()
+            ),
+            DebugView::Group => format!("debug_palette({}u)", self.group_index),
+            DebugView::SpriteIndex if has(Attribute::SPRITE_INDEX) => format!(
+                "debug_palette(u32(particle.{}))",
+                Attribute::SPRITE_INDEX.name()
+            ),
+            _ => "vec3<f32>(0.5)".to_string(),
+        };
+
+        // Continuous values use a blue-green-red ramp, and indices a palette of
+        // hues spaced by the golden angle, so consecutive indices contrast well.
+        context.render_extra += r##"fn debug_ramp(t: f32) -> vec3<f32> {
+    let x = 4.0 * saturate(t);
+    return saturate(vec3<f32>(1.5) - abs(vec3<f32>(x) - vec3<f32>(3.0, 2.0, 1.0)));
+}
+
+fn debug_palette(index: u32) -> vec3<f32> {
+    let hue = fract(f32(index) * 0.618034);
+    return saturate(abs(fract(vec3<f32>(hue) + vec3<f32>(0.0, 2.0, 1.0) / 3.0) * 6.0 - 3.0) - 1.0);
+}
+"##;
+        context.vertex_code += &format!("color = vec4<f32>({}, 1.0);\n", value_code);
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(*self)
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
+
 /// Makes particles round.
 ///
 /// The shape of each particle is a [squircle] (like a rounded rectangle, but
@@ -735,6 +1685,138 @@ mod tests {
         assert_eq!(context.sprite_grid_size.unwrap(), UVec2::new(3, 4));
     }
 
+    #[test]
+    fn mod_texture_atlas() {
+        let modifier = TextureAtlasModifier::from_rects(
+            Vec2::new(64., 32.),
+            [Rect::new(0., 0., 32., 32.), Rect::new(32., 0., 64., 16.)],
+        );
+        assert_eq!(modifier.len(), 2);
+        assert_eq!(modifier.regions[0], Vec4::new(0., 0., 0.5, 1.));
+        assert_eq!(modifier.regions[1], Vec4::new(0.5, 0., 1., 0.5));
+
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+
+        assert!(context.needs_uv);
+        assert!(context.render_extra.contains("array<vec4<f32>, 2>"));
+        assert!(context.vertex_code.contains(Attribute::SPRITE_INDEX.name()));
+
+        // Random selection writes the sprite index
+        let init = modifier.init_random_index(&mut module);
+        assert_eq!(init.attribute, Attribute::SPRITE_INDEX);
+
+        // An empty atlas doesn't modify the UVs
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        TextureAtlasModifier::default().apply_render(&mut module, &mut context);
+        assert!(!context.needs_uv);
+        assert!(context.vertex_code.is_empty());
+    }
+
+    #[test]
+    fn mod_pixel_snap() {
+        let modifier = PixelSnapModifier::new(2.);
+        assert_eq!(modifier.attributes(), &[Attribute::POSITION]);
+
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert!(context.vertex_code.contains("round(size / 2.)"));
+
+        // Invalid pixel sizes don't snap anything
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        PixelSnapModifier::new(0.).apply_render(&mut module, &mut context);
+        assert!(context.vertex_code.is_empty());
+    }
+
+    #[test]
+    fn mod_lighting_2d() {
+        let mut module = Module::default();
+        let emissive = module.lit(0.25);
+        let modifier = Lighting2dModifier::new(emissive);
+        assert_eq!(modifier.attributes(), &[Attribute::POSITION]);
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert!(context.lighting_2d);
+        assert!(context
+            .vertex_code
+            .contains("mix(light_2d, vec3<f32>(1.0), saturate(0.25))"));
+    }
+
+    #[test]
+    fn mod_z_layer_2d() {
+        let mut module = Module::default();
+        let z = module.lit(0.25);
+        let modifier = ZLayer2dModifier::new(z, [2., f32::INFINITY, -2., 0., 2.]);
+        assert_eq!(modifier.sorted_layers(), vec![-2., 0., 2.]);
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert_eq!(context.z_layers_2d, vec![-2., 0., 2.]);
+        assert!(context
+            .vertex_code
+            .contains("select(0u, 1u, z_layer > -1.) + select(0u, 1u, z_layer > 1.)"));
+
+        // A single layer sorts all particles there, without discarding any
+        let modifier = ZLayer2dModifier::new(z, [3.]);
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert_eq!(context.z_layers_2d, vec![3.]);
+        assert!(context.vertex_code.is_empty());
+    }
+
+    #[test]
+    fn mod_debug_view() {
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::new()
+            .append(Attribute::VELOCITY)
+            .append(Attribute::SPRITE_INDEX)
+            .build();
+
+        for (view, expected) in [
+            // Missing attributes draw in grey
+            (DebugView::AgeFraction, "vec3<f32>(0.5)"),
+            (
+                DebugView::Speed,
+                "debug_ramp(log2(1.0 + length(particle.velocity))",
+            ),
+            (DebugView::Group, "debug_palette(3u)"),
+            (
+                DebugView::SpriteIndex,
+                "debug_palette(u32(particle.sprite_index))",
+            ),
+        ] {
+            let modifier = DebugViewModifier {
+                view,
+                group_index: 3,
+            };
+            assert!(modifier.attributes().is_empty());
+            let mut context = RenderContext::new(&property_layout, &particle_layout);
+            modifier.apply_render(&mut module, &mut context);
+            assert!(context.vertex_code.contains(expected));
+            assert!(context.render_extra.contains("fn debug_palette("));
+        }
+
+        let modifier = DebugViewModifier {
+            view: DebugView::Overdraw,
+            group_index: 0,
+        };
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert!(context.vertex_code.is_empty());
+    }
+
     #[test]
     fn mod_color_over_lifetime() {
         let red: Vec4 = Vec4::new(1., 0., 0., 1.);
@@ -757,6 +1839,85 @@ mod tests {
             .contains(&gradient.to_shader_code("key")));
     }
 
+    #[test]
+    fn mod_hdr_color_over_lifetime() {
+        let gradient = Gradient::linear(
+            HdrColor::new(Color::RED),
+            HdrColor::new(Color::RED).with_intensity(8.),
+        );
+        let modifier = HdrColorOverLifetimeModifier {
+            gradient: gradient.clone(),
+        };
+
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+
+        assert!(context
+            .render_extra
+            .contains(&gradient.to_shader_code("key")));
+        assert!(context.vertex_code.contains("color = hdr_color_gradient_"));
+    }
+
+    #[test]
+    fn mod_color_over_lifetime_random() {
+        let red: Vec4 = Vec4::new(1., 0., 0., 1.);
+        let blue: Vec4 = Vec4::new(0., 0., 1., 1.);
+        let gradient0 = Gradient::linear(red, blue);
+        let gradient1 = Gradient::constant(blue);
+        let mut module = Module::default();
+        let blend = module.attr(Attribute::F32_0);
+        let modifier =
+            ColorOverLifetimeRandomModifier::new(gradient0.clone(), gradient1.clone(), blend);
+
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+
+        assert!(context
+            .render_extra
+            .contains(&gradient0.to_shader_code("key")));
+        assert!(context
+            .render_extra
+            .contains(&gradient1.to_shader_code("key")));
+        assert!(context.vertex_code.contains("color = mix("));
+        assert!(context
+            .vertex_code
+            .contains(&format!("saturate(particle.{})", Attribute::F32_0.name())));
+
+        // Identical gradients are only defined once
+        let modifier =
+            ColorOverLifetimeRandomModifier::new(gradient0.clone(), gradient0.clone(), blend);
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+        assert_eq!(
+            context.render_extra.matches("fn color_gradient_").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn mod_color_over_lifetime_texture() {
+        let texture = Handle::<Image>::default();
+        let modifier = ColorOverLifetimeTextureModifier {
+            texture: texture.clone(),
+        };
+
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+
+        assert_eq!(context.color_lut, Some(texture));
+        assert!(context.particle_texture.is_none());
+        assert!(!context.needs_uv);
+        assert!(context.vertex_code.contains("color_lut_texture"));
+    }
+
     #[test]
     fn mod_size_over_lifetime() {
         let x = Vec2::new(1., 0.);
@@ -780,6 +1941,25 @@ mod tests {
             .contains(&gradient.to_shader_code("key")));
     }
 
+    #[test]
+    fn mod_size_over_lifetime_curve() {
+        let curve = Curve::new()
+            .with_key_tangents(0., Vec2::ZERO, Vec2::ZERO, Vec2::splat(4.))
+            .with_key(1., Vec2::ONE);
+        let modifier = SizeOverLifetimeCurveModifier {
+            curve: curve.clone(),
+        };
+
+        let mut module = Module::default();
+        let property_layout = PropertyLayout::default();
+        let particle_layout = ParticleLayout::default();
+        let mut context = RenderContext::new(&property_layout, &particle_layout);
+        modifier.apply_render(&mut module, &mut context);
+
+        assert!(context.render_extra.contains(&curve.to_shader_code("key")));
+        assert!(context.vertex_code.contains("size = size_curve_"));
+    }
+
     #[test]
     fn mod_set_color() {
         let mut modifier = SetColorModifier::default();
@@ -0,0 +1,162 @@
+//! Renders particles as trails through their position history.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calc_func_id, impl_mod_render, Attribute, EvalContext, ExprError, Gradient, Modifier,
+    ModifierContext, Module, RenderContext, RenderModifier, ShaderCode, ShaderWriter, ToWgslString,
+};
+
+/// Renders each particle as a ribbon trailing behind it, through the history of
+/// its past positions.
+///
+/// When an effect uses this modifier, each particle records its last
+/// [`length`] positions into a secondary GPU buffer, one position per
+/// simulation frame. The particle is then rendered as a camera-facing ribbon
+/// made of `length - 1` quads joining those positions, instead of a single
+/// quad at its current position.
+///
+/// The width and color of the ribbon vary along its length, from the current
+/// particle position (ratio `0.0`) to the oldest recorded position (ratio
+/// `1.0`). The color gradient multiplies the color of the particle.
+///
+/// Unlike the [`RibbonModifier`], which threads distinct particles together,
+/// this modifier gives each particle its own trail, which is typically used for
+/// projectile trails. To also render the particle itself as a sprite, clone it
+/// into another group with a [`CloneModifier`], and render only one of the two
+/// groups with this modifier.
+///
+/// The history of particles is recorded when they're spawned, so particles
+/// cloned into a group rendered with a trail start with a stale history.
+///
+/// # Attributes
+///
+/// This modifier requires the following particle attributes:
+/// - [`Attribute::POSITION`]
+///
+/// [`length`]: crate::TrailModifier::length
+/// [`RibbonModifier`]: crate::RibbonModifier
+/// [`CloneModifier`]: crate::CloneModifier
+#[derive(Debug, Clone, PartialEq, Hash, Reflect, Serialize, Deserialize)]
+pub struct TrailModifier {
+    /// Number of positions recorded per particle, including the current one.
+    ///
+    /// This is clamped to the range `[2:MAX_LENGTH]`.
+    pub length: u32,
+    /// Width of the trail along its length.
+    pub width: Gradient<f32>,
+    /// Color multiplier of the trail along its length.
+    pub color: Gradient<Vec4>,
+}
+
+impl_mod_render!(TrailModifier, &[Attribute::POSITION]);
+
+impl TrailModifier {
+    /// Maximum number of positions recorded per particle.
+    pub const MAX_LENGTH: u32 = 64;
+
+    /// Create a new modifier recording the given number of positions per
+    /// particle, with a constant width of `1.0` and a white color.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is less than 2 or greater than
+    /// [`TrailModifier::MAX_LENGTH`].
+    pub fn new(length: u32) -> Self {
+        assert!(
+            (2..=Self::MAX_LENGTH).contains(&length),
+            "Invalid trail length {}, must be in [2:{}].",
+            length,
+            Self::MAX_LENGTH
+        );
+        Self {
+            length,
+            width: Gradient::constant(1.),
+            color: Gradient::constant(Vec4::ONE),
+        }
+    }
+
+    /// Set the width of the trail along its length.
+    pub fn with_width(mut self, width: Gradient<f32>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the color multiplier of the trail along its length.
+    pub fn with_color(mut self, color: Gradient<Vec4>) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Get the effective length of the trail, clamped to the supported range.
+    pub(crate) fn clamped_length(&self) -> u32 {
+        self.length.clamp(2, Self::MAX_LENGTH)
+    }
+}
+
+#[typetag::serde]
+impl RenderModifier for TrailModifier {
+    fn apply_render(&self, _module: &mut Module, context: &mut RenderContext) {
+        let width_func_name = format!("trail_width_{0:016X}", calc_func_id(&self.width));
+        context.render_extra += &format!(
+            r#"fn {0}(key: f32) -> f32 {{
+    {1}
+}}
+
+"#,
+            width_func_name,
+            self.width.to_shader_code("key")
+        );
+
+        let color_func_name = format!("trail_color_{0:016X}", calc_func_id(&self.color));
+        context.render_extra += &format!(
+            r#"fn {0}(key: f32) -> vec4<f32> {{
+    {1}
+}}
+
+"#,
+            color_func_name,
+            self.color.to_shader_code("key")
+        );
+
+        // Each quad joins two consecutive positions of the history. The ratio along
+        // the trail is calculated per vertex, from the side of the quad the vertex
+        // is on.
+        context.vertex_code += &format!(
+            r##"
+    let trail_segment = vertex_index / 6u;
+    let trail_base = index * trail_stride;
+    let trail_head = trail_buffer[trail_base + trail_segment].xyz;
+    let trail_tail = trail_buffer[trail_base + trail_segment + 1u].xyz;
+    let trail_delta = trail_head - trail_tail;
+    let trail_segment_length = length(trail_delta);
+    if (trail_segment_length < 1e-6) {{
+        out.position = vec4(0.0);
+        return out;
+    }}
+    let trail_ratio = (f32(trail_segment) + 0.5 - vertex_position.x) / {2};
+
+    position = mix(trail_head, trail_tail, 0.5);
+    let trail_dir = normalize(position - get_camera_position_effect_space());
+    axis_x = trail_delta / trail_segment_length;
+    axis_y = normalize(cross(trail_dir, axis_x));
+    axis_z = cross(axis_x, axis_y);
+
+    size = vec2(trail_segment_length, {0}(trail_ratio));
+    color = color * {1}(trail_ratio);
+"##,
+            width_func_name,
+            color_func_name,
+            ((self.clamped_length() - 1) as f32).to_wgsl_string(),
+        );
+    }
+
+    fn boxed_render_clone(&self) -> Box<dyn RenderModifier> {
+        Box::new(self.clone())
+    }
+
+    fn as_modifier(&self) -> &dyn Modifier {
+        self
+    }
+}
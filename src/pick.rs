@@ -0,0 +1,234 @@
+use bevy::prelude::*;
+
+use crate::{render::ReadbackChannel, CompiledParticleEffect};
+
+/// Component making the particles of an effect instance pickable.
+///
+/// Insert this component on the entity of a [`ParticleEffect`] to include its
+/// particles in the tests against the ray of the [`ParticlePicking`] resource.
+/// Each particle is tested as a sphere of the given [`radius`], or of half its
+/// [`Attribute::SIZE`] if larger, centered on its [`Attribute::POSITION`].
+/// Effects without a position attribute can't be picked.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`radius`]: ParticlePickable::radius
+/// [`Attribute::SIZE`]: crate::Attribute::SIZE
+/// [`Attribute::POSITION`]: crate::Attribute::POSITION
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct ParticlePickable {
+    /// Minimum radius of the sphere around each particle hit by the ray, in
+    /// simulation space.
+    pub radius: f32,
+}
+
+impl Default for ParticlePickable {
+    fn default() -> Self {
+        Self { radius: 0.1 }
+    }
+}
+
+impl ParticlePickable {
+    /// Make the particles pickable with the given minimum radius.
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+/// A single particle hit by the pick ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticlePick {
+    /// Entity holding the [`ParticleEffect`] instance the particle belongs to.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Index of the group of the particle.
+    pub group_index: u32,
+    /// Index of the particle in the effect instance.
+    ///
+    /// This identifies the particle as long as it's alive; the index of a dead
+    /// particle is reused by the next particles spawned.
+    pub particle_index: u32,
+    /// Position of the particle, in world space.
+    pub position: Vec3,
+    /// Distance from the ray origin to the particle, along the ray.
+    pub distance: f32,
+}
+
+/// Resource picking the particles of the effect instances with a
+/// [`ParticlePickable`] component.
+///
+/// While [`ray`] is set, the pickable particles are tested against it on GPU
+/// at the end of each rendered frame, and the index of the nearest particle
+/// hit is written into a small buffer, which is read back asynchronously. The
+/// result is available through [`pick()`] a few frames later, and updated each
+/// time a new readback completes. Only the alive particles drawn during the
+/// frame are tested. Picking is disabled when the ray is `None`, which is the
+/// default.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::{prelude::*, window::PrimaryWindow};
+/// # use bevy_hanabi::*;
+/// fn pick_under_cursor(
+///     mut picking: ResMut<ParticlePicking>,
+///     window: Query<&Window, With<PrimaryWindow>>,
+///     camera: Query<(&Camera, &GlobalTransform)>,
+/// ) {
+///     let (camera, camera_transform) = camera.single();
+///     let cursor = window.single().cursor_position();
+///     picking.set_ray_from_cursor(camera, camera_transform, cursor);
+///
+///     if let Some(pick) = picking.pick() {
+///         info!("Hovering particle #{} at {}", pick.particle_index, pick.position);
+///     }
+/// }
+/// ```
+///
+/// [`ray`]: ParticlePicking::ray
+/// [`pick()`]: ParticlePicking::pick
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct ParticlePicking {
+    /// Ray to pick the particles along, in world space, if any.
+    pub ray: Option<Ray3d>,
+    /// Nearest particle hit by the last completed readback.
+    pick: Option<ParticlePick>,
+}
+
+impl ParticlePicking {
+    /// Set the pick ray from a cursor position in the viewport of a camera.
+    ///
+    /// Picking is disabled if the cursor position is `None`, for example when
+    /// the cursor is outside the window.
+    pub fn set_ray_from_cursor(
+        &mut self,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        cursor: Option<Vec2>,
+    ) {
+        self.ray = cursor.and_then(|cursor| camera.viewport_to_world(camera_transform, cursor));
+    }
+
+    /// Get the nearest particle hit by the ray, if any.
+    ///
+    /// The result lags a few frames behind the ray, and is `None` while
+    /// picking is disabled.
+    pub fn pick(&self) -> Option<&ParticlePick> {
+        self.pick.as_ref()
+    }
+}
+
+/// Particle hit by the pick ray in a single group of an effect instance, read
+/// back from GPU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PickReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Index of the group of the particle.
+    pub group_index: u32,
+    /// Index of the particle in the effect instance.
+    pub particle_index: u32,
+    /// Position of the particle, in simulation space.
+    pub position: Vec3,
+}
+
+/// Store the nearest particle hit by the pick ray into the [`ParticlePicking`]
+/// resource.
+///
+/// Each readback sends all the particles hit, possibly none, at once, so only
+/// the last readback completed since the previous frame is used.
+pub(crate) fn update_particle_picking(
+    channel: Res<ReadbackChannel<Vec<PickReport>>>,
+    mut picking: ResMut<ParticlePicking>,
+    effects: Query<(&CompiledParticleEffect, Option<&GlobalTransform>)>,
+) {
+    let reports = channel.take().pop();
+    let Some(ray) = picking.ray else {
+        if picking.pick.is_some() {
+            picking.pick = None;
+        }
+        return;
+    };
+    let Some(reports) = reports else {
+        return;
+    };
+
+    let pick = reports
+        .into_iter()
+        .filter_map(|report| {
+            // The effect may have been despawned since the readback was issued
+            let (compiled, transform) = effects.get(report.entity).ok()?;
+            let position = compiled
+                .simulation_to_world(transform)
+                .transform_point3(report.position);
+            Some(ParticlePick {
+                entity: report.entity,
+                group_index: report.group_index,
+                particle_index: report.particle_index,
+                position,
+                distance: (position - ray.origin).dot(*ray.direction),
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance));
+    picking.pick = pick;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulationSpace;
+
+    #[test]
+    fn update_pick() {
+        let mut app = App::new();
+        app.init_resource::<ReadbackChannel<Vec<PickReport>>>()
+            .init_resource::<ParticlePicking>()
+            .add_systems(Update, update_particle_picking);
+
+        let global = app.world.spawn(CompiledParticleEffect::default()).id();
+        let local = app
+            .world
+            .spawn((
+                CompiledParticleEffect {
+                    simulation_space: SimulationSpace::Local,
+                    ..default()
+                },
+                GlobalTransform::from_xyz(0., 0., -2.),
+            ))
+            .id();
+        let report = |entity, particle_index, z| PickReport {
+            entity,
+            group_index: 0,
+            particle_index,
+            position: Vec3::new(0., 0., z),
+        };
+
+        // Reports are ignored while picking is disabled
+        let channel = app
+            .world
+            .resource::<ReadbackChannel<Vec<PickReport>>>()
+            .clone();
+        channel.send([vec![report(global, 0, -1.)]]);
+        app.update();
+        assert!(app.world.resource::<ParticlePicking>().pick().is_none());
+
+        app.world.resource_mut::<ParticlePicking>().ray = Some(Ray3d::new(Vec3::ZERO, -Vec3::Z));
+        channel.send([vec![report(global, 3, -5.), report(local, 7, -1.)]]);
+        app.update();
+        let pick = *app.world.resource::<ParticlePicking>().pick().unwrap();
+        assert_eq!(pick.entity, local);
+        assert_eq!(pick.particle_index, 7);
+        assert_eq!(pick.position, Vec3::new(0., 0., -3.));
+        assert_eq!(pick.distance, 3.);
+
+        // The last pick is kept until the next readback
+        app.update();
+        assert!(app.world.resource::<ParticlePicking>().pick().is_some());
+        channel.send([vec![]]);
+        app.update();
+        assert!(app.world.resource::<ParticlePicking>().pick().is_none());
+    }
+}
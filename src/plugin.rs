@@ -1,38 +1,91 @@
 #[cfg(feature = "2d")]
 use bevy::core_pipeline::core_2d::Transparent2d;
 #[cfg(feature = "3d")]
-use bevy::core_pipeline::core_3d::{AlphaMask3d, Transparent3d};
+use bevy::core_pipeline::{
+    core_3d::{AlphaMask3d, Transparent3d},
+    deferred::AlphaMask3dDeferred,
+};
 use bevy::{
+    asset::processor::LoadAndSave,
     prelude::*,
     render::{
+        camera::CameraUpdateSystem,
+        extract_resource::ExtractResourcePlugin,
         render_graph::RenderGraph,
         render_phase::DrawFunctions,
         render_resource::{SpecializedComputePipelines, SpecializedRenderPipelines},
-        renderer::{RenderAdapterInfo, RenderDevice},
+        renderer::{RenderAdapter, RenderAdapterInfo, RenderDevice},
         view::{prepare_view_uniforms, visibility::VisibilitySystems},
         Render, RenderApp, RenderSet,
     },
     time::{virtual_time_system, TimeSystem},
+    transform::TransformSystem,
 };
+use wgpu::DownlevelFlags;
 
+#[cfg(debug_assertions)]
+use crate::hot_reload::{hot_reload_shader_templates, ShaderTemplateWatcher};
+#[cfg(feature = "fog")]
+use crate::render::extract_fog_settings;
 use crate::{
-    asset::{EffectAsset, EffectAssetLoader},
-    compile_effects, gather_removed_effects,
-    properties::EffectProperties,
+    apply_particle_budget,
+    asset::{EffectAsset, EffectAssetLoader, EffectVariantLoader},
+    attach::update_joint_attachments,
+    bounds::{update_gpu_bounds, BoundsReport},
+    bvh::update_collision_bvh,
+    capabilities::detect_missing_capabilities,
+    capacity::{report_group_occupancy, OccupancyReport, RenderStatsReport},
+    capture::{update_particle_captures, CaptureReport},
+    compile_effects,
+    cpu_sim::simulate_cpu_particles,
+    feedback::{update_particle_impulses, ImpulseReport},
+    gather_removed_effects,
+    pick::{update_particle_picking, PickReport},
+    pool::update_effect_pools,
+    precompile::{update_effect_precompiler, PrecompileReport},
+    process::EffectAssetSaver,
+    properties::{EffectProperties, GlobalProperties, HdrColor},
     render::{
-        extract_effect_events, extract_effects, prepare_bind_groups, prepare_effects,
-        prepare_resources, queue_effects, DispatchIndirectPipeline, DrawEffects, EffectAssetEvents,
-        EffectBindGroups, EffectCache, EffectsMeta, ExtractedEffects, GpuDispatchIndirect,
-        GpuParticleGroup, GpuRenderEffectMetadata, GpuRenderGroupIndirect, GpuSpawnerParams,
-        ParticlesInitPipeline, ParticlesRenderPipeline, ParticlesUpdatePipeline, ShaderCache,
-        SimParams, StorageType as _, VfxSimulateDriverNode, VfxSimulateNode,
+        extract_capture_requests, extract_cpu_effects, extract_density_volumes,
+        extract_effect_events, extract_effects, extract_pick_requests, extract_precompile_requests,
+        extract_stats_requests, extract_stereo_eyes, extract_track_requests,
+        inject_particle_density, pick_particles, precompile_effects, prepare_billboard_views,
+        prepare_bind_groups, prepare_cpu_bind_groups, prepare_cpu_effects, prepare_effects,
+        prepare_gpu_timing, prepare_lights_2d, prepare_particle_culling, prepare_resources,
+        prepare_static_colliders, prepare_view_fogs, queue_cpu_effects, queue_effects,
+        readback_bounds, readback_gpu_timings, readback_group_occupancy, readback_impulses,
+        readback_particles, report_render_stats, track_particles, BillboardViewUniforms,
+        BoundsReadback, CaptureReadback, CpuEffectsMeta, CpuParticlesPipeline, CullMeta,
+        DensityInjection, DispatchIndirectPipeline, DrawCpuEffects, DrawEffects, EffectAssetEvents,
+        EffectBindGroups, EffectCache, EffectsMeta, ExtractedCpuEffects, ExtractedEffects,
+        GpuDispatchIndirect, GpuParticleGroup, GpuRenderEffectMetadata, GpuRenderGroupIndirect,
+        GpuSpawnerParams, GpuTimingQueries, ImpulseReadback, InitDispatchPipeline,
+        OccupancyReadback, ParticlesInitPipeline, ParticlesRenderPipeline, ParticlesUpdatePipeline,
+        PickReadback, PrecompileQueue, ReadbackChannel, ShaderCache, SimParams,
+        SimulationWorkgroupSize, StorageType as _, TrackReadback, VfxSimulateDriverNode,
+        VfxSimulateNode, ViewFogUniforms, HANABI_CPU_SHADER_HANDLE,
     },
+    render_target::update_effect_render_targets,
+    shared::{update_shared_assets, ColorGradient, SizeCurve},
     spawn::{self, Random},
-    tick_spawners,
+    spawn_effect_children, submit_injected_particles, tick_property_tweens, tick_spawners,
     time::effect_simulation_time_system,
-    update_properties_from_asset, EffectSimulation, ParticleEffect, RemovedEffectsEvent, Spawner,
+    timing::{report_gpu_timings, GpuTimingReport},
+    track::{update_particle_trackers, TrackReport},
+    update_effect_lod, update_offscreen_throttle, update_properties_from_asset, BudgetPriority,
+    CapacityDiagnostics, CapacityExceededEvent, CollisionBvh, EffectComposition, EffectLod,
+    EffectParent, EffectPool, EffectPrecompiler, EffectRenderTarget, EffectRenderTargetSource,
+    EffectSimulation, EffectStats, EffectStereoEye, GpuCapabilities, GpuTimingDiagnostics,
+    GpuTimingPass, HanabiStats, JointAttachment, Lights2d, LodTier, MissingCapability,
+    ModifierToggles, OffscreenThrottle, ParticleBudget, ParticleCollider, ParticleDensitySource,
+    ParticleDensityVolume, ParticleEffect, ParticlePickable, ParticlePicking, ParticleTracker,
+    PropertyTween, RemovedEffectsEvent, SimulationBackend, SimulationFallback, SpawnDroppedEvent,
+    Spawner, StaticColliders, ThrottleMode, TweenEasing, VoxelGrid,
 };
 
+/// Asset processor generating the shaders of effect assets at build time.
+type EffectAssetProcessor = LoadAndSave<EffectAssetLoader, EffectAssetSaver>;
+
 /// Labels for the Hanabi systems.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
 pub enum EffectSystems {
@@ -119,7 +172,91 @@ pub mod simulate_graph {
 const HANABI_COMMON_TEMPLATE_HANDLE: Handle<Shader> =
     Handle::weak_from_u128(0x626E7AD34E54487EB7969A90E34CC1ECu128);
 
+/// Settings of the [`HanabiPlugin`].
+///
+/// The default settings are suited to most applications. Some fields allow
+/// tuning the GPU simulation for a given target device. Insert this resource
+/// before adding the [`HanabiPlugin`] to change them; the settings are read
+/// once when the plugin builds, so changing them afterward has no effect.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// App::new()
+///     .add_plugins(DefaultPlugins)
+///     .insert_resource(HanabiSettings::default().with_workgroup_size(128))
+///     .add_plugins(HanabiPlugin)
+///     .run();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct HanabiSettings {
+    /// Number of threads per workgroup of the compute passes initializing,
+    /// updating, and culling the particles. Defaults to
+    /// [`DEFAULT_WORKGROUP_SIZE`].
+    ///
+    /// Each thread simulates a single particle. The optimal size depends on
+    /// the GPU, and larger workgroups can give measurable gains for effects
+    /// with many particles on some devices. The size must be supported by the
+    /// render device (`Limits::max_compute_workgroup_size_x` and
+    /// `Limits::max_compute_invocations_per_workgroup`); an unsupported size
+    /// is replaced with the default one, and a warning is logged.
+    ///
+    /// [`DEFAULT_WORKGROUP_SIZE`]: crate::HanabiSettings::DEFAULT_WORKGROUP_SIZE
+    pub workgroup_size: u32,
+    /// Hot-reload the WGSL templates of the plugin (`vfx_init.wgsl`,
+    /// `vfx_update.wgsl`, `vfx_render.wgsl`, and `vfx_cull.wgsl`) when their
+    /// source files are modified. Defaults to `false`.
+    ///
+    /// This helps iterating on the shaders generated by Hanabi without
+    /// restarting the application. The files are looked up in the source
+    /// directory Hanabi was compiled from, so this requires a local checkout
+    /// of the crate, for example through a `[patch]` section or a path
+    /// dependency. Modifying any template recompiles all effects.
+    ///
+    /// This is ignored in release builds (without `debug_assertions`).
+    pub shader_hot_reload: bool,
+}
+
+impl Default for HanabiSettings {
+    fn default() -> Self {
+        Self {
+            workgroup_size: Self::DEFAULT_WORKGROUP_SIZE,
+            shader_hot_reload: false,
+        }
+    }
+}
+
+impl HanabiSettings {
+    /// Default number of threads per workgroup of the simulation passes.
+    pub const DEFAULT_WORKGROUP_SIZE: u32 = 64;
+
+    /// Set the number of threads per workgroup of the simulation passes.
+    ///
+    /// See [`workgroup_size`] for details.
+    ///
+    /// [`workgroup_size`]: crate::HanabiSettings::workgroup_size
+    pub fn with_workgroup_size(mut self, workgroup_size: u32) -> Self {
+        self.workgroup_size = workgroup_size;
+        self
+    }
+
+    /// Enable or disable the hot-reloading of the WGSL templates of the
+    /// plugin in debug builds.
+    ///
+    /// See [`shader_hot_reload`] for details.
+    ///
+    /// [`shader_hot_reload`]: crate::HanabiSettings::shader_hot_reload
+    pub fn with_shader_hot_reload(mut self, shader_hot_reload: bool) -> Self {
+        self.shader_hot_reload = shader_hot_reload;
+        self
+    }
+}
+
 /// Plugin to add systems related to Hanabi.
+///
+/// The plugin can be configured by inserting a [`HanabiSettings`] resource
+/// before adding it.
 #[derive(Debug, Clone, Copy)]
 pub struct HanabiPlugin;
 
@@ -167,17 +304,158 @@ impl HanabiPlugin {
                 .to_string_lossy(),
         )
     }
+
+    /// Finish building the plugin for a headless app, without any render
+    /// device.
+    ///
+    /// Effects are simulated on CPU if [`SimulationFallback::Cpu`] is enabled,
+    /// but are not rendered. This allows running the same effects on a
+    /// headless server, or testing them in CI.
+    fn finish_headless(app: &mut App) {
+        let fallback = app
+            .world
+            .get_resource::<SimulationFallback>()
+            .copied()
+            .unwrap_or_default();
+        let capabilities =
+            GpuCapabilities::new("<none>", vec![MissingCapability::RenderDevice], fallback);
+        let backend = capabilities.backend();
+        app.insert_resource(capabilities);
+        if backend == SimulationBackend::Cpu {
+            info!("Initializing Hanabi without render device, simulating effects on CPU.");
+            app.add_systems(
+                PostUpdate,
+                simulate_cpu_particles.after(EffectSystems::TickSpawners),
+            );
+        } else {
+            info!("Initializing Hanabi without render device, particle effects are disabled.");
+        }
+
+        // The shaders of the effects are still generated, into shader assets normally
+        // registered by the RenderPlugin.
+        if !app.world.contains_resource::<Assets<Shader>>() {
+            app.init_asset::<Shader>();
+        }
+    }
+
+    /// Finish building the plugin to simulate effects on CPU, for devices not
+    /// supporting the GPU simulation.
+    ///
+    /// See [`SimulationFallback::Cpu`] for details.
+    fn finish_cpu(app: &mut App) {
+        {
+            let cpu_shader = Shader::from_wgsl(
+                include_str!("render/vfx_cpu.wgsl"),
+                std::path::Path::new(file!())
+                    .parent()
+                    .unwrap()
+                    .join("render/vfx_cpu.wgsl")
+                    .to_string_lossy(),
+            );
+            let mut assets = app.world.resource_mut::<Assets<Shader>>();
+            assets.insert(HANABI_CPU_SHADER_HANDLE, cpu_shader);
+        }
+
+        app.add_systems(
+            PostUpdate,
+            simulate_cpu_particles.after(EffectSystems::TickSpawners),
+        );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<ExtractedCpuEffects>()
+            .init_resource::<CpuEffectsMeta>()
+            .init_resource::<CpuParticlesPipeline>()
+            .init_resource::<SpecializedRenderPipelines<CpuParticlesPipeline>>()
+            .configure_sets(
+                Render,
+                (
+                    EffectSystems::PrepareEffectAssets.in_set(RenderSet::PrepareAssets),
+                    EffectSystems::QueueEffects.in_set(RenderSet::Queue),
+                    EffectSystems::PrepareBindGroups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            )
+            .edit_schedule(ExtractSchedule, |schedule| {
+                schedule.add_systems(extract_cpu_effects);
+            })
+            .add_systems(
+                Render,
+                (
+                    prepare_cpu_effects.in_set(EffectSystems::PrepareEffectAssets),
+                    queue_cpu_effects.in_set(EffectSystems::QueueEffects),
+                    prepare_cpu_bind_groups.in_set(EffectSystems::PrepareBindGroups),
+                ),
+            );
+
+        #[cfg(feature = "2d")]
+        {
+            let draw_particles = DrawCpuEffects::new(&mut render_app.world);
+            render_app
+                .world
+                .resource::<DrawFunctions<Transparent2d>>()
+                .write()
+                .add(draw_particles);
+        }
+        #[cfg(feature = "3d")]
+        {
+            let draw_particles = DrawCpuEffects::new(&mut render_app.world);
+            render_app
+                .world
+                .resource::<DrawFunctions<Transparent3d>>()
+                .write()
+                .add(draw_particles);
+
+            let draw_particles = DrawCpuEffects::new(&mut render_app.world);
+            render_app
+                .world
+                .resource::<DrawFunctions<AlphaMask3d>>()
+                .write()
+                .add(draw_particles);
+        }
+    }
 }
 
 impl Plugin for HanabiPlugin {
     fn build(&self, app: &mut App) {
         // Register asset
         app.init_asset::<EffectAsset>()
+            .init_asset::<ColorGradient>()
+            .init_asset::<SizeCurve>()
             .add_event::<RemovedEffectsEvent>()
             .insert_resource(Random(spawn::new_rng()))
             .init_resource::<ShaderCache>()
             .init_asset_loader::<EffectAssetLoader>()
+            .init_asset_loader::<EffectVariantLoader>()
+            .register_asset_processor::<EffectAssetProcessor>(EffectAssetSaver.into())
+            .set_default_asset_processor::<EffectAssetProcessor>("effect")
             .init_resource::<Time<EffectSimulation>>()
+            .init_resource::<GlobalProperties>()
+            .init_resource::<ParticleBudget>()
+            .init_resource::<CapacityDiagnostics>()
+            .init_resource::<VoxelGrid>()
+            .init_resource::<CollisionBvh>()
+            .init_resource::<StaticColliders>()
+            .init_resource::<Lights2d>()
+            .init_resource::<ReadbackChannel<OccupancyReport>>()
+            .init_resource::<ReadbackChannel<RenderStatsReport>>()
+            .init_resource::<ReadbackChannel<BoundsReport>>()
+            .init_resource::<ReadbackChannel<ImpulseReport>>()
+            .init_resource::<ReadbackChannel<CaptureReport>>()
+            .init_resource::<ParticlePicking>()
+            .init_resource::<ReadbackChannel<Vec<PickReport>>>()
+            .init_resource::<ReadbackChannel<TrackReport>>()
+            .init_resource::<GpuTimingDiagnostics>()
+            .init_resource::<ReadbackChannel<GpuTimingReport>>()
+            .init_resource::<EffectPrecompiler>()
+            .init_resource::<ReadbackChannel<PrecompileReport>>()
+            .add_event::<CapacityExceededEvent>()
+            .add_event::<SpawnDroppedEvent>()
+            .add_plugins(ExtractResourcePlugin::<CapacityDiagnostics>::default())
+            .add_plugins(ExtractResourcePlugin::<GpuTimingDiagnostics>::default())
+            .add_plugins(ExtractResourcePlugin::<VoxelGrid>::default())
+            .add_plugins(ExtractResourcePlugin::<CollisionBvh>::default())
+            .add_plugins(ExtractResourcePlugin::<StaticColliders>::default())
+            .add_plugins(ExtractResourcePlugin::<Lights2d>::default())
             .configure_sets(
                 PostUpdate,
                 (
@@ -199,44 +477,166 @@ impl Plugin for HanabiPlugin {
                     .after(virtual_time_system)
                     .in_set(TimeSystem),
             )
+            .add_systems(
+                PreUpdate,
+                (
+                    report_group_occupancy,
+                    update_gpu_bounds,
+                    update_particle_impulses,
+                    update_particle_captures,
+                    update_particle_picking,
+                    update_particle_trackers,
+                    report_gpu_timings,
+                ),
+            )
             .add_systems(
                 PostUpdate,
                 (
                     tick_spawners.in_set(EffectSystems::TickSpawners),
+                    update_effect_lod
+                        .in_set(EffectSystems::TickSpawners)
+                        .after(tick_spawners),
+                    apply_particle_budget
+                        .in_set(EffectSystems::TickSpawners)
+                        .after(update_effect_lod),
+                    update_offscreen_throttle
+                        .in_set(EffectSystems::TickSpawners)
+                        .after(apply_particle_budget)
+                        // Needs the visibility of the current frame, which includes the
+                        // frustum culling.
+                        .after(VisibilitySystems::CheckVisibility),
+                    submit_injected_particles
+                        .in_set(EffectSystems::TickSpawners)
+                        .before(tick_spawners),
+                    tick_property_tweens
+                        .in_set(EffectSystems::TickSpawners)
+                        .before(tick_spawners),
+                    spawn_effect_children
+                        .in_set(EffectSystems::TickSpawners)
+                        .after(tick_spawners),
+                    update_shared_assets
+                        .in_set(EffectSystems::CompileEffects)
+                        .before(compile_effects),
                     compile_effects.in_set(EffectSystems::CompileEffects),
+                    update_effect_precompiler
+                        .in_set(EffectSystems::CompileEffects)
+                        .after(compile_effects),
                     update_properties_from_asset.in_set(EffectSystems::UpdatePropertiesFromAsset),
+                    update_collision_bvh.after(TransformSystem::TransformPropagate),
+                    update_joint_attachments
+                        .after(TransformSystem::TransformPropagate)
+                        .before(VisibilitySystems::CheckVisibility)
+                        .before(EffectSystems::TickSpawners),
+                    update_effect_render_targets
+                        .after(EffectSystems::CompileEffects)
+                        .before(CameraUpdateSystem),
                     gather_removed_effects.in_set(EffectSystems::GatherRemovedEffects),
+                    // Released instances are hidden, and acquired ones moved, during the
+                    // same frame.
+                    update_effect_pools
+                        .before(TransformSystem::TransformPropagate)
+                        .before(VisibilitySystems::VisibilityPropagate),
                 ),
             );
 
+        #[cfg(debug_assertions)]
+        if app
+            .world
+            .get_resource::<HanabiSettings>()
+            .is_some_and(|settings| settings.shader_hot_reload)
+        {
+            app.init_resource::<ShaderTemplateWatcher>().add_systems(
+                PostUpdate,
+                hot_reload_shader_templates
+                    .in_set(EffectSystems::CompileEffects)
+                    .before(compile_effects),
+            );
+        }
+
         // Register types with reflection
         app.register_type::<EffectAsset>()
             .register_type::<ParticleEffect>()
             .register_type::<EffectProperties>()
+            .register_type::<GlobalProperties>()
+            .register_type::<HdrColor>()
             .register_type::<Spawner>()
-            .register_type::<Time<EffectSimulation>>();
+            .register_type::<ParticleBudget>()
+            .register_type::<BudgetPriority>()
+            .register_type::<EffectParent>()
+            .register_type::<JointAttachment>()
+            .register_type::<EffectRenderTarget>()
+            .register_type::<EffectRenderTargetSource>()
+            .register_type::<ParticlePickable>()
+            .register_type::<ParticleDensityVolume>()
+            .register_type::<ParticleDensitySource>()
+            .register_type::<ParticleTracker>()
+            .register_type::<EffectPool>()
+            .register_type::<ModifierToggles>()
+            .register_type::<EffectStereoEye>()
+            .register_type::<EffectComposition>()
+            .register_type::<EffectLod>()
+            .register_type::<LodTier>()
+            .register_type::<OffscreenThrottle>()
+            .register_type::<ThrottleMode>()
+            .register_type::<PropertyTween>()
+            .register_type::<TweenEasing>()
+            .register_type::<CapacityDiagnostics>()
+            .register_type::<EffectStats>()
+            .register_type::<HanabiStats>()
+            .register_type::<GpuTimingDiagnostics>()
+            .register_type::<GpuTimingPass>()
+            .register_type::<GpuCapabilities>()
+            .register_type::<SimulationBackend>()
+            .register_type::<SimulationFallback>()
+            .register_type::<VoxelGrid>()
+            .register_type::<ParticleCollider>()
+            .register_type::<StaticColliders>()
+            .register_type::<Lights2d>()
+            .register_type::<Time<EffectSimulation>>()
+            .register_type::<HanabiSettings>();
     }
 
     fn finish(&self, app: &mut App) {
-        let render_device = app
-            .sub_app(RenderApp)
-            .world
-            .resource::<RenderDevice>()
-            .clone();
+        let Ok(render_app) = app.get_sub_app(RenderApp) else {
+            Self::finish_headless(app);
+            return;
+        };
+        let render_device = render_app.world.resource::<RenderDevice>().clone();
 
         let adapter_name = app
             .world
             .get_resource::<RenderAdapterInfo>()
-            .map(|ai| &ai.name[..])
-            .unwrap_or("<unknown>");
+            .map(|ai| ai.name.clone())
+            .unwrap_or_else(|| "<unknown>".to_string());
 
-        // Check device limits
-        let limits = render_device.limits();
-        if limits.max_bind_groups < 4 {
-            error!("Hanabi requires a GPU device supporting at least 4 bind groups (Limits::max_bind_groups).\n  Current adapter: {}\n  Supported bind groups: {}", adapter_name, limits.max_bind_groups);
-            return;
-        } else {
-            info!("Initializing Hanabi for GPU adapter {}", adapter_name);
+        // Check device capabilities, and select the simulation backend
+        let downlevel_flags = render_app
+            .world
+            .get_resource::<RenderAdapter>()
+            .map_or(DownlevelFlags::all(), |adapter| {
+                adapter.get_downlevel_capabilities().flags
+            });
+        let missing = detect_missing_capabilities(&render_device.limits(), downlevel_flags);
+        let fallback = app
+            .world
+            .get_resource::<SimulationFallback>()
+            .copied()
+            .unwrap_or_default();
+        let capabilities = GpuCapabilities::new(adapter_name.clone(), missing, fallback);
+        let backend = capabilities.backend();
+        match capabilities.diagnostic() {
+            Some(diagnostic) if backend == SimulationBackend::Cpu => warn!("{}", diagnostic),
+            Some(diagnostic) => error!("{}", diagnostic),
+            None => info!("Initializing Hanabi for GPU adapter {}", adapter_name),
+        }
+        app.insert_resource(capabilities);
+        match backend {
+            SimulationBackend::Gpu => {}
+            SimulationBackend::Cpu => {
+                Self::finish_cpu(app);
+                return;
+            }
+            SimulationBackend::Disabled => return,
         }
 
         // Insert the properly aligned `vfx_common.wgsl` shader into Assets<Shader>, so
@@ -250,25 +650,88 @@ impl Plugin for HanabiPlugin {
             assets.insert(HANABI_COMMON_TEMPLATE_HANDLE, common_shader);
         }
 
+        let settings = app
+            .world
+            .get_resource::<HanabiSettings>()
+            .copied()
+            .unwrap_or_default();
+        let workgroup_size =
+            SimulationWorkgroupSize::new(settings.workgroup_size, &render_device.limits());
         let effects_meta = EffectsMeta::new(render_device.clone());
         let effect_cache = EffectCache::new(render_device);
+        let occupancy_channel = app
+            .world
+            .resource::<ReadbackChannel<OccupancyReport>>()
+            .clone();
+        let render_stats_channel = app
+            .world
+            .resource::<ReadbackChannel<RenderStatsReport>>()
+            .clone();
+        let bounds_channel = app
+            .world
+            .resource::<ReadbackChannel<BoundsReport>>()
+            .clone();
+        let impulse_channel = app
+            .world
+            .resource::<ReadbackChannel<ImpulseReport>>()
+            .clone();
+        let capture_channel = app
+            .world
+            .resource::<ReadbackChannel<CaptureReport>>()
+            .clone();
+        let pick_channel = app
+            .world
+            .resource::<ReadbackChannel<Vec<PickReport>>>()
+            .clone();
+        let track_channel = app.world.resource::<ReadbackChannel<TrackReport>>().clone();
+        let gpu_timing_channel = app
+            .world
+            .resource::<ReadbackChannel<GpuTimingReport>>()
+            .clone();
+        let precompile_channel = app
+            .world
+            .resource::<ReadbackChannel<PrecompileReport>>()
+            .clone();
 
         // Register the custom render pipeline
         let render_app = app.sub_app_mut(RenderApp);
         render_app
+            .insert_resource(workgroup_size)
             .insert_resource(effects_meta)
             .insert_resource(effect_cache)
             .init_resource::<EffectBindGroups>()
             .init_resource::<DispatchIndirectPipeline>()
+            .init_resource::<InitDispatchPipeline>()
             .init_resource::<ParticlesInitPipeline>()
             .init_resource::<SpecializedComputePipelines<ParticlesInitPipeline>>()
             .init_resource::<ParticlesUpdatePipeline>()
             .init_resource::<SpecializedComputePipelines<ParticlesUpdatePipeline>>()
             .init_resource::<ParticlesRenderPipeline>()
             .init_resource::<SpecializedRenderPipelines<ParticlesRenderPipeline>>()
+            .init_resource::<CullMeta>()
             .init_resource::<ExtractedEffects>()
             .init_resource::<EffectAssetEvents>()
             .init_resource::<SimParams>()
+            .init_resource::<OccupancyReadback>()
+            .insert_resource(occupancy_channel)
+            .insert_resource(render_stats_channel)
+            .init_resource::<BoundsReadback>()
+            .insert_resource(bounds_channel)
+            .init_resource::<ImpulseReadback>()
+            .insert_resource(impulse_channel)
+            .init_resource::<CaptureReadback>()
+            .insert_resource(capture_channel)
+            .init_resource::<BillboardViewUniforms>()
+            .init_resource::<ViewFogUniforms>()
+            .init_resource::<PickReadback>()
+            .insert_resource(pick_channel)
+            .init_resource::<TrackReadback>()
+            .insert_resource(track_channel)
+            .init_resource::<DensityInjection>()
+            .init_resource::<GpuTimingQueries>()
+            .insert_resource(gpu_timing_channel)
+            .init_resource::<PrecompileQueue>()
+            .insert_resource(precompile_channel)
             .configure_sets(
                 Render,
                 (
@@ -279,7 +742,17 @@ impl Plugin for HanabiPlugin {
                 ),
             )
             .edit_schedule(ExtractSchedule, |schedule| {
-                schedule.add_systems((extract_effects, extract_effect_events));
+                schedule.add_systems((
+                    extract_effects,
+                    extract_effect_events,
+                    extract_stats_requests,
+                    extract_capture_requests,
+                    extract_pick_requests,
+                    extract_density_volumes,
+                    extract_track_requests,
+                    extract_precompile_requests,
+                    extract_stereo_eyes,
+                ));
             })
             .add_systems(
                 Render,
@@ -288,15 +761,45 @@ impl Plugin for HanabiPlugin {
                     queue_effects
                         .in_set(EffectSystems::QueueEffects)
                         .after(prepare_effects),
+                    prepare_particle_culling
+                        .in_set(EffectSystems::PrepareEffectGpuResources)
+                        .before(prepare_resources),
+                    (prepare_billboard_views, prepare_view_fogs)
+                        .in_set(EffectSystems::PrepareEffectGpuResources)
+                        .before(prepare_resources),
                     prepare_resources
                         .in_set(EffectSystems::PrepareEffectGpuResources)
                         .after(prepare_view_uniforms),
+                    prepare_static_colliders
+                        .in_set(EffectSystems::PrepareEffectGpuResources)
+                        .before(prepare_bind_groups),
+                    prepare_lights_2d
+                        .in_set(EffectSystems::PrepareEffectGpuResources)
+                        .before(prepare_resources),
                     prepare_bind_groups
                         .in_set(EffectSystems::PrepareBindGroups)
                         .after(queue_effects),
+                    readback_group_occupancy.in_set(RenderSet::Cleanup),
+                    readback_bounds.in_set(RenderSet::Cleanup),
+                    readback_impulses.in_set(RenderSet::Cleanup),
+                    readback_particles.in_set(RenderSet::Cleanup),
+                    pick_particles.in_set(RenderSet::Cleanup),
+                    inject_particle_density.in_set(RenderSet::Cleanup),
+                    track_particles.in_set(RenderSet::Cleanup),
+                    report_render_stats.in_set(RenderSet::Cleanup),
+                    precompile_effects
+                        .in_set(EffectSystems::QueueEffects)
+                        .after(prepare_effects),
+                    prepare_gpu_timing.in_set(RenderSet::Prepare),
+                    readback_gpu_timings.in_set(RenderSet::Cleanup),
                 ),
             );
 
+        #[cfg(feature = "fog")]
+        render_app.edit_schedule(ExtractSchedule, |schedule| {
+            schedule.add_systems(extract_fog_settings);
+        });
+
         // Register the draw function for drawing the particles. This will be called
         // during the main 2D/3D pass, at the Transparent2d/3d phase, after the
         // opaque objects have been rendered (or, rather, commands for those
@@ -328,6 +831,14 @@ impl Plugin for HanabiPlugin {
                 .unwrap()
                 .write()
                 .add(draw_particles);
+
+            let draw_particles = DrawEffects::new(&mut render_app.world);
+            render_app
+                .world
+                .get_resource::<DrawFunctions<AlphaMask3dDeferred>>()
+                .unwrap()
+                .write()
+                .add(draw_particles);
         }
 
         // Add the simulation sub-graph. This render graph runs once per frame no matter
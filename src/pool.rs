@@ -0,0 +1,336 @@
+use bevy::{ecs::system::Command, prelude::*, utils::HashSet};
+
+use crate::{EffectAsset, EffectSpawner, ParticleEffectBundle};
+
+/// Component managing a pool of pre-spawned instances of an effect.
+///
+/// Effects fired very frequently, like muzzle flashes or impacts, pay the cost
+/// of spawning a new instance each time: the entity is created, the GPU
+/// buffers of the instance are allocated, and its bind groups are created
+/// during the first frame it's rendered, which may cause a hitch. A pool
+/// instead spawns [`size`] hidden instances of the [`asset`] upfront, during
+/// the first update after the component is inserted, and hands them out with
+/// [`acquire()`]. Once done with an instance, hand it back with [`release()`],
+/// or let the pool reclaim it automatically after [`release_after`] seconds.
+///
+/// An instance acquired is made visible at the given transform, and restarted
+/// from scratch with [`EffectSpawner::restart()`], killing all the particles
+/// left from its previous use. A released instance is hidden and stopped.
+/// Only effects simulated [`SimulationCondition::WhenVisible`] are fully
+/// frozen while hidden in the pool.
+///
+/// The instances are independent entities, not children of the pool entity,
+/// so their transform is in world space. They're despawned along with the
+/// pool when the component is removed.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn setup(mut commands: Commands, effect: Res<MuzzleFlash>) {
+///     commands.spawn(EffectPool::new(effect.0.clone(), 8).with_release_after(0.5));
+/// }
+///
+/// fn fire(mut commands: Commands, mut pools: Query<&mut EffectPool>, gun: Query<&GlobalTransform>) {
+///     let mut pool = pools.single_mut();
+///     let muzzle = gun.single().compute_transform();
+///     if pool.acquire(&mut commands, muzzle).is_none() {
+///         warn!("All muzzle flashes are in use.");
+///     }
+/// }
+/// # #[derive(Resource)]
+/// # struct MuzzleFlash(Handle<EffectAsset>);
+/// ```
+///
+/// [`size`]: EffectPool::size
+/// [`asset`]: EffectPool::asset
+/// [`acquire()`]: EffectPool::acquire
+/// [`release()`]: EffectPool::release
+/// [`release_after`]: EffectPool::release_after
+/// [`SimulationCondition::WhenVisible`]: crate::SimulationCondition::WhenVisible
+#[derive(Debug, Default, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectPool {
+    /// Asset of the instances of the pool.
+    ///
+    /// Changing the asset doesn't affect the instances already spawned.
+    pub asset: Handle<EffectAsset>,
+    /// Number of instances of the pool.
+    ///
+    /// When increased, the missing instances are spawned on the next update.
+    /// When decreased, the free instances in excess are despawned.
+    pub size: u32,
+    /// Delay after which an acquired instance is released automatically, in
+    /// seconds, or `None` to only release instances with [`release()`].
+    ///
+    /// [`release()`]: EffectPool::release
+    pub release_after: Option<f32>,
+    /// Instances ready to be acquired.
+    #[reflect(ignore)]
+    free: Vec<Entity>,
+    /// Instances acquired, with the time elapsed since they were acquired.
+    #[reflect(ignore)]
+    active: Vec<(Entity, f32)>,
+}
+
+impl EffectPool {
+    /// Create a new pool of the given number of instances of an effect.
+    pub fn new(asset: Handle<EffectAsset>, size: u32) -> Self {
+        Self {
+            asset,
+            size,
+            release_after: None,
+            free: vec![],
+            active: vec![],
+        }
+    }
+
+    /// Release the acquired instances automatically after the given delay, in
+    /// seconds.
+    pub fn with_release_after(mut self, seconds: f32) -> Self {
+        self.release_after = Some(seconds);
+        self
+    }
+
+    /// Acquire a free instance, showing and restarting it at the given
+    /// transform.
+    ///
+    /// Returns the entity of the instance, or `None` if all instances are in
+    /// use, or the pool was not filled yet.
+    pub fn acquire(&mut self, commands: &mut Commands, transform: Transform) -> Option<Entity> {
+        let entity = self.free.pop()?;
+        self.active.push((entity, 0.));
+        commands.add(AcquirePooledEffect { entity, transform });
+        Some(entity)
+    }
+
+    /// Release an instance acquired from this pool, hiding and stopping it.
+    ///
+    /// Returns `false` if the entity is not an instance acquired from this
+    /// pool.
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity) -> bool {
+        let Some(index) = self.active.iter().position(|(e, _)| *e == entity) else {
+            return false;
+        };
+        self.active.swap_remove(index);
+        self.free.push(entity);
+        commands.add(ReleasePooledEffect { entity });
+        true
+    }
+
+    /// Get the number of instances ready to be acquired.
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Get the number of instances currently acquired.
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Check if an entity is an instance currently acquired from this pool.
+    pub fn is_active(&self, entity: Entity) -> bool {
+        self.active.iter().any(|(e, _)| *e == entity)
+    }
+}
+
+/// Component marking an effect instance owned by an [`EffectPool`].
+#[derive(Debug, Clone, Copy, Component)]
+pub(crate) struct PooledEffect {
+    /// Entity holding the [`EffectPool`].
+    pool: Entity,
+}
+
+/// Command showing and restarting an instance acquired from a pool.
+struct AcquirePooledEffect {
+    entity: Entity,
+    transform: Transform,
+}
+
+impl Command for AcquirePooledEffect {
+    fn apply(self, world: &mut World) {
+        let Some(mut entity) = world.get_entity_mut(self.entity) else {
+            return;
+        };
+        entity.insert((self.transform, Visibility::Inherited));
+        // Instances never shown yet have no spawner; it's created when they're
+        // first simulated.
+        if let Some(mut spawner) = entity.get_mut::<EffectSpawner>() {
+            spawner.restart();
+        }
+    }
+}
+
+/// Command hiding and stopping an instance released into a pool.
+struct ReleasePooledEffect {
+    entity: Entity,
+}
+
+impl Command for ReleasePooledEffect {
+    fn apply(self, world: &mut World) {
+        let Some(mut entity) = world.get_entity_mut(self.entity) else {
+            return;
+        };
+        entity.insert(Visibility::Hidden);
+        if let Some(mut spawner) = entity.get_mut::<EffectSpawner>() {
+            spawner.stop();
+        }
+    }
+}
+
+/// Fill the [`EffectPool`]s with their instances, and release the instances
+/// acquired for longer than their [`release_after`] delay.
+///
+/// [`release_after`]: EffectPool::release_after
+pub(crate) fn update_effect_pools(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pools: Query<(Entity, &mut EffectPool)>,
+    instances: Query<(), With<PooledEffect>>,
+    mut removed_pools: RemovedComponents<EffectPool>,
+    pooled: Query<(Entity, &PooledEffect)>,
+) {
+    // Despawn the instances of the pools removed
+    let removed: HashSet<Entity> = removed_pools.read().collect();
+    if !removed.is_empty() {
+        for (entity, pooled) in &pooled {
+            if removed.contains(&pooled.pool) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    let dt = time.delta_seconds();
+    for (pool_entity, mut pool) in &mut pools {
+        // Forget the instances despawned by the application
+        let pool = pool.bypass_change_detection();
+        pool.free.retain(|entity| instances.contains(*entity));
+        pool.active
+            .retain(|(entity, _)| instances.contains(*entity));
+
+        // Release the instances acquired for too long
+        for (_, elapsed) in &mut pool.active {
+            *elapsed += dt;
+        }
+        if let Some(release_after) = pool.release_after {
+            let mut index = 0;
+            while index < pool.active.len() {
+                let (entity, elapsed) = pool.active[index];
+                if elapsed >= release_after {
+                    pool.release(&mut commands, entity);
+                } else {
+                    index += 1;
+                }
+            }
+        }
+
+        // Spawn the missing instances, hidden, or despawn the free ones in excess
+        let size = pool.size as usize;
+        while pool.free.len() + pool.active.len() < size {
+            let entity = commands
+                .spawn((
+                    ParticleEffectBundle {
+                        visibility: Visibility::Hidden,
+                        ..ParticleEffectBundle::new(pool.asset.clone())
+                    },
+                    PooledEffect { pool: pool_entity },
+                ))
+                .id();
+            pool.free.push(entity);
+        }
+        while pool.free.len() + pool.active.len() > size {
+            let Some(entity) = pool.free.pop() else {
+                break;
+            };
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .add_systems(Update, update_effect_pools);
+        app
+    }
+
+    #[test]
+    fn acquire_release() {
+        let mut app = pool_app();
+        let pool_entity = app.world.spawn(EffectPool::new(Handle::default(), 2)).id();
+
+        // The pool is filled on the first update
+        app.update();
+        let pool = app.world.get::<EffectPool>(pool_entity).unwrap();
+        assert_eq!(pool.free_count(), 2);
+        assert_eq!(pool.active_count(), 0);
+
+        let acquire = |world: &mut World| {
+            let mut pool = world.get_mut::<EffectPool>(pool_entity).unwrap().clone();
+            let mut queue = bevy::ecs::system::CommandQueue::default();
+            let entity = pool.acquire(
+                &mut Commands::new(&mut queue, world),
+                Transform::from_xyz(1., 0., 0.),
+            );
+            queue.apply(world);
+            *world.get_mut::<EffectPool>(pool_entity).unwrap() = pool;
+            entity
+        };
+
+        let first = acquire(&mut app.world).unwrap();
+        assert_eq!(
+            app.world.get::<Visibility>(first),
+            Some(&Visibility::Inherited)
+        );
+        assert_eq!(
+            app.world.get::<Transform>(first).unwrap().translation,
+            Vec3::X
+        );
+        assert!(acquire(&mut app.world).is_some());
+        assert!(acquire(&mut app.world).is_none());
+
+        // Released instances can be acquired again
+        let mut pool = app.world.get::<EffectPool>(pool_entity).unwrap().clone();
+        let mut queue = bevy::ecs::system::CommandQueue::default();
+        assert!(pool.release(&mut Commands::new(&mut queue, &app.world), first));
+        assert!(!pool.release(&mut Commands::new(&mut queue, &app.world), first));
+        queue.apply(&mut app.world);
+        *app.world.get_mut::<EffectPool>(pool_entity).unwrap() = pool;
+        assert_eq!(
+            app.world.get::<Visibility>(first),
+            Some(&Visibility::Hidden)
+        );
+        assert_eq!(acquire(&mut app.world), Some(first));
+    }
+
+    #[test]
+    fn remove_pool() {
+        let mut app = pool_app();
+        let pool_entity = app.world.spawn(EffectPool::new(Handle::default(), 3)).id();
+        app.update();
+        assert_eq!(
+            app.world.query::<&PooledEffect>().iter(&app.world).count(),
+            3
+        );
+
+        // Shrinking the pool despawns the free instances in excess
+        app.world.get_mut::<EffectPool>(pool_entity).unwrap().size = 1;
+        app.update();
+        assert_eq!(
+            app.world.query::<&PooledEffect>().iter(&app.world).count(),
+            1
+        );
+
+        app.world.despawn(pool_entity);
+        app.update();
+        assert_eq!(
+            app.world.query::<&PooledEffect>().iter(&app.world).count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,295 @@
+#[cfg(feature = "2d")]
+use bevy::utils::FloatOrd;
+use bevy::{asset::LoadState, prelude::*, utils::HashMap};
+
+use crate::{
+    render::ReadbackChannel, CompiledParticleEffect, EffectAsset, EffectShader, LayoutFlags,
+    ParticleLayout, PropertyLayout, ShaderCache,
+};
+
+/// Compilation state of an effect asset registered with the
+/// [`EffectPrecompiler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum PrecompileState {
+    /// The asset is not loaded yet, or its shaders are not generated yet.
+    Pending,
+    /// The shaders of the asset are generated, and its pipelines are being
+    /// compiled by the render world.
+    Compiling,
+    /// All the pipelines of the asset are compiled. Spawning an instance of
+    /// the effect won't compile any new pipeline, unless it's rendered by a new
+    /// kind of view.
+    Ready,
+    /// The asset failed to load, or one of its shaders or pipelines failed to
+    /// compile. The error is logged.
+    Failed,
+}
+
+impl PrecompileState {
+    /// Check if the compilation completed, successfully or not.
+    pub fn is_done(&self) -> bool {
+        matches!(self, PrecompileState::Ready | PrecompileState::Failed)
+    }
+}
+
+/// Everything the render world needs to specialize the pipelines of an effect
+/// asset, without any effect instance.
+#[derive(Debug, Clone)]
+pub(crate) struct PrecompileRequest {
+    pub id: AssetId<EffectAsset>,
+    pub effect_shader: EffectShader,
+    pub particle_layout: ParticleLayout,
+    pub property_layout: PropertyLayout,
+    pub layout_flags: LayoutFlags,
+    pub group_layout_flags: Vec<LayoutFlags>,
+    pub has_injection: bool,
+}
+
+/// Effect asset registered with the [`EffectPrecompiler`].
+#[derive(Debug)]
+struct PrecompileEntry {
+    /// Strong handle keeping the asset loaded until its pipelines are compiled.
+    handle: Handle<EffectAsset>,
+    /// Current compilation state.
+    state: PrecompileState,
+    /// Request sent to the render world while compiling.
+    request: Option<PrecompileRequest>,
+}
+
+/// Compile the pipelines of effect assets ahead of time.
+///
+/// The GPU pipelines of an effect are generally compiled the first time an
+/// instance of that effect is spawned, which can take long enough to stall the
+/// render thread for a few frames, for example on the first explosion of a
+/// game. Registering the effect assets with [`precompile()`] during a loading
+/// screen generates their shaders and compiles their pipelines in the
+/// background, without spawning any instance, so they're ready to be used
+/// when needed.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn start_loading(
+///     asset_server: Res<AssetServer>,
+///     mut precompiler: ResMut<EffectPrecompiler>,
+/// ) {
+///     precompiler.precompile(asset_server.load("explosion.effect"));
+/// }
+///
+/// fn check_loading(precompiler: Res<EffectPrecompiler>) {
+///     if precompiler.all_done() {
+///         // Leave the loading screen
+///     }
+/// }
+/// ```
+///
+/// Pipelines are specialized for the views existing at the time they're
+/// compiled, so the loading screen should already use the same kind of cameras
+/// (2D or 3D, HDR or not) as the game. If no camera exists, the render
+/// pipelines are compiled for a non-HDR view with the current [`Msaa`].
+///
+/// [`precompile()`]: crate::EffectPrecompiler::precompile
+#[derive(Debug, Default, Resource)]
+pub struct EffectPrecompiler {
+    entries: HashMap<AssetId<EffectAsset>, PrecompileEntry>,
+}
+
+impl EffectPrecompiler {
+    /// Register an effect asset to compile its pipelines ahead of time.
+    ///
+    /// The asset doesn't need to be loaded yet; its pipelines are compiled
+    /// once it's loaded. Registering an asset already registered does nothing.
+    /// The precompiler keeps the asset loaded until it's [`remove()`]d.
+    ///
+    /// [`remove()`]: crate::EffectPrecompiler::remove
+    pub fn precompile(&mut self, handle: Handle<EffectAsset>) {
+        self.entries
+            .entry(handle.id())
+            .or_insert_with(|| PrecompileEntry {
+                handle,
+                state: PrecompileState::Pending,
+                request: None,
+            });
+    }
+
+    /// Unregister an effect asset, releasing its handle.
+    ///
+    /// This doesn't release the compiled pipelines, which are kept by the
+    /// render world and reused by any instance of the effect.
+    pub fn remove(&mut self, id: impl Into<AssetId<EffectAsset>>) {
+        self.entries.remove(&id.into());
+    }
+
+    /// Get the compilation state of a registered effect asset, or `None` if
+    /// the asset is not registered.
+    pub fn state(&self, id: impl Into<AssetId<EffectAsset>>) -> Option<PrecompileState> {
+        self.entries.get(&id.into()).map(|entry| entry.state)
+    }
+
+    /// Check if all the pipelines of a registered effect asset are compiled.
+    pub fn is_ready(&self, id: impl Into<AssetId<EffectAsset>>) -> bool {
+        self.state(id) == Some(PrecompileState::Ready)
+    }
+
+    /// Check if the compilation of all registered effect assets completed,
+    /// successfully or not.
+    ///
+    /// Failed compilations are considered completed, so that a loading screen
+    /// waiting for this doesn't wait forever.
+    pub fn all_done(&self) -> bool {
+        self.entries.values().all(|entry| entry.state.is_done())
+    }
+
+    /// Get the number of registered effect assets whose compilation completed,
+    /// and the total number of registered effect assets, for example to
+    /// display a progress bar.
+    pub fn progress(&self) -> (usize, usize) {
+        let done = self
+            .entries
+            .values()
+            .filter(|entry| entry.state.is_done())
+            .count();
+        (done, self.entries.len())
+    }
+
+    /// Iterate over the requests of the assets currently being compiled.
+    pub(crate) fn requests(&self) -> impl Iterator<Item = &PrecompileRequest> {
+        self.entries
+            .values()
+            .filter(|entry| entry.state == PrecompileState::Compiling)
+            .filter_map(|entry| entry.request.as_ref())
+    }
+
+    /// Complete the compilation of an asset.
+    fn complete(&mut self, id: AssetId<EffectAsset>, state: PrecompileState) {
+        if let Some(entry) = self
+            .entries
+            .get_mut(&id)
+            .filter(|entry| entry.state == PrecompileState::Compiling)
+        {
+            entry.state = state;
+            entry.request = None;
+        }
+    }
+}
+
+/// Result of the pipeline compilation of an effect asset, sent by the render
+/// world to the main world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PrecompileReport {
+    /// Compiled asset.
+    pub id: AssetId<EffectAsset>,
+    /// Final state of the asset, either [`PrecompileState::Ready`] or
+    /// [`PrecompileState::Failed`].
+    pub state: PrecompileState,
+}
+
+/// Generate the shaders of the effect assets registered with the
+/// [`EffectPrecompiler`] once they're loaded, and update their state with the
+/// results sent by the render world.
+///
+/// This system runs in the [`EffectSystems::CompileEffects`] set.
+///
+/// [`EffectSystems::CompileEffects`]: crate::EffectSystems::CompileEffects
+pub(crate) fn update_effect_precompiler(
+    assets: Res<Assets<EffectAsset>>,
+    asset_server: Option<Res<AssetServer>>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut shader_cache: ResMut<ShaderCache>,
+    channel: Res<ReadbackChannel<PrecompileReport>>,
+    mut precompiler: ResMut<EffectPrecompiler>,
+) {
+    trace!("update_effect_precompiler");
+
+    for report in channel.take() {
+        precompiler.complete(report.id, report.state);
+    }
+
+    for entry in precompiler
+        .entries
+        .values_mut()
+        .filter(|entry| entry.state == PrecompileState::Pending)
+    {
+        let Some(asset) = assets.get(&entry.handle) else {
+            let failed = asset_server.as_ref().is_some_and(|asset_server| {
+                asset_server.get_load_state(&entry.handle) == Some(LoadState::Failed)
+            });
+            if failed {
+                error!(
+                    "Failed to load effect asset {:?} for pipeline pre-compilation.",
+                    entry.handle
+                );
+                entry.state = PrecompileState::Failed;
+            }
+            continue;
+        };
+
+        // Generate the shaders like for any instance without LOD, which also makes
+        // them available to the instances spawned later.
+        let mut compiled = CompiledParticleEffect::default();
+        compiled.update(
+            false,
+            #[cfg(feature = "2d")]
+            FloatOrd(asset.z_layer_2d),
+            #[cfg(feature = "2d")]
+            None,
+            entry.handle.clone(),
+            asset,
+            asset.simulation_space,
+            None,
+            None,
+            None,
+            None,
+            &mut shaders,
+            &mut shader_cache,
+        );
+        let Some(effect_shader) = compiled.get_configured_shader() else {
+            entry.state = PrecompileState::Failed;
+            continue;
+        };
+
+        entry.request = Some(PrecompileRequest {
+            id: entry.handle.id(),
+            effect_shader,
+            particle_layout: asset.particle_layout(),
+            property_layout: asset.property_layout(),
+            layout_flags: compiled.layout_flags,
+            group_layout_flags: compiled.group_layout_flags,
+            has_injection: asset.injection_capacity > 0,
+        });
+        entry.state = PrecompileState::Compiling;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precompiler_state() {
+        let mut precompiler = EffectPrecompiler::default();
+        assert!(precompiler.all_done());
+        assert_eq!(precompiler.progress(), (0, 0));
+
+        let handle = Handle::<EffectAsset>::weak_from_u128(42);
+        precompiler.precompile(handle.clone());
+        precompiler.precompile(handle.clone());
+        assert_eq!(precompiler.state(&handle), Some(PrecompileState::Pending));
+        assert!(!precompiler.is_ready(&handle));
+        assert!(!precompiler.all_done());
+        assert_eq!(precompiler.progress(), (0, 1));
+
+        // Results for assets not being compiled are ignored
+        precompiler.complete(handle.id(), PrecompileState::Ready);
+        assert_eq!(precompiler.state(&handle), Some(PrecompileState::Pending));
+
+        precompiler.entries.get_mut(&handle.id()).unwrap().state = PrecompileState::Compiling;
+        precompiler.complete(handle.id(), PrecompileState::Ready);
+        assert!(precompiler.is_ready(&handle));
+        assert!(precompiler.all_done());
+        assert_eq!(precompiler.progress(), (1, 1));
+
+        precompiler.remove(&handle);
+        assert_eq!(precompiler.state(&handle), None);
+    }
+}
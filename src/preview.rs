@@ -0,0 +1,272 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::CameraProjection,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{graph::ExprError, CpuSimulation, EffectAsset, EffectProperties};
+
+/// Offscreen renderer of effect previews, for asset browsers and editors.
+///
+/// The preview of an effect asset is rendered into an [`Image`], without
+/// spawning the effect in any app. The effect is simulated for
+/// [`duration`] seconds with a [`CpuSimulation`], then its particles are
+/// rasterized on CPU as camera-facing quads, alpha-blended back to front.
+///
+/// The preview is an approximation of the final effect: the limitations of
+/// [`CpuSimulation`] apply, and the particles are drawn as flat colored
+/// quads, without any texture, lighting, or orientation.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// let mut module = Module::default();
+/// let lifetime = module.lit(2.);
+/// let asset = EffectAsset::new(vec![16], Spawner::once(4.0.into(), true), module)
+///     .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+///     .render(SetColorModifier {
+///         color: Vec4::new(1., 0., 0., 1.).into(),
+///     });
+///
+/// let image = EffectPreview::default()
+///     .with_size(UVec2::new(128, 128))
+///     .with_duration(0.5)
+///     .render(&asset)
+///     .unwrap();
+/// assert_eq!(image.width(), 128);
+/// ```
+///
+/// [`duration`]: EffectPreview::duration
+#[derive(Debug, Clone)]
+pub struct EffectPreview {
+    /// Size of the rendered image, in pixels.
+    pub size: UVec2,
+    /// Simulation time after which the preview is rendered, in seconds.
+    pub duration: f32,
+    /// Maximum delta time of each simulation step, in seconds.
+    pub time_step: f32,
+    /// Seed of the simulation, to render deterministic previews.
+    pub seed: u32,
+    /// Transform of the camera the preview is rendered from, looking along
+    /// its local -Z axis like any Bevy camera.
+    pub camera: Transform,
+    /// Projection of the camera the preview is rendered from.
+    pub projection: Projection,
+    /// Color of the background of the preview.
+    pub background: Color,
+    /// Properties the effect is simulated with, or `None` to use the default
+    /// value of the properties of the asset.
+    pub properties: Option<EffectProperties>,
+}
+
+impl Default for EffectPreview {
+    fn default() -> Self {
+        Self {
+            size: UVec2::new(256, 256),
+            duration: 1.,
+            time_step: 1. / 60.,
+            seed: 0,
+            camera: Transform::from_xyz(0., 0., 10.).looking_at(Vec3::ZERO, Vec3::Y),
+            projection: Projection::Perspective(default()),
+            background: Color::NONE,
+            properties: None,
+        }
+    }
+}
+
+impl EffectPreview {
+    /// Set the size of the rendered image, in pixels.
+    pub fn with_size(mut self, size: UVec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Set the simulation time after which the preview is rendered, in
+    /// seconds.
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Set the maximum delta time of each simulation step, in seconds.
+    pub fn with_time_step(mut self, time_step: f32) -> Self {
+        self.time_step = time_step;
+        self
+    }
+
+    /// Set the seed of the simulation.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Set the transform and projection of the camera the preview is rendered
+    /// from.
+    pub fn with_camera(mut self, camera: Transform, projection: Projection) -> Self {
+        self.camera = camera;
+        self.projection = projection;
+        self
+    }
+
+    /// Set the color of the background of the preview.
+    pub fn with_background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Set the properties the effect is simulated with.
+    pub fn with_properties(mut self, properties: EffectProperties) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+
+    /// Simulate an effect asset and render its preview.
+    ///
+    /// The returned image has the [`TextureFormat::Rgba8UnormSrgb`] format,
+    /// and is usable both on CPU and as a texture, for example in a UI.
+    pub fn render(&self, asset: &EffectAsset) -> Result<Image, ExprError> {
+        let mut sim = CpuSimulation::new(asset, self.seed);
+        let steps = (self.duration / self.time_step.max(f32::EPSILON))
+            .ceil()
+            .max(1.);
+        let dt = self.duration.max(0.) / steps;
+        for _ in 0..steps as u32 {
+            sim.step(asset, self.properties.as_ref(), dt)?;
+        }
+
+        let width = self.size.x.max(1);
+        let height = self.size.y.max(1);
+        let viewport = Vec2::new(width as f32, height as f32);
+        let mut projection = self.projection.clone();
+        projection.update(viewport.x, viewport.y);
+        let proj = projection.get_projection_matrix();
+        let view_proj = proj * self.camera.compute_matrix().inverse();
+
+        // Project the particles, and sort them back to front
+        let mut quads: Vec<_> = sim
+            .instances()
+            .iter()
+            .filter_map(|particle| {
+                let clip = view_proj * Vec3::from(particle.position).extend(1.);
+                if clip.w <= 0. {
+                    return None;
+                }
+                let ndc = clip.truncate() / clip.w;
+                let center = Vec2::new(ndc.x + 1., 1. - ndc.y) * 0.5 * viewport;
+                let half_size = Vec2::from(particle.size)
+                    * Vec2::new(proj.x_axis.x, proj.y_axis.y)
+                    * 0.25
+                    * viewport
+                    / clip.w;
+                Some((clip.w, center, half_size.abs(), Vec4::from(particle.color)))
+            })
+            .collect();
+        quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // Blend in linear space, with premultiplied alpha
+        let background = Vec4::from(self.background.as_linear_rgba_f32());
+        let background = (background.truncate() * background.w).extend(background.w);
+        let mut pixels = vec![background; (width * height) as usize];
+        for (_, center, half_size, color) in quads {
+            // Quads smaller than a pixel cover at least one pixel, faded by
+            // their actual coverage
+            let covered = half_size.max(Vec2::splat(0.5));
+            let coverage = half_size / covered;
+            let alpha = (color.w * coverage.x * coverage.y).clamp(0., 1.);
+            if alpha <= 0. {
+                continue;
+            }
+            let min = (center - covered).round().max(Vec2::ZERO);
+            let max = (center + covered).round().min(viewport);
+            for y in min.y as u32..max.y as u32 {
+                for x in min.x as u32..max.x as u32 {
+                    let dst = &mut pixels[(y * width + x) as usize];
+                    *dst = (color.truncate() * alpha).extend(alpha) + *dst * (1. - alpha);
+                }
+            }
+        }
+
+        let data = pixels
+            .into_iter()
+            .flat_map(|pixel| {
+                let pixel = pixel.clamp(Vec4::ZERO, Vec4::ONE);
+                // Unpremultiply the color for storage
+                let rgb = if pixel.w > 0. {
+                    pixel.truncate() / pixel.w
+                } else {
+                    Vec3::ZERO
+                };
+                // Round to the nearest value, as the sRGB conversion of an exact 1.0 can be
+                // slightly smaller
+                Color::rgba_linear(rgb.x, rgb.y, rgb.z, pixel.w)
+                    .as_rgba_f32()
+                    .map(|c| (c * 255.).round() as u8)
+            })
+            .collect();
+        Ok(Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, Module, SetAttributeModifier, SetColorModifier, Spawner};
+
+    #[test]
+    fn render() {
+        // A single particle of 1 world unit, projected to about 4x4 pixels, which
+        // fully covers the center pixel
+        let mut module = Module::default();
+        let size = module.lit(1.);
+        let lifetime = module.lit(10.);
+        let asset = EffectAsset::new(vec![16], Spawner::once(1.0.into(), true), module)
+            .init(SetAttributeModifier::new(Attribute::SIZE, size))
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+            .render(SetColorModifier {
+                color: Vec4::new(1., 0., 0., 1.).into(),
+            });
+
+        let preview = EffectPreview::default().with_size(UVec2::new(64, 32));
+        let image = preview.render(&asset).unwrap();
+        assert_eq!(image.width(), 64);
+        assert_eq!(image.height(), 32);
+        assert_eq!(
+            image.texture_descriptor.format,
+            TextureFormat::Rgba8UnormSrgb
+        );
+        let pixel = |x: u32, y: u32| {
+            let index = ((y * 64 + x) * 4) as usize;
+            &image.data[index..index + 4]
+        };
+
+        // The single particle is at the center of the view
+        assert_eq!(pixel(32, 16), &[255, 0, 0, 255]);
+        assert_eq!(pixel(40, 16), &[0, 0, 0, 0]);
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(63, 31), &[0, 0, 0, 0]);
+
+        // Particles behind the camera are not rendered
+        let image = preview
+            .with_camera(
+                Transform::from_xyz(0., 0., 10.).looking_at(Vec3::new(0., 0., 20.), Vec3::Y),
+                Projection::Perspective(default()),
+            )
+            .with_background(Color::WHITE)
+            .render(&asset)
+            .unwrap();
+        assert!(image.data.iter().all(|c| *c == 255));
+    }
+}
@@ -0,0 +1,331 @@
+use bevy::{
+    asset::{
+        io::Writer,
+        saver::{AssetSaver, SavedAsset},
+        AsyncWriteExt,
+    },
+    log::debug,
+    utils::{thiserror::Error, BoxedFuture},
+};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset::EffectAssetLoader, lod::LodShaderConfig, render::ShaderTemplates,
+    ColorOverLifetimeTextureModifier, EffectAsset, EffectDiagnostic, EffectShaderSource,
+    LayoutFlags, ParticleTextureModifier, SimulationSpace, ZLayer2dModifier,
+};
+
+/// Shader code of an [`EffectAsset`] generated ahead of time, when the asset
+/// was processed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PregeneratedShaders {
+    /// Hash of the shader templates and of the asset the shaders were
+    /// generated from, to detect stale shaders.
+    source_hash: u64,
+    /// Simulation space the shaders simulate the particles in.
+    simulation_space: SimulationSpace,
+    /// Source code of the init shader.
+    init: String,
+    /// Source code of the init shaders of the additional emitters.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    emitter_init: Vec<String>,
+    /// Source code of the update shader of each group.
+    update: Vec<String>,
+    /// Source code of the render shader of each group.
+    render: Vec<String>,
+    /// Bits of the layout flags of each group.
+    group_layout_flags: Vec<u32>,
+}
+
+/// Error processing an [`EffectAsset`].
+#[derive(Debug, Error)]
+pub enum EffectAssetProcessError {
+    /// I/O error writing the processed asset.
+    #[error("An IO error occurred during processing of a particle effect")]
+    Io(#[from] std::io::Error),
+
+    /// Error during RON format serialization.
+    #[error("A RON format error occurred during processing of a particle effect")]
+    Ron(#[from] ron::Error),
+
+    /// The asset is invalid. This is the first error reported by
+    /// [`EffectAsset::validate()`].
+    #[error("The particle effect is invalid: {0}")]
+    Invalid(EffectDiagnostic),
+
+    /// Error generating the shaders of the asset.
+    #[error("Failed to generate the shaders of a particle effect: {0}")]
+    Generate(String),
+}
+
+/// Hash the shader templates and the asset the shaders are generated from.
+///
+/// The hash is stable across platforms and runs, since the asset may be
+/// processed on a different machine than the one it's loaded on. Any
+/// pre-generated shader stored in the asset is not part of the hash.
+fn source_hash(templates: &ShaderTemplates, asset: &EffectAsset) -> Result<u64, ron::Error> {
+    let asset = if asset.pregenerated_shaders().is_some() {
+        let mut asset = asset.clone();
+        asset.set_pregenerated_shaders(None);
+        ron::to_string(&asset)?
+    } else {
+        ron::to_string(asset)?
+    };
+
+    // FNV-1a
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for source in [
+        &templates.init,
+        &templates.update,
+        &templates.render,
+        &templates.cull,
+    ]
+    .into_iter()
+    .map(AsRef::<str>::as_ref)
+    .chain(std::iter::once(asset.as_str()))
+    {
+        for byte in source.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Ok(hash)
+}
+
+impl EffectAsset {
+    /// Generate the shaders of the effect ahead of time.
+    ///
+    /// The generated WGSL code and its layout metadata are stored in the
+    /// asset, and serialized with it. When an instance of the effect is
+    /// spawned, the pre-generated shaders are used instead of generating them
+    /// again, unless they're stale because the asset was modified since, the
+    /// instance overrides the simulation space of the asset, or a LOD tier
+    /// applies to the instance.
+    ///
+    /// This is normally called by the [`EffectAssetSaver`] when the asset is
+    /// processed by the Bevy asset processor, so the cost of generating the
+    /// shaders is paid at build time, and any error in the effect is caught
+    /// before shipping.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`validate()`] reports any error, or if the shaders
+    /// fail to generate. Any shader previously generated is discarded.
+    ///
+    /// [`validate()`]: crate::EffectAsset::validate
+    pub fn pregenerate_shaders(&mut self) -> Result<(), EffectAssetProcessError> {
+        self.set_pregenerated_shaders(None);
+        if let Some(diagnostic) = self.validate().into_iter().find(|d| d.is_error()) {
+            return Err(EffectAssetProcessError::Invalid(diagnostic));
+        }
+
+        let templates = ShaderTemplates::default();
+        let source = EffectShaderSource::generate(&templates, self, self.simulation_space, None)
+            .map_err(|err| EffectAssetProcessError::Generate(err.to_string()))?;
+        let pregenerated = PregeneratedShaders {
+            source_hash: source_hash(&templates, self)?,
+            simulation_space: self.simulation_space,
+            init: source.init,
+            emitter_init: source.emitter_init,
+            update: source.update,
+            render: source.render,
+            group_layout_flags: source
+                .group_layout_flags
+                .iter()
+                .map(|flags| flags.bits())
+                .collect(),
+        };
+        self.set_pregenerated_shaders(Some(pregenerated));
+        Ok(())
+    }
+
+    /// Check if the asset contains shaders generated ahead of time.
+    ///
+    /// See [`pregenerate_shaders()`] for details.
+    ///
+    /// [`pregenerate_shaders()`]: crate::EffectAsset::pregenerate_shaders
+    pub fn has_pregenerated_shaders(&self) -> bool {
+        self.pregenerated_shaders().is_some()
+    }
+}
+
+impl EffectShaderSource {
+    /// Get the shader source code pre-generated when the asset was processed,
+    /// if it's still valid for the given templates, simulation space, and LOD
+    /// tier.
+    pub(crate) fn pregenerated(
+        templates: &ShaderTemplates,
+        asset: &EffectAsset,
+        simulation_space: SimulationSpace,
+        lod: Option<&LodShaderConfig>,
+    ) -> Option<EffectShaderSource> {
+        let pregenerated = asset.pregenerated_shaders()?;
+        if lod.is_some() || pregenerated.simulation_space != simulation_space {
+            return None;
+        }
+        if source_hash(templates, asset).ok()? != pregenerated.source_hash {
+            debug!(
+                "Ignoring stale pre-generated shaders of effect asset {}.",
+                asset.name
+            );
+            return None;
+        }
+
+        let group_layout_flags: Vec<_> = pregenerated
+            .group_layout_flags
+            .iter()
+            .map(|bits| LayoutFlags::from_bits_truncate(*bits))
+            .collect();
+        let layout_flags = group_layout_flags
+            .iter()
+            .fold(LayoutFlags::NONE, |acc, flags| acc | *flags);
+        // Textures are not serialized, so are taken from the asset itself
+        let particle_textures = (0..asset.capacities().len() as u32)
+            .map(|group_index| {
+                asset
+                    .render_modifiers_for_group(group_index)
+                    .filter_map(|m| {
+                        m.as_modifier()
+                            .as_any()
+                            .downcast_ref::<ParticleTextureModifier>()
+                            .map(|m| m.texture.clone())
+                    })
+                    .last()
+            })
+            .collect();
+        let color_luts = (0..asset.capacities().len() as u32)
+            .map(|group_index| {
+                asset
+                    .render_modifiers_for_group(group_index)
+                    .filter_map(|m| {
+                        m.as_modifier()
+                            .as_any()
+                            .downcast_ref::<ColorOverLifetimeTextureModifier>()
+                            .map(|m| m.texture.clone())
+                    })
+                    .last()
+            })
+            .collect();
+        let z_layers_2d = (0..asset.capacities().len() as u32)
+            .map(|group_index| {
+                asset
+                    .render_modifiers_for_group(group_index)
+                    .filter_map(|m| {
+                        m.as_modifier()
+                            .as_any()
+                            .downcast_ref::<ZLayer2dModifier>()
+                            .map(ZLayer2dModifier::sorted_layers)
+                    })
+                    .last()
+                    .unwrap_or_default()
+            })
+            .collect();
+        Some(EffectShaderSource {
+            init: pregenerated.init.clone(),
+            emitter_init: pregenerated.emitter_init.clone(),
+            update: pregenerated.update.clone(),
+            render: pregenerated.render.clone(),
+            layout_flags,
+            group_layout_flags,
+            particle_textures,
+            color_luts,
+            z_layers_2d,
+        })
+    }
+}
+
+/// Saver of processed [`EffectAsset`], for the Bevy asset processor.
+///
+/// When the Bevy asset processor is enabled, with [`AssetMode::Processed`],
+/// effect assets with the `.effect` extension are processed by default by
+/// loading them, generating their shaders with
+/// [`EffectAsset::pregenerate_shaders()`], and saving them along with their
+/// shaders in the same RON format. Processing fails for invalid effects,
+/// which catches errors at build time instead of when the effect is first
+/// spawned.
+///
+/// [`AssetMode::Processed`]: bevy::asset::AssetMode::Processed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EffectAssetSaver;
+
+impl AssetSaver for EffectAssetSaver {
+    type Asset = EffectAsset;
+
+    type Settings = ();
+
+    type OutputLoader = EffectAssetLoader;
+
+    type Error = EffectAssetProcessError;
+
+    fn save<'a>(
+        &'a self,
+        writer: &'a mut Writer,
+        asset: SavedAsset<'a, Self::Asset>,
+        _settings: &'a Self::Settings,
+    ) -> BoxedFuture<'a, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let mut asset = EffectAsset::clone(&asset);
+            asset.pregenerate_shaders()?;
+            let text = ron::ser::to_string_pretty(&asset, PrettyConfig::default())?;
+            writer.write_all(text.as_bytes()).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::{Attribute, Module, SetAttributeModifier, Spawner};
+
+    #[test]
+    fn pregenerate_shaders() {
+        let mut module = Module::default();
+        let position = module.lit(Vec3::ZERO);
+        let zero = module.lit(0.);
+        let lifetime = module.lit(1.);
+        let mut asset = EffectAsset::new(vec![32], Spawner::rate(5.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::POSITION, position))
+            .init(SetAttributeModifier::new(Attribute::AGE, zero))
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime));
+        assert!(!asset.has_pregenerated_shaders());
+        asset.pregenerate_shaders().unwrap();
+        assert!(asset.has_pregenerated_shaders());
+
+        // The pre-generated shaders survive serialization, and match the ones
+        // generated at runtime
+        let text = ron::to_string(&asset).unwrap();
+        let asset: EffectAsset = ron::from_str(&text).unwrap();
+        let templates = ShaderTemplates::default();
+        let space = asset.simulation_space;
+        let pregenerated =
+            EffectShaderSource::pregenerated(&templates, &asset, space, None).unwrap();
+        let generated = EffectShaderSource::generate(&templates, &asset, space, None).unwrap();
+        assert_eq!(pregenerated.init, generated.init);
+        assert_eq!(pregenerated.update, generated.update);
+        assert_eq!(pregenerated.render, generated.render);
+        assert_eq!(pregenerated.layout_flags, generated.layout_flags);
+
+        // Stale shaders are ignored
+        let lod = LodShaderConfig {
+            size_scale: 0.5,
+            disabled_modifiers: vec![],
+        };
+        assert!(EffectShaderSource::pregenerated(&templates, &asset, space, Some(&lod)).is_none());
+        let mut modified = asset.clone();
+        modified.prewarm = 1.;
+        assert!(EffectShaderSource::pregenerated(&templates, &modified, space, None).is_none());
+
+        // Invalid assets fail to process
+        let mut asset = EffectAsset::new(vec![0], Spawner::rate(5.0.into()), Module::default());
+        assert!(matches!(
+            asset.pregenerate_shaders(),
+            Err(EffectAssetProcessError::Invalid(
+                EffectDiagnostic::ZeroCapacity { group_index: 0 }
+            ))
+        ));
+    }
+}
@@ -67,8 +67,27 @@
 //! initial value of some or all of the properties. Note that the component is
 //! not automatically added if the [`Module`] doesn't declare any property.
 //!
-//! To change the value of a property, call [`EffectProperties::set()`] or
-//! [`EffectProperties::set_if_changed()`]. The former is easier to use but
+//! To change the value of a property, use a typed [`PropertyMut`] handle
+//! returned by [`EffectProperties::property()`], or call
+//! [`EffectProperties::set()`] or [`EffectProperties::set_if_changed()`].
+//!
+//! The typed handle checks that the property exists and has the requested
+//! type, and reports an error otherwise, so typos in property names don't go
+//! unnoticed.
+//!
+//! ```
+//! # use bevy_hanabi::*;
+//! # use bevy::prelude::*;
+//! fn blow_wind(mut query: Query<&mut EffectProperties>) {
+//!   let mut effect_properties = query.single_mut();
+//!   match effect_properties.property::<Vec3>("wind") {
+//!     Ok(mut wind) => wind.set(Vec3::X * 3.),
+//!     Err(err) => warn!("Cannot set wind: {}", err),
+//!   }
+//! }
+//! ```
+//!
+//! The untyped [`EffectProperties::set()`] is easier to use but
 //! always triggers the change detection mechanism, even if the value assigned
 //! is the same as the previous one. [`EffectProperties::set_if_changed()`] on
 //! the other hand will compare the current value and only assign if different.
@@ -86,23 +105,49 @@
 //! }
 //! ```
 //!
+//! # Animation
+//!
+//! Property values can't currently be keyframed in an `AnimationClip` of
+//! `bevy_animation`, which in Bevy 0.13 only animates the [`Transform`] and
+//! the morph weights of an entity, and has no extension point for other
+//! targets. To animate a property from one value to another, insert a
+//! [`PropertyTween`] on the entity of the effect instance. For more complex
+//! animations, write a system sampling your own curves, for example a
+//! [`Gradient`], and assigning the result with [`EffectProperties::set()`].
+//! The same applies to the spawn rate, which such a system can drive by
+//! overriding the [`EffectSpawner::spawn_count`] each frame.
+//!
+//! [`Transform`]: bevy::transform::components::Transform
+//! [`PropertyTween`]: crate::PropertyTween
+//! [`Gradient`]: crate::Gradient
+//! [`EffectSpawner::spawn_count`]: crate::EffectSpawner::spawn_count
 //! [`Module`]: crate::Module
 //! [`Module::add_property()`]: crate::Module::add_property
 //! [`EffectAsset`]: crate::EffectAsset
 //! [`add_property()`]: crate::Module::add_property
 //! [`ParticleEffect`]: crate::ParticleEffect
 
-use std::num::NonZeroU64;
+use std::{marker::PhantomData, num::NonZeroU64};
 
 use bevy::{
-    ecs::{component::Component, reflect::ReflectComponent, world::Mut},
+    ecs::{
+        component::Component,
+        reflect::{ReflectComponent, ReflectResource},
+        system::Resource,
+        world::Mut,
+    },
     log::trace,
+    math::Vec4,
     reflect::Reflect,
-    utils::HashSet,
+    render::color::Color,
+    utils::{thiserror::Error, HashSet},
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{graph::Value, next_multiple_of, ToWgslString, ValueType};
+use crate::{
+    graph::{ExprError, Value},
+    next_multiple_of, ToWgslString, ValueType,
+};
 
 /// A single property of an [`EffectAsset`].
 ///
@@ -171,6 +216,98 @@ impl ToWgslString for Property {
     }
 }
 
+/// HDR color value for a color property.
+///
+/// Shaders operate on linear colors, so assigning a raw [`Vec4`] to a color
+/// property requires converting colors authored in sRGB space first, which is
+/// easy to forget. This type stores a Bevy [`Color`] in any color space, and
+/// an HDR intensity multiplier, and converts into a [`Value`] holding the
+/// linear RGBA color, with the RGB components scaled by the intensity. This
+/// makes the intent of a color property explicit, and the type is reflected
+/// so inspectors show a color picker instead of a raw vector.
+///
+/// The color property is declared like any other property, and its value is a
+/// `vec4<f32>` in the shader code.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// let mut module = Module::default();
+/// let tint = module.add_property("tint", HdrColor::new(Color::ORANGE).into());
+///
+/// fn flash(mut query: Query<&mut EffectProperties>) {
+///     for mut properties in &mut query {
+///         if let Ok(mut tint) = properties.property::<HdrColor>("tint") {
+///             tint.set(HdrColor::new(Color::ORANGE).with_intensity(4.));
+///         }
+///     }
+/// }
+/// ```
+///
+/// [`Vec4`]: bevy::math::Vec4
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct HdrColor {
+    /// The base color, in any color space.
+    pub color: Color,
+    /// The HDR intensity multiplier of the RGB components of the color.
+    pub intensity: f32,
+}
+
+impl Default for HdrColor {
+    fn default() -> Self {
+        Self::new(Color::WHITE)
+    }
+}
+
+impl HdrColor {
+    /// Create a new color with an intensity of `1.0`.
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            intensity: 1.,
+        }
+    }
+
+    /// Set the HDR intensity multiplier of the color.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Get the linear RGBA value of the color, with the RGB components scaled
+    /// by the intensity.
+    pub fn to_linear_vec4(&self) -> Vec4 {
+        let linear = self.color.rgba_linear_to_vec4();
+        (linear.truncate() * self.intensity).extend(linear.w)
+    }
+}
+
+impl From<Color> for HdrColor {
+    fn from(color: Color) -> Self {
+        Self::new(color)
+    }
+}
+
+impl From<HdrColor> for Value {
+    fn from(color: HdrColor) -> Self {
+        color.to_linear_vec4().into()
+    }
+}
+
+impl TryFrom<Value> for HdrColor {
+    type Error = ExprError;
+
+    /// Convert a linear RGBA value back into a color.
+    ///
+    /// The value doesn't retain the intensity, so the color is returned in
+    /// linear space with an intensity of `1.0`, and its RGB components may be
+    /// greater than `1.0`.
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let value: Vec4 = value.try_into()?;
+        Ok(Self::new(Color::rgba_linear_from_array(value)))
+    }
+}
+
 /// Instance of a [`Property`] owned by a specific [`ParticleEffect`] component.
 ///
 /// The property instance is stored inside an [`EffectProperties`].
@@ -267,7 +404,6 @@ impl EffectProperties {
     }
 
     /// Get all properties currently stored in this component.
-    #[allow(dead_code)] // used in tests
     pub(crate) fn properties(&self) -> &[PropertyInstance] {
         &self.properties
     }
@@ -340,6 +476,53 @@ impl EffectProperties {
         }
     }
 
+    /// Get a typed handle to a stored property.
+    ///
+    /// The handle allows reading and writing the value of the property as a
+    /// `T`, without going through [`Value`]. Unlike [`set()`], this checks
+    /// that the property exists and has the requested type, and returns an
+    /// error otherwise.
+    ///
+    /// Like [`get_stored()`], the property is matched by name against the
+    /// properties already stored in this [`EffectProperties`] component, so
+    /// the properties declared in the [`Module`] of the effect are only found
+    /// once the [`EffectSystems::UpdatePropertiesFromAsset`] stage added them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PropertyError::UnknownProperty`] if no property with that
+    /// name is stored, or [`PropertyError::TypeMismatch`] if the property has
+    /// a type other than `T`.
+    ///
+    /// [`set()`]: crate::EffectProperties::set
+    /// [`get_stored()`]: crate::EffectProperties::get_stored
+    /// [`Module`]: crate::Module
+    /// [`EffectSystems::UpdatePropertiesFromAsset`]: crate::EffectSystems::UpdatePropertiesFromAsset
+    pub fn property<T>(&mut self, name: &str) -> Result<PropertyMut<'_, T>, PropertyError>
+    where
+        T: Into<Value>,
+        Value: TryInto<T>,
+    {
+        let Some(instance) = self
+            .properties
+            .iter_mut()
+            .find(|prop| prop.def.name() == name)
+        else {
+            return Err(PropertyError::UnknownProperty(name.to_string()));
+        };
+        if TryInto::<T>::try_into(instance.value).is_err() {
+            return Err(PropertyError::TypeMismatch {
+                name: name.to_string(),
+                value_type: instance.def.value_type(),
+                requested: std::any::type_name::<T>(),
+            });
+        }
+        Ok(PropertyMut {
+            instance,
+            marker: PhantomData,
+        })
+    }
+
     /// Set the value of a property, only if it changed.
     ///
     /// This is similar to [`set()`], with the notable difference that this
@@ -434,7 +617,10 @@ impl EffectProperties {
     /// Return the binary blob where properties have been written according to
     /// the given property layout. The size of the output blob is guaranteed
     /// to be equal to the size of the layout.
-    pub(crate) fn serialize(&self, layout: &PropertyLayout) -> Vec<u8> {
+    ///
+    /// The value of any property also defined in the [`GlobalProperties`] with
+    /// the same type is taken from the latter instead.
+    pub(crate) fn serialize(&self, layout: &PropertyLayout, globals: &GlobalProperties) -> Vec<u8> {
         let size = layout.size() as usize;
         let mut data = vec![0; size];
         // FIXME: O(n^2) search due to offset() being O(n) linear search already
@@ -442,7 +628,8 @@ impl EffectProperties {
             if let Some(offset) = layout.offset(property.def.name()) {
                 let offset = offset as usize;
                 let size = property.def.size();
-                let src = property.value.as_bytes();
+                let value = globals.get_for(&property.def).unwrap_or(property.value);
+                let src = value.as_bytes();
                 debug_assert_eq!(src.len(), size);
                 let dst = &mut data[offset..offset + size];
                 dst.copy_from_slice(src);
@@ -452,6 +639,154 @@ impl EffectProperties {
     }
 }
 
+/// Global properties shared by all effect instances.
+///
+/// This resource stores values for properties shared by all effects, like the
+/// direction of the wind, or the time of day. Any property declared in the
+/// [`Module`] of an effect with the same name and type as a global property
+/// takes the value of the global property, instead of the value stored in the
+/// [`EffectProperties`] of the effect instance. This avoids having to copy
+/// world-wide parameters into every effect instance each frame.
+///
+/// Changing the value of a global property re-uploads the properties of all
+/// effect instances to GPU, so should be done sparingly, typically at most
+/// once per frame.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// // The effect declares a regular property, with a default value used until
+/// // the global property is defined.
+/// let mut module = Module::default();
+/// let wind = module.add_property("wind", Vec3::ZERO.into());
+///
+/// fn update_wind(time: Res<Time>, mut globals: ResMut<GlobalProperties>) {
+///     let angle = time.elapsed_seconds() * 0.1;
+///     globals.set("wind", Vec3::new(angle.cos(), 0., angle.sin()).into());
+/// }
+/// ```
+///
+/// [`Module`]: crate::Module
+#[derive(Debug, Default, Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct GlobalProperties {
+    /// The global properties.
+    properties: Vec<PropertyInstance>,
+}
+
+impl GlobalProperties {
+    /// Get the value of a global property, if defined.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.properties
+            .iter()
+            .find(|prop| prop.def.name() == name)
+            .map(|prop| prop.value)
+    }
+
+    /// Set the value of a global property, defining it if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the global property is already defined with a different type.
+    pub fn set(&mut self, name: &str, value: Value) {
+        if let Some(prop) = self
+            .properties
+            .iter_mut()
+            .find(|prop| prop.def.name() == name)
+        {
+            assert_eq!(
+                prop.def.value_type(),
+                value.value_type(),
+                "Cannot assign value of type {:?} to global property '{}' of type {:?}",
+                value.value_type(),
+                prop.def.name(),
+                prop.def.value_type()
+            );
+            prop.value = value;
+        } else {
+            self.properties.push(PropertyInstance {
+                def: Property::new(name, value),
+                value,
+            });
+        }
+    }
+
+    /// Remove a global property.
+    ///
+    /// Effect instances use their own value of the property again. Returns the
+    /// value of the removed property, if it was defined.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let index = self
+            .properties
+            .iter()
+            .position(|prop| prop.def.name() == name)?;
+        Some(self.properties.remove(index).value)
+    }
+
+    /// Get the global value of an effect property, if a global property with
+    /// the same name and type is defined.
+    pub(crate) fn get_for(&self, property: &Property) -> Option<Value> {
+        self.get(property.name())
+            .filter(|value| value.value_type() == property.value_type())
+    }
+}
+
+/// Error accessing a property with [`EffectProperties::property()`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PropertyError {
+    /// No property with this name is stored in the [`EffectProperties`].
+    #[error("Unknown property '{0}'.")]
+    UnknownProperty(String),
+    /// The property exists but has a different type than the one requested.
+    #[error("Property '{name}' has type {value_type:?}, but was accessed as {requested}.")]
+    TypeMismatch {
+        /// Name of the property.
+        name: String,
+        /// Actual type of the property.
+        value_type: ValueType,
+        /// Name of the Rust type the property was accessed as.
+        requested: &'static str,
+    },
+}
+
+/// Typed handle to a property stored in an [`EffectProperties`].
+///
+/// The handle is returned by [`EffectProperties::property()`], which already
+/// checked that the property exists and has type `T`.
+#[derive(Debug)]
+pub struct PropertyMut<'a, T> {
+    /// The property instance.
+    instance: &'a mut PropertyInstance,
+    /// Type of the property value.
+    marker: PhantomData<T>,
+}
+
+impl<'a, T> PropertyMut<'a, T>
+where
+    T: Into<Value>,
+    Value: TryInto<T>,
+{
+    /// Get the name of the property.
+    pub fn name(&self) -> &str {
+        self.instance.def.name()
+    }
+
+    /// Get the current value of the property.
+    pub fn get(&self) -> T {
+        match self.instance.value.try_into() {
+            Ok(value) => value,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Set a new value for the property.
+    pub fn set(&mut self, value: T) {
+        self.instance.value = value.into();
+    }
+}
+
 #[derive(Clone)]
 struct PropertyLayoutEntry {
     property: Property,
@@ -983,6 +1318,64 @@ mod tests {
         assert_ne!(hash_ple(&entry1b), hash_ple(&entry4));
     }
 
+    #[test]
+    fn hdr_color() {
+        let white = HdrColor::new(Color::WHITE).with_intensity(2.);
+        assert_eq!(Value::from(white), Value::from(Vec4::new(2., 2., 2., 1.)));
+
+        // Linear colors are unchanged
+        let color = HdrColor::from(Color::rgba_linear(0.25, 0.5, 1., 0.5));
+        assert_eq!(color.to_linear_vec4(), Vec4::new(0.25, 0.5, 1., 0.5));
+
+        // sRGB colors are converted to linear
+        let color = HdrColor::new(Color::rgb(0.5, 0.5, 0.5));
+        let linear = color.to_linear_vec4();
+        assert!(linear.x < 0.25);
+        assert_eq!(
+            linear,
+            Vec4::from(Color::rgb(0.5, 0.5, 0.5).as_linear_rgba_f32())
+        );
+
+        // Colors can be used with the typed property accessor
+        let mut properties = EffectProperties::default()
+            .with_properties([("tint".to_string(), HdrColor::default().into())]);
+        let mut tint = properties.property::<HdrColor>("tint").unwrap();
+        assert_eq!(tint.get().to_linear_vec4(), Vec4::ONE);
+        tint.set(white);
+        assert_eq!(
+            properties.get_stored("tint"),
+            Some(Vec4::new(2., 2., 2., 1.).into())
+        );
+    }
+
+    #[test]
+    fn typed_property() {
+        let mut properties = EffectProperties::default().with_properties([
+            ("wind".to_string(), Value::from(Vec3::X)),
+            ("count".to_string(), Value::from(3_u32)),
+        ]);
+
+        let mut wind = properties.property::<Vec3>("wind").unwrap();
+        assert_eq!(wind.name(), "wind");
+        assert_eq!(wind.get(), Vec3::X);
+        wind.set(Vec3::Y);
+        assert_eq!(properties.get_stored("wind"), Some(Value::from(Vec3::Y)));
+        assert_eq!(properties.property::<u32>("count").unwrap().get(), 3);
+
+        assert_eq!(
+            properties.property::<Vec3>("wnid").unwrap_err(),
+            PropertyError::UnknownProperty("wnid".to_string())
+        );
+        assert_eq!(
+            properties.property::<f32>("count").unwrap_err(),
+            PropertyError::TypeMismatch {
+                name: "count".to_string(),
+                value_type: ValueType::Scalar(crate::ScalarType::Uint),
+                requested: "f32",
+            }
+        );
+    }
+
     #[test]
     fn layout_empty() {
         let l = PropertyLayout::empty();
@@ -1300,7 +1693,7 @@ mod tests {
             ("b".to_string(), Vec3::ONE.into()),
         ]);
         let layout = PropertyLayout::new(ep.properties().iter().map(|pi| &pi.def));
-        let blob = ep.serialize(&layout);
+        let blob = ep.serialize(&layout, &GlobalProperties::default());
         assert_eq!(blob.len(), layout.size() as usize);
 
         let pi_a = &ep.properties()[0];
@@ -1321,4 +1714,38 @@ mod tests {
         let raw_ref: &[u8; 12] = unsafe { std::mem::transmute(&[1_f32, 1_f32, 1_f32]) };
         assert_eq!(raw, raw_ref);
     }
+
+    #[test]
+    fn global_properties() {
+        let ep = EffectProperties::default().with_properties([
+            ("a".to_string(), 3_f32.into()),
+            ("b".to_string(), Vec3::ONE.into()),
+        ]);
+        let layout = PropertyLayout::new(ep.properties().iter().map(|pi| &pi.def));
+        let offset_a = layout.offset("a").unwrap() as usize;
+        let offset_b = layout.offset("b").unwrap() as usize;
+
+        // Global properties override the instance value if their type matches
+        let mut globals = GlobalProperties::default();
+        globals.set("a", 5_f32.into());
+        globals.set("b", 2_f32.into());
+        assert_eq!(globals.get("a"), Some(5_f32.into()));
+        let blob = ep.serialize(&layout, &globals);
+        assert_eq!(&blob[offset_a..offset_a + 4], 5_f32.to_ne_bytes());
+        assert_eq!(&blob[offset_b..offset_b + 4], 1_f32.to_ne_bytes());
+
+        // Removed global properties don't override anymore
+        assert_eq!(globals.remove("a"), Some(5_f32.into()));
+        assert_eq!(globals.remove("a"), None);
+        let blob = ep.serialize(&layout, &globals);
+        assert_eq!(&blob[offset_a..offset_a + 4], 3_f32.to_ne_bytes());
+    }
+
+    #[test]
+    #[should_panic]
+    fn global_properties_set_type_mismatch() {
+        let mut globals = GlobalProperties::default();
+        globals.set("a", 3_f32.into());
+        globals.set("a", Vec3::ZERO.into());
+    }
 }
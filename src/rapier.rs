@@ -0,0 +1,418 @@
+use bevy::{math::Mat3A, prelude::*, render::primitives::Aabb, transform::TransformSystem};
+#[cfg(feature = "rapier2d")]
+use bevy_rapier2d::prelude as rapier2d;
+#[cfg(feature = "rapier3d")]
+use bevy_rapier3d::prelude as rapier3d;
+
+use crate::{CompiledParticleEffect, LayoutFlags, StaticCollider, StaticColliders};
+
+/// Plugin filling the [`StaticColliders`] from the static colliders of a
+/// Rapier physics world.
+///
+/// Each frame, the colliders of the fixed rigid bodies, and the colliders not
+/// attached to any rigid body, are converted into [`StaticCollider`]
+/// primitives, replacing the content of the [`StaticColliders`]. Sensors and
+/// disabled colliders are ignored. The 2D balls, cuboids, capsules, and
+/// segments, and the 3D balls, cuboids, and capsules are converted; other
+/// shapes are ignored. Rotated cuboids are approximated by their axis-aligned
+/// bounds.
+///
+/// Only the colliders overlapping the [`Aabb`] of an effect using the
+/// [`CollideStaticModifier`] are kept, so the list stays small in large
+/// worlds. If one of those effects doesn't have an [`Aabb`], all the colliders
+/// are kept. The list is empty if no effect uses the modifier.
+///
+/// This plugin requires the `rapier2d` or `rapier3d` feature of this crate,
+/// which depend on `bevy_rapier2d` and `bevy_rapier3d` respectively; with both
+/// features, the colliders of both worlds are converted. The colliders are
+/// read from their components, so the physics plugin itself is optional.
+///
+/// [`CollideStaticModifier`]: crate::CollideStaticModifier
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RapierCollidersPlugin;
+
+impl Plugin for RapierCollidersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            update_rapier_colliders.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Replace the [`StaticColliders`] with the static Rapier colliders
+/// overlapping the effects colliding with them.
+pub(crate) fn update_rapier_colliders(
+    mut static_colliders: ResMut<StaticColliders>,
+    effects: Query<(&CompiledParticleEffect, Option<&Aabb>, &GlobalTransform)>,
+    parents: Query<&Parent>,
+    #[cfg(feature = "rapier2d")] bodies_2d: Query<&rapier2d::RigidBody>,
+    #[cfg(feature = "rapier2d")] colliders_2d: Query<
+        (Entity, &rapier2d::Collider, &GlobalTransform),
+        (
+            Without<rapier2d::Sensor>,
+            Without<rapier2d::ColliderDisabled>,
+        ),
+    >,
+    #[cfg(feature = "rapier3d")] bodies_3d: Query<&rapier3d::RigidBody>,
+    #[cfg(feature = "rapier3d")] colliders_3d: Query<
+        (Entity, &rapier3d::Collider, &GlobalTransform),
+        (
+            Without<rapier3d::Sensor>,
+            Without<rapier3d::ColliderDisabled>,
+        ),
+    >,
+) {
+    let mut keep_all = false;
+    let mut effect_bounds = vec![];
+    for (compiled, aabb, transform) in &effects {
+        if !compiled
+            .layout_flags
+            .contains(LayoutFlags::STATIC_COLLIDERS)
+        {
+            continue;
+        }
+        match aabb {
+            Some(aabb) => effect_bounds.push(world_bounds(aabb, transform)),
+            None => keep_all = true,
+        }
+    }
+
+    let mut list = vec![];
+    if keep_all || !effect_bounds.is_empty() {
+        let mut push = |collider: StaticCollider| {
+            if keep_all
+                || effect_bounds
+                    .iter()
+                    .any(|b| overlaps(*b, collider.bounds()))
+            {
+                list.push(collider);
+            }
+        };
+
+        #[cfg(feature = "rapier2d")]
+        for (entity, collider, transform) in &colliders_2d {
+            let is_fixed = |entity| {
+                bodies_2d
+                    .get(entity)
+                    .ok()
+                    .map(|body| *body == rapier2d::RigidBody::Fixed)
+            };
+            if is_static(entity, &parents, is_fixed) {
+                if let Some(collider) = convert_collider_2d(collider, transform) {
+                    push(collider);
+                }
+            }
+        }
+
+        #[cfg(feature = "rapier3d")]
+        for (entity, collider, transform) in &colliders_3d {
+            let is_fixed = |entity| {
+                bodies_3d
+                    .get(entity)
+                    .ok()
+                    .map(|body| *body == rapier3d::RigidBody::Fixed)
+            };
+            if is_static(entity, &parents, is_fixed) {
+                if let Some(collider) = convert_collider_3d(collider, transform) {
+                    push(collider);
+                }
+            }
+        }
+    }
+
+    if static_colliders.colliders != list {
+        static_colliders.colliders = list;
+    }
+}
+
+/// Check if a collider is static, given whether the rigid body of an entity, if
+/// any, is fixed.
+///
+/// Like Rapier, the collider is attached to the rigid body of its entity, or
+/// else of its closest ancestor, and is static if there's none.
+fn is_static(
+    entity: Entity,
+    parents: &Query<&Parent>,
+    is_fixed: impl Fn(Entity) -> Option<bool>,
+) -> bool {
+    std::iter::successors(Some(entity), |entity| {
+        parents.get(*entity).ok().map(Parent::get)
+    })
+    .find_map(is_fixed)
+    .unwrap_or(true)
+}
+
+/// Convert a 2D Rapier collider into a [`StaticCollider`], if its shape is
+/// supported.
+#[cfg(feature = "rapier2d")]
+fn convert_collider_2d(
+    collider: &rapier2d::Collider,
+    transform: &GlobalTransform,
+) -> Option<StaticCollider> {
+    // The shapes of the colliders are already scaled by Rapier
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let to_world = |point: Vec2| (translation + rotation * point.extend(0.)).truncate();
+    if let Some(ball) = collider.as_ball() {
+        Some(StaticCollider::Circle {
+            center: translation.truncate(),
+            radius: ball.radius(),
+        })
+    } else if let Some(cuboid) = collider.as_cuboid() {
+        let half_extents = cuboid.half_extents();
+        let x_axis = (rotation * Vec3::X).truncate().abs();
+        let y_axis = (rotation * Vec3::Y).truncate().abs();
+        Some(StaticCollider::Rect {
+            center: translation.truncate(),
+            half_size: x_axis * half_extents.x + y_axis * half_extents.y,
+        })
+    } else if let Some(capsule) = collider.as_capsule() {
+        let segment = capsule.segment();
+        Some(StaticCollider::Segment {
+            start: to_world(segment.a()),
+            end: to_world(segment.b()),
+            radius: capsule.radius(),
+        })
+    } else {
+        collider
+            .as_segment()
+            .map(|segment| StaticCollider::Segment {
+                start: to_world(segment.a()),
+                end: to_world(segment.b()),
+                radius: 0.,
+            })
+    }
+}
+
+/// Convert a 3D Rapier collider into a [`StaticCollider`], if its shape is
+/// supported.
+#[cfg(feature = "rapier3d")]
+fn convert_collider_3d(
+    collider: &rapier3d::Collider,
+    transform: &GlobalTransform,
+) -> Option<StaticCollider> {
+    // The shapes of the colliders are already scaled by Rapier
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    if let Some(ball) = collider.as_ball() {
+        Some(StaticCollider::Sphere {
+            center: translation,
+            radius: ball.radius(),
+        })
+    } else if let Some(cuboid) = collider.as_cuboid() {
+        let matrix = Mat3::from_quat(rotation);
+        let matrix = Mat3::from_cols(
+            matrix.x_axis.abs(),
+            matrix.y_axis.abs(),
+            matrix.z_axis.abs(),
+        );
+        Some(StaticCollider::Box {
+            center: translation,
+            half_size: matrix * cuboid.half_extents(),
+        })
+    } else {
+        collider.as_capsule().map(|capsule| {
+            let segment = capsule.segment();
+            StaticCollider::Capsule {
+                start: translation + rotation * segment.a(),
+                end: translation + rotation * segment.b(),
+                radius: capsule.radius(),
+            }
+        })
+    }
+}
+
+/// Get the minimum and maximum corners of the world-space bounds of an
+/// [`Aabb`].
+fn world_bounds(aabb: &Aabb, transform: &GlobalTransform) -> (Vec3, Vec3) {
+    let affine = transform.affine();
+    let center = affine.transform_point3a(aabb.center);
+    let matrix = affine.matrix3;
+    let half_extents = Mat3A::from_cols(
+        matrix.x_axis.abs(),
+        matrix.y_axis.abs(),
+        matrix.z_axis.abs(),
+    ) * aabb.half_extents;
+    (
+        (center - half_extents).into(),
+        (center + half_extents).into(),
+    )
+}
+
+/// Check if two bounds given by their minimum and maximum corners overlap.
+fn overlaps(a: (Vec3, Vec3), b: (Vec3, Vec3)) -> bool {
+    a.0.cmple(b.1).all() && b.0.cmple(a.1).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collide_static_effect() -> CompiledParticleEffect {
+        CompiledParticleEffect {
+            layout_flags: LayoutFlags::STATIC_COLLIDERS,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn world_bounds_rotated() {
+        let aabb = Aabb::from_min_max(Vec3::new(-2., -1., 0.), Vec3::new(2., 1., 0.));
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(10., 0., 0.)
+                .with_rotation(Quat::from_rotation_z(90_f32.to_radians())),
+        );
+        let (min, max) = world_bounds(&aabb, &transform);
+        assert!(min.abs_diff_eq(Vec3::new(9., -2., 0.), 1e-5), "{min}");
+        assert!(max.abs_diff_eq(Vec3::new(11., 2., 0.), 1e-5), "{max}");
+    }
+
+    #[cfg(feature = "rapier2d")]
+    #[test]
+    fn update_colliders_2d() {
+        use rapier2d::{Collider, RigidBody, Sensor};
+
+        let mut app = App::new();
+        app.init_resource::<StaticColliders>()
+            .add_systems(Update, update_rapier_colliders);
+
+        // No effect colliding with static colliders
+        app.world
+            .spawn((Collider::ball(1.), GlobalTransform::from_xyz(1., 0., 0.)));
+        app.update();
+        assert!(app.world.resource::<StaticColliders>().colliders.is_empty());
+
+        app.world.spawn((
+            collide_static_effect(),
+            Aabb::from_min_max(Vec3::splat(-5.), Vec3::splat(5.)),
+            GlobalTransform::default(),
+        ));
+        // Culled by the effect bounds
+        app.world.spawn((
+            RigidBody::Fixed,
+            Collider::ball(1.),
+            GlobalTransform::from_xyz(100., 0., 0.),
+        ));
+        // Not static
+        app.world.spawn((
+            RigidBody::Dynamic,
+            Collider::ball(1.),
+            GlobalTransform::default(),
+        ));
+        app.world
+            .spawn((Collider::ball(1.), Sensor, GlobalTransform::default()));
+        // Rotated cuboid attached to a fixed body
+        let body = app
+            .world
+            .spawn((RigidBody::Fixed, GlobalTransform::default()))
+            .id();
+        let cuboid = app
+            .world
+            .spawn((
+                Collider::cuboid(2., 1.),
+                GlobalTransform::from(
+                    Transform::from_xyz(0., 5.5, 0.)
+                        .with_rotation(Quat::from_rotation_z(90_f32.to_radians())),
+                ),
+            ))
+            .id();
+        app.world.entity_mut(body).add_child(cuboid);
+        app.update();
+
+        let colliders = &app.world.resource::<StaticColliders>().colliders;
+        assert_eq!(colliders.len(), 2);
+        assert_eq!(
+            colliders[0],
+            StaticCollider::Circle {
+                center: Vec2::new(1., 0.),
+                radius: 1.,
+            }
+        );
+        let StaticCollider::Rect { center, half_size } = colliders[1] else {
+            panic!("{:?}", colliders[1]);
+        };
+        assert!(center.abs_diff_eq(Vec2::new(0., 5.5), 1e-5), "{center}");
+        assert!(
+            half_size.abs_diff_eq(Vec2::new(1., 2.), 1e-5),
+            "{half_size}"
+        );
+
+        // Any effect without bounds keeps all colliders
+        app.world
+            .spawn((collide_static_effect(), GlobalTransform::default()));
+        app.update();
+        assert_eq!(app.world.resource::<StaticColliders>().colliders.len(), 3);
+    }
+
+    #[cfg(feature = "rapier3d")]
+    #[test]
+    fn update_colliders_3d() {
+        use rapier3d::{Collider, ColliderDisabled, RigidBody};
+
+        let mut app = App::new();
+        app.init_resource::<StaticColliders>()
+            .add_systems(Update, update_rapier_colliders);
+
+        app.world.spawn((
+            collide_static_effect(),
+            Aabb::from_min_max(Vec3::splat(-5.), Vec3::splat(5.)),
+            GlobalTransform::from_xyz(0., 0., -10.),
+        ));
+        app.world
+            .spawn((Collider::ball(1.), GlobalTransform::from_xyz(0., 0., -12.)));
+        // Culled by the effect bounds, along the Z axis
+        app.world
+            .spawn((Collider::ball(1.), GlobalTransform::default()));
+        // Not static
+        app.world.spawn((
+            RigidBody::KinematicPositionBased,
+            Collider::ball(1.),
+            GlobalTransform::from_xyz(0., 0., -10.),
+        ));
+        app.world.spawn((
+            Collider::ball(1.),
+            ColliderDisabled,
+            GlobalTransform::from_xyz(0., 0., -10.),
+        ));
+        app.world.spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(2., 1., 1.),
+            GlobalTransform::from(
+                Transform::from_xyz(0., 0., -10.)
+                    .with_rotation(Quat::from_rotation_y(90_f32.to_radians())),
+            ),
+        ));
+        app.world.spawn((
+            RigidBody::Fixed,
+            Collider::capsule_y(1., 0.5),
+            GlobalTransform::from(
+                Transform::from_xyz(1., 0., -10.)
+                    .with_rotation(Quat::from_rotation_z(90_f32.to_radians())),
+            ),
+        ));
+        app.update();
+
+        let colliders = &app.world.resource::<StaticColliders>().colliders;
+        assert_eq!(colliders.len(), 3);
+        assert_eq!(
+            colliders[0],
+            StaticCollider::Sphere {
+                center: Vec3::new(0., 0., -12.),
+                radius: 1.,
+            }
+        );
+        let StaticCollider::Box { center, half_size } = colliders[1] else {
+            panic!("{:?}", colliders[1]);
+        };
+        assert_eq!(center, Vec3::new(0., 0., -10.));
+        assert!(
+            half_size.abs_diff_eq(Vec3::new(1., 1., 2.), 1e-5),
+            "{half_size}"
+        );
+        let StaticCollider::Capsule { start, end, radius } = colliders[2] else {
+            panic!("{:?}", colliders[2]);
+        };
+        assert!(start.abs_diff_eq(Vec3::new(2., 0., -10.), 1e-5), "{start}");
+        assert!(end.abs_diff_eq(Vec3::new(0., 0., -10.), 1e-5), "{end}");
+        assert_eq!(radius, 0.5);
+    }
+}
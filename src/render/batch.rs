@@ -12,7 +12,7 @@ use crate::{EffectAsset, EffectShader, ParticleLayout, PropertyLayout};
 
 use super::{
     effect_cache::{DispatchBufferIndices, EffectSlices},
-    EffectCacheId, GpuCompressedTransform, LayoutFlags,
+    EffectCacheId, ExtractedEmitter, GpuCompressedTransform, GpuUpdateInstance, LayoutFlags,
 };
 
 /// Data needed to render all batches pertaining to a specific effect.
@@ -26,8 +26,18 @@ pub(crate) struct EffectBatches {
     pub buffer_index: u32,
     /// Index of the first Spawner of the effects in the batch.
     pub spawner_base: u32,
-    /// Number of particles to spawn/init this frame.
+    /// Number of particles to spawn/init this frame, as known on CPU.
     pub spawn_count: u32,
+    /// Whether the effect also spawns particles from the events of a parent
+    /// effect. The number of such particles is only known on GPU, so the init
+    /// pass is dispatched indirectly.
+    pub spawn_from_events: bool,
+    /// Additional emitters of the effect. The spawner of each emitter directly
+    /// follows the one of the effect, in order.
+    pub emitter_batches: Vec<EmitterBatch>,
+    /// Bitfield of the particle groups for which all particles are killed
+    /// during this frame's update pass.
+    pub kill_groups: u32,
     /// The effect cache ID.
     pub effect_cache_id: EffectCacheId,
     /// The indices within the various indirect dispatch buffers.
@@ -39,17 +49,18 @@ pub(crate) struct EffectBatches {
     pub first_particle_group_buffer_index: u32,
     /// Particle layout.
     pub particle_layout: ParticleLayout,
-    /// Flags describing the render layout.
+    /// Flags describing the render layout, as the union of the flags of all
+    /// groups.
     pub layout_flags: LayoutFlags,
     /// Entities holding the source [`ParticleEffect`] instances which were
     /// batched into this single batch. Used to determine visibility per view.
     ///
     /// [`ParticleEffect`]: crate::ParticleEffect
     pub entities: Vec<u32>,
-    /// Texture to modulate the particle color.
+    /// Entity holding the source [`ParticleEffect`] instance in the main world.
     ///
-    /// TODO: We should support more than one of these.
-    pub image_handle: Handle<Image>,
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub main_entity: Entity,
     /// Configured shaders used for the particle rendering of this batch.
     /// Note that we don't need to keep the init/update shaders alive because
     /// their pipeline specialization is doing it via the specialization key.
@@ -58,6 +69,15 @@ pub(crate) struct EffectBatches {
     pub init_pipeline_id: CachedComputePipelineId,
     /// Update compute pipeline specialized for this batch.
     pub update_pipeline_ids: Vec<CachedComputePipelineId>,
+    /// Cull compute pipeline of each group, if the effect culls its particles
+    /// per view. Otherwise this is empty.
+    pub cull_pipeline_ids: Vec<CachedComputePipelineId>,
+    /// Index of the [`UpdateBatch`] updating the particles of the effect
+    /// together with other instances, if any. Otherwise the effect is updated
+    /// by its own dispatches, with the [`update_pipeline_ids`].
+    ///
+    /// [`update_pipeline_ids`]: EffectBatches::update_pipeline_ids
+    pub update_batch: Option<u32>,
 }
 
 impl Index<u32> for EffectBatches {
@@ -90,11 +110,31 @@ pub(crate) struct EffectDrawBatch {
     pub translation_3d: Vec3,
 }
 
+/// Batch data specific to an additional emitter of an effect.
+#[derive(Debug)]
+pub(crate) struct EmitterBatch {
+    /// Index of the group the emitter spawns particles into.
+    pub group_index: u32,
+    /// Number of particles to spawn/init this frame.
+    pub spawn_count: u32,
+    /// Init compute pipeline specialized for this emitter.
+    pub init_pipeline_id: CachedComputePipelineId,
+}
+
 /// Batch data specific to a single particle group.
 #[derive(Debug)]
 pub(crate) struct EffectBatch {
     /// Slice of particles in the GPU effect buffer for the entire batch.
     pub slice: Range<u32>,
+    /// Flags describing the render layout of the group.
+    pub layout_flags: LayoutFlags,
+    /// Texture to modulate the particle color.
+    pub image_handle: Handle<Image>,
+    /// LUT texture defining the particle color over its lifetime.
+    pub color_lut_handle: Handle<Image>,
+    /// Z coordinates of the 2D layers the particles are split into, each drawn
+    /// by its own draw call, or empty to draw all particles at once.
+    pub z_layers_2d: Vec<f32>,
 }
 
 impl EffectBatches {
@@ -104,7 +144,10 @@ impl EffectBatches {
         spawner_base: u32,
         effect_cache_id: EffectCacheId,
         init_pipeline_id: CachedComputePipelineId,
+        emitter_init_pipeline_ids: Vec<CachedComputePipelineId>,
         update_pipeline_ids: Vec<CachedComputePipelineId>,
+        cull_pipeline_ids: Vec<CachedComputePipelineId>,
+        update_batch: Option<u32>,
         dispatch_buffer_indices: DispatchBufferIndices,
         first_particle_group_buffer_index: u32,
     ) -> EffectBatches {
@@ -112,6 +155,18 @@ impl EffectBatches {
             buffer_index: input.effect_slices.buffer_index,
             spawner_base,
             spawn_count: input.spawn_count,
+            spawn_from_events: input.parent_event != u32::MAX,
+            emitter_batches: input
+                .emitters
+                .iter()
+                .zip(emitter_init_pipeline_ids)
+                .map(|(emitter, init_pipeline_id)| EmitterBatch {
+                    group_index: emitter.group_index,
+                    spawn_count: emitter.spawn_count,
+                    init_pipeline_id,
+                })
+                .collect(),
+            kill_groups: input.kill_groups,
             particle_layout: input.effect_slices.particle_layout,
             effect_cache_id,
             dispatch_buffer_indices,
@@ -120,21 +175,81 @@ impl EffectBatches {
                 .effect_slices
                 .slices
                 .windows(2)
-                .map(|range| EffectBatch {
-                    slice: range[0]..range[1],
-                })
+                .zip(input.group_layout_flags)
+                .zip(input.image_handles)
+                .zip(input.color_lut_handles)
+                .zip(input.z_layers_2d)
+                .map(
+                    |((((range, layout_flags), image_handle), color_lut_handle), z_layers_2d)| {
+                        EffectBatch {
+                            slice: range[0]..range[1],
+                            layout_flags,
+                            image_handle,
+                            color_lut_handle,
+                            z_layers_2d,
+                        }
+                    },
+                )
                 .collect(),
             handle: input.handle,
             layout_flags: input.layout_flags,
-            image_handle: input.image_handle,
             render_shaders: input.effect_shader.render,
             init_pipeline_id,
             update_pipeline_ids,
+            cull_pipeline_ids,
+            update_batch,
             entities: vec![input.entity.index()],
+            main_entity: input.entity,
         }
     }
 }
 
+/// Instances of a same effect buffer with the same update shaders, updated
+/// together by a single dispatch per group.
+///
+/// The update passes of the instances are otherwise dispatched indirectly with
+/// the exact number of particles alive, which is only known on GPU. A batched
+/// dispatch instead covers the capacity of each group, and the threads past the
+/// particles alive of their instance exit early. This trades some idle GPU
+/// threads for far fewer commands to encode on CPU with many instances.
+#[derive(Debug)]
+pub(crate) struct UpdateBatch {
+    /// Batched update pipeline of each group.
+    pub pipeline_ids: Vec<CachedComputePipelineId>,
+    /// Number of workgroups along the X axis of the dispatch of each group,
+    /// covering the capacity of the group.
+    pub workgroup_counts: Vec<u32>,
+    /// Per-instance data of the batch, in the order of the workgroup rows of
+    /// the dispatch.
+    pub instances: Vec<GpuUpdateInstance>,
+    /// Index of the first instance of the batch in the instance buffer, once
+    /// packed with [`pack_update_batches()`].
+    pub first_instance: u32,
+}
+
+/// Pack the instances of several batches into a single buffer.
+///
+/// Each batch is bound at a dynamic offset, so its first instance must start
+/// at a multiple of `align` instances. Returns the index of the first instance
+/// of each batch, and the total number of instances of the buffer including
+/// padding, such that a binding of as many instances as the largest batch fits
+/// in the buffer from the first instance of any batch.
+pub(crate) fn pack_update_batches(counts: &[u32], align: u32) -> (Vec<u32>, u32) {
+    let align = align.max(1);
+    let mut len = 0u32;
+    let first_instances = counts
+        .iter()
+        .map(|count| {
+            let first = len.div_ceil(align) * align;
+            len = first + count;
+            first
+        })
+        .collect::<Vec<_>>();
+    let max_count = counts.iter().copied().max().unwrap_or(0);
+    let len = first_instances.last().map_or(0, |first| first + max_count);
+    (first_instances, len)
+}
+
 /// Effect batching input, obtained from extracted effects.
 #[derive(Debug, Clone)]
 pub(crate) struct BatchesInput {
@@ -151,10 +266,31 @@ pub(crate) struct BatchesInput {
     pub effect_shader: EffectShader,
     /// Various flags related to the effect.
     pub layout_flags: LayoutFlags,
-    /// Texture to modulate the particle color.
-    pub image_handle: Handle<Image>,
+    /// Render layout flags of each group.
+    pub group_layout_flags: Vec<LayoutFlags>,
+    /// Texture to modulate the particle color, for each group.
+    pub image_handles: Vec<Handle<Image>>,
+    /// LUT texture defining the particle color over its lifetime, for each
+    /// group.
+    pub color_lut_handles: Vec<Handle<Image>>,
+    /// Z coordinates of the 2D layers the particles are split into, for each
+    /// group.
+    pub z_layers_2d: Vec<Vec<f32>>,
     /// Number of particles to spawn for this effect.
     pub spawn_count: u32,
+    /// Pre-warming duration this frame, in seconds.
+    pub prewarm_time: f32,
+    /// Seed of the GPU random number generator this frame.
+    pub seed: u32,
+    /// Additional emitters of the effect.
+    pub emitters: Vec<ExtractedEmitter>,
+    /// Scale applied to the simulation delta time of the effect.
+    pub time_scale: f32,
+    /// Bitfield of the particle groups for which all particles are killed
+    /// this frame.
+    pub kill_groups: u32,
+    /// Bitfield of the modifiers disabled on the effect instance.
+    pub disabled_modifiers: u32,
     /// Emitter transform.
     pub transform: GpuCompressedTransform,
     /// Emitter inverse transform.
@@ -164,7 +300,41 @@ pub(crate) struct BatchesInput {
     /// Serialized property data.
     // FIXME - Contains a single effect's data; should handle multiple ones.
     pub property_data: Option<Vec<u8>>,
+    /// GPU buffer where particles injected from the CPU need to be written.
+    pub injection_buffer: Option<Buffer>,
+    /// Serialized injected particles data.
+    pub injection_data: Option<Vec<u8>>,
+    /// Number of particles injected from the CPU this frame.
+    pub inject_count: u32,
+    /// Index of the first GPU event counter written by this effect, or
+    /// `u32::MAX` if the effect is not the parent of any other effect.
+    pub event_slot: u32,
+    /// Index of the GPU event counter of the parent effect driving the
+    /// spawning of this effect, or `u32::MAX` if the effect has no parent.
+    pub parent_event: u32,
+    /// Number of particles to spawn per event of the parent effect.
+    pub spawn_per_event: u32,
     /// Sort key, for 2D only.
     #[cfg(feature = "2d")]
     pub z_sort_key_2d: FloatOrd,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_update_batches_aligned() {
+        assert_eq!(pack_update_batches(&[], 16), (vec![], 0));
+        assert_eq!(pack_update_batches(&[3], 16), (vec![0], 3));
+
+        // Each batch starts at a multiple of the alignment, and the largest batch
+        // fits after the last one
+        let (first_instances, len) = pack_update_batches(&[20, 3, 16], 16);
+        assert_eq!(first_instances, vec![0, 32, 48]);
+        assert_eq!(len, 68);
+
+        // Any alignment is rounded up to a single instance
+        assert_eq!(pack_update_batches(&[2, 5], 0), (vec![0, 2], 7));
+    }
+}
@@ -266,25 +266,68 @@ impl<T: Pod + ShaderSize> BufferTable<T> {
     /// For performance reasons, this buffers the row content on the CPU until
     /// the next GPU update, to minimize the number of CPU to GPU transfers.
     pub fn insert(&mut self, value: T) -> BufferTableId {
+        self.insert_contiguous(std::iter::once(value))
+    }
+
+    /// Insert several new rows into the table, at contiguous indices.
+    ///
+    /// The rows are allocated from the first run of contiguous free rows large
+    /// enough to hold them, if any, or otherwise appended at the end of the
+    /// table. When the table is full, its capacity grows geometrically to
+    /// amortize the cost of reallocating the GPU buffer.
+    ///
+    /// Returns the index of the first row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` is empty.
+    pub fn insert_contiguous(&mut self, values: impl ExactSizeIterator<Item = T>) -> BufferTableId {
+        let count = values.len();
+        assert!(count > 0, "Cannot insert zero rows into a buffer table.");
         trace!(
-            "Inserting into table buffer with {} free indices, capacity: {}, active_size: {}",
+            "Inserting {} rows into table buffer with {} free indices, capacity: {}, active_size: {}",
+            count,
             self.free_indices.len(),
             self.capacity,
             self.active_size
         );
-        let index = if self.free_indices.is_empty() {
-            let index = self.active_size;
-            if index == self.capacity {
-                self.capacity += 1;
+        let first_index = self.allocate_rows(count);
+        for (index, value) in (first_index..).zip(values) {
+            self.write_row(index, value);
+        }
+        BufferTableId(first_index as u32)
+    }
+
+    /// Allocate `count` contiguous rows, and return the index of the first
+    /// one.
+    fn allocate_rows(&mut self, count: usize) -> usize {
+        // Find the first run of contiguous free rows large enough. The free list is
+        // sorted, so a run is a sequence of consecutive indices.
+        let mut run_start = 0;
+        for pos in 0..self.free_indices.len() {
+            if pos > 0 && self.free_indices[pos] != self.free_indices[pos - 1] + 1 {
+                run_start = pos;
             }
-            debug_assert!(index < self.capacity);
-            self.active_size += 1;
-            index as u32
-        } else {
-            // Note: this is inefficient O(n) but we need to apply the same logic as the
-            // EffectCache because we rely on indices being in sync.
-            self.free_indices.remove(0)
-        } as usize;
+            if pos + 1 - run_start == count {
+                let first_index = self.free_indices[run_start] as usize;
+                self.free_indices.drain(run_start..=pos);
+                return first_index;
+            }
+        }
+
+        // Otherwise append at the end of the active zone. There's never any free row
+        // at the end of it, as removing the last row shrinks the active zone.
+        let first_index = self.active_size;
+        self.active_size += count;
+        if self.active_size > self.capacity {
+            self.capacity = self.active_size.max(self.capacity * 2);
+        }
+        first_index
+    }
+
+    /// Write the value of a newly allocated row, to be uploaded on next GPU
+    /// update.
+    fn write_row(&mut self, index: usize, value: T) {
         let allocated_size = self
             .buffer
             .as_ref()
@@ -309,7 +352,6 @@ impl<T: Pod + ShaderSize> BufferTable<T> {
                 self.extra_pending_values.alloc().init(value);
             }
         }
-        BufferTableId(index as u32)
     }
 
     /// Remove a row from the table.
@@ -327,10 +369,14 @@ impl<T: Pod + ShaderSize> BufferTable<T> {
         // let allocated_count = allocated_size / self.aligned_size;
 
         // If this is the last item in the active zone, just shrink the active zone
-        // (implicit free list).
+        // (implicit free list), including any free row now at the end of it. The
+        // capacity is kept, as the GPU buffer is not shrunk.
         if index == self.active_size as u32 - 1 {
             self.active_size -= 1;
-            self.capacity -= 1;
+            while self.free_indices.last() == Some(&(self.active_size as u32).wrapping_sub(1)) {
+                self.free_indices.pop();
+                self.active_size -= 1;
+            }
         } else {
             // This is very inefficient but we need to apply the same logic as the
             // EffectCache because we rely on indices being in sync.
@@ -549,6 +595,66 @@ mod tests {
         pub large: [f32; 128],
     }
 
+    #[test]
+    fn table_alloc() {
+        let mut table = BufferTable::<GpuDummyComposed>::new(BufferUsages::STORAGE, None, None);
+        let row = |tag| GpuDummyComposed {
+            tag,
+            ..Default::default()
+        };
+
+        // The capacity grows geometrically
+        for i in 0..5 {
+            assert_eq!(table.insert(row(i)).0, i);
+        }
+        assert_eq!(table.len(), 5);
+        assert_eq!(table.capacity(), 8);
+
+        // Free rows are kept in the free list
+        table.remove(BufferTableId(1));
+        table.remove(BufferTableId(2));
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.free_indices, vec![1, 2]);
+
+        // Contiguous rows not fitting in the free list are appended
+        assert_eq!(
+            table
+                .insert_contiguous([row(5), row(6), row(7)].into_iter())
+                .0,
+            5
+        );
+        assert_eq!(table.active_size, 8);
+        assert_eq!(table.capacity(), 8);
+
+        // Contiguous rows fitting in the free list reuse the free rows
+        assert_eq!(table.insert_contiguous([row(8), row(9)].into_iter()).0, 1);
+        assert!(table.free_indices.is_empty());
+
+        // Removing the last rows shrinks the active zone, including the free rows
+        // located before them
+        table.remove(BufferTableId(5));
+        table.remove(BufferTableId(6));
+        assert_eq!(table.free_indices, vec![5, 6]);
+        table.remove(BufferTableId(7));
+        assert!(table.free_indices.is_empty());
+        assert_eq!(table.active_size, 5);
+
+        // Runs of free rows too small are skipped
+        table.remove(BufferTableId(3));
+        assert_eq!(table.insert_contiguous([row(10), row(11)].into_iter()).0, 5);
+        assert_eq!(table.free_indices, vec![3]);
+        table.remove(BufferTableId(4));
+        assert_eq!(table.free_indices, vec![3, 4]);
+        assert_eq!(
+            table
+                .insert_contiguous([row(12), row(13), row(14)].into_iter())
+                .0,
+            7
+        );
+        assert_eq!(table.active_size, 10);
+        assert_eq!(table.capacity(), 16);
+    }
+
     #[test]
     fn table_sizes() {
         // Rust
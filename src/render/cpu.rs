@@ -0,0 +1,586 @@
+use std::{mem::size_of, ops::Range};
+
+#[cfg(feature = "2d")]
+use bevy::core_pipeline::core_2d::Transparent2d;
+#[cfg(feature = "3d")]
+use bevy::core_pipeline::core_3d::{AlphaMask3d, Transparent3d};
+#[cfg(feature = "2d")]
+use bevy::utils::FloatOrd;
+use bevy::{
+    core::{Pod, Zeroable},
+    ecs::system::{lifetimeless::*, SystemState},
+    log::trace,
+    prelude::*,
+    render::{
+        render_phase::{Draw, DrawFunctions, PhaseItem, RenderPhase, TrackedRenderPass},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        texture::BevyDefault,
+        view::{
+            ExtractedView, ViewTarget, ViewUniform, ViewUniformOffset, ViewUniforms,
+            VisibleEntities,
+        },
+        Extract,
+    },
+};
+
+use super::stereo::{BillboardViewOffset, BillboardViewUniforms, GpuBillboardView};
+#[cfg(all(feature = "2d", feature = "3d"))]
+use super::PipelineMode;
+use crate::cpu_sim::CpuParticles;
+
+// {0A6F4C1B-9E3D-4C6A-8B2F-5D7E1A3C9B40}
+pub(crate) const HANABI_CPU_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x0A6F4C1B9E3D4C6A8B2F5D7E1A3C9B40u128);
+
+/// A single particle simulated on CPU, as rendered by the `vfx_cpu.wgsl`
+/// shader.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub(crate) struct GpuCpuParticle {
+    /// World-space position of the particle.
+    pub position: [f32; 3],
+    /// Size of the particle quad.
+    pub size: [f32; 2],
+    /// Color of the particle.
+    pub color: [f32; 4],
+}
+
+/// An effect instance simulated on CPU, extracted into the render world.
+struct ExtractedCpuEffect {
+    /// Main world entity of the effect instance, for visibility checks.
+    main_entity: Entity,
+    instances: Vec<GpuCpuParticle>,
+    use_alpha_mask: bool,
+    #[cfg(feature = "2d")]
+    z_sort_key_2d: FloatOrd,
+    #[cfg(feature = "3d")]
+    translation_3d: Vec3,
+}
+
+/// All effect instances simulated on CPU extracted this frame.
+#[derive(Default, Resource)]
+pub(crate) struct ExtractedCpuEffects {
+    effects: Vec<ExtractedCpuEffect>,
+}
+
+pub(crate) fn extract_cpu_effects(
+    query: Extract<
+        Query<(
+            Entity,
+            Option<&ViewVisibility>,
+            &CpuParticles,
+            Option<&GlobalTransform>,
+        )>,
+    >,
+    mut extracted_effects: ResMut<ExtractedCpuEffects>,
+) {
+    extracted_effects.effects.clear();
+    for (main_entity, view_visibility, particles, transform) in query.iter() {
+        if particles.instances.is_empty() || view_visibility.is_some_and(|vis| !vis.get()) {
+            continue;
+        }
+        #[cfg(feature = "3d")]
+        let translation_3d = transform.map_or(Vec3::ZERO, |t| t.translation());
+        #[cfg(not(feature = "3d"))]
+        let _ = transform;
+        extracted_effects.effects.push(ExtractedCpuEffect {
+            main_entity,
+            instances: particles.instances.clone(),
+            use_alpha_mask: particles.use_alpha_mask,
+            #[cfg(feature = "2d")]
+            z_sort_key_2d: FloatOrd(particles.z_layer_2d),
+            #[cfg(feature = "3d")]
+            translation_3d,
+        });
+    }
+}
+
+/// GPU resources to render the effects simulated on CPU.
+#[derive(Resource)]
+pub(crate) struct CpuEffectsMeta {
+    /// Particles of all the effect instances, drawn as instanced quads.
+    instances: BufferVec<GpuCpuParticle>,
+    view_bind_group: Option<BindGroup>,
+}
+
+impl Default for CpuEffectsMeta {
+    fn default() -> Self {
+        Self {
+            instances: BufferVec::new(BufferUsages::VERTEX),
+            view_bind_group: None,
+        }
+    }
+}
+
+/// Draw batch of a single effect instance simulated on CPU.
+#[derive(Debug, Component)]
+pub(crate) struct CpuDrawBatch {
+    /// Main world entity of the effect instance.
+    main_entity: Entity,
+    /// Range of the particles of the effect in the instance buffer.
+    range: Range<u32>,
+    use_alpha_mask: bool,
+    #[cfg(feature = "2d")]
+    z_sort_key_2d: FloatOrd,
+    #[cfg(feature = "3d")]
+    translation_3d: Vec3,
+}
+
+/// Upload the particles of all the effects simulated on CPU, and spawn their
+/// draw batches.
+pub(crate) fn prepare_cpu_effects(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut meta: ResMut<CpuEffectsMeta>,
+    mut extracted_effects: ResMut<ExtractedCpuEffects>,
+) {
+    meta.instances.clear();
+    for effect in extracted_effects.effects.drain(..) {
+        let start = meta.instances.len() as u32;
+        for instance in effect.instances {
+            meta.instances.push(instance);
+        }
+        commands.spawn(CpuDrawBatch {
+            main_entity: effect.main_entity,
+            range: start..meta.instances.len() as u32,
+            use_alpha_mask: effect.use_alpha_mask,
+            #[cfg(feature = "2d")]
+            z_sort_key_2d: effect.z_sort_key_2d,
+            #[cfg(feature = "3d")]
+            translation_3d: effect.translation_3d,
+        });
+    }
+    meta.instances.write_buffer(&render_device, &render_queue);
+}
+
+pub(crate) fn prepare_cpu_bind_groups(
+    mut meta: ResMut<CpuEffectsMeta>,
+    render_device: Res<RenderDevice>,
+    view_uniforms: Res<ViewUniforms>,
+    billboard_views: Res<BillboardViewUniforms>,
+    pipeline: Res<CpuParticlesPipeline>,
+) {
+    let (Some(view_binding), Some(billboard_binding)) = (
+        view_uniforms.uniforms.binding(),
+        billboard_views.uniforms.binding(),
+    ) else {
+        return;
+    };
+    meta.view_bind_group = Some(render_device.create_bind_group(
+        "hanabi:bind_group_cpu_view",
+        &pipeline.view_layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: view_binding,
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: billboard_binding,
+            },
+        ],
+    ));
+}
+
+#[derive(Resource)]
+pub(crate) struct CpuParticlesPipeline {
+    view_layout: BindGroupLayout,
+}
+
+impl FromWorld for CpuParticlesPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let view_layout = render_device.create_bind_group_layout(
+            "hanabi:view_layout_cpu",
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(ViewUniform::min_size()),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuBillboardView::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        );
+        Self { view_layout }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub(crate) struct CpuParticlesPipelineKey {
+    /// Key: USE_ALPHA_MASK
+    /// The effect is rendered with alpha masking.
+    use_alpha_mask: bool,
+    /// For dual-mode configurations only, the actual mode of the current render
+    /// pipeline.
+    #[cfg(all(feature = "2d", feature = "3d"))]
+    pipeline_mode: PipelineMode,
+    /// MSAA sample count.
+    msaa_samples: u32,
+    /// Is the camera using an HDR render target?
+    hdr: bool,
+}
+
+impl SpecializedRenderPipeline for CpuParticlesPipeline {
+    type Key = CpuParticlesPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        trace!("Specializing CPU render pipeline for key: {:?}", key);
+
+        let vertex_buffer_layout = VertexBufferLayout {
+            array_stride: size_of::<GpuCpuParticle>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                //  @location(0) position: vec3<f32>
+                VertexAttribute {
+                    format: VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                //  @location(1) size: vec2<f32>
+                VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: 12,
+                    shader_location: 1,
+                },
+                //  @location(2) color: vec4<f32>
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 20,
+                    shader_location: 2,
+                },
+            ],
+        };
+
+        let mut shader_defs = vec![];
+        if key.use_alpha_mask {
+            shader_defs.push("USE_ALPHA_MASK".into());
+        }
+
+        let depth_stencil_3d = DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            // Use depth buffer with alpha-masked particles, not with transparent ones
+            depth_write_enabled: key.use_alpha_mask,
+            // Bevy uses reverse-Z, so Greater really means closer
+            depth_compare: CompareFunction::Greater,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        };
+
+        #[cfg(all(feature = "2d", feature = "3d"))]
+        let depth_stencil = match key.pipeline_mode {
+            // Bevy's Transparent2d render phase doesn't support a depth-stencil buffer.
+            PipelineMode::Camera2d => None,
+            PipelineMode::Camera3d => Some(depth_stencil_3d),
+        };
+
+        #[cfg(all(feature = "2d", not(feature = "3d")))]
+        let depth_stencil: Option<DepthStencilState> = {
+            let _ = depth_stencil_3d;
+            None
+        };
+
+        #[cfg(all(feature = "3d", not(feature = "2d")))]
+        let depth_stencil = Some(depth_stencil_3d);
+
+        let format = if key.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        RenderPipelineDescriptor {
+            vertex: VertexState {
+                shader: HANABI_CPU_SHADER_HANDLE,
+                entry_point: "vertex".into(),
+                shader_defs: shader_defs.clone(),
+                buffers: vec![vertex_buffer_layout],
+            },
+            fragment: Some(FragmentState {
+                shader: HANABI_CPU_SHADER_HANDLE,
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            layout: vec![self.view_layout.clone()],
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+            },
+            depth_stencil,
+            multisample: MultisampleState {
+                count: key.msaa_samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            label: Some("hanabi:pipeline_render_cpu".into()),
+            push_constant_ranges: Vec::new(),
+        }
+    }
+}
+
+/// Emit the draw calls of all the CPU batches visible in each view of a render
+/// phase.
+#[allow(clippy::too_many_arguments)]
+fn emit_cpu_draw<T, F>(
+    views: &mut Query<(&mut RenderPhase<T>, &VisibleEntities, &ExtractedView)>,
+    draw_batches: &Query<(Entity, &CpuDrawBatch)>,
+    pipeline: &CpuParticlesPipeline,
+    specialized_pipelines: &mut SpecializedRenderPipelines<CpuParticlesPipeline>,
+    pipeline_cache: &PipelineCache,
+    msaa_samples: u32,
+    make_phase_item: F,
+    #[cfg(all(feature = "2d", feature = "3d"))] pipeline_mode: PipelineMode,
+    use_alpha_mask: bool,
+) where
+    T: PhaseItem,
+    F: Fn(CachedRenderPipelineId, Entity, &CpuDrawBatch, &ExtractedView) -> T,
+{
+    for (mut render_phase, visible_entities, view) in views.iter_mut() {
+        for (draw_entity, draw_batch) in draw_batches.iter() {
+            if draw_batch.use_alpha_mask != use_alpha_mask
+                || !visible_entities.entities.contains(&draw_batch.main_entity)
+            {
+                continue;
+            }
+            let pipeline_id = specialized_pipelines.specialize(
+                pipeline_cache,
+                pipeline,
+                CpuParticlesPipelineKey {
+                    use_alpha_mask,
+                    #[cfg(all(feature = "2d", feature = "3d"))]
+                    pipeline_mode,
+                    msaa_samples,
+                    hdr: view.hdr,
+                },
+            );
+            render_phase.add(make_phase_item(pipeline_id, draw_entity, draw_batch, view));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn queue_cpu_effects(
+    #[cfg(feature = "2d")] mut views_2d: Query<(
+        &mut RenderPhase<Transparent2d>,
+        &VisibleEntities,
+        &ExtractedView,
+    )>,
+    #[cfg(feature = "3d")] mut views_3d: Query<(
+        &mut RenderPhase<Transparent3d>,
+        &VisibleEntities,
+        &ExtractedView,
+    )>,
+    #[cfg(feature = "3d")] mut views_alpha_mask: Query<(
+        &mut RenderPhase<AlphaMask3d>,
+        &VisibleEntities,
+        &ExtractedView,
+    )>,
+    #[cfg(feature = "2d")] draw_functions_2d: Res<DrawFunctions<Transparent2d>>,
+    #[cfg(feature = "3d")] draw_functions_3d: Res<DrawFunctions<Transparent3d>>,
+    #[cfg(feature = "3d")] draw_functions_alpha_mask: Res<DrawFunctions<AlphaMask3d>>,
+    pipeline: Res<CpuParticlesPipeline>,
+    mut specialized_pipelines: ResMut<SpecializedRenderPipelines<CpuParticlesPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    msaa: Res<Msaa>,
+    draw_batches: Query<(Entity, &CpuDrawBatch)>,
+) {
+    if draw_batches.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "2d")]
+    {
+        let draw_function = draw_functions_2d.read().id::<DrawCpuEffects>();
+        emit_cpu_draw(
+            &mut views_2d,
+            &draw_batches,
+            &pipeline,
+            &mut specialized_pipelines,
+            &pipeline_cache,
+            msaa.samples(),
+            |id, entity, batch, _view| Transparent2d {
+                draw_function,
+                pipeline: id,
+                entity,
+                sort_key: batch.z_sort_key_2d,
+                batch_range: 0..1,
+                dynamic_offset: None,
+            },
+            #[cfg(feature = "3d")]
+            PipelineMode::Camera2d,
+            false,
+        );
+    }
+
+    #[cfg(feature = "3d")]
+    {
+        let draw_function = draw_functions_3d.read().id::<DrawCpuEffects>();
+        emit_cpu_draw(
+            &mut views_3d,
+            &draw_batches,
+            &pipeline,
+            &mut specialized_pipelines,
+            &pipeline_cache,
+            msaa.samples(),
+            |id, entity, batch, view| Transparent3d {
+                draw_function,
+                pipeline: id,
+                entity,
+                distance: view
+                    .rangefinder3d()
+                    .distance_translation(&batch.translation_3d),
+                batch_range: 0..1,
+                dynamic_offset: None,
+            },
+            #[cfg(feature = "2d")]
+            PipelineMode::Camera3d,
+            false,
+        );
+
+        let draw_function = draw_functions_alpha_mask.read().id::<DrawCpuEffects>();
+        emit_cpu_draw(
+            &mut views_alpha_mask,
+            &draw_batches,
+            &pipeline,
+            &mut specialized_pipelines,
+            &pipeline_cache,
+            msaa.samples(),
+            |id, entity, batch, view| AlphaMask3d {
+                draw_function,
+                pipeline: id,
+                entity,
+                distance: view
+                    .rangefinder3d()
+                    .distance_translation(&batch.translation_3d),
+                batch_range: 0..1,
+                dynamic_offset: None,
+            },
+            #[cfg(feature = "2d")]
+            PipelineMode::Camera3d,
+            true,
+        );
+    }
+}
+
+type DrawCpuEffectsSystemState = SystemState<(
+    SRes<CpuEffectsMeta>,
+    SRes<PipelineCache>,
+    SQuery<(Read<ViewUniformOffset>, Read<BillboardViewOffset>)>,
+    SQuery<Read<CpuDrawBatch>>,
+)>;
+
+/// Draw function for rendering the effects simulated on CPU.
+pub(crate) struct DrawCpuEffects {
+    params: DrawCpuEffectsSystemState,
+}
+
+impl DrawCpuEffects {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            params: SystemState::new(world),
+        }
+    }
+
+    fn draw_batch<'w>(
+        &mut self,
+        world: &'w World,
+        pass: &mut TrackedRenderPass<'w>,
+        view: Entity,
+        entity: Entity,
+        pipeline_id: CachedRenderPipelineId,
+    ) {
+        let (meta, pipeline_cache, views, draw_batches) = self.params.get(world);
+        let meta = meta.into_inner();
+        let (
+            Ok((view_uniform, billboard_view)),
+            Ok(draw_batch),
+            Some(view_bind_group),
+            Some(instances),
+        ) = (
+            views.get(view),
+            draw_batches.get(entity),
+            meta.view_bind_group.as_ref(),
+            meta.instances.buffer(),
+        )
+        else {
+            return;
+        };
+        let Some(pipeline) = pipeline_cache.into_inner().get_render_pipeline(pipeline_id) else {
+            return;
+        };
+
+        pass.set_render_pipeline(pipeline);
+        pass.set_bind_group(
+            0,
+            view_bind_group,
+            &[view_uniform.offset, billboard_view.offset],
+        );
+        pass.set_vertex_buffer(0, instances.slice(..));
+        // Each particle is a quad made of 2 triangles
+        pass.draw(0..6, draw_batch.range.clone());
+    }
+}
+
+#[cfg(feature = "2d")]
+impl Draw<Transparent2d> for DrawCpuEffects {
+    fn draw<'w>(
+        &mut self,
+        world: &'w World,
+        pass: &mut TrackedRenderPass<'w>,
+        view: Entity,
+        item: &Transparent2d,
+    ) {
+        self.draw_batch(world, pass, view, item.entity, item.pipeline);
+    }
+}
+
+#[cfg(feature = "3d")]
+impl Draw<Transparent3d> for DrawCpuEffects {
+    fn draw<'w>(
+        &mut self,
+        world: &'w World,
+        pass: &mut TrackedRenderPass<'w>,
+        view: Entity,
+        item: &Transparent3d,
+    ) {
+        self.draw_batch(world, pass, view, item.entity, item.pipeline);
+    }
+}
+
+#[cfg(feature = "3d")]
+impl Draw<AlphaMask3d> for DrawCpuEffects {
+    fn draw<'w>(
+        &mut self,
+        world: &'w World,
+        pass: &mut TrackedRenderPass<'w>,
+        view: Entity,
+        item: &AlphaMask3d,
+    ) {
+        self.draw_batch(world, pass, view, item.entity, item.pipeline);
+    }
+}
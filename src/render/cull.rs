@@ -0,0 +1,260 @@
+use std::num::NonZeroU64;
+
+use bevy::{
+    core::{Pod, Zeroable},
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        primitives::Frustum,
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+    },
+    utils::HashMap,
+};
+
+use super::{
+    aligned_buffer_vec::AlignedBufferVec, batch::EffectBatches, LayoutFlags,
+    ParticlesUpdatePipeline,
+};
+
+/// Parameters of the culling of the particles of a single group against a
+/// single view.
+///
+/// This is the GPU representation of the `CullParams` struct of
+/// `vfx_cull.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuCullParams {
+    /// Frustum planes of the view, in world space, as `(normal, distance)`.
+    pub planes: [Vec4; 6],
+    /// Offset of the list of visible particles in the cull index buffer.
+    pub index_offset: u32,
+    /// Index of the indirect draw arguments in the cull draw buffer.
+    pub draw_index: u32,
+    pub __pad: [u32; 2],
+}
+
+/// Indirect draw arguments of the visible particles of a single group in a
+/// single view.
+///
+/// The instance count is accumulated by the cull pass, while the base instance
+/// is the offset of the list of visible particles in the cull index buffer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuCullDraw {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub vertex_offset: i32,
+    pub base_instance: u32,
+}
+
+/// Resources for the per-view culling of individual particles.
+///
+/// See [`EffectAsset::with_particle_culling()`] for details.
+///
+/// [`EffectAsset::with_particle_culling()`]: crate::EffectAsset::with_particle_culling
+#[derive(Resource)]
+pub(crate) struct CullMeta {
+    /// Cull parameters of each group of each culled effect, for each view.
+    params: AlignedBufferVec<GpuCullParams>,
+    /// Indirect draw arguments, in the same order as [`params`].
+    ///
+    /// [`params`]: CullMeta::params
+    draws: BufferVec<GpuCullDraw>,
+    /// Lists of the indices of the visible particles, one list per entry of
+    /// [`params`]. This buffer is always allocated, as it's bound to the view
+    /// bind group of all effects.
+    ///
+    /// [`params`]: CullMeta::params
+    index_buffer: Option<Buffer>,
+    /// Number of indices [`index_buffer`] can hold.
+    ///
+    /// [`index_buffer`]: CullMeta::index_buffer
+    index_buffer_capacity: u32,
+    /// Index of the entry of a group of an [`EffectBatches`] for a view, keyed
+    /// by the entity of the batches, group index, and view entity.
+    slots: HashMap<(Entity, u32, Entity), u32>,
+    /// Bind group of the cull pass.
+    bind_group: Option<BindGroup>,
+}
+
+impl FromWorld for CullMeta {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let storage_alignment = render_device.limits().min_storage_buffer_offset_alignment;
+        Self {
+            params: AlignedBufferVec::new(
+                BufferUsages::STORAGE,
+                NonZeroU64::new(storage_alignment as u64),
+                Some("hanabi:buffer:cull_params".to_string()),
+            ),
+            draws: BufferVec::new(BufferUsages::STORAGE | BufferUsages::INDIRECT),
+            index_buffer: None,
+            index_buffer_capacity: 0,
+            slots: default(),
+            bind_group: None,
+        }
+    }
+}
+
+impl CullMeta {
+    /// Get the index of the culled draw of a group in a view, if any.
+    pub fn slot(&self, batches_entity: Entity, group_index: u32, view: Entity) -> Option<u32> {
+        self.slots
+            .get(&(batches_entity, group_index, view))
+            .copied()
+    }
+
+    /// Get the dynamic offset of the cull parameters of a slot.
+    pub fn params_offset(&self, slot: u32) -> u32 {
+        slot * self.params.aligned_size() as u32
+    }
+
+    /// Get the offset of the indirect draw arguments of a slot.
+    pub fn draw_offset(&self, slot: u32) -> u64 {
+        slot as u64 * GpuCullDraw::min_size().get()
+    }
+
+    /// Iterate over the slots of a group of an [`EffectBatches`], for all views.
+    pub fn group_slots(
+        &self,
+        batches_entity: Entity,
+        group_index: u32,
+    ) -> impl Iterator<Item = u32> + '_ {
+        self.slots
+            .iter()
+            .filter(move |((entity, index, _), _)| {
+                *entity == batches_entity && *index == group_index
+            })
+            .map(|(_, slot)| *slot)
+    }
+
+    pub fn index_buffer(&self) -> Option<&Buffer> {
+        self.index_buffer.as_ref()
+    }
+
+    pub fn draw_buffer(&self) -> Option<&Buffer> {
+        self.draws.buffer()
+    }
+
+    pub fn bind_group(&self) -> Option<&BindGroup> {
+        self.bind_group.as_ref()
+    }
+}
+
+/// Allocate the per-view lists of visible particles of all effects culling
+/// their particles, and upload the frustum of each view.
+///
+/// This system runs in the [`Prepare`] render set, before the view bind group
+/// referencing the cull index buffer is created.
+///
+/// [`Prepare`]: bevy::render::RenderSet::Prepare
+pub(crate) fn prepare_particle_culling(
+    mut cull_meta: ResMut<CullMeta>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    update_pipeline: Res<ParticlesUpdatePipeline>,
+    effect_batches: Query<(Entity, &EffectBatches)>,
+    views: Query<(Entity, &Frustum), With<ExtractedCamera>>,
+) {
+    let cull_meta = cull_meta.as_mut();
+    cull_meta.params.clear();
+    cull_meta.draws.clear();
+    cull_meta.slots.clear();
+
+    let mut index_count = 0;
+    for (batches_entity, batches) in &effect_batches {
+        if !batches.layout_flags.contains(LayoutFlags::CULL_PARTICLES) {
+            continue;
+        }
+        for (group_index, group_batch) in batches.group_batches.iter().enumerate() {
+            for (view_entity, frustum) in &views {
+                let slot = cull_meta.params.push(GpuCullParams {
+                    planes: frustum.half_spaces.map(|half_space| half_space.normal_d()),
+                    index_offset: index_count,
+                    draw_index: cull_meta.draws.len() as u32,
+                    ..default()
+                });
+                cull_meta.draws.push(GpuCullDraw {
+                    base_instance: index_count,
+                    ..default()
+                });
+                cull_meta.slots.insert(
+                    (batches_entity, group_index as u32, view_entity),
+                    slot as u32,
+                );
+                index_count += group_batch.slice.len() as u32;
+            }
+        }
+    }
+
+    // (Re-)allocate the index buffer if needed. Always allocate at least one index,
+    // as the buffer is bound to the view bind group even if no effect is culled.
+    let index_count = index_count.max(1);
+    let index_buffer_changed =
+        cull_meta.index_buffer.is_none() || cull_meta.index_buffer_capacity < index_count;
+    if index_buffer_changed {
+        trace!("Allocating cull index buffer for {} indices", index_count);
+        cull_meta.index_buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("hanabi:buffer:cull_index"),
+            size: index_count as u64 * 4,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        }));
+        cull_meta.index_buffer_capacity = index_count;
+    }
+
+    if cull_meta.slots.is_empty() {
+        cull_meta.bind_group = None;
+        return;
+    }
+
+    // The instance counts are reset each frame by uploading the draws again
+    let params_changed = cull_meta.params.write_buffer(&render_device, &render_queue);
+    let draw_buffer = cull_meta.draws.buffer().map(|buffer| buffer.id());
+    cull_meta.draws.write_buffer(&render_device, &render_queue);
+    let draws_changed = cull_meta.draws.buffer().map(|buffer| buffer.id()) != draw_buffer;
+
+    if cull_meta.bind_group.is_none() || params_changed || draws_changed || index_buffer_changed {
+        cull_meta.bind_group = Some(render_device.create_bind_group(
+            "hanabi:bind_group_cull",
+            &update_pipeline.cull_layout,
+            &[
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: cull_meta.params.buffer().unwrap(),
+                        offset: 0,
+                        size: Some(GpuCullParams::min_size()),
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: cull_meta.index_buffer.as_ref().unwrap().as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: cull_meta.draws.buffer().unwrap().as_entire_binding(),
+                },
+            ],
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cull_params_layout() {
+        // The layout must match the CullParams struct of vfx_cull.wgsl
+        assert_eq!(GpuCullParams::min_size().get(), 112);
+        assert_eq!(std::mem::size_of::<GpuCullParams>(), 112);
+        assert_eq!(std::mem::offset_of!(GpuCullParams, index_offset), 96);
+        assert_eq!(std::mem::offset_of!(GpuCullParams, draw_index), 100);
+
+        // The draw arguments must match the layout expected by draw_indirect()
+        assert_eq!(GpuCullDraw::min_size().get(), 16);
+        assert_eq!(std::mem::size_of::<GpuCullDraw>(), 16);
+    }
+}
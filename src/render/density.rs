@@ -0,0 +1,482 @@
+use std::borrow::Cow;
+
+use bevy::{
+    math::Affine3A,
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+            BufferBinding, BufferBindingType, BufferDescriptor, BufferInitDescriptor, BufferSize,
+            BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
+            PipelineLayoutDescriptor, RawComputePipelineDescriptor, ShaderModuleDescriptor,
+            ShaderSource, ShaderStages, ShaderType, StorageTextureAccess, TextureDimension,
+            TextureFormat, TextureUsages, TextureViewDimension,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use super::{EffectCache, EffectsMeta};
+use crate::{Attribute, CompiledParticleEffect, ParticleDensitySource, ParticleDensityVolume};
+
+/// Scale of the fixed point density values accumulated on GPU.
+///
+/// This must match the `DENSITY_SCALE` constant of `vfx_density.wgsl`.
+const DENSITY_SCALE: f32 = 256.;
+
+/// Workgroup size of the inject shader.
+const INJECT_WORKGROUP_SIZE: u32 = 64;
+
+/// Workgroup size of the resolve shader, along each axis.
+const RESOLVE_WORKGROUP_SIZE: u32 = 4;
+
+/// Parameters of the injection of the particles of a single group into a
+/// volume, or of the resolve of a volume.
+///
+/// This is the GPU representation of the `DensityParams` struct of
+/// `vfx_density.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuDensityParams {
+    /// Transform from the simulation space of the effect to the cell
+    /// coordinates of the volume.
+    pub simulation_to_cell: Mat4,
+    /// Number of cells of the volume along each axis.
+    pub resolution: UVec3,
+    /// Density added by each particle, in fixed point.
+    pub density: u32,
+    /// Size of a single particle, in words.
+    pub particle_stride: u32,
+    /// Offset of the position attribute in a particle, in words.
+    pub position_offset: u32,
+    /// Offset of the render group indirect arguments of the group, in words.
+    pub render_group_word: u32,
+    /// Offset of the render effect metadata of the effect, in words.
+    pub render_effect_word: u32,
+}
+
+/// Volume extracted from a [`ParticleDensityVolume`].
+struct DensityVolume {
+    /// Image the density is written into.
+    image: Handle<Image>,
+    /// Transform from world space to the local space of the volume.
+    world_to_local: Affine3A,
+}
+
+/// Effect instance extracted from a [`ParticleDensitySource`].
+struct DensitySource {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Density added by each particle, in fixed point.
+    density: u32,
+    /// Transform from the simulation space of the effect to world space.
+    simulation_to_world: Affine3A,
+}
+
+/// Render world resource accumulating the density of the particles of the
+/// effect instances with a [`ParticleDensitySource`] into the images of the
+/// [`ParticleDensityVolume`]s.
+///
+/// The density shaders run at the end of the frame. For each volume, the
+/// particles of each group of each source are added into a buffer with one
+/// atomic word per cell, which is then written into the image of the volume.
+#[derive(Resource)]
+pub(crate) struct DensityInjection {
+    /// Layout of the bind group of the inject pipeline.
+    inject_layout: BindGroupLayout,
+    /// Layout of the bind group of the resolve pipeline.
+    resolve_layout: BindGroupLayout,
+    /// Pipeline adding the density of the particles of a group into a volume.
+    inject_pipeline: ComputePipeline,
+    /// Pipeline writing the density of a volume into its image.
+    resolve_pipeline: ComputePipeline,
+    /// Volumes extracted this frame.
+    volumes: Vec<DensityVolume>,
+    /// Sources extracted this frame.
+    sources: Vec<DensitySource>,
+}
+
+impl FromWorld for DensityInjection {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let params_entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: Some(GpuDensityParams::min_size()),
+            },
+            count: None,
+        };
+        let storage_entry = |binding: u32, read_only: bool| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(4),
+            },
+            count: None,
+        };
+        let inject_layout = render_device.create_bind_group_layout(
+            "hanabi:bind_group_layout:density_inject",
+            &[
+                // @binding(0) var<storage, read> params : DensityParams
+                params_entry,
+                // @binding(1) var<storage, read> particle_buffer : array<u32>
+                storage_entry(1, true),
+                // @binding(2) var<storage, read> indirect_buffer : array<u32>
+                storage_entry(2, true),
+                // @binding(3) var<storage, read> render_group_buffer : array<u32>
+                storage_entry(3, true),
+                // @binding(4) var<storage, read> render_effect_buffer : array<u32>
+                storage_entry(4, true),
+                // @binding(5) var<storage, read_write> density_buffer : array<atomic<u32>>
+                storage_entry(5, false),
+            ],
+        );
+        let resolve_layout = render_device.create_bind_group_layout(
+            "hanabi:bind_group_layout:density_resolve",
+            &[
+                // @binding(0) var<storage, read> params : DensityParams
+                params_entry,
+                // @binding(5) var<storage, read_write> density_buffer : array<atomic<u32>>
+                storage_entry(5, false),
+                // @binding(6) var density_texture : texture_storage_3d<r32float, write>
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::R32Float,
+                        view_dimension: TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let shader_module = render_device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("hanabi:vfx_density_shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("vfx_density.wgsl"))),
+        });
+
+        let create_pipeline = |layout: &BindGroupLayout, entry_point: &str| {
+            let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some(&format!("hanabi:pipeline_layout:density_{}", entry_point)),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+                label: Some(&format!("hanabi:compute_pipeline:density_{}", entry_point)),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+
+        Self {
+            inject_pipeline: create_pipeline(&inject_layout, "inject"),
+            resolve_pipeline: create_pipeline(&resolve_layout, "resolve"),
+            inject_layout,
+            resolve_layout,
+            volumes: vec![],
+            sources: vec![],
+        }
+    }
+}
+
+/// Extract the [`ParticleDensityVolume`]s and the effect instances with a
+/// [`ParticleDensitySource`].
+pub(crate) fn extract_density_volumes(
+    mut injection: ResMut<DensityInjection>,
+    volumes: Extract<Query<(&ParticleDensityVolume, &GlobalTransform)>>,
+    sources: Extract<
+        Query<(
+            Entity,
+            &ParticleDensitySource,
+            &CompiledParticleEffect,
+            Option<&GlobalTransform>,
+        )>,
+    >,
+) {
+    injection.volumes.clear();
+    injection.sources.clear();
+    if volumes.is_empty() {
+        return;
+    }
+    injection
+        .volumes
+        .extend(volumes.iter().map(|(volume, transform)| DensityVolume {
+            image: volume.image.clone(),
+            world_to_local: transform.affine().inverse(),
+        }));
+    injection.sources.extend(
+        sources
+            .iter()
+            .filter_map(|(entity, source, compiled, transform)| {
+                let density = (source.density * DENSITY_SCALE).round();
+                (density >= 1.).then(|| DensitySource {
+                    entity,
+                    density: density as u32,
+                    simulation_to_world: compiled.simulation_to_world(transform),
+                })
+            }),
+    );
+}
+
+/// Accumulate the density of the particles of the sources into the images of
+/// the volumes.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted, so the images are sampled during the
+/// next frame.
+pub(crate) fn inject_particle_density(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    effect_cache: Res<EffectCache>,
+    gpu_images: Res<RenderAssets<Image>>,
+    injection: Res<DensityInjection>,
+) {
+    if injection.volumes.is_empty() {
+        return;
+    }
+    let (Some(render_group_buffer), Some(render_effect_buffer)) = (
+        effects_meta.render_group_dispatch_buffer.buffer(),
+        effects_meta.render_effect_dispatch_buffer.buffer(),
+    ) else {
+        return;
+    };
+    let render_group_row_words =
+        effects_meta.render_group_dispatch_buffer.aligned_size() as u32 / 4;
+    let render_effect_row_words =
+        effects_meta.render_effect_dispatch_buffer.aligned_size() as u32 / 4;
+
+    // Collect the parameters of each volume, followed by the ones of each group
+    // of the allocated sources
+    let mut volumes = vec![];
+    let mut params = vec![];
+    let mut groups = vec![];
+    for volume in &injection.volumes {
+        let Some(gpu_image) = gpu_images.get(&volume.image) else {
+            continue;
+        };
+        let texture = &gpu_image.texture;
+        if texture.format() != TextureFormat::R32Float
+            || !texture.usage().contains(TextureUsages::STORAGE_BINDING)
+            || texture.dimension() != TextureDimension::D3
+        {
+            continue;
+        }
+        let size = texture.size();
+        let resolution = UVec3::new(size.width, size.height, size.depth_or_array_layers);
+        let world_to_cell = Mat4::from_scale(resolution.as_vec3())
+            * Mat4::from_translation(Vec3::splat(0.5))
+            * Mat4::from(volume.world_to_local);
+
+        let volume_index = volumes.len();
+        volumes.push((params.len(), gpu_image, resolution));
+        params.push(GpuDensityParams {
+            simulation_to_cell: world_to_cell,
+            resolution,
+            ..default()
+        });
+
+        for source in &injection.sources {
+            let Some(entry) = effects_meta.entity_map.get(&source.entity) else {
+                continue;
+            };
+            let effect_slices = effect_cache.get_slices(entry.cache_id);
+            let layout = &effect_slices.particle_layout;
+            let Some(position_offset) = layout
+                .attributes()
+                .iter()
+                .find(|attr_layout| attr_layout.attribute == Attribute::POSITION)
+                .map(|attr_layout| attr_layout.offset / 4)
+            else {
+                continue;
+            };
+            let dispatch_buffer_indices = effect_cache.get_dispatch_buffer_indices(entry.cache_id);
+            let simulation_to_cell = world_to_cell * Mat4::from(source.simulation_to_world);
+            for (group_index, group) in effect_slices.slices.windows(2).enumerate() {
+                groups.push((
+                    volume_index,
+                    params.len(),
+                    effect_slices.buffer_index,
+                    group[1] - group[0],
+                ));
+                params.push(GpuDensityParams {
+                    simulation_to_cell,
+                    resolution,
+                    density: source.density,
+                    particle_stride: layout.min_binding_size().get() as u32 / 4,
+                    position_offset,
+                    render_group_word: (dispatch_buffer_indices
+                        .first_render_group_dispatch_buffer_index
+                        .0
+                        + group_index as u32)
+                        * render_group_row_words,
+                    render_effect_word: dispatch_buffer_indices
+                        .render_effect_metadata_buffer_index
+                        .0
+                        * render_effect_row_words,
+                });
+            }
+        }
+    }
+    if volumes.is_empty() {
+        return;
+    }
+
+    // Pack the parameters at the storage buffer offset alignment
+    let alignment = render_device.limits().min_storage_buffer_offset_alignment as usize;
+    let params_size = GpuDensityParams::min_size().get() as usize;
+    let params_stride = params_size.next_multiple_of(alignment);
+    let mut params_data = vec![0u8; params.len() * params_stride];
+    for (index, volume_params) in params.iter().enumerate() {
+        params_data[index * params_stride..index * params_stride + params_size]
+            .copy_from_slice(bytemuck::bytes_of(volume_params));
+    }
+    let params_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("hanabi:buffer:density_params"),
+        contents: &params_data,
+        usage: BufferUsages::STORAGE,
+    });
+    let params_binding = |index: usize| {
+        BindingResource::Buffer(BufferBinding {
+            buffer: &params_buffer,
+            offset: (index * params_stride) as u64,
+            size: Some(GpuDensityParams::min_size()),
+        })
+    };
+
+    // Buffers are zero-initialized, so each volume starts empty
+    let density_buffers: Vec<_> = volumes
+        .iter()
+        .map(|(_, _, resolution)| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some("hanabi:buffer:density"),
+                size: (resolution.x * resolution.y * resolution.z) as u64 * 4,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        })
+        .collect();
+
+    let inject_bind_groups: Vec<_> = groups
+        .iter()
+        .filter_map(|(volume_index, params_index, buffer_index, capacity)| {
+            let effect_buffer = effect_cache.buffers()[*buffer_index as usize].as_ref()?;
+            let bind_group = render_device.create_bind_group(
+                "hanabi:bind_group_density_inject",
+                &injection.inject_layout,
+                &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: params_binding(*params_index),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: effect_buffer.particle_buffer().as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: effect_buffer.indirect_buffer().as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: render_group_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: render_effect_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: density_buffers[*volume_index].as_entire_binding(),
+                    },
+                ],
+            );
+            Some((bind_group, capacity.div_ceil(INJECT_WORKGROUP_SIZE)))
+        })
+        .collect();
+
+    let resolve_bind_groups: Vec<_> = volumes
+        .iter()
+        .zip(&density_buffers)
+        .map(|((params_index, gpu_image, resolution), density_buffer)| {
+            let bind_group = render_device.create_bind_group(
+                "hanabi:bind_group_density_resolve",
+                &injection.resolve_layout,
+                &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: params_binding(*params_index),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: density_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: BindingResource::TextureView(&gpu_image.texture_view),
+                    },
+                ],
+            );
+            let workgroup_count =
+                (*resolution + (RESOLVE_WORKGROUP_SIZE - 1)) / RESOLVE_WORKGROUP_SIZE;
+            (bind_group, workgroup_count)
+        })
+        .collect();
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:particle_density"),
+    });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("hanabi:density"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&injection.inject_pipeline);
+        for (bind_group, workgroup_count) in &inject_bind_groups {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(*workgroup_count, 1, 1);
+        }
+        compute_pass.set_pipeline(&injection.resolve_pipeline);
+        for (bind_group, workgroup_count) in &resolve_bind_groups {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                workgroup_count.x,
+                workgroup_count.y,
+                workgroup_count.z,
+            );
+        }
+    }
+    render_queue.submit([encoder.finish()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn density_params_layout() {
+        // The layout must match the DensityParams struct of vfx_density.wgsl
+        assert_eq!(GpuDensityParams::min_size().get(), 96);
+        assert_eq!(std::mem::size_of::<GpuDensityParams>(), 96);
+        assert_eq!(std::mem::offset_of!(GpuDensityParams, resolution), 64);
+        assert_eq!(std::mem::offset_of!(GpuDensityParams, density), 76);
+        assert_eq!(
+            std::mem::offset_of!(GpuDensityParams, render_effect_word),
+            92
+        );
+    }
+}
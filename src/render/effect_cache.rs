@@ -15,6 +15,7 @@ use std::{
 
 use crate::{
     asset::EffectAsset,
+    inject::injected_particle_stride,
     render::{
         GpuDispatchIndirect, GpuParticleGroup, GpuSpawnerParams, LayoutFlags, StorageType as _,
     },
@@ -111,6 +112,16 @@ pub struct EffectBuffer {
     /// GPU buffer holding the properties of the effect(s), if any. This is
     /// always `None` if the property layout is empty.
     properties_buffer: Option<Buffer>,
+    /// GPU buffer holding the particles injected from the CPU this frame, if
+    /// any. This is always `None` if the injection capacity is zero.
+    injection_buffer: Option<Buffer>,
+    /// Capacity of the injection buffer, in number of particles.
+    injection_capacity: u32,
+    /// GPU buffer holding the trail history of all particles, if any. This is
+    /// always `None` if the trail stride is zero.
+    trail_buffer: Option<Buffer>,
+    /// Number of positions recorded per particle in the trail buffer.
+    trail_stride: u32,
     /// Layout of particles.
     particle_layout: ParticleLayout,
     /// Layout of properties of the effect(s), if using properties.
@@ -166,6 +177,8 @@ impl EffectBuffer {
         particle_layout: ParticleLayout,
         property_layout: PropertyLayout,
         layout_flags: LayoutFlags,
+        injection_capacity: u32,
+        trail_stride: u32,
         render_device: &RenderDevice,
         label: Option<&str>,
     ) -> Self {
@@ -190,7 +203,8 @@ impl EffectBuffer {
         let particle_buffer = render_device.create_buffer(&BufferDescriptor {
             label,
             size: particle_capacity_bytes,
-            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            // COPY_SRC for the particle captures
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
 
@@ -204,7 +218,7 @@ impl EffectBuffer {
         let indirect_buffer = render_device.create_buffer(&BufferDescriptor {
             label: Some(&indirect_label),
             size: capacity_bytes * 3, // ping-pong + deadlist
-            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
             mapped_at_creation: true,
         });
         // Set content
@@ -239,6 +253,41 @@ impl EffectBuffer {
             Some(properties_buffer)
         };
 
+        let injection_buffer = if injection_capacity == 0 {
+            None
+        } else {
+            let injection_label = if let Some(label) = label {
+                format!("{}_injection", label)
+            } else {
+                "hanabi:buffer:effect_injection".to_owned()
+            };
+            let size = injection_capacity as u64 * injected_particle_stride(&particle_layout);
+            Some(render_device.create_buffer(&BufferDescriptor {
+                label: Some(&injection_label),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }))
+        };
+
+        let trail_buffer = if trail_stride == 0 {
+            None
+        } else {
+            let trail_label = if let Some(label) = label {
+                format!("{}_trail", label)
+            } else {
+                "hanabi:buffer:effect_trail".to_owned()
+            };
+            // One vec4<f32> per recorded position
+            let size = capacity as u64 * trail_stride as u64 * 16;
+            Some(render_device.create_buffer(&BufferDescriptor {
+                label: Some(&trail_label),
+                size,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }))
+        };
+
         // TODO - Cache particle_layout and associated bind group layout, instead of
         // creating one bind group layout per buffer using that layout...
         let particle_group_size = GpuParticleGroup::aligned_size(
@@ -293,6 +342,32 @@ impl EffectBuffer {
                 count: None,
             });
         }
+        if injection_capacity > 0 {
+            // @binding(4) var<storage, read> injected_particles : array<InjectedParticle>
+            entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(injected_particle_stride(&particle_layout)),
+                },
+                count: None,
+            });
+        }
+        if trail_stride > 0 {
+            // @binding(5) var<storage, read_write> trail_buffer : array<vec4<f32>>
+            entries.push(BindGroupLayoutEntry {
+                binding: 5,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(16),
+                },
+                count: None,
+            });
+        }
         let label = "hanabi:sim_particles_buffer_layout";
         trace!(
             "Creating particle bind group layout '{}' for simulation passes with {} entries.",
@@ -336,9 +411,10 @@ impl EffectBuffer {
                 },
                 count: None,
             },
-        ];
-        if layout_flags.contains(LayoutFlags::LOCAL_SPACE_SIMULATION) {
-            entries.push(BindGroupLayoutEntry {
+            // The spawner is only used by effects simulated in local space, but is always
+            // bound so that the layout doesn't depend on the simulation space, which can
+            // change at runtime.
+            BindGroupLayoutEntry {
                 binding: 3,
                 visibility: ShaderStages::VERTEX,
                 ty: BindingType::Buffer {
@@ -347,6 +423,19 @@ impl EffectBuffer {
                     min_binding_size: Some(GpuSpawnerParams::min_size()), // TODO - array
                 },
                 count: None,
+            },
+        ];
+        if trail_stride > 0 {
+            // @binding(4) var<storage, read> trail_buffer : array<vec4<f32>>
+            entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(16),
+                },
+                count: None,
             });
         }
         trace!(
@@ -361,6 +450,10 @@ impl EffectBuffer {
             particle_buffer,
             indirect_buffer,
             properties_buffer,
+            injection_buffer,
+            injection_capacity,
+            trail_buffer,
+            trail_stride,
             particle_layout,
             property_layout,
             layout_flags,
@@ -374,10 +467,24 @@ impl EffectBuffer {
         }
     }
 
+    /// Get the GPU buffer holding all the particles.
+    pub fn particle_buffer(&self) -> &Buffer {
+        &self.particle_buffer
+    }
+
+    /// Get the GPU buffer holding the ping-pong and dead list indices.
+    pub fn indirect_buffer(&self) -> &Buffer {
+        &self.indirect_buffer
+    }
+
     pub fn properties_buffer(&self) -> Option<&Buffer> {
         self.properties_buffer.as_ref()
     }
 
+    pub fn injection_buffer(&self) -> Option<&Buffer> {
+        self.injection_buffer.as_ref()
+    }
+
     pub fn particle_layout(&self) -> &ParticleLayout {
         &self.particle_layout
     }
@@ -390,6 +497,22 @@ impl EffectBuffer {
         self.layout_flags
     }
 
+    /// Get the total size of all the GPU buffers of this effect buffer, in
+    /// bytes.
+    pub fn memory_size(&self) -> u64 {
+        [
+            Some(&self.particle_buffer),
+            Some(&self.indirect_buffer),
+            self.properties_buffer.as_ref(),
+            self.injection_buffer.as_ref(),
+            self.trail_buffer.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|buffer| buffer.size())
+        .sum()
+    }
+
     pub fn particle_layout_bind_group_sim(&self) -> &BindGroupLayout {
         &self.particles_buffer_layout_sim
     }
@@ -443,6 +566,33 @@ impl EffectBuffer {
         })
     }
 
+    /// Return a binding for the entire injection buffer associated with the
+    /// current effect buffer, if any.
+    pub fn injection_max_binding(&self) -> Option<BindingResource> {
+        self.injection_buffer.as_ref().map(|buffer| {
+            let capacity_bytes =
+                self.injection_capacity as u64 * injected_particle_stride(&self.particle_layout);
+            BindingResource::Buffer(BufferBinding {
+                buffer,
+                offset: 0,
+                size: Some(NonZeroU64::new(capacity_bytes).unwrap()),
+            })
+        })
+    }
+
+    /// Return a binding for the entire trail buffer associated with the current
+    /// effect buffer, if any.
+    pub fn trail_max_binding(&self) -> Option<BindingResource> {
+        self.trail_buffer.as_ref().map(|buffer| {
+            let capacity_bytes = self.capacity as u64 * self.trail_stride as u64 * 16;
+            BindingResource::Buffer(BufferBinding {
+                buffer,
+                offset: 0,
+                size: Some(NonZeroU64::new(capacity_bytes).unwrap()),
+            })
+        })
+    }
+
     /// Create the bind group for the init and update passes if needed.
     ///
     /// The `buffer_index` must be the index of the current [`EffectBuffer`]
@@ -480,6 +630,18 @@ impl EffectBuffer {
                 resource: property_binding,
             });
         }
+        if let Some(injection_binding) = self.injection_max_binding() {
+            bindings.push(BindGroupEntry {
+                binding: 4,
+                resource: injection_binding,
+            });
+        }
+        if let Some(trail_binding) = self.trail_max_binding() {
+            bindings.push(BindGroupEntry {
+                binding: 5,
+                resource: trail_binding,
+            });
+        }
         trace!(
             "Create simulate bind group '{}' with {} entries",
             label,
@@ -616,7 +778,9 @@ impl EffectBuffer {
                 BufferState::Used
             }
         } else {
-            // Free slice is not at end; insert it in free list
+            // Free slice is not at end; insert it in free list, merging it with any
+            // adjacent free slice so that the free list doesn't fragment over time into
+            // many small slices unable to hold a new effect.
             let range = slice.range;
             match self.free_slices.binary_search_by(|s| {
                 if s.end <= range.start {
@@ -628,7 +792,20 @@ impl EffectBuffer {
                 }
             }) {
                 Ok(_) => warn!("Range {:?} already present in free list!", range),
-                Err(index) => self.free_slices.insert(index, range),
+                Err(index) => {
+                    let merge_prev = index > 0 && self.free_slices[index - 1].end == range.start;
+                    let merge_next = index < self.free_slices.len()
+                        && self.free_slices[index].start == range.end;
+                    match (merge_prev, merge_next) {
+                        (true, true) => {
+                            self.free_slices[index - 1].end = self.free_slices[index].end;
+                            self.free_slices.remove(index);
+                        }
+                        (true, false) => self.free_slices[index - 1].end = range.end,
+                        (false, true) => self.free_slices[index].start = range.start,
+                        (false, false) => self.free_slices.insert(index, range),
+                    }
+                }
             }
             BufferState::Used
         }
@@ -730,6 +907,16 @@ impl EffectCache {
         &mut self.buffers
     }
 
+    /// Get the total size of the GPU buffers of all the effect buffers, in
+    /// bytes.
+    pub fn memory_size(&self) -> u64 {
+        self.buffers
+            .iter()
+            .flatten()
+            .map(EffectBuffer::memory_size)
+            .sum()
+    }
+
     pub fn insert(
         &mut self,
         asset: Handle<EffectAsset>,
@@ -737,6 +924,8 @@ impl EffectCache {
         particle_layout: &ParticleLayout,
         property_layout: &PropertyLayout,
         layout_flags: LayoutFlags,
+        injection_capacity: u32,
+        trail_stride: u32,
         dispatch_buffer_indices: DispatchBufferIndices,
     ) -> EffectCacheId {
         let total_capacity = capacities.iter().cloned().sum();
@@ -782,6 +971,8 @@ impl EffectCache {
                     particle_layout.clone(),
                     property_layout.clone(),
                     layout_flags,
+                    injection_capacity,
+                    trail_stride,
                     &self.device,
                     Some(&format!("hanabi:buffer:effect{buffer_index}_particles")),
                 );
@@ -871,6 +1062,18 @@ impl EffectCache {
         }
     }
 
+    pub fn get_injection_buffer(&self, id: EffectCacheId) -> Option<&Buffer> {
+        if let Some(cached_effect_indices) = self.effects.get(&id) {
+            if let Some(buffer) = &self.buffers[cached_effect_indices.buffer_index as usize] {
+                buffer.injection_buffer()
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
     /// Remove an effect from the cache. If this was the last effect, drop the
     /// underlying buffer and return the index of the dropped buffer.
     pub fn remove(&mut self, id: EffectCacheId) -> Option<CachedEffectIndices> {
@@ -888,6 +1091,11 @@ impl EffectCache {
 
         if buffer.free_slice(slice) == BufferState::Free {
             self.buffers[indices.buffer_index as usize] = None;
+            // Drop the unused slots at the end of the buffer list, so it doesn't keep
+            // growing with effects spawned and despawned over time
+            while let Some(None) = self.buffers.last() {
+                self.buffers.pop();
+            }
             return Some(indices);
         }
 
@@ -1008,6 +1216,8 @@ mod gpu_tests {
             l64.clone(),
             PropertyLayout::empty(), // not using properties
             LayoutFlags::NONE,
+            0, // not using injection
+            0, // not using trails
             &render_device,
             Some("my_buffer"),
         );
@@ -1083,6 +1293,8 @@ mod gpu_tests {
             l64.clone(),
             PropertyLayout::empty(), // not using properties
             LayoutFlags::NONE,
+            0, // not using injection
+            0, // not using trails
             &render_device,
             Some("my_buffer"),
         );
@@ -1131,6 +1343,60 @@ mod gpu_tests {
         assert!(buffer.free_slices.is_empty()); // recycled
     }
 
+    #[test]
+    fn free_slice_merge() {
+        let renderer = MockRenderer::new();
+        let render_device = renderer.device();
+
+        let l64 = ParticleLayout::new()
+            .append(F4A)
+            .append(F4B)
+            .append(F4C)
+            .append(F4D)
+            .build();
+
+        let asset = Handle::<EffectAsset>::default();
+        let mut buffer = EffectBuffer::new(
+            asset,
+            1024,
+            l64.clone(),
+            PropertyLayout::empty(), // not using properties
+            LayoutFlags::NONE,
+            0, // not using injection
+            0, // not using trails
+            &render_device,
+            Some("my_buffer"),
+        );
+
+        let slices: Vec<_> = (0..5)
+            .map(|_| buffer.allocate_slice(32, &l64).unwrap())
+            .collect();
+        assert_eq!(slices[4].range, 128..160);
+
+        // Non-adjacent slices are kept separate
+        assert_eq!(buffer.free_slice(slices[1].clone()), BufferState::Used);
+        assert_eq!(buffer.free_slice(slices[3].clone()), BufferState::Used);
+        assert_eq!(buffer.free_slices, vec![32..64, 96..128]);
+
+        // Freeing the slice in-between merges all three
+        assert_eq!(buffer.free_slice(slices[2].clone()), BufferState::Used);
+        assert_eq!(buffer.free_slices, vec![32..128]);
+
+        // Merging with the previous slice only
+        assert_eq!(buffer.free_slice(slices[0].clone()), BufferState::Used);
+        assert_eq!(buffer.free_slices, vec![0..128]);
+
+        // The merged slice can hold a larger effect than any of the original ones
+        let slice = buffer.allocate_slice(96, &l64).unwrap();
+        assert_eq!(slice.range, 0..96);
+        assert_eq!(buffer.free_slices, vec![96..128]);
+
+        // Freeing the last slice releases the trailing free slice too
+        assert_eq!(buffer.free_slice(slices[4].clone()), BufferState::Used);
+        assert!(buffer.free_slices.is_empty());
+        assert_eq!(buffer.free_slice(slice), BufferState::Free);
+    }
+
     #[test]
     fn effect_cache() {
         let renderer = MockRenderer::new();
@@ -1155,6 +1421,8 @@ mod gpu_tests {
             &l32,
             &empty_property_layout,
             LayoutFlags::NONE,
+            0,
+            0,
             DispatchBufferIndices::default(),
         );
         assert!(id1.is_valid());
@@ -1172,6 +1440,8 @@ mod gpu_tests {
             &l32,
             &empty_property_layout,
             LayoutFlags::NONE,
+            0,
+            0,
             DispatchBufferIndices::default(),
         );
         assert!(id2.is_valid());
@@ -1199,6 +1469,8 @@ mod gpu_tests {
             &l32,
             &empty_property_layout,
             LayoutFlags::NONE,
+            0,
+            0,
             DispatchBufferIndices::default(),
         );
         assert!(id3.is_valid());
@@ -1214,5 +1486,11 @@ mod gpu_tests {
             assert!(buffers[0].is_some()); // id3
             assert!(buffers[1].is_some()); // id2
         }
+
+        // Removing the last buffers trims the buffer list
+        assert!(effect_cache.remove(id2).is_some());
+        assert_eq!(effect_cache.buffers().len(), 1);
+        assert!(effect_cache.remove(id3).is_some());
+        assert!(effect_cache.buffers().is_empty());
     }
 }
@@ -0,0 +1,161 @@
+#[cfg(feature = "fog")]
+use bevy::pbr::{FogFalloff, FogSettings};
+#[cfg(feature = "fog")]
+use bevy::render::Extract;
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{DynamicUniformBuffer, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
+    },
+};
+
+/// Fog disabled, or the view has no fog.
+pub(crate) const FOG_MODE_OFF: u32 = 0;
+/// Linear fog, from `FogFalloff::Linear`.
+#[cfg_attr(not(feature = "fog"), allow(dead_code))]
+pub(crate) const FOG_MODE_LINEAR: u32 = 1;
+/// Exponential fog, from `FogFalloff::Exponential`.
+#[cfg_attr(not(feature = "fog"), allow(dead_code))]
+pub(crate) const FOG_MODE_EXPONENTIAL: u32 = 2;
+/// Exponential squared fog, from `FogFalloff::ExponentialSquared`.
+#[cfg_attr(not(feature = "fog"), allow(dead_code))]
+pub(crate) const FOG_MODE_EXPONENTIAL_SQUARED: u32 = 3;
+/// Atmospheric fog, from `FogFalloff::Atmospheric`.
+#[cfg_attr(not(feature = "fog"), allow(dead_code))]
+pub(crate) const FOG_MODE_ATMOSPHERIC: u32 = 4;
+
+/// Fog of a view applied to the particles.
+///
+/// This is the GPU representation of the `Fog` struct of `vfx_render.wgsl`,
+/// with the same encoding as the fog of the Bevy PBR shaders.
+#[derive(Debug, Clone, Copy, PartialEq, ShaderType)]
+pub(crate) struct GpuFog {
+    /// Fog color, in linear RGB, with the alpha scaling the fog.
+    pub base_color: Vec4,
+    /// Start and end distances for [`FOG_MODE_LINEAR`], density for the
+    /// exponential modes in `x`, or extinction for [`FOG_MODE_ATMOSPHERIC`].
+    pub be: Vec3,
+    /// One of the `FOG_MODE_*` constants.
+    pub mode: u32,
+    /// Inscattering for [`FOG_MODE_ATMOSPHERIC`].
+    pub bi: Vec3,
+}
+
+impl Default for GpuFog {
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::ZERO,
+            be: Vec3::ZERO,
+            mode: FOG_MODE_OFF,
+            bi: Vec3::ZERO,
+        }
+    }
+}
+
+#[cfg(feature = "fog")]
+impl From<&FogSettings> for GpuFog {
+    fn from(settings: &FogSettings) -> Self {
+        let base_color = Vec4::from_array(settings.color.as_linear_rgba_f32());
+        let (mode, be, bi) = match settings.falloff {
+            FogFalloff::Linear { start, end } => {
+                (FOG_MODE_LINEAR, Vec3::new(start, end, 0.), Vec3::ZERO)
+            }
+            FogFalloff::Exponential { density } => {
+                (FOG_MODE_EXPONENTIAL, Vec3::new(density, 0., 0.), Vec3::ZERO)
+            }
+            FogFalloff::ExponentialSquared { density } => (
+                FOG_MODE_EXPONENTIAL_SQUARED,
+                Vec3::new(density, 0., 0.),
+                Vec3::ZERO,
+            ),
+            FogFalloff::Atmospheric {
+                extinction,
+                inscattering,
+            } => (FOG_MODE_ATMOSPHERIC, extinction, inscattering),
+        };
+        Self {
+            base_color,
+            be,
+            mode,
+            bi,
+        }
+    }
+}
+
+/// Fog of a view, extracted from its `FogSettings`.
+#[derive(Debug, Clone, Copy, Component)]
+pub(crate) struct ExtractedFog(GpuFog);
+
+/// Offset of the [`GpuFog`] of a view in the [`ViewFogUniforms`].
+#[derive(Debug, Clone, Copy, Component)]
+pub(crate) struct ViewFogOffset {
+    pub offset: u32,
+}
+
+/// Fog of all views, bound to the view bind group of the particle render
+/// pipelines.
+#[derive(Default, Resource)]
+pub(crate) struct ViewFogUniforms {
+    pub uniforms: DynamicUniformBuffer<GpuFog>,
+}
+
+/// Extract the `FogSettings` of the cameras.
+#[cfg(feature = "fog")]
+pub(crate) fn extract_fog_settings(
+    mut commands: Commands,
+    cameras: Extract<Query<(Entity, &FogSettings), With<Camera>>>,
+) {
+    for (entity, settings) in &cameras {
+        commands
+            .get_or_spawn(entity)
+            .insert(ExtractedFog(settings.into()));
+    }
+}
+
+/// Upload the fog of each view, disabled for the views without fog.
+pub(crate) fn prepare_view_fogs(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut view_fogs: ResMut<ViewFogUniforms>,
+    views: Query<(Entity, Option<&ExtractedFog>), With<ExtractedView>>,
+) {
+    view_fogs.uniforms.clear();
+    for (entity, fog) in &views {
+        let fog = fog.map_or_else(GpuFog::default, |fog| fog.0);
+        let offset = view_fogs.uniforms.push(&fog);
+        commands.entity(entity).insert(ViewFogOffset { offset });
+    }
+    view_fogs
+        .uniforms
+        .write_buffer(&render_device, &render_queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_layout() {
+        // The layout must match the Fog struct of the render shader
+        assert_eq!(GpuFog::min_size().get(), 48);
+    }
+
+    #[cfg(feature = "fog")]
+    #[test]
+    fn from_settings() {
+        let fog = GpuFog::from(&FogSettings {
+            color: Color::WHITE,
+            falloff: FogFalloff::Linear {
+                start: 5.,
+                end: 20.,
+            },
+            ..default()
+        });
+        assert_eq!(fog.mode, FOG_MODE_LINEAR);
+        assert_eq!(fog.base_color, Vec4::ONE);
+        assert_eq!(fog.be, Vec3::new(5., 20., 0.));
+    }
+}
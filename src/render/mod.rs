@@ -2,6 +2,13 @@
 use bevy::utils::FloatOrd;
 use bevy::{
     core::{Pod, Zeroable},
+    core_pipeline::{
+        deferred::{DEFERRED_LIGHTING_PASS_ID_FORMAT, DEFERRED_PREPASS_FORMAT},
+        prepass::{
+            DeferredPrepass, MotionVectorPrepass, NormalPrepass, MOTION_VECTOR_PREPASS_FORMAT,
+            NORMAL_PREPASS_FORMAT,
+        },
+    },
     ecs::{
         prelude::*,
         system::{lifetimeless::*, SystemParam, SystemState},
@@ -21,53 +28,152 @@ use bevy::{
         },
         Extract,
     },
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use bitflags::bitflags;
 use fixedbitset::FixedBitSet;
 use naga_oil::compose::{Composer, NagaModuleDescriptor};
-use rand::random;
 use std::{
     borrow::Cow,
     num::{NonZeroU32, NonZeroU64},
+    sync::Mutex,
 };
 use std::{iter, marker::PhantomData};
 
 #[cfg(feature = "2d")]
 use bevy::core_pipeline::core_2d::Transparent2d;
 #[cfg(feature = "3d")]
-use bevy::core_pipeline::core_3d::{AlphaMask3d, Transparent3d};
+use bevy::core_pipeline::{
+    core_3d::{AlphaMask3d, Transparent3d},
+    deferred::AlphaMask3dDeferred,
+};
 
 use crate::{
     asset::EffectAsset,
+    collider::GpuStaticCollider,
+    inject::{injected_particle_stride, serialize_injected_particles},
+    light2d::GpuLights2d,
     next_multiple_of,
     render::{
         batch::{BatchesInput, EffectDrawBatch},
         effect_cache::DispatchBufferIndices,
     },
-    spawn::EffectSpawner,
-    CompiledParticleEffect, EffectProperties, EffectShader, EffectSimulation, HanabiPlugin,
-    ParticleLayout, PropertyLayout, RemovedEffectsEvent, SimulationCondition, ToWgslString,
+    spawn::{EffectPlayback, EffectSpawner},
+    CollisionBvh, CompiledParticleEffect, EffectInjector, EffectParent, EffectProperties,
+    EffectShader, EffectSimulation, GlobalProperties, GpuTimingPass, HanabiPlugin, HanabiSettings,
+    Lights2d, ModifierToggles, OffscreenThrottle, ParentEvent, ParticleLayout, PropertyLayout,
+    RemovedEffectsEvent, SimulationCondition, StaticColliders, ToWgslString, TrailModifier,
+    VoxelGrid,
 };
 
 mod aligned_buffer_vec;
 mod batch;
 mod buffer_table;
+mod cpu;
+mod cull;
+mod density;
 mod effect_cache;
+mod fog;
+mod particle_query;
+mod pick;
+mod precompile;
+mod readback;
 mod shader_cache;
+// The radix sort is only compiled for its tests until a feature sorts particles
+// with it. Its pipelines and buffers are only used by the GPU tests.
+#[cfg(test)]
+#[cfg_attr(not(feature = "gpu_tests"), allow(dead_code))]
+mod sort;
+mod stereo;
+mod timing;
+mod track;
 
 use aligned_buffer_vec::AlignedBufferVec;
 use buffer_table::{BufferTable, BufferTableId};
+pub(crate) use cpu::{
+    extract_cpu_effects, prepare_cpu_bind_groups, prepare_cpu_effects, queue_cpu_effects,
+    CpuEffectsMeta, CpuParticlesPipeline, DrawCpuEffects, ExtractedCpuEffects, GpuCpuParticle,
+    HANABI_CPU_SHADER_HANDLE,
+};
+pub(crate) use cull::{prepare_particle_culling, CullMeta};
+use cull::{GpuCullDraw, GpuCullParams};
+pub(crate) use density::{extract_density_volumes, inject_particle_density, DensityInjection};
 pub(crate) use effect_cache::{EffectCache, EffectCacheId};
+#[cfg(feature = "fog")]
+pub(crate) use fog::extract_fog_settings;
+pub(crate) use fog::{prepare_view_fogs, ViewFogUniforms};
+use fog::{GpuFog, ViewFogOffset};
+pub(crate) use pick::{extract_pick_requests, pick_particles, PickReadback};
+pub(crate) use precompile::{extract_precompile_requests, precompile_effects, PrecompileQueue};
+pub(crate) use readback::{
+    extract_capture_requests, extract_stats_requests, readback_bounds, readback_group_occupancy,
+    readback_impulses, readback_particles, report_render_stats, BoundsReadback, CaptureReadback,
+    ImpulseReadback, OccupancyReadback, ReadbackChannel,
+};
+pub(crate) use stereo::{extract_stereo_eyes, prepare_billboard_views, BillboardViewUniforms};
+use stereo::{BillboardViewOffset, GpuBillboardView};
+pub(crate) use timing::{prepare_gpu_timing, readback_gpu_timings, GpuTimingQueries};
+pub(crate) use track::{extract_track_requests, track_particles, TrackReadback};
 
 pub use shader_cache::ShaderCache;
+pub(crate) use shader_cache::{CompiledEffectShader, ShaderTemplates};
 
-use self::batch::EffectBatches;
+use self::batch::{pack_update_batches, EffectBatches, UpdateBatch};
 
 // Size of an indirect index (including both parts of the ping-pong buffer) in
 // bytes.
 const INDIRECT_INDEX_SIZE: u32 = 12;
 
+// Size of the indirect workgroup counts of the init pass of a single spawner, in
+// bytes. The 3 counts are padded to 16 bytes.
+const INIT_DISPATCH_SIZE: u64 = 16;
+
+// Size of the bounds of a single effect instance in the event buffer, in bytes.
+// The 3 minimum and 3 maximum coordinates are each padded to 16 bytes.
+const BOUNDS_SIZE: u64 = 32;
+const BOUNDS_WORDS: u32 = BOUNDS_SIZE as u32 / 4;
+
+// Size of the impulse accumulated by the force receivers of a single effect
+// instance in the event buffer, in bytes. The 3 fixed-point coordinates are
+// followed by the number of absorbed particles.
+const IMPULSE_SIZE: u64 = 16;
+const IMPULSE_WORDS: u32 = IMPULSE_SIZE as u32 / 4;
+
+/// Number of storage buffers bound by the init and update passes of any effect.
+///
+/// Those are the particle, indirect, and particle group buffers (group 1), the
+/// spawner and event buffers (group 2), and the render indirect buffers (group
+/// 3). Properties, injected particles, and trails each bind an additional
+/// storage buffer, and the update pass of effects colliding with the
+/// [`StaticColliders`] binds their list (group 0).
+pub(crate) const BASE_STORAGE_BUFFER_COUNT: u32 = 7;
+
+/// Number of storage buffers bound by the cull pass in group 0, in place of
+/// the simulation parameters of the update pass.
+const CULL_STORAGE_BUFFER_COUNT: u32 = 3;
+
+/// Number of additional storage buffers bound by a batched update pass, which
+/// binds the per-instance data and the particle groups of all instances in
+/// group 3, compared to the update pass of a single instance.
+const BATCHED_UPDATE_STORAGE_BUFFER_COUNT: u32 = 2;
+
+/// Number of storage buffers bound by the largest of the init and update
+/// passes of an effect, and by its cull pass, respectively.
+pub(crate) fn effect_storage_buffer_counts(
+    has_properties: bool,
+    has_injection: bool,
+    layout_flags: LayoutFlags,
+) -> (u32, u32) {
+    let count = BASE_STORAGE_BUFFER_COUNT
+        + u32::from(has_properties)
+        + u32::from(has_injection)
+        + u32::from(layout_flags.contains(LayoutFlags::TRAILS));
+    (
+        count + u32::from(layout_flags.contains(LayoutFlags::STATIC_COLLIDERS)),
+        count + CULL_STORAGE_BUFFER_COUNT,
+    )
+}
+
 /// Simulation parameters, available to all shaders of all effects.
 #[derive(Debug, Default, Clone, Copy, Resource)]
 pub(crate) struct SimParams {
@@ -264,8 +370,57 @@ pub(crate) struct GpuSpawnerParams {
     count: i32,
     /// Index of the effect in the indirect dispatch and render buffers.
     effect_index: u32,
+    /// Pre-warming duration this frame, in seconds, or zero if the effect is
+    /// not pre-warming.
+    prewarm: f32,
+    /// Scale applied to the simulation delta time of the effect, used to
+    /// freeze a paused effect.
+    time_scale: f32,
+    /// Bitfield of the particle groups for which all particles are killed
+    /// during this frame's update pass.
+    kill_groups: u32,
+    /// Number of particles injected from the CPU this frame. Those are the
+    /// first particles spawned, and are included in [`spawn`].
+    ///
+    /// [`spawn`]: crate::render::GpuSpawnerParams::spawn
+    inject: u32,
+    /// Index of the first GPU event counter written by this effect for its
+    /// child effects, or `u32::MAX` if the effect has no child.
+    event_slot: u32,
+    /// Index of the GPU event counter of the parent effect driving the
+    /// spawning of this effect, or `u32::MAX` if the effect has no parent.
+    parent_event: u32,
+    /// Number of particles to spawn per event of the parent effect.
+    spawn_per_event: u32,
+    /// Index of the bounds of this effect in the GPU bounds buffer, or
+    /// `u32::MAX` if the effect doesn't compute its bounds.
+    bounds_slot: u32,
+    /// Conversion of the existing particles into the simulation space of the
+    /// effect this frame, one of the `SPACE_CONVERSION_*` values. This is only
+    /// set on the frame the effect switches simulation space.
+    space_conversion: u32,
+    /// Index of the impulse accumulated by the force receivers of this effect
+    /// in the GPU event buffer, in units of [`IMPULSE_SIZE`], or `u32::MAX` if
+    /// the effect doesn't have any force receiver.
+    feedback_slot: u32,
+    /// Bitfield of the modifiers disabled by the [`ModifierToggles`] of the
+    /// effect instance, indexed like [`EffectAsset::modifiers()`].
+    ///
+    /// [`ModifierToggles`]: crate::ModifierToggles
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    disabled_modifiers: u32,
+    /// Explicit padding to the 16-byte alignment of the struct, which would
+    /// otherwise be implicit and prevent deriving [`Pod`].
+    _pad: u32,
 }
 
+/// No conversion of the particles this frame.
+const SPACE_CONVERSION_NONE: u32 = 0;
+/// Convert the particles from local space into global space this frame.
+const SPACE_CONVERSION_TO_GLOBAL: u32 = 1;
+/// Convert the particles from global space into local space this frame.
+const SPACE_CONVERSION_TO_LOCAL: u32 = 2;
+
 // FIXME - min_storage_buffer_offset_alignment
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable, ShaderType)]
@@ -305,6 +460,11 @@ pub struct GpuRenderGroupIndirect {
     pub alive_count: u32,
     pub max_update: u32,
     pub dead_count: u32,
+    /// Total number of particles spawned into the group, wrapping on overflow.
+    pub spawned_count: u32,
+    /// Total number of particles not spawned into the group because it was full,
+    /// wrapping on overflow.
+    pub dropped_count: u32,
 }
 
 /// Stores metadata about each particle group.
@@ -332,6 +492,24 @@ pub struct GpuParticleGroup {
     pub effect_particle_offset: u32,
 }
 
+/// Per-instance data of a batched update pass.
+///
+/// Instances sharing the same effect buffer and update shaders are updated by
+/// a single dispatch per group, each workgroup row of the dispatch updating a
+/// different instance. This is written by the CPU and read by the GPU.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuUpdateInstance {
+    /// Index of the spawner of the instance in the spawner buffer.
+    pub spawner_index: u32,
+    /// Index of the `RenderEffectMetadata` of the instance.
+    pub render_effect_index: u32,
+    /// Index of the `RenderGroupIndirect` of the first group of the instance.
+    pub render_group_base: u32,
+    /// Index of the [`GpuParticleGroup`] of the first group of the instance.
+    pub particle_group_base: u32,
+}
+
 /// Compute pipeline to run the `vfx_indirect` dispatch workgroup calculation
 /// shader.
 #[derive(Resource)]
@@ -469,7 +647,12 @@ impl FromWorld for DispatchIndirectPipeline {
                 assert!(res.is_ok());
             }
 
-            let shader_defs = default();
+            let workgroup_size = world.resource::<SimulationWorkgroupSize>().0;
+            let shader_defs = [(
+                "WORKGROUP_SIZE".to_string(),
+                naga_oil::compose::ShaderDefValue::UInt(workgroup_size),
+            )]
+            .into();
 
             match composer.make_naga_module(NagaModuleDescriptor {
                 source: &indirect_code,
@@ -506,6 +689,135 @@ impl FromWorld for DispatchIndirectPipeline {
     }
 }
 
+/// Compute pipeline to run the `vfx_init_dispatch` shader, which calculates the
+/// indirect workgroup counts of the init pass of all spawners.
+///
+/// This is used for effects spawning particles from the events of a parent
+/// effect, whose number of particles to spawn is only known on GPU.
+#[derive(Resource)]
+pub(crate) struct InitDispatchPipeline {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl FromWorld for InitDispatchPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let storage_alignment = render_device.limits().min_storage_buffer_offset_alignment;
+        let spawner_size = GpuSpawnerParams::aligned_size(storage_alignment);
+
+        let layout = render_device.create_bind_group_layout(
+            "hanabi:bind_group_layout:init_dispatch",
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(spawner_size),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(4),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(INIT_DISPATCH_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("hanabi:pipeline_layout:init_dispatch"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let workgroup_size = world.resource::<SimulationWorkgroupSize>().0;
+        let init_dispatch_code = include_str!("vfx_init_dispatch.wgsl")
+            .replace(
+                "{{SPAWNER_STRIDE}}",
+                &(spawner_size.get() as u32).to_wgsl_string(),
+            )
+            .replace("{{WORKGROUP_SIZE}}", &workgroup_size.to_wgsl_string());
+
+        debug!("Create init dispatch shader:\n{}", init_dispatch_code);
+
+        let shader_module = render_device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("hanabi:vfx_init_dispatch_shader"),
+            source: ShaderSource::Wgsl(Cow::Owned(init_dispatch_code)),
+        });
+
+        let pipeline = render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+            label: Some("hanabi:compute_pipeline:init_dispatch"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "main",
+        });
+
+        Self { layout, pipeline }
+    }
+}
+
+/// Number of threads per workgroup of the init, update, and cull passes.
+///
+/// This is configured with [`HanabiSettings::workgroup_size`], and validated
+/// against the limits of the render device when the plugin finishes building.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub(crate) struct SimulationWorkgroupSize(pub u32);
+
+impl Default for SimulationWorkgroupSize {
+    fn default() -> Self {
+        Self(HanabiSettings::DEFAULT_WORKGROUP_SIZE)
+    }
+}
+
+impl SimulationWorkgroupSize {
+    /// Validate a requested workgroup size against the limits of the render
+    /// device, falling back to the default size if not supported.
+    pub fn new(requested: u32, limits: &WgpuLimits) -> Self {
+        let max_size = limits
+            .max_compute_workgroup_size_x
+            .min(limits.max_compute_invocations_per_workgroup);
+        if requested == 0 || requested > max_size {
+            warn!(
+                "Invalid simulation workgroup size {}; the GPU device supports between 1 and {} threads per workgroup. Using the default size of {} instead.",
+                requested, max_size, HanabiSettings::DEFAULT_WORKGROUP_SIZE
+            );
+            Self::default()
+        } else {
+            Self(requested)
+        }
+    }
+
+    /// Get the shader definition substituting `#{WORKGROUP_SIZE}` in the
+    /// simulation shaders.
+    pub fn shader_def(&self) -> ShaderDefVal {
+        ShaderDefVal::UInt("WORKGROUP_SIZE".into(), self.0)
+    }
+
+    /// Get the number of workgroups to dispatch to run one thread per item.
+    pub fn workgroup_count(&self, item_count: u32) -> u32 {
+        item_count.div_ceil(self.0)
+    }
+}
+
 #[derive(Resource)]
 pub(crate) struct ParticlesInitPipeline {
     /// Render device the pipeline is attached to.
@@ -513,6 +825,7 @@ pub(crate) struct ParticlesInitPipeline {
     sim_params_layout: BindGroupLayout,
     spawner_buffer_layout: BindGroupLayout,
     render_indirect_layout: BindGroupLayout,
+    workgroup_size: SimulationWorkgroupSize,
 }
 
 impl FromWorld for ParticlesInitPipeline {
@@ -548,16 +861,29 @@ impl FromWorld for ParticlesInitPipeline {
         );
         let spawner_buffer_layout = render_device.create_bind_group_layout(
             "hanabi:buffer_layout:init_spawner",
-            &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: true,
-                    min_binding_size: Some(GpuSpawnerParams::min_size()),
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuSpawnerParams::min_size()),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                // @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(ParentEvent::COUNT as u64 * 4),
+                    },
+                    count: None,
+                },
+            ],
         );
 
         let storage_alignment = render_device.limits().min_storage_buffer_offset_alignment;
@@ -599,11 +925,14 @@ impl FromWorld for ParticlesInitPipeline {
             ],
         );
 
+        let workgroup_size = *world.resource::<SimulationWorkgroupSize>();
+
         Self {
             render_device: render_device.clone(),
             sim_params_layout,
             spawner_buffer_layout,
             render_indirect_layout,
+            workgroup_size,
         }
     }
 }
@@ -617,6 +946,11 @@ pub(crate) struct ParticleInitPipelineKey {
     /// Minimum binding size in bytes for the property layout buffer, if the
     /// effect has any property. Otherwise this is `None`.
     property_layout_min_binding_size: Option<NonZeroU64>,
+    /// Minimum binding size in bytes for the injection buffer, if the effect
+    /// has an injection capacity. Otherwise this is `None`.
+    injection_min_binding_size: Option<NonZeroU64>,
+    /// Whether the effect records a trail history for its particles.
+    trails: bool,
 }
 
 impl SpecializedComputePipeline for ParticlesInitPipeline {
@@ -683,6 +1017,32 @@ impl SpecializedComputePipeline for ParticlesInitPipeline {
                 count: None,
             });
         }
+        if let Some(min_binding_size) = key.injection_min_binding_size {
+            // (1,4) array<InjectedParticle>
+            entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(min_binding_size),
+                },
+                count: None,
+            });
+        }
+        if key.trails {
+            // (1,5) array<vec4<f32>>
+            entries.push(BindGroupLayoutEntry {
+                binding: 5,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(16),
+                },
+                count: None,
+            });
+        }
 
         let label = "hanabi:init_particles_buffer_layout";
         trace!(
@@ -701,7 +1061,7 @@ impl SpecializedComputePipeline for ParticlesInitPipeline {
                 self.render_indirect_layout.clone(),
             ],
             shader: key.shader,
-            shader_defs: vec![],
+            shader_defs: vec![self.workgroup_size.shader_def()],
             entry_point: "main".into(),
             push_constant_ranges: Vec::new(),
         }
@@ -712,8 +1072,34 @@ impl SpecializedComputePipeline for ParticlesInitPipeline {
 pub(crate) struct ParticlesUpdatePipeline {
     render_device: RenderDevice,
     sim_params_layout: BindGroupLayout,
+    /// Layout of the simulation parameters (group 0) of the effects colliding
+    /// with the [`StaticColliders`], which also binds their storage buffer.
+    ///
+    /// [`StaticColliders`]: crate::StaticColliders
+    static_colliders_sim_params_layout: BindGroupLayout,
+    /// Empty 1x1x1 occupancy grid bound when no [`VoxelGrid`] is available.
+    ///
+    /// [`VoxelGrid`]: crate::VoxelGrid
+    dummy_voxel_grid: TextureView,
+    /// Empty collision BVH bound when no [`CollisionBvh`] is available.
+    ///
+    /// [`CollisionBvh`]: crate::CollisionBvh
+    dummy_collision_bvh: TextureView,
+    /// Single unused collider bound when the [`StaticColliders`] list is
+    /// empty.
+    ///
+    /// [`StaticColliders`]: crate::StaticColliders
+    dummy_static_colliders: Buffer,
     spawner_buffer_layout: BindGroupLayout,
     render_indirect_layout: BindGroupLayout,
+    /// Layout of the spawners (group 2) of a batched update pass, which binds
+    /// the entire spawner buffer instead of the spawner of a single instance.
+    batched_spawner_layout: BindGroupLayout,
+    /// Layout of the render indirect buffers (group 3) of a batched update
+    /// pass, which also binds the per-instance data and the particle groups.
+    batched_render_indirect_layout: BindGroupLayout,
+    cull_layout: BindGroupLayout,
+    workgroup_size: SimulationWorkgroupSize,
 }
 
 impl FromWorld for ParticlesUpdatePipeline {
@@ -729,9 +1115,8 @@ impl FromWorld for ParticlesUpdatePipeline {
         );
 
         trace!("GpuSimParams: min_size={}", GpuSimParams::min_size());
-        let sim_params_layout = render_device.create_bind_group_layout(
-            "hanabi:update_sim_params_layout",
-            &[BindGroupLayoutEntry {
+        let mut sim_params_entries = vec![
+            BindGroupLayoutEntry {
                 binding: 0,
                 visibility: ShaderStages::COMPUTE,
                 ty: BindingType::Buffer {
@@ -740,25 +1125,123 @@ impl FromWorld for ParticlesUpdatePipeline {
                     min_binding_size: Some(GpuSimParams::min_size()),
                 },
                 count: None,
-            }],
+            },
+            // Occupancy grid for the CollideVoxelModifier
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            // Triangle BVH for the CollideMeshModifier
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+        let sim_params_layout = render_device
+            .create_bind_group_layout("hanabi:update_sim_params_layout", &sim_params_entries);
+
+        // Only the effects with a CollideStaticModifier bind the static colliders, as
+        // the additional storage buffer would otherwise exceed the WebGPU limit for the
+        // effects using properties, injected particles, or trails.
+        sim_params_entries.push(BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size_of::<GpuStaticCollider>() as u64),
+            },
+            count: None,
+        });
+        let static_colliders_sim_params_layout = render_device.create_bind_group_layout(
+            "hanabi:update_static_colliders_sim_params_layout",
+            &sim_params_entries,
         );
 
+        // Textures are zero-initialized, so this grid is empty
+        let dummy_voxel_grid = render_device
+            .create_texture(&TextureDescriptor {
+                label: Some("hanabi:dummy_voxel_grid"),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: TextureFormat::R8Uint,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+
+        // Likewise, this BVH has no node
+        let dummy_collision_bvh = render_device
+            .create_texture(&TextureDescriptor {
+                label: Some("hanabi:dummy_collision_bvh"),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba32Float,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+
+        // A zero kind marks an unused collider entry, which the shader skips
+        let dummy_static_colliders = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("hanabi:buffer:dummy_static_colliders"),
+            contents: bytemuck::bytes_of(&GpuStaticCollider::default()),
+            usage: BufferUsages::STORAGE,
+        });
+
         trace!(
             "GpuSpawnerParams: min_size={}",
             GpuSpawnerParams::min_size()
         );
         let spawner_buffer_layout = render_device.create_bind_group_layout(
             "hanabi:update_spawner_buffer_layout",
-            &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: true,
-                    min_binding_size: Some(GpuSpawnerParams::min_size()),
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuSpawnerParams::min_size()),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                // @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(ParentEvent::COUNT as u64 * 4),
+                    },
+                    count: None,
+                },
+            ],
         );
 
         let storage_alignment = render_device.limits().min_storage_buffer_offset_alignment;
@@ -796,35 +1279,192 @@ impl FromWorld for ParticlesUpdatePipeline {
             ],
         );
 
-        Self {
-            render_device: render_device.clone(),
-            sim_params_layout,
-            spawner_buffer_layout,
-            render_indirect_layout,
-        }
-    }
-}
-
-#[derive(Default, Clone, Hash, PartialEq, Eq)]
-pub(crate) struct ParticleUpdatePipelineKey {
-    /// Compute shader, with snippets applied, but not preprocessed yet.
-    shader: Handle<Shader>,
-    /// Particle layout.
-    particle_layout: ParticleLayout,
-    /// Property layout.
-    property_layout: PropertyLayout,
-}
-
-impl SpecializedComputePipeline for ParticlesUpdatePipeline {
-    type Key = ParticleUpdatePipelineKey;
+        let spawner_size = GpuSpawnerParams::aligned_size(storage_alignment);
+        let batched_spawner_layout = render_device.create_bind_group_layout(
+            "hanabi:update_batched_spawner_layout",
+            &[
+                // @binding(0) var<storage, read> spawners : array<Spawner>
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(spawner_size),
+                    },
+                    count: None,
+                },
+                // @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(ParentEvent::COUNT as u64 * 4),
+                    },
+                    count: None,
+                },
+            ],
+        );
 
-    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
-        trace!(
-            "GpuParticle: attributes.min_binding_size={} properties.min_binding_size={}",
-            key.particle_layout.min_binding_size().get(),
-            if key.property_layout.is_empty() {
-                0
-            } else {
+        let particle_group_size = GpuParticleGroup::aligned_size(storage_alignment);
+        let batched_render_indirect_layout = render_device.create_bind_group_layout(
+            "hanabi:update_batched_render_indirect_layout",
+            &[
+                // @binding(0) var<storage, read_write> render_effect_indirects :
+                // array<RenderEffectMetadata>
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(render_effect_indirect_size),
+                    },
+                    count: None,
+                },
+                // @binding(1) var<storage, read_write> render_group_indirect :
+                // array<RenderGroupIndirect>
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(render_group_indirect_size),
+                    },
+                    count: None,
+                },
+                // @binding(2) var<storage, read> update_instances : array<UpdateInstance>
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuUpdateInstance::min_size()),
+                    },
+                    count: None,
+                },
+                // @binding(3) var<storage, read> particle_groups : array<ParticleGroup>
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(particle_group_size),
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        // Layout of the bind group replacing the simulation parameters for the cull
+        // entry point of the update shader.
+        let cull_layout = render_device.create_bind_group_layout(
+            "hanabi:cull_layout",
+            &[
+                // @binding(1) var<storage, read> cull_params : CullParams
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuCullParams::min_size()),
+                    },
+                    count: None,
+                },
+                // @binding(2) var<storage, read_write> cull_index_buffer : array<u32>
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(4),
+                    },
+                    count: None,
+                },
+                // @binding(3) var<storage, read_write> cull_draw_buffer : array<atomic<u32>>
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(GpuCullDraw::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let workgroup_size = *world.resource::<SimulationWorkgroupSize>();
+
+        Self {
+            render_device: render_device.clone(),
+            sim_params_layout,
+            static_colliders_sim_params_layout,
+            dummy_voxel_grid,
+            dummy_collision_bvh,
+            dummy_static_colliders,
+            spawner_buffer_layout,
+            render_indirect_layout,
+            batched_spawner_layout,
+            batched_render_indirect_layout,
+            cull_layout,
+            workgroup_size,
+        }
+    }
+}
+
+#[derive(Default, Clone, Hash, PartialEq, Eq)]
+pub(crate) struct ParticleUpdatePipelineKey {
+    /// Compute shader, with snippets applied, but not preprocessed yet.
+    shader: Handle<Shader>,
+    /// Particle layout.
+    particle_layout: ParticleLayout,
+    /// Property layout.
+    property_layout: PropertyLayout,
+    /// Minimum binding size in bytes for the injection buffer, if the effect
+    /// has an injection capacity. Otherwise this is `None`.
+    ///
+    /// The update pass doesn't use the injection buffer, but shares its bind
+    /// group with the init pass, so needs a compatible layout.
+    injection_min_binding_size: Option<NonZeroU64>,
+    /// Whether the effect records a trail history for its particles.
+    trails: bool,
+    /// Whether the effect collides its particles with the [`StaticColliders`],
+    /// binding their storage buffer in the first bind group.
+    ///
+    /// [`StaticColliders`]: crate::StaticColliders
+    static_colliders: bool,
+    /// Specialize the pipeline of the `cull` entry point instead of the `main`
+    /// one. The cull pipeline only differs by its first bind group.
+    cull: bool,
+    /// Specialize the pipeline of a batched update pass, updating several
+    /// instances sharing the same effect buffer in a single dispatch. The
+    /// batched pipeline only differs by its last two bind groups.
+    batched: bool,
+    /// Index of the particle group updated, passed to the shader as the
+    /// `GROUP_INDEX` shader definition. Groups with identical update code share
+    /// the same shader, and only differ by this key.
+    group_index: u32,
+}
+
+impl SpecializedComputePipeline for ParticlesUpdatePipeline {
+    type Key = ParticleUpdatePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
+        trace!(
+            "GpuParticle: attributes.min_binding_size={} properties.min_binding_size={}",
+            key.particle_layout.min_binding_size().get(),
+            if key.property_layout.is_empty() {
+                0
+            } else {
                 key.property_layout.min_binding_size().get()
             },
         );
@@ -882,6 +1522,32 @@ impl SpecializedComputePipeline for ParticlesUpdatePipeline {
                 count: None,
             });
         }
+        if let Some(min_binding_size) = key.injection_min_binding_size {
+            // @binding(4) var<storage, read> injected_particles : array<InjectedParticle>
+            entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(min_binding_size),
+                },
+                count: None,
+            });
+        }
+        if key.trails {
+            // @binding(5) var<storage, read_write> trail_buffer : array<vec4<f32>>
+            entries.push(BindGroupLayoutEntry {
+                binding: 5,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(16),
+                },
+                count: None,
+            });
+        }
 
         let label = "hanabi:update_particles_buffer_layout";
         trace!(
@@ -892,17 +1558,55 @@ impl SpecializedComputePipeline for ParticlesUpdatePipeline {
         let update_particles_buffer_layout =
             self.render_device.create_bind_group_layout(label, &entries);
 
+        let mut shader_defs = vec![
+            "REM_MAX_SPAWN_ATOMIC".into(),
+            ShaderDefVal::UInt("GROUP_INDEX".into(), key.group_index),
+            self.workgroup_size.shader_def(),
+        ];
+        // The cull pipeline doesn't bind the static colliders, but shares the shader
+        // of the update pipeline, which references them.
+        if key.static_colliders {
+            shader_defs.push("STATIC_COLLIDERS".into());
+        }
+        let (spawner_layout, render_indirect_layout) = if key.batched {
+            shader_defs.push("BATCHED_UPDATE".into());
+            // The spawners are read as an array of padded structs
+            shader_defs.push("SPAWNER_READ_ONLY".into());
+            shader_defs.push("SPAWNER_PADDING".into());
+            (
+                &self.batched_spawner_layout,
+                &self.batched_render_indirect_layout,
+            )
+        } else {
+            (&self.spawner_buffer_layout, &self.render_indirect_layout)
+        };
+        let (label, first_layout, entry_point) = if key.cull {
+            ("hanabi:pipeline_cull_compute", &self.cull_layout, "cull")
+        } else if key.static_colliders {
+            (
+                "hanabi:pipeline_update_compute",
+                &self.static_colliders_sim_params_layout,
+                "main",
+            )
+        } else {
+            (
+                "hanabi:pipeline_update_compute",
+                &self.sim_params_layout,
+                "main",
+            )
+        };
+
         ComputePipelineDescriptor {
-            label: Some("hanabi:pipeline_update_compute".into()),
+            label: Some(label.into()),
             layout: vec![
-                self.sim_params_layout.clone(),
+                first_layout.clone(),
                 update_particles_buffer_layout,
-                self.spawner_buffer_layout.clone(),
-                self.render_indirect_layout.clone(),
+                spawner_layout.clone(),
+                render_indirect_layout.clone(),
             ],
             shader: key.shader,
-            shader_defs: vec!["REM_MAX_SPAWN_ATOMIC".into()],
-            entry_point: "main".into(),
+            shader_defs,
+            entry_point: entry_point.into(),
             push_constant_ranges: Vec::new(),
         }
     }
@@ -913,6 +1617,11 @@ pub(crate) struct ParticlesRenderPipeline {
     render_device: RenderDevice,
     view_layout: BindGroupLayout,
     material_layout: BindGroupLayout,
+    /// Layout of the color over lifetime LUT texture (group 3).
+    color_lut_layout: BindGroupLayout,
+    /// Empty layout standing in for the material layout (group 2) of the
+    /// effects with a color LUT but without particle texture.
+    empty_layout: BindGroupLayout,
 }
 
 impl FromWorld for ParticlesRenderPipeline {
@@ -943,6 +1652,50 @@ impl FromWorld for ParticlesRenderPipeline {
                     },
                     count: None,
                 },
+                // @binding(2) var<storage, read> cull_index_buffer : array<u32>
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(4),
+                    },
+                    count: None,
+                },
+                // @binding(3) var<uniform> billboard_view : BillboardView
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuBillboardView::min_size()),
+                    },
+                    count: None,
+                },
+                // @binding(4) var<uniform> fog : Fog
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuFog::min_size()),
+                    },
+                    count: None,
+                },
+                // @binding(5) var<uniform> lights_2d : Lights2d
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(GpuLights2d::min_size()),
+                    },
+                    count: None,
+                },
             ],
         );
 
@@ -951,7 +1704,7 @@ impl FromWorld for ParticlesRenderPipeline {
             &[
                 BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
                     ty: BindingType::Texture {
                         multisampled: false,
                         sample_type: TextureSampleType::Float { filterable: true },
@@ -961,17 +1714,46 @@ impl FromWorld for ParticlesRenderPipeline {
                 },
                 BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
             ],
         );
 
+        let color_lut_layout = render_device.create_bind_group_layout(
+            "hanabi:color_lut_layout_render",
+            &[
+                // @binding(0) var color_lut_texture: texture_2d<f32>
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                // @binding(1) var color_lut_sampler: sampler
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+
+        let empty_layout =
+            render_device.create_bind_group_layout("hanabi:empty_layout_render", &[]);
+
         Self {
             render_device: render_device.clone(),
             view_layout,
             material_layout,
+            color_lut_layout,
+            empty_layout,
         }
     }
 }
@@ -994,6 +1776,10 @@ pub(crate) struct ParticleRenderPipelineKey {
     /// This key requires the presence of UV coordinates on the particle
     /// vertices.
     has_image: bool,
+    /// Key: COLOR_LUT
+    /// Define a LUT texture sampled to get the particle color over its
+    /// lifetime.
+    has_color_lut: bool,
     /// Key: LOCAL_SPACE_SIMULATION
     /// The effect is simulated in local space, and during rendering all
     /// particles are transformed by the effect's [`GlobalTransform`].
@@ -1008,6 +1794,41 @@ pub(crate) struct ParticleRenderPipelineKey {
     /// Key: NEEDS_UV
     /// The effect needs UVs.
     needs_uv: bool,
+    /// Key: TRAILS
+    /// The effect records a trail history for its particles, which the render
+    /// shader reads.
+    trails: bool,
+    /// Key: CULL_PARTICLES
+    /// The particles are culled per view, and the render shader reads the
+    /// indices of the visible particles from the cull index buffer.
+    cull_particles: bool,
+    /// Key: OVERDRAW
+    /// Each fragment adds a constant color with additive blending, instead of
+    /// drawing the particle color, to visualize the overdraw.
+    overdraw: bool,
+    /// Key: FOG
+    /// The fog of the view is applied to the particle fragments.
+    fog: bool,
+    /// Key: BLOB_SHADOW
+    /// Each particle draws an extra quad for its blob shadow.
+    blob_shadow: bool,
+    /// Key: DEFERRED_PREPASS
+    /// The particles are written into the G-buffer during the deferred prepass,
+    /// instead of being drawn into the view target.
+    deferred_prepass: bool,
+    /// Key: NORMAL_PREPASS
+    /// The deferred prepass also writes the normal prepass texture.
+    normal_prepass: bool,
+    /// Key: MOTION_VECTOR_PREPASS
+    /// The deferred prepass also writes the motion vector prepass texture.
+    motion_vector_prepass: bool,
+    /// Key: LIGHTING_2D
+    /// The particles are lit by the [`Lights2d`].
+    lighting_2d: bool,
+    /// Key: Z_LAYER_2D
+    /// Index of the 2D layer drawn, if the particles of the group are split
+    /// into several layers. The particles of the other layers are discarded.
+    z_layer_2d: Option<u32>,
     /// For dual-mode configurations only, the actual mode of the current render
     /// pipeline. Otherwise the mode is implicitly determined by the active
     /// feature.
@@ -1025,10 +1846,21 @@ impl Default for ParticleRenderPipelineKey {
             shader: Handle::default(),
             particle_layout: ParticleLayout::empty(),
             has_image: false,
+            has_color_lut: false,
             local_space_simulation: false,
             use_alpha_mask: false,
             flipbook: false,
             needs_uv: false,
+            trails: false,
+            cull_particles: false,
+            overdraw: false,
+            fog: false,
+            blob_shadow: false,
+            deferred_prepass: false,
+            normal_prepass: false,
+            motion_vector_prepass: false,
+            lighting_2d: false,
+            z_layer_2d: None,
             #[cfg(all(feature = "2d", feature = "3d"))]
             pipeline_mode: PipelineMode::Camera3d,
             msaa_samples: Msaa::default().samples(),
@@ -1117,9 +1949,9 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
                 },
                 count: None,
             },
-        ];
-        if key.local_space_simulation {
-            entries.push(BindGroupLayoutEntry {
+            // Always bound, to match the layout of the effect buffer, even if only used
+            // by effects simulated in local space.
+            BindGroupLayoutEntry {
                 binding: 3,
                 visibility: ShaderStages::VERTEX,
                 ty: BindingType::Buffer {
@@ -1128,6 +1960,18 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
                     min_binding_size: Some(GpuSpawnerParams::min_size()),
                 },
                 count: None,
+            },
+        ];
+        if key.trails {
+            entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(16),
+                },
+                count: None,
             });
         }
 
@@ -1159,6 +2003,15 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             // vertex_buffer_layout.array_stride += 8;
         }
 
+        // Key: COLOR_LUT
+        if key.has_color_lut {
+            if !key.has_image {
+                layout.push(self.empty_layout.clone());
+            }
+            layout.push(self.color_lut_layout.clone());
+            shader_defs.push("COLOR_LUT".into());
+        }
+
         // Key: LOCAL_SPACE_SIMULATION
         if key.local_space_simulation {
             shader_defs.push("LOCAL_SPACE_SIMULATION".into());
@@ -1179,6 +2032,47 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             shader_defs.push("NEEDS_UV".into());
         }
 
+        // Key: CULL_PARTICLES
+        if key.cull_particles {
+            shader_defs.push("CULL_PARTICLES".into());
+        }
+
+        // Key: OVERDRAW
+        if key.overdraw {
+            shader_defs.push("OVERDRAW".into());
+        }
+
+        // Key: FOG
+        if key.fog {
+            shader_defs.push("FOG".into());
+        }
+
+        // Key: BLOB_SHADOW
+        if key.blob_shadow {
+            shader_defs.push("BLOB_SHADOW".into());
+        }
+
+        // Key: DEFERRED_PREPASS
+        if key.deferred_prepass {
+            shader_defs.push("DEFERRED_PREPASS".into());
+            if key.normal_prepass {
+                shader_defs.push("NORMAL_PREPASS".into());
+            }
+            if key.motion_vector_prepass {
+                shader_defs.push("MOTION_VECTOR_PREPASS".into());
+            }
+        }
+
+        // Key: LIGHTING_2D
+        if key.lighting_2d {
+            shader_defs.push("LIGHTING_2D".into());
+        }
+
+        // Key: Z_LAYER_2D
+        if let Some(z_layer) = key.z_layer_2d {
+            shader_defs.push(ShaderDefVal::UInt("Z_LAYER_2D".into(), z_layer));
+        }
+
         #[cfg(all(feature = "2d", feature = "3d"))]
         let depth_stencil = match key.pipeline_mode {
             // Bevy's Transparent2d render phase doesn't support a depth-stencil buffer.
@@ -1214,6 +2108,45 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             TextureFormat::bevy_default()
         };
 
+        // The overdraw view accumulates a constant color per fragment
+        let blend = if key.overdraw {
+            let additive = BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            };
+            BlendState {
+                color: additive,
+                alpha: additive,
+            }
+        } else {
+            BlendState::ALPHA_BLENDING
+        };
+
+        let targets = if key.deferred_prepass {
+            // Same attachments as the deferred prepass of Bevy, where the prepass
+            // textures not used by the view are left unbound
+            let target = |enabled: bool, format: TextureFormat| {
+                enabled.then_some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })
+            };
+            vec![
+                target(key.normal_prepass, NORMAL_PREPASS_FORMAT),
+                target(key.motion_vector_prepass, MOTION_VECTOR_PREPASS_FORMAT),
+                target(true, DEFERRED_PREPASS_FORMAT),
+                target(true, DEFERRED_LIGHTING_PASS_ID_FORMAT),
+            ]
+        } else {
+            vec![Some(ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: ColorWrites::ALL,
+            })]
+        };
+
         RenderPipelineDescriptor {
             vertex: VertexState {
                 shader: key.shader.clone(),
@@ -1225,11 +2158,7 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
                 shader: key.shader,
                 shader_defs,
                 entry_point: "fragment".into(),
-                targets: vec![Some(ColorTargetState {
-                    format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::ALL,
-                })],
+                targets,
             }),
             layout,
             primitive: PrimitiveState {
@@ -1283,16 +2212,51 @@ pub(crate) struct ExtractedEffect {
     ///
     /// [`EffectSpawner::tick()`]: crate::EffectSpawner::tick
     pub spawn_count: u32,
+    /// Pre-warming duration this frame, in seconds.
+    ///
+    /// Obtained from [`EffectSpawner::prewarm_time()`] on the source effect
+    /// instance.
+    ///
+    /// [`EffectSpawner::prewarm_time()`]: crate::EffectSpawner::prewarm_time
+    pub prewarm_time: f32,
+    /// Seed of the GPU random number generator this frame.
+    pub seed: u32,
+    /// Additional emitters of the effect, in the order of
+    /// [`EffectAsset::emitters()`].
+    ///
+    /// [`EffectAsset::emitters()`]: crate::EffectAsset::emitters
+    pub emitters: Vec<ExtractedEmitter>,
+    /// Scale applied to the simulation delta time of the effect.
+    pub time_scale: f32,
+    /// Bitfield of the modifiers disabled on the effect instance.
+    pub disabled_modifiers: u32,
+    /// Serialized particles injected from the CPU this frame, if any.
+    pub injection_data: Option<Vec<u8>>,
+    /// Number of particles in [`injection_data`].
+    ///
+    /// [`injection_data`]: ExtractedEffect::injection_data
+    pub inject_count: u32,
+    /// Parent effect driving the spawning of this effect, if any.
+    pub parent: Option<EffectParent>,
     /// Global transform of the effect origin, extracted from the
     /// [`GlobalTransform`].
     pub transform: Mat4,
     /// Inverse global transform of the effect origin, extracted from the
     /// [`GlobalTransform`].
     pub inverse_transform: Mat4,
-    /// Layout flags.
+    /// Layout flags, as the union of the layout flags of all groups.
     pub layout_flags: LayoutFlags,
-    /// Texture to modulate the particle color.
-    pub image_handle: Handle<Image>,
+    /// Layout flags of each group.
+    pub group_layout_flags: Vec<LayoutFlags>,
+    /// Texture to modulate the particle color, for each group. Groups without
+    /// texture have a default handle.
+    pub image_handles: Vec<Handle<Image>>,
+    /// LUT texture defining the particle color over its lifetime, for each
+    /// group. Groups without LUT have a default handle.
+    pub color_lut_handles: Vec<Handle<Image>>,
+    /// Z coordinates of the 2D layers the particles are split into, for each
+    /// group. Groups drawn at the layer of the effect instance have none.
+    pub z_layers_2d: Vec<Vec<f32>>,
     /// Effect shader.
     pub effect_shader: EffectShader,
     /// For 2D rendering, the Z coordinate used as the sort key. Ignored for 3D
@@ -1301,6 +2265,23 @@ pub(crate) struct ExtractedEffect {
     pub z_sort_key_2d: FloatOrd,
 }
 
+/// Extracted spawning state of an additional emitter of an effect.
+///
+/// See [`EffectAsset::with_emitter()`].
+///
+/// [`EffectAsset::with_emitter()`]: crate::EffectAsset::with_emitter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ExtractedEmitter {
+    /// Index of the group the emitter spawns particles into.
+    pub group_index: u32,
+    /// Number of particles to spawn this frame.
+    pub spawn_count: u32,
+    /// Pre-warming duration this frame, in seconds.
+    pub prewarm_time: f32,
+    /// Seed of the GPU random number generator this frame.
+    pub seed: u32,
+}
+
 /// Extracted data for newly-added [`ParticleEffect`] component requiring a new
 /// GPU allocation.
 ///
@@ -1319,6 +2300,13 @@ pub struct AddedEffect {
     /// an empty layout.
     pub property_layout: PropertyLayout,
     pub layout_flags: LayoutFlags,
+    /// Capacity of the buffer of particles injected from the CPU, in number
+    /// of particles.
+    pub injection_capacity: u32,
+    /// Length of the trail of each group, or zero for groups without trail.
+    pub trail_lengths: Vec<u32>,
+    /// Whether each group draws a blob shadow under its particles.
+    pub blob_shadows: Vec<bool>,
     /// Handle of the effect asset.
     pub handle: Handle<EffectAsset>,
 }
@@ -1338,6 +2326,10 @@ pub(crate) struct ExtractedEffects {
     pub removed_effect_entities: Vec<Entity>,
     /// Newly added effects without a GPU allocation yet.
     pub added_effects: Vec<AddedEffect>,
+    /// Bitfield of the particle groups requested to be killed this frame, for
+    /// each effect. This includes the effects not simulated this frame, whose
+    /// kill requests are kept pending until they're simulated again.
+    pub kill_requests: Vec<(Entity, u32)>,
 }
 
 #[derive(Default, Resource)]
@@ -1376,6 +2368,7 @@ pub(crate) fn extract_effects(
     time: Extract<Res<Time<EffectSimulation>>>,
     effects: Extract<Res<Assets<EffectAsset>>>,
     _images: Extract<Res<Assets<Image>>>,
+    globals: Extract<Res<GlobalProperties>>,
     mut query: Extract<
         ParamSet<(
             // All existing ParticleEffect components
@@ -1386,12 +2379,17 @@ pub(crate) fn extract_effects(
                 &EffectSpawner,
                 &CompiledParticleEffect,
                 Option<Ref<EffectProperties>>,
+                Option<&EffectInjector>,
+                Option<&EffectParent>,
+                Option<&OffscreenThrottle>,
+                Option<&ModifierToggles>,
                 &GlobalTransform,
             )>,
-            // Newly added ParticleEffect components
+            // Newly added ParticleEffect components, and existing ones whose asset was
+            // modified
             Query<
-                (Entity, &CompiledParticleEffect),
-                (Added<CompiledParticleEffect>, With<GlobalTransform>),
+                (Entity, Ref<CompiledParticleEffect>),
+                (Changed<CompiledParticleEffect>, With<GlobalTransform>),
             >,
         )>,
     >,
@@ -1424,10 +2422,24 @@ pub(crate) fn extract_effects(
         extracted_effects.removed_effect_entities.len()
     );
 
-    // Collect added effects for later GPU data allocation
-    extracted_effects.added_effects = query
+    // Collect added effects for later GPU data allocation. Effects whose asset
+    // was modified are reallocated, as their capacities or layouts may have
+    // changed, so are both removed and added again.
+    let mut modified_effect_entities = vec![];
+    let added_effects = query
         .p1()
         .iter()
+        .filter(|(entity, effect)| {
+            if effect.is_added() {
+                return true;
+            }
+            if effect.asset_modified && effects.contains(&effect.asset) {
+                trace!("Reallocating effect on entity {:?} with modified asset.", entity);
+                modified_effect_entities.push(*entity);
+                return true;
+            }
+            false
+        })
         .map(|(entity, effect)| {
             let handle = effect.asset.clone_weak();
             let asset = effects.get(&effect.asset).unwrap();
@@ -1447,13 +2459,25 @@ pub(crate) fn extract_effects(
                 particle_layout,
                 property_layout,
                 layout_flags: effect.layout_flags,
+                injection_capacity: asset.injection_capacity,
+                trail_lengths: asset.trail_lengths(),
+                blob_shadows: effect
+                    .group_layout_flags
+                    .iter()
+                    .map(|flags| flags.contains(LayoutFlags::BLOB_SHADOW))
+                    .collect(),
                 handle,
             }
         })
         .collect();
+    extracted_effects.added_effects = added_effects;
+    extracted_effects
+        .removed_effect_entities
+        .extend(modified_effect_entities);
 
     // Loop over all existing effects to update them
     extracted_effects.effects.clear();
+    extracted_effects.kill_requests.clear();
     for (
         entity,
         maybe_inherited_visibility,
@@ -1461,14 +2485,32 @@ pub(crate) fn extract_effects(
         spawner,
         effect,
         maybe_properties,
+        maybe_injector,
+        maybe_parent,
+        maybe_throttle,
+        maybe_toggles,
         transform,
     ) in query.p0().iter_mut()
     {
+        // Extract the particles to kill before any check skipping the effect this
+        // frame, so they're killed once it's simulated again
+        if spawner.kill_groups() != 0 {
+            extracted_effects
+                .kill_requests
+                .push((entity, spawner.kill_groups()));
+        }
+
         // Check if shaders are configured
         let Some(effect_shader) = effect.get_configured_shader() else {
             continue;
         };
 
+        // Check if the simulation is skipped this frame because the effect is
+        // off-screen
+        if maybe_throttle.is_some_and(OffscreenThrottle::is_skipped) {
+            continue;
+        }
+
         // Check if hidden, unless always simulated
         if effect.simulation_condition == SimulationCondition::WhenVisible
             && !maybe_inherited_visibility
@@ -1479,6 +2521,17 @@ pub(crate) fn extract_effects(
             continue;
         }
 
+        // Check if culled from all views by the bounds computed on GPU. Effects without
+        // such bounds are never culled. Throttled effects are still simulated on the
+        // frames they're not skipped.
+        if effect.simulation_condition == SimulationCondition::WhenVisible
+            && effect.layout_flags.contains(LayoutFlags::GPU_BOUNDS)
+            && maybe_throttle.is_none()
+            && !maybe_view_visibility.map(|cv| cv.get()).unwrap_or(true)
+        {
+            continue;
+        }
+
         // Check if asset is available, otherwise silently ignore
         let Some(asset) = effects.get(&effect.asset) else {
             trace!(
@@ -1489,13 +2542,25 @@ pub(crate) fn extract_effects(
         };
 
         #[cfg(feature = "2d")]
-        let z_sort_key_2d = effect.z_layer_2d;
-
-        let image_handle = effect
-            .particle_texture
-            .as_ref()
-            .map(|handle| handle.clone_weak())
-            .unwrap_or_default();
+        let z_sort_key_2d = effect.z_sort_key_2d(transform);
+
+        let image_handles = effect
+            .particle_textures()
+            .map(|texture| {
+                texture
+                    .map(|handle| handle.clone_weak())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let color_lut_handles = effect
+            .color_luts()
+            .map(|texture| {
+                texture
+                    .map(|handle| handle.clone_weak())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let z_layers_2d = effect.z_layers_2d().to_vec();
 
         let property_layout = asset.property_layout();
 
@@ -1504,9 +2569,9 @@ pub(crate) fn extract_effects(
             // EffectProperties component is marked as changed when added but contains an
             // empty Vec if there's no property, which would later raise an error if we
             // don't return None here.
-            if properties.is_changed() && !property_layout.is_empty() {
+            if (properties.is_changed() || globals.is_changed()) && !property_layout.is_empty() {
                 trace!("Detected property change, re-serializing...");
-                Some(properties.serialize(&property_layout))
+                Some(properties.serialize(&property_layout, &globals))
             } else {
                 None
             }
@@ -1514,18 +2579,36 @@ pub(crate) fn extract_effects(
             None
         };
 
-        let mut layout_flags = effect.layout_flags;
-        if effect.particle_texture.is_some() {
-            layout_flags |= LayoutFlags::PARTICLE_TEXTURE;
-        }
+        // Serialize the particles injected this frame, if any
+        let (injection_data, inject_count) = match maybe_injector {
+            Some(injector) if asset.injection_capacity > 0 && !injector.submitted().is_empty() => {
+                let (data, count) = serialize_injected_particles(
+                    injector.submitted(),
+                    &asset.particle_layout(),
+                    asset.injection_capacity,
+                );
+                (Some(data), count)
+            }
+            Some(injector) if !injector.submitted().is_empty() => {
+                warn!(
+                    "Effect '{}' on entity {:?} has an EffectInjector but its asset has no injection capacity. Injected particles are ignored.",
+                    asset.name, entity
+                );
+                (None, 0)
+            }
+            _ => (None, 0),
+        };
+
+        let layout_flags = effect.layout_flags;
+        let group_layout_flags = effect.group_layout_flags.clone();
 
         trace!(
-            "Extracted instance of effect '{}' on entity {:?}: image_handle={:?} has_image={} layout_flags={:?}",
+            "Extracted instance of effect '{}' on entity {:?}: image_handles={:?} layout_flags={:?} group_layout_flags={:?}",
             asset.name,
             entity,
-            image_handle,
-            effect.particle_texture.is_some(),
+            image_handles,
             layout_flags,
+            group_layout_flags,
         );
 
         extracted_effects.effects.insert(
@@ -1535,12 +2618,37 @@ pub(crate) fn extract_effects(
                 particle_layout: asset.particle_layout().clone(),
                 property_layout,
                 property_data,
-                spawn_count: spawner.spawn_count,
+                spawn_count: spawner.spawn_count + inject_count,
+                prewarm_time: spawner.prewarm_time(),
+                seed: spawner.gpu_seed(),
+                emitters: asset
+                    .emitters()
+                    .iter()
+                    .zip(spawner.emitters())
+                    .map(|(emitter, emitter_spawner)| ExtractedEmitter {
+                        group_index: emitter.group_index,
+                        spawn_count: emitter_spawner.spawn_count,
+                        prewarm_time: emitter_spawner.prewarm_time(),
+                        seed: emitter_spawner.gpu_seed(),
+                    })
+                    .collect(),
+                time_scale: if spawner.playback() == EffectPlayback::Paused {
+                    0.
+                } else {
+                    spawner.time_scale() * maybe_throttle.map_or(1., OffscreenThrottle::time_scale)
+                },
+                disabled_modifiers: maybe_toggles.map_or(0, |toggles| toggles.disabled_mask(asset)),
+                injection_data,
+                inject_count,
+                parent: maybe_parent.copied(),
                 transform: transform.compute_matrix(),
                 // TODO - more efficient/correct way than inverse()?
                 inverse_transform: transform.compute_matrix().inverse(),
                 layout_flags,
-                image_handle,
+                group_layout_flags,
+                image_handles,
+                color_lut_handles,
+                z_layers_2d,
                 effect_shader,
                 #[cfg(feature = "2d")]
                 z_sort_key_2d,
@@ -1578,6 +2686,14 @@ struct GpuLimits {
     /// [`WgpuLimits::min_storage_buffer_offset_alignment`]: bevy::render::settings::WgpuLimits::min_storage_buffer_offset_alignment
     render_group_indirect_aligned_size: NonZeroU32,
     particle_group_aligned_size: NonZeroU32,
+    /// Value of [`WgpuLimits::max_storage_buffers_per_shader_stage`].
+    ///
+    /// [`WgpuLimits::max_storage_buffers_per_shader_stage`]: bevy::render::settings::WgpuLimits::max_storage_buffers_per_shader_stage
+    max_storage_buffers_per_shader_stage: u32,
+    /// Value of [`WgpuLimits::max_compute_workgroups_per_dimension`].
+    ///
+    /// [`WgpuLimits::max_compute_workgroups_per_dimension`]: bevy::render::settings::WgpuLimits::max_compute_workgroups_per_dimension
+    max_compute_workgroups_per_dimension: u32,
 }
 
 impl GpuLimits {
@@ -1623,6 +2739,12 @@ impl GpuLimits {
             render_effect_indirect_aligned_size,
             render_group_indirect_aligned_size,
             particle_group_aligned_size,
+            max_storage_buffers_per_shader_stage: render_device
+                .limits()
+                .max_storage_buffers_per_shader_stage,
+            max_compute_workgroups_per_dimension: render_device
+                .limits()
+                .max_compute_workgroups_per_dimension,
         }
     }
 
@@ -1631,6 +2753,16 @@ impl GpuLimits {
         self.storage_buffer_align
     }
 
+    /// Maximum number of storage buffers a single compute pass can bind.
+    pub fn max_storage_buffers_per_shader_stage(&self) -> u32 {
+        self.max_storage_buffers_per_shader_stage
+    }
+
+    /// Maximum number of workgroups of a compute dispatch along any dimension.
+    pub fn max_compute_workgroups_per_dimension(&self) -> u32 {
+        self.max_compute_workgroups_per_dimension
+    }
+
     /// Byte alignment for [`GpuDispatchIndirect`].
     pub fn dispatch_indirect_offset(&self, buffer_index: u32) -> u32 {
         self.dispatch_indirect_aligned_size.get() * buffer_index
@@ -1660,6 +2792,65 @@ impl GpuLimits {
 
 struct CacheEntry {
     cache_id: EffectCacheId,
+    /// Whether the particles of the effect were last simulated in local space,
+    /// or `None` if the effect was never simulated yet.
+    local_space_simulation: Option<bool>,
+}
+
+/// Particle groups to kill of each effect, pending until an update pass
+/// applying them is recorded.
+///
+/// Effects are not simulated every frame, for example if culled or throttled,
+/// so the kill requests extracted from the main world can't be applied right
+/// away. The [`VfxSimulateNode`] can't mutate this during the render graph
+/// execution, so it confirms the groups actually killed by each update pass
+/// recorded, which are only removed from the pending ones during the next
+/// [`prepare_effects()`].
+#[derive(Debug, Default)]
+pub(crate) struct PendingKills {
+    /// Bitfield of the particle groups to kill, for each effect.
+    pending: HashMap<Entity, u32>,
+    /// Bitfield of the particle groups killed by the update passes recorded
+    /// since the last call to [`flush_confirmed()`].
+    ///
+    /// [`flush_confirmed()`]: PendingKills::flush_confirmed
+    confirmed: Mutex<Vec<(Entity, u32)>>,
+}
+
+impl PendingKills {
+    /// Request to kill some particle groups of an effect.
+    pub fn request(&mut self, entity: Entity, groups: u32) {
+        *self.pending.entry(entity).or_default() |= groups;
+    }
+
+    /// Get the bitfield of the particle groups of an effect to kill.
+    pub fn get(&self, entity: Entity) -> u32 {
+        self.pending.get(&entity).copied().unwrap_or(0)
+    }
+
+    /// Confirm that an update pass killing some groups of an effect was
+    /// recorded.
+    pub fn confirm(&self, entity: Entity, groups: u32) {
+        self.confirmed.lock().unwrap().push((entity, groups));
+    }
+
+    /// Remove the groups confirmed killed since the last call from the pending
+    /// ones.
+    pub fn flush_confirmed(&mut self) {
+        for (entity, groups) in self.confirmed.get_mut().unwrap().drain(..) {
+            if let Some(pending) = self.pending.get_mut(&entity) {
+                *pending &= !groups;
+                if *pending == 0 {
+                    self.pending.remove(&entity);
+                }
+            }
+        }
+    }
+
+    /// Forget the pending groups of a removed effect.
+    pub fn remove(&mut self, entity: Entity) {
+        self.pending.remove(&entity);
+    }
 }
 
 /// Global resource containing the GPU data to draw all the particle effects in
@@ -1682,8 +2873,37 @@ pub struct EffectsMeta {
     /// Bind group for the simulation parameters, like the current time and
     /// frame delta time.
     sim_params_bind_group: Option<BindGroup>,
+    /// Bind group for the simulation parameters of the update pass, which also
+    /// contains the occupancy grid of the [`VoxelGrid`] and the
+    /// [`CollisionBvh`].
+    ///
+    /// [`VoxelGrid`]: crate::VoxelGrid
+    /// [`CollisionBvh`]: crate::CollisionBvh
+    update_sim_params_bind_group: Option<BindGroup>,
+    /// Same as [`update_sim_params_bind_group`], with the addition of the
+    /// [`StaticColliders`], for the update pass of the effects colliding with
+    /// them.
+    ///
+    /// [`update_sim_params_bind_group`]: EffectsMeta::update_sim_params_bind_group
+    /// [`StaticColliders`]: crate::StaticColliders
+    static_colliders_sim_params_bind_group: Option<BindGroup>,
+    /// IDs of the occupancy grid and collision BVH texture views, and ID and
+    /// bound size of the static collider buffer, bound in
+    /// [`static_colliders_sim_params_bind_group`], to detect changes.
+    ///
+    /// [`static_colliders_sim_params_bind_group`]: EffectsMeta::static_colliders_sim_params_bind_group
+    collider_binding_ids: Option<(TextureViewId, TextureViewId, BufferId, u64)>,
+    /// Static colliders of the [`StaticColliders`] list, uploaded each time the
+    /// list changes.
+    ///
+    /// [`StaticColliders`]: crate::StaticColliders
+    static_collider_buffer: BufferVec<GpuStaticCollider>,
+    /// Lighting of the [`Lights2d`], uploaded each time it changes.
+    ///
+    /// [`Lights2d`]: crate::Lights2d
+    lights_2d_uniform: UniformBuffer<GpuLights2d>,
     /// Bind group for the spawning parameters (number of particles to spawn
-    /// this frame, ...).
+    /// this frame, ...) and the particle event counters.
     spawner_bind_group: Option<BindGroup>,
     /// Bind group #0 of the vfx_indirect shader, containing both the indirect
     /// compute dispatch and render buffers.
@@ -1691,6 +2911,13 @@ pub struct EffectsMeta {
     /// Bind group #3 of the vfx_init shader, containing the indirect render
     /// buffer.
     init_render_indirect_bind_group: Option<BindGroup>,
+    /// Bind group #2 of the batched update passes, containing all spawners and
+    /// the particle event counters.
+    batched_spawner_bind_group: Option<BindGroup>,
+    /// Bind group #3 of the batched update passes, containing the indirect
+    /// render buffers, the per-instance data of all batches, and the particle
+    /// groups.
+    batched_render_indirect_bind_group: Option<BindGroup>,
 
     sim_params_uniforms: UniformBuffer<GpuSimParams>,
     spawner_buffer: AlignedBufferVec<GpuSpawnerParams>,
@@ -1708,6 +2935,63 @@ pub struct EffectsMeta {
     /// each particle group that's populated by the CPU and read (only read) by
     /// the GPU.
     particle_group_buffer: AlignedBufferVec<GpuParticleGroup>,
+    /// Instances updated together by a single dispatch per group this frame.
+    update_batches: Vec<UpdateBatch>,
+    /// Per-instance data of all the [`update_batches`], each batch starting at
+    /// an offset aligned for binding.
+    ///
+    /// [`update_batches`]: EffectsMeta::update_batches
+    update_instance_buffer: BufferVec<GpuUpdateInstance>,
+    /// Counters of the particle events emitted by the effects which are the
+    /// parent of some other effect, written by the update pass and consumed by
+    /// the init pass of the child effects on the next frame. Each parent effect
+    /// owns [`ParentEvent::COUNT`] consecutive counters.
+    ///
+    /// The counters are followed, at [`bounds_offset`], by the bounds of the
+    /// effects computing them on GPU, accumulated by the update pass in the
+    /// local space of each effect, and read back by the [`BoundsReadback`].
+    /// Sharing a single buffer saves a storage buffer binding in the init and
+    /// update passes, whose number is limited on some platforms like WebGPU.
+    ///
+    /// [`bounds_offset`]: EffectsMeta::bounds_offset
+    event_buffer: Option<Buffer>,
+    /// Number of `u32` counters allocated in [`event_buffer`], including the
+    /// bounds.
+    ///
+    /// [`event_buffer`]: EffectsMeta::event_buffer
+    event_buffer_capacity: u32,
+    /// Indirect workgroup counts of the init pass of each spawner, written on
+    /// GPU by the [`InitDispatchPipeline`]. Only allocated if some effect spawns
+    /// particles from the events of a parent effect.
+    init_dispatch_buffer: Option<Buffer>,
+    /// Number of spawners [`init_dispatch_buffer`] can hold.
+    ///
+    /// [`init_dispatch_buffer`]: EffectsMeta::init_dispatch_buffer
+    init_dispatch_buffer_capacity: u32,
+    /// Bind group of the [`InitDispatchPipeline`].
+    init_dispatch_bind_group: Option<BindGroup>,
+    /// Offset in bytes of the first bounds slot in [`event_buffer`] this
+    /// frame. This is aligned to [`BOUNDS_SIZE`].
+    ///
+    /// [`event_buffer`]: EffectsMeta::event_buffer
+    bounds_offset: u64,
+    /// Entities of the effects owning each bounds slot of [`event_buffer`]
+    /// this frame.
+    ///
+    /// [`event_buffer`]: EffectsMeta::event_buffer
+    bounds_entities: Vec<Entity>,
+    /// Offset in bytes of the first impulse slot in [`event_buffer`] this
+    /// frame, after the bounds slots. This is aligned to [`IMPULSE_SIZE`].
+    ///
+    /// [`event_buffer`]: EffectsMeta::event_buffer
+    feedback_offset: u64,
+    /// Entities of the effects owning each impulse slot of [`event_buffer`]
+    /// this frame.
+    ///
+    /// [`event_buffer`]: EffectsMeta::event_buffer
+    feedback_entities: Vec<Entity>,
+    /// Particle groups to kill of each effect.
+    pending_kills: PendingKills,
     /// Unscaled vertices of the mesh of a single particle, generally a quad.
     /// The mesh is later scaled during rendering by the "particle size".
     // FIXME - This is a per-effect thing, unless we merge all meshes into a single buffer (makes
@@ -1716,6 +3000,8 @@ pub struct EffectsMeta {
     /// The pipeline for the indirect dispatch shader, which populates the
     /// indirect compute dispatch buffers.
     indirect_dispatch_pipeline: Option<ComputePipeline>,
+    /// The pipeline calculating the indirect workgroup counts of the init pass.
+    init_dispatch_pipeline: Option<ComputePipeline>,
     /// Various GPU limits and aligned sizes lazily allocated and cached for
     /// convenience.
     gpu_limits: GpuLimits,
@@ -1723,14 +3009,19 @@ pub struct EffectsMeta {
 
 impl EffectsMeta {
     pub fn new(device: RenderDevice) -> Self {
+        // Repeat the quad once per trail segment, so that particles rendered with a
+        // trail can draw one quad per segment. Other particles only draw the first
+        // quad.
         let mut vertices = BufferVec::new(BufferUsages::VERTEX);
-        for v in QUAD_VERTEX_POSITIONS {
-            let uv = v.truncate() + 0.5;
-            let v = *v * Vec3::new(1.0, 1.0, 1.0);
-            vertices.push(GpuParticleVertex {
-                position: v.into(),
-                uv: uv.into(),
-            });
+        for _ in 0..(TrailModifier::MAX_LENGTH - 1) {
+            for v in QUAD_VERTEX_POSITIONS {
+                let uv = v.truncate() + 0.5;
+                let v = *v * Vec3::new(1.0, 1.0, 1.0);
+                vertices.push(GpuParticleVertex {
+                    position: v.into(),
+                    uv: uv.into(),
+                });
+            }
         }
 
         let gpu_limits = GpuLimits::from_device(&device);
@@ -1747,9 +3038,16 @@ impl EffectsMeta {
             entity_map: HashMap::default(),
             view_bind_group: None,
             sim_params_bind_group: None,
+            update_sim_params_bind_group: None,
+            static_colliders_sim_params_bind_group: None,
+            collider_binding_ids: None,
+            static_collider_buffer: BufferVec::new(BufferUsages::STORAGE),
+            lights_2d_uniform: UniformBuffer::default(),
             spawner_bind_group: None,
             dr_indirect_bind_group: None,
             init_render_indirect_bind_group: None,
+            batched_spawner_bind_group: None,
+            batched_render_indirect_bind_group: None,
             sim_params_uniforms: UniformBuffer::default(),
             spawner_buffer: AlignedBufferVec::new(
                 BufferUsages::STORAGE,
@@ -1780,8 +3078,21 @@ impl EffectsMeta {
                 NonZeroU64::new(item_align),
                 Some("hanabi:buffer:particle_group".to_string()),
             ),
+            update_batches: vec![],
+            update_instance_buffer: BufferVec::new(BufferUsages::STORAGE),
+            event_buffer: None,
+            event_buffer_capacity: 0,
+            init_dispatch_buffer: None,
+            init_dispatch_buffer_capacity: 0,
+            init_dispatch_bind_group: None,
+            bounds_offset: 0,
+            bounds_entities: vec![],
+            feedback_offset: 0,
+            feedback_entities: vec![],
+            pending_kills: PendingKills::default(),
             vertices,
             indirect_dispatch_pipeline: None,
+            init_dispatch_pipeline: None,
             gpu_limits,
         }
     }
@@ -1805,6 +3116,7 @@ impl EffectsMeta {
         );
         for entity in &removed_effect_entities {
             trace!("Removing ParticleEffect on entity {:?}", entity);
+            self.pending_kills.remove(*entity);
             if let Some(entry) = self.entity_map.remove(entity) {
                 trace!(
                     "=> ParticleEffect on entity {:?} had cache ID {:?}, removing...",
@@ -1875,16 +3187,24 @@ impl EffectsMeta {
             let mut current_base_instance = 0;
             let first_render_group_dispatch_buffer_index = allocate_sequential_buffers(
                 &mut self.render_group_dispatch_buffer,
-                added_effect.capacities.iter().map(|&capacity| {
-                    let indirect_dispatch = GpuRenderGroupIndirect {
-                        vertex_count: 6, // TODO - Flexible vertex count and mesh particles
-                        dead_count: capacity,
-                        base_instance: current_base_instance,
-                        ..default()
-                    };
-                    current_base_instance += capacity;
-                    indirect_dispatch
-                }),
+                added_effect
+                    .capacities
+                    .iter()
+                    .zip(added_effect.trail_lengths.iter())
+                    .zip(added_effect.blob_shadows.iter())
+                    .map(|((&capacity, &trail_length), &blob_shadow)| {
+                        // Particles rendered with a trail draw one quad per trail segment,
+                        // and particles with a blob shadow an extra quad for the shadow
+                        let quad_count = trail_length.max(2) - 1 + blob_shadow as u32;
+                        let indirect_dispatch = GpuRenderGroupIndirect {
+                            vertex_count: 6 * quad_count, // TODO - Flexible vertex count and mesh particles
+                            dead_count: capacity,
+                            base_instance: current_base_instance,
+                            ..default()
+                        };
+                        current_base_instance += capacity;
+                        indirect_dispatch
+                    }),
             );
 
             let dispatch_buffer_indices = DispatchBufferIndices {
@@ -1899,11 +3219,24 @@ impl EffectsMeta {
                 &added_effect.particle_layout,
                 &added_effect.property_layout,
                 added_effect.layout_flags,
+                added_effect.injection_capacity,
+                added_effect
+                    .trail_lengths
+                    .iter()
+                    .copied()
+                    .max()
+                    .unwrap_or(0),
                 dispatch_buffer_indices,
             );
 
             let entity = added_effect.entity;
-            self.entity_map.insert(entity, CacheEntry { cache_id });
+            self.entity_map.insert(
+                entity,
+                CacheEntry {
+                    cache_id,
+                    local_space_simulation: None,
+                },
+            );
 
             // Note: those effects are already in extracted_effects.effects
             // because they were gathered by the same query as
@@ -1981,6 +3314,45 @@ bitflags! {
         const FLIPBOOK = (1 << 4);
         /// The effect needs UVs.
         const NEEDS_UV = (1 << 5);
+        /// The effect records a trail history for its particles.
+        const TRAILS = (1 << 6);
+        /// The effect computes its bounds on GPU.
+        const GPU_BOUNDS = (1 << 7);
+        /// The particles of the effect are culled individually against the
+        /// frustum of each view.
+        const CULL_PARTICLES = (1 << 8);
+        /// The effect accumulates the momentum of the particles absorbed by a
+        /// [`ForceReceiverModifier`] on GPU.
+        ///
+        /// [`ForceReceiverModifier`]: crate::ForceReceiverModifier
+        const FORCE_FEEDBACK = (1 << 9);
+        /// The particles are drawn with additive blending to visualize their
+        /// overdraw, for [`DebugView::Overdraw`].
+        ///
+        /// [`DebugView::Overdraw`]: crate::DebugView::Overdraw
+        const OVERDRAW = (1 << 10);
+        /// The particles are written into the G-buffer of the deferred renderer,
+        /// in the views using it. Only used with [`USE_ALPHA_MASK`].
+        ///
+        /// [`USE_ALPHA_MASK`]: LayoutFlags::USE_ALPHA_MASK
+        const DEFERRED = (1 << 11);
+        /// The fog of the view is applied to the particles.
+        const FOG = (1 << 12);
+        /// Each particle draws a blob shadow on a plane before the particle
+        /// itself.
+        const BLOB_SHADOW = (1 << 13);
+        /// The effect samples a LUT texture to color the particles over their
+        /// lifetime.
+        const COLOR_LUT = (1 << 14);
+        /// The update pass collides particles with the [`StaticColliders`], and
+        /// binds their storage buffer.
+        ///
+        /// [`StaticColliders`]: crate::StaticColliders
+        const STATIC_COLLIDERS = (1 << 15);
+        /// The particles are lit by the [`Lights2d`] in the render pass.
+        ///
+        /// [`Lights2d`]: crate::Lights2d
+        const LIGHTING_2D = (1 << 16);
     }
 }
 
@@ -1990,6 +3362,49 @@ impl Default for LayoutFlags {
     }
 }
 
+/// Allocate the GPU event counters of the parent effects driving the spawning
+/// of some child effect.
+///
+/// Each parent effect owns [`ParentEvent::COUNT`] consecutive counters in the
+/// event buffer, and the returned map holds the index of the first one. Parent
+/// entities for which `is_extracted` returns `false` are skipped, as no update
+/// pass writes their events. The counters are allocated again each frame, so
+/// the counters of a removed parent effect are reused by the other ones.
+pub(crate) fn allocate_event_slots(
+    parents: impl IntoIterator<Item = EffectParent>,
+    is_extracted: impl Fn(Entity) -> bool,
+) -> HashMap<Entity, u32> {
+    let mut event_slots = HashMap::<Entity, u32>::default();
+    for parent in parents {
+        if !is_extracted(parent.entity) {
+            continue;
+        }
+        let next_slot = event_slots.len() as u32 * ParentEvent::COUNT;
+        event_slots.entry(parent.entity).or_insert(next_slot);
+    }
+    event_slots
+}
+
+/// Index of the GPU event counter driving the spawning of a child effect, if
+/// its parent effect was allocated some counters.
+pub(crate) fn parent_event_counter(
+    event_slots: &HashMap<Entity, u32>,
+    parent: &EffectParent,
+) -> Option<u32> {
+    event_slots
+        .get(&parent.entity)
+        .map(|slot| slot + parent.event.counter_index())
+}
+
+/// Index of the first bounds slot in the event buffer, after the event counters
+/// of the given number of parent effects.
+///
+/// At least one set of counters is always reserved, so the event buffer is never
+/// empty.
+fn bounds_slot_base(parent_count: u32) -> u32 {
+    (parent_count.max(1) * ParentEvent::COUNT).div_ceil(BOUNDS_WORDS)
+}
+
 pub(crate) fn prepare_effects(
     mut commands: Commands,
     sim_params: Res<SimParams>,
@@ -1997,6 +3412,7 @@ pub(crate) fn prepare_effects(
     render_queue: Res<RenderQueue>,
     pipeline_cache: Res<PipelineCache>,
     dispatch_indirect_pipeline: Res<DispatchIndirectPipeline>,
+    init_dispatch_pipeline: Res<InitDispatchPipeline>,
     init_pipeline: Res<ParticlesInitPipeline>,
     update_pipeline: Res<ParticlesUpdatePipeline>,
     mut specialized_init_pipelines: ResMut<SpecializedComputePipelines<ParticlesInitPipeline>>,
@@ -2007,6 +3423,7 @@ pub(crate) fn prepare_effects(
     mut effect_cache: ResMut<EffectCache>,
     mut extracted_effects: ResMut<ExtractedEffects>,
     mut effect_bind_groups: ResMut<EffectBindGroups>,
+    mut unsupported_effects: Local<HashSet<Handle<EffectAsset>>>,
 ) {
     trace!("prepare_effects");
 
@@ -2021,6 +3438,7 @@ pub(crate) fn prepare_effects(
         .write_buffer(&render_device, &render_queue);
 
     effects_meta.indirect_dispatch_pipeline = Some(dispatch_indirect_pipeline.pipeline.clone());
+    effects_meta.init_dispatch_pipeline = Some(init_dispatch_pipeline.pipeline.clone());
 
     // Clear last frame's buffer resizes which may have occured during last frame,
     // during `Node::run()` while the `BufferTable` could not be mutated.
@@ -2034,10 +3452,18 @@ pub(crate) fn prepare_effects(
         .render_group_dispatch_buffer
         .clear_previous_frame_resizes();
 
-    // Allocate new effects, deallocate removed ones
+    // Allocate new effects, deallocate removed ones. Effects reallocated because
+    // their asset was modified are both removed and added, and are simulated
+    // this frame like newly spawned ones.
     let removed_effect_entities = std::mem::take(&mut extracted_effects.removed_effect_entities);
     for entity in &removed_effect_entities {
-        extracted_effects.effects.remove(entity);
+        if !extracted_effects
+            .added_effects
+            .iter()
+            .any(|added_effect| added_effect.entity == *entity)
+        {
+            extracted_effects.effects.remove(entity);
+        }
     }
     effects_meta.add_remove_effects(
         std::mem::take(&mut extracted_effects.added_effects),
@@ -2048,6 +3474,13 @@ pub(crate) fn prepare_effects(
         &mut effect_cache,
     );
 
+    // Merge this frame's kill requests with the ones not applied yet, now that the
+    // ones applied by the update passes recorded last frame are known.
+    effects_meta.pending_kills.flush_confirmed();
+    for (entity, groups) in std::mem::take(&mut extracted_effects.kill_requests) {
+        effects_meta.pending_kills.request(entity, groups);
+    }
+
     // // sort first by z and then by handle. this ensures that, when possible,
     // batches span multiple z layers // batches won't span z-layers if there is
     // another batch between them extracted_effects.effects.sort_by(|a, b| {
@@ -2060,13 +3493,32 @@ pub(crate) fn prepare_effects(
     // Build batcher inputs from extracted effects
     let effects = std::mem::take(&mut extracted_effects.effects);
 
+    // Allocate the GPU event counters of all effects which are the parent of some
+    // other extracted effect.
+    let event_slots = allocate_event_slots(
+        effects
+            .values()
+            .filter_map(|extracted_effect| extracted_effect.parent),
+        |entity| effects.contains_key(&entity),
+    );
+
     let effect_entity_list = effects
         .into_iter()
         .map(|(entity, extracted_effect)| {
             let id = effects_meta.entity_map.get(&entity).unwrap().cache_id;
             let property_buffer = effect_cache.get_property_buffer(id).cloned(); // clone handle for lifetime
+            let injection_buffer = effect_cache.get_injection_buffer(id).cloned();
             let effect_slices = effect_cache.get_slices(id);
 
+            let event_slot = event_slots.get(&entity).copied().unwrap_or(u32::MAX);
+            let (parent_event, spawn_per_event) = extracted_effect
+                .parent
+                .and_then(|parent| {
+                    parent_event_counter(&event_slots, &parent)
+                        .map(|counter| (counter, parent.particles_per_event))
+                })
+                .unwrap_or((u32::MAX, 0));
+
             BatchesInput {
                 handle: extracted_effect.handle,
                 entity,
@@ -2074,12 +3526,27 @@ pub(crate) fn prepare_effects(
                 property_layout: extracted_effect.property_layout.clone(),
                 effect_shader: extracted_effect.effect_shader.clone(),
                 layout_flags: extracted_effect.layout_flags,
-                image_handle: extracted_effect.image_handle,
+                group_layout_flags: extracted_effect.group_layout_flags,
+                image_handles: extracted_effect.image_handles,
+                color_lut_handles: extracted_effect.color_lut_handles,
+                z_layers_2d: extracted_effect.z_layers_2d,
                 spawn_count: extracted_effect.spawn_count,
+                prewarm_time: extracted_effect.prewarm_time,
+                seed: extracted_effect.seed,
+                emitters: extracted_effect.emitters,
+                time_scale: extracted_effect.time_scale,
+                kill_groups: effects_meta.pending_kills.get(entity),
+                disabled_modifiers: extracted_effect.disabled_modifiers,
                 transform: extracted_effect.transform.into(),
                 inverse_transform: extracted_effect.inverse_transform.into(),
                 property_buffer,
                 property_data: extracted_effect.property_data,
+                injection_buffer,
+                injection_data: extracted_effect.injection_data,
+                inject_count: extracted_effect.inject_count,
+                event_slot,
+                parent_event,
+                spawn_per_event,
                 #[cfg(feature = "2d")]
                 z_sort_key_2d: extracted_effect.z_sort_key_2d,
             }
@@ -2095,12 +3562,78 @@ pub(crate) fn prepare_effects(
     // information, and the proper ordering implementation.
     // effect_entity_list.sort_by_key(|a| a.effect_slice.clone());
 
-    // Loop on all extracted effects in order and try to batch them together to
-    // reduce draw calls
-    effects_meta.spawner_buffer.clear();
-    effects_meta.particle_group_buffer.clear();
-    let mut total_group_count = 0;
-    for (effect_index, input) in effect_entity_list.into_iter().enumerate() {
+    // Loop on all extracted effects in order and try to batch them together to
+    // reduce draw calls
+    effects_meta.spawner_buffer.clear();
+    effects_meta.particle_group_buffer.clear();
+    effects_meta.update_batches.clear();
+    effects_meta.update_instance_buffer.clear();
+    effects_meta.bounds_entities.clear();
+    effects_meta.feedback_entities.clear();
+
+    // The bounds slots are stored in the event buffer after the event counters
+    let bounds_slot_base = bounds_slot_base(event_slots.len() as u32);
+    effects_meta.bounds_offset = bounds_slot_base as u64 * BOUNDS_SIZE;
+
+    // The impulse slots are stored after the bounds slots. Effects may be skipped
+    // below, so reserve a bounds slot for all effects which may need one.
+    let bounds_slot_count = effect_entity_list
+        .iter()
+        .filter(|input| input.layout_flags.contains(LayoutFlags::GPU_BOUNDS))
+        .count() as u32;
+    let feedback_slot_base = (bounds_slot_base + bounds_slot_count) * BOUNDS_WORDS / IMPULSE_WORDS;
+    effects_meta.feedback_offset = feedback_slot_base as u64 * IMPULSE_SIZE;
+    // Count the instances sharing the same effect buffer and update shaders, whose
+    // update passes can be batched together.
+    let mut update_instance_counts = HashMap::<(u32, Vec<Handle<Shader>>), u32>::default();
+    for input in &effect_entity_list {
+        *update_instance_counts
+            .entry((
+                input.effect_slices.buffer_index,
+                input.effect_shader.update.clone(),
+            ))
+            .or_default() += 1;
+    }
+    // Index of the update batch currently filled for each of those
+    let mut open_update_batches = HashMap::<(u32, Vec<Handle<Shader>>), usize>::default();
+
+    let mut total_group_count = 0;
+    for (effect_index, mut input) in effect_entity_list.into_iter().enumerate() {
+        // Check the storage buffers bound by the effect against the device limit,
+        // which is as low as 8 on WebGPU. Effects exceeding it cannot be simulated
+        // at all, while culling individual particles is only an optimization.
+        let max_storage_buffers = effects_meta
+            .gpu_limits
+            .max_storage_buffers_per_shader_stage();
+        let (storage_buffer_count, cull_storage_buffer_count) = effect_storage_buffer_counts(
+            !input.property_layout.is_empty(),
+            input.injection_buffer.is_some(),
+            input.layout_flags,
+        );
+        if storage_buffer_count > max_storage_buffers {
+            if unsupported_effects.insert(input.handle.clone()) {
+                error!(
+                    "Effect {:?} requires {} storage buffers per shader stage, but the GPU device only supports {} (Limits::max_storage_buffers_per_shader_stage). The effect will not be simulated nor rendered.",
+                    input.handle, storage_buffer_count, max_storage_buffers
+                );
+            }
+            continue;
+        }
+        if input.layout_flags.contains(LayoutFlags::CULL_PARTICLES)
+            && cull_storage_buffer_count > max_storage_buffers
+        {
+            if unsupported_effects.insert(input.handle.clone()) {
+                warn!(
+                    "Effect {:?} requires {} storage buffers per shader stage to cull its particles, but the GPU device only supports {} (Limits::max_storage_buffers_per_shader_stage). Particle culling is disabled for that effect.",
+                    input.handle, cull_storage_buffer_count, max_storage_buffers
+                );
+            }
+            input.layout_flags.remove(LayoutFlags::CULL_PARTICLES);
+            for group_flags in &mut input.group_layout_flags {
+                group_flags.remove(LayoutFlags::CULL_PARTICLES);
+            }
+        }
+
         // Specialize the init pipeline based on the effect. Note that this is shared by
         // all effect groups of a same effect.
         trace!(
@@ -2108,6 +3641,14 @@ pub(crate) fn prepare_effects(
             input.effect_shader.init,
             input.effect_slices.particle_layout
         );
+        let injection_min_binding_size = input.injection_buffer.as_ref().map(|_| {
+            NonZeroU64::new(injected_particle_stride(
+                &input.effect_slices.particle_layout,
+            ))
+            .unwrap()
+        });
+        let trails = input.layout_flags.contains(LayoutFlags::TRAILS);
+        let static_colliders = input.layout_flags.contains(LayoutFlags::STATIC_COLLIDERS);
         let init_pipeline_id = specialized_init_pipelines.specialize(
             &pipeline_cache,
             &init_pipeline,
@@ -2122,10 +3663,44 @@ pub(crate) fn prepare_effects(
                 } else {
                     Some(input.property_layout.min_binding_size())
                 },
+                injection_min_binding_size,
+                trails,
             },
         );
         trace!("Init pipeline specialized: id={:?}", init_pipeline_id);
 
+        // Specialize the init pipelines of the additional emitters, which only differ
+        // from the one of the effect by their shader
+        let emitter_init_pipeline_ids: Vec<_> = input
+            .effect_shader
+            .emitter_init
+            .iter()
+            .map(|init_source| {
+                specialized_init_pipelines.specialize(
+                    &pipeline_cache,
+                    &init_pipeline,
+                    ParticleInitPipelineKey {
+                        shader: init_source.clone(),
+                        particle_layout_min_binding_size: input
+                            .effect_slices
+                            .particle_layout
+                            .min_binding_size(),
+                        property_layout_min_binding_size: if input.property_layout.is_empty() {
+                            None
+                        } else {
+                            Some(input.property_layout.min_binding_size())
+                        },
+                        injection_min_binding_size,
+                        trails,
+                    },
+                )
+            })
+            .collect();
+        trace!(
+            "Emitter init pipeline(s) specialized: ids={:?}",
+            emitter_init_pipeline_ids
+        );
+
         // Specialize the update pipelines based on the effect
         trace!(
             "Specializing update pipeline(s): update_shader(s)={:?} particle_layout={:?} property_layout={:?}",
@@ -2137,7 +3712,8 @@ pub(crate) fn prepare_effects(
             .effect_shader
             .update
             .iter()
-            .map(|update_source| {
+            .enumerate()
+            .map(|(group_index, update_source)| {
                 specialized_update_pipelines.specialize(
                     &pipeline_cache,
                     &update_pipeline,
@@ -2145,6 +3721,12 @@ pub(crate) fn prepare_effects(
                         shader: update_source.clone(),
                         particle_layout: input.effect_slices.particle_layout.clone(),
                         property_layout: input.property_layout.clone(),
+                        injection_min_binding_size,
+                        trails,
+                        static_colliders,
+                        cull: false,
+                        batched: false,
+                        group_index: group_index as u32,
                     },
                 )
             })
@@ -2154,6 +3736,115 @@ pub(crate) fn prepare_effects(
             update_pipeline_ids
         );
 
+        // Specialize the batched update pipelines if other instances share the same
+        // effect buffer and update shaders, unless the additional storage buffers of
+        // the batched pass exceed the device limit.
+        let update_batch_key = (
+            input.effect_slices.buffer_index,
+            input.effect_shader.update.clone(),
+        );
+        let batched_update_pipeline_ids: Vec<_> = if update_instance_counts
+            .get(&update_batch_key)
+            .is_some_and(|count| *count > 1)
+            && storage_buffer_count + BATCHED_UPDATE_STORAGE_BUFFER_COUNT <= max_storage_buffers
+        {
+            input
+                .effect_shader
+                .update
+                .iter()
+                .enumerate()
+                .map(|(group_index, update_source)| {
+                    specialized_update_pipelines.specialize(
+                        &pipeline_cache,
+                        &update_pipeline,
+                        ParticleUpdatePipelineKey {
+                            shader: update_source.clone(),
+                            particle_layout: input.effect_slices.particle_layout.clone(),
+                            property_layout: input.property_layout.clone(),
+                            injection_min_binding_size,
+                            trails,
+                            static_colliders,
+                            cull: false,
+                            batched: true,
+                            group_index: group_index as u32,
+                        },
+                    )
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // Specialize the cull pipelines, which run the cull entry point of the update
+        // shader of each group, if the effect culls its particles
+        let cull_pipeline_ids: Vec<_> = if input.layout_flags.contains(LayoutFlags::CULL_PARTICLES)
+        {
+            input
+                .effect_shader
+                .update
+                .iter()
+                .enumerate()
+                .map(|(group_index, update_source)| {
+                    specialized_update_pipelines.specialize(
+                        &pipeline_cache,
+                        &update_pipeline,
+                        ParticleUpdatePipelineKey {
+                            shader: update_source.clone(),
+                            particle_layout: input.effect_slices.particle_layout.clone(),
+                            property_layout: input.property_layout.clone(),
+                            injection_min_binding_size,
+                            trails,
+                            static_colliders,
+                            cull: true,
+                            batched: false,
+                            group_index: group_index as u32,
+                        },
+                    )
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // Check if the effect switched simulation space since it was last simulated.
+        // The update pass of the new shaders converts the existing particles into the
+        // new space on the first frame they run, so wait until they're compiled, to
+        // avoid skipping that frame. In the meantime, the effect is neither simulated
+        // nor rendered.
+        let local_space_simulation = input
+            .layout_flags
+            .contains(LayoutFlags::LOCAL_SPACE_SIMULATION);
+        let cache_entry = effects_meta.entity_map.get_mut(&input.entity).unwrap();
+        let space_conversion = match cache_entry.local_space_simulation {
+            Some(was_local) if was_local != local_space_simulation => {
+                let ready = update_pipeline_ids
+                    .iter()
+                    .all(|id| pipeline_cache.get_compute_pipeline(*id).is_some());
+                if !ready {
+                    trace!(
+                        "Effect {:?} switched simulation space, waiting for its update pipelines.",
+                        input.entity
+                    );
+                    continue;
+                }
+                // Don't spawn any particle this frame; new particles are already in the
+                // new space, and would be converted twice by the update pass.
+                input.spawn_count = 0;
+                input.inject_count = 0;
+                input.parent_event = u32::MAX;
+                for emitter in &mut input.emitters {
+                    emitter.spawn_count = 0;
+                }
+                if local_space_simulation {
+                    SPACE_CONVERSION_TO_LOCAL
+                } else {
+                    SPACE_CONVERSION_TO_GLOBAL
+                }
+            }
+            _ => SPACE_CONVERSION_NONE,
+        };
+        cache_entry.local_space_simulation = Some(local_space_simulation);
+
         let init_shader = input.effect_shader.init.clone();
         trace!("init_shader = {:?}", init_shader);
 
@@ -2163,7 +3854,7 @@ pub(crate) fn prepare_effects(
         let render_shader = input.effect_shader.render.clone();
         trace!("render_shader(s) = {:?}", render_shader);
 
-        trace!("image_handle = {:?}", input.image_handle);
+        trace!("image_handles = {:?}", input.image_handles);
 
         let layout_flags = input.layout_flags;
         trace!("layout_flags = {:?}", layout_flags);
@@ -2183,21 +3874,69 @@ pub(crate) fn prepare_effects(
         // will be pushed in order into the array.
         let spawner_base = effects_meta.spawner_buffer.len() as u32;
 
+        // Allocate a slot in the bounds buffer if the effect computes its bounds
+        let bounds_slot = if input.layout_flags.contains(LayoutFlags::GPU_BOUNDS) {
+            effects_meta.bounds_entities.push(input.entity);
+            bounds_slot_base + effects_meta.bounds_entities.len() as u32 - 1
+        } else {
+            u32::MAX
+        };
+
+        // Allocate a slot for the impulse of the force receivers of the effect, if any
+        let feedback_slot = if input.layout_flags.contains(LayoutFlags::FORCE_FEEDBACK) {
+            effects_meta.feedback_entities.push(input.entity);
+            feedback_slot_base + effects_meta.feedback_entities.len() as u32 - 1
+        } else {
+            u32::MAX
+        };
+
         let spawner_params = GpuSpawnerParams {
             transform: input.transform,
             inverse_transform: input.inverse_transform,
             spawn: input.spawn_count as i32,
-            // FIXME - Probably bad to re-seed each time there's a change
-            seed: random::<u32>(),
+            seed: input.seed,
             count: 0,
             // FIXME: the effect_index is global inside the global spawner buffer,
             // but the group_index is the index of the particle buffer, which can
             // in theory (with batching) contain > 1 effect per buffer.
             effect_index: input.effect_slices.buffer_index,
+            prewarm: input.prewarm_time,
+            time_scale: input.time_scale,
+            kill_groups: input.kill_groups,
+            inject: input.inject_count,
+            event_slot: input.event_slot,
+            parent_event: input.parent_event,
+            spawn_per_event: input.spawn_per_event,
+            bounds_slot,
+            space_conversion,
+            feedback_slot,
+            disabled_modifiers: input.disabled_modifiers,
+            _pad: 0,
         };
         trace!("spawner_params = {:?}", spawner_params);
         effects_meta.spawner_buffer.push(spawner_params);
 
+        // Push the spawners of the additional emitters right after the one of the
+        // effect. Those are only used by the init pass of each emitter, so only need
+        // their own spawn count, seed, and pre-warming duration. Particles injected
+        // from the CPU or spawned from parent events are handled by the effect
+        // spawner only.
+        for emitter in &input.emitters {
+            let emitter_params = GpuSpawnerParams {
+                spawn: emitter.spawn_count as i32,
+                seed: emitter.seed,
+                prewarm: emitter.prewarm_time,
+                kill_groups: 0,
+                inject: 0,
+                parent_event: u32::MAX,
+                spawn_per_event: 0,
+                space_conversion: SPACE_CONVERSION_NONE,
+                ..spawner_params
+            };
+            trace!("emitter_params = {:?}", emitter_params);
+            effects_meta.spawner_buffer.push(emitter_params);
+        }
+
         // Create the particle group buffer entries.
         let mut first_particle_group_buffer_index = None;
         let mut local_group_count = 0;
@@ -2221,6 +3960,66 @@ pub(crate) fn prepare_effects(
         let effect_cache_id = effects_meta.entity_map.get(&input.entity).unwrap().cache_id;
         let dispatch_buffer_indices = effect_cache.get_dispatch_buffer_indices(effect_cache_id);
 
+        // Add the instance to a batched update pass once all its batched pipelines are
+        // compiled. Until then, the instance is updated by its own dispatches.
+        let update_batch = if !batched_update_pipeline_ids.is_empty()
+            && batched_update_pipeline_ids
+                .iter()
+                .all(|id| pipeline_cache.get_compute_pipeline(*id).is_some())
+        {
+            // Each instance is a workgroup row of the dispatch, so the batch size is
+            // limited like any dispatch dimension.
+            let max_instances = effects_meta
+                .gpu_limits
+                .max_compute_workgroups_per_dimension() as usize;
+            let batch_index = match open_update_batches.get(&update_batch_key) {
+                Some(&index)
+                    if effects_meta.update_batches[index].instances.len() < max_instances =>
+                {
+                    index
+                }
+                _ => {
+                    let workgroup_size = update_pipeline.workgroup_size.0;
+                    let max_workgroups = effects_meta
+                        .gpu_limits
+                        .max_compute_workgroups_per_dimension();
+                    effects_meta.update_batches.push(UpdateBatch {
+                        pipeline_ids: batched_update_pipeline_ids,
+                        workgroup_counts: input
+                            .effect_slices
+                            .slices
+                            .windows(2)
+                            .map(|range| {
+                                (range[1] - range[0])
+                                    .div_ceil(workgroup_size)
+                                    .min(max_workgroups)
+                            })
+                            .collect(),
+                        instances: vec![],
+                        first_instance: 0,
+                    });
+                    let index = effects_meta.update_batches.len() - 1;
+                    open_update_batches.insert(update_batch_key, index);
+                    index
+                }
+            };
+            effects_meta.update_batches[batch_index]
+                .instances
+                .push(GpuUpdateInstance {
+                    spawner_index: spawner_base,
+                    render_effect_index: dispatch_buffer_indices
+                        .render_effect_metadata_buffer_index
+                        .0,
+                    render_group_base: dispatch_buffer_indices
+                        .first_render_group_dispatch_buffer_index
+                        .0,
+                    particle_group_base: first_particle_group_buffer_index.unwrap_or_default(),
+                });
+            Some(batch_index as u32)
+        } else {
+            None
+        };
+
         // Write properties for this effect if they were modified.
         // FIXME - This doesn't work with batching!
         if let Some(property_data) = &input.property_data {
@@ -2233,6 +4032,19 @@ pub(crate) fn prepare_effects(
             }
         }
 
+        // Write the particles injected from the CPU this frame, if any.
+        if let Some(injection_data) = &input.injection_data {
+            if let Some(injection_buffer) = input.injection_buffer.as_ref() {
+                trace!(
+                    "Scheduled upload of {} injected particle(s) to GPU",
+                    input.inject_count
+                );
+                render_queue.write_buffer(injection_buffer, 0, injection_data);
+            } else {
+                error!("Cannot upload injected particles to GPU, no injection buffer!");
+            }
+        }
+
         #[cfg(feature = "2d")]
         let z_sort_key_2d = input.z_sort_key_2d;
 
@@ -2247,7 +4059,10 @@ pub(crate) fn prepare_effects(
             spawner_base,
             effect_cache_id,
             init_pipeline_id,
+            emitter_init_pipeline_ids,
             update_pipeline_ids,
+            cull_pipeline_ids,
+            update_batch,
             dispatch_buffer_indices,
             first_particle_group_buffer_index.unwrap_or_default(),
         );
@@ -2268,11 +4083,82 @@ pub(crate) fn prepare_effects(
         }
     }
 
+    // (Re-)allocate the event counters and bounds if needed. Always allocate at least
+    // one set of counters, as the buffer is bound even if no effect has any child.
+    let event_counter_count = feedback_slot_base * IMPULSE_WORDS
+        + effects_meta.feedback_entities.len() as u32 * IMPULSE_WORDS;
+    if effects_meta.event_buffer.is_none()
+        || effects_meta.event_buffer_capacity < event_counter_count
+    {
+        trace!(
+            "Allocating event buffer for {} counters",
+            event_counter_count
+        );
+        effects_meta.event_buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("hanabi:buffer:event"),
+            size: event_counter_count as u64 * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        }));
+        effects_meta.event_buffer_capacity = event_counter_count;
+    }
+
+    // (Re-)allocate the indirect init dispatch buffer if needed. This is only used by
+    // effects spawning from the events of a parent effect, so only exists if any.
+    let spawner_count = effects_meta.spawner_buffer.len() as u32;
+    if !event_slots.is_empty()
+        && (effects_meta.init_dispatch_buffer.is_none()
+            || effects_meta.init_dispatch_buffer_capacity < spawner_count)
+    {
+        trace!(
+            "Allocating init dispatch buffer for {} spawners",
+            spawner_count
+        );
+        effects_meta.init_dispatch_buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("hanabi:buffer:init_dispatch"),
+            size: spawner_count as u64 * INIT_DISPATCH_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        }));
+        effects_meta.init_dispatch_buffer_capacity = spawner_count;
+    }
+
     // Write the entire spawner buffer for this frame, for all effects combined
     effects_meta
         .spawner_buffer
         .write_buffer(&render_device, &render_queue);
 
+    // Pack the per-instance data of all batched update passes, each batch starting
+    // at an offset aligned for binding, and write it for this frame
+    let instance_align = effects_meta.gpu_limits.storage_buffer_align().get()
+        / GpuUpdateInstance::min_size().get() as u32;
+    let counts = effects_meta
+        .update_batches
+        .iter()
+        .map(|batch| batch.instances.len() as u32)
+        .collect::<Vec<_>>();
+    let (first_instances, instance_count) = pack_update_batches(&counts, instance_align);
+    let EffectsMeta {
+        update_batches,
+        update_instance_buffer,
+        ..
+    } = &mut *effects_meta;
+    for (batch, first_instance) in update_batches.iter_mut().zip(first_instances) {
+        batch.first_instance = first_instance;
+        update_instance_buffer
+            .values_mut()
+            .resize(first_instance as usize, GpuUpdateInstance::default());
+        update_instance_buffer
+            .values_mut()
+            .extend_from_slice(&batch.instances);
+    }
+    update_instance_buffer
+        .values_mut()
+        .resize(instance_count as usize, GpuUpdateInstance::default());
+    if !update_instance_buffer.is_empty() {
+        update_instance_buffer.write_buffer(&render_device, &render_queue);
+    }
+
     // Write the entire particle group buffer for this frame
     if effects_meta
         .particle_group_buffer
@@ -2312,7 +4198,57 @@ pub(crate) fn prepare_effects(
     if prev_buffer_id != effects_meta.sim_params_uniforms.buffer().map(|b| b.id()) {
         // Buffer changed, invalidate bind groups
         effects_meta.sim_params_bind_group = None;
+        effects_meta.update_sim_params_bind_group = None;
+        effects_meta.static_colliders_sim_params_bind_group = None;
+    }
+}
+
+/// Upload the [`StaticColliders`] list to GPU each time it changes.
+///
+/// [`StaticColliders`]: crate::StaticColliders
+pub(crate) fn prepare_static_colliders(
+    mut effects_meta: ResMut<EffectsMeta>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    static_colliders: Option<Res<StaticColliders>>,
+) {
+    let Some(static_colliders) = static_colliders else {
+        return;
+    };
+    if !static_colliders.is_changed() {
+        return;
+    }
+
+    let buffer = &mut effects_meta.static_collider_buffer;
+    buffer.clear();
+    for collider in &static_colliders.colliders {
+        buffer.push(collider.into());
+    }
+    buffer.write_buffer(&render_device, &render_queue);
+}
+
+/// Upload the [`Lights2d`] lighting the particles, each time they change.
+pub(crate) fn prepare_lights_2d(
+    mut effects_meta: ResMut<EffectsMeta>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    lights_2d: Option<Res<Lights2d>>,
+) {
+    // The uniform is bound to all render pipelines, so is written at least once
+    if effects_meta.lights_2d_uniform.buffer().is_some()
+        && !lights_2d.as_ref().is_some_and(|lights| lights.is_changed())
+    {
+        return;
     }
+
+    let lights_2d = lights_2d.map_or_else(
+        || GpuLights2d::from(&Lights2d::default()),
+        |lights| GpuLights2d::from(&*lights),
+    );
+    effects_meta.lights_2d_uniform.set(lights_2d);
+    effects_meta
+        .lights_2d_uniform
+        .write_buffer(&render_device, &render_queue);
 }
 
 /// The per-buffer bind group for the GPU particle buffer.
@@ -2337,6 +4273,11 @@ pub struct EffectBindGroups {
     particle_buffers: HashMap<u32, BufferBindGroups>,
     /// Map of bind groups for image assets used as particle textures.
     images: HashMap<AssetId<Image>, BindGroup>,
+    /// Map of bind groups for image assets used as color LUT textures.
+    color_luts: HashMap<AssetId<Image>, BindGroup>,
+    /// Empty bind group bound in place of the particle texture of the groups
+    /// with a color LUT but without particle texture.
+    empty: Option<BindGroup>,
     /// Map from effect index to its update render indirect bind group (group
     /// 3).
     update_render_indirect_bind_groups: HashMap<EffectCacheId, BindGroup>,
@@ -2358,13 +4299,29 @@ pub struct QueueEffectsReadOnlyParams<'w, 's> {
     draw_functions_3d: Res<'w, DrawFunctions<Transparent3d>>,
     #[cfg(feature = "3d")]
     draw_functions_alpha_mask: Res<'w, DrawFunctions<AlphaMask3d>>,
+    #[cfg(feature = "3d")]
+    draw_functions_deferred: Res<'w, DrawFunctions<AlphaMask3dDeferred>>,
     render_pipeline: Res<'w, ParticlesRenderPipeline>,
     #[system_param(ignore)]
     marker: PhantomData<&'s usize>,
 }
 
+/// Views drawing the effects into a render phase, with the prepasses they use.
+type EffectViews<'w, 's, T> = Query<
+    'w,
+    's,
+    (
+        &'static mut RenderPhase<T>,
+        &'static VisibleEntities,
+        &'static ExtractedView,
+        Has<NormalPrepass>,
+        Has<MotionVectorPrepass>,
+        Has<DeferredPrepass>,
+    ),
+>;
+
 fn emit_draw<T, F>(
-    views: &mut Query<(&mut RenderPhase<T>, &VisibleEntities, &ExtractedView)>,
+    views: &mut EffectViews<T>,
     view_entities: &mut FixedBitSet,
     effect_batches: &Query<(Entity, &mut EffectBatches)>,
     effect_draw_batches: &Query<(Entity, &mut EffectDrawBatch)>,
@@ -2375,12 +4332,26 @@ fn emit_draw<T, F>(
     make_phase_item: F,
     #[cfg(all(feature = "2d", feature = "3d"))] pipeline_mode: PipelineMode,
     use_alpha_mask: bool,
+    deferred: bool,
+    split_z_layers_2d: bool,
 ) where
     T: PhaseItem,
-    F: Fn(CachedRenderPipelineId, Entity, &EffectDrawBatch, u32, &ExtractedView) -> T,
+    F: Fn(CachedRenderPipelineId, Entity, &EffectDrawBatch, u32, Option<f32>, &ExtractedView) -> T,
 {
-    for (mut render_phase, visible_entities, view) in views.iter_mut() {
-        trace!("Process new view (use_alpha_mask={})", use_alpha_mask);
+    for (
+        mut render_phase,
+        visible_entities,
+        view,
+        normal_prepass,
+        motion_vector_prepass,
+        deferred_prepass,
+    ) in views.iter_mut()
+    {
+        trace!(
+            "Process new view (use_alpha_mask={} deferred={})",
+            use_alpha_mask,
+            deferred
+        );
 
         {
             #[cfg(feature = "trace")]
@@ -2410,15 +4381,26 @@ fn emit_draw<T, F>(
                 continue;
             };
 
+            let group_flags = batches[draw_batch.group_index].layout_flags;
             trace!(
-                "-> EffectBaches: entity={:?} buffer_index={} spawner_base={} layout_flags={:?}",
+                "-> EffectBaches: entity={:?} buffer_index={} spawner_base={} layout_flags={:?} group_flags={:?}",
                 batches_entity,
                 batches.buffer_index,
                 batches.spawner_base,
                 batches.layout_flags,
+                group_flags,
             );
 
-            if use_alpha_mask != batches.layout_flags.contains(LayoutFlags::USE_ALPHA_MASK) {
+            // Each group is rendered in the phase matching its own alpha mode.
+            if use_alpha_mask != group_flags.contains(LayoutFlags::USE_ALPHA_MASK) {
+                continue;
+            }
+
+            // Groups writing into the G-buffer are drawn in the deferred prepass of
+            // the views using the deferred renderer, and in the forward passes of
+            // the other views.
+            let group_deferred = group_flags.contains(LayoutFlags::DEFERRED);
+            if deferred != (group_deferred && deferred_prepass) {
                 continue;
             }
 
@@ -2444,13 +4426,18 @@ fn emit_draw<T, F>(
             // FIXME - We draw the entire batch, but part of it may not be visible in this
             // view! We should re-batch for the current view specifically!
 
-            let local_space_simulation = batches
-                .layout_flags
-                .contains(LayoutFlags::LOCAL_SPACE_SIMULATION);
-            let use_alpha_mask = batches.layout_flags.contains(LayoutFlags::USE_ALPHA_MASK);
-            let flipbook = batches.layout_flags.contains(LayoutFlags::FLIPBOOK);
-            let needs_uv = batches.layout_flags.contains(LayoutFlags::NEEDS_UV);
-            let has_image = batches.layout_flags.contains(LayoutFlags::PARTICLE_TEXTURE);
+            let local_space_simulation = group_flags.contains(LayoutFlags::LOCAL_SPACE_SIMULATION);
+            let use_alpha_mask = group_flags.contains(LayoutFlags::USE_ALPHA_MASK);
+            let flipbook = group_flags.contains(LayoutFlags::FLIPBOOK);
+            let needs_uv = group_flags.contains(LayoutFlags::NEEDS_UV);
+            let trails = group_flags.contains(LayoutFlags::TRAILS);
+            let cull_particles = group_flags.contains(LayoutFlags::CULL_PARTICLES);
+            let overdraw = group_flags.contains(LayoutFlags::OVERDRAW);
+            let fog = group_flags.contains(LayoutFlags::FOG);
+            let blob_shadow = group_flags.contains(LayoutFlags::BLOB_SHADOW);
+            let lighting_2d = group_flags.contains(LayoutFlags::LIGHTING_2D);
+            let has_image = group_flags.contains(LayoutFlags::PARTICLE_TEXTURE);
+            let has_color_lut = group_flags.contains(LayoutFlags::COLOR_LUT);
 
             // Specialize the render pipeline based on the effect batch
             trace!(
@@ -2467,24 +4454,70 @@ fn emit_draw<T, F>(
             let render_shader_source = &batches.render_shaders[draw_batch.group_index as usize];
             trace!("Emit for group index #{}", draw_batch.group_index);
 
+            let key = ParticleRenderPipelineKey {
+                shader: render_shader_source.clone(),
+                particle_layout: batches.particle_layout.clone(),
+                has_image,
+                has_color_lut,
+                local_space_simulation,
+                use_alpha_mask,
+                flipbook,
+                needs_uv,
+                trails,
+                cull_particles,
+                overdraw,
+                fog,
+                blob_shadow,
+                deferred_prepass: deferred,
+                normal_prepass: deferred && normal_prepass,
+                motion_vector_prepass: deferred && motion_vector_prepass,
+                lighting_2d,
+                z_layer_2d: None,
+                #[cfg(all(feature = "2d", feature = "3d"))]
+                pipeline_mode,
+                msaa_samples,
+                hdr: view.hdr,
+            };
+
+            // In 2D, a group split into layers by a ZLayer2dModifier is drawn once per
+            // layer, each draw sorted at the Z coordinate of its layer and keeping
+            // only the particles of that layer.
+            let z_layers_2d = &batches[draw_batch.group_index].z_layers_2d;
+            if split_z_layers_2d && !z_layers_2d.is_empty() {
+                for (index, &z_layer) in z_layers_2d.iter().enumerate() {
+                    let render_pipeline_id = specialized_render_pipelines.specialize(
+                        pipeline_cache,
+                        &read_params.render_pipeline,
+                        ParticleRenderPipelineKey {
+                            z_layer_2d: (z_layers_2d.len() > 1).then_some(index as u32),
+                            ..key.clone()
+                        },
+                    );
+                    trace!(
+                        "+ Add Transparent for layer #{} at z={} on draw_entity {:?}: pipeline={:?}",
+                        index,
+                        z_layer,
+                        draw_entity,
+                        render_pipeline_id
+                    );
+                    render_phase.add(make_phase_item(
+                        render_pipeline_id,
+                        draw_entity,
+                        draw_batch,
+                        draw_batch.group_index,
+                        Some(z_layer),
+                        view,
+                    ));
+                }
+                continue;
+            }
+
             #[cfg(feature = "trace")]
             let _span_specialize = bevy::utils::tracing::info_span!("specialize").entered();
             let render_pipeline_id = specialized_render_pipelines.specialize(
                 pipeline_cache,
                 &read_params.render_pipeline,
-                ParticleRenderPipelineKey {
-                    shader: render_shader_source.clone(),
-                    particle_layout: batches.particle_layout.clone(),
-                    has_image,
-                    local_space_simulation,
-                    use_alpha_mask,
-                    flipbook,
-                    needs_uv,
-                    #[cfg(all(feature = "2d", feature = "3d"))]
-                    pipeline_mode,
-                    msaa_samples,
-                    hdr: view.hdr,
-                },
+                key,
             );
             #[cfg(feature = "trace")]
             _span_specialize.exit();
@@ -2508,6 +4541,7 @@ fn emit_draw<T, F>(
                 draw_entity,
                 draw_batch,
                 draw_batch.group_index,
+                None,
                 view,
             ));
         }
@@ -2516,21 +4550,10 @@ fn emit_draw<T, F>(
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn queue_effects(
-    #[cfg(feature = "2d")] mut views_2d: Query<(
-        &mut RenderPhase<Transparent2d>,
-        &VisibleEntities,
-        &ExtractedView,
-    )>,
-    #[cfg(feature = "3d")] mut views_3d: Query<(
-        &mut RenderPhase<Transparent3d>,
-        &VisibleEntities,
-        &ExtractedView,
-    )>,
-    #[cfg(feature = "3d")] mut views_alpha_mask: Query<(
-        &mut RenderPhase<AlphaMask3d>,
-        &VisibleEntities,
-        &ExtractedView,
-    )>,
+    #[cfg(feature = "2d")] mut views_2d: EffectViews<Transparent2d>,
+    #[cfg(feature = "3d")] mut views_3d: EffectViews<Transparent3d>,
+    #[cfg(feature = "3d")] mut views_alpha_mask: EffectViews<AlphaMask3d>,
+    #[cfg(feature = "3d")] mut views_deferred: EffectViews<AlphaMask3dDeferred>,
     effects_meta: Res<EffectsMeta>,
     mut specialized_render_pipelines: ResMut<SpecializedRenderPipelines<ParticlesRenderPipeline>>,
     pipeline_cache: Res<PipelineCache>,
@@ -2555,10 +4578,12 @@ pub(crate) fn queue_effects(
             AssetEvent::Unused { .. } => None,
             AssetEvent::Modified { id } => {
                 trace!("Destroy bind group of modified image asset {:?}", id);
+                effect_bind_groups.color_luts.remove(id);
                 effect_bind_groups.images.remove(id)
             }
             AssetEvent::Removed { id } => {
                 trace!("Destroy bind group of removed image asset {:?}", id);
+                effect_bind_groups.color_luts.remove(id);
                 effect_bind_groups.images.remove(id)
             }
         };
@@ -2593,17 +4618,19 @@ pub(crate) fn queue_effects(
                 specialized_render_pipelines.reborrow(),
                 &pipeline_cache,
                 msaa.samples(),
-                |id, entity, draw_batch, _group, _view| Transparent2d {
+                |id, entity, draw_batch, _group, z_layer, _view| Transparent2d {
                     draw_function: draw_effects_function_2d,
                     pipeline: id,
                     entity,
-                    sort_key: draw_batch.z_sort_key_2d,
+                    sort_key: z_layer.map_or(draw_batch.z_sort_key_2d, FloatOrd),
                     batch_range: 0..1,
                     dynamic_offset: None,
                 },
                 #[cfg(feature = "3d")]
                 PipelineMode::Camera2d,
                 false,
+                false,
+                true,
             );
         }
     }
@@ -2633,7 +4660,7 @@ pub(crate) fn queue_effects(
                 specialized_render_pipelines.reborrow(),
                 &pipeline_cache,
                 msaa.samples(),
-                |id, entity, batch, _group, view| Transparent3d {
+                |id, entity, batch, _group, _z_layer, view| Transparent3d {
                     draw_function: draw_effects_function_3d,
                     pipeline: id,
                     entity,
@@ -2646,6 +4673,8 @@ pub(crate) fn queue_effects(
                 #[cfg(feature = "2d")]
                 PipelineMode::Camera3d,
                 false,
+                false,
+                false,
             );
         }
 
@@ -2671,7 +4700,7 @@ pub(crate) fn queue_effects(
                 specialized_render_pipelines.reborrow(),
                 &pipeline_cache,
                 msaa.samples(),
-                |id, entity, batch, _group, view| AlphaMask3d {
+                |id, entity, batch, _group, _z_layer, view| AlphaMask3d {
                     draw_function: draw_effects_function_alpha_mask,
                     pipeline: id,
                     entity,
@@ -2684,6 +4713,48 @@ pub(crate) fn queue_effects(
                 #[cfg(feature = "2d")]
                 PipelineMode::Camera3d,
                 true,
+                false,
+                false,
+            );
+        }
+
+        // Effects with alpha mask written into the G-buffer
+        if !views_deferred.is_empty() {
+            #[cfg(feature = "trace")]
+            let _span_draw = bevy::utils::tracing::info_span!("draw_deferred").entered();
+
+            trace!("Emit effect draw calls for deferred 3D views...");
+
+            let draw_effects_function_deferred = read_params
+                .draw_functions_deferred
+                .read()
+                .get_id::<DrawEffects>()
+                .unwrap();
+
+            emit_draw(
+                &mut views_deferred,
+                &mut view_entities,
+                &effect_batches,
+                &effect_draw_batches,
+                &read_params,
+                specialized_render_pipelines.reborrow(),
+                &pipeline_cache,
+                msaa.samples(),
+                |id, entity, batch, _group, _z_layer, view| AlphaMask3dDeferred {
+                    draw_function: draw_effects_function_deferred,
+                    pipeline_id: id,
+                    entity,
+                    distance: view
+                        .rangefinder3d()
+                        .distance_translation(&batch.translation_3d),
+                    batch_range: 0..1,
+                    dynamic_offset: None,
+                },
+                #[cfg(feature = "2d")]
+                PipelineMode::Camera3d,
+                true,
+                true,
+                false,
             );
         }
     }
@@ -2698,11 +4769,19 @@ pub(crate) fn prepare_resources(
     mut effects_meta: ResMut<EffectsMeta>,
     render_device: Res<RenderDevice>,
     view_uniforms: Res<ViewUniforms>,
+    billboard_views: Res<BillboardViewUniforms>,
+    view_fogs: Res<ViewFogUniforms>,
+    cull_meta: Res<CullMeta>,
     read_params: QueueEffectsReadOnlyParams,
 ) {
     // Get the binding for the ViewUniform, the uniform data structure containing
     // the Camera data for the current view.
-    let Some(view_binding) = view_uniforms.uniforms.binding() else {
+    let (Some(view_binding), Some(billboard_binding), Some(fog_binding), Some(lights_2d_binding)) = (
+        view_uniforms.uniforms.binding(),
+        billboard_views.uniforms.binding(),
+        view_fogs.uniforms.binding(),
+        effects_meta.lights_2d_uniform.binding(),
+    ) else {
         return;
     };
 
@@ -2719,6 +4798,22 @@ pub(crate) fn prepare_resources(
                 binding: 1,
                 resource: effects_meta.sim_params_uniforms.binding().unwrap(),
             },
+            BindGroupEntry {
+                binding: 2,
+                resource: cull_meta.index_buffer().unwrap().as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: billboard_binding,
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: fog_binding,
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: lights_2d_binding,
+            },
         ],
     ));
 }
@@ -2730,10 +4825,13 @@ pub(crate) fn prepare_bind_groups(
     effect_batches: Query<(Entity, &mut EffectBatches)>,
     render_device: Res<RenderDevice>,
     dispatch_indirect_pipeline: Res<DispatchIndirectPipeline>,
+    init_dispatch_pipeline: Res<InitDispatchPipeline>,
     init_pipeline: Res<ParticlesInitPipeline>,
     update_pipeline: Res<ParticlesUpdatePipeline>,
     render_pipeline: Res<ParticlesRenderPipeline>,
     gpu_images: Res<RenderAssets<Image>>,
+    voxel_grid: Option<Res<VoxelGrid>>,
+    collision_bvh: Option<Res<CollisionBvh>>,
 ) {
     if effects_meta.spawner_buffer.is_empty() || effects_meta.spawner_buffer.buffer().is_none() {
         return;
@@ -2747,8 +4845,7 @@ pub(crate) fn prepare_bind_groups(
         if effects_meta.sim_params_bind_group.is_none() {
             effects_meta.sim_params_bind_group = Some(render_device.create_bind_group(
                 "hanabi:bind_group_sim_params",
-                &update_pipeline.sim_params_layout, /* FIXME - Shared with vfx_update, is
-                                                     * that OK? */
+                &init_pipeline.sim_params_layout, // FIXME - Shared with vfx_indirect
                 &[BindGroupEntry {
                     binding: 0,
                     resource: effects_meta.sim_params_uniforms.binding().unwrap(),
@@ -2756,6 +4853,79 @@ pub(crate) fn prepare_bind_groups(
             ));
         }
 
+        // Create the bind groups for the simulation parameters of the update pass,
+        // binding the occupancy grid and the collision BVH, and optionally the static
+        // colliders, if any, or empty ones otherwise.
+        let voxel_grid_view = voxel_grid
+            .as_ref()
+            .and_then(|grid| grid.image.as_ref())
+            .and_then(|handle| gpu_images.get(handle))
+            .map(|gpu_image| &gpu_image.texture_view)
+            .unwrap_or(&update_pipeline.dummy_voxel_grid);
+        let collision_bvh_view = collision_bvh
+            .as_ref()
+            .and_then(|bvh| bvh.image.as_ref())
+            .and_then(|handle| gpu_images.get(handle))
+            .map(|gpu_image| &gpu_image.texture_view)
+            .unwrap_or(&update_pipeline.dummy_collision_bvh);
+        // Only bind the colliders of this frame, as the buffer may be larger
+        let (static_colliders_buffer, static_colliders_size) =
+            match effects_meta.static_collider_buffer.buffer() {
+                Some(buffer) if !effects_meta.static_collider_buffer.is_empty() => (
+                    buffer.clone(),
+                    BufferSize::new(
+                        (effects_meta.static_collider_buffer.len() * size_of::<GpuStaticCollider>())
+                            as u64,
+                    ),
+                ),
+                _ => (update_pipeline.dummy_static_colliders.clone(), None),
+            };
+        let collider_binding_ids = Some((
+            voxel_grid_view.id(),
+            collision_bvh_view.id(),
+            static_colliders_buffer.id(),
+            static_colliders_size.map_or(0, |size| size.get()),
+        ));
+        if effects_meta.update_sim_params_bind_group.is_none()
+            || effects_meta.collider_binding_ids != collider_binding_ids
+        {
+            let mut entries = vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: effects_meta.sim_params_uniforms.binding().unwrap(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(voxel_grid_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(collision_bvh_view),
+                },
+            ];
+            let bind_group = render_device.create_bind_group(
+                "hanabi:bind_group_update_sim_params",
+                &update_pipeline.sim_params_layout,
+                &entries,
+            );
+            entries.push(BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &static_colliders_buffer,
+                    offset: 0,
+                    size: static_colliders_size,
+                }),
+            });
+            let static_colliders_bind_group = render_device.create_bind_group(
+                "hanabi:bind_group_update_static_colliders_sim_params",
+                &update_pipeline.static_colliders_sim_params_layout,
+                &entries,
+            );
+            effects_meta.update_sim_params_bind_group = Some(bind_group);
+            effects_meta.static_colliders_sim_params_bind_group = Some(static_colliders_bind_group);
+            effects_meta.collider_binding_ids = collider_binding_ids;
+        }
+
         // Create the bind group for the spawner parameters
         // FIXME - This is shared by init and update; should move
         // "update_pipeline.spawner_buffer_layout" out of "update_pipeline"
@@ -2771,20 +4941,69 @@ pub(crate) fn prepare_bind_groups(
         // Note: we clear effects_meta.spawner_buffer each frame in prepare_effects(),
         // so this bind group is always invalid at the minute and always needs
         // re-creation.
-        effects_meta.spawner_bind_group = Some(render_device.create_bind_group(
-            "hanabi:bind_group_spawner_buffer",
-            &update_pipeline.spawner_buffer_layout, // FIXME - Shared with init,is that OK?
-            &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::Buffer(BufferBinding {
-                    buffer: effects_meta.spawner_buffer.buffer().unwrap(),
-                    offset: 0,
-                    size: Some(
-                        NonZeroU64::new(effects_meta.spawner_buffer.aligned_size() as u64).unwrap(),
-                    ),
-                }),
-            }],
-        ));
+        effects_meta.spawner_bind_group = Some(
+            render_device.create_bind_group(
+                "hanabi:bind_group_spawner_buffer",
+                &update_pipeline.spawner_buffer_layout, // FIXME - Shared with init,is that OK?
+                &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: effects_meta.spawner_buffer.buffer().unwrap(),
+                            offset: 0,
+                            size: Some(
+                                NonZeroU64::new(effects_meta.spawner_buffer.aligned_size() as u64)
+                                    .unwrap(),
+                            ),
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: effects_meta
+                            .event_buffer
+                            .as_ref()
+                            .unwrap()
+                            .as_entire_binding(),
+                    },
+                ],
+            ),
+        );
+
+        // Create the bind group for the calculation of the indirect init dispatches,
+        // if any effect needs it. Like the spawner bind group, it needs re-creation
+        // each frame.
+        effects_meta.init_dispatch_bind_group =
+            effects_meta
+                .init_dispatch_buffer
+                .as_ref()
+                .map(|init_dispatch_buffer| {
+                    render_device.create_bind_group(
+                        "hanabi:bind_group_init_dispatch",
+                        &init_dispatch_pipeline.layout,
+                        &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: effects_meta
+                                    .spawner_buffer
+                                    .buffer()
+                                    .unwrap()
+                                    .as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: effects_meta
+                                    .event_buffer
+                                    .as_ref()
+                                    .unwrap()
+                                    .as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: init_dispatch_buffer.as_entire_binding(),
+                            },
+                        ],
+                    )
+                });
 
         // Create the bind group for the indirect dispatch of all effects
         effects_meta.dr_indirect_bind_group = Some(render_device.create_bind_group(
@@ -2851,6 +5070,89 @@ pub(crate) fn prepare_bind_groups(
                 },
             ],
         ));
+
+        // Create the bind groups of the batched update passes, if any. Like the spawner
+        // bind group, those need re-creation each frame.
+        let max_batch_size = effects_meta
+            .update_batches
+            .iter()
+            .map(|batch| batch.instances.len() as u64)
+            .max();
+        if let (Some(max_batch_size), Some(update_instance_buffer)) = (
+            max_batch_size,
+            effects_meta.update_instance_buffer.buffer().cloned(),
+        ) {
+            effects_meta.batched_spawner_bind_group = Some(
+                render_device.create_bind_group(
+                    "hanabi:bind_group_batched_spawner_buffer",
+                    &update_pipeline.batched_spawner_layout,
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: effects_meta
+                                .spawner_buffer
+                                .buffer()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: effects_meta
+                                .event_buffer
+                                .as_ref()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                    ],
+                ),
+            );
+            effects_meta.batched_render_indirect_bind_group = Some(
+                render_device.create_bind_group(
+                    "hanabi:bind_group_batched_render_indirect",
+                    &update_pipeline.batched_render_indirect_layout,
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: effects_meta
+                                .render_effect_dispatch_buffer
+                                .buffer()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: effects_meta
+                                .render_group_dispatch_buffer
+                                .buffer()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                        // The instances of each batch are bound with a dynamic offset
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::Buffer(BufferBinding {
+                                buffer: &update_instance_buffer,
+                                offset: 0,
+                                size: NonZeroU64::new(
+                                    max_batch_size * GpuUpdateInstance::min_size().get(),
+                                ),
+                            }),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: effects_meta
+                                .particle_group_buffer
+                                .buffer()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                    ],
+                ),
+            );
+        } else {
+            effects_meta.batched_spawner_bind_group = None;
+            effects_meta.batched_render_indirect_bind_group = None;
+        }
     }
 
     // Make a copy of the buffer ID before borrowing effects_meta mutably in the
@@ -2910,16 +5212,20 @@ pub(crate) fn prepare_bind_groups(
                             offset: 0,
                             size: Some(dispatch_indirect_size),
                         }),
-                    },
-                ];
-                if buffer.layout_flags().contains(LayoutFlags::LOCAL_SPACE_SIMULATION) {
-                    entries.push(BindGroupEntry {
+                    },
+                    BindGroupEntry {
                         binding: 3,
                         resource: BindingResource::Buffer(BufferBinding {
                             buffer: &spawner_buffer,
                             offset: 0,
                             size: Some(GpuSpawnerParams::min_size()),
                         }),
+                    },
+                ];
+                if let Some(trail_binding) = buffer.trail_max_binding() {
+                    entries.push(BindGroupEntry {
+                        binding: 4,
+                        resource: trail_binding,
                     });
                 }
                 trace!("Creating render bind group with {} entries (layout flags: {:?})", entries.len(), buffer.layout_flags());
@@ -3028,15 +5334,18 @@ pub(crate) fn prepare_bind_groups(
                 );
         }
 
-        // Ensure the particle texture is available as a GPU resource and create a bind
-        // group for it
-        if effect_batches
-            .layout_flags
-            .contains(LayoutFlags::PARTICLE_TEXTURE)
-        {
+        // Ensure the particle texture of each group is available as a GPU resource and
+        // create a bind group for it
+        for effect_batch in &effect_batches.group_batches {
+            if !effect_batch
+                .layout_flags
+                .contains(LayoutFlags::PARTICLE_TEXTURE)
+            {
+                continue;
+            }
             if effect_bind_groups
                 .images
-                .get(&effect_batches.image_handle.id())
+                .get(&effect_batch.image_handle.id())
                 .is_none()
             {
                 trace!(
@@ -3045,7 +5354,7 @@ pub(crate) fn prepare_bind_groups(
                 );
                 // If texture doesn't have a bind group yet from another instance of the
                 // same effect, then try to create one now
-                if let Some(gpu_image) = gpu_images.get(&effect_batches.image_handle) {
+                if let Some(gpu_image) = gpu_images.get(&effect_batch.image_handle) {
                     let bind_group = render_device.create_bind_group(
                         "hanabi:material_bind_group",
                         &render_pipeline.material_layout,
@@ -3062,23 +5371,68 @@ pub(crate) fn prepare_bind_groups(
                     );
                     effect_bind_groups
                         .images
-                        .insert(effect_batches.image_handle.id(), bind_group);
+                        .insert(effect_batch.image_handle.id(), bind_group);
                 } else {
                     // Texture is not ready; skip for now...
-                    trace!("GPU image not yet available; skipping batch for now.");
+                    trace!("GPU image not yet available; skipping group for now.");
                     continue;
                 }
             } else {
                 trace!(
                     "Image {:?} already has bind group {:?}.",
-                    effect_batches.image_handle,
+                    effect_batch.image_handle,
                     effect_bind_groups
                         .images
-                        .get(&effect_batches.image_handle.id())
+                        .get(&effect_batch.image_handle.id())
                         .unwrap()
                 );
             }
         }
+
+        // Same for the color LUT texture of each group
+        for effect_batch in &effect_batches.group_batches {
+            if !effect_batch.layout_flags.contains(LayoutFlags::COLOR_LUT) {
+                continue;
+            }
+            if !effect_batch
+                .layout_flags
+                .contains(LayoutFlags::PARTICLE_TEXTURE)
+                && effect_bind_groups.empty.is_none()
+            {
+                effect_bind_groups.empty = Some(render_device.create_bind_group(
+                    "hanabi:empty_bind_group",
+                    &render_pipeline.empty_layout,
+                    &[],
+                ));
+            }
+            if effect_bind_groups
+                .color_luts
+                .contains_key(&effect_batch.color_lut_handle.id())
+            {
+                continue;
+            }
+            if let Some(gpu_image) = gpu_images.get(&effect_batch.color_lut_handle) {
+                let bind_group = render_device.create_bind_group(
+                    "hanabi:color_lut_bind_group",
+                    &render_pipeline.color_lut_layout,
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&gpu_image.texture_view),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(&gpu_image.sampler),
+                        },
+                    ],
+                );
+                effect_bind_groups
+                    .color_luts
+                    .insert(effect_batch.color_lut_handle.id(), bind_group);
+            } else {
+                trace!("GPU color LUT image not yet available; skipping group for now.");
+            }
+        }
     }
 }
 
@@ -3086,9 +5440,15 @@ type DrawEffectsSystemState = SystemState<(
     SRes<EffectsMeta>,
     SRes<EffectBindGroups>,
     SRes<PipelineCache>,
-    SQuery<Read<ViewUniformOffset>>,
+    SQuery<(
+        Read<ViewUniformOffset>,
+        Read<BillboardViewOffset>,
+        Read<ViewFogOffset>,
+    )>,
     SQuery<Read<EffectBatches>>,
     SQuery<Read<EffectDrawBatch>>,
+    SRes<CullMeta>,
+    SRes<GpuTimingQueries>,
 )>;
 
 /// Draw function for rendering all active effects for the current frame.
@@ -3118,9 +5478,19 @@ fn draw<'w>(
     pipeline_id: CachedRenderPipelineId,
     params: &mut DrawEffectsSystemState,
 ) {
-    let (effects_meta, effect_bind_groups, pipeline_cache, views, effects, effect_draw_batches) =
-        params.get(world);
-    let view_uniform = views.get(view).unwrap();
+    let (
+        effects_meta,
+        effect_bind_groups,
+        pipeline_cache,
+        views,
+        effects,
+        effect_draw_batches,
+        cull_meta,
+        gpu_timing,
+    ) = params.get(world);
+    let Ok((view_uniform, billboard_view, view_fog)) = views.get(view) else {
+        return;
+    };
     let effects_meta = effects_meta.into_inner();
     let effect_bind_groups = effect_bind_groups.into_inner();
     let effect_draw_batch = effect_draw_batches.get(entity).unwrap();
@@ -3143,7 +5513,7 @@ fn draw<'w>(
     pass.set_bind_group(
         0,
         effects_meta.view_bind_group.as_ref().unwrap(),
-        &[view_uniform.offset],
+        &[view_uniform.offset, billboard_view.offset, view_fog.offset],
     );
 
     // Particles buffer
@@ -3157,30 +5527,25 @@ fn draw<'w>(
     assert!(spawner_buffer_aligned >= GpuSpawnerParams::min_size().get() as usize);
     let spawner_offset = spawner_base * spawner_buffer_aligned as u32;
     let dyn_uniform_indices: [u32; 2] = [dispatch_indirect_offset, spawner_offset];
-    let dyn_uniform_indices = if effect_batches
-        .layout_flags
-        .contains(LayoutFlags::LOCAL_SPACE_SIMULATION)
-    {
-        &dyn_uniform_indices
-    } else {
-        &dyn_uniform_indices[..1]
-    };
     pass.set_bind_group(
         1,
         effect_bind_groups
             .particle_render(effect_batches.buffer_index)
             .unwrap(),
-        dyn_uniform_indices,
+        &dyn_uniform_indices,
     );
 
-    // Particle texture
-    if effect_batches
+    let group_index = effect_draw_batch.group_index;
+    let effect_batch = &effect_batches.group_batches[group_index as usize];
+
+    // Particle texture of the group
+    if effect_batch
         .layout_flags
         .contains(LayoutFlags::PARTICLE_TEXTURE)
     {
         if let Some(bind_group) = effect_bind_groups
             .images
-            .get(&effect_batches.image_handle.id())
+            .get(&effect_batch.image_handle.id())
         {
             pass.set_bind_group(2, bind_group, &[]);
         } else {
@@ -3193,9 +5558,33 @@ fn draw<'w>(
         }
     }
 
+    // Color LUT texture of the group
+    if effect_batch.layout_flags.contains(LayoutFlags::COLOR_LUT) {
+        if !effect_batch
+            .layout_flags
+            .contains(LayoutFlags::PARTICLE_TEXTURE)
+        {
+            let Some(bind_group) = effect_bind_groups.empty.as_ref() else {
+                return;
+            };
+            pass.set_bind_group(2, bind_group, &[]);
+        }
+        if let Some(bind_group) = effect_bind_groups
+            .color_luts
+            .get(&effect_batch.color_lut_handle.id())
+        {
+            pass.set_bind_group(3, bind_group, &[]);
+        } else {
+            // Texture not ready; skip this drawing for now
+            trace!(
+                "Color LUT bind group not available for batch buf={}. Skipping draw call.",
+                effect_batches.buffer_index,
+            );
+            return;
+        }
+    }
+
     let render_indirect_buffer = effects_meta.render_group_dispatch_buffer.buffer().unwrap();
-    let group_index = effect_draw_batch.group_index;
-    let effect_batch = &effect_batches.group_batches[group_index as usize];
 
     let render_group_dispatch_indirect_index = effect_batches
         .dispatch_buffer_indices
@@ -3204,7 +5593,7 @@ fn draw<'w>(
         + group_index;
 
     trace!(
-        "Draw up to {} particles with {} vertices per particle for batch from buffer #{} \
+        "Draw up to {} particles with up to {} vertices per particle for batch from buffer #{} \
             (render_group_dispatch_indirect_index={:?}, group_index={}).",
         effect_batch.slice.len(),
         effects_meta.vertices.len(),
@@ -3213,11 +5602,43 @@ fn draw<'w>(
         group_index,
     );
 
-    pass.draw_indirect(
-        render_indirect_buffer,
-        render_group_dispatch_indirect_index as u64
-            * u32::from(gpu_limits.render_group_indirect_aligned_size) as u64,
-    );
+    // Particles culled per view are drawn from the list of the particles visible in
+    // the current view, with their own draw arguments.
+    let (indirect_buffer, indirect_offset) = if effect_batch
+        .layout_flags
+        .contains(LayoutFlags::CULL_PARTICLES)
+    {
+        let cull_meta = cull_meta.into_inner();
+        let (Some(slot), Some(draw_buffer)) = (
+            cull_meta.slot(effect_draw_batch.batches_entity, group_index, view),
+            cull_meta.draw_buffer(),
+        ) else {
+            trace!(
+                "No culled particles for batch buf={} in view {:?}. Skipping draw call.",
+                effect_batches.buffer_index,
+                view
+            );
+            return;
+        };
+        (draw_buffer, cull_meta.draw_offset(slot))
+    } else {
+        (
+            render_indirect_buffer,
+            render_group_dispatch_indirect_index as u64
+                * u32::from(gpu_limits.render_group_indirect_aligned_size) as u64,
+        )
+    };
+
+    let timing = gpu_timing
+        .into_inner()
+        .allocate(effect_batches.main_entity, GpuTimingPass::Render);
+    if let Some((query_set, index)) = timing {
+        pass.wgpu_pass().write_timestamp(query_set, index);
+    }
+    pass.draw_indirect(indirect_buffer, indirect_offset);
+    if let Some((query_set, index)) = timing {
+        pass.wgpu_pass().write_timestamp(query_set, index + 1);
+    }
 }
 
 #[cfg(feature = "2d")]
@@ -3283,6 +5704,27 @@ impl Draw<AlphaMask3d> for DrawEffects {
     }
 }
 
+#[cfg(feature = "3d")]
+impl Draw<AlphaMask3dDeferred> for DrawEffects {
+    fn draw<'w>(
+        &mut self,
+        world: &'w World,
+        pass: &mut TrackedRenderPass<'w>,
+        view: Entity,
+        item: &AlphaMask3dDeferred,
+    ) {
+        trace!("Draw<AlphaMask3dDeferred>: view={:?}", view);
+        draw(
+            world,
+            pass,
+            view,
+            item.entity,
+            item.pipeline_id,
+            &mut self.params,
+        );
+    }
+}
+
 /// Render node to run the simulation sub-graph once per frame.
 ///
 /// This node doesn't simulate anything by itself, but instead schedules the
@@ -3360,6 +5802,8 @@ impl Node for VfxSimulateNode {
         let effects_meta = world.resource::<EffectsMeta>();
         let effect_cache = world.resource::<EffectCache>();
         let effect_bind_groups = world.resource::<EffectBindGroups>();
+        let gpu_timing = world.resource::<GpuTimingQueries>();
+        let workgroup_size = world.resource::<SimulationWorkgroupSize>();
         // let render_queue = world.resource::<RenderQueue>();
 
         // Make sure to schedule any buffer copy from changed effects before accessing
@@ -3385,6 +5829,31 @@ impl Node for VfxSimulateNode {
                         timestamp_writes: None,
                     });
 
+            // Calculate the indirect workgroup counts of the init pass of all spawners,
+            // for effects spawning from the events of their parent effect.
+            if self
+                .effect_query
+                .iter_manual(world)
+                .any(|(_, batches)| batches.spawn_from_events)
+            {
+                if let (Some(init_dispatch_pipeline), Some(init_dispatch_bind_group)) = (
+                    &effects_meta.init_dispatch_pipeline,
+                    &effects_meta.init_dispatch_bind_group,
+                ) {
+                    const WORKGROUP_SIZE: u32 = 64;
+                    let spawner_count = effects_meta.spawner_buffer.len() as u32;
+                    let workgroup_count = spawner_count.div_ceil(WORKGROUP_SIZE);
+                    trace!(
+                        "record commands for init dispatch pipeline ({} spawners = {} workgroups)",
+                        spawner_count,
+                        workgroup_count
+                    );
+                    compute_pass.set_pipeline(init_dispatch_pipeline);
+                    compute_pass.set_bind_group(0, init_dispatch_bind_group, &[]);
+                    compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
+                }
+            }
+
             {
                 trace!("loop over effect batches...");
 
@@ -3402,38 +5871,37 @@ impl Node for VfxSimulateNode {
                     // e.g. dormant or finished effects at the cost of extra complexity.
                     // total_group_count += batches.group_batches.len() as u32;
 
-                    let Some(init_pipeline) =
-                        pipeline_cache.get_compute_pipeline(batches.init_pipeline_id)
-                    else {
-                        error!(
-                            "Failed to find init pipeline #{} for effect {:?}",
-                            batches.init_pipeline_id.id(),
-                            entity
-                        );
-                        continue;
-                    };
-
-                    // Do not dispatch any init work if there's nothing to spawn this frame
-                    let spawn_count = batches.spawn_count;
-                    if spawn_count == 0 {
+                    // Collect the init dispatches of the effect spawner, which spawns into the
+                    // first group, and of any additional emitter, whose spawners directly
+                    // follow the one of the effect.
+                    let init_dispatches = iter::once((
+                        batches.init_pipeline_id,
+                        batches.spawn_count,
+                        batches.spawner_base,
+                        0,
+                        batches.spawn_from_events,
+                    ))
+                    .chain(batches.emitter_batches.iter().enumerate().map(
+                        |(emitter_index, emitter)| {
+                            (
+                                emitter.init_pipeline_id,
+                                emitter.spawn_count,
+                                batches.spawner_base + 1 + emitter_index as u32,
+                                emitter.group_index,
+                                false,
+                            )
+                        },
+                    ))
+                    // Do not dispatch any init work if there's nothing to spawn this frame.
+                    // Spawning from events is only known on GPU, so always dispatched.
+                    .filter(|(_, spawn_count, _, _, indirect)| *spawn_count > 0 || *indirect)
+                    .collect::<Vec<_>>();
+                    if init_dispatches.is_empty() {
                         continue;
                     }
 
-                    const WORKGROUP_SIZE: u32 = 64;
-                    let workgroup_count = (spawn_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
-
                     let effect_cache_id = batches.effect_cache_id;
 
-                    // for (effect_entity, effect_slice) in effects_meta.entity_map.iter() {
-                    // Retrieve the ExtractedEffect from the entity
-                    // trace!("effect_entity={:?} effect_slice={:?}", effect_entity,
-                    // effect_slice); let effect =
-                    // self.effect_query.get_manual(world, *effect_entity).unwrap();
-
-                    // Get the slice to init
-                    // let effect_slice = effects_meta.get(&effect_entity);
-                    // let effect_group =
-                    //     &effects_meta.effect_cache.buffers()[batch.buffer_index as usize];
                     let Some(particles_init_bind_group) =
                         effect_cache.init_bind_group(effect_cache_id)
                     else {
@@ -3444,65 +5912,118 @@ impl Node for VfxSimulateNode {
                         continue;
                     };
 
-                    let spawner_base = batches.spawner_base;
-
                     let spawner_buffer_aligned = effects_meta.spawner_buffer.aligned_size();
                     assert!(spawner_buffer_aligned >= GpuSpawnerParams::min_size().get() as usize);
-                    let spawner_offset = spawner_base * spawner_buffer_aligned as u32;
 
                     let render_effect_indirect_offset = effects_meta
                         .gpu_limits
                         .render_effect_indirect_offset(render_effect_dispatch_buffer_index.0);
 
-                    let first_render_group_indirect_offset = effects_meta
-                        .gpu_limits
-                        .render_group_indirect_offset(first_render_group_dispatch_buffer_index.0);
+                    let timing = gpu_timing.allocate(batches.main_entity, GpuTimingPass::Init);
+                    if let Some((query_set, index)) = timing {
+                        compute_pass.write_timestamp(query_set, index);
+                    }
 
-                    trace!(
-                        "record commands for init pipeline of effect {:?} \
-                            (spawn {} = {} workgroups) spawner_base={} \
-                            spawner_offset={} \
-                            render_effect_indirect_offset={} \
-                            first_render_group_indirect_offset={}...",
-                        batches.handle,
-                        spawn_count,
-                        workgroup_count,
-                        spawner_base,
-                        spawner_offset,
-                        render_effect_indirect_offset,
-                        first_render_group_indirect_offset,
-                    );
+                    for (init_pipeline_id, spawn_count, spawner_index, group_index, indirect) in
+                        init_dispatches
+                    {
+                        let Some(init_pipeline) =
+                            pipeline_cache.get_compute_pipeline(init_pipeline_id)
+                        else {
+                            error!(
+                                "Failed to find init pipeline #{} for effect {:?}",
+                                init_pipeline_id.id(),
+                                entity
+                            );
+                            continue;
+                        };
+
+                        let workgroup_count = workgroup_size.workgroup_count(spawn_count);
+
+                        let spawner_offset = spawner_index * spawner_buffer_aligned as u32;
+
+                        let render_group_indirect_offset =
+                            effects_meta.gpu_limits.render_group_indirect_offset(
+                                first_render_group_dispatch_buffer_index.0 + group_index,
+                            );
 
-                    // Setup compute pass
-                    compute_pass.set_pipeline(init_pipeline);
-                    compute_pass.set_bind_group(
-                        0,
-                        effects_meta.sim_params_bind_group.as_ref().unwrap(),
-                        &[],
-                    );
-                    compute_pass.set_bind_group(1, particles_init_bind_group, &[]);
-                    compute_pass.set_bind_group(
-                        2,
-                        effects_meta.spawner_bind_group.as_ref().unwrap(),
-                        &[spawner_offset],
-                    );
-                    compute_pass.set_bind_group(
-                        3,
-                        effects_meta
-                            .init_render_indirect_bind_group
-                            .as_ref()
-                            .unwrap(),
-                        &[
-                            render_effect_indirect_offset as u32,
-                            first_render_group_indirect_offset as u32,
-                        ],
-                    );
-                    compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
-                    trace!("init compute dispatched");
+                        trace!(
+                            "record commands for init pipeline of effect {:?} \
+                                (spawn {} = {} workgroups) group_index={} spawner_index={} \
+                                spawner_offset={} \
+                                render_effect_indirect_offset={} \
+                                render_group_indirect_offset={}...",
+                            batches.handle,
+                            spawn_count,
+                            workgroup_count,
+                            group_index,
+                            spawner_index,
+                            spawner_offset,
+                            render_effect_indirect_offset,
+                            render_group_indirect_offset,
+                        );
+
+                        // Setup compute pass
+                        compute_pass.set_pipeline(init_pipeline);
+                        compute_pass.set_bind_group(
+                            0,
+                            effects_meta.sim_params_bind_group.as_ref().unwrap(),
+                            &[],
+                        );
+                        compute_pass.set_bind_group(1, particles_init_bind_group, &[]);
+                        compute_pass.set_bind_group(
+                            2,
+                            effects_meta.spawner_bind_group.as_ref().unwrap(),
+                            &[spawner_offset],
+                        );
+                        compute_pass.set_bind_group(
+                            3,
+                            effects_meta
+                                .init_render_indirect_bind_group
+                                .as_ref()
+                                .unwrap(),
+                            &[
+                                render_effect_indirect_offset as u32,
+                                render_group_indirect_offset as u32,
+                            ],
+                        );
+                        if indirect {
+                            let Some(init_dispatch_buffer) = &effects_meta.init_dispatch_buffer
+                            else {
+                                error!(
+                                    "Failed to find init dispatch buffer for effect {:?}",
+                                    entity
+                                );
+                                continue;
+                            };
+                            compute_pass.dispatch_workgroups_indirect(
+                                init_dispatch_buffer,
+                                spawner_index as u64 * INIT_DISPATCH_SIZE,
+                            );
+                        } else {
+                            compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
+                        }
+                        trace!("init compute dispatched");
+                    }
+
+                    if let Some((query_set, index)) = timing {
+                        compute_pass.write_timestamp(query_set, index + 1);
+                    }
                 }
             }
         }
 
+        // Reset the particle event counters, which were consumed by the init pass of
+        // child effects, before the update pass of parent effects counts new events.
+        // This also resets the bounds of all effects, which are accumulated again by
+        // the update pass. The bounds of the previous frame were already copied for
+        // readback.
+        if let Some(event_buffer) = &effects_meta.event_buffer {
+            render_context
+                .command_encoder()
+                .clear_buffer(event_buffer, 0, None);
+        }
+
         // Compute indirect dispatch pass
         if effects_meta.spawner_buffer.buffer().is_some()
             && !effects_meta.spawner_buffer.is_empty()
@@ -3564,8 +6085,36 @@ impl Node for VfxSimulateNode {
                         timestamp_writes: None,
                     });
 
+            // Record the update passes of instances sharing the same asset, and therefore
+            // the same pipelines and particle buffer, consecutively, so that most state
+            // changes between their dispatches can be skipped. Most of those instances
+            // are updated together by a batched update pass instead, dispatched once for
+            // all instances of the batch. The others, for example while their batched
+            // pipelines compile, still need their own dispatch, as the unbatched update
+            // shader reads the spawner of a single instance and the indirect dispatch
+            // arguments are per instance.
+            let mut sorted_batches = self.effect_query.iter_manual(world).collect::<Vec<_>>();
+            sorted_batches.sort_by_key(|(_, batches)| {
+                (
+                    batches.buffer_index,
+                    batches
+                        .update_pipeline_ids
+                        .first()
+                        .map(|id| id.id())
+                        .unwrap_or_default(),
+                )
+            });
+
+            let mut bound_static_colliders = None;
+            let mut bound_pipeline_id = None;
+            let mut bound_particles_bind_group = None;
+            let mut bound_render_indirect_bind_group = None;
+
+            // Groups dispatched by each batched update pass, once recorded
+            let mut dispatched_update_batches = vec![None; effects_meta.update_batches.len()];
+
             // Dispatch update compute jobs
-            for (entity, batches) in self.effect_query.iter_manual(world) {
+            for (entity, batches) in sorted_batches {
                 let effect_cache_id = batches.effect_cache_id;
 
                 let Some(particles_update_bind_group) =
@@ -3578,6 +6127,99 @@ impl Node for VfxSimulateNode {
                     continue;
                 };
 
+                // Record the batched update pass of the instance, if any, with the first
+                // instance of the batch. The GPU timings are not recorded per instance for
+                // batched update passes.
+                if let Some(batch_index) = batches.update_batch {
+                    let batch_index = batch_index as usize;
+                    let dispatched_groups = *dispatched_update_batches[batch_index]
+                        .get_or_insert_with(|| {
+                            let batch = &effects_meta.update_batches[batch_index];
+                            let (Some(spawner_bind_group), Some(render_indirect_bind_group)) = (
+                                &effects_meta.batched_spawner_bind_group,
+                                &effects_meta.batched_render_indirect_bind_group,
+                            ) else {
+                                error!(
+                                    "Failed to find batched update bind groups for effect {:?}",
+                                    entity
+                                );
+                                return 0;
+                            };
+                            let sim_params_bind_group =
+                                if batches.layout_flags.contains(LayoutFlags::STATIC_COLLIDERS) {
+                                    &effects_meta.static_colliders_sim_params_bind_group
+                                } else {
+                                    &effects_meta.update_sim_params_bind_group
+                                };
+                            let instance_offset =
+                                batch.first_instance * GpuUpdateInstance::min_size().get() as u32;
+
+                            let mut dispatched_groups = 0;
+                            for (group_index, (pipeline_id, workgroup_count)) in batch
+                                .pipeline_ids
+                                .iter()
+                                .zip(&batch.workgroup_counts)
+                                .enumerate()
+                            {
+                                let Some(update_pipeline) =
+                                    pipeline_cache.get_compute_pipeline(*pipeline_id)
+                                else {
+                                    error!(
+                                        "Failed to find batched update pipeline #{} for effect {:?}, group {}",
+                                        pipeline_id.id(),
+                                        entity,
+                                        group_index
+                                    );
+                                    continue;
+                                };
+
+                                trace!(
+                                    "record commands for batched update pipeline of effect {:?} \
+                                    group={} instance_count={} workgroup_count={}",
+                                    batches.handle,
+                                    group_index,
+                                    batch.instances.len(),
+                                    workgroup_count,
+                                );
+
+                                compute_pass.set_pipeline(update_pipeline);
+                                if let Some(sim_params_bind_group) = sim_params_bind_group {
+                                    compute_pass.set_bind_group(0, sim_params_bind_group, &[]);
+                                }
+                                compute_pass.set_bind_group(1, particles_update_bind_group, &[]);
+                                compute_pass.set_bind_group(2, spawner_bind_group, &[]);
+                                compute_pass.set_bind_group(
+                                    3,
+                                    render_indirect_bind_group,
+                                    &[instance_offset],
+                                );
+                                // Each workgroup row updates a different instance
+                                compute_pass.dispatch_workgroups(
+                                    *workgroup_count,
+                                    batch.instances.len() as u32,
+                                    1,
+                                );
+                                dispatched_groups |= 1 << group_index;
+                            }
+                            dispatched_groups
+                        });
+
+                    // The batched pipelines use different layouts for their last bind
+                    // groups, so the next unbatched update pass needs to re-bind them all.
+                    bound_pipeline_id = None;
+                    bound_static_colliders = None;
+                    bound_particles_bind_group = None;
+                    bound_render_indirect_bind_group = None;
+
+                    let killed_groups = batches.kill_groups & dispatched_groups;
+                    if killed_groups != 0 {
+                        effects_meta
+                            .pending_kills
+                            .confirm(batches.main_entity, killed_groups);
+                    }
+                    continue;
+                }
+
                 let first_update_group_dispatch_buffer_index = batches
                     .dispatch_buffer_indices
                     .first_update_group_dispatch_buffer_index;
@@ -3602,6 +6244,14 @@ impl Node for VfxSimulateNode {
                     continue;
                 };
 
+                let timing = gpu_timing.allocate(batches.main_entity, GpuTimingPass::Update);
+                if let Some((query_set, index)) = timing {
+                    compute_pass.write_timestamp(query_set, index);
+                }
+
+                // Groups whose particles were killed by the update passes recorded
+                let mut killed_groups = 0;
+
                 for (group_index, update_pipeline_id) in
                     batches.update_pipeline_ids.iter().enumerate()
                 {
@@ -3641,21 +6291,47 @@ impl Node for VfxSimulateNode {
                         update_group_dispatch_buffer_offset,
                     );
 
-                    // Setup compute pass
-                    // compute_pass.set_pipeline(&effect_group.update_pipeline);
-                    compute_pass.set_pipeline(update_pipeline);
-                    compute_pass.set_bind_group(
-                        0,
-                        effects_meta.sim_params_bind_group.as_ref().unwrap(),
-                        &[],
-                    );
-                    compute_pass.set_bind_group(1, particles_update_bind_group, &[]);
+                    // Setup compute pass, skipping any state already bound by the previous
+                    // dispatch. Only the spawner offset always changes between instances.
+                    // Conservatively re-bind everything when changing pipeline, as its layout
+                    // may differ.
+                    if bound_pipeline_id != Some(*update_pipeline_id) {
+                        compute_pass.set_pipeline(update_pipeline);
+                        bound_pipeline_id = Some(*update_pipeline_id);
+                        bound_static_colliders = None;
+                        bound_particles_bind_group = None;
+                        bound_render_indirect_bind_group = None;
+                    }
+                    // Only the effects colliding with the static colliders bind them
+                    let static_colliders =
+                        batches.layout_flags.contains(LayoutFlags::STATIC_COLLIDERS);
+                    if bound_static_colliders != Some(static_colliders) {
+                        let sim_params_bind_group = if static_colliders {
+                            &effects_meta.static_colliders_sim_params_bind_group
+                        } else {
+                            &effects_meta.update_sim_params_bind_group
+                        };
+                        if let Some(sim_params_bind_group) = sim_params_bind_group {
+                            compute_pass.set_bind_group(0, sim_params_bind_group, &[]);
+                        }
+                        bound_static_colliders = Some(static_colliders);
+                    }
+                    if bound_particles_bind_group != Some(particles_update_bind_group.id()) {
+                        compute_pass.set_bind_group(1, particles_update_bind_group, &[]);
+                        bound_particles_bind_group = Some(particles_update_bind_group.id());
+                    }
                     compute_pass.set_bind_group(
                         2,
                         effects_meta.spawner_bind_group.as_ref().unwrap(),
                         &[spawner_base * spawner_buffer_aligned as u32],
                     );
-                    compute_pass.set_bind_group(3, update_render_indirect_bind_group, &[]);
+                    if bound_render_indirect_bind_group
+                        != Some(update_render_indirect_bind_group.id())
+                    {
+                        compute_pass.set_bind_group(3, update_render_indirect_bind_group, &[]);
+                        bound_render_indirect_bind_group =
+                            Some(update_render_indirect_bind_group.id());
+                    }
 
                     if let Some(buffer) = effects_meta.dispatch_indirect_buffer.buffer() {
                         trace!(
@@ -3668,10 +6344,99 @@ impl Node for VfxSimulateNode {
                             update_group_dispatch_buffer_offset as u64,
                         );
                         // TODO - offset
+                        killed_groups |= batches.kill_groups & (1 << group_index);
                     }
 
                     trace!("update compute dispatched");
                 }
+
+                if killed_groups != 0 {
+                    effects_meta
+                        .pending_kills
+                        .confirm(batches.main_entity, killed_groups);
+                }
+
+                if let Some((query_set, index)) = timing {
+                    compute_pass.write_timestamp(query_set, index + 1);
+                }
+            }
+        }
+
+        // Compute cull pass, building for each view the list of the particles visible
+        // in that view, for the effects culling their particles individually
+        let cull_meta = world.resource::<CullMeta>();
+        if let (Some(cull_bind_group), Some(dispatch_buffer)) = (
+            cull_meta.bind_group(),
+            effects_meta.dispatch_indirect_buffer.buffer(),
+        ) {
+            let mut compute_pass =
+                render_context
+                    .command_encoder()
+                    .begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("hanabi:cull"),
+                        timestamp_writes: None,
+                    });
+
+            let spawner_buffer_aligned = effects_meta.spawner_buffer.aligned_size();
+            for (entity, batches) in self.effect_query.iter_manual(world) {
+                if batches.cull_pipeline_ids.is_empty() {
+                    continue;
+                }
+                let effect_cache_id = batches.effect_cache_id;
+                let (Some(particles_update_bind_group), Some(update_render_indirect_bind_group)) = (
+                    effect_cache.update_bind_group(effect_cache_id),
+                    effect_bind_groups
+                        .update_render_indirect_bind_groups
+                        .get(&effect_cache_id),
+                ) else {
+                    continue;
+                };
+
+                for (group_index, cull_pipeline_id) in batches.cull_pipeline_ids.iter().enumerate()
+                {
+                    let Some(cull_pipeline) =
+                        pipeline_cache.get_compute_pipeline(*cull_pipeline_id)
+                    else {
+                        continue;
+                    };
+
+                    // Cull with the same workgroup count as the update pass, which is an
+                    // upper bound of the number of particles alive after it.
+                    let update_group_dispatch_buffer_offset =
+                        effects_meta.gpu_limits.dispatch_indirect_offset(
+                            batches
+                                .dispatch_buffer_indices
+                                .first_update_group_dispatch_buffer_index
+                                .0
+                                + group_index as u32,
+                        );
+
+                    compute_pass.set_pipeline(cull_pipeline);
+                    compute_pass.set_bind_group(1, particles_update_bind_group, &[]);
+                    compute_pass.set_bind_group(
+                        2,
+                        effects_meta.spawner_bind_group.as_ref().unwrap(),
+                        &[batches.spawner_base * spawner_buffer_aligned as u32],
+                    );
+                    compute_pass.set_bind_group(3, update_render_indirect_bind_group, &[]);
+                    for slot in cull_meta.group_slots(entity, group_index as u32) {
+                        compute_pass.set_bind_group(
+                            0,
+                            cull_bind_group,
+                            &[cull_meta.params_offset(slot)],
+                        );
+                        compute_pass.dispatch_workgroups_indirect(
+                            dispatch_buffer,
+                            update_group_dispatch_buffer_offset as u64,
+                        );
+                    }
+
+                    trace!(
+                        "cull compute dispatched for effect {:?} group {}",
+                        entity,
+                        group_index
+                    );
+                }
             }
         }
 
@@ -3689,25 +6454,9 @@ where
     T: Pod + ShaderSize,
     I: Iterator<Item = T>,
 {
-    let mut first_buffer = None;
-    for (object_index, object) in iterator.enumerate() {
-        let buffer = buffer_table.insert(object);
-        match first_buffer {
-            None => first_buffer = Some(buffer),
-            Some(ref first_buffer) => {
-                if first_buffer.0 + object_index as u32 != buffer.0 {
-                    error!(
-                        "Allocator didn't allocate sequential indices (expected {:?}, got {:?}). \
-                        Expect trouble!",
-                        first_buffer.0 + object_index as u32,
-                        buffer.0
-                    );
-                }
-            }
-        }
-    }
-
-    first_buffer.expect("No buffers allocated")
+    let rows: Vec<T> = iterator.collect();
+    assert!(!rows.is_empty(), "No buffers allocated");
+    buffer_table.insert_contiguous(rows.into_iter())
 }
 
 #[cfg(test)]
@@ -3720,6 +6469,167 @@ mod tests {
         assert_eq!(flags, LayoutFlags::NONE);
     }
 
+    #[test]
+    fn spawner_params_offsets() {
+        // Offsets hard-coded in vfx_init_dispatch.wgsl
+        let params = GpuSpawnerParams {
+            spawn: 7,
+            parent_event: 3,
+            spawn_per_event: 2,
+            ..default()
+        };
+        let words: &[u32] = bytemuck::cast_slice(std::slice::from_ref(&params));
+        assert_eq!(words.len() * 4, GpuSpawnerParams::min_size().get() as usize);
+        assert_eq!(words[24], 7);
+        assert_eq!(words[33], 3);
+        assert_eq!(words[34], 2);
+    }
+
+    #[test]
+    fn workgroup_size() {
+        let limits = WgpuLimits::downlevel_defaults();
+        assert_eq!(
+            SimulationWorkgroupSize::new(128, &limits),
+            SimulationWorkgroupSize(128)
+        );
+        // Unsupported sizes fall back to the default
+        assert_eq!(
+            SimulationWorkgroupSize::new(0, &limits),
+            SimulationWorkgroupSize::default()
+        );
+        assert_eq!(
+            SimulationWorkgroupSize::new(limits.max_compute_invocations_per_workgroup + 1, &limits),
+            SimulationWorkgroupSize(HanabiSettings::DEFAULT_WORKGROUP_SIZE)
+        );
+
+        let size = SimulationWorkgroupSize(128);
+        assert_eq!(size.workgroup_count(0), 0);
+        assert_eq!(size.workgroup_count(1), 1);
+        assert_eq!(size.workgroup_count(128), 1);
+        assert_eq!(size.workgroup_count(129), 2);
+    }
+
+    #[test]
+    fn pending_kills() {
+        let entity = Entity::from_raw(3);
+        let mut kills = PendingKills::default();
+        assert_eq!(kills.get(entity), 0);
+
+        // Requests accumulate until confirmed, even over several frames
+        kills.request(entity, 0b001);
+        kills.flush_confirmed();
+        kills.request(entity, 0b100);
+        assert_eq!(kills.get(entity), 0b101);
+
+        // Confirmed groups are only removed once flushed, and only those
+        kills.confirm(entity, 0b001);
+        assert_eq!(kills.get(entity), 0b101);
+        kills.flush_confirmed();
+        assert_eq!(kills.get(entity), 0b100);
+        kills.confirm(entity, 0b100);
+        kills.flush_confirmed();
+        assert_eq!(kills.get(entity), 0);
+
+        kills.request(entity, 0b010);
+        kills.remove(entity);
+        assert_eq!(kills.get(entity), 0);
+    }
+
+    #[test]
+    fn parent_event_counter_index() {
+        assert_eq!(ParentEvent::Death.counter_index(), 0);
+        assert_eq!(ParentEvent::Collision.counter_index(), 1);
+        assert!(ParentEvent::Collision.counter_index() < ParentEvent::COUNT);
+    }
+
+    #[test]
+    fn event_slots() {
+        let parent0 = Entity::from_raw(0);
+        let parent1 = Entity::from_raw(1);
+        let removed = Entity::from_raw(2);
+
+        // Two children of the same parent share its counters, and parents which
+        // aren't extracted don't get any.
+        let children = [
+            EffectParent::new(parent0),
+            EffectParent::new(parent1).with_event(ParentEvent::Collision),
+            EffectParent::new(parent0).with_event(ParentEvent::Collision),
+            EffectParent::new(removed),
+        ];
+        let event_slots = allocate_event_slots(children, |entity| entity != removed);
+        assert_eq!(event_slots.len(), 2);
+        assert!(!event_slots.contains_key(&removed));
+        let mut slots: Vec<_> = event_slots.values().copied().collect();
+        slots.sort_unstable();
+        assert_eq!(slots, vec![0, ParentEvent::COUNT]);
+
+        // Each child reads the counter of its event among the ones of its parent
+        let slot0 = event_slots[&parent0];
+        let slot1 = event_slots[&parent1];
+        assert_eq!(
+            parent_event_counter(&event_slots, &children[0]),
+            Some(slot0)
+        );
+        assert_eq!(
+            parent_event_counter(&event_slots, &children[1]),
+            Some(slot1 + 1)
+        );
+        assert_eq!(
+            parent_event_counter(&event_slots, &children[2]),
+            Some(slot0 + 1)
+        );
+        assert_eq!(parent_event_counter(&event_slots, &children[3]), None);
+
+        // The bounds are stored after the counters, aligned to a bounds slot
+        assert_eq!(bounds_slot_base(0), 1);
+        assert_eq!(bounds_slot_base(2), 1);
+        assert_eq!(bounds_slot_base(BOUNDS_WORDS / ParentEvent::COUNT + 1), 2);
+
+        // Once the first parent is removed, the other parent reuses the first slot
+        let event_slots =
+            allocate_event_slots(children, |entity| entity != removed && entity != parent0);
+        assert_eq!(event_slots.len(), 1);
+        assert_eq!(event_slots[&parent1], 0);
+        assert_eq!(parent_event_counter(&event_slots, &children[0]), None);
+        assert_eq!(parent_event_counter(&event_slots, &children[1]), Some(1));
+    }
+
+    #[test]
+    fn storage_buffer_counts() {
+        assert_eq!(
+            effect_storage_buffer_counts(false, false, LayoutFlags::NONE),
+            (7, 10)
+        );
+        assert_eq!(
+            effect_storage_buffer_counts(true, true, LayoutFlags::TRAILS),
+            (10, 13)
+        );
+
+        // Only the update pass binds the static colliders, in group 0 which the cull
+        // pass replaces
+        assert_eq!(
+            effect_storage_buffer_counts(true, false, LayoutFlags::STATIC_COLLIDERS),
+            (9, 11)
+        );
+
+        // The update shader only declares the static colliders when they're bound. It
+        // also declares the 4 buffers of groups 2 and 3 again for the batched update
+        // pass, which additionally binds the per-instance data and the particle groups.
+        let update_source = include_str!("vfx_update.wgsl");
+        assert_eq!(
+            update_source.matches("var<storage").count() as u32,
+            BASE_STORAGE_BUFFER_COUNT + 1 + 4 + BATCHED_UPDATE_STORAGE_BUFFER_COUNT
+        );
+        assert!(update_source.contains(
+            "#ifdef STATIC_COLLIDERS\n@group(0) @binding(3) var<storage, read> static_colliders"
+        ));
+        let init_source = include_str!("vfx_init.wgsl");
+        assert_eq!(
+            init_source.matches("var<storage").count() as u32,
+            BASE_STORAGE_BUFFER_COUNT
+        );
+    }
+
     #[cfg(feature = "gpu_tests")]
     #[test]
     fn gpu_limits() {
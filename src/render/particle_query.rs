@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+            Buffer, BufferBinding, BufferBindingType, BufferInitDescriptor, BufferSize,
+            BufferUsages, CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor,
+            ComputePipeline, PipelineLayoutDescriptor, RawComputePipelineDescriptor,
+            ShaderModuleDescriptor, ShaderSource, ShaderStages,
+        },
+        renderer::RenderDevice,
+    },
+};
+use bytemuck::Pod;
+
+use super::{EffectCache, EffectsMeta};
+use crate::{Attribute, ParticleLayout};
+
+/// Workgroup size of the particle query shaders.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Pipelines of a compute shader querying the alive particles of some groups,
+/// like the pick and track shaders.
+///
+/// All the query shaders share the same bind group layout. The parameters of
+/// the query of a group are bound at `@binding(0)`, and the result buffer
+/// shared by all groups at `@binding(5)`. The other bindings, and the helpers
+/// to access the alive particles of a group, are declared in
+/// `vfx_particle_query.wgsl`, which is prepended to the shader source.
+pub(crate) struct ParticleQueryPipelines {
+    /// Layout of the single bind group of all the pipelines.
+    layout: BindGroupLayout,
+    /// Pipelines, dispatched in order for all the groups queried.
+    pipelines: Vec<ComputePipeline>,
+    /// Size of the parameters of the query of a group, in bytes.
+    params_size: BufferSize,
+}
+
+impl ParticleQueryPipelines {
+    /// Create the pipelines of the given entry points of a query shader.
+    ///
+    /// The `name` is used in the labels of the GPU objects, and `params_size`
+    /// is the size of the parameters bound at `@binding(0)`.
+    pub fn new(
+        render_device: &RenderDevice,
+        name: &str,
+        params_size: BufferSize,
+        source: &str,
+        entry_points: &[&str],
+    ) -> Self {
+        let storage_entry =
+            |binding: u32, read_only: bool, min_binding_size| BindGroupLayoutEntry {
+                binding,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size,
+                },
+                count: None,
+            };
+        let word = BufferSize::new(4);
+        let layout = render_device.create_bind_group_layout(
+            &*format!("hanabi:bind_group_layout:{}", name),
+            &[
+                // @binding(0) var<storage, read> params : <query parameters>
+                storage_entry(0, true, Some(params_size)),
+                // @binding(1) var<storage, read> particle_buffer : array<u32>
+                storage_entry(1, true, word),
+                // @binding(2) var<storage, read> indirect_buffer : array<u32>
+                storage_entry(2, true, word),
+                // @binding(3) var<storage, read> render_group_buffer : array<u32>
+                storage_entry(3, true, word),
+                // @binding(4) var<storage, read> render_effect_buffer : array<u32>
+                storage_entry(4, true, word),
+                // @binding(5) var<storage, read_write> result_buffer : <query results>
+                storage_entry(5, false, word),
+            ],
+        );
+
+        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&format!("hanabi:pipeline_layout:{}", name)),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = render_device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&format!("hanabi:vfx_{}_shader", name)),
+            source: ShaderSource::Wgsl(Cow::Owned(shader_source(source))),
+        });
+
+        let pipelines = entry_points
+            .iter()
+            .map(|entry_point| {
+                render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+                    label: Some(&format!("hanabi:compute_pipeline:{}", entry_point)),
+                    layout: Some(&pipeline_layout),
+                    module: &shader_module,
+                    entry_point,
+                })
+            })
+            .collect();
+
+        Self {
+            layout,
+            pipelines,
+            params_size,
+        }
+    }
+
+    /// Record the dispatch of all the pipelines for the given queries, writing
+    /// into the result buffer.
+    ///
+    /// Each query is made of the group queried, the number of threads to
+    /// dispatch for it, and its parameters. Returns `None` if the render
+    /// group and render effect buffers are not allocated yet.
+    pub fn dispatch<P: Pod>(
+        &self,
+        render_device: &RenderDevice,
+        effects_meta: &EffectsMeta,
+        effect_cache: &EffectCache,
+        queries: &[(QueryGroup, u32, P)],
+        result_buffer: &Buffer,
+    ) -> Option<CommandEncoder> {
+        let render_group_buffer = effects_meta.render_group_dispatch_buffer.buffer()?;
+        let render_effect_buffer = effects_meta.render_effect_dispatch_buffer.buffer()?;
+
+        // Pack the parameters of each group at the storage buffer offset alignment
+        let alignment = render_device.limits().min_storage_buffer_offset_alignment as usize;
+        let params_size = self.params_size.get() as usize;
+        let params_stride = params_size.next_multiple_of(alignment);
+        let mut params_data = vec![0u8; queries.len() * params_stride];
+        for (index, (_, _, params)) in queries.iter().enumerate() {
+            params_data[index * params_stride..index * params_stride + params_size]
+                .copy_from_slice(bytemuck::bytes_of(params));
+        }
+        let params_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("hanabi:buffer:particle_query_params"),
+            contents: &params_data,
+            usage: BufferUsages::STORAGE,
+        });
+
+        let bind_groups: Vec<_> = queries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (group, thread_count, _))| {
+                let effect_buffer = effect_cache.buffers()[group.buffer_index as usize].as_ref()?;
+                let bind_group = render_device.create_bind_group(
+                    "hanabi:bind_group_particle_query",
+                    &self.layout,
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::Buffer(BufferBinding {
+                                buffer: &params_buffer,
+                                offset: (index * params_stride) as u64,
+                                size: Some(self.params_size),
+                            }),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: effect_buffer.particle_buffer().as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: effect_buffer.indirect_buffer().as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: render_group_buffer.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 4,
+                            resource: render_effect_buffer.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 5,
+                            resource: result_buffer.as_entire_binding(),
+                        },
+                    ],
+                );
+                Some((bind_group, thread_count.div_ceil(WORKGROUP_SIZE)))
+            })
+            .collect();
+
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("hanabi:particle_query"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("hanabi:particle_query"),
+                timestamp_writes: None,
+            });
+            for pipeline in &self.pipelines {
+                compute_pass.set_pipeline(pipeline);
+                for (bind_group, workgroup_count) in &bind_groups {
+                    compute_pass.set_bind_group(0, bind_group, &[]);
+                    compute_pass.dispatch_workgroups(*workgroup_count, 1, 1);
+                }
+            }
+        }
+        Some(encoder)
+    }
+}
+
+/// Group of an allocated effect instance whose alive particles are queried.
+pub(crate) struct QueryGroup {
+    /// Index of the effect buffer storing the particles of the effect.
+    pub buffer_index: u32,
+    /// Index of the group in the effect.
+    pub group_index: u32,
+    /// Index of the first particle of the effect in the particle buffer.
+    pub effect_start: u32,
+    /// Capacity of the group.
+    pub capacity: u32,
+    /// Size of a single particle, in words.
+    pub particle_stride: u32,
+    /// Offset of the position attribute in a particle, in words.
+    pub position_offset: u32,
+    /// Offset of the render group indirect arguments of the group, in words.
+    pub render_group_word: u32,
+    /// Offset of the render effect metadata of the effect, in words.
+    pub render_effect_word: u32,
+    /// Particle layout of the effect.
+    particle_layout: ParticleLayout,
+}
+
+impl QueryGroup {
+    /// Collect the groups of an effect instance, if allocated.
+    ///
+    /// Returns an empty list if the effect is not allocated, or if its
+    /// particles don't have a position.
+    pub fn of_effect(
+        effects_meta: &EffectsMeta,
+        effect_cache: &EffectCache,
+        entity: Entity,
+    ) -> Vec<QueryGroup> {
+        let Some(entry) = effects_meta.entity_map.get(&entity) else {
+            return vec![];
+        };
+        let render_group_row_words =
+            effects_meta.render_group_dispatch_buffer.aligned_size() as u32 / 4;
+        let render_effect_row_words =
+            effects_meta.render_effect_dispatch_buffer.aligned_size() as u32 / 4;
+        let effect_slices = effect_cache.get_slices(entry.cache_id);
+        let Some(position_offset) =
+            attribute_offset(&effect_slices.particle_layout, Attribute::POSITION)
+        else {
+            return vec![];
+        };
+        let dispatch_buffer_indices = effect_cache.get_dispatch_buffer_indices(entry.cache_id);
+        effect_slices
+            .slices
+            .windows(2)
+            .enumerate()
+            .map(|(group_index, group)| QueryGroup {
+                buffer_index: effect_slices.buffer_index,
+                group_index: group_index as u32,
+                effect_start: effect_slices.slices[0],
+                capacity: group[1] - group[0],
+                particle_stride: effect_slices.particle_layout.min_binding_size().get() as u32 / 4,
+                position_offset,
+                render_group_word: (dispatch_buffer_indices
+                    .first_render_group_dispatch_buffer_index
+                    .0
+                    + group_index as u32)
+                    * render_group_row_words,
+                render_effect_word: dispatch_buffer_indices
+                    .render_effect_metadata_buffer_index
+                    .0
+                    * render_effect_row_words,
+                particle_layout: effect_slices.particle_layout.clone(),
+            })
+            .collect()
+    }
+
+    /// Get the offset of an attribute in a particle, in words, or `u32::MAX`
+    /// if the particles don't have that attribute.
+    pub fn offset_of(&self, attribute: Attribute) -> u32 {
+        attribute_offset(&self.particle_layout, attribute).unwrap_or(u32::MAX)
+    }
+}
+
+/// Prepend the common bindings and helpers of `vfx_particle_query.wgsl` to the
+/// source of a query shader.
+fn shader_source(source: &str) -> String {
+    format!("{}\n{}", include_str!("vfx_particle_query.wgsl"), source)
+}
+
+/// Get the offset of an attribute in a particle, in words.
+fn attribute_offset(layout: &ParticleLayout, attribute: Attribute) -> Option<u32> {
+    layout
+        .attributes()
+        .iter()
+        .find(|attr_layout| attr_layout.attribute == attribute)
+        .map(|attr_layout| attr_layout.offset / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_shaders() {
+        for (source, entry_points) in [
+            (
+                include_str!("vfx_pick.wgsl"),
+                &["pick_distance", "pick_index", "pick_position"][..],
+            ),
+            (include_str!("vfx_track.wgsl"), &["track"][..]),
+        ] {
+            let module = naga::front::wgsl::parse_str(&shader_source(source)).unwrap();
+            naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all(),
+                naga::valid::Capabilities::default(),
+            )
+            .validate(&module)
+            .unwrap();
+
+            // The workgroup size must match the one the dispatches are sized for
+            for name in entry_points {
+                let entry_point = module
+                    .entry_points
+                    .iter()
+                    .find(|entry_point| entry_point.name == *name)
+                    .unwrap();
+                assert_eq!(entry_point.workgroup_size, [WORKGROUP_SIZE, 1, 1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{BufferInitDescriptor, BufferUsages, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use super::{
+    particle_query::{ParticleQueryPipelines, QueryGroup},
+    readback::{ReadbackPoll, StagingReadback},
+    EffectCache, EffectsMeta, ReadbackChannel,
+};
+use crate::{
+    pick::PickReport, Attribute, CompiledParticleEffect, ParticlePickable, ParticlePicking,
+};
+
+/// Number of words of a single entry of the result buffer.
+const RESULT_WORDS: usize = 5;
+
+/// Parameters of the picking of the particles of a single group.
+///
+/// This is the GPU representation of the `PickParams` struct of
+/// `vfx_pick.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuPickParams {
+    /// Origin of the ray, in simulation space.
+    pub origin: Vec3,
+    /// Minimum radius of the sphere around each particle.
+    pub radius: f32,
+    /// Normalized direction of the ray, in simulation space.
+    pub direction: Vec3,
+    /// Offset of the size attribute in a particle, in words, or `u32::MAX`.
+    pub size_offset: u32,
+    /// Size of a single particle, in words.
+    pub particle_stride: u32,
+    /// Offset of the position attribute in a particle, in words.
+    pub position_offset: u32,
+    /// Offset of the render group indirect arguments of the group, in words.
+    pub render_group_word: u32,
+    /// Offset of the render effect metadata of the effect, in words.
+    pub render_effect_word: u32,
+    /// Index of the entry in the result buffer.
+    pub result_index: u32,
+    pub __pad: [u32; 3],
+}
+
+/// Pick ray of an effect instance with a [`ParticlePickable`] component.
+struct PickRequest {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Origin of the ray, in simulation space.
+    origin: Vec3,
+    /// Normalized direction of the ray, in simulation space.
+    direction: Vec3,
+    /// Minimum radius of the sphere around each particle.
+    radius: f32,
+}
+
+/// Group of an effect instance whose particles are being picked.
+struct PickGroup {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Index of the group in the effect.
+    group_index: u32,
+    /// Index of the first particle of the effect in the particle buffer.
+    effect_start: u32,
+}
+
+/// Render world resource picking the particles of the effect instances with a
+/// [`ParticlePickable`] along the ray of the [`ParticlePicking`] resource.
+///
+/// The pick shaders run at the end of the frame, once per group, and write the
+/// nearest particle hit into a small result buffer, which is read back with a
+/// [`StagingReadback`] and sent to the main world via a [`ReadbackChannel`].
+#[derive(Resource)]
+pub(crate) struct PickReadback {
+    /// Pipelines finding the smallest distance of the particles hit, then the
+    /// smallest index of the particles hit at that distance, then writing the
+    /// position of the particle picked.
+    pipelines: ParticleQueryPipelines,
+    /// Pick rays of the effects, extracted this frame.
+    requests: Vec<PickRequest>,
+    /// Readback of the result buffer, with the groups picked in the order of
+    /// its entries.
+    staging: StagingReadback<Vec<PickGroup>>,
+}
+
+impl FromWorld for PickReadback {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        Self {
+            pipelines: ParticleQueryPipelines::new(
+                render_device,
+                "pick",
+                GpuPickParams::min_size(),
+                include_str!("vfx_pick.wgsl"),
+                &["pick_distance", "pick_index", "pick_position"],
+            ),
+            requests: vec![],
+            staging: default(),
+        }
+    }
+}
+
+impl PickReadback {
+    /// Parse the mapped staging buffer into pick reports.
+    fn parse(data: &[u8], groups: &[PickGroup]) -> Vec<PickReport> {
+        let words: &[u32] = bytemuck::cast_slice(data);
+        groups
+            .iter()
+            .zip(words.chunks_exact(RESULT_WORDS))
+            .filter(|(_, result)| result[0] != u32::MAX)
+            .map(|(group, result)| PickReport {
+                entity: group.entity,
+                group_index: group.group_index,
+                particle_index: result[1] - group.effect_start,
+                position: Vec3::new(
+                    f32::from_bits(result[2]),
+                    f32::from_bits(result[3]),
+                    f32::from_bits(result[4]),
+                ),
+            })
+            .collect()
+    }
+}
+
+/// Extract the pick ray of the effect instances with a [`ParticlePickable`],
+/// converted into their simulation space.
+pub(crate) fn extract_pick_requests(
+    mut readback: ResMut<PickReadback>,
+    picking: Extract<Option<Res<ParticlePicking>>>,
+    effects: Extract<
+        Query<(
+            Entity,
+            &ParticlePickable,
+            &CompiledParticleEffect,
+            Option<&GlobalTransform>,
+        )>,
+    >,
+) {
+    readback.requests.clear();
+    let Some(ray) = picking.as_ref().and_then(|picking| picking.ray) else {
+        return;
+    };
+    readback.requests.extend(effects.iter().filter_map(
+        |(entity, pickable, compiled, transform)| {
+            let world_to_simulation = compiled.simulation_to_world(transform).inverse();
+            let direction = world_to_simulation
+                .transform_vector3(*ray.direction)
+                .try_normalize()?;
+            Some(PickRequest {
+                entity,
+                origin: world_to_simulation.transform_point3(ray.origin),
+                direction,
+                radius: pickable.radius,
+            })
+        },
+    ));
+}
+
+/// Pick the particles along the pick ray, and send the particles hit to the
+/// main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn pick_particles(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    effect_cache: Res<EffectCache>,
+    channel: Res<ReadbackChannel<Vec<PickReport>>>,
+    mut readback: ResMut<PickReadback>,
+) {
+    // Complete the pick in flight, if any
+    match readback.staging.poll(&render_device, |data, groups| {
+        PickReadback::parse(data, groups)
+    }) {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => channel.send([reports]),
+        ReadbackPoll::Failed => warn!("Failed to read back the picked particles."),
+    }
+
+    if readback.requests.is_empty() {
+        return;
+    }
+
+    // Collect the parameters of each group of the allocated effects
+    let mut groups = vec![];
+    let mut queries = vec![];
+    for request in &readback.requests {
+        for group in QueryGroup::of_effect(&effects_meta, &effect_cache, request.entity) {
+            let params = GpuPickParams {
+                origin: request.origin,
+                radius: request.radius,
+                direction: request.direction,
+                size_offset: group.offset_of(Attribute::SIZE),
+                particle_stride: group.particle_stride,
+                position_offset: group.position_offset,
+                render_group_word: group.render_group_word,
+                render_effect_word: group.render_effect_word,
+                result_index: groups.len() as u32,
+                ..default()
+            };
+            groups.push(PickGroup {
+                entity: request.entity,
+                group_index: group.group_index,
+                effect_start: group.effect_start,
+            });
+            let capacity = group.capacity;
+            queries.push((group, capacity, params));
+        }
+    }
+    if groups.is_empty() {
+        return;
+    }
+
+    let results = vec![u32::MAX; groups.len() * RESULT_WORDS];
+    let result_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("hanabi:buffer:pick_results"),
+        contents: bytemuck::cast_slice(&results),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+    });
+    let Some(mut encoder) = readback.pipelines.dispatch(
+        &render_device,
+        &effects_meta,
+        &effect_cache,
+        &queries,
+        &result_buffer,
+    ) else {
+        return;
+    };
+    let size = (results.len() * 4) as u64;
+    let buffer = readback
+        .staging
+        .reserve(&render_device, "hanabi:buffer:pick_readback", size);
+    encoder.copy_buffer_to_buffer(&result_buffer, 0, &buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    readback.staging.map(buffer, size, groups);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_params_layout() {
+        // The layout must match the PickParams struct of vfx_pick.wgsl
+        assert_eq!(GpuPickParams::min_size().get(), 64);
+        assert_eq!(std::mem::size_of::<GpuPickParams>(), 64);
+        assert_eq!(std::mem::offset_of!(GpuPickParams, direction), 16);
+        assert_eq!(std::mem::offset_of!(GpuPickParams, size_offset), 28);
+        assert_eq!(std::mem::offset_of!(GpuPickParams, result_index), 48);
+    }
+}
@@ -0,0 +1,300 @@
+use std::num::NonZeroU64;
+
+#[cfg(feature = "2d")]
+use bevy::core_pipeline::core_2d::Transparent2d;
+#[cfg(feature = "3d")]
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::{
+    prelude::*,
+    render::{
+        render_phase::RenderPhase,
+        render_resource::{
+            CachedPipelineState, PipelineCache, PipelineCacheError, SpecializedComputePipelines,
+            SpecializedRenderPipelines,
+        },
+        view::ExtractedView,
+        Extract,
+    },
+    utils::HashSet,
+};
+
+#[cfg(all(feature = "2d", feature = "3d"))]
+use super::PipelineMode;
+use super::{
+    effect_storage_buffer_counts, EffectsMeta, LayoutFlags, ParticleInitPipelineKey,
+    ParticleRenderPipelineKey, ParticleUpdatePipelineKey, ParticlesInitPipeline,
+    ParticlesRenderPipeline, ParticlesUpdatePipeline, ReadbackChannel,
+};
+use crate::{
+    inject::injected_particle_stride,
+    precompile::{PrecompileReport, PrecompileRequest, PrecompileState},
+    EffectPrecompiler,
+};
+
+/// Requests of the effect assets whose pipelines are being pre-compiled,
+/// extracted each frame from the [`EffectPrecompiler`].
+#[derive(Default, Resource)]
+pub(crate) struct PrecompileQueue {
+    requests: Vec<PrecompileRequest>,
+}
+
+/// Extract the requests of the [`EffectPrecompiler`] into the render world.
+pub(crate) fn extract_precompile_requests(
+    precompiler: Extract<Res<EffectPrecompiler>>,
+    mut queue: ResMut<PrecompileQueue>,
+) {
+    queue.requests.clear();
+    queue.requests.extend(precompiler.requests().cloned());
+}
+
+/// Get the combined state of a set of pipelines, or `None` if some are still
+/// being compiled.
+fn combined_state<'a>(
+    states: impl IntoIterator<Item = &'a CachedPipelineState>,
+) -> Option<PrecompileState> {
+    let mut ready = true;
+    for state in states {
+        match state {
+            CachedPipelineState::Ok(_) => {}
+            // Those errors are transient, and the pipeline cache retries later
+            CachedPipelineState::Err(
+                PipelineCacheError::ShaderNotLoaded(_)
+                | PipelineCacheError::ShaderImportNotYetAvailable,
+            ) => ready = false,
+            CachedPipelineState::Err(_) => return Some(PrecompileState::Failed),
+            _ => ready = false,
+        }
+    }
+    ready.then_some(PrecompileState::Ready)
+}
+
+/// Specialize the pipelines of the effect assets registered with the
+/// [`EffectPrecompiler`], and report to the main world the assets whose
+/// pipelines are all compiled.
+///
+/// The pipelines are specialized with the same keys as the ones used by the
+/// effect instances, so the instances spawned later reuse them. The render
+/// pipelines are specialized for each kind of view currently rendering
+/// effects. Specializing a pipeline already specialized is cheap, so this is
+/// repeated each frame until the pipeline cache finished compiling them.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn precompile_effects(
+    queue: Res<PrecompileQueue>,
+    channel: Res<ReadbackChannel<PrecompileReport>>,
+    effects_meta: Res<EffectsMeta>,
+    pipeline_cache: Res<PipelineCache>,
+    init_pipeline: Res<ParticlesInitPipeline>,
+    update_pipeline: Res<ParticlesUpdatePipeline>,
+    render_pipeline: Res<ParticlesRenderPipeline>,
+    mut specialized_init_pipelines: ResMut<SpecializedComputePipelines<ParticlesInitPipeline>>,
+    mut specialized_update_pipelines: ResMut<SpecializedComputePipelines<ParticlesUpdatePipeline>>,
+    mut specialized_render_pipelines: ResMut<SpecializedRenderPipelines<ParticlesRenderPipeline>>,
+    #[cfg(feature = "2d")] views_2d: Query<&ExtractedView, With<RenderPhase<Transparent2d>>>,
+    #[cfg(feature = "3d")] views_3d: Query<&ExtractedView, With<RenderPhase<Transparent3d>>>,
+    msaa: Res<Msaa>,
+) {
+    if queue.requests.is_empty() {
+        return;
+    }
+
+    // Collect the kinds of views to specialize the render pipelines for
+    #[cfg(all(feature = "2d", feature = "3d"))]
+    let mut views: HashSet<(PipelineMode, bool)> = views_2d
+        .iter()
+        .map(|view| (PipelineMode::Camera2d, view.hdr))
+        .chain(
+            views_3d
+                .iter()
+                .map(|view| (PipelineMode::Camera3d, view.hdr)),
+        )
+        .collect();
+    #[cfg(all(feature = "2d", not(feature = "3d")))]
+    let mut views: HashSet<((), bool)> = views_2d.iter().map(|view| ((), view.hdr)).collect();
+    #[cfg(all(feature = "3d", not(feature = "2d")))]
+    let mut views: HashSet<((), bool)> = views_3d.iter().map(|view| ((), view.hdr)).collect();
+    if views.is_empty() {
+        #[cfg(all(feature = "2d", feature = "3d"))]
+        views.insert((PipelineMode::Camera3d, false));
+        #[cfg(not(all(feature = "2d", feature = "3d")))]
+        views.insert(((), false));
+    }
+
+    let max_storage_buffers = effects_meta
+        .gpu_limits
+        .max_storage_buffers_per_shader_stage();
+
+    for request in &queue.requests {
+        // Apply the same device limits as when simulating an instance
+        let (storage_buffer_count, cull_storage_buffer_count) = effect_storage_buffer_counts(
+            !request.property_layout.is_empty(),
+            request.has_injection,
+            request.layout_flags,
+        );
+        if storage_buffer_count > max_storage_buffers {
+            error!(
+                "Effect asset {:?} requires {} storage buffers per shader stage, but the GPU device only supports {}. Its pipelines cannot be compiled.",
+                request.id, storage_buffer_count, max_storage_buffers
+            );
+            channel.send([PrecompileReport {
+                id: request.id,
+                state: PrecompileState::Failed,
+            }]);
+            continue;
+        }
+        let cull = request.layout_flags.contains(LayoutFlags::CULL_PARTICLES)
+            && cull_storage_buffer_count <= max_storage_buffers;
+
+        let injection_min_binding_size = request
+            .has_injection
+            .then(|| NonZeroU64::new(injected_particle_stride(&request.particle_layout)).unwrap());
+        let trails = request.layout_flags.contains(LayoutFlags::TRAILS);
+        let static_colliders = request.layout_flags.contains(LayoutFlags::STATIC_COLLIDERS);
+
+        let mut compute_ids = Vec::new();
+        for shader in std::iter::once(&request.effect_shader.init)
+            .chain(request.effect_shader.emitter_init.iter())
+        {
+            compute_ids.push(specialized_init_pipelines.specialize(
+                &pipeline_cache,
+                &init_pipeline,
+                ParticleInitPipelineKey {
+                    shader: shader.clone(),
+                    particle_layout_min_binding_size: request.particle_layout.min_binding_size(),
+                    property_layout_min_binding_size: if request.property_layout.is_empty() {
+                        None
+                    } else {
+                        Some(request.property_layout.min_binding_size())
+                    },
+                    injection_min_binding_size,
+                    trails,
+                },
+            ));
+        }
+        for (group_index, shader) in request.effect_shader.update.iter().enumerate() {
+            for cull in [false, true].into_iter().take(1 + usize::from(cull)) {
+                compute_ids.push(specialized_update_pipelines.specialize(
+                    &pipeline_cache,
+                    &update_pipeline,
+                    ParticleUpdatePipelineKey {
+                        shader: shader.clone(),
+                        particle_layout: request.particle_layout.clone(),
+                        property_layout: request.property_layout.clone(),
+                        injection_min_binding_size,
+                        trails,
+                        static_colliders,
+                        cull,
+                        // Instances fall back to unbatched update passes until their
+                        // batched pipelines are compiled, so those never stall.
+                        batched: false,
+                        group_index: group_index as u32,
+                    },
+                ));
+            }
+        }
+
+        let mut render_ids = Vec::new();
+        for (shader, group_flags) in request
+            .effect_shader
+            .render
+            .iter()
+            .zip(request.group_layout_flags.iter())
+        {
+            #[allow(unused_variables)]
+            for &(pipeline_mode, hdr) in &views {
+                render_ids.push(specialized_render_pipelines.specialize(
+                    &pipeline_cache,
+                    &render_pipeline,
+                    ParticleRenderPipelineKey {
+                        shader: shader.clone(),
+                        particle_layout: request.particle_layout.clone(),
+                        has_image: group_flags.contains(LayoutFlags::PARTICLE_TEXTURE),
+                        has_color_lut: group_flags.contains(LayoutFlags::COLOR_LUT),
+                        local_space_simulation:
+                            group_flags.contains(LayoutFlags::LOCAL_SPACE_SIMULATION),
+                        use_alpha_mask: group_flags.contains(LayoutFlags::USE_ALPHA_MASK),
+                        flipbook: group_flags.contains(LayoutFlags::FLIPBOOK),
+                        needs_uv: group_flags.contains(LayoutFlags::NEEDS_UV),
+                        trails: group_flags.contains(LayoutFlags::TRAILS),
+                        cull_particles: cull && group_flags.contains(LayoutFlags::CULL_PARTICLES),
+                        overdraw: group_flags.contains(LayoutFlags::OVERDRAW),
+                        fog: group_flags.contains(LayoutFlags::FOG),
+                        blob_shadow: group_flags.contains(LayoutFlags::BLOB_SHADOW),
+                        // Pipelines of the deferred prepass depend on the prepass
+                        // textures of each view, so are not precompiled
+                        deferred_prepass: false,
+                        normal_prepass: false,
+                        motion_vector_prepass: false,
+                        lighting_2d: group_flags.contains(LayoutFlags::LIGHTING_2D),
+                        // The pipelines of the 2D layers split by a ZLayer2dModifier
+                        // are compiled on first use
+                        z_layer_2d: None,
+                        #[cfg(all(feature = "2d", feature = "3d"))]
+                        pipeline_mode,
+                        msaa_samples: msaa.samples(),
+                        hdr,
+                    },
+                ));
+            }
+        }
+
+        let state = combined_state(
+            compute_ids
+                .iter()
+                .map(|id| pipeline_cache.get_compute_pipeline_state(*id))
+                .chain(
+                    render_ids
+                        .iter()
+                        .map(|id| pipeline_cache.get_render_pipeline_state(*id)),
+                ),
+        );
+        match state {
+            Some(PrecompileState::Failed) => {
+                error!(
+                    "Failed to pre-compile the pipelines of effect asset {:?}.",
+                    request.id
+                );
+                channel.send([PrecompileReport {
+                    id: request.id,
+                    state: PrecompileState::Failed,
+                }]);
+            }
+            Some(state) => {
+                debug!(
+                    "Pre-compiled the pipelines of effect asset {:?}.",
+                    request.id
+                );
+                channel.send([PrecompileReport {
+                    id: request.id,
+                    state,
+                }]);
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::Shader;
+
+    use super::*;
+
+    #[test]
+    fn combined_state() {
+        let queued = CachedPipelineState::Queued;
+        let not_loaded = CachedPipelineState::Err(PipelineCacheError::ShaderNotLoaded(AssetId::<
+            Shader,
+        >::default(
+        )));
+        let failed =
+            CachedPipelineState::Err(PipelineCacheError::CreateShaderModule("error".into()));
+
+        assert_eq!(super::combined_state([]), Some(PrecompileState::Ready));
+        assert_eq!(super::combined_state([&queued]), None);
+        assert_eq!(super::combined_state([&not_loaded]), None);
+        assert_eq!(
+            super::combined_state([&queued, &failed]),
+            Some(PrecompileState::Failed)
+        );
+    }
+}
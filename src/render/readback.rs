@@ -0,0 +1,903 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use bevy::{
+    core::FrameCount,
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CachedPipelineState, CommandEncoderDescriptor,
+            Maintain, MapMode, PipelineCache, PipelineDescriptor, ShaderType,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+
+use super::{
+    batch::{EffectBatches, EffectDrawBatch},
+    EffectCache, EffectsMeta, GpuRenderGroupIndirect, SimParams, BOUNDS_SIZE, IMPULSE_SIZE,
+    INDIRECT_INDEX_SIZE,
+};
+use crate::{
+    bounds::{decode_bounds, BoundsReport},
+    capacity::{
+        CapacityDiagnostics, EffectStats, GroupOccupancy, HanabiStats, OccupancyReport,
+        RenderStatsReport,
+    },
+    capture::{decode_particle, CaptureReport, ParticleCapture},
+    feedback::{decode_impulse, ImpulseReport},
+    ParticleLayout,
+};
+
+/// Channel sending some reports from the render world to the main world.
+///
+/// The render world sends the reports once read back from GPU, and the main
+/// world takes them all at once, typically in a system consuming them each
+/// frame. Each type of report has its own channel, inserted as a resource
+/// shared by the main and render worlds.
+#[derive(Debug, Resource)]
+pub(crate) struct ReadbackChannel<T: Send + Sync + 'static>(Arc<Mutex<Vec<T>>>);
+
+impl<T: Send + Sync + 'static> Default for ReadbackChannel<T> {
+    fn default() -> Self {
+        Self(default())
+    }
+}
+
+impl<T: Send + Sync + 'static> Clone for ReadbackChannel<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Send + Sync + 'static> ReadbackChannel<T> {
+    /// Send some reports to the main world.
+    pub fn send(&self, reports: impl IntoIterator<Item = T>) {
+        self.0.lock().unwrap().extend(reports);
+    }
+
+    /// Take all reports sent since the last call.
+    pub fn take(&self) -> Vec<T> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// The buffer is still being mapped.
+const MAP_PENDING: u32 = 0;
+/// The buffer is mapped and ready to be read.
+const MAP_READY: u32 = 1;
+/// Mapping the buffer failed.
+const MAP_FAILED: u32 = 2;
+
+/// Outcome of [`StagingReadback::poll()`].
+pub(crate) enum ReadbackPoll<R> {
+    /// No readback is in flight.
+    Idle,
+    /// The staging buffer is still being mapped.
+    Pending,
+    /// The staging buffer was mapped and parsed, and can be reused.
+    Ready(R),
+    /// Mapping the staging buffer failed.
+    Failed,
+}
+
+/// Readback in flight of a [`StagingReadback`].
+struct PendingMap<T> {
+    /// Data needed to parse the content of the staging buffer.
+    info: T,
+    /// Staging buffer being mapped.
+    buffer: Buffer,
+    /// Size of the copied data, in bytes.
+    size: u64,
+    /// Mapping state, one of `MAP_PENDING`, `MAP_READY`, or `MAP_FAILED`.
+    state: Arc<AtomicU32>,
+}
+
+/// Asynchronous readback of some GPU data through a staging buffer.
+///
+/// The data is copied at the end of the frame into a staging buffer, which is
+/// then mapped asynchronously and polled on the following frames. Only one
+/// readback is in flight at any time; a new one is issued only once the
+/// previous one completed. The `T` parameter is the data, captured when the
+/// readback is issued, needed to parse the content of the staging buffer.
+pub(crate) struct StagingReadback<T> {
+    /// Staging buffer reused by the readbacks, grown on demand.
+    buffer: Option<Buffer>,
+    /// Size of the staging buffer, in bytes.
+    buffer_size: u64,
+    /// Readback in flight, if any.
+    pending: Option<PendingMap<T>>,
+}
+
+impl<T> Default for StagingReadback<T> {
+    fn default() -> Self {
+        Self {
+            buffer: None,
+            buffer_size: 0,
+            pending: None,
+        }
+    }
+}
+
+impl<T> StagingReadback<T> {
+    /// Check if a readback is in flight.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Get a staging buffer of at least `size` bytes, (re-)allocating it if
+    /// needed.
+    pub fn reserve(&mut self, render_device: &RenderDevice, label: &str, size: u64) -> Buffer {
+        if self.buffer_size < size {
+            self.buffer = Some(create_staging_buffer(render_device, label, size));
+            self.buffer_size = size;
+        }
+        self.buffer.clone().unwrap()
+    }
+
+    /// Start mapping the first `size` bytes of a staging buffer, once the
+    /// commands copying the data into it were submitted.
+    pub fn map(&mut self, buffer: Buffer, size: u64, info: T) {
+        let state = Arc::new(AtomicU32::new(MAP_PENDING));
+        let map_state = state.clone();
+        buffer
+            .slice(..size)
+            .map_async(MapMode::Read, move |result| {
+                let state = if result.is_ok() {
+                    MAP_READY
+                } else {
+                    MAP_FAILED
+                };
+                map_state.store(state, Ordering::Release);
+            });
+        self.pending = Some(PendingMap {
+            info,
+            buffer,
+            size,
+            state,
+        });
+    }
+
+    /// Poll the readback in flight, if any, and parse the staging buffer once
+    /// mapped.
+    pub fn poll<R>(
+        &mut self,
+        render_device: &RenderDevice,
+        parse: impl FnOnce(&[u8], &T) -> R,
+    ) -> ReadbackPoll<R> {
+        let Some(pending) = self.pending.as_ref() else {
+            return ReadbackPoll::Idle;
+        };
+        render_device.poll(Maintain::Poll);
+
+        let result = match pending.state.load(Ordering::Acquire) {
+            MAP_PENDING => return ReadbackPoll::Pending,
+            MAP_READY => {
+                let result = {
+                    let data = pending.buffer.slice(..pending.size).get_mapped_range();
+                    parse(&data, &pending.info)
+                };
+                pending.buffer.unmap();
+                ReadbackPoll::Ready(result)
+            }
+            _ => ReadbackPoll::Failed,
+        };
+        self.pending = None;
+        result
+    }
+}
+
+/// Create a staging buffer the GPU data is copied into, to be mapped by a
+/// [`StagingReadback`].
+pub(crate) fn create_staging_buffer(
+    render_device: &RenderDevice,
+    label: &str,
+    size: u64,
+) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Effect instance whose group occupancy is being read back.
+struct ReadbackEffect {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Row of the first group of the effect in the render group indirect
+    /// buffer.
+    first_row: u32,
+    /// Capacity of each group.
+    capacities: Vec<u32>,
+}
+
+/// Readback currently in flight.
+struct PendingReadback {
+    /// Effects being read back.
+    effects: Vec<ReadbackEffect>,
+    /// Size of a single row of the render group indirect buffer, in bytes.
+    row_size: usize,
+    /// Frame the readback was issued.
+    frame: u32,
+}
+
+/// Render world resource reading back the occupancy of all particle groups.
+///
+/// The alive and spawned counts of each group are copied at the end of the
+/// frame from the render group indirect buffer into a [`StagingReadback`], and
+/// sent to the main world via a [`ReadbackChannel`] once mapped.
+///
+/// The readback runs if either the [`CapacityDiagnostics`] are enabled, any
+/// effect instance has an [`EffectStats`] component, or the [`HanabiStats`]
+/// resource exists.
+///
+/// [`EffectStats`]: crate::EffectStats
+/// [`HanabiStats`]: crate::HanabiStats
+#[derive(Default, Resource)]
+pub(crate) struct OccupancyReadback {
+    /// Whether any effect instance requested its [`EffectStats`], or the
+    /// [`HanabiStats`] were requested, this frame.
+    ///
+    /// [`EffectStats`]: crate::EffectStats
+    /// [`HanabiStats`]: crate::HanabiStats
+    stats_requested: bool,
+    /// Readback of the render group indirect buffer.
+    staging: StagingReadback<PendingReadback>,
+}
+
+impl OccupancyReadback {
+    /// Parse the mapped staging buffer into occupancy reports.
+    fn parse(data: &[u8], pending: &PendingReadback) -> Vec<OccupancyReport> {
+        let item_size = GpuRenderGroupIndirect::min_size().get() as usize;
+        pending
+            .effects
+            .iter()
+            .map(|effect| {
+                let indirects: Vec<GpuRenderGroupIndirect> = (0..effect.capacities.len())
+                    .map(|group_index| {
+                        let offset = (effect.first_row as usize + group_index) * pending.row_size;
+                        bytemuck::pod_read_unaligned(&data[offset..offset + item_size])
+                    })
+                    .collect();
+                OccupancyReport {
+                    entity: effect.entity,
+                    groups: effect
+                        .capacities
+                        .iter()
+                        .zip(&indirects)
+                        .map(|(&capacity, indirect)| GroupOccupancy {
+                            capacity,
+                            alive_count: indirect.alive_count,
+                        })
+                        .collect(),
+                    spawned_counts: indirects.iter().map(|i| i.spawned_count).collect(),
+                    dropped_counts: indirects.iter().map(|i| i.dropped_count).collect(),
+                    frame: pending.frame,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Check if any effect instance requests its [`EffectStats`], or if the
+/// [`HanabiStats`] are requested, which are read back along the group
+/// occupancy.
+///
+/// [`EffectStats`]: crate::EffectStats
+/// [`HanabiStats`]: crate::HanabiStats
+pub(crate) fn extract_stats_requests(
+    mut readback: ResMut<OccupancyReadback>,
+    stats: Extract<Query<(), With<EffectStats>>>,
+    hanabi_stats: Extract<Option<Res<HanabiStats>>>,
+) {
+    readback.stats_requested = !stats.is_empty() || hanabi_stats.is_some();
+}
+
+/// Send the render statistics of this frame to the main world, for the
+/// [`HanabiStats`].
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, before the render
+/// entities are cleared.
+///
+/// [`HanabiStats`]: crate::HanabiStats
+pub(crate) fn report_render_stats(
+    effect_cache: Res<EffectCache>,
+    batches: Query<(), With<EffectBatches>>,
+    draw_batches: Query<(), With<EffectDrawBatch>>,
+    pipeline_cache: Res<PipelineCache>,
+    channel: Res<ReadbackChannel<RenderStatsReport>>,
+) {
+    // All the pipelines of Hanabi have a label starting with "hanabi:"
+    let pipeline_count = pipeline_cache
+        .pipelines()
+        .filter(|pipeline| matches!(pipeline.state, CachedPipelineState::Ok(_)))
+        .filter(|pipeline| {
+            let label = match &pipeline.descriptor {
+                PipelineDescriptor::RenderPipelineDescriptor(desc) => desc.label.as_ref(),
+                PipelineDescriptor::ComputePipelineDescriptor(desc) => desc.label.as_ref(),
+            };
+            label.is_some_and(|label| label.starts_with("hanabi:"))
+        })
+        .count();
+    channel.send([RenderStatsReport {
+        batch_count: batches.iter().count() as u32,
+        draw_batch_count: draw_batches.iter().count() as u32,
+        buffer_memory: effect_cache.memory_size(),
+        pipeline_count: pipeline_count as u32,
+    }]);
+}
+
+/// Read back the occupancy of all particle groups, and send it to the main
+/// world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn readback_group_occupancy(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    frame_count: Option<Res<FrameCount>>,
+    diagnostics: Option<Res<CapacityDiagnostics>>,
+    effects_meta: Res<EffectsMeta>,
+    effect_cache: Res<EffectCache>,
+    channel: Res<ReadbackChannel<OccupancyReport>>,
+    mut readback: ResMut<OccupancyReadback>,
+) {
+    // Complete the readback in flight, if any
+    match readback
+        .staging
+        .poll(&render_device, OccupancyReadback::parse)
+    {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => {
+            trace!("Read back occupancy of {} effects.", reports.len());
+            channel.send(reports);
+        }
+        ReadbackPoll::Failed => warn!("Failed to read back the occupancy of particle groups."),
+    }
+
+    if !readback.stats_requested && !diagnostics.is_some_and(|diagnostics| diagnostics.enabled) {
+        return;
+    }
+    let Some(source) = effects_meta.render_group_dispatch_buffer.buffer() else {
+        return;
+    };
+
+    // Gather the rows of all allocated effects
+    let effects: Vec<_> = effects_meta
+        .entity_map
+        .iter()
+        .map(|(entity, entry)| {
+            let slices = effect_cache.get_slices(entry.cache_id).slices;
+            let capacities = slices.windows(2).map(|range| range[1] - range[0]).collect();
+            let first_row = effect_cache
+                .get_dispatch_buffer_indices(entry.cache_id)
+                .first_render_group_dispatch_buffer_index
+                .0;
+            ReadbackEffect {
+                entity: *entity,
+                first_row,
+                capacities,
+            }
+        })
+        .collect();
+    let Some(row_count) = effects
+        .iter()
+        .map(|effect| effect.first_row + effect.capacities.len() as u32)
+        .max()
+    else {
+        return;
+    };
+    let row_size = effects_meta.render_group_dispatch_buffer.aligned_size();
+    let size = row_count as u64 * row_size as u64;
+    let buffer = readback
+        .staging
+        .reserve(&render_device, "hanabi:buffer:occupancy_readback", size);
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:occupancy_readback"),
+    });
+    encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    readback.staging.map(
+        buffer,
+        size,
+        PendingReadback {
+            effects,
+            row_size,
+            frame: frame_count
+                .map(|frame_count| frame_count.0)
+                .unwrap_or_default(),
+        },
+    );
+}
+
+/// Render world resource reading back the bounds of the effects computing
+/// them on GPU.
+///
+/// The bounds are copied at the end of the frame from the bounds buffer into a
+/// [`StagingReadback`]. Like for the [`OccupancyReadback`], the result is sent
+/// to the main world via a [`ReadbackChannel`].
+#[derive(Default, Resource)]
+pub(crate) struct BoundsReadback {
+    /// Readback of the bounds buffer, with the entities of the effects owning
+    /// each slot of the copied bounds.
+    staging: StagingReadback<Vec<Entity>>,
+}
+
+impl BoundsReadback {
+    /// Parse the mapped staging buffer into bounds reports.
+    fn parse(data: &[u8], entities: &[Entity]) -> Vec<BoundsReport> {
+        let slot_size = BOUNDS_SIZE as usize;
+        entities
+            .iter()
+            .enumerate()
+            .map(|(slot, entity)| {
+                let offset = slot * slot_size;
+                let words: [u32; 8] =
+                    bytemuck::pod_read_unaligned(&data[offset..offset + slot_size]);
+                BoundsReport {
+                    entity: *entity,
+                    bounds: decode_bounds(&words),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Read back the bounds of the effects computing them on GPU, and send them to
+/// the main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn readback_bounds(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    channel: Res<ReadbackChannel<BoundsReport>>,
+    mut readback: ResMut<BoundsReadback>,
+) {
+    // Complete the readback in flight, if any
+    match readback.staging.poll(&render_device, |data, entities| {
+        BoundsReadback::parse(data, entities)
+    }) {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => {
+            trace!("Read back bounds of {} effects.", reports.len());
+            channel.send(reports);
+        }
+        ReadbackPoll::Failed => warn!("Failed to read back the bounds of effects."),
+    }
+
+    if effects_meta.bounds_entities.is_empty() {
+        return;
+    }
+    let Some(source) = effects_meta.event_buffer.as_ref() else {
+        return;
+    };
+    let entities = effects_meta.bounds_entities.clone();
+    let size = entities.len() as u64 * BOUNDS_SIZE;
+    let buffer = readback
+        .staging
+        .reserve(&render_device, "hanabi:buffer:bounds_readback", size);
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:bounds_readback"),
+    });
+    encoder.copy_buffer_to_buffer(source, effects_meta.bounds_offset, &buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    readback.staging.map(buffer, size, entities);
+}
+
+/// Impulse readback currently in flight.
+struct PendingImpulseReadback {
+    /// Entities of the effects owning each slot of the copied impulses.
+    entities: Vec<Entity>,
+    /// Simulation delta time of the frame the impulses were accumulated.
+    delta_time: f32,
+}
+
+/// Render world resource reading back the impulses accumulated by the force
+/// receivers of the effects.
+///
+/// This works like the [`BoundsReadback`], with only one readback in flight at
+/// any time, and sends the result to the main world via the
+/// [`ReadbackChannel`].
+#[derive(Default, Resource)]
+pub(crate) struct ImpulseReadback {
+    /// Readback of the impulses.
+    staging: StagingReadback<PendingImpulseReadback>,
+}
+
+impl ImpulseReadback {
+    /// Parse the mapped staging buffer into impulse reports.
+    fn parse(data: &[u8], pending: &PendingImpulseReadback) -> Vec<ImpulseReport> {
+        let slot_size = IMPULSE_SIZE as usize;
+        pending
+            .entities
+            .iter()
+            .enumerate()
+            .map(|(slot, entity)| {
+                let offset = slot * slot_size;
+                let words: [u32; 4] =
+                    bytemuck::pod_read_unaligned(&data[offset..offset + slot_size]);
+                ImpulseReport {
+                    entity: *entity,
+                    impulse: decode_impulse(&words),
+                    delta_time: pending.delta_time,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Read back the impulses accumulated by the force receivers of the effects,
+/// and send them to the main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn readback_impulses(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    sim_params: Res<SimParams>,
+    channel: Res<ReadbackChannel<ImpulseReport>>,
+    mut readback: ResMut<ImpulseReadback>,
+) {
+    // Complete the readback in flight, if any
+    match readback
+        .staging
+        .poll(&render_device, ImpulseReadback::parse)
+    {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => {
+            trace!("Read back impulses of {} effects.", reports.len());
+            channel.send(reports);
+        }
+        ReadbackPoll::Failed => warn!("Failed to read back the impulses of effects."),
+    }
+
+    if effects_meta.feedback_entities.is_empty() {
+        return;
+    }
+    let Some(source) = effects_meta.event_buffer.as_ref() else {
+        return;
+    };
+    let entities = effects_meta.feedback_entities.clone();
+    let size = entities.len() as u64 * IMPULSE_SIZE;
+    let buffer = readback
+        .staging
+        .reserve(&render_device, "hanabi:buffer:impulse_readback", size);
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:impulse_readback"),
+    });
+    encoder.copy_buffer_to_buffer(source, effects_meta.feedback_offset, &buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    readback.staging.map(
+        buffer,
+        size,
+        PendingImpulseReadback {
+            entities,
+            delta_time: sim_params.delta_time,
+        },
+    );
+}
+
+/// Effect instance whose particles are being captured.
+struct CaptureEffect {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Slices of the groups of the effect in the particle buffer, in items.
+    slices: Vec<u32>,
+    /// Particle layout of the effect.
+    particle_layout: ParticleLayout,
+    /// Offset of the rows of the render group indirect buffer in the staging
+    /// buffer, in bytes.
+    rows_offset: usize,
+    /// Offset of the particles in the staging buffer, in bytes.
+    particles_offset: usize,
+    /// Offset of the indirect indices in the staging buffer, in bytes.
+    indices_offset: usize,
+}
+
+/// Particle capture currently in flight.
+struct PendingCaptureReadback {
+    /// Effects being captured.
+    effects: Vec<CaptureEffect>,
+    /// Size of a single row of the render group indirect buffer, in bytes.
+    row_size: usize,
+}
+
+/// Render world resource reading back the particles of the effect instances
+/// with a [`ParticleCapture`].
+///
+/// For each requested effect, the particles and the indirect indices of all
+/// groups are copied at the end of the frame into a staging buffer, along with
+/// the render group indirect rows holding the size of the dead lists. The alive
+/// particles are the ones not referenced by any dead list. Captures are rare
+/// and meant for tests, so unlike the other readbacks the staging buffer is
+/// allocated for each capture instead of being reused. The result is sent to
+/// the main world via the [`ReadbackChannel`].
+#[derive(Default, Resource)]
+pub(crate) struct CaptureReadback {
+    /// Entities of the effects whose capture was requested this frame.
+    requests: Vec<Entity>,
+    /// Capture in flight, if any.
+    staging: StagingReadback<PendingCaptureReadback>,
+}
+
+impl CaptureReadback {
+    /// Parse the mapped staging buffer into capture reports.
+    fn parse(data: &[u8], pending: &PendingCaptureReadback) -> Vec<CaptureReport> {
+        let item_size = GpuRenderGroupIndirect::min_size().get() as usize;
+        let index_size = INDIRECT_INDEX_SIZE as usize;
+        pending
+            .effects
+            .iter()
+            .map(|effect| {
+                let start = effect.slices[0] as usize;
+                let count = *effect.slices.last().unwrap() as usize - start;
+
+                // Flag the particles referenced by the dead list of any group. Like in
+                // the update pass, the dead list of a group starts at the first slot of
+                // the group.
+                let mut dead = vec![false; count];
+                for (group_index, &group_start) in
+                    effect.slices[..effect.slices.len() - 1].iter().enumerate()
+                {
+                    let offset = effect.rows_offset + group_index * pending.row_size;
+                    let indirect: GpuRenderGroupIndirect =
+                        bytemuck::pod_read_unaligned(&data[offset..offset + item_size]);
+                    // The indices were copied from the start of the effect, not of the
+                    // buffer, so rebase the slots of the group onto the staging data.
+                    let first_slot = (group_start - effect.slices[0]) as usize;
+                    for slot in first_slot..first_slot + indirect.dead_count as usize {
+                        if slot >= count {
+                            break;
+                        }
+                        let offset = effect.indices_offset + slot * index_size + 8;
+                        let index: u32 = bytemuck::pod_read_unaligned(&data[offset..offset + 4]);
+                        if let Some(flag) = (index as usize)
+                            .checked_sub(start)
+                            .and_then(|index| dead.get_mut(index))
+                        {
+                            *flag = true;
+                        }
+                    }
+                }
+
+                let particle_size = effect.particle_layout.min_binding_size().get() as usize;
+                let particles = dead
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &dead)| !dead)
+                    .map(|(index, _)| {
+                        let offset = effect.particles_offset + index * particle_size;
+                        decode_particle(
+                            &effect.particle_layout,
+                            &data[offset..offset + particle_size],
+                        )
+                    })
+                    .collect();
+                CaptureReport {
+                    entity: effect.entity,
+                    particles,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Extract the effect instances whose [`ParticleCapture`] is waiting for a
+/// readback.
+pub(crate) fn extract_capture_requests(
+    mut readback: ResMut<CaptureReadback>,
+    captures: Extract<Query<(Entity, &ParticleCapture)>>,
+) {
+    readback.requests = captures
+        .iter()
+        .filter(|(_, capture)| capture.is_requested())
+        .map(|(entity, _)| entity)
+        .collect();
+}
+
+/// Read back the particles of the effect instances with a [`ParticleCapture`],
+/// and send them to the main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn readback_particles(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    effect_cache: Res<EffectCache>,
+    channel: Res<ReadbackChannel<CaptureReport>>,
+    mut readback: ResMut<CaptureReadback>,
+) {
+    // Complete the capture in flight, if any. Don't issue a new one this frame,
+    // as the main world didn't see the result yet and still requests it.
+    match readback
+        .staging
+        .poll(&render_device, CaptureReadback::parse)
+    {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => {
+            trace!("Captured particles of {} effects.", reports.len());
+            channel.send(reports);
+            return;
+        }
+        ReadbackPoll::Failed => {
+            warn!("Failed to capture the particles of effects.");
+            return;
+        }
+    }
+
+    if readback.requests.is_empty() {
+        return;
+    }
+    let Some(rows_buffer) = effects_meta.render_group_dispatch_buffer.buffer() else {
+        return;
+    };
+    let row_size = effects_meta.render_group_dispatch_buffer.aligned_size();
+
+    // Lay out the data of all the allocated effects in the staging buffer
+    let mut effects = vec![];
+    let mut size = 0;
+    for entity in &readback.requests {
+        let Some(entry) = effects_meta.entity_map.get(entity) else {
+            continue;
+        };
+        let effect_slices = effect_cache.get_slices(entry.cache_id);
+        let group_count = effect_slices.slices.len() - 1;
+        let particle_count = (effect_slices.slices[group_count] - effect_slices.slices[0]) as usize;
+        let particle_size = effect_slices.particle_layout.min_binding_size().get() as usize;
+        let rows_offset = size;
+        let particles_offset = rows_offset + group_count * row_size;
+        let indices_offset = particles_offset + particle_count * particle_size;
+        size = indices_offset + particle_count * INDIRECT_INDEX_SIZE as usize;
+        effects.push((
+            entry.cache_id,
+            effect_slices.buffer_index,
+            CaptureEffect {
+                entity: *entity,
+                slices: effect_slices.slices,
+                particle_layout: effect_slices.particle_layout,
+                rows_offset,
+                particles_offset,
+                indices_offset,
+            },
+        ));
+    }
+    if size == 0 {
+        return;
+    }
+    let size = size as u64;
+
+    let buffer = create_staging_buffer(&render_device, "hanabi:buffer:particle_capture", size);
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:particle_capture"),
+    });
+    let effects = effects
+        .into_iter()
+        .filter_map(|(cache_id, buffer_index, effect)| {
+            let effect_buffer = effect_cache.buffers()[buffer_index as usize].as_ref()?;
+            let start = effect.slices[0] as u64;
+            let end = *effect.slices.last().unwrap() as u64;
+            let group_count = effect.slices.len() as u64 - 1;
+            let particle_size = effect.particle_layout.min_binding_size().get();
+            let first_row = effect_cache
+                .get_dispatch_buffer_indices(cache_id)
+                .first_render_group_dispatch_buffer_index
+                .0 as u64;
+            encoder.copy_buffer_to_buffer(
+                rows_buffer,
+                first_row * row_size as u64,
+                &buffer,
+                effect.rows_offset as u64,
+                group_count * row_size as u64,
+            );
+            encoder.copy_buffer_to_buffer(
+                effect_buffer.particle_buffer(),
+                start * particle_size,
+                &buffer,
+                effect.particles_offset as u64,
+                (end - start) * particle_size,
+            );
+            encoder.copy_buffer_to_buffer(
+                effect_buffer.indirect_buffer(),
+                start * INDIRECT_INDEX_SIZE as u64,
+                &buffer,
+                effect.indices_offset as u64,
+                (end - start) * INDIRECT_INDEX_SIZE as u64,
+            );
+            Some(effect)
+        })
+        .collect();
+    render_queue.submit([encoder.finish()]);
+
+    readback
+        .staging
+        .map(buffer, size, PendingCaptureReadback { effects, row_size });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Attribute;
+
+    #[test]
+    fn capture_parse_offset_slices() {
+        // An effect allocated after another one in the same buffer, with a single
+        // group of 32 particles.
+        let particle_layout = ParticleLayout::new().append(Attribute::AGE).build();
+        let particle_size = particle_layout.min_binding_size().get() as usize;
+        let index_size = INDIRECT_INDEX_SIZE as usize;
+        let row_size = GpuRenderGroupIndirect::min_size().get() as usize;
+        let count = 32;
+        let rows_offset = 0;
+        let particles_offset = rows_offset + row_size;
+        let indices_offset = particles_offset + count * particle_size;
+        let mut data = vec![0u8; indices_offset + count * index_size];
+
+        // The age of each particle is its index within the effect
+        for index in 0..count {
+            let offset = particles_offset + index * particle_size;
+            data[offset..offset + 4].copy_from_slice(&(index as f32).to_le_bytes());
+        }
+
+        // The 2 first slots of the dead list reference the particles at buffer
+        // indices 40 and 33, that is the particles #8 and #1 of the effect.
+        let indirect = GpuRenderGroupIndirect {
+            dead_count: 2,
+            ..default()
+        };
+        data[rows_offset..rows_offset + row_size].copy_from_slice(bytemuck::bytes_of(&indirect));
+        for (slot, index) in [40u32, 33].into_iter().enumerate() {
+            let offset = indices_offset + slot * index_size + 8;
+            data[offset..offset + 4].copy_from_slice(&index.to_le_bytes());
+        }
+
+        let entity = Entity::from_raw(42);
+        let pending = PendingCaptureReadback {
+            effects: vec![CaptureEffect {
+                entity,
+                slices: vec![32, 64],
+                particle_layout,
+                rows_offset,
+                particles_offset,
+                indices_offset,
+            }],
+            row_size,
+        };
+        let reports = CaptureReadback::parse(&data, &pending);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].entity, entity);
+        let ages: Vec<_> = reports[0]
+            .particles
+            .iter()
+            .map(|particle| particle.age().unwrap())
+            .collect();
+        let expected: Vec<_> = (0..count)
+            .filter(|&index| index != 1 && index != 8)
+            .map(|index| index as f32)
+            .collect();
+        assert_eq!(ages, expected);
+    }
+}
@@ -1,13 +1,68 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    borrow::Cow,
+    hash::{Hash, Hasher},
+};
 
 use bevy::{
-    asset::{Assets, Handle},
+    asset::{AssetId, Assets, Handle},
     ecs::{change_detection::ResMut, system::Resource},
     log::{debug, trace},
-    render::render_resource::Shader,
+    math::UVec2,
+    render::{render_resource::Shader, texture::Image},
     utils::HashMap,
 };
 
+use crate::{
+    lod::LodShaderConfig, render::LayoutFlags, EffectAsset, EffectShader, SimulationSpace,
+};
+
+/// Shaders and associated data compiled for an [`EffectAsset`].
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledEffectShader {
+    /// Handles to the shaders of all passes.
+    pub shader: EffectShader,
+    /// Layout flags, as the union of the layout flags of all groups.
+    pub layout_flags: LayoutFlags,
+    /// Layout flags of each group.
+    pub group_layout_flags: Vec<LayoutFlags>,
+    /// Particle texture of each group, if any.
+    pub particle_textures: Vec<Option<Handle<Image>>>,
+    /// Color over lifetime LUT texture of each group, if any.
+    pub color_luts: Vec<Option<Handle<Image>>>,
+    /// Z coordinates of the 2D layers of each group, if any.
+    pub z_layers_2d: Vec<Vec<f32>>,
+}
+
+/// WGSL templates the shaders of the effects are generated from.
+///
+/// Those are built into the plugin, but can be hot-reloaded from their source
+/// files in debug builds. See [`HanabiPlugin::shader_hot_reload`].
+///
+/// [`HanabiPlugin::shader_hot_reload`]: crate::HanabiPlugin::shader_hot_reload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ShaderTemplates {
+    /// Template of the init shader, from `vfx_init.wgsl`.
+    pub init: Cow<'static, str>,
+    /// Template of the update shader, from `vfx_update.wgsl`.
+    pub update: Cow<'static, str>,
+    /// Template of the render shader, from `vfx_render.wgsl`.
+    pub render: Cow<'static, str>,
+    /// Template of the cull code appended to the update shader, from
+    /// `vfx_cull.wgsl`.
+    pub cull: Cow<'static, str>,
+}
+
+impl Default for ShaderTemplates {
+    fn default() -> Self {
+        Self {
+            init: Cow::Borrowed(include_str!("vfx_init.wgsl")),
+            update: Cow::Borrowed(include_str!("vfx_update.wgsl")),
+            render: Cow::Borrowed(include_str!("vfx_render.wgsl")),
+            cull: Cow::Borrowed(include_str!("vfx_cull.wgsl")),
+        }
+    }
+}
+
 /// Cache of baked shaders variants.
 ///
 /// Baked shader variants are shaders where the placeholders `{{PLACEHOLDER}}`
@@ -17,13 +72,59 @@ use bevy::{
 /// Shaders present in the cache are allocated [`Shader`] resources. Note that a
 /// [`Shader`] resource _may_ further be preprocessed to replace `#define`
 /// directives; to this extent, some entries may not be compilable WGSL as is.
+///
+/// The cache also remembers the shaders compiled for each [`EffectAsset`],
+/// simulation space, and LOD tier, so spawning many instances of a same effect
+/// generates its shader code only once. Since shaders are deduplicated by their
+/// code, different assets producing identical WGSL code share the same
+/// [`Shader`] resources, and therefore the same compiled pipelines.
 #[derive(Default, Resource)]
 pub struct ShaderCache {
     /// Map of allocated shader resources from their baked shader code.
     cache: HashMap<String, Handle<Shader>>,
+    /// Map of the shaders compiled for each effect asset, simulation space, LOD
+    /// tier, and flipbook sprite grid size override.
+    effects: HashMap<
+        (
+            AssetId<EffectAsset>,
+            SimulationSpace,
+            Option<LodShaderConfig>,
+            Option<UVec2>,
+        ),
+        CompiledEffectShader,
+    >,
+    /// Templates the effect shaders are generated from.
+    templates: ShaderTemplates,
+    /// Were the templates replaced since the last call to
+    /// [`take_templates_modified()`]?
+    ///
+    /// [`take_templates_modified()`]: ShaderCache::take_templates_modified
+    templates_modified: bool,
 }
 
 impl ShaderCache {
+    /// Get the templates the effect shaders are generated from.
+    pub(crate) fn templates(&self) -> &ShaderTemplates {
+        &self.templates
+    }
+
+    /// Replace the templates the effect shaders are generated from.
+    ///
+    /// This forgets the shaders compiled for all effect assets, which need to
+    /// be generated again from the new templates.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub(crate) fn set_templates(&mut self, templates: ShaderTemplates) {
+        self.templates = templates;
+        self.effects.clear();
+        self.templates_modified = true;
+    }
+
+    /// Check if the templates were replaced since the last call, and reset
+    /// the modification flag.
+    pub(crate) fn take_templates_modified(&mut self) -> bool {
+        std::mem::take(&mut self.templates_modified)
+    }
+
     /// Get an existing baked shader variant, or insert it into the cache and
     /// allocate a new [`Shader`] resource for it.
     ///
@@ -56,4 +157,140 @@ impl ShaderCache {
             handle
         }
     }
+
+    /// Get the shaders compiled for an effect asset, simulation space, LOD
+    /// tier, and flipbook sprite grid size override, if any.
+    pub(crate) fn get_effect(
+        &self,
+        id: AssetId<EffectAsset>,
+        simulation_space: SimulationSpace,
+        lod: Option<&LodShaderConfig>,
+        sprite_grid_size: Option<UVec2>,
+    ) -> Option<&CompiledEffectShader> {
+        self.effects
+            .get(&(id, simulation_space, lod.cloned(), sprite_grid_size))
+    }
+
+    /// Insert the shaders compiled for an effect asset, simulation space, LOD
+    /// tier, and flipbook sprite grid size override.
+    pub(crate) fn insert_effect(
+        &mut self,
+        id: AssetId<EffectAsset>,
+        simulation_space: SimulationSpace,
+        lod: Option<LodShaderConfig>,
+        sprite_grid_size: Option<UVec2>,
+        compiled: CompiledEffectShader,
+    ) {
+        self.effects
+            .insert((id, simulation_space, lod, sprite_grid_size), compiled);
+    }
+
+    /// Forget the shaders compiled for all variants of an effect asset, for
+    /// example because the asset was modified.
+    ///
+    /// The [`Shader`] resources themselves are kept, as they may be shared
+    /// with other assets.
+    pub(crate) fn invalidate_effect(&mut self, id: AssetId<EffectAsset>) {
+        self.effects
+            .retain(|(effect_id, _, _, _), _| *effect_id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::{system::SystemState, world::World};
+
+    use super::*;
+
+    #[test]
+    fn dedup_shaders() {
+        let mut world = World::new();
+        world.init_resource::<Assets<Shader>>();
+        let mut system_state = SystemState::<ResMut<Assets<Shader>>>::new(&mut world);
+        let mut shaders = system_state.get_mut(&mut world);
+
+        let mut cache = ShaderCache::default();
+        let a = cache.get_or_insert("a", "fn main() {}", &mut shaders);
+        let b = cache.get_or_insert("b", "fn main() {}", &mut shaders);
+        let c = cache.get_or_insert("a", "fn other() {}", &mut shaders);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(shaders.len(), 2);
+    }
+
+    #[test]
+    fn invalidate_effect() {
+        let mut cache = ShaderCache::default();
+        let id0 = AssetId::<EffectAsset>::Uuid {
+            uuid: bevy::utils::Uuid::from_u128(1),
+        };
+        let id1 = AssetId::<EffectAsset>::Uuid {
+            uuid: bevy::utils::Uuid::from_u128(2),
+        };
+        let compiled = CompiledEffectShader {
+            shader: EffectShader::default(),
+            layout_flags: LayoutFlags::NONE,
+            group_layout_flags: vec![],
+            particle_textures: vec![],
+            color_luts: vec![],
+            z_layers_2d: vec![],
+        };
+        let lod = LodShaderConfig {
+            size_scale: 0.5,
+            disabled_modifiers: vec![],
+        };
+        let global = SimulationSpace::Global;
+        let local = SimulationSpace::Local;
+        cache.insert_effect(id0, global, None, None, compiled.clone());
+        cache.insert_effect(id0, global, Some(lod.clone()), None, compiled.clone());
+        cache.insert_effect(id0, local, None, None, compiled.clone());
+        cache.insert_effect(id0, global, None, Some(UVec2::new(4, 4)), compiled.clone());
+        cache.insert_effect(id1, global, None, None, compiled);
+        assert!(cache.get_effect(id0, global, Some(&lod), None).is_some());
+        assert!(cache
+            .get_effect(id0, global, None, Some(UVec2::new(4, 4)))
+            .is_some());
+        assert!(cache
+            .get_effect(id0, global, None, Some(UVec2::new(2, 2)))
+            .is_none());
+        assert!(cache.get_effect(id0, local, Some(&lod), None).is_none());
+        assert!(cache.get_effect(id1, global, Some(&lod), None).is_none());
+
+        cache.invalidate_effect(id0);
+        assert!(cache.get_effect(id0, global, None, None).is_none());
+        assert!(cache.get_effect(id0, global, Some(&lod), None).is_none());
+        assert!(cache.get_effect(id0, local, None, None).is_none());
+        assert!(cache
+            .get_effect(id0, global, None, Some(UVec2::new(4, 4)))
+            .is_none());
+        assert!(cache.get_effect(id1, global, None, None).is_some());
+    }
+
+    #[test]
+    fn set_templates() {
+        let mut cache = ShaderCache::default();
+        assert!(!cache.take_templates_modified());
+        let id = AssetId::<EffectAsset>::Uuid {
+            uuid: bevy::utils::Uuid::from_u128(1),
+        };
+        let compiled = CompiledEffectShader {
+            shader: EffectShader::default(),
+            layout_flags: LayoutFlags::NONE,
+            group_layout_flags: vec![],
+            particle_textures: vec![],
+            color_luts: vec![],
+            z_layers_2d: vec![],
+        };
+        cache.insert_effect(id, SimulationSpace::Global, None, None, compiled);
+
+        let mut templates = cache.templates().clone();
+        templates.update = Cow::Owned(format!("// tweak\n{}", templates.update));
+        cache.set_templates(templates.clone());
+        assert_eq!(cache.templates(), &templates);
+        assert!(cache
+            .get_effect(id, SimulationSpace::Global, None, None)
+            .is_none());
+        assert!(cache.take_templates_modified());
+        assert!(!cache.take_templates_modified());
+    }
 }
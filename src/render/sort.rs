@@ -0,0 +1,550 @@
+use std::{borrow::Cow, num::NonZeroU64};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource,
+            BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize,
+            BufferUsages, ComputePass, ComputePipeline, PipelineLayoutDescriptor,
+            RawComputePipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+            ShaderType,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use super::aligned_buffer_vec::AlignedBufferVec;
+
+/// Number of bits of the keys sorted by each radix sort pass.
+const RADIX_BITS: u32 = 4;
+
+/// Number of distinct digits sorted by each radix sort pass.
+const RADIX_SIZE: u32 = 1 << RADIX_BITS;
+
+/// Workgroup size of the sort shaders, which is also the number of keys in a
+/// block.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// GPU representation of the parameters of a single radix sort pass.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+struct GpuSortParams {
+    /// Number of key/value pairs to sort.
+    count: u32,
+    /// Bit offset of the digit sorted by the pass.
+    shift: u32,
+    /// Number of blocks of [`WORKGROUP_SIZE`] keys.
+    block_count: u32,
+    /// Padding.
+    _pad: u32,
+}
+
+/// Get the number of passes needed to sort keys of `key_bits` bits.
+///
+/// The count is rounded up to an even number, so that after ping-ponging
+/// between the two sets of buffers the sorted pairs always end up back into
+/// the input buffers.
+fn pass_count(key_bits: u32) -> u32 {
+    let pass_count = key_bits.min(32).div_ceil(RADIX_BITS);
+    (pass_count + 1) & !1
+}
+
+/// Get the number of blocks of [`WORKGROUP_SIZE`] keys needed to sort `count`
+/// pairs.
+fn block_count(count: u32) -> u32 {
+    count.div_ceil(WORKGROUP_SIZE)
+}
+
+/// Compute pipelines of the GPU radix sort.
+///
+/// The radix sort sorts pairs of `u32` keys and values stored in some
+/// [`RadixSortBuffers`], in increasing key order. The sort is stable: pairs
+/// with equal keys keep their relative order. This can be used to sort particle
+/// indices by view depth, or by spatial hash cell.
+///
+/// Each pass sorts the pairs by a single digit of 4 bits of the keys, starting
+/// from the least significant one. Keys with fewer significant bits sort with
+/// fewer passes; see [`RadixSortBuffers::prepare()`].
+///
+/// The pipelines are not created by the [`HanabiPlugin`], to avoid compiling
+/// the sort shaders in applications not sorting any particle. Instead, the
+/// features sorting particles create this resource on first use with
+/// [`new()`], for example via [`World::get_resource_or_insert_with()`].
+///
+/// [`HanabiPlugin`]: crate::HanabiPlugin
+/// [`new()`]: RadixSortPipeline::new
+#[derive(Resource)]
+pub(crate) struct RadixSortPipeline {
+    /// Layout of the single bind group of all the sort pipelines.
+    layout: BindGroupLayout,
+    /// Pipeline counting the keys per digit for each block.
+    count_pipeline: ComputePipeline,
+    /// Pipeline scanning the digit histogram of all blocks.
+    scan_pipeline: ComputePipeline,
+    /// Pipeline scattering the pairs to their sorted position.
+    scatter_pipeline: ComputePipeline,
+}
+
+impl RadixSortPipeline {
+    /// Create the sort pipelines.
+    ///
+    /// This compiles the sort shaders synchronously, so should only be called
+    /// once the first effect needing the sort is prepared.
+    pub fn new(render_device: &RenderDevice) -> Self {
+        let storage_entry = |binding: u32, read_only: bool, min_size: u64| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(min_size),
+            },
+            count: None,
+        };
+        let layout = render_device.create_bind_group_layout(
+            "hanabi:bind_group_layout:radix_sort",
+            &[
+                // @binding(0) var<uniform> params : SortParams
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuSortParams::min_size()),
+                    },
+                    count: None,
+                },
+                // @binding(1) var<storage, read> src_pairs : array<vec2<u32>>
+                storage_entry(1, true, 8),
+                // @binding(2) var<storage, read_write> dst_pairs : array<vec2<u32>>
+                storage_entry(2, false, 8),
+                // @binding(3) var<storage, read_write> histogram : array<u32>
+                storage_entry(3, false, 4),
+            ],
+        );
+
+        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("hanabi:pipeline_layout:radix_sort"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = render_device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("hanabi:vfx_sort_shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("vfx_sort.wgsl"))),
+        });
+
+        let create_pipeline = |entry_point: &str| {
+            render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+                label: Some(&format!(
+                    "hanabi:compute_pipeline:radix_sort_{}",
+                    entry_point
+                )),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+
+        Self {
+            count_pipeline: create_pipeline("count"),
+            scan_pipeline: create_pipeline("scan"),
+            scatter_pipeline: create_pipeline("scatter"),
+            layout,
+        }
+    }
+
+    /// Record the commands sorting the key/value pairs of some buffers.
+    ///
+    /// The buffers must have been prepared with [`RadixSortBuffers::prepare()`]
+    /// for the current frame. Once the commands executed, the sorted pairs are
+    /// stored in [`RadixSortBuffers::pairs()`].
+    pub fn dispatch<'a>(
+        &'a self,
+        compute_pass: &mut ComputePass<'a>,
+        buffers: &'a RadixSortBuffers,
+    ) {
+        let Some(bind_groups) = &buffers.bind_groups else {
+            return;
+        };
+        if buffers.count == 0 {
+            return;
+        }
+
+        let block_count = block_count(buffers.count);
+        for pass in 0..buffers.pass_count {
+            let params_offset = pass * buffers.params.aligned_size() as u32;
+            compute_pass.set_bind_group(0, &bind_groups[(pass & 1) as usize], &[params_offset]);
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch_workgroups(block_count, 1, 1);
+            compute_pass.set_pipeline(&self.scan_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+            compute_pass.set_pipeline(&self.scatter_pipeline);
+            compute_pass.dispatch_workgroups(block_count, 1, 1);
+        }
+    }
+}
+
+/// GPU buffers storing the key/value pairs sorted by a [`RadixSortPipeline`].
+///
+/// The pairs to sort are written into [`pairs()`], either from the CPU or by
+/// some compute shader, then sorted in place on GPU. An additional buffer of
+/// the same size is allocated internally to ping-pong between passes.
+///
+/// [`pairs()`]: RadixSortBuffers::pairs
+pub(crate) struct RadixSortBuffers {
+    /// Maximum number of pairs the buffers can store.
+    capacity: u32,
+    /// Pair buffers. The first one holds the input and sorted pairs.
+    pairs: [Buffer; 2],
+    /// Digit histogram of all blocks.
+    histogram: Buffer,
+    /// Parameters of each pass.
+    params: AlignedBufferVec<GpuSortParams>,
+    /// Bind groups of even and odd passes, or `None` if not prepared yet.
+    bind_groups: Option<[BindGroup; 2]>,
+    /// Number of pairs sorted by the last [`prepare()`] call.
+    ///
+    /// [`prepare()`]: RadixSortBuffers::prepare
+    count: u32,
+    /// Number of passes of the sort.
+    pass_count: u32,
+}
+
+impl RadixSortBuffers {
+    /// Allocate some buffers to sort up to `capacity` key/value pairs.
+    pub fn new(render_device: &RenderDevice, capacity: u32, label: &str) -> Self {
+        let capacity = capacity.max(1);
+        let create_buffer = |name: &str, size: u64| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(&format!("hanabi:buffer:{}_{}", label, name)),
+                size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let size = capacity as u64 * 8;
+        let histogram_size = (RADIX_SIZE * block_count(capacity)) as u64 * 4;
+        let uniform_align = render_device.limits().min_uniform_buffer_offset_alignment;
+        Self {
+            capacity,
+            pairs: [
+                create_buffer("pairs", size),
+                create_buffer("pairs_tmp", size),
+            ],
+            histogram: create_buffer("histogram", histogram_size),
+            params: AlignedBufferVec::new(
+                BufferUsages::UNIFORM,
+                NonZeroU64::new(uniform_align as u64),
+                Some(format!("hanabi:buffer:{}_params", label)),
+            ),
+            bind_groups: None,
+            count: 0,
+            pass_count: 0,
+        }
+    }
+
+    /// Maximum number of key/value pairs the buffers can sort.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Buffer of the key/value pairs to sort, which contains the pairs in
+    /// sorted key order once sorted.
+    ///
+    /// Each pair is a `vec2<u32>`, with the key in `x` and the value in `y`.
+    pub fn pairs(&self) -> &Buffer {
+        &self.pairs[0]
+    }
+
+    /// Prepare sorting the first `count` pairs, by the `key_bits` least
+    /// significant bits of their keys.
+    ///
+    /// The count is capped to the capacity of the buffers. Restricting the
+    /// number of key bits reduces the number of passes; for example depth keys
+    /// quantized to 16 bits sort in 4 passes instead of 8.
+    ///
+    /// This needs to be called once per frame before
+    /// [`RadixSortPipeline::dispatch()`], and uploads the sort parameters to
+    /// GPU.
+    pub fn prepare(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        pipeline: &RadixSortPipeline,
+        count: u32,
+        key_bits: u32,
+    ) {
+        self.count = count.min(self.capacity);
+        self.pass_count = pass_count(key_bits);
+
+        self.params.clear();
+        let block_count = block_count(self.count);
+        for pass in 0..self.pass_count {
+            self.params.push(GpuSortParams {
+                count: self.count,
+                shift: pass * RADIX_BITS,
+                block_count,
+                _pad: 0,
+            });
+        }
+        let reallocated = self.params.write_buffer(render_device, render_queue);
+
+        if reallocated || self.bind_groups.is_none() {
+            let Some(params_buffer) = self.params.buffer() else {
+                return;
+            };
+            let params_size = NonZeroU64::new(GpuSortParams::min_size().get());
+            let create_bind_group = |src: usize, dst: usize| {
+                render_device.create_bind_group(
+                    "hanabi:bind_group:radix_sort",
+                    &pipeline.layout,
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::Buffer(BufferBinding {
+                                buffer: params_buffer,
+                                offset: 0,
+                                size: params_size,
+                            }),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: self.pairs[src].as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: self.pairs[dst].as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: self.histogram.as_entire_binding(),
+                        },
+                    ],
+                )
+            };
+            self.bind_groups = Some([create_bind_group(0, 1), create_bind_group(1, 0)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CPU version of the GPU radix sort, processing the same blocks in the
+    /// same order as the shaders, to validate the algorithm.
+    pub(super) fn cpu_radix_sort(keys: &mut Vec<u32>, values: &mut Vec<u32>, key_bits: u32) {
+        let count = keys.len() as u32;
+        let block_count = block_count(count);
+        let size = WORKGROUP_SIZE as usize;
+        for pass in 0..pass_count(key_bits) {
+            let shift = pass * RADIX_BITS;
+            let digit_of = |key: u32| ((key >> shift) & (RADIX_SIZE - 1)) as usize;
+
+            // count
+            let mut histogram = vec![0u32; (RADIX_SIZE * block_count) as usize];
+            for (block, chunk) in keys.chunks(size).enumerate() {
+                for &key in chunk {
+                    histogram[digit_of(key) * block_count as usize + block] += 1;
+                }
+            }
+
+            // scan
+            let mut sum = 0;
+            for value in histogram.iter_mut() {
+                let count = *value;
+                *value = sum;
+                sum += count;
+            }
+
+            // scatter
+            let mut dst_keys = vec![0; keys.len()];
+            let mut dst_values = vec![0; values.len()];
+            for (block, (key_chunk, value_chunk)) in
+                keys.chunks(size).zip(values.chunks(size)).enumerate()
+            {
+                for (lid, (&key, &value)) in key_chunk.iter().zip(value_chunk).enumerate() {
+                    let digit = digit_of(key);
+                    let rank = key_chunk[..lid]
+                        .iter()
+                        .filter(|&&k| digit_of(k) == digit)
+                        .count();
+                    let dst = histogram[digit * block_count as usize + block] as usize + rank;
+                    dst_keys[dst] = key;
+                    dst_values[dst] = value;
+                }
+            }
+            *keys = dst_keys;
+            *values = dst_values;
+        }
+    }
+
+    #[test]
+    fn passes() {
+        assert_eq!(pass_count(0), 0);
+        assert_eq!(pass_count(4), 2);
+        assert_eq!(pass_count(16), 4);
+        assert_eq!(pass_count(20), 6);
+        assert_eq!(pass_count(32), 8);
+        assert_eq!(pass_count(64), 8);
+
+        assert_eq!(block_count(0), 0);
+        assert_eq!(block_count(1), 1);
+        assert_eq!(block_count(256), 1);
+        assert_eq!(block_count(257), 2);
+    }
+
+    #[test]
+    fn sort() {
+        // Pseudo-random keys spanning several blocks, with many duplicates
+        let count = 1000u32;
+        let mut keys: Vec<u32> = (0..count)
+            .map(|i| i.wrapping_mul(2654435761) % 97)
+            .collect();
+        let mut values: Vec<u32> = (0..count).collect();
+
+        let mut expected: Vec<(u32, u32)> =
+            keys.iter().copied().zip(values.iter().copied()).collect();
+        expected.sort_by_key(|&(key, _)| key);
+
+        cpu_radix_sort(&mut keys, &mut values, 8);
+        let sorted: Vec<(u32, u32)> = keys.into_iter().zip(values).collect();
+
+        // Stable sort, so the values of equal keys keep their order
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn validate_shader() {
+        let module = naga::front::wgsl::parse_str(include_str!("vfx_sort.wgsl")).unwrap();
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .unwrap();
+
+        // The pipelines are created from those entry points, with the workgroup size
+        // the blocks are sized for
+        for name in ["count", "scan", "scatter"] {
+            let entry_point = module
+                .entry_points
+                .iter()
+                .find(|entry_point| entry_point.name == name)
+                .unwrap();
+            assert_eq!(entry_point.stage, naga::ShaderStage::Compute);
+        }
+        let count = module
+            .entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == "count")
+            .unwrap();
+        assert_eq!(count.workgroup_size, [WORKGROUP_SIZE, 1, 1]);
+    }
+}
+
+#[cfg(all(test, feature = "gpu_tests"))]
+mod gpu_tests {
+    use bevy::render::render_resource::{CommandEncoderDescriptor, ComputePassDescriptor};
+
+    use super::{tests::cpu_radix_sort, *};
+    use crate::test_utils::MockRenderer;
+
+    /// Sort some pairs on GPU, and read back the sorted keys and values.
+    fn gpu_radix_sort(
+        renderer: &MockRenderer,
+        pipeline: &RadixSortPipeline,
+        buffers: &mut RadixSortBuffers,
+        keys: &[u32],
+        values: &[u32],
+        key_bits: u32,
+    ) -> (Vec<u32>, Vec<u32>) {
+        let device = renderer.device();
+        let queue = renderer.queue();
+
+        let pairs: Vec<u32> = keys
+            .iter()
+            .zip(values)
+            .flat_map(|(&key, &value)| [key, value])
+            .collect();
+        queue.write_buffer(buffers.pairs(), 0, bytemuck::cast_slice(&pairs[..]));
+        let count = keys.len() as u32;
+        buffers.prepare(&device, &queue, pipeline, count, key_bits);
+
+        let size = count as u64 * 8;
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("hanabi:buffer:test_sort_readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("test"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("test_sort"),
+                timestamp_writes: None,
+            });
+            pipeline.dispatch(&mut compute_pass, buffers);
+        }
+        encoder.copy_buffer_to_buffer(buffers.pairs(), 0, &readback, 0, size);
+        queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        let (tx, rx) = futures::channel::oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            tx.send(result).unwrap();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(rx).unwrap().unwrap();
+        let view = slice.get_mapped_range();
+        let pairs: &[u32] = bytemuck::cast_slice(&view);
+        pairs.chunks(2).map(|pair| (pair[0], pair[1])).unzip()
+    }
+
+    #[test]
+    fn sort() {
+        let renderer = MockRenderer::new();
+        let device = renderer.device();
+        let pipeline = RadixSortPipeline::new(&device);
+
+        // Leave some spare capacity to check that only the prepared pairs are sorted
+        let mut buffers = RadixSortBuffers::new(&device, 1500, "test_sort");
+        assert_eq!(buffers.capacity(), 1500);
+
+        let count = 1000u32;
+        let values: Vec<u32> = (0..count).collect();
+        for (keys, key_bits) in [
+            // Small keys spanning several blocks, with many duplicates
+            (
+                (0..count)
+                    .map(|i| i.wrapping_mul(2654435761) % 97)
+                    .collect::<Vec<u32>>(),
+                8,
+            ),
+            // Full 32-bit keys
+            (
+                (0..count)
+                    .map(|i| i.wrapping_mul(2654435761) ^ (i << 7))
+                    .collect::<Vec<u32>>(),
+                32,
+            ),
+        ] {
+            let (gpu_keys, gpu_values) =
+                gpu_radix_sort(&renderer, &pipeline, &mut buffers, &keys, &values, key_bits);
+
+            let mut cpu_keys = keys.clone();
+            let mut cpu_values = values.clone();
+            cpu_radix_sort(&mut cpu_keys, &mut cpu_values, key_bits);
+
+            assert_eq!(gpu_keys, cpu_keys);
+            assert_eq!(gpu_values, cpu_values);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{DynamicUniformBuffer, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
+        Extract,
+    },
+};
+
+use crate::EffectStereoEye;
+
+/// Transform the particles of a view are billboarded against.
+///
+/// This is the GPU representation of the `BillboardView` struct of
+/// `vfx_render.wgsl` and `vfx_cpu.wgsl`.
+#[derive(Debug, Default, Clone, Copy, ShaderType)]
+pub(crate) struct GpuBillboardView {
+    /// World transform of the billboard reference, like the `view` field of
+    /// the Bevy view uniform.
+    pub view: Mat4,
+}
+
+/// World transform of the head of the stereo rig a view belongs to.
+#[derive(Debug, Clone, Copy, Component)]
+pub(crate) struct ExtractedStereoHead(GlobalTransform);
+
+/// Offset of the [`GpuBillboardView`] of a view in the
+/// [`BillboardViewUniforms`].
+#[derive(Debug, Clone, Copy, Component)]
+pub(crate) struct BillboardViewOffset {
+    pub offset: u32,
+}
+
+/// Billboard transforms of all views, bound to the view bind group of the
+/// particle render pipelines.
+#[derive(Default, Resource)]
+pub(crate) struct BillboardViewUniforms {
+    pub uniforms: DynamicUniformBuffer<GpuBillboardView>,
+}
+
+/// Extract the head transform of the cameras with an [`EffectStereoEye`].
+pub(crate) fn extract_stereo_eyes(
+    mut commands: Commands,
+    eyes: Extract<Query<(Entity, &EffectStereoEye)>>,
+    transforms: Extract<Query<&GlobalTransform>>,
+) {
+    for (entity, eye) in &eyes {
+        if let Ok(head) = transforms.get(eye.head) {
+            commands
+                .get_or_spawn(entity)
+                .insert(ExtractedStereoHead(*head));
+        }
+    }
+}
+
+/// Upload the billboard transform of each view.
+///
+/// Views of a stereo rig use the transform of their head, and all other views
+/// their own transform.
+pub(crate) fn prepare_billboard_views(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut billboard_views: ResMut<BillboardViewUniforms>,
+    views: Query<(Entity, &ExtractedView, Option<&ExtractedStereoHead>)>,
+) {
+    billboard_views.uniforms.clear();
+    for (entity, view, head) in &views {
+        let transform = head.map_or(view.transform, |head| head.0);
+        let offset = billboard_views.uniforms.push(&GpuBillboardView {
+            view: transform.compute_matrix(),
+        });
+        commands
+            .entity(entity)
+            .insert(BillboardViewOffset { offset });
+    }
+    billboard_views
+        .uniforms
+        .write_buffer(&render_device, &render_queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn billboard_view_layout() {
+        // The layout must match the BillboardView struct of the render shaders
+        assert_eq!(GpuBillboardView::min_size().get(), 64);
+    }
+}
@@ -0,0 +1,253 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Mutex,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, WgpuFeatures,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+    utils::HashMap,
+};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType};
+
+use super::{
+    readback::{ReadbackPoll, StagingReadback},
+    ReadbackChannel,
+};
+use crate::timing::{GpuTimingDiagnostics, GpuTimingPass, GpuTimingReport};
+
+/// Maximum number of timestamps written per frame. Each measurement uses two
+/// timestamps.
+const QUERY_CAPACITY: u32 = 4096;
+
+/// Size of a single timestamp, in bytes.
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// Single measurement, made of two consecutive timestamps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimingQuery {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Measured pass.
+    pass: GpuTimingPass,
+    /// Index of the first timestamp in the query set.
+    index: u32,
+}
+
+/// Render world resource measuring the GPU time of each effect instance with
+/// timestamp queries.
+///
+/// The render graph nodes and draw functions allocate the timestamps of each
+/// measurement while recording their commands, which only have read access to
+/// the render world, so the allocation uses interior mutability. At the end of
+/// the frame, the timestamps are resolved and read back with a
+/// [`StagingReadback`], like for the [`OccupancyReadback`]. No timestamp is
+/// written while a readback is in flight.
+///
+/// [`OccupancyReadback`]: super::OccupancyReadback
+#[derive(Default, Resource)]
+pub(crate) struct GpuTimingQueries {
+    /// Whether measurements are recorded this frame.
+    active: bool,
+    /// Query set the timestamps are written into.
+    query_set: Option<QuerySet>,
+    /// Buffer the query set is resolved into.
+    resolve_buffer: Option<Buffer>,
+    /// Index of the next free timestamp in the query set.
+    next_index: AtomicU32,
+    /// Measurements recorded this frame.
+    queries: Mutex<Vec<TimingQuery>>,
+    /// Duration of a timestamp tick, in nanoseconds.
+    period: f32,
+    /// Readback of the resolved timestamps, with the measurements being read
+    /// back.
+    staging: StagingReadback<Vec<TimingQuery>>,
+}
+
+impl GpuTimingQueries {
+    /// Allocate the two timestamps measuring a pass of an effect instance.
+    ///
+    /// Returns the query set and the index of the first timestamp, to write
+    /// before the measured commands, while the second timestamp is to be
+    /// written after them. Returns `None` if no measurement is recorded this
+    /// frame, or if the query set is full.
+    pub fn allocate(&self, entity: Entity, pass: GpuTimingPass) -> Option<(&QuerySet, u32)> {
+        if !self.active {
+            return None;
+        }
+        let query_set = self.query_set.as_ref()?;
+        let index = self.next_index.fetch_add(2, Ordering::Relaxed);
+        if index + 2 > QUERY_CAPACITY {
+            return None;
+        }
+        self.queries.lock().unwrap().push(TimingQuery {
+            entity,
+            pass,
+            index,
+        });
+        Some((query_set, index))
+    }
+
+    /// Sum the durations of all measurements of a same pass of a same effect
+    /// instance.
+    fn accumulate(
+        timestamps: &[u64],
+        queries: &[TimingQuery],
+        period: f32,
+    ) -> Vec<GpuTimingReport> {
+        let mut durations = HashMap::<(Entity, GpuTimingPass), u64>::default();
+        for query in queries {
+            let index = query.index as usize;
+            let ticks = timestamps[index + 1].saturating_sub(timestamps[index]);
+            *durations.entry((query.entity, query.pass)).or_default() += ticks;
+        }
+        durations
+            .into_iter()
+            .map(|((entity, pass), ticks)| GpuTimingReport {
+                entity,
+                pass,
+                milliseconds: ticks as f64 * period as f64 / 1_000_000.,
+            })
+            .collect()
+    }
+}
+
+/// Enable or disable the GPU timing measurements for this frame, and allocate
+/// the query set if needed.
+///
+/// This system runs in the [`RenderSet::Prepare`] set, before any command is
+/// recorded.
+pub(crate) fn prepare_gpu_timing(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    diagnostics: Option<Res<GpuTimingDiagnostics>>,
+    mut timing: ResMut<GpuTimingQueries>,
+) {
+    let required = WgpuFeatures::TIMESTAMP_QUERY | WgpuFeatures::TIMESTAMP_QUERY_INSIDE_PASSES;
+    timing.active = diagnostics.is_some_and(|diagnostics| diagnostics.enabled)
+        && render_device.features().contains(required)
+        && !timing.staging.is_pending();
+    *timing.next_index.get_mut() = 0;
+    timing.queries.get_mut().unwrap().clear();
+    if !timing.active || timing.query_set.is_some() {
+        return;
+    }
+
+    let size = QUERY_CAPACITY as u64 * TIMESTAMP_SIZE;
+    timing.query_set = Some(
+        render_device
+            .wgpu_device()
+            .create_query_set(&QuerySetDescriptor {
+                label: Some("hanabi:query_set:gpu_timing"),
+                ty: QueryType::Timestamp,
+                count: QUERY_CAPACITY,
+            }),
+    );
+    timing.resolve_buffer = Some(render_device.create_buffer(&BufferDescriptor {
+        label: Some("hanabi:buffer:gpu_timing_resolve"),
+        size,
+        usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    }));
+    timing
+        .staging
+        .reserve(&render_device, "hanabi:buffer:gpu_timing_readback", size);
+    timing.period = render_queue.get_timestamp_period();
+}
+
+/// Read back the timestamps written this frame, and send the GPU time of each
+/// effect instance to the main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn readback_gpu_timings(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    channel: Res<ReadbackChannel<GpuTimingReport>>,
+    mut timing: ResMut<GpuTimingQueries>,
+) {
+    // Complete the readback in flight, if any
+    let period = timing.period;
+    match timing.staging.poll(&render_device, |data, queries| {
+        let timestamps: Vec<u64> = bytemuck::pod_collect_to_vec(data);
+        GpuTimingQueries::accumulate(&timestamps, queries, period)
+    }) {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => {
+            trace!("Read back {} GPU timings.", reports.len());
+            channel.send(reports);
+        }
+        ReadbackPoll::Failed => warn!("Failed to read back the GPU timings of effects."),
+    }
+
+    if !timing.active {
+        return;
+    }
+    let queries = std::mem::take(timing.queries.get_mut().unwrap());
+    let Some(count) = queries.iter().map(|query| query.index + 2).max() else {
+        return;
+    };
+    let size = count as u64 * TIMESTAMP_SIZE;
+    let timing = timing.as_mut();
+    let (Some(query_set), Some(resolve_buffer)) =
+        (timing.query_set.as_ref(), timing.resolve_buffer.as_ref())
+    else {
+        return;
+    };
+    let staging_buffer =
+        timing
+            .staging
+            .reserve(&render_device, "hanabi:buffer:gpu_timing_readback", size);
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("hanabi:gpu_timing_readback"),
+    });
+    encoder.resolve_query_set(query_set, 0..count, resolve_buffer, 0);
+    encoder.copy_buffer_to_buffer(resolve_buffer, 0, &staging_buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    timing.staging.map(staging_buffer, size, queries);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate() {
+        let entity = Entity::from_raw(3);
+        let queries = [
+            TimingQuery {
+                entity,
+                pass: GpuTimingPass::Render,
+                index: 0,
+            },
+            TimingQuery {
+                entity,
+                pass: GpuTimingPass::Update,
+                index: 2,
+            },
+            TimingQuery {
+                entity,
+                pass: GpuTimingPass::Render,
+                index: 4,
+            },
+        ];
+        let timestamps = [100, 600, 1000, 3000, 4000, 4500];
+        let mut reports = GpuTimingQueries::accumulate(&timestamps, &queries, 2.);
+        reports.sort_by_key(|report| report.pass.name());
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].pass, GpuTimingPass::Render);
+        assert!((reports[0].milliseconds - 0.002).abs() < 1e-9);
+        assert_eq!(reports[1].pass, GpuTimingPass::Update);
+        assert!((reports[1].milliseconds - 0.004).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,258 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{BufferDescriptor, BufferUsages, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+        Extract,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use super::{
+    particle_query::{ParticleQueryPipelines, QueryGroup},
+    readback::{ReadbackPoll, StagingReadback},
+    EffectCache, EffectsMeta, ReadbackChannel,
+};
+use crate::{
+    track::{TrackReport, TrackedParticle},
+    Attribute, ParticleTracker,
+};
+
+/// Number of words of a single particle in the result buffer.
+const PARTICLE_WORDS: usize = 7;
+
+/// Parameters of the tracking of the particles of a single group.
+///
+/// This is the GPU representation of the `TrackParams` struct of
+/// `vfx_track.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub(crate) struct GpuTrackParams {
+    /// Size of a single particle, in words.
+    pub particle_stride: u32,
+    /// Offset of the position attribute in a particle, in words.
+    pub position_offset: u32,
+    /// Offset of the velocity attribute in a particle, in words, or
+    /// `u32::MAX`.
+    pub velocity_offset: u32,
+    /// Offset of the render group indirect arguments of the group, in words.
+    pub render_group_word: u32,
+    /// Offset of the render effect metadata of the effect, in words.
+    pub render_effect_word: u32,
+    /// Maximum number of particles written.
+    pub max_count: u32,
+    /// Offset of the entry in the result buffer, in words.
+    pub result_word: u32,
+    pub __pad: u32,
+}
+
+/// Group tracked by a [`ParticleTracker`], extracted this frame.
+struct TrackRequest {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Index of the group tracked.
+    group_index: u32,
+}
+
+/// Group whose particles are being read back.
+struct TrackGroup {
+    /// Entity holding the [`ParticleEffect`] instance in the main world.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    entity: Entity,
+    /// Index of the group tracked.
+    group_index: u32,
+    /// Index of the first particle of the effect in the particle buffer.
+    effect_start: u32,
+    /// Offset of the entry of the group in the result buffer, in words.
+    result_word: usize,
+}
+
+/// Render world resource reading back the particles of the groups tracked by
+/// the [`ParticleTracker`]s.
+///
+/// The track shader runs at the end of the frame, once per group, and gathers
+/// the position and velocity of the alive particles into a small result
+/// buffer. Like for the [`PickReadback`], the result buffer is read back with
+/// a [`StagingReadback`] and sent to the main world via the
+/// [`ReadbackChannel`].
+///
+/// [`PickReadback`]: super::PickReadback
+#[derive(Resource)]
+pub(crate) struct TrackReadback {
+    /// Pipeline gathering the particles of a group.
+    pipelines: ParticleQueryPipelines,
+    /// Groups tracked, extracted this frame.
+    requests: Vec<TrackRequest>,
+    /// Readback of the result buffer, with the groups tracked.
+    staging: StagingReadback<Vec<TrackGroup>>,
+}
+
+impl FromWorld for TrackReadback {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        Self {
+            pipelines: ParticleQueryPipelines::new(
+                render_device,
+                "track",
+                GpuTrackParams::min_size(),
+                include_str!("vfx_track.wgsl"),
+                &["track"],
+            ),
+            requests: vec![],
+            staging: default(),
+        }
+    }
+}
+
+impl TrackReadback {
+    /// Parse the mapped staging buffer into track reports.
+    fn parse(data: &[u8], groups: &[TrackGroup]) -> Vec<TrackReport> {
+        let words: &[u32] = bytemuck::cast_slice(data);
+        let vec3 = |words: &[u32]| {
+            Vec3::new(
+                f32::from_bits(words[0]),
+                f32::from_bits(words[1]),
+                f32::from_bits(words[2]),
+            )
+        };
+        groups
+            .iter()
+            .map(|group| {
+                let count = words[group.result_word] as usize;
+                let start = group.result_word + 1;
+                let particles = words[start..start + count * PARTICLE_WORDS]
+                    .chunks_exact(PARTICLE_WORDS)
+                    .map(|particle| TrackedParticle {
+                        particle_index: particle[0] - group.effect_start,
+                        position: vec3(&particle[1..4]),
+                        velocity: vec3(&particle[4..7]),
+                    })
+                    .collect();
+                TrackReport {
+                    entity: group.entity,
+                    group_index: group.group_index,
+                    particles,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Extract the groups tracked by the [`ParticleTracker`]s.
+pub(crate) fn extract_track_requests(
+    mut readback: ResMut<TrackReadback>,
+    trackers: Extract<Query<(Entity, &ParticleTracker)>>,
+) {
+    readback.requests = trackers
+        .iter()
+        .map(|(entity, tracker)| TrackRequest {
+            entity,
+            group_index: tracker.group_index,
+        })
+        .collect();
+}
+
+/// Read back the particles of the groups tracked by the [`ParticleTracker`]s,
+/// and send them to the main world.
+///
+/// This system runs in the [`RenderSet::Cleanup`] set, once all the GPU
+/// commands of the frame were submitted.
+pub(crate) fn track_particles(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    effects_meta: Res<EffectsMeta>,
+    effect_cache: Res<EffectCache>,
+    channel: Res<ReadbackChannel<TrackReport>>,
+    mut readback: ResMut<TrackReadback>,
+) {
+    // Complete the readback in flight, if any
+    match readback.staging.poll(&render_device, |data, groups| {
+        TrackReadback::parse(data, groups)
+    }) {
+        ReadbackPoll::Idle => {}
+        ReadbackPoll::Pending => return,
+        ReadbackPoll::Ready(reports) => channel.send(reports),
+        ReadbackPoll::Failed => warn!("Failed to read back the tracked particles."),
+    }
+
+    if readback.requests.is_empty() {
+        return;
+    }
+
+    // Collect the parameters of each group tracked of the allocated effects
+    let mut groups = vec![];
+    let mut queries = vec![];
+    let mut result_words = 0;
+    for request in &readback.requests {
+        let Some(group) = QueryGroup::of_effect(&effects_meta, &effect_cache, request.entity)
+            .into_iter()
+            .nth(request.group_index as usize)
+        else {
+            continue;
+        };
+        let max_count = group.capacity.min(ParticleTracker::MAX_PARTICLES);
+        let params = GpuTrackParams {
+            particle_stride: group.particle_stride,
+            position_offset: group.position_offset,
+            velocity_offset: group.offset_of(Attribute::VELOCITY),
+            render_group_word: group.render_group_word,
+            render_effect_word: group.render_effect_word,
+            max_count,
+            result_word: result_words as u32,
+            ..default()
+        };
+        groups.push(TrackGroup {
+            entity: request.entity,
+            group_index: request.group_index,
+            effect_start: group.effect_start,
+            result_word: result_words,
+        });
+        // Always dispatch at least one thread to write the particle count
+        queries.push((group, max_count.max(1), params));
+        result_words += 1 + max_count as usize * PARTICLE_WORDS;
+    }
+    if groups.is_empty() {
+        return;
+    }
+
+    // Buffers are zero-initialized, so groups not dispatched read back empty
+    let size = (result_words * 4) as u64;
+    let result_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("hanabi:buffer:track_results"),
+        size,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let Some(mut encoder) = readback.pipelines.dispatch(
+        &render_device,
+        &effects_meta,
+        &effect_cache,
+        &queries,
+        &result_buffer,
+    ) else {
+        return;
+    };
+    let buffer = readback
+        .staging
+        .reserve(&render_device, "hanabi:buffer:track_readback", size);
+    encoder.copy_buffer_to_buffer(&result_buffer, 0, &buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    readback.staging.map(buffer, size, groups);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_params_layout() {
+        // The layout must match the TrackParams struct of vfx_track.wgsl
+        assert_eq!(GpuTrackParams::min_size().get(), 32);
+        assert_eq!(std::mem::size_of::<GpuTrackParams>(), 32);
+        assert_eq!(std::mem::offset_of!(GpuTrackParams, result_word), 24);
+    }
+}
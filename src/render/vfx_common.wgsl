@@ -22,8 +22,26 @@ struct Spawner {
     inverse_transform: mat3x4<f32>, // transposed (row-major)
     spawn: i32,
     seed: u32,
+    // Atomics can't be bound as read-only storage, nor copied into a private variable,
+    // so the batched update pass reads this as a plain integer.
+#ifdef SPAWNER_READ_ONLY
+    count: i32,
+#else
     count: atomic<i32>,
+#endif
     effect_index: u32,
+    prewarm: f32,
+    time_scale: f32,
+    kill_groups: u32,
+    inject: u32,
+    event_slot: u32,
+    parent_event: u32,
+    spawn_per_event: u32,
+    bounds_slot: u32,
+    space_conversion: u32,
+    feedback_slot: u32,
+    disabled_modifiers: u32,
+    _pad: u32,
 #ifdef SPAWNER_PADDING
     {{SPAWNER_PADDING}}
 #endif
@@ -128,6 +146,12 @@ struct RenderGroupIndirect {
     /// Number of dead particles, decremented during the init pass as new particles
     /// are spawned, and incremented during the update pass as existing particles die.
     dead_count: atomic<u32>,
+    /// Total number of particles spawned into the group since it was allocated,
+    /// incremented during the init pass. Read back to report spawn statistics.
+    spawned_count: atomic<u32>,
+    /// Total number of particles which couldn't be spawned into the group because it
+    /// was full, incremented during the init pass. Read back to warn about them.
+    dropped_count: atomic<u32>,
     {{RENDER_GROUP_INDIRECT_PADDING}}
 }
 
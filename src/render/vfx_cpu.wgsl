@@ -0,0 +1,54 @@
+#import bevy_render::view::View
+
+// Transform the particles are billboarded against. This is the view transform,
+// or the head transform for the eyes of a stereo rig.
+struct BillboardView {
+    view: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> view: View;
+@group(0) @binding(1) var<uniform> billboard_view: BillboardView;
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> VertexOutput {
+    // Quad made of 2 triangles, centered on the particle position
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+    let corner = corners[vertex_index] * size;
+
+    // Face the camera
+    let axis_x = billboard_view.view[0].xyz;
+    let axis_y = billboard_view.view[1].xyz;
+    let world_position = position + axis_x * corner.x + axis_y * corner.y;
+
+    var out: VertexOutput;
+    out.position = view.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef USE_ALPHA_MASK
+    if (in.color.a < 0.5) {
+        discard;
+    }
+#endif
+    return in.color;
+}
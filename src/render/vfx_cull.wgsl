@@ -0,0 +1,56 @@
+// Per-view frustum culling of individual particles. This is appended to the update
+// shader of the effects enabling particle culling, and dispatched once per view
+// right after the update pass, with the same workgroup count.
+
+struct CullParams {
+    // Frustum planes of the view, in world space, as (normal, distance). The
+    // normals point toward the inside of the frustum.
+    planes: array<vec4<f32>, 6>,
+    // Offset of the list of visible particles of this group in cull_index_buffer.
+    index_offset: u32,
+    // Index of the indirect draw arguments of this group in cull_draw_buffer.
+    draw_index: u32,
+}
+
+@group(0) @binding(1) var<storage, read> cull_params : CullParams;
+@group(0) @binding(2) var<storage, read_write> cull_index_buffer : array<u32>;
+@group(0) @binding(3) var<storage, read_write> cull_draw_buffer : array<atomic<u32>>;
+
+@compute @workgroup_size(#{WORKGROUP_SIZE})
+fn cull(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let thread_index = global_invocation_id.x;
+
+    // The vertex count of the particle mesh is only known on GPU
+    if (thread_index == 0u) {
+        atomicStore(
+            &cull_draw_buffer[cull_params.draw_index * 4u],
+            render_group_indirect[#{GROUP_INDEX}].vertex_count
+        );
+    }
+
+    // Only the particles still alive after the update pass are drawn
+    if (thread_index >= atomicLoad(&render_group_indirect[#{GROUP_INDEX}].instance_count)) {
+        return;
+    }
+
+    // The update pass just wrote the indices of the alive particles into ping
+    let ping = render_effect_indirect.ping;
+    let effect_particle_offset = particle_groups[#{GROUP_INDEX}].effect_particle_offset;
+    let base_index = effect_particle_offset + particle_groups[#{GROUP_INDEX}].indirect_index;
+    let index = indirect_buffer.indices[3u * (base_index + thread_index) + ping];
+
+    let particle: Particle = particle_buffer.particles[index];
+    let world_position = {{CULL_POSITION}};
+
+    // Cull the bounding sphere of the particle against all planes
+    let radius = {{CULL_RADIUS}};
+    for (var i = 0u; i < 6u; i += 1u) {
+        let plane = cull_params.planes[i];
+        if (dot(plane.xyz, world_position) + plane.w + radius <= 0.0) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&cull_draw_buffer[cull_params.draw_index * 4u + 1u], 1u);
+    cull_index_buffer[cull_params.index_offset + slot] = index;
+}
@@ -0,0 +1,72 @@
+// Accumulation of the density of the particles into a 3D volume. The inject pass
+// adds the density of the particles of a single group into the cell of the volume
+// each of them is in, as a fixed point value in an atomic buffer with one word per
+// cell. The resolve pass then converts the buffer of a volume into its 3D image.
+
+// Scale of the fixed point density values, matching DENSITY_SCALE in density.rs.
+const DENSITY_SCALE: f32 = 256.0;
+
+struct DensityParams {
+    // Transform from the simulation space of the effect to the cell coordinates of
+    // the volume.
+    simulation_to_cell: mat4x4<f32>,
+    // Number of cells of the volume along each axis.
+    resolution: vec3<u32>,
+    // Density added by each particle, in fixed point.
+    density: u32,
+    // Size of a single particle, in words.
+    particle_stride: u32,
+    // Offset of the position attribute in a particle, in words.
+    position_offset: u32,
+    // Offset of the render group indirect arguments of the group, in words.
+    render_group_word: u32,
+    // Offset of the render effect metadata of the effect, in words.
+    render_effect_word: u32,
+}
+
+@group(0) @binding(0) var<storage, read> params : DensityParams;
+@group(0) @binding(1) var<storage, read> particle_buffer : array<u32>;
+@group(0) @binding(2) var<storage, read> indirect_buffer : array<u32>;
+@group(0) @binding(3) var<storage, read> render_group_buffer : array<u32>;
+@group(0) @binding(4) var<storage, read> render_effect_buffer : array<u32>;
+@group(0) @binding(5) var<storage, read_write> density_buffer : array<atomic<u32>>;
+@group(0) @binding(6) var density_texture : texture_storage_3d<r32float, write>;
+
+fn cell_index(cell: vec3<u32>) -> u32 {
+    return cell.x + params.resolution.x * (cell.y + params.resolution.y * cell.z);
+}
+
+@compute @workgroup_size(64)
+fn inject(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Only the alive particles drawn this frame contribute, like for picking
+    let thread_index = global_invocation_id.x;
+    let instance_count = render_group_buffer[params.render_group_word + 1u];
+    if (thread_index >= instance_count) {
+        return;
+    }
+    let base_instance = render_group_buffer[params.render_group_word + 3u];
+    let ping = render_effect_buffer[params.render_effect_word + 1u];
+    let index = indirect_buffer[3u * (base_instance + thread_index) + ping];
+
+    let base = index * params.particle_stride + params.position_offset;
+    let position = vec3<f32>(
+        bitcast<f32>(particle_buffer[base]),
+        bitcast<f32>(particle_buffer[base + 1u]),
+        bitcast<f32>(particle_buffer[base + 2u]),
+    );
+    let cell = floor((params.simulation_to_cell * vec4<f32>(position, 1.0)).xyz);
+    if (any(cell < vec3<f32>(0.0)) || any(cell >= vec3<f32>(params.resolution))) {
+        return;
+    }
+    atomicAdd(&density_buffer[cell_index(vec3<u32>(cell))], params.density);
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn resolve(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let cell = global_invocation_id;
+    if (any(cell >= params.resolution)) {
+        return;
+    }
+    let density = f32(atomicLoad(&density_buffer[cell_index(cell)])) / DENSITY_SCALE;
+    textureStore(density_texture, cell, vec4<f32>(density, 0.0, 0.0, 0.0));
+}
@@ -50,10 +50,10 @@ fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     render_group_indirect_buffer[rgi_base + RGI_OFFSET_INSTANCE_COUNT] = 0u;
 
     // Calculate the number of thread groups to dispatch for the update
-    // pass, which is the number of alive particles rounded up to 64
-    // (workgroup_size).
+    // pass, which is the number of alive particles rounded up to the
+    // workgroup size of that pass.
     let alive_count = render_group_indirect_buffer[rgi_base + RGI_OFFSET_ALIVE_COUNT];
-    dispatch_indirect_buffer[di_base + DI_OFFSET_X] = (alive_count + 63u) >> 6u;
+    dispatch_indirect_buffer[di_base + DI_OFFSET_X] = (alive_count + #{WORKGROUP_SIZE}u - 1u) / #{WORKGROUP_SIZE}u;
 
     // Update max_update from current value of alive_count, so that the
     // update pass coming next can cap its threads to this value, while also
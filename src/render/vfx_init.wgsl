@@ -19,32 +19,36 @@ struct ParticleBuffer {
 @group(1) @binding(1) var<storage, read_write> indirect_buffer : IndirectBuffer;
 @group(1) @binding(2) var<storage, read> particle_groups : array<ParticleGroup>;
 {{PROPERTIES_BINDING}}
+{{INJECTION_BINDING}}
+{{TRAIL_BINDING}}
 @group(2) @binding(0) var<storage, read_write> spawner : Spawner; // NOTE - same group as update
+@group(2) @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>;
 @group(3) @binding(0) var<storage, read_write> render_effect_indirect : RenderEffectMetadata;
 @group(3) @binding(1) var<storage, read_write> render_group_indirect : RenderGroupIndirect;
 
 {{INIT_EXTRA}}
 
-@compute @workgroup_size(64)
+@compute @workgroup_size(#{WORKGROUP_SIZE})
 fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     var index = global_invocation_id.x;
 
-    // Cap to max number of dead particles, copied from dead_count at the end of the
-    // previous iteration, and constant during this pass (unlike dead_count).
-    if (index >= render_effect_indirect.max_spawn) {
-        return;
-    }
-
     // Cap to the actual number of spawning requested by CPU, since compute shaders run
-    // in workgroup_size(64) so more threads than needed are launched (rounded up to 64).
-    let spawn_count : u32 = u32(spawner.spawn);
+    // in workgroups so more threads than needed are launched (rounded up to the workgroup size).
+    var spawn_count : u32 = u32(spawner.spawn);
+    // Add the particles spawned from the events of the parent effect, if any,
+    // counted on GPU during the update pass of the previous frame.
+    if (spawner.parent_event != 0xFFFFFFFFu) {
+        spawn_count += atomicLoad(&event_buffer[spawner.parent_event]) * spawner.spawn_per_event;
+    }
     if (index >= spawn_count) {
         return;
     }
 
-    // Recycle a dead particle from the first group
-    let base_index = particle_groups[0].effect_particle_offset;
-    let dead_index = atomicSub(&render_group_indirect.dead_count, 1u) - 1u;
+    // Cap to the free capacity of the group, counting the dropped particles
+    {{SPAWN_CAP_CODE}}
+
+    // Recycle a dead particle from the group spawned into
+    {{RECYCLE_CODE}}
     index = indirect_buffer.indices[3u * (base_index + dead_index) + 2u];
 
     // Update PRNG seed
@@ -66,8 +70,14 @@ fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
 
     {{SIMULATION_SPACE_TRANSFORM_PARTICLE}}
 
+    // Overwrite attributes explicitly set on particles injected from the CPU
+    {{INJECTION_CODE}}
+
+    {{TRAIL_CODE}}
+
     // Count as alive
     atomicAdd(&render_group_indirect.alive_count, 1u);
+    atomicAdd(&render_group_indirect.spawned_count, 1u);
 
     // Always write into ping, read from pong
     let ping = render_effect_indirect.ping;
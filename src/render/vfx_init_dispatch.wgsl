@@ -0,0 +1,45 @@
+// Offsets (in number of u32 items) of the fields of the Spawner struct read by
+// this shader. See vfx_common.wgsl.
+const SPAWNER_OFFSET_SPAWN: u32 = 24u;
+const SPAWNER_OFFSET_PARENT_EVENT: u32 = 33u;
+const SPAWNER_OFFSET_SPAWN_PER_EVENT: u32 = 34u;
+
+const SPAWNER_STRIDE: u32 = {{SPAWNER_STRIDE}} / 4u;
+
+// Size of a single set of workgroup counts, padded to 16 bytes.
+const INIT_DISPATCH_STRIDE: u32 = 4u;
+
+// Number of threads per workgroup of the init pass.
+const WORKGROUP_SIZE: u32 = {{WORKGROUP_SIZE}};
+
+@group(0) @binding(0) var<storage, read> spawner_buffer : array<u32>;
+@group(0) @binding(1) var<storage, read> event_buffer : array<u32>;
+@group(0) @binding(2) var<storage, read_write> init_dispatch_buffer : array<u32>;
+
+/// Calculate the workgroup counts of the init pass of each spawner, from the
+/// number of particles spawned by the CPU and the number of events emitted on
+/// GPU by the parent effect during the previous frame, if any.
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+
+    let base = index * SPAWNER_STRIDE;
+    let dispatch_base = index * INIT_DISPATCH_STRIDE;
+    if (base + SPAWNER_STRIDE > arrayLength(&spawner_buffer)
+        || dispatch_base + INIT_DISPATCH_STRIDE > arrayLength(&init_dispatch_buffer)) {
+        return;
+    }
+
+    var spawn_count = u32(max(bitcast<i32>(spawner_buffer[base + SPAWNER_OFFSET_SPAWN]), 0));
+    let parent_event = spawner_buffer[base + SPAWNER_OFFSET_PARENT_EVENT];
+    if (parent_event != 0xFFFFFFFFu) {
+        spawn_count += event_buffer[parent_event] * spawner_buffer[base + SPAWNER_OFFSET_SPAWN_PER_EVENT];
+    }
+
+    // Round up to the workgroup size of the init pass, capped to the maximum
+    // of 65535 workgroups per dimension. The init pass is anyway capped to the
+    // number of dead particles.
+    init_dispatch_buffer[dispatch_base] = (min(spawn_count, 65535u * WORKGROUP_SIZE) + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+    init_dispatch_buffer[dispatch_base + 1u] = 1u;
+    init_dispatch_buffer[dispatch_base + 2u] = 1u;
+}
@@ -0,0 +1,28 @@
+// Common bindings and helpers of the shaders querying the alive particles of a group,
+// prepended to the source of each of them. Each shader declares its own parameters at
+// @binding(0), and its own result buffer at @binding(5).
+
+@group(0) @binding(1) var<storage, read> particle_buffer : array<u32>;
+@group(0) @binding(2) var<storage, read> indirect_buffer : array<u32>;
+@group(0) @binding(3) var<storage, read> render_group_buffer : array<u32>;
+@group(0) @binding(4) var<storage, read> render_effect_buffer : array<u32>;
+
+// Number of alive particles of a group drawn this frame. Like in the render pass, this
+// is the instance count of the render group indirect arguments of the group.
+fn alive_count(render_group_word: u32) -> u32 {
+    return render_group_buffer[render_group_word + 1u];
+}
+
+// Index in the particle buffer of the alive particle #thread_index of a group, which
+// must be less than alive_count(). Like in the render pass, the base instance is the
+// offset of the group in the indirect buffer.
+fn alive_particle_index(render_group_word: u32, render_effect_word: u32, thread_index: u32) -> u32 {
+    let base_instance = render_group_buffer[render_group_word + 3u];
+    let ping = render_effect_buffer[render_effect_word + 1u];
+    return indirect_buffer[3u * (base_instance + thread_index) + ping];
+}
+
+// Read the raw bits of a vec3 attribute starting at the given word of the particle buffer.
+fn read_vec3(word: u32) -> vec3<u32> {
+    return vec3<u32>(particle_buffer[word], particle_buffer[word + 1u], particle_buffer[word + 2u]);
+}
@@ -0,0 +1,95 @@
+// Picking of individual particles along a ray. The particles of a single group are
+// tested against the ray by each entry, in three passes: the first one finds the
+// smallest distance along the ray of all the particles hit, the second one the
+// smallest index of the particles hit at that distance, and the last one writes the
+// position of that particle. Each entry owns 5 words of the result buffer, which are
+// all initialized to 0xFFFFFFFF:
+// - [0] Distance along the ray, as the bits of a positive f32.
+// - [1] Index of the particle in the particle buffer.
+// - [2..5] Position of the particle, in simulation space.
+
+struct PickParams {
+    // Origin of the ray, in simulation space.
+    origin: vec3<f32>,
+    // Radius of the sphere around each particle hit by the ray.
+    radius: f32,
+    // Normalized direction of the ray, in simulation space.
+    direction: vec3<f32>,
+    // Offset of the f32 size attribute in a particle, in words, or 0xFFFFFFFF if none.
+    size_offset: u32,
+    // Size of a single particle, in words.
+    particle_stride: u32,
+    // Offset of the position attribute in a particle, in words.
+    position_offset: u32,
+    // Offset of the render group indirect arguments of the group, in words.
+    render_group_word: u32,
+    // Offset of the render effect metadata of the effect, in words.
+    render_effect_word: u32,
+    // Index of the entry in the result buffer.
+    result_index: u32,
+}
+
+struct Hit {
+    // Distance along the ray, or a negative value if the particle was not hit.
+    distance: f32,
+    // Index of the particle in the particle buffer.
+    index: u32,
+    // Position of the particle, in simulation space.
+    position: vec3<f32>,
+}
+
+@group(0) @binding(0) var<storage, read> params : PickParams;
+@group(0) @binding(5) var<storage, read_write> result_buffer : array<atomic<u32>>;
+
+fn hit_particle(thread_index: u32) -> Hit {
+    var hit = Hit(-1.0, 0u, vec3<f32>(0.0));
+
+    // Only the alive particles drawn this frame can be picked
+    if (thread_index >= alive_count(params.render_group_word)) {
+        return hit;
+    }
+    hit.index = alive_particle_index(params.render_group_word, params.render_effect_word, thread_index);
+    hit.position = bitcast<vec3<f32>>(read_vec3(hit.index * params.particle_stride + params.position_offset));
+    var radius = params.radius;
+    if (params.size_offset != 0xFFFFFFFFu) {
+        let size = bitcast<f32>(particle_buffer[hit.index * params.particle_stride + params.size_offset]);
+        radius = max(radius, size * 0.5);
+    }
+
+    // Test the bounding sphere of the particle against the ray
+    let offset = hit.position - params.origin;
+    let distance = dot(offset, params.direction);
+    if (distance >= 0.0 && dot(offset, offset) - distance * distance <= radius * radius) {
+        hit.distance = distance;
+    }
+    return hit;
+}
+
+@compute @workgroup_size(64)
+fn pick_distance(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let hit = hit_particle(global_invocation_id.x);
+    if (hit.distance >= 0.0) {
+        // The bits of positive floats sort like the floats themselves
+        atomicMin(&result_buffer[params.result_index * 5u], bitcast<u32>(hit.distance));
+    }
+}
+
+@compute @workgroup_size(64)
+fn pick_index(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let hit = hit_particle(global_invocation_id.x);
+    let base = params.result_index * 5u;
+    if (hit.distance >= 0.0 && bitcast<u32>(hit.distance) == atomicLoad(&result_buffer[base])) {
+        atomicMin(&result_buffer[base + 1u], hit.index);
+    }
+}
+
+@compute @workgroup_size(64)
+fn pick_position(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let hit = hit_particle(global_invocation_id.x);
+    let base = params.result_index * 5u;
+    if (hit.distance >= 0.0 && hit.index == atomicLoad(&result_buffer[base + 1u])) {
+        atomicStore(&result_buffer[base + 2u], bitcast<u32>(hit.position.x));
+        atomicStore(&result_buffer[base + 3u], bitcast<u32>(hit.position.y));
+        atomicStore(&result_buffer[base + 4u], bitcast<u32>(hit.position.z));
+    }
+}
@@ -13,33 +13,98 @@ struct ParticleBuffer {
     particles: array<Particle>,
 }
 
+// Transform the particles are billboarded against. This is the view transform,
+// or the head transform for the eyes of a stereo rig.
+struct BillboardView {
+    view: mat4x4<f32>,
+}
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec4<f32>,
 #ifdef NEEDS_UV
     @location(1) uv: vec2<f32>,
 #endif
+#ifdef DEFERRED_PREPASS
+    @location(2) world_normal: vec3<f32>,
+#endif
+#ifdef FOG
+    @location(3) world_position: vec3<f32>,
+#endif
+#ifdef BLOB_SHADOW
+    // Position in the shadow disc in xy, in [-1:1], and 1.0 in z for the
+    // vertices of the shadow, or 0.0 for the ones of the particle.
+    @location(4) blob_shadow: vec3<f32>,
+#endif
+}
+
+// Fog of the view, with the same encoding as the fog of Bevy PBR.
+struct Fog {
+    base_color: vec4<f32>,
+    be: vec3<f32>,
+    mode: u32,
+    bi: vec3<f32>,
+}
+
+// Point light of the 2D lighting, with its radius in position_radius.z, and its
+// color multiplied by its intensity.
+struct PointLight2d {
+    position_radius: vec4<f32>,
+    color: vec4<f32>,
+}
+
+// 2D lighting of the particles, copied from the Lights2d resource.
+struct Lights2d {
+    ambient: vec4<f32>,
+    point_light_count: u32,
+    point_lights: array<PointLight2d, 16>,
+}
+
+#ifdef DEFERRED_PREPASS
+// Output of the deferred G-buffer pass of Bevy, with the same attachments as
+// the deferred prepass of the StandardMaterial.
+struct FragmentOutput {
+#ifdef NORMAL_PREPASS
+    @location(0) normal: vec4<f32>,
+#endif
+#ifdef MOTION_VECTOR_PREPASS
+    @location(1) motion_vector: vec2<f32>,
+#endif
+    @location(2) deferred: vec4<u32>,
+    @location(3) deferred_lighting_pass_id: u32,
 }
+#endif
 
 @group(0) @binding(0) var<uniform> view: View;
 @group(0) @binding(1) var<uniform> sim_params : SimParams;
+#ifdef CULL_PARTICLES
+@group(0) @binding(2) var<storage, read> cull_index_buffer : array<u32>;
+#endif
+@group(0) @binding(3) var<uniform> billboard_view : BillboardView;
+#ifdef FOG
+@group(0) @binding(4) var<uniform> fog : Fog;
+#endif
+#ifdef LIGHTING_2D
+@group(0) @binding(5) var<uniform> lights_2d : Lights2d;
+#endif
 @group(1) @binding(0) var<storage, read> particle_buffer : ParticleBuffer;
 @group(1) @binding(1) var<storage, read> indirect_buffer : IndirectBuffer;
 @group(1) @binding(2) var<storage, read> dispatch_indirect : DispatchIndirect;
 #ifdef RENDER_NEEDS_SPAWNER
 @group(1) @binding(3) var<storage, read> spawner : Spawner; // NOTE - same group as update
 #endif
+{{TRAIL_BINDING}}
 #ifdef PARTICLE_TEXTURE
 @group(2) @binding(0) var particle_texture: texture_2d<f32>;
 @group(2) @binding(1) var particle_sampler: sampler;
 #endif
-// #ifdef PARTICLE_GRADIENTS
-// @group(3) @binding(0) var gradient_texture: texture_2d<f32>;
-// @group(3) @binding(1) var gradient_sampler: sampler;
-// #endif
+#ifdef COLOR_LUT
+@group(3) @binding(0) var color_lut_texture: texture_2d<f32>;
+@group(3) @binding(1) var color_lut_sampler: sampler;
+#endif
 
 fn get_camera_position_effect_space() -> vec3<f32> {
-    let view_pos = view.view[3].xyz;
+    let view_pos = billboard_view.view[3].xyz;
 #ifdef LOCAL_SPACE_SIMULATION
     let inverse_transform = transpose(
         mat3x3(
@@ -55,7 +120,11 @@ fn get_camera_position_effect_space() -> vec3<f32> {
 }
 
 fn get_camera_rotation_effect_space() -> mat3x3<f32> {
-    let view_rot = mat3x3(view.view[0].xyz, view.view[1].xyz, view.view[2].xyz);
+    let view_rot = mat3x3(
+        billboard_view.view[0].xyz,
+        billboard_view.view[1].xyz,
+        billboard_view.view[2].xyz
+    );
 #ifdef LOCAL_SPACE_SIMULATION
     let inverse_transform = transpose(
         mat3x3(
@@ -105,11 +174,197 @@ fn transform_position_simulation_to_clip(sim_position: vec3<f32>) -> vec4<f32> {
     return view.view_proj * transform_position_simulation_to_world(sim_position);
 }
 
+#ifdef FOG
+const FOG_MODE_LINEAR: u32 = 1u;
+const FOG_MODE_EXPONENTIAL: u32 = 2u;
+const FOG_MODE_EXPONENTIAL_SQUARED: u32 = 3u;
+const FOG_MODE_ATMOSPHERIC: u32 = 4u;
+
+/// Apply the fog of the view to a particle fragment, like bevy_pbr::fog does
+/// for meshes, except for the directional light inscattering.
+fn apply_fog(color: vec4<f32>, world_position: vec3<f32>) -> vec4<f32> {
+    let distance = length(world_position - view.world_position);
+    var fog_color = fog.base_color;
+    if (fog.mode == FOG_MODE_LINEAR) {
+        let start = fog.be.x;
+        let end = fog.be.y;
+        fog_color.a *= 1.0 - clamp((end - distance) / (end - start), 0.0, 1.0);
+    } else if (fog.mode == FOG_MODE_EXPONENTIAL) {
+        fog_color.a *= 1.0 - 1.0 / exp(distance * fog.be.x);
+    } else if (fog.mode == FOG_MODE_EXPONENTIAL_SQUARED) {
+        fog_color.a *= 1.0 - 1.0 / exp(pow(distance * fog.be.x, 2.0));
+    } else if (fog.mode == FOG_MODE_ATMOSPHERIC) {
+        let extinction_factor = 1.0 - 1.0 / exp(distance * fog.be);
+        let inscattering_factor = 1.0 - 1.0 / exp(distance * fog.bi);
+        return vec4<f32>(
+            color.rgb * (1.0 - extinction_factor * fog_color.a)
+                + fog_color.rgb * inscattering_factor * fog_color.a,
+            color.a
+        );
+    } else {
+        return color;
+    }
+    return vec4<f32>(mix(color.rgb, fog_color.rgb, fog_color.a), color.a);
+}
+#endif
+
+#ifdef LIGHTING_2D
+/// Get the light received at a world position by the 2D lighting, as the ambient
+/// light plus the point lights, each falling off smoothly to zero at its radius.
+/// The lights ignore the Z coordinate.
+fn lighting_2d(world_position: vec2<f32>) -> vec3<f32> {
+    var light = lights_2d.ambient.rgb;
+    for (var i = 0u; i < lights_2d.point_light_count; i += 1u) {
+        let point_light = lights_2d.point_lights[i];
+        let radius = point_light.position_radius.z;
+        let distance = length(world_position - point_light.position_radius.xy);
+        let falloff = saturate(1.0 - (distance * distance) / max(radius * radius, 1e-6));
+        light += point_light.color.rgb * (falloff * falloff);
+    }
+    return light;
+}
+#endif
+
+#ifdef DEFERRED_PREPASS
+// Surface properties: roughness, metallic, reflectance, and unlit flag
+const deferred_material: vec4<f32> = {{DEFERRED_MATERIAL}};
+
+// Lighting pass of the Bevy StandardMaterial
+const DEFERRED_LIGHTING_PASS_ID: u32 = 1u;
+const DEFERRED_FLAGS_UNLIT_BIT: u32 = 1u;
+const DEFERRED_FLAGS_FOG_ENABLED_BIT: u32 = 2u;
+const DEFERRED_MESH_FLAGS_SHADOW_RECEIVER_BIT: u32 = 4u;
+
+/// Transform a simulation space direction into a world space direction.
+fn transform_direction_simulation_to_world(sim_direction: vec3<f32>) -> vec3<f32> {
+#ifdef LOCAL_SPACE_SIMULATION
+    let transform = unpack_compressed_transform(spawner.transform);
+    return (transform * vec4<f32>(sim_direction, 0.0)).xyz;
+#else
+    return sim_direction;
+#endif
+}
+
+/// Encode a unit vector into the [0:1]^2 square with an octahedral mapping,
+/// like bevy_pbr::utils::octahedral_encode().
+fn octahedral_encode(v: vec3<f32>) -> vec2<f32> {
+    let n = v / (abs(v.x) + abs(v.y) + abs(v.z));
+    let octahedral_wrap = (1.0 - abs(n.yx)) * select(vec2(-1.0), vec2(1.0), n.xy > vec2(0.0));
+    let n_xy = select(octahedral_wrap, n.xy, n.z >= 0.0);
+    return n_xy * 0.5 + 0.5;
+}
+
+/// Pack a color into the shared exponent RGB9E5 format, like
+/// bevy_pbr::rgb9e5::vec3_to_rgb9e5_().
+fn pack_rgb9e5(rgb_in: vec3<f32>) -> u32 {
+    let rgb = clamp(rgb_in, vec3(0.0), vec3(65408.0));
+    let max_rgb = max(rgb.r, max(rgb.g, rgb.b));
+    let floor_log2 = i32((bitcast<u32>(max_rgb) & 0x7F800000u) >> 23u) - 127;
+    var exp_shared = max(-16, floor_log2) + 16;
+    var denom = exp2(f32(exp_shared - 24));
+    if (i32(floor(max_rgb / denom + 0.5)) == 512) {
+        denom *= 2.0;
+        exp_shared += 1;
+    }
+    let n = vec3<u32>(floor(rgb / denom + 0.5));
+    return (u32(exp_shared) << 27u) | (n.b << 18u) | (n.g << 9u) | n.r;
+}
+
+/// Write a particle fragment into the G-buffer, with the same encoding as
+/// bevy_pbr::pbr_deferred_functions::deferred_gbuffer_from_pbr_input().
+fn deferred_output(in: VertexOutput, color: vec4<f32>) -> FragmentOutput {
+    let normal = normalize(in.world_normal);
+    let roughness = deferred_material.x;
+    let unlit = deferred_material.w > 0.5;
+
+    var base_color_srgb = vec3<f32>(0.0);
+    var emissive = vec3<f32>(0.0);
+    var flags = DEFERRED_FLAGS_FOG_ENABLED_BIT | DEFERRED_MESH_FLAGS_SHADOW_RECEIVER_BIT;
+    if (unlit) {
+        // Unlit particles store their color in the emissive component
+        emissive = color.rgb;
+        flags |= DEFERRED_FLAGS_UNLIT_BIT;
+    } else {
+        base_color_srgb = pow(color.rgb, vec3(1.0 / 2.2));
+    }
+    let props = pack4x8unorm(vec4<f32>(deferred_material.z, deferred_material.y, 1.0, in.position.z));
+    let octahedral_normal = octahedral_encode(normal);
+    let packed_normal = u32(saturate(octahedral_normal.x) * 4095.0 + 0.5)
+        | (u32(saturate(octahedral_normal.y) * 4095.0 + 0.5) << 12u)
+        | (flags << 24u);
+
+    var out: FragmentOutput;
+#ifdef NORMAL_PREPASS
+    out.normal = vec4<f32>(normal * 0.5 + 0.5, roughness);
+#endif
+#ifdef MOTION_VECTOR_PREPASS
+    // The previous position of the particles is not known
+    out.motion_vector = vec2<f32>(0.0);
+#endif
+    out.deferred = vec4<u32>(
+        pack4x8unorm(vec4<f32>(base_color_srgb, roughness)),
+        pack_rgb9e5(emissive),
+        props,
+        packed_normal,
+    );
+    out.deferred_lighting_pass_id = DEFERRED_LIGHTING_PASS_ID;
+    return out;
+}
+#endif
+
+{{BLOB_SHADOW}}
+
+#ifdef BLOB_SHADOW
+// Output a vertex of the blob shadow of a particle, from the vertex of its quad.
+// The particle center is projected along the shadow direction onto the shadow
+// plane, and the shadow disc is stretched along the direction projected onto the
+// plane, into the ellipse a sphere would cast.
+fn blob_shadow_vertex(
+    particle_out: VertexOutput,
+    position: vec3<f32>,
+    size: vec2<f32>,
+    vertex_position: vec3<f32>,
+) -> VertexOutput {
+    var out = particle_out;
+    let center = transform_position_simulation_to_world(position).xyz;
+    let cos_angle = dot(blob_shadow_normal, blob_shadow_direction);
+    let distance = (blob_shadow_distance - dot(blob_shadow_normal, center)) / cos_angle;
+    if (abs(cos_angle) < 1e-3 || distance < 0.0) {
+        // Degenerate quad, discarded by the rasterizer
+        out.position = vec4<f32>(0.0);
+        return out;
+    }
+
+    // Offset the shadow slightly above the plane to avoid z-fighting
+    let origin = center + blob_shadow_direction * distance + blob_shadow_normal * 1e-3;
+    var tangent = blob_shadow_direction - blob_shadow_normal * cos_angle;
+    if (dot(tangent, tangent) < 1e-6) {
+        tangent = cross(blob_shadow_normal, select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(blob_shadow_normal.x) > 0.9));
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(blob_shadow_normal, tangent);
+    let radius = max(size.x, size.y) * blob_shadow_scale;
+    let stretch = min(1.0 / abs(cos_angle), 4.0);
+    let world_position = origin
+        + tangent * (vertex_position.x * radius * stretch)
+        + bitangent * (vertex_position.y * radius);
+
+    out.position = view.view_proj * vec4<f32>(world_position, 1.0);
+#ifdef FOG
+    out.world_position = world_position;
+#endif
+    out.color = vec4<f32>(0.0, 0.0, 0.0, particle_out.color.a * blob_shadow_opacity);
+    out.blob_shadow = vec3<f32>(vertex_position.xy * 2.0, 1.0);
+    return out;
+}
+#endif
+
 {{RENDER_EXTRA}}
 
 @vertex
 fn vertex(
     @builtin(instance_index) instance_index: u32,
+    @builtin(vertex_index) vertex_index: u32,
     @location(0) vertex_position: vec3<f32>,
 #ifdef NEEDS_UV
     @location(1) vertex_uv: vec2<f32>,
@@ -117,8 +372,13 @@ fn vertex(
     // @location(1) vertex_color: u32,
     // @location(1) vertex_velocity: vec3<f32>,
 ) -> VertexOutput {
+#ifdef CULL_PARTICLES
+    // The cull pass wrote the indices of the particles visible in this view
+    let index = cull_index_buffer[instance_index];
+#else
     let pong = dispatch_indirect.pong;
     let index = indirect_buffer.indices[3u * instance_index + pong];
+#endif
     var particle = particle_buffer.particles[index];
     var out: VertexOutput;
 #ifdef NEEDS_UV
@@ -128,26 +388,73 @@ fn vertex(
     let ij = vec2<f32>(f32(particle.sprite_index % row_count), f32(particle.sprite_index / row_count));
     uv = (ij + uv) * {{FLIPBOOK_SCALE}};
 #endif
-    out.uv = uv;
 #endif  // NEEDS_UV
 
 {{INPUTS}}
 
 {{VERTEX_MODIFIERS}}
 
+#ifdef NEEDS_UV
+    out.uv = uv;
+#endif
+
     // Expand particle mesh vertex based on particle position ("origin"), and local
     // orientation and size of the particle mesh (currently: only quad).
     let vpos = vertex_position * vec3<f32>(size.x, size.y, 1.0);
     let sim_position = position + axis_x * vpos.x + axis_y * vpos.y;
     out.position = transform_position_simulation_to_clip(sim_position);
 
+#ifdef FOG
+    out.world_position = transform_position_simulation_to_world(sim_position).xyz;
+#endif
+
+#ifdef DEFERRED_PREPASS
+    // Particles are double-sided, so light the side facing the camera
+    var world_normal = normalize(transform_direction_simulation_to_world(axis_z));
+    let world_position = transform_position_simulation_to_world(sim_position).xyz;
+    if (dot(world_normal, view.world_position - world_position) < 0.0) {
+        world_normal = -world_normal;
+    }
+    out.world_normal = world_normal;
+#endif
+
     out.color = color;
 
+#ifdef BLOB_SHADOW
+    // The first quad of each particle is its blob shadow, drawn before the particle
+    out.blob_shadow = vec3<f32>(0.0);
+    if (vertex_index < 6u) {
+        return blob_shadow_vertex(out, position, size, vertex_position);
+    }
+#endif
+
     return out;
 }
 
 @fragment
+#ifdef DEFERRED_PREPASS
+fn fragment(in: VertexOutput) -> FragmentOutput {
+#else
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+#endif
+
+#ifdef OVERDRAW
+    // Count every rasterized fragment, whatever its color. With additive blending
+    // the red channel saturates after 8 layers, the green one after 24, and the
+    // blue one after 64, giving a black-red-yellow-white heatmap.
+    return vec4<f32>(1.0 / 8.0, 1.0 / 24.0, 1.0 / 64.0, 1.0);
+#else
+
+#ifdef BLOB_SHADOW
+    if (in.blob_shadow.z > 0.5) {
+        let edge = 1.0 - smoothstep(1.0 - blob_shadow_softness, 1.0, length(in.blob_shadow.xy));
+        var shadow = vec4<f32>(in.color.rgb, in.color.a * edge);
+#ifdef FOG
+        shadow = apply_fog(shadow, in.world_position);
+#endif
+        return shadow;
+    }
+#endif
 
 #ifdef USE_ALPHA_MASK
     var alpha_cutoff: f32 = {{ALPHA_CUTOFF}};
@@ -170,5 +477,14 @@ fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
     }
 #endif
 
+#ifdef DEFERRED_PREPASS
+    // Fog is applied by the deferred lighting pass
+    return deferred_output(in, color);
+#else
+#ifdef FOG
+    color = apply_fog(color, in.world_position);
+#endif
     return color;
+#endif
+#endif
 }
\ No newline at end of file
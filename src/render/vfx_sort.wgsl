@@ -0,0 +1,141 @@
+// Key/value radix sort.
+//
+// The pairs are stored as vec2<u32>, with the key in x and the value in y. Each
+// pass sorts the pairs by a single digit of RADIX_BITS bits of the keys, starting
+// from the least significant one. A pass is made of 3 dispatches:
+// - count: count the number of keys per digit in each block of WORKGROUP_SIZE keys;
+// - scan: exclusive prefix sum of the digit-major histogram of all blocks, which
+//   yields the output offset of the first key of each digit in each block;
+// - scatter: write each pair to its output offset, preserving the order of pairs
+//   with the same digit so that the sort is stable across passes.
+
+const RADIX_BITS: u32 = 4u;
+const RADIX_SIZE: u32 = 16u;
+const RADIX_MASK: u32 = 15u;
+const WORKGROUP_SIZE: u32 = 256u;
+
+struct SortParams {
+    // Number of key/value pairs to sort.
+    count: u32,
+    // Bit offset of the digit sorted by this pass.
+    shift: u32,
+    // Number of blocks of WORKGROUP_SIZE keys.
+    block_count: u32,
+}
+
+@group(0) @binding(0) var<uniform> params : SortParams;
+@group(0) @binding(1) var<storage, read> src_pairs : array<vec2<u32>>;
+@group(0) @binding(2) var<storage, read_write> dst_pairs : array<vec2<u32>>;
+@group(0) @binding(3) var<storage, read_write> histogram : array<u32>;
+
+var<workgroup> local_histogram : array<atomic<u32>, RADIX_SIZE>;
+var<workgroup> local_digits : array<u32, WORKGROUP_SIZE>;
+var<workgroup> scan_buffer : array<u32, WORKGROUP_SIZE>;
+var<workgroup> scan_carry : u32;
+
+fn digit_of(key: u32) -> u32 {
+    return (key >> params.shift) & RADIX_MASK;
+}
+
+/// Count the number of keys per digit in each block.
+@compute @workgroup_size(256)
+fn count(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let lid = local_id.x;
+    if (lid < RADIX_SIZE) {
+        atomicStore(&local_histogram[lid], 0u);
+    }
+    workgroupBarrier();
+
+    let index = workgroup_id.x * WORKGROUP_SIZE + lid;
+    if (index < params.count) {
+        atomicAdd(&local_histogram[digit_of(src_pairs[index].x)], 1u);
+    }
+    workgroupBarrier();
+
+    // Digit-major layout, so that an exclusive scan of the entire histogram
+    // yields the output offset of each digit of each block.
+    if (lid < RADIX_SIZE) {
+        histogram[lid * params.block_count + workgroup_id.x] = atomicLoad(&local_histogram[lid]);
+    }
+}
+
+/// Exclusive prefix sum of the histogram, in place, with a single workgroup.
+@compute @workgroup_size(256)
+fn scan(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    let lid = local_id.x;
+    let total = RADIX_SIZE * params.block_count;
+    if (lid == 0u) {
+        scan_carry = 0u;
+    }
+
+    for (var base = 0u; base < total; base += WORKGROUP_SIZE) {
+        let index = base + lid;
+        var value = 0u;
+        if (index < total) {
+            value = histogram[index];
+        }
+        scan_buffer[lid] = value;
+        workgroupBarrier();
+
+        // Inclusive scan of the chunk
+        for (var offset = 1u; offset < WORKGROUP_SIZE; offset <<= 1u) {
+            var sum = scan_buffer[lid];
+            if (lid >= offset) {
+                sum += scan_buffer[lid - offset];
+            }
+            workgroupBarrier();
+            scan_buffer[lid] = sum;
+            workgroupBarrier();
+        }
+
+        // Offset by the sum of all previous chunks, and make exclusive
+        let carry = scan_carry;
+        if (index < total) {
+            histogram[index] = carry + scan_buffer[lid] - value;
+        }
+        workgroupBarrier();
+        if (lid == WORKGROUP_SIZE - 1u) {
+            scan_carry = carry + scan_buffer[lid];
+        }
+        workgroupBarrier();
+    }
+}
+
+/// Write each key/value pair of each block to its sorted position.
+@compute @workgroup_size(256)
+fn scatter(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let lid = local_id.x;
+    let index = workgroup_id.x * WORKGROUP_SIZE + lid;
+
+    // Out-of-range threads use an invalid digit, never matched below
+    var pair = vec2<u32>(0u);
+    var digit = RADIX_SIZE;
+    if (index < params.count) {
+        pair = src_pairs[index];
+        digit = digit_of(pair.x);
+    }
+    local_digits[lid] = digit;
+    workgroupBarrier();
+
+    if (index >= params.count) {
+        return;
+    }
+
+    // Rank the key among the keys of the block with the same digit, preserving
+    // their relative order.
+    var rank = 0u;
+    for (var i = 0u; i < lid; i += 1u) {
+        if (local_digits[i] == digit) {
+            rank += 1u;
+        }
+    }
+
+    let dst_index = histogram[digit * params.block_count + workgroup_id.x] + rank;
+    dst_pairs[dst_index] = pair;
+}
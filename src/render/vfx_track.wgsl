@@ -0,0 +1,55 @@
+// Gathering of the position and velocity of the alive particles of a single group,
+// for readback. Each entry owns 1 + 7 * max_count words of the result buffer:
+// - [0] Number of particles written, at most max_count.
+// - [1 + 7 * i] Index of the particle #i in the particle buffer.
+// - [2 + 7 * i .. 5 + 7 * i] Position of the particle #i, in simulation space.
+// - [5 + 7 * i .. 8 + 7 * i] Velocity of the particle #i, in simulation space.
+
+struct TrackParams {
+    // Size of a single particle, in words.
+    particle_stride: u32,
+    // Offset of the position attribute in a particle, in words.
+    position_offset: u32,
+    // Offset of the velocity attribute in a particle, in words, or 0xFFFFFFFF if none.
+    velocity_offset: u32,
+    // Offset of the render group indirect arguments of the group, in words.
+    render_group_word: u32,
+    // Offset of the render effect metadata of the effect, in words.
+    render_effect_word: u32,
+    // Maximum number of particles written.
+    max_count: u32,
+    // Offset of the entry in the result buffer, in words.
+    result_word: u32,
+}
+
+@group(0) @binding(0) var<storage, read> params : TrackParams;
+@group(0) @binding(5) var<storage, read_write> result_buffer : array<u32>;
+
+@compute @workgroup_size(64)
+fn track(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let thread_index = global_invocation_id.x;
+    let count = min(alive_count(params.render_group_word), params.max_count);
+    if (thread_index == 0u) {
+        result_buffer[params.result_word] = count;
+    }
+    if (thread_index >= count) {
+        return;
+    }
+    let index = alive_particle_index(params.render_group_word, params.render_effect_word, thread_index);
+
+    let base = index * params.particle_stride;
+    let position = read_vec3(base + params.position_offset);
+    var velocity = vec3<u32>(0u);
+    if (params.velocity_offset != 0xFFFFFFFFu) {
+        velocity = read_vec3(base + params.velocity_offset);
+    }
+
+    let out = params.result_word + 1u + 7u * thread_index;
+    result_buffer[out] = index;
+    result_buffer[out + 1u] = position.x;
+    result_buffer[out + 2u] = position.y;
+    result_buffer[out + 3u] = position.z;
+    result_buffer[out + 4u] = velocity.x;
+    result_buffer[out + 5u] = velocity.y;
+    result_buffer[out + 6u] = velocity.z;
+}
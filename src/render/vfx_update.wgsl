@@ -14,39 +14,333 @@ struct ParticleBuffer {
 
 {{PROPERTIES}}
 
-@group(0) @binding(0) var<uniform> sim_params : SimParams;
+// A static collider, mirroring GpuStaticCollider in collider.rs.
+struct StaticCollider {
+    // 1 = circle, 2 = rectangle, 3 = segment, 4 = sphere, 5 = box, 6 = capsule, or 0 for
+    // an unused entry.
+    kind: u32,
+    radius: f32,
+    a: vec3<f32>,
+    b: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> global_sim_params : SimParams;
+@group(0) @binding(1) var voxel_grid : texture_3d<u32>;
+@group(0) @binding(2) var collision_bvh : texture_2d<f32>;
+#ifdef STATIC_COLLIDERS
+@group(0) @binding(3) var<storage, read> static_colliders : array<StaticCollider>;
+#endif
 @group(1) @binding(0) var<storage, read_write> particle_buffer : ParticleBuffer;
 @group(1) @binding(1) var<storage, read_write> indirect_buffer : IndirectBuffer;
+#ifndef BATCHED_UPDATE
 @group(1) @binding(2) var<storage, read> particle_groups : array<ParticleGroup>;
+#endif
 {{PROPERTIES_BINDING}}
+{{TRAIL_BINDING}}
+#ifdef BATCHED_UPDATE
+// Per-instance data of a batched update pass, mirroring GpuUpdateInstance in
+// render/mod.rs. Each workgroup row (workgroup_id.y) of the dispatch updates a
+// different effect instance sharing the same effect buffer.
+struct UpdateInstance {
+    // Index of the Spawner of the instance in the spawner buffer.
+    spawner_index: u32,
+    // Index of the RenderEffectMetadata of the instance.
+    render_effect_index: u32,
+    // Index of the RenderGroupIndirect of the first group of the instance.
+    render_group_base: u32,
+    // Index of the ParticleGroup of the first group of the instance.
+    particle_group_base: u32,
+}
+
+@group(2) @binding(0) var<storage, read> spawners : array<Spawner>;
+@group(2) @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>;
+@group(3) @binding(0) var<storage, read_write> render_effect_indirects : array<RenderEffectMetadata>;
+@group(3) @binding(1) var<storage, read_write> render_group_indirect : array<RenderGroupIndirect>;
+@group(3) @binding(2) var<storage, read> update_instances : array<UpdateInstance>;
+@group(3) @binding(3) var<storage, read> particle_groups : array<ParticleGroup>;
+
+// Spawner of the instance updated by the current workgroup.
+var<private> spawner : Spawner;
+#else
 @group(2) @binding(0) var<storage, read_write> spawner : Spawner; // NOTE - same group as init
+@group(2) @binding(1) var<storage, read_write> event_buffer : array<atomic<u32>>;
 @group(3) @binding(0) var<storage, read_write> render_effect_indirect : RenderEffectMetadata;
 @group(3) @binding(1) var<storage, read_write> render_group_indirect : array<RenderGroupIndirect>;
+#endif
+
+// Index of the first ParticleGroup and RenderGroupIndirect of the updated instance.
+// Those are always zero outside of a batched update pass, whose arrays are bound
+// at the instance offset.
+var<private> particle_group_base : u32 = 0u;
+var<private> render_group_base : u32 = 0u;
+
+// Index of the ping buffer of particle indices, written into by this pass.
+var<private> ping : u32;
+
+// Simulation parameters for this effect, with the per-effect time scale applied.
+var<private> sim_params : SimParams;
+
+// Count a particle event for the child effects of this effect, if any.
+fn emit_parent_event(kind: u32) {
+    if (spawner.event_slot != 0xFFFFFFFFu) {
+        atomicAdd(&event_buffer[spawner.event_slot + kind], 1u);
+    }
+}
+
+// Map a float to an unsigned integer with the same ordering.
+fn order_f32(value: f32) -> u32 {
+    let bits = bitcast<u32>(value);
+    if ((bits & 0x80000000u) != 0u) {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+// Accumulate a position, in the local space of the effect, into the bounds of the
+// effect. The bounds are stored in the event buffer, after the event counters. The
+// minimum coordinates are stored inverted, so that all coordinates are reduced with
+// atomicMax() and cleared bounds (all zeros) are empty.
+fn accumulate_bounds(position: vec3<f32>) {
+    let base = spawner.bounds_slot * 8u;
+    atomicMax(&event_buffer[base], ~order_f32(position.x));
+    atomicMax(&event_buffer[base + 1u], ~order_f32(position.y));
+    atomicMax(&event_buffer[base + 2u], ~order_f32(position.z));
+    atomicMax(&event_buffer[base + 4u], order_f32(position.x));
+    atomicMax(&event_buffer[base + 5u], order_f32(position.y));
+    atomicMax(&event_buffer[base + 6u], order_f32(position.z));
+}
+
+// Accumulate the momentum of a particle absorbed by a force receiver, in simulation
+// space, into the event buffer. The momentum is stored in 22.10 fixed point, as
+// floats can't be added atomically, followed by the number of absorbed particles.
+fn accumulate_impulse(momentum: vec3<f32>) {
+    let base = spawner.feedback_slot * 4u;
+    let fixed = vec3<i32>(round(momentum * 1024.0));
+    atomicAdd(&event_buffer[base], bitcast<u32>(fixed.x));
+    atomicAdd(&event_buffer[base + 1u], bitcast<u32>(fixed.y));
+    atomicAdd(&event_buffer[base + 2u], bitcast<u32>(fixed.z));
+    atomicAdd(&event_buffer[base + 3u], 1u);
+}
+
+// Check if a cell of the voxel grid is filled. Cells outside the grid are empty.
+fn is_voxel_filled(cell: vec3<i32>) -> bool {
+    let size = vec3<i32>(textureDimensions(voxel_grid));
+    if (any(cell < vec3<i32>(0)) || any(cell >= size)) {
+        return false;
+    }
+    return textureLoad(voxel_grid, cell, 0).r != 0u;
+}
+
+// Load a texel of the collision BVH.
+fn bvh_texel(index: u32) -> vec4<f32> {
+    let width = textureDimensions(collision_bvh).x;
+    return textureLoad(collision_bvh, vec2<u32>(index % width, index / width), 0);
+}
+
+// Find the closest triangle of the collision BVH hit by a segment. Returns the
+// triangle normal in xyz and the hit fraction along the segment in w, or a
+// fraction of 2.0 if nothing was hit.
+fn raycast_bvh(origin: vec3<f32>, delta: vec3<f32>) -> vec4<f32> {
+    var hit = vec4<f32>(0.0, 0.0, 0.0, 2.0);
+    let node_count = u32(bvh_texel(0u).x);
+    if (node_count == 0u) {
+        return hit;
+    }
+    let first_triangle = 1u + 2u * node_count;
+    let inv_delta = 1.0 / delta;
+
+    var stack: array<u32, 32>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+    while (stack_size > 0u) {
+        stack_size -= 1u;
+        let node = stack[stack_size];
+        let lo = bvh_texel(1u + 2u * node);
+        let hi = bvh_texel(2u + 2u * node);
+
+        // Slab test of the segment against the node bounds
+        let t0 = (lo.xyz - origin) * inv_delta;
+        let t1 = (hi.xyz - origin) * inv_delta;
+        let t_min = min(t0, t1);
+        let t_max = max(t0, t1);
+        let t_near = max(max(t_min.x, t_min.y), max(t_min.z, 0.0));
+        let t_far = min(min(t_max.x, t_max.y), min(t_max.z, hit.w));
+        if (t_near > t_far) {
+            continue;
+        }
+
+        let count = u32(hi.w);
+        if (count == 0u) {
+            // Internal node; the left child immediately follows it
+            if (stack_size + 2u <= 32u) {
+                stack[stack_size] = u32(lo.w);
+                stack[stack_size + 1u] = node + 1u;
+                stack_size += 2u;
+            }
+            continue;
+        }
+
+        // Leaf node; intersect its triangles (Moller-Trumbore)
+        for (var i = 0u; i < count; i += 1u) {
+            let base = first_triangle + 3u * (u32(lo.w) + i);
+            let v0 = bvh_texel(base).xyz;
+            let e1 = bvh_texel(base + 1u).xyz - v0;
+            let e2 = bvh_texel(base + 2u).xyz - v0;
+            let p = cross(delta, e2);
+            let det = dot(e1, p);
+            if (abs(det) < 1e-12) {
+                continue;
+            }
+            let inv_det = 1.0 / det;
+            let s = origin - v0;
+            let u = dot(s, p) * inv_det;
+            let q = cross(s, e1);
+            let v = dot(delta, q) * inv_det;
+            let t = dot(e2, q) * inv_det;
+            if (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t >= 0.0 && t < hit.w) {
+                hit = vec4<f32>(normalize(cross(e1, e2)), t);
+            }
+        }
+    }
+    return hit;
+}
+
+// Signed distance of a position to a 2D static collider in the XY plane. Returns the
+// outward normal of the collider at the closest point in xyz and the distance in w,
+// negative inside the collider.
+fn static_collider_distance_2d(collider: StaticCollider, position: vec2<f32>) -> vec4<f32> {
+    if (collider.kind == 2u) {
+        // Rectangle
+        let q = position - collider.a.xy;
+        let a = abs(q) - collider.b.xy;
+        let s = select(vec2<f32>(-1.0), vec2<f32>(1.0), q >= vec2<f32>(0.0));
+        if (a.x > 0.0 || a.y > 0.0) {
+            // Outside, the closest point is on an edge or a corner
+            let o = max(a, vec2<f32>(0.0));
+            let dist = length(o);
+            return vec4<f32>(o * s / dist, 0.0, dist);
+        } else if (a.x > a.y) {
+            // Inside, push toward the closest edge
+            return vec4<f32>(s.x, 0.0, 0.0, a.x);
+        }
+        return vec4<f32>(0.0, s.y, 0.0, a.y);
+    }
+
+    // Circle, or segment with a thickness
+    var closest = collider.a.xy;
+    if (collider.kind == 3u) {
+        let ab = collider.b.xy - closest;
+        let t = saturate(dot(position - closest, ab) / max(dot(ab, ab), 1e-12));
+        closest += ab * t;
+    }
+    let delta = position - closest;
+    let len = length(delta);
+    let n = select(vec2<f32>(0.0, 1.0), delta / len, len > 0.0);
+    return vec4<f32>(n, 0.0, len - collider.radius);
+}
+
+// Signed distance of a position to a 3D static collider. Returns the outward normal of
+// the collider at the closest point in xyz and the distance in w, negative inside the
+// collider.
+fn static_collider_distance_3d(collider: StaticCollider, position: vec3<f32>) -> vec4<f32> {
+    if (collider.kind == 5u) {
+        // Box
+        let q = position - collider.a;
+        let a = abs(q) - collider.b;
+        let s = select(vec3<f32>(-1.0), vec3<f32>(1.0), q >= vec3<f32>(0.0));
+        if (any(a > vec3<f32>(0.0))) {
+            // Outside, the closest point is on a face, an edge, or a corner
+            let o = max(a, vec3<f32>(0.0));
+            let dist = length(o);
+            return vec4<f32>(o * s / dist, dist);
+        } else if (a.x > a.y && a.x > a.z) {
+            // Inside, push toward the closest face
+            return vec4<f32>(s.x, 0.0, 0.0, a.x);
+        } else if (a.y > a.z) {
+            return vec4<f32>(0.0, s.y, 0.0, a.y);
+        }
+        return vec4<f32>(0.0, 0.0, s.z, a.z);
+    }
+
+    // Sphere, or capsule
+    var closest = collider.a;
+    if (collider.kind == 6u) {
+        let ab = collider.b - closest;
+        let t = saturate(dot(position - closest, ab) / max(dot(ab, ab), 1e-12));
+        closest += ab * t;
+    }
+    let delta = position - closest;
+    let len = length(delta);
+    let n = select(vec3<f32>(0.0, 1.0, 0.0), delta / len, len > 0.0);
+    return vec4<f32>(n, len - collider.radius);
+}
+
+#ifdef STATIC_COLLIDERS
+// Find the closest static collider to a position, in world space. Returns the outward
+// normal of the collider at the closest point in xyz and the signed distance in w, or
+// a distance of 3.4e38 if there's no collider.
+fn closest_static_collider(position: vec3<f32>) -> vec4<f32> {
+    var closest = vec4<f32>(0.0, 0.0, 0.0, 3.4e38);
+    let count = arrayLength(&static_colliders);
+    for (var i = 0u; i < count; i += 1u) {
+        let collider = static_colliders[i];
+        if (collider.kind == 0u) {
+            continue;
+        }
+        var hit: vec4<f32>;
+        if (collider.kind >= 4u) {
+            hit = static_collider_distance_3d(collider, position);
+        } else {
+            hit = static_collider_distance_2d(collider, position.xy);
+        }
+        if (hit.w < closest.w) {
+            closest = hit;
+        }
+    }
+    return closest;
+}
+#endif
 
 {{UPDATE_EXTRA}}
 
-@compute @workgroup_size(64)
-fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+@compute @workgroup_size(#{WORKGROUP_SIZE})
+fn main(
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
     let thread_index = global_invocation_id.x;
 
+#ifdef BATCHED_UPDATE
+    // Select the instance updated by this workgroup row
+    let instance = update_instances[workgroup_id.y];
+    spawner = spawners[instance.spawner_index];
+    particle_group_base = instance.particle_group_base;
+    render_group_base = instance.render_group_base;
+    let render_effect = &render_effect_indirects[instance.render_effect_index];
+#else
+    let render_effect = &render_effect_indirect;
+#endif
+    let group_index = particle_group_base + #{GROUP_INDEX}u;
+    let render_group_index = render_group_base + #{GROUP_INDEX}u;
+
     // Cap at maximum number of particles.
     // FIXME - This is probably useless given below cap
-    let max_particles : u32 = particle_groups[{{GROUP_INDEX}}].capacity;
+    let max_particles : u32 = particle_groups[group_index].capacity;
     if (thread_index >= max_particles) {
         return;
     }
 
     // Cap at maximum number of alive particles.
-    if (thread_index >= render_group_indirect[{{GROUP_INDEX}}].max_update) {
+    if (thread_index >= render_group_indirect[render_group_index].max_update) {
         return;
     }
 
     // Always write into ping, read from pong
-    let ping = render_effect_indirect.ping;
+    ping = (*render_effect).ping;
     let pong = 1u - ping;
 
-    let effect_particle_offset = particle_groups[{{GROUP_INDEX}}].effect_particle_offset;
-    let base_index = effect_particle_offset + particle_groups[{{GROUP_INDEX}}].indirect_index;
+    let effect_particle_offset = particle_groups[group_index].effect_particle_offset;
+    let base_index = effect_particle_offset + particle_groups[group_index].indirect_index;
     let index = indirect_buffer.indices[3u * (base_index + thread_index) + pong];
 
     var particle: Particle = particle_buffer.particles[index];
@@ -54,24 +348,45 @@ fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     // Update PRNG seed
     seed = pcg_hash(index ^ spawner.seed);
 
+    // Apply the per-effect time scale
+    sim_params = global_sim_params;
+    sim_params.delta_time *= spawner.time_scale;
+
+    // Convert the particle into the simulation space if it just changed
+    {{SPACE_CONVERSION_CODE}}
+
     {{AGE_CODE}}
     {{UPDATE_CODE}}
     {{REAP_CODE}}
 
+    // Kill all particles of this group if requested
+    if ((spawner.kill_groups & (1u << #{GROUP_INDEX}u)) != 0u) {
+        is_alive = false;
+    }
+
     particle_buffer.particles[index] = particle;
 
     // Check if alive
     if (!is_alive) {
+        // Count a death event for child effects
+        emit_parent_event(0u);
+
         // Save dead index
-        let dead_index = atomicAdd(&render_group_indirect[{{GROUP_INDEX}}].dead_count, 1u);
+        let dead_index = atomicAdd(&render_group_indirect[render_group_index].dead_count, 1u);
         indirect_buffer.indices[3u * (base_index + dead_index) + 2u] = index;
         // Also increment copy of dead count, which was updated in dispatch indirect
         // pass just before, and need to remain correct after this pass
-        atomicAdd(&render_effect_indirect.max_spawn, 1u);
-        atomicSub(&render_group_indirect[{{GROUP_INDEX}}].alive_count, 1u);
+        atomicAdd(&(*render_effect).max_spawn, 1u);
+        atomicSub(&render_group_indirect[render_group_index].alive_count, 1u);
     } else {
         // Increment alive particle count and write indirection index for later rendering
-        let indirect_index = atomicAdd(&render_group_indirect[{{GROUP_INDEX}}].instance_count, 1u);
+        let indirect_index = atomicAdd(&render_group_indirect[render_group_index].instance_count, 1u);
         indirect_buffer.indices[3u * (base_index + indirect_index) + ping] = index;
+
+        {{BOUNDS_CODE}}
     }
 }
+
+#ifndef BATCHED_UPDATE
+{{CULL_CODE}}
+#endif
@@ -0,0 +1,223 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        texture::TextureFormatPixelInfo,
+    },
+};
+
+use crate::CompiledParticleEffect;
+
+/// Component rendering into offscreen images, for effects sampling the output
+/// of other effects or their own.
+///
+/// Insert this component on a [`Camera`] entity to render into the given
+/// images instead of a window. Like any camera, it draws the effect instances
+/// visible from it; use [`RenderLayers`] to restrict it to the effects feeding
+/// the image. The images are then sampled by the effects with an
+/// [`EffectRenderTargetSource`].
+///
+/// With two images, the camera renders alternately into each image, so that
+/// the effects can sample the image rendered during the previous frame while
+/// the other one is rendered. This allows an effect to sample its own output,
+/// for example to draw a full-screen particle blending the previous frame into
+/// the current one for feedback trails. With a single image, the image
+/// rendered is also the one sampled, so the effects sampling it must not be
+/// drawn by the camera itself. Disabling the clear of the camera with
+/// [`ClearColorConfig::None`] accumulates the particles of all frames in the
+/// image, for example for a heat map.
+///
+/// The images must be usable as render attachments, like the ones created
+/// with [`EffectRenderTarget::create_image()`].
+///
+/// # Example
+///
+/// ```
+/// # use bevy::{prelude::*, render::{render_resource::TextureFormat, view::RenderLayers}};
+/// # use bevy_hanabi::*;
+/// fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, effect: Res<MyTrail>) {
+///     let mut create_image = || {
+///         images.add(EffectRenderTarget::create_image(
+///             UVec2::splat(512),
+///             TextureFormat::Rgba8UnormSrgb,
+///         ))
+///     };
+///     let target = EffectRenderTarget::ping_pong(create_image(), create_image());
+///
+///     // Camera rendering the effects of layer 1 into the images
+///     let camera = commands
+///         .spawn((Camera3dBundle::default(), RenderLayers::layer(1), target))
+///         .id();
+///
+///     // Effect rendered by the camera and sampling its previous output
+///     commands.spawn((
+///         ParticleEffectBundle::new(effect.0.clone()),
+///         RenderLayers::layer(1),
+///         EffectRenderTargetSource(camera),
+///     ));
+/// }
+/// # #[derive(Resource)]
+/// # struct MyTrail(Handle<EffectAsset>);
+/// ```
+///
+/// [`RenderLayers`]: bevy::render::view::RenderLayers
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectRenderTarget {
+    /// Images rendered into, alternately if more than one.
+    images: Vec<Handle<Image>>,
+    /// Index of the image rendered into this frame.
+    current: usize,
+}
+
+impl Default for EffectRenderTarget {
+    fn default() -> Self {
+        Self::single(Handle::default())
+    }
+}
+
+impl EffectRenderTarget {
+    /// Render into a single image, which is also the one sampled.
+    pub fn single(image: Handle<Image>) -> Self {
+        Self {
+            images: vec![image],
+            current: 0,
+        }
+    }
+
+    /// Render alternately into two images, sampling the one not rendered into.
+    pub fn ping_pong(image0: Handle<Image>, image1: Handle<Image>) -> Self {
+        Self {
+            images: vec![image0, image1],
+            current: 0,
+        }
+    }
+
+    /// Create an image which can be both rendered into and sampled.
+    ///
+    /// The image is cleared to transparent black, and only exists on GPU.
+    pub fn create_image(size: UVec2, format: TextureFormat) -> Image {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: size.x.max(1),
+                height: size.y.max(1),
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &vec![0; format.pixel_size()],
+            format,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_DST
+            | TextureUsages::RENDER_ATTACHMENT;
+        image
+    }
+
+    /// Get the image rendered into this frame.
+    pub fn render_image(&self) -> &Handle<Image> {
+        &self.images[self.current]
+    }
+
+    /// Get the image sampled this frame.
+    ///
+    /// With two images, this is the one rendered during the previous frame.
+    pub fn sampled_image(&self) -> &Handle<Image> {
+        &self.images[(self.current + 1) % self.images.len()]
+    }
+}
+
+/// Component overriding the particle texture of an effect instance with the
+/// image sampled from an [`EffectRenderTarget`].
+///
+/// The component holds the entity of the camera with the render target, which
+/// may be rendering the effect itself. Like [`ParticleEffect::texture`], this
+/// replaces the texture of all groups sampling a particle texture, but doesn't
+/// require new shaders when the image changes each frame.
+///
+/// [`ParticleEffect::texture`]: crate::ParticleEffect::texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectRenderTargetSource(pub Entity);
+
+impl Default for EffectRenderTargetSource {
+    fn default() -> Self {
+        Self(Entity::PLACEHOLDER)
+    }
+}
+
+/// Swap the images of the [`EffectRenderTarget`]s, and bind the sampled images
+/// to the effects with an [`EffectRenderTargetSource`].
+pub(crate) fn update_effect_render_targets(
+    mut targets: Query<(&mut EffectRenderTarget, &mut Camera)>,
+    mut effects: Query<(&EffectRenderTargetSource, &mut CompiledParticleEffect)>,
+) {
+    for (mut target, mut camera) in &mut targets {
+        if target.images.len() > 1 {
+            target.current = (target.current + 1) % target.images.len();
+        }
+        let render_image = target.render_image().clone();
+        if !matches!(&camera.target, RenderTarget::Image(image) if *image == render_image) {
+            camera.target = RenderTarget::Image(render_image);
+        }
+    }
+
+    for (source, mut compiled) in &mut effects {
+        let Ok((target, _)) = targets.get(source.0) else {
+            continue;
+        };
+        let image = target.sampled_image();
+        if compiled.texture.as_ref() != Some(image) {
+            compiled.texture = Some(image.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap() {
+        let mut app = App::new();
+        app.add_systems(Update, update_effect_render_targets);
+
+        let image0 = Handle::weak_from_u128(0x1234);
+        let image1 = Handle::weak_from_u128(0x5678);
+        let camera = app
+            .world
+            .spawn((
+                Camera::default(),
+                EffectRenderTarget::ping_pong(image0.clone(), image1.clone()),
+            ))
+            .id();
+        let effect = app
+            .world
+            .spawn((
+                EffectRenderTargetSource(camera),
+                CompiledParticleEffect::default(),
+            ))
+            .id();
+
+        for (render_image, sampled_image) in [(&image1, &image0), (&image0, &image1)] {
+            app.update();
+            let target = &app.world.get::<Camera>(camera).unwrap().target;
+            assert!(matches!(target, RenderTarget::Image(image) if image == render_image));
+            let compiled = app.world.get::<CompiledParticleEffect>(effect).unwrap();
+            assert_eq!(compiled.texture.as_ref(), Some(sampled_image));
+        }
+    }
+
+    #[test]
+    fn create_image() {
+        let image =
+            EffectRenderTarget::create_image(UVec2::new(64, 32), TextureFormat::Rgba16Float);
+        assert_eq!(image.size(), UVec2::new(64, 32));
+        assert!(image
+            .texture_descriptor
+            .usage
+            .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING));
+    }
+}
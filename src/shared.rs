@@ -0,0 +1,155 @@
+use bevy::{
+    asset::{Asset, AssetEvent, Assets},
+    ecs::{event::EventReader, system::Res, system::ResMut},
+    log::trace,
+    math::{Vec2, Vec4},
+    reflect::TypePath,
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ColorOverLifetimeAssetModifier, Curve, EffectAsset, Gradient, Modifier,
+    SizeOverLifetimeAssetModifier,
+};
+
+/// A color gradient stored as a standalone asset, to be shared by several
+/// effects.
+///
+/// Reference the gradient from an effect with a
+/// [`ColorOverLifetimeAssetModifier`]. Editing the gradient asset updates all
+/// the effects referencing it, which allows for example authoring a palette
+/// ramp once and reusing it across a whole project.
+#[derive(Asset, TypePath, Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorGradient(pub Gradient<Vec4>);
+
+/// A size curve stored as a standalone asset, to be shared by several
+/// effects.
+///
+/// Reference the curve from an effect with a
+/// [`SizeOverLifetimeAssetModifier`]. Editing the curve asset updates all the
+/// effects referencing it.
+#[derive(Asset, TypePath, Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SizeCurve(pub Curve<Vec2>);
+
+/// Check if a modifier references a shared asset whose content changed since
+/// it was last resolved.
+fn is_stale(
+    modifier: &dyn Modifier,
+    gradients: &Assets<ColorGradient>,
+    curves: &Assets<SizeCurve>,
+) -> bool {
+    let any = modifier.as_any();
+    if let Some(m) = any.downcast_ref::<ColorOverLifetimeAssetModifier>() {
+        gradients
+            .get(&m.gradient)
+            .map_or(!m.resolved.is_empty(), |g| g.0 != m.resolved)
+    } else if let Some(m) = any.downcast_ref::<SizeOverLifetimeAssetModifier>() {
+        curves
+            .get(&m.curve)
+            .map_or(!m.resolved.is_empty(), |c| c.0 != m.resolved)
+    } else {
+        false
+    }
+}
+
+/// Copy the current content of the shared asset referenced by a modifier into
+/// that modifier. Assets not loaded resolve to an empty gradient or curve.
+fn resolve(
+    modifier: &mut dyn Modifier,
+    gradients: &Assets<ColorGradient>,
+    curves: &Assets<SizeCurve>,
+) {
+    let any = modifier.as_any_mut();
+    if let Some(m) = any.downcast_mut::<ColorOverLifetimeAssetModifier>() {
+        m.resolved = gradients
+            .get(&m.gradient)
+            .map(|g| g.0.clone())
+            .unwrap_or_default();
+    } else if let Some(m) = any.downcast_mut::<SizeOverLifetimeAssetModifier>() {
+        m.resolved = curves
+            .get(&m.curve)
+            .map(|c| c.0.clone())
+            .unwrap_or_default();
+    }
+}
+
+/// Update the effect assets referencing a shared [`ColorGradient`] or
+/// [`SizeCurve`] asset.
+///
+/// The content of the shared assets is baked into the shaders of the effects,
+/// so when a shared asset changes, this system copies its new content into the
+/// modifiers referencing it. This modifies the [`EffectAsset`], and therefore
+/// recompiles all its instances like hot-reloading the effect would.
+pub fn update_shared_assets(
+    mut gradient_events: EventReader<AssetEvent<ColorGradient>>,
+    mut curve_events: EventReader<AssetEvent<SizeCurve>>,
+    mut effect_events: EventReader<AssetEvent<EffectAsset>>,
+    gradients: Res<Assets<ColorGradient>>,
+    curves: Res<Assets<SizeCurve>>,
+    mut effects: ResMut<Assets<EffectAsset>>,
+) {
+    trace!("update_shared_assets");
+
+    // Consume all events; any of them may require resolving some modifiers again
+    let num_events =
+        gradient_events.read().count() + curve_events.read().count() + effect_events.read().count();
+    if num_events == 0 {
+        return;
+    }
+
+    // Only mutate the effects which actually changed, to avoid triggering a
+    // recompile of all effects, and a new asset event next frame.
+    let stale: HashSet<_> = effects
+        .iter()
+        .filter(|(_, asset)| {
+            asset
+                .render_modifiers()
+                .any(|m| is_stale(m.as_modifier(), &gradients, &curves))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    for id in stale {
+        if let Some(asset) = effects.get_mut(id) {
+            for modifier in asset.render_modifiers_mut() {
+                resolve(modifier.as_mut(), &gradients, &curves);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_shared() {
+        let mut gradients = Assets::<ColorGradient>::default();
+        let curves = Assets::<SizeCurve>::default();
+        let gradient = Gradient::linear(Vec4::ONE, Vec4::ZERO);
+        let handle = gradients.add(ColorGradient(gradient.clone()));
+
+        let mut modifier = ColorOverLifetimeAssetModifier::new(handle.clone());
+        assert!(is_stale(&modifier, &gradients, &curves));
+        resolve(&mut modifier, &gradients, &curves);
+        assert_eq!(modifier.resolved, gradient);
+        assert!(!is_stale(&modifier, &gradients, &curves));
+
+        // Editing the shared asset invalidates the modifier
+        gradients.get_mut(&handle).unwrap().0 = Gradient::constant(Vec4::X);
+        assert!(is_stale(&modifier, &gradients, &curves));
+        resolve(&mut modifier, &gradients, &curves);
+        assert_eq!(modifier.resolved, Gradient::constant(Vec4::X));
+
+        // Removing it resolves to an empty gradient
+        gradients.remove(&handle);
+        assert!(is_stale(&modifier, &gradients, &curves));
+        resolve(&mut modifier, &gradients, &curves);
+        assert!(modifier.resolved.is_empty());
+        assert!(!is_stale(&modifier, &gradients, &curves));
+
+        // Curves not loaded yet are not stale
+        let modifier = SizeOverLifetimeAssetModifier::new(Default::default());
+        assert!(!is_stale(&modifier, &gradients, &curves));
+    }
+}
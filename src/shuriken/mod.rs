@@ -0,0 +1,995 @@
+//! Importer for the particle systems of the Unity engine, known as Shuriken.
+
+use std::ops::{Add, Mul};
+
+use bevy::{
+    log::warn,
+    math::{Vec2, Vec3, Vec4},
+    utils::{thiserror::Error, HashMap},
+};
+
+use self::yaml::{parse_documents, Node};
+use crate::{
+    graph::expr::TernaryOperator, AccelModifier, Attribute, BoxedModifier, BuiltInOperator,
+    ColorOverLifetimeModifier, CpuValue, EffectAsset, ExprHandle, Gradient, ModifierContext,
+    Module, RenderModifier, SetAttributeModifier, SetColorModifier, SetPositionCircleModifier,
+    SetPositionSphereModifier, SetSizeModifier, SetVelocityCircleModifier,
+    SetVelocitySphereModifier, ShapeDimension, SimulationSpace, SizeOverLifetimeModifier, Spawner,
+    VectorType,
+};
+
+mod yaml;
+
+/// Class ID of the `GameObject` objects in serialized Unity assets.
+const GAME_OBJECT_CLASS_ID: u32 = 1;
+/// Class ID of the `ParticleSystem` components in serialized Unity assets.
+const PARTICLE_SYSTEM_CLASS_ID: u32 = 198;
+
+/// Number of samples taken between two keys of a curve, to approximate the
+/// Hermite curves of Unity with the linear gradients of Hanabi.
+const CURVE_SAMPLES_PER_KEY: usize = 4;
+
+/// Error importing a Unity particle system with [`import_shuriken()`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ShurikenImportError {
+    /// The source is not a valid serialized Unity asset.
+    #[error("Line {line}: {message}")]
+    Parse {
+        /// 1-based line number of the error.
+        line: usize,
+        /// Description of the error.
+        message: String,
+    },
+    /// The source doesn't contain any particle system.
+    #[error("No particle system found")]
+    NoParticleSystem,
+}
+
+/// Convert the particle systems of a Unity prefab or scene to effect assets.
+///
+/// The `source` is the content of a `.prefab` or `.unity` file serialized in
+/// the text format of Unity (the default one). One [`EffectAsset`] is returned
+/// for each `ParticleSystem` component found, in order, named after its game
+/// object.
+///
+/// Only a subset of the settings of a particle system are imported:
+/// - the duration, looping, prewarm, maximum number of particles, and
+///   simulation space;
+/// - the start lifetime, speed, size, and color, as constants or random
+///   values between two constants;
+/// - the gravity modifier;
+/// - the emission rate over time, or the first burst;
+/// - the sphere, hemisphere, cone, box, and circle shapes;
+/// - the color and size over lifetime;
+/// - a constant velocity over lifetime, which is added to the initial
+///   velocity.
+///
+/// Unity curves are sampled into linear gradients. Settings which can't be
+/// converted exactly are approximated, and a warning is logged. Other modules
+/// and settings, like the shape transform, the renderer material, or the
+/// texture sheet animation, are ignored.
+///
+/// The transform of the game object is not imported either. Cone, box, and
+/// circle shapes emit particles along the +Y axis, like the default particle
+/// system of Unity, which is rotated to face upward. Unity uses a left-handed
+/// coordinate system, so the Z coordinates of the imported vectors are
+/// negated.
+///
+/// # Example
+///
+/// ```no_run
+/// # use bevy_hanabi::*;
+/// let source = std::fs::read_to_string("Fire.prefab").unwrap();
+/// for asset in import_shuriken(&source).unwrap() {
+///     let ron = ron::ser::to_string_pretty(&asset, Default::default()).unwrap();
+///     std::fs::write(format!("{}.effect", asset.name), ron).unwrap();
+/// }
+/// ```
+pub fn import_shuriken(source: &str) -> Result<Vec<EffectAsset>, ShurikenImportError> {
+    let documents = parse_documents(source)?;
+
+    let names: HashMap<i64, &str> = documents
+        .iter()
+        .filter(|doc| doc.class_id == GAME_OBJECT_CLASS_ID)
+        .filter_map(|doc| {
+            let name = doc.root.get("GameObject")?.get("m_Name")?.as_str()?;
+            Some((doc.file_id, name))
+        })
+        .collect();
+
+    let assets = documents
+        .iter()
+        .filter(|doc| doc.class_id == PARTICLE_SYSTEM_CLASS_ID)
+        .filter_map(|doc| doc.root.get("ParticleSystem"))
+        .map(|ps| {
+            let name = ps
+                .get("m_GameObject")
+                .and_then(|go| go.i64("fileID"))
+                .and_then(|id| names.get(&id))
+                .copied()
+                .unwrap_or("ParticleSystem");
+            convert(name, ps)
+        })
+        .collect::<Vec<_>>();
+
+    if assets.is_empty() {
+        Err(ShurikenImportError::NoParticleSystem)
+    } else {
+        Ok(assets)
+    }
+}
+
+/// Convert a Unity coordinate to the coordinate system of Bevy.
+fn to_bevy(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.y, -v.z)
+}
+
+fn lerp<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(a: T, b: T, s: f32) -> T {
+    a * (1. - s) + b * s
+}
+
+/// Sample some linear keys at a time.
+fn sample_keys<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(keys: &[(f32, T)], t: f32) -> T {
+    let index = keys.partition_point(|(time, _)| *time <= t);
+    if index == 0 {
+        return keys[0].1;
+    }
+    if index == keys.len() {
+        return keys[index - 1].1;
+    }
+    let (t0, v0) = keys[index - 1];
+    let (t1, v1) = keys[index];
+    lerp(v0, v1, (t - t0) / (t1 - t0).max(f32::EPSILON))
+}
+
+/// Key of a Unity animation curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CurveKey {
+    time: f32,
+    value: f32,
+    in_slope: f32,
+    out_slope: f32,
+}
+
+/// Evaluate a Unity animation curve between two keys, with a cubic Hermite
+/// spline.
+#[allow(clippy::suboptimal_flops)]
+fn eval_segment(k0: &CurveKey, k1: &CurveKey, t: f32) -> f32 {
+    // Infinite slopes make a step
+    if !k0.out_slope.is_finite() || !k1.in_slope.is_finite() {
+        return k0.value;
+    }
+    let dt = k1.time - k0.time;
+    if dt <= 0. {
+        return k1.value;
+    }
+    let s = (t - k0.time) / dt;
+    let s2 = s * s;
+    let s3 = s2 * s;
+    let h00 = 2. * s3 - 3. * s2 + 1.;
+    let h10 = s3 - 2. * s2 + s;
+    let h01 = -2. * s3 + 3. * s2;
+    let h11 = s3 - s2;
+    h00 * k0.value + h10 * dt * k0.out_slope + h01 * k1.value + h11 * dt * k1.in_slope
+}
+
+/// Sample a Unity animation curve into linear keys, in \[0:1\].
+fn sample_curve(keys: &[CurveKey]) -> Vec<(f32, f32)> {
+    match keys {
+        [] => return vec![(0., 0.)],
+        [key] => return vec![(0., key.value)],
+        _ => {}
+    }
+    let mut samples = vec![(keys[0].time.clamp(0., 1.), keys[0].value)];
+    for pair in keys.windows(2) {
+        for index in 1..=CURVE_SAMPLES_PER_KEY {
+            let t = lerp(
+                pair[0].time,
+                pair[1].time,
+                index as f32 / CURVE_SAMPLES_PER_KEY as f32,
+            );
+            samples.push((t.clamp(0., 1.), eval_segment(&pair[0], &pair[1], t)));
+        }
+    }
+    samples.dedup_by(|b, a| b.0 <= a.0);
+    samples
+}
+
+/// Unity `MinMaxCurve`, a value which is a constant, a random value between two
+/// constants, a curve, or a random value between two curves.
+#[derive(Debug, Clone, PartialEq)]
+struct MinMaxCurve {
+    mode: i64,
+    scalar: f32,
+    min_scalar: f32,
+    max_curve: Vec<CurveKey>,
+    min_curve: Vec<CurveKey>,
+}
+
+impl MinMaxCurve {
+    const CONSTANT: i64 = 0;
+    const CURVE: i64 = 1;
+    const TWO_CURVES: i64 = 2;
+    const TWO_CONSTANTS: i64 = 3;
+
+    fn parse(node: &Node) -> Self {
+        let curve = |key: &str| {
+            node.get(key)
+                .and_then(|curve| curve.get("m_Curve"))
+                .map(|keys| {
+                    keys.items()
+                        .iter()
+                        .map(|key| CurveKey {
+                            time: key.f32("time").unwrap_or(0.),
+                            value: key.f32("value").unwrap_or(0.),
+                            in_slope: key.f32("inSlope").unwrap_or(0.),
+                            out_slope: key.f32("outSlope").unwrap_or(0.),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let scalar = node.f32("scalar").unwrap_or(0.);
+        Self {
+            mode: node.i64("minMaxState").unwrap_or(Self::CONSTANT),
+            scalar,
+            min_scalar: node.f32("minScalar").unwrap_or(scalar),
+            max_curve: curve("maxCurve"),
+            min_curve: curve("minCurve"),
+        }
+    }
+
+    /// Get the value as a constant, or a random value between two constants.
+    ///
+    /// Curves are evaluated at their start.
+    fn value(&self, name: &str, setting: &str) -> CpuValue<f32> {
+        match self.mode {
+            Self::CONSTANT => CpuValue::Single(self.scalar),
+            Self::TWO_CONSTANTS => CpuValue::Uniform((
+                self.min_scalar.min(self.scalar),
+                self.min_scalar.max(self.scalar),
+            )),
+            _ => {
+                warn!(
+                    "Particle system '{}': curves are not supported for the {}. Using the start value of the curve.",
+                    name, setting
+                );
+                CpuValue::Single(self.scalar * sample_curve(&self.max_curve)[0].1)
+            }
+        }
+    }
+
+    /// Get the value over the lifetime of the particles, as linear keys in
+    /// \[0:1\].
+    fn keys(&self, name: &str, setting: &str) -> Vec<(f32, f32)> {
+        match self.mode {
+            Self::CURVE => sample_curve(&self.max_curve)
+                .into_iter()
+                .map(|(t, v)| (t, v * self.scalar))
+                .collect(),
+            Self::TWO_CURVES => {
+                warn!(
+                    "Particle system '{}': random values between two curves are not supported for the {}. Using the average of the two curves.",
+                    name, setting
+                );
+                let min_keys = sample_curve(&self.min_curve);
+                sample_curve(&self.max_curve)
+                    .into_iter()
+                    .map(|(t, v)| (t, (v + sample_keys(&min_keys, t)) * 0.5 * self.scalar))
+                    .collect()
+            }
+            _ => match self.value(name, setting) {
+                CpuValue::Single(v) => vec![(0., v)],
+                CpuValue::Uniform((a, b)) => {
+                    warn!(
+                        "Particle system '{}': random values are not supported for the {}. Using the average value.",
+                        name, setting
+                    );
+                    vec![(0., (a + b) * 0.5)]
+                }
+            },
+        }
+    }
+
+    /// Check if the value is the constant zero.
+    fn is_zero(&self) -> bool {
+        self.mode == Self::CONSTANT && self.scalar == 0.
+    }
+}
+
+fn parse_color(node: Option<&Node>) -> Vec4 {
+    let Some(node) = node else {
+        return Vec4::ONE;
+    };
+    Vec4::new(
+        node.f32("r").unwrap_or(1.),
+        node.f32("g").unwrap_or(1.),
+        node.f32("b").unwrap_or(1.),
+        node.f32("a").unwrap_or(1.),
+    )
+}
+
+/// Convert a Unity `Gradient` to linear keys in \[0:1\], merging the separate
+/// color and alpha keys of Unity.
+fn parse_gradient(node: Option<&Node>) -> Vec<(f32, Vec4)> {
+    let Some(node) = node else {
+        return vec![(0., Vec4::ONE)];
+    };
+    // Unity gradients have between 2 and 8 keys, with times encoded in
+    // [0:65535]
+    let keys = |count: &str, time: &str| -> Vec<(f32, Vec4)> {
+        let count = node.i64(count).unwrap_or(2).clamp(1, 8);
+        (0..count)
+            .map(|index| {
+                (
+                    node.f32(&format!("{}{}", time, index)).unwrap_or(0.) / 65535.,
+                    parse_color(node.get(&format!("key{}", index))),
+                )
+            })
+            .collect()
+    };
+    let colors = keys("m_NumColorKeys", "ctime")
+        .into_iter()
+        .map(|(t, c)| (t, c.truncate()))
+        .collect::<Vec<_>>();
+    let alphas = keys("m_NumAlphaKeys", "atime")
+        .into_iter()
+        .map(|(t, c)| (t, c.w))
+        .collect::<Vec<_>>();
+
+    let mut times = colors
+        .iter()
+        .map(|(t, _)| *t)
+        .chain(alphas.iter().map(|(t, _)| *t))
+        .collect::<Vec<_>>();
+    times.sort_by(f32::total_cmp);
+    times.dedup_by(|b, a| *b - *a < 1e-4);
+    times
+        .into_iter()
+        .map(|t| (t, sample_keys(&colors, t).extend(sample_keys(&alphas, t))))
+        .collect()
+}
+
+/// Convert a Unity `MinMaxGradient` to linear keys in \[0:1\].
+fn parse_min_max_gradient(node: Option<&Node>, name: &str, setting: &str) -> Vec<(f32, Vec4)> {
+    let Some(node) = node else {
+        return vec![(0., Vec4::ONE)];
+    };
+    match node.i64("minMaxState").unwrap_or(0) {
+        // Color
+        0 => vec![(0., parse_color(node.get("maxColor")))],
+        // Gradient
+        1 => parse_gradient(node.get("maxGradient")),
+        // TwoColors
+        2 => {
+            warn!(
+                "Particle system '{}': random colors are not supported for the {}. Using the average color.",
+                name, setting
+            );
+            let min = parse_color(node.get("minColor"));
+            let max = parse_color(node.get("maxColor"));
+            vec![(0., (min + max) * 0.5)]
+        }
+        // TwoGradients
+        3 => {
+            warn!(
+                "Particle system '{}': random gradients are not supported for the {}. Using the max gradient.",
+                name, setting
+            );
+            parse_gradient(node.get("maxGradient"))
+        }
+        _ => {
+            warn!(
+                "Particle system '{}': random colors are not supported for the {}. Using white.",
+                name, setting
+            );
+            vec![(0., Vec4::ONE)]
+        }
+    }
+}
+
+/// Build an expression for a constant or random value.
+fn value_expr(module: &mut Module, value: CpuValue<f32>) -> ExprHandle {
+    match value {
+        CpuValue::Single(v) => module.lit(v),
+        CpuValue::Uniform((a, b)) => {
+            let a = module.lit(a);
+            let b = module.lit(b);
+            module.uniform(a, b)
+        }
+    }
+}
+
+/// Convert the spawner settings of a particle system.
+fn convert_spawner(name: &str, ps: &Node, emission: &Node) -> Spawner {
+    let duration = ps.f32("lengthInSec").unwrap_or(5.).max(f32::EPSILON);
+    let looping = ps.bool("looping").unwrap_or(true);
+
+    if !emission.bool("enabled").unwrap_or(true) {
+        return Spawner::once(0.0.into(), false);
+    }
+
+    let rate = emission
+        .get("rateOverTime")
+        .map(MinMaxCurve::parse)
+        .unwrap_or_else(|| MinMaxCurve::parse(&Node::Map(vec![])));
+    let bursts = emission
+        .get("m_Bursts")
+        .map(Node::items)
+        .unwrap_or_default();
+    if emission
+        .get("rateOverDistance")
+        .map(MinMaxCurve::parse)
+        .is_some_and(|rate| !rate.is_zero())
+    {
+        warn!(
+            "Particle system '{}': emission over distance is not supported.",
+            name
+        );
+    }
+
+    let Some(burst) = bursts.first().filter(|_| rate.is_zero()) else {
+        if !bursts.is_empty() {
+            warn!(
+                "Particle system '{}': combining an emission rate and bursts is not supported. Ignoring the bursts.",
+                name
+            );
+        }
+        let rate = rate.value(name, "emission rate");
+        return if looping {
+            Spawner::rate(rate)
+        } else {
+            let count = match rate {
+                CpuValue::Single(r) => CpuValue::Single(r * duration),
+                CpuValue::Uniform((a, b)) => CpuValue::Uniform((a * duration, b * duration)),
+            };
+            Spawner::new(count, duration.into(), duration.into()).with_loop_count(Some(1))
+        };
+    };
+
+    if bursts.len() > 1 {
+        warn!(
+            "Particle system '{}': multiple bursts are not supported. Using the first burst only.",
+            name
+        );
+    }
+    if burst.f32("time").unwrap_or(0.) > 0. {
+        warn!(
+            "Particle system '{}': delayed bursts are not supported. The burst starts immediately.",
+            name
+        );
+    }
+    let count = burst.get("countCurve").map(MinMaxCurve::parse).map_or(
+        CpuValue::Single(burst.f32("minCount").unwrap_or(30.)),
+        |count| count.value(name, "burst count"),
+    );
+    let cycles = burst.i64("cycleCount").unwrap_or(1).max(0) as u32;
+    let interval = burst
+        .f32("repeatInterval")
+        .unwrap_or(duration)
+        .max(f32::EPSILON);
+    if burst.f32("probability").unwrap_or(1.) < 1. {
+        warn!(
+            "Particle system '{}': burst probabilities are not supported. The burst always spawns.",
+            name
+        );
+    }
+    match (cycles, looping) {
+        (1, false) => Spawner::once(count, true),
+        (1, true) => Spawner::burst(count, duration.into()),
+        (0, _) | (_, true) => Spawner::burst(count, interval.into()),
+        (cycles, false) => Spawner::burst(count, interval.into()).with_loop_count(Some(cycles)),
+    }
+}
+
+/// Convert the shape of a particle system to position and velocity modifiers.
+fn convert_shape(
+    name: &str,
+    shape: &Node,
+    module: &mut Module,
+    speed: ExprHandle,
+) -> Vec<BoxedModifier> {
+    let center = module.lit(Vec3::ZERO);
+    let radius_value = shape
+        .get("radius")
+        .and_then(|radius| radius.f32("value"))
+        .or_else(|| shape.f32("radius"))
+        .unwrap_or(1.);
+    let radius = module.lit(radius_value);
+    // Unity emits from the volume when the thickness is 1, and from the surface
+    // when 0
+    let dimension = if shape.f32("radiusThickness").unwrap_or(1.) > 0. {
+        ShapeDimension::Volume
+    } else {
+        ShapeDimension::Surface
+    };
+    let up = module.lit(Vec3::Y);
+    let velocity_up = module.mul(up, speed);
+
+    if !shape.bool("enabled").unwrap_or(true) {
+        return vec![Box::new(SetAttributeModifier::new(
+            Attribute::VELOCITY,
+            velocity_up,
+        ))];
+    }
+
+    match shape.i64("type").unwrap_or(4) {
+        // Sphere, SphereShell (legacy)
+        0 | 1 => vec![
+            Box::new(SetPositionSphereModifier {
+                center,
+                radius,
+                dimension,
+            }),
+            Box::new(SetVelocitySphereModifier { center, speed }),
+        ],
+        // Hemisphere, HemisphereShell (legacy)
+        2 | 3 => {
+            // Fold the sphere onto its upper half
+            let pos = module.attr(Attribute::POSITION);
+            let x = module.x(pos);
+            let y = module.y(pos);
+            let z = module.z(pos);
+            let y = module.abs(y);
+            let pos = module.ternary(TernaryOperator::Vec3, x, y, z);
+            vec![
+                Box::new(SetPositionSphereModifier {
+                    center,
+                    radius,
+                    dimension,
+                }),
+                Box::new(SetAttributeModifier::new(Attribute::POSITION, pos)),
+                Box::new(SetVelocitySphereModifier { center, speed }),
+            ]
+        }
+        // Cone, ConeShell (legacy), ConeVolume, ConeVolumeShell (legacy)
+        4 | 7 | 8 | 9 => {
+            let angle = shape.f32("angle").unwrap_or(25.).clamp(0., 90.);
+            let position = Box::new(SetPositionCircleModifier {
+                center,
+                axis: up,
+                radius,
+                dimension,
+            });
+            if angle < 0.01 || radius_value <= 0. {
+                return vec![
+                    position,
+                    Box::new(SetAttributeModifier::new(Attribute::VELOCITY, velocity_up)),
+                ];
+            }
+            // The particles move away from the apex of the cone, below its base
+            let apex = module.lit(Vec3::new(0., -radius_value / angle.to_radians().tan(), 0.));
+            vec![
+                position,
+                Box::new(SetVelocitySphereModifier {
+                    center: apex,
+                    speed,
+                }),
+            ]
+        }
+        // Box, BoxShell, BoxEdge
+        5 | 15 | 16 => {
+            let scale = shape
+                .get("m_Scale")
+                .map(|scale| {
+                    Vec3::new(
+                        scale.f32("x").unwrap_or(1.),
+                        scale.f32("y").unwrap_or(1.),
+                        scale.f32("z").unwrap_or(1.),
+                    )
+                })
+                .unwrap_or(Vec3::ONE);
+            let rand = module.builtin(BuiltInOperator::Rand(VectorType::VEC3F.into()));
+            let half = module.lit(Vec3::splat(0.5));
+            let pos = module.sub(rand, half);
+            // The box is rotated like the default particle system, facing upward
+            let scale = module.lit(Vec3::new(scale.x, scale.z, scale.y));
+            let pos = module.mul(pos, scale);
+            vec![
+                Box::new(SetAttributeModifier::new(Attribute::POSITION, pos)),
+                Box::new(SetAttributeModifier::new(Attribute::VELOCITY, velocity_up)),
+            ]
+        }
+        // Circle, CircleEdge (legacy)
+        10 | 11 => vec![
+            Box::new(SetPositionCircleModifier {
+                center,
+                axis: up,
+                radius,
+                dimension,
+            }),
+            Box::new(SetVelocityCircleModifier {
+                center,
+                axis: up,
+                speed,
+            }),
+        ],
+        shape_type => {
+            warn!(
+                "Particle system '{}': shape type {} is not supported. Emitting from a single point.",
+                name, shape_type
+            );
+            let pos = module.lit(Vec3::ZERO);
+            vec![
+                Box::new(SetAttributeModifier::new(Attribute::POSITION, pos)),
+                Box::new(SetAttributeModifier::new(Attribute::VELOCITY, velocity_up)),
+            ]
+        }
+    }
+}
+
+/// Convert a single particle system.
+fn convert(name: &str, ps: &Node) -> EffectAsset {
+    let empty = Node::Map(vec![]);
+    let get = |key: &str| ps.get(key).unwrap_or(&empty);
+    let enabled = |node: &Node| node.bool("enabled").unwrap_or(false);
+    let initial = get("InitialModule");
+    let curve = |node: &Node, key: &str| node.get(key).map(MinMaxCurve::parse);
+
+    let mut module = Module::default();
+    let mut init: Vec<BoxedModifier> = vec![];
+    let mut update: Vec<BoxedModifier> = vec![];
+    let mut render: Vec<Box<dyn RenderModifier>> = vec![];
+
+    // Initial values
+    let age = module.lit(0.);
+    init.push(Box::new(SetAttributeModifier::new(Attribute::AGE, age)));
+    let lifetime = curve(initial, "startLifetime")
+        .map_or(CpuValue::Single(5.), |c| c.value(name, "start lifetime"));
+    let lifetime = value_expr(&mut module, lifetime);
+    init.push(Box::new(SetAttributeModifier::new(
+        Attribute::LIFETIME,
+        lifetime,
+    )));
+    let speed =
+        curve(initial, "startSpeed").map_or(CpuValue::Single(5.), |c| c.value(name, "start speed"));
+    let speed = value_expr(&mut module, speed);
+    init.extend(convert_shape(name, get("ShapeModule"), &mut module, speed));
+
+    // Velocity over lifetime, folded into the initial velocity
+    let velocity_module = get("VelocityModule");
+    if enabled(velocity_module) {
+        let axis = |key: &str| {
+            let keys = curve(velocity_module, key)
+                .map_or(vec![(0., 0.)], |c| c.keys(name, "velocity over lifetime"));
+            keys.iter().map(|(_, v)| *v).sum::<f32>() / keys.len() as f32
+        };
+        let velocity = to_bevy(Vec3::new(axis("x"), axis("y"), axis("z")));
+        if velocity != Vec3::ZERO {
+            if ["x", "y", "z"].iter().any(|key| {
+                curve(velocity_module, key).is_some_and(|c| c.mode == MinMaxCurve::CURVE)
+            }) {
+                warn!(
+                    "Particle system '{}': non-constant velocity over lifetime is not supported. Using the average velocity.",
+                    name
+                );
+            }
+            let initial_velocity = module.attr(Attribute::VELOCITY);
+            let velocity = module.lit(velocity);
+            let velocity = module.add(initial_velocity, velocity);
+            init.push(Box::new(SetAttributeModifier::new(
+                Attribute::VELOCITY,
+                velocity,
+            )));
+        }
+    }
+
+    // Gravity
+    let gravity =
+        curve(initial, "gravityModifier").map_or(0., |c| match c.value(name, "gravity modifier") {
+            CpuValue::Single(g) => g,
+            CpuValue::Uniform((a, b)) => (a + b) * 0.5,
+        });
+    if gravity != 0. {
+        let accel = module.lit(Vec3::new(0., -9.81 * gravity, 0.));
+        update.push(Box::new(AccelModifier::new(accel)));
+    }
+
+    // Color, combining the start color and the color over lifetime
+    let start_color = parse_min_max_gradient(initial.get("startColor"), name, "start color");
+    if start_color.len() > 1 {
+        warn!(
+            "Particle system '{}': gradients are not supported for the start color. Using the first color of the gradient.",
+            name
+        );
+    }
+    let start_color = start_color[0].1;
+    let color_module = get("ColorModule");
+    if enabled(color_module) {
+        let keys =
+            parse_min_max_gradient(color_module.get("gradient"), name, "color over lifetime");
+        render.push(Box::new(ColorOverLifetimeModifier {
+            gradient: Gradient::from_keys(keys.into_iter().map(|(t, c)| (t, c * start_color))),
+        }));
+    } else {
+        render.push(Box::new(SetColorModifier {
+            color: start_color.into(),
+        }));
+    }
+
+    // Size, combining the start size and the size over lifetime
+    if initial.bool("size3D").unwrap_or(false) {
+        warn!(
+            "Particle system '{}': 3D start sizes are not supported. Using the X size.",
+            name
+        );
+    }
+    let start_size =
+        curve(initial, "startSize").map_or(CpuValue::Single(1.), |c| c.value(name, "start size"));
+    let size_module = get("SizeModule");
+    if enabled(size_module) {
+        let start_size = match start_size {
+            CpuValue::Single(s) => s,
+            CpuValue::Uniform((a, b)) => {
+                warn!(
+                    "Particle system '{}': random start sizes are not supported with a size over lifetime. Using the average size.",
+                    name
+                );
+                (a + b) * 0.5
+            }
+        };
+        let keys = curve(size_module, "curve")
+            .map_or(vec![(0., 1.)], |c| c.keys(name, "size over lifetime"));
+        render.push(Box::new(SizeOverLifetimeModifier {
+            gradient: Gradient::from_keys(
+                keys.into_iter()
+                    .map(|(t, s)| (t, Vec2::splat(s * start_size))),
+            ),
+            screen_space_size: false,
+        }));
+    } else {
+        render.push(Box::new(SetSizeModifier {
+            size: match start_size {
+                CpuValue::Single(s) => Vec2::splat(s).into(),
+                CpuValue::Uniform((a, b)) => CpuValue::Uniform((Vec2::splat(a), Vec2::splat(b))),
+            },
+        }));
+    }
+
+    let spawner = convert_spawner(name, ps, get("EmissionModule"));
+    let capacity = initial.i64("maxNumParticles").unwrap_or(1000).max(1) as u32;
+    // Unity simulation spaces are Local = 0, World = 1, Custom = 2
+    let simulation_space = if ps.i64("moveWithTransform").unwrap_or(0) == 0 {
+        SimulationSpace::Local
+    } else {
+        SimulationSpace::Global
+    };
+
+    let mut asset = EffectAsset::new(vec![capacity], spawner, module)
+        .with_name(name)
+        .with_simulation_space(simulation_space);
+    if ps.bool("prewarm").unwrap_or(false) && ps.bool("looping").unwrap_or(true) {
+        asset = asset.with_prewarm(ps.f32("lengthInSec").unwrap_or(5.));
+    }
+    for modifier in init {
+        asset = asset.add_modifier(ModifierContext::Init, modifier);
+    }
+    for modifier in update {
+        asset = asset.add_modifier(ModifierContext::Update, modifier);
+    }
+    for modifier in render {
+        asset = asset.add_render_modifier(modifier);
+    }
+    asset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFAB: &str = r#"%YAML 1.1
+%TAG !u! tag:unity3d.com,2011:
+--- !u!1 &1000
+GameObject:
+  m_ObjectHideFlags: 0
+  serializedVersion: 6
+  m_Component:
+  - component: {fileID: 4000}
+  - component: {fileID: 198000}
+  m_Name: Sparks
+--- !u!198 &198000
+ParticleSystem:
+  m_GameObject: {fileID: 1000}
+  serializedVersion: 8
+  lengthInSec: 2
+  looping: 1
+  prewarm: 0
+  moveWithTransform: 1
+  InitialModule:
+    serializedVersion: 3
+    enabled: 1
+    startLifetime:
+      serializedVersion: 2
+      minMaxState: 3
+      scalar: 2
+      minScalar: 1
+    startSpeed:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 4
+      minScalar: 5
+    startColor:
+      serializedVersion: 2
+      minMaxState: 0
+      minColor: {r: 1, g: 1, b: 1, a: 1}
+      maxColor: {r: 1, g: 0.5, b: 0, a: 1}
+    startSize:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 0.5
+      minScalar: 1
+    gravityModifier:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 1
+    maxNumParticles: 500
+  ShapeModule:
+    serializedVersion: 6
+    enabled: 1
+    type: 0
+    radiusThickness: 0
+    radius:
+      value: 2
+      mode: 0
+  EmissionModule:
+    enabled: 1
+    serializedVersion: 4
+    rateOverTime:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 0
+    m_BurstCount: 1
+    m_Bursts:
+    - serializedVersion: 2
+      time: 0
+      countCurve:
+        serializedVersion: 2
+        minMaxState: 0
+        scalar: 30
+      cycleCount: 0
+      repeatInterval: 0.5
+      probability: 1
+  SizeModule:
+    enabled: 1
+    curve:
+      serializedVersion: 2
+      minMaxState: 1
+      scalar: 2
+      maxCurve:
+        serializedVersion: 2
+        m_Curve:
+        - serializedVersion: 3
+          time: 0
+          value: 1
+          inSlope: 0
+          outSlope: -1
+          tangentMode: 0
+        - serializedVersion: 3
+          time: 1
+          value: 0
+          inSlope: -1
+          outSlope: 0
+          tangentMode: 0
+  ColorModule:
+    enabled: 1
+    gradient:
+      serializedVersion: 2
+      minMaxState: 1
+      maxGradient:
+        serializedVersion: 2
+        key0: {r: 1, g: 1, b: 1, a: 1}
+        key1: {r: 0, g: 0, b: 0, a: 0}
+        ctime0: 0
+        ctime1: 65535
+        atime0: 0
+        atime1: 65535
+        m_Mode: 0
+        m_NumColorKeys: 2
+        m_NumAlphaKeys: 2
+  VelocityModule:
+    enabled: 1
+    x:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 0
+    y:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 0
+    z:
+      serializedVersion: 2
+      minMaxState: 0
+      scalar: 3
+"#;
+
+    #[test]
+    fn import() {
+        let assets = import_shuriken(PREFAB).unwrap();
+        assert_eq!(assets.len(), 1);
+        let asset = &assets[0];
+        assert_eq!(asset.name, "Sparks");
+        assert_eq!(asset.capacities(), &[500]);
+        assert_eq!(asset.simulation_space, SimulationSpace::Global);
+        assert_eq!(asset.spawner, Spawner::burst(30.0.into(), 0.5.into()));
+
+        // Age, lifetime, sphere position and velocity, and velocity over lifetime
+        assert_eq!(asset.init_modifiers().count(), 5);
+        // Gravity
+        assert_eq!(asset.update_modifiers().count(), 1);
+
+        let render = asset.render_modifiers().collect::<Vec<_>>();
+        assert_eq!(render.len(), 2);
+        let color = render[0]
+            .as_reflect()
+            .downcast_ref::<ColorOverLifetimeModifier>()
+            .unwrap();
+        assert_eq!(
+            color.gradient,
+            Gradient::from_keys([(0., Vec4::new(1., 0.5, 0., 1.)), (1., Vec4::ZERO)])
+        );
+        let size = render[1]
+            .as_reflect()
+            .downcast_ref::<SizeOverLifetimeModifier>()
+            .unwrap();
+        let keys = size.gradient.keys();
+        assert_eq!(keys.len(), CURVE_SAMPLES_PER_KEY + 1);
+        assert_eq!(keys[0].value, Vec2::splat(1.));
+        assert_eq!(keys[0].ratio(), 0.);
+        assert_eq!(keys[CURVE_SAMPLES_PER_KEY].value, Vec2::ZERO);
+        assert_eq!(keys[CURVE_SAMPLES_PER_KEY].ratio(), 1.);
+    }
+
+    #[test]
+    fn import_errors() {
+        assert!(matches!(
+            import_shuriken("--- !u!1 &1\nGameObject:\n  m_Name: Empty\n"),
+            Err(ShurikenImportError::NoParticleSystem)
+        ));
+        assert!(matches!(
+            import_shuriken("--- !u!198 &1\nParticleSystem:\n  a: {x: 1\n"),
+            Err(ShurikenImportError::Parse { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn curves() {
+        // Linear curve
+        let keys = [
+            CurveKey {
+                time: 0.,
+                value: 0.,
+                in_slope: 1.,
+                out_slope: 1.,
+            },
+            CurveKey {
+                time: 1.,
+                value: 1.,
+                in_slope: 1.,
+                out_slope: 1.,
+            },
+        ];
+        for (t, v) in sample_curve(&keys) {
+            assert!((t - v).abs() < 1e-5);
+        }
+
+        // Step
+        let mut step = keys;
+        step[0].out_slope = f32::INFINITY;
+        assert_eq!(eval_segment(&step[0], &step[1], 0.9), 0.);
+
+        // Single key
+        assert_eq!(sample_curve(&keys[1..]), vec![(0., 1.)]);
+        assert_eq!(sample_curve(&[]), vec![(0., 0.)]);
+    }
+
+    #[test]
+    fn gradient() {
+        let source = "--- !u!1 &1\nGradient:\n  key0: {r: 1, g: 0, b: 0, a: 1}\n  key1: {r: 0, g: 0, b: 1, a: 0}\n  ctime0: 0\n  ctime1: 65535\n  atime0: 0\n  atime1: 32767.5\n  m_NumColorKeys: 2\n  m_NumAlphaKeys: 2\n";
+        let documents = parse_documents(source).unwrap();
+        let keys = parse_gradient(documents[0].root.get("Gradient"));
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0], (0., Vec4::new(1., 0., 0., 1.)));
+        assert_eq!(keys[1], (0.5, Vec4::new(0.5, 0., 0.5, 0.)));
+        assert_eq!(keys[2], (1., Vec4::new(0., 0., 1., 0.)));
+    }
+}
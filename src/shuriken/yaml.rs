@@ -0,0 +1,480 @@
+//! Reader for the subset of YAML used by the serialized Unity assets.
+//!
+//! Unity serializes its assets as a stream of YAML documents, one per object,
+//! using only block mappings and sequences, flow mappings like
+//! `{x: 0, y: 1, z: 0}`, and plain or quoted scalars. This reader supports
+//! exactly that, and doesn't pretend to be a general-purpose YAML parser.
+
+use super::ShurikenImportError;
+
+/// Node of a YAML document.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    /// Scalar value, unquoted.
+    Scalar(String),
+    /// Mapping, with its entries in order.
+    Map(Vec<(String, Node)>),
+    /// Sequence of nodes.
+    Seq(Vec<Node>),
+}
+
+impl Node {
+    /// Get the value of a key of a mapping.
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        match self {
+            Node::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Get a scalar value as a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Node::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get a scalar value as a float.
+    ///
+    /// Unity serializes infinite values as `Infinity` and `-Infinity`.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self.as_str()? {
+            "Infinity" => Some(f32::INFINITY),
+            "-Infinity" => Some(f32::NEG_INFINITY),
+            s => s.parse().ok(),
+        }
+    }
+
+    /// Get the items of a sequence, or an empty slice if this is not a
+    /// sequence.
+    pub fn items(&self) -> &[Node] {
+        match self {
+            Node::Seq(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// Get a float value of a mapping.
+    pub fn f32(&self, key: &str) -> Option<f32> {
+        self.get(key).and_then(Node::as_f32)
+    }
+
+    /// Get an integer value of a mapping.
+    pub fn i64(&self, key: &str) -> Option<i64> {
+        self.get(key)
+            .and_then(Node::as_str)
+            .and_then(|s| s.parse().ok())
+    }
+
+    /// Get a boolean value of a mapping, serialized by Unity as `0` or `1`.
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        self.i64(key).map(|i| i != 0)
+    }
+}
+
+/// YAML document of a serialized Unity object.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Document {
+    /// Class ID of the object, like `198` for a `ParticleSystem`.
+    pub class_id: u32,
+    /// File ID of the object, used by other objects to reference it.
+    pub file_id: i64,
+    /// Root node, generally a mapping with a single entry named after the
+    /// class of the object.
+    pub root: Node,
+}
+
+/// Line of the source text, without its indentation.
+#[derive(Debug, Clone, Copy)]
+struct Line<'a> {
+    /// 1-based line number, for error reporting.
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// Parse all the documents of a serialized Unity asset.
+pub(crate) fn parse_documents(source: &str) -> Result<Vec<Document>, ShurikenImportError> {
+    let mut documents = vec![];
+    let mut header: Option<(u32, i64)> = None;
+    let mut lines = vec![];
+    for (index, raw) in source.lines().enumerate() {
+        let number = index + 1;
+        if let Some(tag) = raw.strip_prefix("--- ") {
+            if let Some((class_id, file_id)) = header.take() {
+                documents.push(parse_document(class_id, file_id, &lines)?);
+            }
+            lines.clear();
+            header = Some(parse_header(number, tag)?);
+            continue;
+        }
+        let text = raw.trim_start_matches(' ');
+        if text.is_empty() || text.starts_with('#') || text.starts_with('%') {
+            continue;
+        }
+        lines.push(Line {
+            number,
+            indent: raw.len() - text.len(),
+            text: text.trim_end(),
+        });
+    }
+    if let Some((class_id, file_id)) = header {
+        documents.push(parse_document(class_id, file_id, &lines)?);
+    }
+    Ok(documents)
+}
+
+/// Parse a document header like `!u!198 &1234`, possibly followed by
+/// `stripped`.
+fn parse_header(number: usize, tag: &str) -> Result<(u32, i64), ShurikenImportError> {
+    let mut parts = tag.split_whitespace();
+    let class_id = parts
+        .next()
+        .and_then(|s| s.strip_prefix("!u!"))
+        .and_then(|s| s.parse().ok());
+    let file_id = parts
+        .next()
+        .and_then(|s| s.strip_prefix('&'))
+        .and_then(|s| s.parse().ok());
+    match (class_id, file_id) {
+        (Some(class_id), Some(file_id)) => Ok((class_id, file_id)),
+        _ => Err(ShurikenImportError::Parse {
+            line: number,
+            message: format!("Invalid document header '--- {}'", tag),
+        }),
+    }
+}
+
+fn parse_document(
+    class_id: u32,
+    file_id: i64,
+    lines: &[Line],
+) -> Result<Document, ShurikenImportError> {
+    let mut parser = Parser {
+        lines: lines.to_vec(),
+        pos: 0,
+    };
+    let root = match parser.lines.first() {
+        Some(line) => parser.parse_block(line.indent)?,
+        None => Node::Map(vec![]),
+    };
+    if let Some(line) = parser.lines.get(parser.pos) {
+        return Err(parser.error(line.number, "Unexpected indentation"));
+    }
+    Ok(Document {
+        class_id,
+        file_id,
+        root,
+    })
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, line: usize, message: impl Into<String>) -> ShurikenImportError {
+        ShurikenImportError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn is_seq_item(text: &str) -> bool {
+        text == "-" || text.starts_with("- ")
+    }
+
+    /// Parse the block mapping or sequence starting at the current line, whose
+    /// entries are at the given indentation.
+    fn parse_block(&mut self, indent: usize) -> Result<Node, ShurikenImportError> {
+        let first = self.lines[self.pos];
+        if Self::is_seq_item(first.text) {
+            self.parse_seq(indent)
+        } else {
+            self.parse_map(indent)
+        }
+    }
+
+    fn parse_seq(&mut self, indent: usize) -> Result<Node, ShurikenImportError> {
+        let mut items = vec![];
+        while let Some(line) = self.lines.get(self.pos).copied() {
+            if line.indent != indent || !Self::is_seq_item(line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                // Item content on the next lines
+                self.pos += 1;
+                match self.lines.get(self.pos) {
+                    Some(next) if next.indent > indent => {
+                        items.push(self.parse_block(next.indent)?);
+                    }
+                    _ => items.push(Node::Scalar(String::new())),
+                }
+            } else if split_key(rest).is_some() {
+                // The item is a mapping whose first entry is on the same line as the
+                // dash; parse it as if the dash was indentation.
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: indent + (line.text.len() - rest.len()),
+                    text: rest,
+                };
+                let item_indent = self.lines[self.pos].indent;
+                items.push(self.parse_map(item_indent)?);
+            } else {
+                self.pos += 1;
+                items.push(parse_flow(rest).map_err(|message| self.error(line.number, message))?);
+            }
+        }
+        Ok(Node::Seq(items))
+    }
+
+    fn parse_map(&mut self, indent: usize) -> Result<Node, ShurikenImportError> {
+        let mut entries = vec![];
+        while let Some(line) = self.lines.get(self.pos).copied() {
+            if line.indent < indent || (line.indent == indent && Self::is_seq_item(line.text)) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(self.error(line.number, "Unexpected indentation"));
+            }
+            let Some((key, value)) = split_key(line.text) else {
+                return Err(self.error(line.number, format!("Expected a key in '{}'", line.text)));
+            };
+            self.pos += 1;
+            let node = if value.is_empty() {
+                match self.lines.get(self.pos) {
+                    Some(next) if next.indent > indent => self.parse_block(next.indent)?,
+                    // Sequences are indented like their key
+                    Some(next) if next.indent == indent && Self::is_seq_item(next.text) => {
+                        self.parse_seq(indent)?
+                    }
+                    _ => Node::Scalar(String::new()),
+                }
+            } else {
+                // Plain scalars may continue on the next, more indented, lines
+                let mut value = value.to_string();
+                while let Some(next) = self.lines.get(self.pos) {
+                    if next.indent <= indent
+                        || value.starts_with('{')
+                        || value.starts_with('[')
+                        || Self::is_seq_item(next.text)
+                        || split_key(next.text).is_some()
+                    {
+                        break;
+                    }
+                    value.push(' ');
+                    value.push_str(next.text);
+                    self.pos += 1;
+                }
+                parse_flow(&value).map_err(|message| self.error(line.number, message))?
+            };
+            entries.push((key.to_string(), node));
+        }
+        Ok(Node::Map(entries))
+    }
+}
+
+/// Split a `key: value` line, returning `None` if the line is not a mapping
+/// entry.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with('{') || text.starts_with('[') || text.starts_with('"') {
+        return None;
+    }
+    if let Some(key) = text.strip_suffix(':') {
+        return Some((key, ""));
+    }
+    text.split_once(": ")
+        .map(|(key, value)| (key, value.trim_start()))
+}
+
+/// Parse an inline value, either a flow mapping, a flow sequence, or a scalar.
+fn parse_flow(text: &str) -> Result<Node, String> {
+    let mut chars = text.char_indices().peekable();
+    let node = parse_flow_value(text, &mut chars, false)?;
+    match chars.next() {
+        None => Ok(node),
+        Some((index, c)) => Err(format!("Unexpected '{}' at column {}", c, index + 1)),
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+fn skip_spaces(chars: &mut Chars) {
+    while chars.next_if(|(_, c)| *c == ' ').is_some() {}
+}
+
+fn parse_flow_value(text: &str, chars: &mut Chars, nested: bool) -> Result<Node, String> {
+    skip_spaces(chars);
+    match chars.peek().map(|(_, c)| *c) {
+        Some('{') => {
+            chars.next();
+            let mut entries = vec![];
+            loop {
+                skip_spaces(chars);
+                if chars.next_if(|(_, c)| *c == '}').is_some() {
+                    break;
+                }
+                let key = parse_scalar(text, chars, true)?;
+                if chars.next_if(|(_, c)| *c == ':').is_none() {
+                    return Err(format!("Expected ':' after key '{}'", key));
+                }
+                let value = parse_flow_value(text, chars, true)?;
+                entries.push((key, value));
+                skip_spaces(chars);
+                match chars.next() {
+                    Some((_, ',')) => {}
+                    Some((_, '}')) => break,
+                    _ => return Err("Expected ',' or '}' in flow mapping".to_string()),
+                }
+            }
+            Ok(Node::Map(entries))
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = vec![];
+            loop {
+                skip_spaces(chars);
+                if chars.next_if(|(_, c)| *c == ']').is_some() {
+                    break;
+                }
+                items.push(parse_flow_value(text, chars, true)?);
+                skip_spaces(chars);
+                match chars.next() {
+                    Some((_, ',')) => {}
+                    Some((_, ']')) => break,
+                    _ => return Err("Expected ',' or ']' in flow sequence".to_string()),
+                }
+            }
+            Ok(Node::Seq(items))
+        }
+        _ => parse_scalar(text, chars, nested).map(Node::Scalar),
+    }
+}
+
+/// Parse a scalar. Inside flow collections, plain scalars end at the first
+/// `,`, `:`, `}`, or `]`.
+fn parse_scalar(text: &str, chars: &mut Chars, nested: bool) -> Result<String, String> {
+    skip_spaces(chars);
+    let Some(&(start, first)) = chars.peek() else {
+        return Ok(String::new());
+    };
+    if first == '"' || first == '\'' {
+        chars.next();
+        let mut s = String::new();
+        loop {
+            match chars.next() {
+                None => return Err("Unterminated quoted string".to_string()),
+                // Quotes are escaped by doubling them in single-quoted strings
+                Some((_, c)) if c == first => {
+                    if first == '\'' && chars.next_if(|(_, c)| *c == '\'').is_some() {
+                        s.push('\'');
+                    } else {
+                        break;
+                    }
+                }
+                Some((_, '\\')) if first == '"' => match chars.next() {
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, c)) => s.push(c),
+                    None => return Err("Unterminated quoted string".to_string()),
+                },
+                Some((_, c)) => s.push(c),
+            }
+        }
+        return Ok(s);
+    }
+    let mut end = text.len();
+    while let Some(&(index, c)) = chars.peek() {
+        if nested && matches!(c, ',' | ':' | '}' | ']') {
+            end = index;
+            break;
+        }
+        chars.next();
+    }
+    Ok(text[start..end].trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar(s: &str) -> Node {
+        Node::Scalar(s.to_string())
+    }
+
+    #[test]
+    fn parse() {
+        let source = r#"%YAML 1.1
+%TAG !u! tag:unity3d.com,2011:
+--- !u!1 &100
+GameObject:
+  m_Name: 'Fire ''big'''
+  m_Component:
+  - component: {fileID: 200}
+  - component: {fileID: 300}
+--- !u!198 &300
+ParticleSystem:
+  lengthInSec: 5
+  InitialModule:
+    startColor:
+      minColor: {r: 1, g: 0.5, b: 0, a: 1}
+  EmissionModule:
+    m_Bursts:
+    - serializedVersion: 2
+      time: 0
+      countCurve:
+        scalar: 30
+    m_Empty: []
+"#;
+        let documents = parse_documents(source).unwrap();
+        assert_eq!(documents.len(), 2);
+
+        let go = &documents[0];
+        assert_eq!(go.class_id, 1);
+        assert_eq!(go.file_id, 100);
+        let go = go.root.get("GameObject").unwrap();
+        assert_eq!(go.get("m_Name"), Some(&scalar("Fire 'big'")));
+        let components = go.get("m_Component").unwrap().items();
+        assert_eq!(components.len(), 2);
+        assert_eq!(
+            components[1].get("component").unwrap().i64("fileID"),
+            Some(300)
+        );
+
+        let ps = &documents[1];
+        assert_eq!(ps.class_id, 198);
+        let ps = ps.root.get("ParticleSystem").unwrap();
+        assert_eq!(ps.f32("lengthInSec"), Some(5.));
+        let color = ps
+            .get("InitialModule")
+            .and_then(|m| m.get("startColor"))
+            .and_then(|c| c.get("minColor"))
+            .unwrap();
+        assert_eq!(color.f32("g"), Some(0.5));
+        let emission = ps.get("EmissionModule").unwrap();
+        let bursts = emission.get("m_Bursts").unwrap().items();
+        assert_eq!(bursts.len(), 1);
+        assert_eq!(bursts[0].f32("time"), Some(0.));
+        assert_eq!(
+            bursts[0].get("countCurve").unwrap().f32("scalar"),
+            Some(30.)
+        );
+        assert_eq!(emission.get("m_Empty"), Some(&Node::Seq(vec![])));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err =
+            parse_documents("--- !u!198 &1\nParticleSystem:\n  a: 1\n    b: 2\n").unwrap_err();
+        assert!(matches!(err, ShurikenImportError::Parse { line: 4, .. }));
+
+        let err = parse_documents("--- !u!198 &1\nParticleSystem:\n  a: {x: 1\n").unwrap_err();
+        assert!(matches!(err, ShurikenImportError::Parse { line: 3, .. }));
+
+        let err = parse_documents("--- !u!x\n").unwrap_err();
+        assert!(matches!(err, ShurikenImportError::Parse { line: 1, .. }));
+    }
+}
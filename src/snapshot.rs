@@ -0,0 +1,135 @@
+use bevy::log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::Value, EffectProperties, EffectSpawner, EffectSpawnerSnapshot};
+
+/// Serializable snapshot of the runtime state of an effect instance.
+///
+/// The snapshot holds the state of the [`EffectSpawner`] of the instance,
+/// including its spawn phase, elapsed time, and random number generator, as
+/// well as the current value of its [`EffectProperties`]. Games with a save
+/// system can serialize it alongside the entity owning the effect, and restore
+/// it on load, so ambient effects resume mid-loop instead of visibly
+/// restarting.
+///
+/// The particles themselves live on GPU and are not part of the snapshot.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn save(query: Query<(&EffectSpawner, Option<&EffectProperties>)>) {
+///     for (spawner, properties) in &query {
+///         let snapshot = EffectSnapshot::capture(spawner, properties);
+///         let text = ron::to_string(&snapshot).unwrap();
+///         // [...] write the text to the save file
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectSnapshot {
+    /// State of the spawner of the effect instance.
+    pub spawner: EffectSpawnerSnapshot,
+    /// Value of the properties of the effect instance, by name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<(String, Value)>,
+}
+
+impl EffectSnapshot {
+    /// Capture the runtime state of an effect instance.
+    pub fn capture(spawner: &EffectSpawner, properties: Option<&EffectProperties>) -> Self {
+        Self {
+            spawner: spawner.snapshot(),
+            properties: properties
+                .map(|properties| {
+                    properties
+                        .properties()
+                        .iter()
+                        .map(|prop| (prop.def.name().to_string(), prop.value))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Restore the runtime state of an effect instance.
+    ///
+    /// The spawner state is restored with [`EffectSpawner::restore()`]. The
+    /// value of each property of the snapshot is assigned with
+    /// [`EffectProperties::set()`], except for properties whose type changed
+    /// since the snapshot was captured, which are skipped with a warning.
+    ///
+    /// If the entity of the effect instance doesn't have an
+    /// [`EffectProperties`] component yet, for example because it was just
+    /// spawned while loading a saved game, insert one created with
+    /// [`EffectProperties::with_properties()`] from the [`properties`] of the
+    /// snapshot instead.
+    ///
+    /// [`properties`]: crate::EffectSnapshot::properties
+    pub fn restore(&self, spawner: &mut EffectSpawner, properties: Option<&mut EffectProperties>) {
+        spawner.restore(&self.spawner);
+        let Some(properties) = properties else {
+            return;
+        };
+        for (name, value) in &self.properties {
+            if let Some(current) = properties.get_stored(name) {
+                if current.value_type() != value.value_type() {
+                    warn!(
+                        "Cannot restore property '{}' of type {:?} from a value of type {:?}.",
+                        name,
+                        current.value_type(),
+                        value.value_type()
+                    );
+                    continue;
+                }
+            }
+            properties.set(name, *value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EffectAsset, Module, Spawner};
+
+    #[test]
+    fn snapshot() {
+        let asset = EffectAsset::new(vec![32], Spawner::rate(3.0.into()), Module::default());
+        let mut spawner = EffectSpawner::new(&asset).with_seed(Some(42));
+        let mut rng = crate::spawn::new_rng();
+        spawner.tick(0.5, &mut rng);
+        let properties = EffectProperties::default()
+            .with_properties([("speed".to_string(), Value::from(2_f32))]);
+
+        let snapshot = EffectSnapshot::capture(&spawner, Some(&properties));
+        assert_eq!(
+            snapshot.properties,
+            vec![("speed".to_string(), Value::from(2_f32))]
+        );
+        let text = ron::to_string(&snapshot).unwrap();
+        let snapshot: EffectSnapshot = ron::from_str(&text).unwrap();
+
+        // The restored spawner resumes exactly where the original was
+        let mut restored = EffectSpawner::new(&asset);
+        let mut restored_properties = EffectProperties::default()
+            .with_properties([("speed".to_string(), Value::from(1_f32))]);
+        snapshot.restore(&mut restored, Some(&mut restored_properties));
+        assert_eq!(restored.snapshot(), spawner.snapshot());
+        assert_eq!(restored.elapsed(), 0.5);
+        assert_eq!(
+            restored_properties.get_stored("speed"),
+            Some(Value::from(2_f32))
+        );
+        for _ in 0..10 {
+            assert_eq!(spawner.tick(0.1, &mut rng), restored.tick(0.1, &mut rng));
+        }
+
+        // Properties whose type changed are not restored
+        let mut properties = EffectProperties::default()
+            .with_properties([("speed".to_string(), Value::from(1_i32))]);
+        snapshot.restore(&mut restored, Some(&mut properties));
+        assert_eq!(properties.get_stored("speed"), Some(Value::from(1_i32)));
+    }
+}
@@ -1,14 +1,22 @@
 use std::hash::{Hash, Hasher};
 
-use bevy::{ecs::system::Resource, prelude::*, reflect::Reflect, utils::FloatOrd};
+use bevy::{
+    ecs::system::Resource,
+    prelude::*,
+    reflect::Reflect,
+    utils::{FloatOrd, HashSet},
+};
 use rand::{
     distributions::{uniform::SampleUniform, Distribution, Uniform},
-    SeedableRng,
+    Rng, SeedableRng,
 };
 use rand_pcg::Pcg32;
 use serde::{Deserialize, Serialize};
 
-use crate::{EffectAsset, EffectSimulation, ParticleEffect, SimulationCondition};
+use crate::{
+    EffectAsset, EffectProperties, EffectSimulation, GlobalProperties, ParticleEffect,
+    ParticleGroupSet, SimulationCondition,
+};
 
 /// An RNG to be used in the CPU for the particle system engine
 pub(crate) fn new_rng() -> Pcg32 {
@@ -18,6 +26,9 @@ pub(crate) fn new_rng() -> Pcg32 {
     Pcg32::from_seed(seed)
 }
 
+/// Maximum number of frames replayed to pre-warm a stochastic spawner.
+const MAX_PREWARM_FRAMES: u32 = 10_000;
+
 /// An RNG resource
 #[derive(Resource)]
 pub struct Random(pub Pcg32);
@@ -182,6 +193,32 @@ pub struct Spawner {
     /// spawner becomes active. If `false`, the spawner doesn't do anything
     /// until [`EffectSpawner::reset()`] is called.
     starts_immediately: bool,
+
+    /// Per-frame spawn probability, for stochastic spawners.
+    ///
+    /// If `Some(p)`, each frame the spawner emits a burst of `num_particles`
+    /// particles with probability `p`, and ignores `spawn_time` and `period`.
+    /// See [`Spawner::probabilistic()`].
+    #[serde(default)]
+    probability: Option<f32>,
+
+    /// Delay between two consecutive spawn cycles, in seconds.
+    ///
+    /// See [`Spawner::with_loop_delay()`].
+    #[serde(default)]
+    loop_delay: Option<CpuValue<f32>>,
+
+    /// Maximum number of spawn cycles, or `None` to loop forever.
+    ///
+    /// See [`Spawner::with_loop_count()`].
+    #[serde(default)]
+    loop_count: Option<u32>,
+
+    /// Scaling of the spawn count with the scale of the emitter entity.
+    ///
+    /// See [`Spawner::with_spawn_scaling()`].
+    #[serde(default)]
+    spawn_scaling: SpawnScaling,
 }
 
 impl Default for Spawner {
@@ -250,6 +287,10 @@ impl Spawner {
             period,
             starts_active: true,
             starts_immediately: true,
+            probability: None,
+            loop_delay: None,
+            loop_count: None,
+            spawn_scaling: SpawnScaling::Disabled,
         }
     }
 
@@ -335,6 +376,161 @@ impl Spawner {
         Self::new(count, 0.0.into(), period)
     }
 
+    /// Create a stochastic spawner that, each frame, spawns `count` particles
+    /// with the given `probability`.
+    ///
+    /// This is useful for sparse random events, like the occasional ember
+    /// popping out of a fire, which look too regular with a rate-based or
+    /// burst-based spawner. The `count` is sampled independently each time the
+    /// spawner emits a burst.
+    ///
+    /// Note that the probability applies per simulation frame, so the average
+    /// number of particles spawned per second depends on the frame rate. For
+    /// the same reason, pre-warming replays the bursts of as many frames as
+    /// the pre-warming duration spans, assuming all frames last as long as the
+    /// first active one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` is not in `[0:1]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::{CpuValue, Spawner};
+    /// // Each frame, 2% chance to spawn between 1 and 3 particles. The count
+    /// // is rounded down, so is uniformly distributed in [1:4[.
+    /// let spawner = Spawner::probabilistic(CpuValue::Uniform((1., 4.)), 0.02);
+    /// ```
+    pub fn probabilistic(count: CpuValue<f32>, probability: f32) -> Self {
+        assert!(
+            (0. ..=1.).contains(&probability),
+            "`probability` must be in [0:1] (was {}).",
+            probability
+        );
+        let mut spawner = Self::new(count, 0.0.into(), 1.0.into());
+        spawner.probability = Some(probability);
+        spawner
+    }
+
+    /// Set a delay between two consecutive spawn cycles, in seconds.
+    ///
+    /// A spawn cycle lasts for [`period()`] seconds. When a loop delay is set,
+    /// the spawner waits for an additional delay after each cycle before
+    /// starting the next one. For [`once()`] spawners, which otherwise never
+    /// loop, setting a loop delay makes the spawner emit a new burst each time
+    /// the delay elapsed.
+    ///
+    /// Use [`with_loop_count()`] to limit the number of cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `loop_delay` can be a negative number, or can only be 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::Spawner;
+    /// // Spawn 3 bursts of 10 particles, 2 seconds apart.
+    /// let spawner = Spawner::once(10.0.into(), true)
+    ///     .with_loop_delay(2.0.into())
+    ///     .with_loop_count(Some(3));
+    /// ```
+    ///
+    /// [`period()`]: crate::Spawner::period
+    /// [`once()`]: crate::Spawner::once
+    /// [`with_loop_count()`]: crate::Spawner::with_loop_count
+    pub fn with_loop_delay(mut self, loop_delay: CpuValue<f32>) -> Self {
+        self.set_loop_delay(loop_delay);
+        self
+    }
+
+    /// Set a delay between two consecutive spawn cycles, in seconds.
+    ///
+    /// See [`with_loop_delay()`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `loop_delay` can be a negative number, or can only be 0.
+    ///
+    /// [`with_loop_delay()`]: crate::Spawner::with_loop_delay
+    pub fn set_loop_delay(&mut self, loop_delay: CpuValue<f32>) {
+        assert!(
+            loop_delay.range()[0] >= 0.,
+            "`loop_delay` must not generate negative numbers (loop_delay.min was {}, expected >= 0).",
+            loop_delay.range()[0]
+        );
+        assert!(
+            loop_delay.range()[1] > 0.,
+            "`loop_delay` must be able to generate a positive number (loop_delay.max was {}, expected > 0).",
+            loop_delay.range()[1]
+        );
+        self.loop_delay = Some(loop_delay);
+    }
+
+    /// Get the delay between two consecutive spawn cycles, if any.
+    pub fn loop_delay(&self) -> Option<CpuValue<f32>> {
+        self.loop_delay
+    }
+
+    /// Set the maximum number of spawn cycles.
+    ///
+    /// Once the spawner completed that many cycles, it stops spawning until
+    /// [`EffectSpawner::reset()`] is called. A value of `None` (the default)
+    /// makes the spawner loop forever.
+    pub fn with_loop_count(mut self, loop_count: Option<u32>) -> Self {
+        self.loop_count = loop_count;
+        self
+    }
+
+    /// Set the maximum number of spawn cycles.
+    ///
+    /// See [`with_loop_count()`] for details.
+    ///
+    /// [`with_loop_count()`]: crate::Spawner::with_loop_count
+    pub fn set_loop_count(&mut self, loop_count: Option<u32>) {
+        self.loop_count = loop_count;
+    }
+
+    /// Get the maximum number of spawn cycles, if any.
+    pub fn loop_count(&self) -> Option<u32> {
+        self.loop_count
+    }
+
+    /// Set how the spawn count scales with the scale of the emitter entity.
+    ///
+    /// By default the spawn count is independent of the emitter scale. When
+    /// scaling is enabled, the number of particles spawned is multiplied by a
+    /// factor derived from the scale of the [`GlobalTransform`] of the entity,
+    /// so that the particle density stays constant when the effect instance is
+    /// scaled up or down. Pick the [`SpawnScaling`] variant matching the
+    /// dimension of the emission shape.
+    pub fn with_spawn_scaling(mut self, spawn_scaling: SpawnScaling) -> Self {
+        self.spawn_scaling = spawn_scaling;
+        self
+    }
+
+    /// Set how the spawn count scales with the scale of the emitter entity.
+    ///
+    /// See [`with_spawn_scaling()`] for details.
+    ///
+    /// [`with_spawn_scaling()`]: crate::Spawner::with_spawn_scaling
+    pub fn set_spawn_scaling(&mut self, spawn_scaling: SpawnScaling) {
+        self.spawn_scaling = spawn_scaling;
+    }
+
+    /// Get how the spawn count scales with the scale of the emitter entity.
+    pub fn spawn_scaling(&self) -> SpawnScaling {
+        self.spawn_scaling
+    }
+
+    /// Get the per-frame spawn probability, if this spawner is stochastic.
+    ///
+    /// See [`Spawner::probabilistic()`].
+    pub fn probability(&self) -> Option<f32> {
+        self.probability
+    }
+
     /// Set the number of particles that are spawned each cycle.
     pub fn with_count(mut self, count: CpuValue<f32>) -> Self {
         self.num_particles = count;
@@ -412,6 +608,101 @@ impl Spawner {
     }
 }
 
+/// Scaling of the spawn count of a [`Spawner`] with the scale of the emitter
+/// entity.
+///
+/// The scale factor is derived from the scale of the [`GlobalTransform`] of the
+/// entity holding the [`ParticleEffect`], and raised to the power matching the
+/// dimension of the emission shape. For non-uniform scales, the geometric mean
+/// of the scale components is used for [`Linear`] and [`Area`].
+///
+/// [`Linear`]: SpawnScaling::Linear
+/// [`Area`]: SpawnScaling::Area
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum SpawnScaling {
+    /// The spawn count doesn't depend on the emitter scale.
+    #[default]
+    Disabled,
+    /// The spawn count scales linearly with the emitter scale. Use for
+    /// emission along a line or a circle.
+    Linear,
+    /// The spawn count scales with the square of the emitter scale. Use for
+    /// emission from a surface.
+    Area,
+    /// The spawn count scales with the cube of the emitter scale. Use for
+    /// emission from a volume.
+    Volume,
+}
+
+impl SpawnScaling {
+    /// Calculate the spawn count factor for an emitter with the given scale.
+    pub fn factor(&self, scale: Vec3) -> f32 {
+        let volume = (scale.x * scale.y * scale.z).abs();
+        match self {
+            SpawnScaling::Disabled => 1.,
+            SpawnScaling::Linear => volume.cbrt(),
+            SpawnScaling::Area => volume.cbrt().powi(2),
+            SpawnScaling::Volume => volume,
+        }
+    }
+}
+
+/// Parameter of the [`Spawner`] of an effect which can be driven by a
+/// property.
+///
+/// See [`EffectAsset::with_spawner_property()`] for details.
+///
+/// [`EffectAsset::with_spawner_property()`]: crate::EffectAsset::with_spawner_property
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum SpawnerParam {
+    /// The number of particles spawned each cycle. See
+    /// [`Spawner::set_count()`].
+    Count,
+    /// The length of the spawn time each cycle, in seconds. See
+    /// [`Spawner::set_spawn_time()`].
+    SpawnTime,
+    /// The wait time between spawn cycles, in seconds. See
+    /// [`Spawner::set_period()`]. Values less than or equal to zero are
+    /// ignored.
+    Period,
+}
+
+impl SpawnerParam {
+    /// Set the value of this parameter on a spawner.
+    fn apply(&self, spawner: &mut Spawner, value: f32) {
+        match self {
+            SpawnerParam::Count => spawner.set_count(CpuValue::Single(value.max(0.))),
+            SpawnerParam::SpawnTime => spawner.set_spawn_time(CpuValue::Single(value.max(0.))),
+            SpawnerParam::Period => {
+                if value > 0. {
+                    spawner.set_period(CpuValue::Single(value));
+                }
+            }
+        }
+    }
+}
+
+/// Playback state of an effect instance.
+///
+/// The playback state is stored in the [`EffectSpawner`] of the effect
+/// instance, and controlled via [`EffectSpawner::play()`],
+/// [`EffectSpawner::pause()`], [`EffectSpawner::stop()`], and
+/// [`EffectSpawner::restart()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum EffectPlayback {
+    /// The effect spawns new particles according to its [`Spawner`], and
+    /// simulates all existing particles.
+    #[default]
+    Playing,
+    /// The effect is frozen. No particle is spawned, and existing particles
+    /// are not simulated (their age doesn't increase and they don't move).
+    /// The particles are still rendered.
+    Paused,
+    /// The effect stopped spawning new particles, but existing particles
+    /// continue to be simulated until they die.
+    Stopped,
+}
+
 /// Runtime component maintaining the state of the spawner for an effect.
 ///
 /// This component is automatically added to the same [`Entity`] as the
@@ -422,14 +713,24 @@ impl Spawner {
 ///
 /// You can manually add this component in advance to override its [`Spawner`].
 /// In that case [`tick_spawners()`] will use the existing component you added.
+/// When the [`EffectAsset`] is modified, for example when hot-reloaded, the
+/// spawner configuration is updated from the modified asset, replacing any such
+/// override, while the current state of the spawner is preserved.
 ///
 /// Each frame, the component will automatically calculate the number of
 /// particles to spawn, via its internal [`Spawner`], and store it into
 /// [`spawn_count`]. You can manually override that value if you want, to create
 /// more complex spawning sequences.
 ///
+/// If the [`EffectAsset`] defines additional emitters (see
+/// [`EffectAsset::with_emitter()`]), the component also holds the state of
+/// their spawners, accessible with [`emitters()`]. Those share the playback
+/// state of this component, but otherwise tick independently.
+///
 /// [`spawn_count`]: crate::EffectSpawner::spawn_count
-#[derive(Default, Clone, Copy, PartialEq, Component)]
+/// [`EffectAsset::with_emitter()`]: crate::EffectAsset::with_emitter
+/// [`emitters()`]: crate::EffectSpawner::emitters
+#[derive(Default, Clone, PartialEq, Component)]
 pub struct EffectSpawner {
     /// The spawner configuration extracted either from the [`EffectAsset`], or
     /// from any overriden value provided by the user on the [`ParticleEffect`].
@@ -467,40 +768,248 @@ pub struct EffectSpawner {
 
     /// Whether the system is active. Defaults to `true`.
     active: bool,
+
+    /// Number of spawn cycles completed since the last reset.
+    cycle: u32,
+
+    /// Simulation time elapsed since the last reset, in seconds.
+    elapsed: f32,
+
+    /// Whether the spawner completed all its cycles, and waits for a reset to
+    /// spawn again.
+    completed: bool,
+
+    /// Pre-warming duration, in seconds, copied from
+    /// [`EffectAsset::prewarm`].
+    prewarm: f32,
+
+    /// Whether pre-warming still needs to be applied on next tick.
+    prewarm_pending: bool,
+
+    /// Pre-warming duration applied this frame, or zero if none.
+    prewarm_time: f32,
+
+    /// Time to fast-forward to on the next tick after a [`seek()`], replacing
+    /// the pre-warming duration.
+    ///
+    /// [`seek()`]: crate::EffectSpawner::seek
+    seek_time: Option<f32>,
+
+    /// Playback state of the effect instance.
+    playback: EffectPlayback,
+
+    /// Bitfield of the particle groups to kill on next tick.
+    pending_kill_groups: u32,
+
+    /// Bitfield of the particle groups for which all particles are killed
+    /// this frame.
+    kill_groups: u32,
+
+    /// Factor applied to the number of particles spawned, derived from the
+    /// emitter scale according to [`Spawner::spawn_scaling()`].
+    spawn_scale: f32,
+
+    /// Factor applied to the simulation delta time of the effect instance.
+    time_scale: f32,
+
+    /// Seed of the random number generator of the effect instance, if fixed.
+    seed: Option<u32>,
+
+    /// Random number generator of the effect instance, if seeded with a fixed
+    /// seed. Otherwise the global [`Random`] generator is used.
+    rng: Option<Pcg32>,
+
+    /// Seed of the GPU random number generator for the current frame, drawn
+    /// from the CPU random number generator on each tick.
+    gpu_seed: u32,
+
+    /// Spawner states of the additional emitters of the effect, in the order
+    /// of [`EffectAsset::emitters()`].
+    emitters: Vec<EffectSpawner>,
 }
 
 impl EffectSpawner {
     /// Create a new spawner state from an asset definition.
     pub fn new(asset: &EffectAsset) -> Self {
-        let spawner = asset.spawner;
+        let mut effect_spawner = Self::from_spawner(asset.spawner, asset.prewarm);
+        effect_spawner.emitters = asset
+            .emitters()
+            .iter()
+            .map(|emitter| Self::from_spawner(emitter.spawner, asset.prewarm))
+            .collect();
+        effect_spawner
+    }
+
+    /// Create a new spawner state for a single spawner, without any additional
+    /// emitter.
+    fn from_spawner(spawner: Spawner, prewarm: f32) -> Self {
         Self {
             spawner,
-            time: if spawner.is_once() && !spawner.starts_immediately {
-                1. // anything > 0
-            } else {
-                0.
-            },
+            time: 0.,
             curr_spawn_time: 0.,
             limit: 0.,
             spawn_count: 0,
             spawn_remainder: 0.,
             active: spawner.starts_active(),
+            cycle: 0,
+            elapsed: 0.,
+            // A once spawner not starting immediately waits for a reset
+            completed: spawner.is_once() && !spawner.starts_immediately,
+            prewarm,
+            prewarm_pending: prewarm > 0.,
+            prewarm_time: 0.,
+            seek_time: None,
+            playback: if spawner.starts_active() {
+                EffectPlayback::Playing
+            } else {
+                EffectPlayback::Stopped
+            },
+            pending_kill_groups: 0,
+            kill_groups: 0,
+            spawn_scale: 1.,
+            time_scale: 1.,
+            seed: None,
+            rng: None,
+            gpu_seed: 0,
+            emitters: vec![],
         }
     }
 
+    /// Update the spawner configurations from a modified asset, keeping the
+    /// current state of the spawners.
+    ///
+    /// The spawners of additional emitters added to the asset start in their
+    /// initial state, while those of removed emitters are discarded.
+    pub(crate) fn reload(&mut self, asset: &EffectAsset) {
+        self.spawner = asset.spawner;
+        self.prewarm = asset.prewarm;
+        self.emitters.truncate(asset.emitters().len());
+        for (index, emitter) in asset.emitters().iter().enumerate() {
+            if let Some(state) = self.emitters.get_mut(index) {
+                state.spawner = emitter.spawner;
+                state.prewarm = asset.prewarm;
+            } else {
+                self.emitters
+                    .push(Self::from_spawner(emitter.spawner, asset.prewarm));
+            }
+        }
+    }
+
+    /// Seed the random number generator of the effect instance.
+    ///
+    /// See [`set_seed()`] for details.
+    ///
+    /// [`set_seed()`]: crate::EffectSpawner::set_seed
+    pub fn with_seed(mut self, seed: Option<u32>) -> Self {
+        self.set_seed(seed);
+        self
+    }
+
+    /// Seed the random number generator of the effect instance.
+    ///
+    /// With a fixed seed, all the random values of the effect instance are
+    /// derived from that seed only: the spawner randomness on CPU, and the
+    /// per-frame seed of the random values evaluated on GPU by the modifiers.
+    /// Two instances with the same seed, ticked with the same sequence of
+    /// delta times, produce the same particles, which allows replays, lockstep
+    /// multiplayer visuals, and screenshot-based regression tests. With `None`,
+    /// the effect draws its random values from the global generator shared by
+    /// all effects, so isn't reproducible.
+    ///
+    /// Setting a seed restarts the random sequence of the effect, but doesn't
+    /// reset the spawner itself; use [`reset()`] for that. This is normally
+    /// set automatically from [`ParticleEffect::seed`] when the spawner is
+    /// created.
+    ///
+    /// Note that the particles are allocated on GPU in a non-deterministic
+    /// order, so the particle buffer may store the same particles in a
+    /// different order on each run.
+    ///
+    /// [`reset()`]: crate::EffectSpawner::reset
+    /// [`ParticleEffect::seed`]: crate::ParticleEffect::seed
+    pub fn set_seed(&mut self, seed: Option<u32>) {
+        self.seed = seed;
+        self.rng = seed.map(|seed| Pcg32::seed_from_u64(seed as u64));
+    }
+
+    /// Get the fixed seed of the random number generator of the effect
+    /// instance, if any.
+    pub fn seed(&self) -> Option<u32> {
+        self.seed
+    }
+
+    /// Get the seed of the GPU random number generator for the current frame.
+    pub(crate) fn gpu_seed(&self) -> u32 {
+        self.gpu_seed
+    }
+
+    /// Get the random number generator of the effect instance, if seeded with
+    /// a fixed seed.
+    pub(crate) fn rng_mut(&mut self) -> Option<&mut Pcg32> {
+        self.rng.as_mut()
+    }
+
+    /// Set the factor applied to the simulation delta time of the effect
+    /// instance.
+    ///
+    /// See [`set_time_scale()`] for details.
+    ///
+    /// [`set_time_scale()`]: crate::EffectSpawner::set_time_scale
+    pub fn with_time_scale(mut self, time_scale: f32) -> Self {
+        self.set_time_scale(time_scale);
+        self
+    }
+
+    /// Set the factor applied to the simulation delta time of the effect
+    /// instance.
+    ///
+    /// The effect instance is simulated with the delta time of the
+    /// [`Time<EffectSimulation>`] clock multiplied by this factor, both to
+    /// spawn new particles and to update existing ones. This is applied on top
+    /// of the speed of the [`Time<Virtual>`] and [`Time<EffectSimulation>`]
+    /// clocks, which affect all effects. A value of `0.5` simulates the effect
+    /// at half speed, while a value of `0.0` freezes it like [`pause()`].
+    /// Negative values are clamped to zero. Defaults to `1.0`.
+    ///
+    /// For example, when slowing down the virtual clock for a slow-motion
+    /// gameplay sequence, setting the time scale of UI effects to the inverse
+    /// of the [`effective_speed()`] of the effect clock keeps those effects at
+    /// real-time speed.
+    ///
+    /// [`Time<EffectSimulation>`]: crate::EffectSimulation
+    /// [`Time<Virtual>`]: bevy::time::Virtual
+    /// [`pause()`]: crate::EffectSpawner::pause
+    /// [`effective_speed()`]: crate::EffectSimulationTime::effective_speed
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.);
+    }
+
+    /// Get the factor applied to the simulation delta time of the effect
+    /// instance.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
     /// Set whether the spawner is active.
     ///
     /// Inactive spawners do not spawn any particle.
     pub fn with_active(mut self, active: bool) -> Self {
-        self.active = active;
+        self.set_active(active);
         self
     }
 
     /// Set whether the spawner is active.
     ///
-    /// Inactive spawners do not spawn any particle.
+    /// Inactive spawners do not spawn any particle. Deactivating the spawner
+    /// changes the playback state to [`EffectPlayback::Stopped`], while
+    /// activating a stopped spawner changes it to [`EffectPlayback::Playing`].
     pub fn set_active(&mut self, active: bool) {
         self.active = active;
+        if !active {
+            self.playback = EffectPlayback::Stopped;
+        } else if self.playback == EffectPlayback::Stopped {
+            self.playback = EffectPlayback::Playing;
+        }
     }
 
     /// Get whether the spawner is active.
@@ -510,6 +1019,139 @@ impl EffectSpawner {
         self.active
     }
 
+    /// Get the playback state of the effect instance.
+    pub fn playback(&self) -> EffectPlayback {
+        self.playback
+    }
+
+    /// Play the effect.
+    ///
+    /// This resumes a paused effect where it was paused, or resumes spawning
+    /// new particles for a stopped effect. This has no effect if the effect is
+    /// already playing.
+    pub fn play(&mut self) {
+        self.playback = EffectPlayback::Playing;
+        self.active = true;
+    }
+
+    /// Pause the effect.
+    ///
+    /// This freezes the effect: the spawner doesn't spawn any new particle,
+    /// and existing particles are not simulated anymore, until [`play()`] is
+    /// called. Existing particles continue to be rendered.
+    ///
+    /// [`play()`]: crate::EffectSpawner::play
+    pub fn pause(&mut self) {
+        self.playback = EffectPlayback::Paused;
+    }
+
+    /// Stop the effect.
+    ///
+    /// This stops spawning new particles, but lets existing particles finish
+    /// their life. This is equivalent to deactivating the spawner with
+    /// [`set_active(false)`].
+    ///
+    /// [`set_active(false)`]: crate::EffectSpawner::set_active
+    pub fn stop(&mut self) {
+        self.playback = EffectPlayback::Stopped;
+        self.active = false;
+    }
+
+    /// Restart the effect from scratch.
+    ///
+    /// This kills all existing particles of all groups, resets the spawner
+    /// with [`reset()`], and plays the effect. The particles are killed on GPU
+    /// during the next simulation update.
+    ///
+    /// [`reset()`]: crate::EffectSpawner::reset
+    pub fn restart(&mut self) {
+        self.reset();
+        self.pending_kill_groups = !0;
+        self.play();
+    }
+
+    /// Seek the effect to the given time, in seconds since it started.
+    ///
+    /// This kills all existing particles of all groups and resets the spawner
+    /// like [`restart()`], then fast-forwards the effect to `time` using the
+    /// same analytic fast-forward as pre-warming (see
+    /// [`EffectAsset::with_prewarm()`]), in place of the pre-warming duration
+    /// of the asset. The particles are killed on the next tick, and the
+    /// particles alive at `time` are spawned on the following one, so that
+    /// the update pass killing the old particles doesn't also kill the new
+    /// ones.
+    ///
+    /// If the effect instance has a fixed seed (see [`set_seed()`]), its random
+    /// number generator is re-seeded, so seeking to the same time always
+    /// spawns the same particles. The playback state is kept, so a paused
+    /// effect is displayed frozen at `time`, which allows scrubbing an effect
+    /// along a timeline.
+    ///
+    /// The result is an approximation of the actual simulation, which ignores
+    /// the update modifiers over the fast-forwarded duration. For an exact
+    /// replay, use the [`CpuSimulation::seek()`] of a CPU simulation.
+    ///
+    /// [`restart()`]: crate::EffectSpawner::restart
+    /// [`EffectAsset::with_prewarm()`]: crate::EffectAsset::with_prewarm
+    /// [`set_seed()`]: crate::EffectSpawner::set_seed
+    /// [`CpuSimulation::seek()`]: crate::CpuSimulation::seek
+    pub fn seek(&mut self, time: f32) {
+        self.reset();
+        self.set_seed(self.seed);
+        self.fast_forward(time.max(0.));
+    }
+
+    /// Step the effect backward by `dt` seconds.
+    ///
+    /// This seeks the effect to its [`elapsed()`] time minus `dt`, clamped to
+    /// zero. See [`seek()`] for details.
+    ///
+    /// [`elapsed()`]: crate::EffectSpawner::elapsed
+    /// [`seek()`]: crate::EffectSpawner::seek
+    pub fn step_back(&mut self, dt: f32) {
+        self.seek(self.elapsed - dt);
+    }
+
+    /// Kill all particles on the next tick, and schedule a fast-forward to
+    /// `time` on the following one, for the spawner and all its emitters.
+    fn fast_forward(&mut self, time: f32) {
+        self.pending_kill_groups = !0;
+        self.prewarm_pending = time > 0.;
+        self.seek_time = Some(time);
+        for emitter in &mut self.emitters {
+            emitter.fast_forward(time);
+        }
+    }
+
+    /// Kill all existing particles of some particle groups.
+    ///
+    /// Unlike [`restart()`], this only affects the particles of the given
+    /// groups, and doesn't change the spawner state nor the playback state.
+    /// For example, this can clear the trail particles left behind by a
+    /// projectile when it's destroyed, while the other groups of the effect
+    /// continue to be simulated. The particles are killed on GPU during the
+    /// next simulation update, which for an effect not simulated this frame,
+    /// for example because it's culled, is the next frame it's simulated.
+    ///
+    /// With named groups, the set of groups can be obtained by name with
+    /// [`EffectAsset::group_set()`].
+    ///
+    /// [`restart()`]: crate::EffectSpawner::restart
+    /// [`EffectAsset::group_set()`]: crate::EffectAsset::group_set
+    pub fn kill_particles(&mut self, groups: ParticleGroupSet) {
+        self.pending_kill_groups |= groups.0;
+    }
+
+    /// Get the bitfield of particle groups requested to be killed since the
+    /// previous tick.
+    ///
+    /// This is extracted to the render world each frame, even if the effect
+    /// is not simulated, and kept pending there until an update pass actually
+    /// applies it.
+    pub(crate) fn kill_groups(&self) -> u32 {
+        self.kill_groups
+    }
+
     /// Get the spawner configuration in use.
     ///
     /// The effective [`Spawner`] used is either the override specified in the
@@ -519,6 +1161,32 @@ impl EffectSpawner {
         &self.spawner
     }
 
+    /// Get the spawner states of the additional emitters of the effect.
+    ///
+    /// There's one entry per emitter, in the order of
+    /// [`EffectAsset::emitters()`]. The number of particles each emitter
+    /// spawns this frame is its [`spawn_count`].
+    ///
+    /// [`EffectAsset::emitters()`]: crate::EffectAsset::emitters
+    /// [`spawn_count`]: crate::EffectSpawner::spawn_count
+    pub fn emitters(&self) -> &[EffectSpawner] {
+        &self.emitters
+    }
+
+    /// Get mutable access to the spawner states of the additional emitters of
+    /// the effect.
+    ///
+    /// This allows overriding the [`spawn_count`] of an emitter, similarly to
+    /// the one of the effect itself. The playback state of the emitters is
+    /// always synchronized with the one of the effect during [`tick()`], so
+    /// can't be controlled individually.
+    ///
+    /// [`spawn_count`]: crate::EffectSpawner::spawn_count
+    /// [`tick()`]: crate::EffectSpawner::tick
+    pub fn emitters_mut(&mut self) -> &mut [EffectSpawner] {
+        &mut self.emitters
+    }
+
     /// Reset the spawner state.
     ///
     /// This resets the internal spawner time to zero, and restarts any internal
@@ -533,13 +1201,36 @@ impl EffectSpawner {
         self.limit = 0.;
         self.spawn_count = 0;
         self.spawn_remainder = 0.;
+        self.cycle = 0;
+        self.elapsed = 0.;
+        self.completed = false;
+        self.prewarm_pending = self.prewarm > 0.;
+        self.prewarm_time = 0.;
+        self.seek_time = None;
+        for emitter in &mut self.emitters {
+            emitter.reset();
+        }
+    }
+
+    /// Get the pre-warming duration applied during the last [`tick()`], in
+    /// seconds.
+    ///
+    /// This is non-zero only for the single frame where the spawner was
+    /// fast-forwarded to pre-warm the effect. See
+    /// [`EffectAsset::with_prewarm()`] for details.
+    ///
+    /// [`tick()`]: crate::EffectSpawner::tick
+    /// [`EffectAsset::with_prewarm()`]: crate::EffectAsset::with_prewarm
+    pub fn prewarm_time(&self) -> f32 {
+        self.prewarm_time
     }
 
     /// Tick the spawner to calculate the number of particles to spawn this
     /// frame.
     ///
-    /// The frame delta time `dt` is added to the current spawner time, before
-    /// the spawner calculates the number of particles to spawn.
+    /// The frame delta time `dt`, multiplied by the [`time_scale()`], is added
+    /// to the current spawner time, before the spawner calculates the number
+    /// of particles to spawn.
     ///
     /// This method is called automatically by [`tick_spawners()`] during the
     /// [`PostUpdate`], so you normally don't have to call it yourself
@@ -549,14 +1240,103 @@ impl EffectSpawner {
     ///
     /// The integral number of particles to spawn this frame. Any fractional
     /// remainder is saved for the next call.
-    pub fn tick(&mut self, mut dt: f32, rng: &mut Pcg32) -> u32 {
-        if !self.active {
+    ///
+    /// The random values are drawn from `rng`, unless the effect instance was
+    /// given a fixed seed with [`set_seed()`], in which case they're drawn
+    /// from its own generator.
+    ///
+    /// [`time_scale()`]: crate::EffectSpawner::time_scale
+    /// [`set_seed()`]: crate::EffectSpawner::set_seed
+    pub fn tick(&mut self, dt: f32, rng: &mut Pcg32) -> u32 {
+        let mut own_rng = self.rng.take();
+        let rng = own_rng.as_mut().unwrap_or(rng);
+        let spawn_count = self.tick_with(dt, rng);
+        self.gpu_seed = rng.gen();
+        self.rng = own_rng;
+        spawn_count
+    }
+
+    /// Tick the spawner with the given random number generator.
+    fn tick_with(&mut self, dt: f32, rng: &mut Pcg32) -> u32 {
+        self.prewarm_time = 0.;
+        self.kill_groups = std::mem::take(&mut self.pending_kill_groups);
+
+        // Additional emitters follow the playback state of the effect
+        for emitter in &mut self.emitters {
+            emitter.active = self.active;
+            emitter.playback = self.playback;
+            emitter.spawn_scale = self.spawn_scale;
+            emitter.time_scale = self.time_scale;
+            emitter.tick(dt, rng);
+        }
+
+        let mut dt = dt * self.time_scale;
+
+        // A seek kills the particles on a first tick, and fast-forwards on the
+        // next one, to not kill the particles spawned by the fast-forward
+        if self.seek_time.is_some() && self.kill_groups != 0 {
             self.spawn_count = 0;
             return 0;
         }
 
+        // A paused effect still applies a pending seek, then stays frozen
+        let seeking = self.seek_time.is_some();
+        if !self.active || (self.playback == EffectPlayback::Paused && !seeking) {
+            self.spawn_count = 0;
+            return 0;
+        }
+        if self.playback == EffectPlayback::Paused {
+            dt = 0.;
+        }
+
+        self.elapsed += dt;
+        let prewarm = self.seek_time.take().unwrap_or(self.prewarm);
+
+        // Stochastic spawners emit a burst each frame with a given probability,
+        // independently of the spawner time. Only the time scale of the effect
+        // changes the burst frequency.
+        if let Some(probability) = self.spawner.probability {
+            // On the first active tick, replay the bursts of all the frames spanned
+            // by the pre-warming duration, assuming they last as long as this one.
+            let mut frame_count = 1;
+            if self.prewarm_pending {
+                self.prewarm_pending = false;
+                self.prewarm_time = prewarm;
+                self.elapsed += prewarm;
+                let frame_time = if dt > 0. { dt } else { 1. / 60. };
+                frame_count += (prewarm / frame_time).ceil().min(MAX_PREWARM_FRAMES as f32) as u32;
+            }
+
+            let spawn_scale = self.spawn_scale();
+            self.spawn_count = 0;
+            for _ in 0..frame_count {
+                if rng.gen::<f32>() < probability * self.time_scale {
+                    self.spawn_count += (self.spawner.num_particles.sample(rng) * spawn_scale)
+                        .max(0.)
+                        .floor() as u32;
+                }
+            }
+            return self.spawn_count;
+        }
+
+        // On the first active tick, fast-forward by the pre-warming duration. All
+        // particles spawned during that interval are emitted this frame, and aged
+        // on GPU by a random fraction of that duration.
+        if self.prewarm_pending {
+            self.prewarm_pending = false;
+            self.prewarm_time = prewarm;
+            self.elapsed += prewarm;
+            dt += prewarm;
+        }
+
+        let spawn_scale = self.spawn_scale();
+
         // The limit can be reached multiple times, so use a loop
         loop {
+            if self.completed {
+                break;
+            }
+
             if self.limit == 0.0 {
                 self.resample(rng);
                 continue;
@@ -566,15 +1346,16 @@ impl EffectSpawner {
             if self.time <= self.curr_spawn_time {
                 // If the spawn time is very small, close to zero, spawn all particles
                 // immediately in one burst over a single frame.
-                self.spawn_remainder += if self.curr_spawn_time < 1e-5f32.max(dt / 100.0) {
-                    self.spawner.num_particles.sample(rng)
-                } else {
-                    // Spawn an amount of particles equal to the fraction of time the current frame
-                    // spans compared to the total burst duration.
-                    self.spawner.num_particles.sample(rng)
-                        * (new_time.min(self.curr_spawn_time) - self.time)
-                        / self.curr_spawn_time
-                };
+                self.spawn_remainder += spawn_scale
+                    * if self.curr_spawn_time < 1e-5f32.max(dt / 100.0) {
+                        self.spawner.num_particles.sample(rng)
+                    } else {
+                        // Spawn an amount of particles equal to the fraction of time the current
+                        // frame spans compared to the total burst duration.
+                        self.spawner.num_particles.sample(rng)
+                            * (new_time.min(self.curr_spawn_time) - self.time)
+                            / self.curr_spawn_time
+                    };
             }
 
             let old_time = self.time;
@@ -583,6 +1364,10 @@ impl EffectSpawner {
             if self.time >= self.limit {
                 dt -= self.limit - old_time;
                 self.time = 0.0; // dt will be added on in the next iteration
+                self.cycle += 1;
+                if let Some(loop_count) = self.spawner.loop_count {
+                    self.completed = self.cycle >= loop_count;
+                }
                 self.resample(rng);
             } else {
                 break;
@@ -596,13 +1381,161 @@ impl EffectSpawner {
         self.spawn_count
     }
 
+    /// Set the factor applied to the number of particles spawned.
+    ///
+    /// This is automatically updated by [`tick_spawners()`] from the scale of
+    /// the emitter entity when [`Spawner::spawn_scaling()`] is enabled, and is
+    /// otherwise ignored.
+    pub fn set_spawn_scale(&mut self, spawn_scale: f32) {
+        self.spawn_scale = spawn_scale.max(0.);
+    }
+
+    /// Get the factor applied to the number of particles spawned.
+    ///
+    /// This is always `1.0` if [`Spawner::spawn_scaling()`] is disabled.
+    pub fn spawn_scale(&self) -> f32 {
+        if self.spawner.spawn_scaling == SpawnScaling::Disabled {
+            1.
+        } else {
+            self.spawn_scale
+        }
+    }
+
+    /// Get the number of spawn cycles completed since the spawner was created
+    /// or last reset.
+    pub fn cycle(&self) -> u32 {
+        self.cycle
+    }
+
+    /// Get the simulation time elapsed since the spawner was created or last
+    /// reset, in seconds.
+    ///
+    /// This only accounts for the time the spawner was active and not paused,
+    /// scaled by the [`time_scale()`], and includes any pre-warming duration.
+    ///
+    /// [`time_scale()`]: crate::EffectSpawner::time_scale
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Capture a snapshot of the runtime state of the spawner.
+    ///
+    /// The snapshot contains the spawn phase, the playback state, and the
+    /// state of the random number generator of the effect instance, as well
+    /// as the state of the spawners of any additional emitter. It can be
+    /// serialized, for example as part of a saved game, and applied with
+    /// [`restore()`] to resume the effect where it was, instead of restarting
+    /// it from scratch.
+    ///
+    /// [`restore()`]: crate::EffectSpawner::restore
+    pub fn snapshot(&self) -> EffectSpawnerSnapshot {
+        EffectSpawnerSnapshot {
+            time: self.time,
+            curr_spawn_time: self.curr_spawn_time,
+            limit: self.limit,
+            spawn_remainder: self.spawn_remainder,
+            active: self.active,
+            playback: self.playback,
+            cycle: self.cycle,
+            elapsed: self.elapsed,
+            completed: self.completed,
+            prewarm_pending: self.prewarm_pending,
+            seek_time: self.seek_time,
+            time_scale: self.time_scale,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            emitters: self.emitters.iter().map(EffectSpawner::snapshot).collect(),
+        }
+    }
+
+    /// Restore the runtime state of the spawner from a snapshot.
+    ///
+    /// The spawner configuration itself is not part of the snapshot, and is
+    /// kept as is. If the number of additional emitters changed since the
+    /// snapshot was captured, for example because the asset was modified, the
+    /// state of the emitters missing from the snapshot is kept, and the state
+    /// of the emitters which don't exist anymore is discarded.
+    ///
+    /// Note that the particles themselves live on GPU and are not part of the
+    /// snapshot, so only the particles spawned after the restore are
+    /// simulated.
+    pub fn restore(&mut self, snapshot: &EffectSpawnerSnapshot) {
+        self.time = snapshot.time;
+        self.curr_spawn_time = snapshot.curr_spawn_time;
+        self.limit = snapshot.limit;
+        self.spawn_remainder = snapshot.spawn_remainder;
+        self.active = snapshot.active;
+        self.playback = snapshot.playback;
+        self.cycle = snapshot.cycle;
+        self.elapsed = snapshot.elapsed;
+        self.completed = snapshot.completed;
+        self.prewarm_pending = snapshot.prewarm_pending;
+        self.seek_time = snapshot.seek_time;
+        self.time_scale = snapshot.time_scale;
+        self.seed = snapshot.seed;
+        self.rng = snapshot.rng.clone();
+        for (emitter, snapshot) in self.emitters.iter_mut().zip(&snapshot.emitters) {
+            emitter.restore(snapshot);
+        }
+    }
+
+    /// Get whether the spawner completed all its spawn cycles.
+    ///
+    /// This is `true` once the spawner completed the number of cycles set
+    /// with [`Spawner::with_loop_count()`], or for a [`Spawner::once()`]
+    /// spawner not spawning immediately, until [`reset()`] is called.
+    ///
+    /// [`reset()`]: crate::EffectSpawner::reset
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
     /// Resamples the spawn time and period.
     fn resample(&mut self, rng: &mut Pcg32) {
-        self.limit = self.spawner.period.sample(rng);
-        self.curr_spawn_time = self.spawner.spawn_time.sample(rng).clamp(0.0, self.limit);
+        let period = self.spawner.period.sample(rng);
+        let spawn_time = self.spawner.spawn_time.sample(rng);
+        self.limit = if let Some(loop_delay) = self.spawner.loop_delay {
+            // A once spawner has an infinite period, so only waits for the loop delay.
+            let period = if period.is_infinite() {
+                spawn_time.max(0.)
+            } else {
+                period
+            };
+            period + loop_delay.sample(rng)
+        } else {
+            period
+        };
+        self.curr_spawn_time = spawn_time.clamp(0.0, self.limit);
     }
 }
 
+/// Serializable snapshot of the runtime state of an [`EffectSpawner`].
+///
+/// The snapshot is captured with [`EffectSpawner::snapshot()`] and applied
+/// with [`EffectSpawner::restore()`]. Its content is opaque, and only meant to
+/// be serialized and deserialized, for example with RON, as part of a saved
+/// game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectSpawnerSnapshot {
+    time: f32,
+    curr_spawn_time: f32,
+    limit: f32,
+    spawn_remainder: f32,
+    active: bool,
+    playback: EffectPlayback,
+    cycle: u32,
+    elapsed: f32,
+    completed: bool,
+    prewarm_pending: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seek_time: Option<f32>,
+    time_scale: f32,
+    seed: Option<u32>,
+    rng: Option<Pcg32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    emitters: Vec<EffectSpawnerSnapshot>,
+}
+
 /// Tick all the [`EffectSpawner`] components of the simulated
 /// [`ParticleEffect`] components.
 ///
@@ -629,25 +1562,55 @@ pub fn tick_spawners(
     mut commands: Commands,
     time: Res<Time<EffectSimulation>>,
     effects: Res<Assets<EffectAsset>>,
+    mut asset_events: EventReader<AssetEvent<EffectAsset>>,
     mut rng: ResMut<Random>,
+    globals: Res<GlobalProperties>,
     mut query: Query<(
         Entity,
-        &ParticleEffect,
+        Ref<ParticleEffect>,
         Option<&InheritedVisibility>,
         Option<&mut EffectSpawner>,
+        Option<&GlobalTransform>,
+        Option<&EffectProperties>,
     )>,
 ) {
     trace!("tick_spawners");
 
     let dt = time.delta_seconds();
 
-    for (entity, effect, maybe_inherited_visibility, maybe_spawner) in query.iter_mut() {
+    // Collect the assets modified since last frame, to update the spawners of their
+    // existing instances.
+    let modified_assets: HashSet<AssetId<EffectAsset>> = asset_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (
+        entity,
+        effect,
+        maybe_inherited_visibility,
+        mut maybe_spawner,
+        maybe_transform,
+        maybe_properties,
+    ) in query.iter_mut()
+    {
         // TODO - maybe cache simulation_condition so we don't need to unconditionally
         // query the asset?
         let Some(asset) = effects.get(&effect.handle) else {
             continue;
         };
 
+        // Update the spawner of the instance even if hidden, so it doesn't keep a stale
+        // configuration.
+        if let Some(spawner) = maybe_spawner.as_mut() {
+            if modified_assets.contains(&effect.handle.id()) {
+                spawner.reload(asset);
+            }
+        }
+
         if asset.simulation_condition == SimulationCondition::WhenVisible
             && !maybe_inherited_visibility
                 .map(|iv| iv.get())
@@ -657,15 +1620,74 @@ pub fn tick_spawners(
         }
 
         if let Some(mut spawner) = maybe_spawner {
+            if effect.is_changed() && spawner.seed() != effect.seed {
+                spawner.set_seed(effect.seed);
+            }
+            update_spawn_scale(&mut spawner, maybe_transform);
+            update_spawner_properties(&mut spawner, asset, maybe_properties, &globals);
             spawner.tick(dt, &mut rng.0);
         } else {
-            let mut spawner = EffectSpawner::new(asset);
+            let mut spawner = EffectSpawner::new(asset).with_seed(effect.seed);
+            update_spawn_scale(&mut spawner, maybe_transform);
+            update_spawner_properties(&mut spawner, asset, maybe_properties, &globals);
             spawner.tick(dt, &mut rng.0);
             commands.entity(entity).insert(spawner);
         }
     }
 }
 
+/// Update the spawner parameters bound to a property with
+/// [`EffectAsset::with_spawner_property()`].
+///
+/// The value of each property is resolved like on GPU: a global property of
+/// the same name and type takes precedence over the value stored in the
+/// [`EffectProperties`] of the instance, itself taking precedence over the
+/// default value declared in the [`Module`] of the effect. Properties which
+/// are not declared, or are not of type `f32`, are ignored.
+///
+/// [`EffectAsset::with_spawner_property()`]: crate::EffectAsset::with_spawner_property
+/// [`Module`]: crate::Module
+fn update_spawner_properties(
+    spawner: &mut EffectSpawner,
+    asset: &EffectAsset,
+    maybe_properties: Option<&EffectProperties>,
+    globals: &GlobalProperties,
+) {
+    for (param, name) in asset.spawner_properties() {
+        let Some(property) = asset
+            .module()
+            .get_property_by_name(name)
+            .and_then(|handle| asset.module().get_property(handle))
+        else {
+            continue;
+        };
+        let value = globals
+            .get_for(property)
+            .or_else(|| {
+                maybe_properties
+                    .and_then(|properties| properties.get_stored(name))
+                    .filter(|value| value.value_type() == property.value_type())
+            })
+            .unwrap_or(*property.default_value());
+        if let Ok(value) = TryInto::<f32>::try_into(value) {
+            param.apply(&mut spawner.spawner, value);
+        }
+    }
+}
+
+/// Update the spawn scale of an [`EffectSpawner`] from the scale of its emitter
+/// entity, if the spawner scales with it.
+fn update_spawn_scale(spawner: &mut EffectSpawner, maybe_transform: Option<&GlobalTransform>) {
+    let spawn_scaling = spawner.spawner.spawn_scaling;
+    if spawn_scaling == SpawnScaling::Disabled {
+        return;
+    }
+    let scale = maybe_transform
+        .map(|transform| transform.to_scale_rotation_translation().0)
+        .unwrap_or(Vec3::ONE);
+    spawner.set_spawn_scale(spawn_scaling.factor(scale));
+}
+
 #[cfg(test)]
 mod test {
     use std::time::Duration;
@@ -823,6 +1845,386 @@ mod test {
         assert_eq!(count, 0);
     }
 
+    #[test]
+    fn test_loop_count() {
+        let rng = &mut new_rng();
+        let spawner = Spawner::burst(5.0.into(), 2.0.into()).with_loop_count(Some(2));
+        let mut spawner = make_effect_spawner(spawner);
+        let count = spawner.tick(1.0, rng);
+        assert_eq!(count, 5);
+        let count = spawner.tick(2.0, rng);
+        assert_eq!(count, 5);
+        assert!(!spawner.is_completed());
+        let count = spawner.tick(2.0, rng);
+        assert_eq!(count, 0);
+        assert!(spawner.is_completed());
+        assert_eq!(spawner.cycle(), 2);
+        let count = spawner.tick(10.0, rng);
+        assert_eq!(count, 0);
+        spawner.reset();
+        assert!(!spawner.is_completed());
+        let count = spawner.tick(1.0, rng);
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_loop_delay() {
+        let rng = &mut new_rng();
+        let spawner = Spawner::once(5.0.into(), true)
+            .with_loop_delay(2.0.into())
+            .with_loop_count(Some(3));
+        let mut spawner = make_effect_spawner(spawner);
+        let count = spawner.tick(1.0, rng); // t = 1s
+        assert_eq!(count, 5);
+        let count = spawner.tick(2.0, rng); // t = 3s
+        assert_eq!(count, 5);
+        let count = spawner.tick(2.0, rng); // t = 5s
+        assert_eq!(count, 5);
+        let count = spawner.tick(10.0, rng);
+        assert_eq!(count, 0);
+        assert!(spawner.is_completed());
+
+        // Burst spawner waits for the period, then the delay
+        let spawner = Spawner::burst(5.0.into(), 1.0.into()).with_loop_delay(1.0.into());
+        let mut spawner = make_effect_spawner(spawner);
+        let count = spawner.tick(0.5, rng); // t = 0.5s
+        assert_eq!(count, 5);
+        let count = spawner.tick(1.0, rng); // t = 1.5s
+        assert_eq!(count, 0);
+        let count = spawner.tick(1.0, rng); // t = 2.5s
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_loop_delay_panic_negative() {
+        let _ = Spawner::once(5.0.into(), true).with_loop_delay(CpuValue::Uniform((-1., 1.)));
+    }
+
+    #[test]
+    fn test_spawn_scaling() {
+        let rng = &mut new_rng();
+
+        assert_eq!(SpawnScaling::Disabled.factor(Vec3::splat(2.)), 1.);
+        assert!((SpawnScaling::Linear.factor(Vec3::splat(2.)) - 2.).abs() < 1e-5);
+        assert!((SpawnScaling::Area.factor(Vec3::splat(2.)) - 4.).abs() < 1e-5);
+        assert!((SpawnScaling::Volume.factor(Vec3::new(1., 2., 4.)) - 8.).abs() < 1e-5);
+
+        // Disabled by default, scale ignored
+        let mut spawner = make_effect_spawner(Spawner::rate(10.0.into()));
+        spawner.set_spawn_scale(3.);
+        assert_eq!(spawner.spawn_scale(), 1.);
+        assert_eq!(spawner.tick(1., rng), 10);
+
+        let spawner = Spawner::rate(10.0.into()).with_spawn_scaling(SpawnScaling::Area);
+        assert_eq!(spawner.spawn_scaling(), SpawnScaling::Area);
+        let mut spawner = make_effect_spawner(spawner);
+        spawner.set_spawn_scale(3.);
+        assert_eq!(spawner.spawn_scale(), 3.);
+        assert_eq!(spawner.tick(1., rng), 30);
+    }
+
+    #[test]
+    fn test_probabilistic() {
+        let rng = &mut new_rng();
+
+        let spawner = Spawner::probabilistic(3.0.into(), 1.);
+        assert_eq!(spawner.probability(), Some(1.));
+        let mut spawner = make_effect_spawner(spawner);
+        for _ in 0..10 {
+            assert_eq!(spawner.tick(1. / 60., rng), 3);
+        }
+
+        let spawner = Spawner::probabilistic(3.0.into(), 0.);
+        let mut spawner = make_effect_spawner(spawner);
+        for _ in 0..10 {
+            assert_eq!(spawner.tick(1. / 60., rng), 0);
+        }
+
+        let spawner = Spawner::probabilistic(1.0.into(), 0.5);
+        let mut spawner = make_effect_spawner(spawner);
+        let count = (0..1000).map(|_| spawner.tick(1. / 60., rng)).sum::<u32>();
+        assert!(count > 350 && count < 650);
+    }
+
+    #[test]
+    fn test_time_scale() {
+        let rng = &mut new_rng();
+
+        let spawner = Spawner::rate(10.0.into());
+        let mut spawner = make_effect_spawner(spawner).with_time_scale(0.5);
+        assert_eq!(spawner.time_scale(), 0.5);
+        assert_eq!(spawner.tick(1., rng), 5);
+
+        spawner.set_time_scale(0.);
+        assert_eq!(spawner.tick(1., rng), 0);
+
+        // Negative values are clamped
+        spawner.set_time_scale(-1.);
+        assert_eq!(spawner.time_scale(), 0.);
+
+        spawner.set_time_scale(2.);
+        assert_eq!(spawner.tick(1., rng), 20);
+
+        let spawner = Spawner::probabilistic(3.0.into(), 1.);
+        let mut spawner = make_effect_spawner(spawner).with_time_scale(0.);
+        assert_eq!(spawner.tick(1. / 60., rng), 0);
+    }
+
+    #[test]
+    fn test_seed() {
+        let spawner = Spawner::new(
+            CpuValue::Uniform((1., 100.)),
+            CpuValue::Uniform((0., 0.5)),
+            CpuValue::Uniform((0.5, 1.)),
+        );
+        let mut a = make_effect_spawner(spawner).with_seed(Some(42));
+        let mut b = make_effect_spawner(spawner).with_seed(Some(42));
+        assert_eq!(a.seed(), Some(42));
+
+        // Fixed seeds ignore the global generator
+        let rng_a = &mut new_rng();
+        let rng_b = &mut new_rng();
+        for _ in 0..100 {
+            assert_eq!(a.tick(0.1, rng_a), b.tick(0.1, rng_b));
+            assert_eq!(a.gpu_seed(), b.gpu_seed());
+        }
+
+        // Re-seeding restarts the random sequence
+        a.reset();
+        a.set_seed(Some(42));
+        let mut c = make_effect_spawner(spawner).with_seed(Some(42));
+        assert_eq!(a.tick(0.1, rng_a), c.tick(0.1, rng_b));
+        assert_eq!(a.gpu_seed(), c.gpu_seed());
+
+        a.set_seed(None);
+        assert_eq!(a.seed(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_probabilistic_panic() {
+        let _ = Spawner::probabilistic(3.0.into(), 1.5);
+    }
+
+    #[test]
+    fn test_prewarm() {
+        let rng = &mut new_rng();
+        let spawner = Spawner::rate(5.0.into());
+        let asset = EffectAsset::new(vec![256], spawner, Module::default()).with_prewarm(2.);
+        let mut spawner = EffectSpawner::new(&asset);
+        // First tick spawns all particles for the pre-warming duration at once
+        let count = spawner.tick(0.01, rng);
+        assert_eq!(count, 10);
+        assert_eq!(spawner.prewarm_time(), 2.);
+        // Next ticks spawn normally
+        let count = spawner.tick(0.1, rng);
+        assert_eq!(count, 0);
+        assert_eq!(spawner.prewarm_time(), 0.);
+        // Reset re-arms pre-warming
+        spawner.reset();
+        let count = spawner.tick(0.01, rng);
+        assert_eq!(count, 10);
+        assert_eq!(spawner.prewarm_time(), 2.);
+
+        // Stochastic spawners replay the bursts of the frames spanned by the
+        // pre-warming duration
+        let spawner = Spawner::probabilistic(3.0.into(), 1.);
+        let asset = EffectAsset::new(vec![256], spawner, Module::default()).with_prewarm(1.);
+        let mut spawner = EffectSpawner::new(&asset);
+        assert_eq!(spawner.tick(0.1, rng), 33);
+        assert_eq!(spawner.prewarm_time(), 1.);
+        assert_eq!(spawner.tick(0.1, rng), 3);
+        assert_eq!(spawner.prewarm_time(), 0.);
+    }
+
+    #[test]
+    fn test_seek() {
+        let rng = &mut new_rng();
+        let mut spawner = make_effect_spawner(Spawner::rate(5.0.into()));
+        assert_eq!(spawner.tick(1., rng), 5);
+
+        // Seeking kills all particles on the first tick, and fast-forwards on the
+        // next one
+        spawner.seek(2.);
+        assert_eq!(spawner.tick(0.01, rng), 0);
+        assert_eq!(spawner.kill_groups(), !0);
+        assert_eq!(spawner.prewarm_time(), 0.);
+        assert_eq!(spawner.tick(0.01, rng), 10);
+        assert_eq!(spawner.kill_groups(), 0);
+        assert_eq!(spawner.prewarm_time(), 2.);
+        assert!((spawner.elapsed() - 2.01).abs() < 1e-5);
+
+        // Stepping back seeks to an earlier time
+        spawner.step_back(1.);
+        assert_eq!(spawner.tick(0.01, rng), 0);
+        assert_eq!(spawner.tick(0.01, rng), 5);
+        assert!((spawner.prewarm_time() - 1.01).abs() < 1e-5);
+
+        // A paused effect is fast-forwarded, then stays frozen
+        spawner.pause();
+        spawner.seek(1.);
+        assert_eq!(spawner.tick(0.5, rng), 0);
+        assert_eq!(spawner.tick(0.5, rng), 5);
+        assert_eq!(spawner.tick(0.5, rng), 0);
+        assert_eq!(spawner.elapsed(), 1.);
+        assert_eq!(spawner.playback(), EffectPlayback::Paused);
+
+        // With a fixed seed, seeking to the same time spawns the same particles
+        let spawner = Spawner::probabilistic(CpuValue::Uniform((1., 100.)), 0.5);
+        let mut spawner = make_effect_spawner(spawner).with_seed(Some(42));
+        let seek = |spawner: &mut EffectSpawner, rng: &mut Pcg32| {
+            spawner.seek(1.);
+            spawner.tick(0.1, rng);
+            let count = spawner.tick(0.1, rng);
+            (count, spawner.gpu_seed())
+        };
+        let first = seek(&mut spawner, rng);
+        spawner.tick(0.3, rng);
+        assert_eq!(seek(&mut spawner, rng), first);
+    }
+
+    #[test]
+    fn test_playback() {
+        let rng = &mut new_rng();
+        let spawner = Spawner::rate(5.0.into());
+        let mut spawner = make_effect_spawner(spawner);
+        assert_eq!(spawner.playback(), EffectPlayback::Playing);
+        let count = spawner.tick(0.5, rng);
+        assert_eq!(count, 2);
+
+        // Pausing freezes the spawner time
+        spawner.pause();
+        assert_eq!(spawner.playback(), EffectPlayback::Paused);
+        let count = spawner.tick(10., rng);
+        assert_eq!(count, 0);
+        spawner.play();
+        let count = spawner.tick(0.11, rng);
+        assert_eq!(count, 1);
+
+        // Stopping deactivates the spawner
+        spawner.stop();
+        assert_eq!(spawner.playback(), EffectPlayback::Stopped);
+        assert!(!spawner.is_active());
+        let count = spawner.tick(1., rng);
+        assert_eq!(count, 0);
+
+        // Restarting resets the spawner, and kills all particles once
+        spawner.restart();
+        assert_eq!(spawner.playback(), EffectPlayback::Playing);
+        assert!(spawner.is_active());
+        let count = spawner.tick(0.5, rng);
+        assert_eq!(count, 2);
+        assert_eq!(spawner.kill_groups(), !0);
+        spawner.tick(0.1, rng);
+        assert_eq!(spawner.kill_groups(), 0);
+    }
+
+    #[test]
+    fn test_kill_particles() {
+        let rng = &mut new_rng();
+        let spawner = Spawner::rate(5.0.into());
+        let mut spawner = make_effect_spawner(spawner);
+        spawner.tick(0.5, rng);
+        assert_eq!(spawner.kill_groups(), 0);
+
+        // Killing particles of some groups doesn't affect the spawner
+        spawner.kill_particles(ParticleGroupSet::single(1));
+        spawner.kill_particles(ParticleGroupSet::single(3));
+        assert_eq!(spawner.playback(), EffectPlayback::Playing);
+        let count = spawner.tick(0.2, rng);
+        assert_eq!(count, 1);
+        assert_eq!(spawner.kill_groups(), 0b1010);
+        spawner.tick(0.1, rng);
+        assert_eq!(spawner.kill_groups(), 0);
+
+        // Particles are killed even while paused
+        spawner.pause();
+        spawner.kill_particles(ParticleGroupSet::single(0));
+        spawner.tick(0.1, rng);
+        assert_eq!(spawner.kill_groups(), 1);
+    }
+
+    #[test]
+    fn test_emitters() {
+        let rng = &mut new_rng();
+        let asset = EffectAsset::new(vec![256, 256], Spawner::rate(5.0.into()), Module::default())
+            .with_emitter(1, Spawner::rate(10.0.into()));
+        let mut spawner = EffectSpawner::new(&asset);
+        assert_eq!(spawner.emitters().len(), 1);
+
+        // Emitters tick independently
+        let count = spawner.tick(1., rng);
+        assert_eq!(count, 5);
+        assert_eq!(spawner.emitters()[0].spawn_count, 10);
+
+        // Emitters follow the playback state of the effect
+        spawner.pause();
+        spawner.tick(1., rng);
+        assert_eq!(spawner.spawn_count, 0);
+        assert_eq!(spawner.emitters()[0].spawn_count, 0);
+        assert_eq!(spawner.emitters()[0].playback(), EffectPlayback::Paused);
+
+        spawner.play();
+        spawner.tick(1., rng);
+        assert_eq!(spawner.emitters()[0].spawn_count, 10);
+    }
+
+    #[test]
+    fn test_reload() {
+        let rng = &mut new_rng();
+        let asset = EffectAsset::new(vec![256, 256], Spawner::rate(5.0.into()), Module::default())
+            .with_emitter(1, Spawner::rate(10.0.into()));
+        let mut spawner = EffectSpawner::new(&asset);
+        spawner.pause();
+
+        // The configuration is updated, but not the playback state
+        let asset = EffectAsset::new(vec![256], Spawner::rate(20.0.into()), Module::default());
+        spawner.reload(&asset);
+        assert_eq!(spawner.spawner().count(), 20.0.into());
+        assert_eq!(spawner.playback(), EffectPlayback::Paused);
+        assert!(spawner.emitters().is_empty());
+
+        spawner.play();
+        assert_eq!(spawner.tick(1., rng), 20);
+    }
+
+    #[test]
+    fn test_spawner_properties() {
+        let mut module = Module::default();
+        module.add_property("rate", 8_f32.into());
+        module.add_property("period", 2_f32.into());
+        module.add_property("color", Vec3::ONE.into());
+        let asset = EffectAsset::new(vec![256], Spawner::rate(1.0.into()), module)
+            .with_spawner_property(SpawnerParam::Count, "rate")
+            .with_spawner_property(SpawnerParam::Period, "period")
+            .with_spawner_property(SpawnerParam::SpawnTime, "color");
+        assert_eq!(asset.spawner_properties().len(), 3);
+        let mut spawner = EffectSpawner::new(&asset);
+        let mut globals = GlobalProperties::default();
+
+        // Default values
+        update_spawner_properties(&mut spawner, &asset, None, &globals);
+        assert_eq!(spawner.spawner().count(), CpuValue::Single(8.));
+        assert_eq!(spawner.spawner().period(), CpuValue::Single(2.));
+        // Non-f32 properties are ignored
+        assert_eq!(spawner.spawner().spawn_time(), CpuValue::Single(1.));
+
+        // Instance values
+        let mut properties = EffectProperties::default();
+        properties.set("rate", 16_f32.into());
+        properties.set("period", 0_f32.into());
+        update_spawner_properties(&mut spawner, &asset, Some(&properties), &globals);
+        assert_eq!(spawner.spawner().count(), CpuValue::Single(16.));
+        // Invalid periods are ignored
+        assert_eq!(spawner.spawner().period(), CpuValue::Single(2.));
+
+        // Global values take precedence
+        globals.set("rate", 4_f32.into());
+        update_spawner_properties(&mut spawner, &asset, Some(&properties), &globals);
+        assert_eq!(spawner.spawner().count(), CpuValue::Single(4.));
+    }
+
     #[test]
     fn test_with_active() {
         let rng = &mut new_rng();
@@ -869,6 +2271,7 @@ mod test {
         app.add_plugins(VisibilityPlugin);
         app.init_resource::<Time<EffectSimulation>>();
         app.insert_resource(Random(new_rng()));
+        app.init_resource::<GlobalProperties>();
         app.init_asset::<EffectAsset>();
         app.add_systems(
             PostUpdate,
@@ -932,6 +2335,13 @@ mod test {
                                 handle: handle.clone(),
                                 #[cfg(feature = "2d")]
                                 z_layer_2d: None,
+                                #[cfg(feature = "2d")]
+                                y_sort_2d: None,
+                                seed: None,
+                                simulation_space: None,
+                                texture: None,
+                                sprite_grid_size: None,
+                                debug_view: None,
                             },
                         ))
                         .id()
@@ -941,6 +2351,13 @@ mod test {
                             handle: handle.clone(),
                             #[cfg(feature = "2d")]
                             z_layer_2d: None,
+                            #[cfg(feature = "2d")]
+                            y_sort_2d: None,
+                            seed: None,
+                            simulation_space: None,
+                            texture: None,
+                            sprite_grid_size: None,
+                            debug_view: None,
                         },))
                         .id()
                 };
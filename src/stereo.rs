@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+/// Component marking a camera as one eye of a stereo rig, like the cameras of
+/// a VR headset.
+///
+/// Each eye of a stereo rig is a separate view, with its own view uniforms,
+/// and the particles are drawn once per eye. By default, the billboarded
+/// particles face each view independently, so both eyes see a slightly
+/// different quad; this breaks the stereo fusion of the particle and makes it
+/// look flat or misaligned. Insert this component on the camera of each eye
+/// to orient the particles toward the [`head`] entity instead, typically the
+/// tracked head between the eyes, so both eyes see the same quad at the right
+/// depth.
+///
+/// This affects all the orientations facing the camera, including the
+/// [`OrientModifier`] modes and the particles simulated on CPU. The position
+/// and projection of each eye are unchanged.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn setup_eyes(mut commands: Commands, head: Query<Entity, With<Head>>) {
+///     let head = head.single();
+///     for x in [-0.032, 0.032] {
+///         let eye = commands
+///             .spawn((
+///                 Camera3dBundle {
+///                     transform: Transform::from_xyz(x, 0., 0.),
+///                     ..default()
+///                 },
+///                 EffectStereoEye::new(head),
+///             ))
+///             .id();
+///         commands.entity(head).add_child(eye);
+///     }
+/// }
+/// # #[derive(Component)]
+/// # struct Head;
+/// ```
+///
+/// [`head`]: EffectStereoEye::head
+/// [`OrientModifier`]: crate::OrientModifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectStereoEye {
+    /// Entity whose [`GlobalTransform`] the particles are oriented toward.
+    ///
+    /// If the entity doesn't exist, the particles face the eye itself.
+    pub head: Entity,
+}
+
+impl Default for EffectStereoEye {
+    fn default() -> Self {
+        Self {
+            head: Entity::PLACEHOLDER,
+        }
+    }
+}
+
+impl EffectStereoEye {
+    /// Create a new eye orienting the particles toward the given head entity.
+    pub fn new(head: Entity) -> Self {
+        Self { head }
+    }
+}
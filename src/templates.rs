@@ -0,0 +1,385 @@
+//! Ready-made effects for common use cases.
+//!
+//! This module contains builder functions returning complete [`EffectAsset`]s
+//! for some effects commonly found in games, like a campfire or some rain.
+//! Each function takes a few parameters to adapt the effect to the scene, like
+//! its size or color. The returned asset can be further customized like any
+//! other asset, for example to add a texture with a
+//! [`ParticleTextureModifier`].
+//!
+//! The templates are also meant as living documentation: the source code of
+//! each function shows a combination of modifiers producing a given look, and
+//! is a good starting point to author new effects.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_hanabi::*;
+//! fn setup(mut commands: Commands, mut effects: ResMut<Assets<EffectAsset>>) {
+//!     let campfire = effects.add(templates::fire(0.5, 2.));
+//!     commands.spawn(ParticleEffectBundle::new(campfire));
+//! }
+//! ```
+//!
+//! All templates emit particles around the origin of the effect, with the +Y
+//! axis pointing upward. Colors are in linear RGBA space, and can exceed `1.0`
+//! to make the particles glow with an HDR camera and bloom.
+//!
+//! [`ParticleTextureModifier`]: crate::ParticleTextureModifier
+
+use bevy::math::{Vec2, Vec3, Vec4};
+
+use crate::{
+    AccelModifier, Attribute, ColorOverLifetimeModifier, EffectAsset, ExprWriter, Gradient,
+    LinearDragModifier, OrientMode, OrientModifier, SetAttributeModifier,
+    SetPositionCircleModifier, SetPositionSphereModifier, SetVelocitySphereModifier,
+    ShapeDimension, SizeOverLifetimeModifier, Spawner, TangentAccelModifier, VectorType,
+};
+
+/// Standard gravity, in meters per second squared.
+const GRAVITY: f32 = 9.81;
+
+/// A fire, like a campfire or a torch.
+///
+/// Flames are emitted from a disc of the given `radius` on the XZ plane, and
+/// rise up to about `height` above it, turning from yellow to red before
+/// fading out.
+pub fn fire(radius: f32, height: f32) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(0.6).uniform(writer.lit(1.)).expr();
+    let center = writer.lit(Vec3::ZERO).expr();
+    let axis = writer.lit(Vec3::Y).expr();
+    let radius_expr = writer.lit(radius).expr();
+    // Rise at a speed reaching the height over the average lifetime, with some
+    // random horizontal motion
+    let jitter = (writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5)))
+        * writer.lit(Vec3::new(radius, 0., radius));
+    let velocity = (jitter + writer.lit(Vec3::Y * height * 0.8)).expr();
+    // Flames accelerate as they rise
+    let buoyancy = writer.lit(Vec3::Y * height * 0.5).expr();
+    let drag = writer.lit(1.).expr();
+
+    EffectAsset::new(vec![2048], Spawner::rate(200.0.into()), writer.finish())
+        .with_name("fire")
+        .init(SetAttributeModifier::new(Attribute::AGE, age))
+        .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+        .init(SetPositionCircleModifier {
+            center,
+            axis,
+            radius: radius_expr,
+            dimension: ShapeDimension::Volume,
+        })
+        .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+        .update(AccelModifier::new(buoyancy))
+        .update(LinearDragModifier::new(drag))
+        .render(ColorOverLifetimeModifier {
+            gradient: Gradient::from_keys([
+                (0., Vec4::new(4., 3., 1., 0.)),
+                (0.1, Vec4::new(4., 2., 0.5, 1.)),
+                (0.5, Vec4::new(3., 0.6, 0., 0.8)),
+                (1., Vec4::new(1., 0., 0., 0.)),
+            ]),
+        })
+        .render(SizeOverLifetimeModifier {
+            gradient: Gradient::from_keys([
+                (0., Vec2::splat(radius * 0.6)),
+                (1., Vec2::splat(radius * 0.1)),
+            ]),
+            screen_space_size: false,
+        })
+}
+
+/// A column of smoke, like above a chimney or a fire.
+///
+/// Puffs are emitted at `rate` per second from a disc of the given `radius`
+/// on the XZ plane, and slowly rise while expanding and fading out.
+pub fn smoke(radius: f32, rate: f32) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(3.).uniform(writer.lit(4.)).expr();
+    let center = writer.lit(Vec3::ZERO).expr();
+    let axis = writer.lit(Vec3::Y).expr();
+    let radius_expr = writer.lit(radius).expr();
+    let rise = writer.lit(0.6).uniform(writer.lit(1.)) * writer.lit(Vec3::Y);
+    let drift = (writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5)))
+        * writer.lit(Vec3::new(0.3, 0., 0.3));
+    let velocity = (rise + drift).expr();
+    let drag = writer.lit(0.5).expr();
+
+    EffectAsset::new(
+        vec![(rate * 4.).ceil().max(1.) as u32],
+        Spawner::rate(rate.into()),
+        writer.finish(),
+    )
+    .with_name("smoke")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetPositionCircleModifier {
+        center,
+        axis,
+        radius: radius_expr,
+        dimension: ShapeDimension::Volume,
+    })
+    .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+    .update(LinearDragModifier::new(drag))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec4::new(0.4, 0.4, 0.4, 0.)),
+            (0.2, Vec4::new(0.35, 0.35, 0.35, 0.5)),
+            (1., Vec4::new(0.3, 0.3, 0.3, 0.)),
+        ]),
+    })
+    .render(SizeOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec2::splat(radius * 0.5)),
+            (1., Vec2::splat(radius * 2.5)),
+        ]),
+        screen_space_size: false,
+    })
+}
+
+/// An explosion, emitting a single burst of particles.
+///
+/// The particles are ejected in all directions up to about `radius` from the
+/// center, then slow down while fading from a bright flash of the given
+/// `color` to transparent.
+pub fn explosion(radius: f32, color: Vec4) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(0.6).uniform(writer.lit(1.)).expr();
+    let center = writer.lit(Vec3::ZERO).expr();
+    let spawn_radius = writer.lit(radius * 0.1).expr();
+    let speed = (writer.lit(radius * 2.).uniform(writer.lit(radius * 4.))).expr();
+    let drag = writer.lit(4.).expr();
+
+    let flash = (color.truncate() * 4.).extend(color.w);
+    let transparent = color.truncate().extend(0.);
+    EffectAsset::new(
+        vec![512],
+        Spawner::once(500.0.into(), true),
+        writer.finish(),
+    )
+    .with_name("explosion")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetPositionSphereModifier {
+        center,
+        radius: spawn_radius,
+        dimension: ShapeDimension::Volume,
+    })
+    .init(SetVelocitySphereModifier { center, speed })
+    .update(LinearDragModifier::new(drag))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([(0., flash), (0.3, color), (1., transparent)]),
+    })
+    .render(SizeOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec2::splat(radius * 0.15)),
+            (1., Vec2::splat(radius * 0.02)),
+        ]),
+        screen_space_size: false,
+    })
+}
+
+/// Rain falling over a rectangular area.
+///
+/// Drops are spawned at `rate` per second over an `area` of the XZ plane
+/// centered on the effect, and fall down by about 20 units before
+/// disappearing. Move the effect above the scene to cover it.
+pub fn rain(area: Vec2, rate: f32) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(1.).expr();
+    let position = ((writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5)))
+        * writer.lit(Vec3::new(area.x, 0., area.y)))
+    .expr();
+    let velocity = writer.lit(Vec3::new(0., -18., 0.)).expr();
+    let gravity = writer.lit(Vec3::new(0., -GRAVITY, 0.)).expr();
+
+    EffectAsset::new(
+        vec![(rate * 1.2).ceil().max(1.) as u32],
+        Spawner::rate(rate.into()),
+        writer.finish(),
+    )
+    .with_name("rain")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetAttributeModifier::new(Attribute::POSITION, position))
+    .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+    .update(AccelModifier::new(gravity))
+    .render(OrientModifier::new(OrientMode::AlongVelocity))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec4::new(0.7, 0.8, 1., 0.5)),
+            (1., Vec4::new(0.7, 0.8, 1., 0.5)),
+        ]),
+    })
+    .render(SizeOverLifetimeModifier {
+        // Elongated along the velocity by the orientation
+        gradient: Gradient::from_keys([(0., Vec2::new(0.4, 0.02))]),
+        screen_space_size: false,
+    })
+}
+
+/// Snow falling over a rectangular area.
+///
+/// Flakes are spawned at `rate` per second over an `area` of the XZ plane
+/// centered on the effect, and slowly fall down by about 10 units while
+/// drifting randomly. Move the effect above the scene to cover it.
+pub fn snow(area: Vec2, rate: f32) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(10.).expr();
+    let position = ((writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5)))
+        * writer.lit(Vec3::new(area.x, 0., area.y)))
+    .expr();
+    let velocity = writer.lit(Vec3::new(0., -1., 0.)).expr();
+    // Random acceleration changing each frame, to make the flakes flutter
+    let flutter = ((writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5)))
+        * writer.lit(Vec3::new(4., 0., 4.)))
+    .expr();
+
+    EffectAsset::new(
+        vec![(rate * 11.).ceil().max(1.) as u32],
+        Spawner::rate(rate.into()),
+        writer.finish(),
+    )
+    .with_name("snow")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetAttributeModifier::new(Attribute::POSITION, position))
+    .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+    .update(AccelModifier::new(flutter))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec4::new(1., 1., 1., 0.)),
+            (0.05, Vec4::new(1., 1., 1., 0.9)),
+            (0.9, Vec4::new(1., 1., 1., 0.9)),
+            (1., Vec4::new(1., 1., 1., 0.)),
+        ]),
+    })
+    .render(SizeOverLifetimeModifier {
+        gradient: Gradient::from_keys([(0., Vec2::splat(0.05))]),
+        screen_space_size: false,
+    })
+}
+
+/// Sparks, like from a grinder or an electrical short circuit.
+///
+/// Bursts of sparks are ejected upward at about `speed`, then fall under the
+/// effect of gravity, fading from a bright `color` to transparent. The sparks
+/// are stretched along their velocity.
+pub fn sparks(speed: f32, color: Vec4) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(0.5).uniform(writer.lit(1.)).expr();
+    // Random direction in the upper hemisphere, biased upward
+    let direction = (writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5))
+        + writer.lit(Vec3::new(0., 0.8, 0.)))
+    .normalized();
+    let speed = writer.lit(speed * 0.5).uniform(writer.lit(speed));
+    let velocity = (direction * speed).expr();
+    let gravity = writer.lit(Vec3::new(0., -GRAVITY, 0.)).expr();
+    let drag = writer.lit(0.5).expr();
+
+    let glow = (color.truncate() * 4.).extend(color.w);
+    let transparent = color.truncate().extend(0.);
+    EffectAsset::new(
+        vec![256],
+        Spawner::burst(20.0.into(), 0.3.into()),
+        writer.finish(),
+    )
+    .with_name("sparks")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetAttributeModifier::new(Attribute::VELOCITY, velocity))
+    .update(AccelModifier::new(gravity))
+    .update(LinearDragModifier::new(drag))
+    .render(OrientModifier::new(OrientMode::AlongVelocity))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([(0., glow), (0.7, color), (1., transparent)]),
+    })
+    .render(SizeOverLifetimeModifier {
+        gradient: Gradient::from_keys([(0., Vec2::new(0.15, 0.02)), (1., Vec2::new(0.05, 0.01))]),
+        screen_space_size: false,
+    })
+}
+
+/// A magic burst, like a spell being cast.
+///
+/// A single burst of particles swirls around the Y axis while expanding up
+/// to about `radius` from the center, glowing with the given `color`.
+pub fn magic_burst(radius: f32, color: Vec4) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    let age = writer.lit(0.).expr();
+    let lifetime = writer.lit(1.).uniform(writer.lit(1.5)).expr();
+    let center = writer.lit(Vec3::ZERO).expr();
+    let axis = writer.lit(Vec3::Y).expr();
+    let spawn_radius = writer.lit(radius * 0.2).expr();
+    let speed = writer.lit(radius * 0.5).uniform(writer.lit(radius)).expr();
+    let swirl = writer.lit(radius * 8.).expr();
+    let drag = writer.lit(2.).expr();
+
+    let glow = (color.truncate() * 6.).extend(color.w);
+    let transparent = color.truncate().extend(0.);
+    EffectAsset::new(
+        vec![512],
+        Spawner::once(300.0.into(), true),
+        writer.finish(),
+    )
+    .with_name("magic_burst")
+    .init(SetAttributeModifier::new(Attribute::AGE, age))
+    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+    .init(SetPositionSphereModifier {
+        center,
+        radius: spawn_radius,
+        dimension: ShapeDimension::Surface,
+    })
+    .init(SetVelocitySphereModifier { center, speed })
+    .update(TangentAccelModifier::new(center, axis, swirl))
+    .update(LinearDragModifier::new(drag))
+    .render(ColorOverLifetimeModifier {
+        gradient: Gradient::from_keys([(0., glow), (0.5, color), (1., transparent)]),
+    })
+    .render(SizeOverLifetimeModifier {
+        gradient: Gradient::from_keys([
+            (0., Vec2::splat(radius * 0.05)),
+            (0.5, Vec2::splat(radius * 0.08)),
+            (1., Vec2::ZERO),
+        ]),
+        screen_space_size: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let color = Vec4::new(0.2, 0.5, 1., 1.);
+        for asset in [
+            fire(0.5, 2.),
+            smoke(0.5, 10.),
+            explosion(5., color),
+            rain(Vec2::splat(20.), 500.),
+            snow(Vec2::splat(20.), 50.),
+            sparks(5., color),
+            magic_burst(2., color),
+        ] {
+            assert!(
+                asset.validate().is_empty(),
+                "Template '{}' has issues: {:?}",
+                asset.name,
+                asset.validate()
+            );
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+
+use crate::{EffectSimulation, EffectSpawner};
+
+/// How an effect instance is simulated while off-screen.
+///
+/// See [`OffscreenThrottle`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub enum ThrottleMode {
+    /// Simulate the effect only once every `interval` frames while off-screen,
+    /// with a delta time covering all the frames since the last simulated one.
+    Throttle {
+        /// Number of frames between two simulated frames. A value of `1`
+        /// simulates every frame, like without throttling.
+        interval: u32,
+    },
+    /// Pause the effect while off-screen, and fast-forward it when it becomes
+    /// visible again by the time it spent off-screen, up to
+    /// `max_fast_forward` seconds.
+    Pause {
+        /// Maximum duration the effect is fast-forwarded by when becoming
+        /// visible again, in seconds.
+        max_fast_forward: f32,
+    },
+}
+
+/// Reduce the cost of simulating an effect instance while off-screen.
+///
+/// Add this component to the same entity as the [`ParticleEffect`] to reduce
+/// its update frequency, or pause it entirely, while it's outside of the
+/// frustum of every camera. This is mostly useful for effects simulated with
+/// [`SimulationCondition::Always`], and for effects simulated with
+/// [`SimulationCondition::WhenVisible`] which would otherwise be entirely
+/// paused when culled.
+///
+/// An effect is considered off-screen when its [`ViewVisibility`] is `false`.
+/// This relies on the frustum culling of Bevy, so requires an [`Aabb`], for
+/// example from the bounds computed on GPU with
+/// [`EffectAsset::with_gpu_bounds()`]. Effects without an [`Aabb`] are never
+/// culled, so are never throttled.
+///
+/// The frames skipped while off-screen are caught up on the next simulated
+/// frame, with a single simulation step:
+/// - the simulation delta time covers all the skipped frames;
+/// - all the particles which would have spawned during the skipped frames are
+///   spawned at once. With [`ThrottleMode::Pause`], only the ones which would
+///   have spawned during the fast-forwarded time are.
+///
+/// This is an approximation, which works well for simple effects like smoke or
+/// fire, but may produce visible artifacts for effects relying on accurate
+/// motion integration.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`SimulationCondition::Always`]: crate::SimulationCondition::Always
+/// [`SimulationCondition::WhenVisible`]: crate::SimulationCondition::WhenVisible
+/// [`Aabb`]: bevy::render::primitives::Aabb
+/// [`EffectAsset::with_gpu_bounds()`]: crate::EffectAsset::with_gpu_bounds
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct OffscreenThrottle {
+    /// Simulation mode while off-screen.
+    pub mode: ThrottleMode,
+    /// Number of consecutive frames skipped.
+    skipped_frames: u32,
+    /// Simulation time elapsed during the skipped frames, in seconds.
+    skipped_time: f32,
+    /// Number of particles to spawn accumulated during the skipped frames.
+    pending_spawn: u32,
+    /// Is the effect skipped this frame?
+    skip: bool,
+    /// Scale applied to the simulation delta time of the effect this frame.
+    time_scale: f32,
+}
+
+impl OffscreenThrottle {
+    /// Create a new component with the given mode.
+    pub fn new(mode: ThrottleMode) -> Self {
+        Self {
+            mode,
+            skipped_frames: 0,
+            skipped_time: 0.,
+            pending_spawn: 0,
+            skip: false,
+            time_scale: 1.,
+        }
+    }
+
+    /// Simulate the effect once every `interval` frames while off-screen.
+    ///
+    /// See [`ThrottleMode::Throttle`].
+    pub fn throttle(interval: u32) -> Self {
+        Self::new(ThrottleMode::Throttle { interval })
+    }
+
+    /// Pause the effect while off-screen, and fast-forward it when it becomes
+    /// visible again.
+    ///
+    /// See [`ThrottleMode::Pause`].
+    pub fn pause(max_fast_forward: f32) -> Self {
+        Self::new(ThrottleMode::Pause { max_fast_forward })
+    }
+
+    /// Check if the simulation of the effect is skipped this frame.
+    pub fn is_skipped(&self) -> bool {
+        self.skip
+    }
+
+    /// Get the scale applied to the simulation delta time of the effect this
+    /// frame, to catch up on the skipped frames.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Update the throttling state for the current frame, and return the
+    /// number of particles to spawn this frame.
+    fn update(&mut self, offscreen: bool, dt: f32, spawn_count: u32) -> u32 {
+        self.skip = offscreen
+            && match self.mode {
+                ThrottleMode::Throttle { interval } => self.skipped_frames + 1 < interval,
+                ThrottleMode::Pause { .. } => true,
+            };
+
+        if self.skip {
+            self.skipped_frames += 1;
+            self.skipped_time += dt;
+            self.pending_spawn = self.pending_spawn.saturating_add(spawn_count);
+            self.time_scale = 1.;
+            return 0;
+        }
+
+        let catch_up_time = match self.mode {
+            ThrottleMode::Throttle { .. } => self.skipped_time,
+            ThrottleMode::Pause { max_fast_forward } => {
+                self.skipped_time.min(max_fast_forward.max(0.))
+            }
+        };
+        self.time_scale = if dt > 0. {
+            (catch_up_time + dt) / dt
+        } else {
+            1.
+        };
+        // Only spawn the particles of the caught up time, so a long pause doesn't
+        // spawn all the particles of the skipped frames at once.
+        let pending_spawn = if catch_up_time < self.skipped_time {
+            (self.pending_spawn as f32 * (catch_up_time / self.skipped_time)).round() as u32
+        } else {
+            self.pending_spawn
+        };
+        let spawn_count = spawn_count.saturating_add(pending_spawn);
+        self.skipped_frames = 0;
+        self.skipped_time = 0.;
+        self.pending_spawn = 0;
+        spawn_count
+    }
+}
+
+/// Update the throttling of all effect instances with an
+/// [`OffscreenThrottle`], based on their visibility.
+///
+/// This system runs in the [`EffectSystems::TickSpawners`] set, after the
+/// spawn counts were finalized and after Bevy computed the visibility of all
+/// entities for the current frame.
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+pub fn update_offscreen_throttle(
+    time: Res<Time<EffectSimulation>>,
+    mut query: Query<(
+        &mut OffscreenThrottle,
+        Option<&ViewVisibility>,
+        &mut EffectSpawner,
+    )>,
+) {
+    trace!("update_offscreen_throttle");
+
+    let dt = time.delta_seconds();
+    for (mut throttle, maybe_view_visibility, mut spawner) in query.iter_mut() {
+        let offscreen = maybe_view_visibility.is_some_and(|vis| !vis.get());
+        spawner.spawn_count = throttle.update(offscreen, dt, spawner.spawn_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle() {
+        let mut throttle = OffscreenThrottle::throttle(4);
+
+        // On-screen, simulated every frame
+        assert_eq!(throttle.update(false, 0.1, 2), 2);
+        assert!(!throttle.is_skipped());
+        assert_eq!(throttle.time_scale(), 1.);
+
+        // Off-screen, simulated every 4th frame with the spawns of all 4 frames
+        for _ in 0..3 {
+            assert_eq!(throttle.update(true, 0.1, 2), 0);
+            assert!(throttle.is_skipped());
+        }
+        assert_eq!(throttle.update(true, 0.1, 2), 8);
+        assert!(!throttle.is_skipped());
+        assert!((throttle.time_scale() - 4.).abs() < 1e-5);
+
+        // Back on-screen after some skipped frames catches up immediately
+        assert_eq!(throttle.update(true, 0.1, 1), 0);
+        assert_eq!(throttle.update(false, 0.1, 1), 2);
+        assert!((throttle.time_scale() - 2.).abs() < 1e-5);
+        assert_eq!(throttle.update(false, 0.1, 1), 1);
+        assert_eq!(throttle.time_scale(), 1.);
+    }
+
+    #[test]
+    fn pause() {
+        let mut throttle = OffscreenThrottle::pause(0.5);
+        for _ in 0..10 {
+            assert_eq!(throttle.update(true, 0.1, 1), 0);
+            assert!(throttle.is_skipped());
+        }
+
+        // Fast-forward is capped, and so are the spawns of the skipped frames
+        assert_eq!(throttle.update(false, 0.1, 1), 6);
+        assert!(!throttle.is_skipped());
+        assert!((throttle.time_scale() - 6.).abs() < 1e-5);
+
+        // A pause shorter than the cap spawns all the skipped particles
+        for _ in 0..3 {
+            assert_eq!(throttle.update(true, 0.1, 1), 0);
+        }
+        assert_eq!(throttle.update(false, 0.1, 1), 4);
+        assert!((throttle.time_scale() - 4.).abs() < 1e-5);
+
+        // Paused simulation time doesn't produce an invalid time scale
+        assert_eq!(throttle.update(false, 0., 0), 0);
+        assert_eq!(throttle.time_scale(), 1.);
+    }
+}
@@ -0,0 +1,329 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    submit_injected_particles, Attribute, CompiledParticleEffect, EffectInjector, EffectSimulation,
+    EffectSystems, InjectedParticle, Random,
+};
+
+/// Plugin emitting particles from the tiles of a tile grid with a
+/// [`TileEmitter`].
+///
+/// The emitted particles are injected into the effect before the injected
+/// particles are submitted for the frame, so this plugin must be added along
+/// with the [`HanabiPlugin`].
+///
+/// This plugin requires the `tilemap` feature of this crate. It doesn't depend
+/// on any tilemap crate: the [`TileGrid`] describes the tile geometry, and the
+/// application selects the emitting tiles from its own tile data. See
+/// [`TileEmitter`] for an example. There's no bridge reading the tile layers
+/// of `bevy_ecs_tilemap` directly, as that crate has no release for the
+/// version of Bevy this crate depends on.
+///
+/// [`HanabiPlugin`]: crate::HanabiPlugin
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TileEmitterPlugin;
+
+impl Plugin for TileEmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TileEmitter>().add_systems(
+            PostUpdate,
+            emit_from_tiles
+                .in_set(EffectSystems::TickSpawners)
+                .before(submit_injected_particles),
+        );
+    }
+}
+
+/// Layout of the tiles of a tilemap.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum TileGridKind {
+    /// Square tiles, with the X and Y tile coordinates along the X and Y axes.
+    #[default]
+    Square,
+    /// Diamond isometric tiles, with the X tile coordinate going right and
+    /// down, and the Y tile coordinate going right and up.
+    Isometric,
+}
+
+/// Geometry of a tile grid, converting tile coordinates into positions.
+///
+/// The center of the tile `(0, 0)` is at the origin of the tilemap transform,
+/// and [`grid_size`] is the distance between the centers of two adjacent
+/// tiles, or the size of the diamond for isometric grids.
+///
+/// [`grid_size`]: TileGrid::grid_size
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct TileGrid {
+    /// Size of a tile cell, in tilemap units.
+    pub grid_size: Vec2,
+    /// Layout of the tiles.
+    pub kind: TileGridKind,
+}
+
+impl Default for TileGrid {
+    fn default() -> Self {
+        Self {
+            grid_size: Vec2::ONE,
+            kind: TileGridKind::Square,
+        }
+    }
+}
+
+impl TileGrid {
+    /// Create a grid of square tiles.
+    pub fn square(grid_size: Vec2) -> Self {
+        Self {
+            grid_size,
+            kind: TileGridKind::Square,
+        }
+    }
+
+    /// Create a grid of diamond isometric tiles.
+    pub fn isometric(grid_size: Vec2) -> Self {
+        Self {
+            grid_size,
+            kind: TileGridKind::Isometric,
+        }
+    }
+
+    /// Convert a position in tile units into a position in the tilemap.
+    ///
+    /// The integer tile coordinates are the centers of the tiles, so a tile
+    /// spans half a unit around them.
+    pub fn to_tilemap(&self, tile: Vec2) -> Vec2 {
+        let tile = match self.kind {
+            TileGridKind::Square => tile,
+            TileGridKind::Isometric => Vec2::new(tile.x + tile.y, tile.y - tile.x) * 0.5,
+        };
+        tile * self.grid_size
+    }
+
+    /// Get the center of a tile in the tilemap.
+    pub fn tile_center(&self, tile: UVec2) -> Vec2 {
+        self.to_tilemap(tile.as_vec2())
+    }
+}
+
+/// Component emitting particles from the tiles of a tilemap.
+///
+/// Add this component to the same entity as a [`ParticleEffect`] with an
+/// [`EffectInjector`] to spawn particles over some [`tiles`] of a tilemap, for
+/// example mist over all water tiles. The particles are emitted by the
+/// [`TileEmitterPlugin`]. Each tile emits on average [`rate`] particles per
+/// second at a random position inside the tile, which are injected with their
+/// [`Attribute::POSITION`] set; the other attributes are initialized by the
+/// init modifiers of the effect. The effect asset must reserve enough
+/// injection capacity with [`EffectAsset::with_injection_capacity()`].
+///
+/// The tile positions are converted into world positions with the [`grid`]
+/// geometry and the [`GlobalTransform`] of the [`tilemap`] entity, so the
+/// tilemap can move or scale. The tiles are selected by the application,
+/// typically by filtering the tiles of a layer of its map when the map
+/// changes.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// # #[derive(PartialEq)]
+/// # enum Terrain { Grass, Water }
+/// // The terrain of a map, stored by the game as one entry per tile
+/// #[derive(Resource)]
+/// struct TerrainMap {
+///     width: u32,
+///     terrain: Vec<Terrain>,
+/// }
+///
+/// fn update_mist_tiles(map: Res<TerrainMap>, mut emitters: Query<&mut TileEmitter>) {
+///     if !map.is_changed() {
+///         return;
+///     }
+///     for mut emitter in &mut emitters {
+///         emitter.tiles = (0..map.terrain.len() as u32)
+///             .filter(|index| map.terrain[*index as usize] == Terrain::Water)
+///             .map(|index| UVec2::new(index % map.width, index / map.width))
+///             .collect();
+///     }
+/// }
+/// ```
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`tiles`]: TileEmitter::tiles
+/// [`rate`]: TileEmitter::rate
+/// [`grid`]: TileEmitter::grid
+/// [`tilemap`]: TileEmitter::tilemap
+/// [`EffectAsset::with_injection_capacity()`]: crate::EffectAsset::with_injection_capacity
+#[derive(Debug, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct TileEmitter {
+    /// Entity holding the [`GlobalTransform`] of the tilemap.
+    ///
+    /// If the entity doesn't exist, the tilemap is at the world origin.
+    pub tilemap: Entity,
+    /// Geometry of the tilemap.
+    pub grid: TileGrid,
+    /// Coordinates of the tiles emitting particles.
+    pub tiles: Vec<UVec2>,
+    /// Average number of particles emitted by each tile per second.
+    pub rate: f32,
+    /// Fraction of the tile the particles are emitted over, from 0 for the
+    /// tile center only to 1 for the entire tile.
+    pub jitter: f32,
+    /// Fractional number of particles left to emit.
+    accumulated: f32,
+}
+
+impl Default for TileEmitter {
+    fn default() -> Self {
+        Self {
+            tilemap: Entity::PLACEHOLDER,
+            grid: default(),
+            tiles: vec![],
+            rate: 1.,
+            jitter: 1.,
+            accumulated: 0.,
+        }
+    }
+}
+
+impl TileEmitter {
+    /// Create a new emitter over the tiles of the given tilemap.
+    pub fn new(tilemap: Entity, grid: TileGrid) -> Self {
+        Self {
+            tilemap,
+            grid,
+            ..default()
+        }
+    }
+
+    /// Set the average number of particles emitted by each tile per second.
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Set the fraction of the tile the particles are emitted over.
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the coordinates of the tiles emitting particles.
+    pub fn with_tiles(mut self, tiles: impl IntoIterator<Item = UVec2>) -> Self {
+        self.tiles = tiles.into_iter().collect();
+        self
+    }
+}
+
+/// Inject the particles emitted by the [`TileEmitter`]s this frame.
+pub(crate) fn emit_from_tiles(
+    time: Res<Time<EffectSimulation>>,
+    mut rng: ResMut<Random>,
+    tilemaps: Query<&GlobalTransform>,
+    mut emitters: Query<(
+        &mut TileEmitter,
+        &mut EffectInjector,
+        &CompiledParticleEffect,
+        Option<&GlobalTransform>,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    for (mut emitter, mut injector, compiled, transform) in &mut emitters {
+        if emitter.tiles.is_empty() {
+            if emitter.accumulated != 0. {
+                emitter.accumulated = 0.;
+            }
+            continue;
+        }
+
+        let total = (emitter.rate * emitter.tiles.len() as f32).mul_add(dt, emitter.accumulated);
+        let count = total.max(0.).floor();
+        emitter.accumulated = total - count;
+        if count == 0. {
+            continue;
+        }
+
+        // Convert the tilemap positions into the simulation space of the effect
+        let tilemap_to_world = tilemaps
+            .get(emitter.tilemap)
+            .map(GlobalTransform::affine)
+            .unwrap_or_default();
+        let tilemap_to_simulation =
+            compiled.simulation_to_world(transform).inverse() * tilemap_to_world;
+
+        let emitter = &*emitter;
+        injector.inject_batch((0..count as u32).map(|_| {
+            let tile = emitter.tiles[rng.0.gen_range(0..emitter.tiles.len())].as_vec2();
+            let offset = Vec2::new(rng.0.gen::<f32>() - 0.5, rng.0.gen::<f32>() - 0.5);
+            let position = emitter.grid.to_tilemap(tile + offset * emitter.jitter);
+            InjectedParticle::new().with(
+                Attribute::POSITION,
+                tilemap_to_simulation.transform_point3(position.extend(0.)),
+            )
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{spawn::new_rng, submit_injected_particles};
+
+    #[test]
+    fn tile_center() {
+        let square = TileGrid::square(Vec2::new(16., 8.));
+        assert_eq!(square.tile_center(UVec2::new(2, 3)), Vec2::new(32., 24.));
+
+        let isometric = TileGrid::isometric(Vec2::new(32., 16.));
+        assert_eq!(isometric.tile_center(UVec2::ZERO), Vec2::ZERO);
+        assert_eq!(isometric.tile_center(UVec2::new(1, 0)), Vec2::new(16., -8.));
+        assert_eq!(isometric.tile_center(UVec2::new(0, 1)), Vec2::new(16., 8.));
+        assert_eq!(isometric.tile_center(UVec2::new(1, 1)), Vec2::new(32., 0.));
+    }
+
+    #[test]
+    fn emit() {
+        let mut app = App::new();
+        app.init_resource::<Time<EffectSimulation>>()
+            .insert_resource(Random(new_rng()))
+            .add_systems(Update, (emit_from_tiles, submit_injected_particles).chain());
+
+        let tilemap = app
+            .world
+            .spawn(GlobalTransform::from_xyz(100., 0., 0.))
+            .id();
+        let tiles = [UVec2::new(0, 0), UVec2::new(3, 1)];
+        let effect = app
+            .world
+            .spawn((
+                TileEmitter::new(tilemap, TileGrid::square(Vec2::splat(10.)))
+                    .with_rate(3.)
+                    .with_jitter(0.)
+                    .with_tiles(tiles),
+                EffectInjector::default(),
+                CompiledParticleEffect::default(),
+            ))
+            .id();
+
+        // 2 tiles * 3 particles/s * 0.5 s
+        app.world
+            .resource_mut::<Time<EffectSimulation>>()
+            .advance_by(Duration::from_millis(500));
+        app.update();
+        let injector = app.world.get::<EffectInjector>(effect).unwrap();
+        assert_eq!(injector.submitted().len(), 3);
+        for particle in injector.submitted() {
+            let position: Vec3 = (*particle.get(Attribute::POSITION).unwrap())
+                .try_into()
+                .unwrap();
+            assert!(
+                position == Vec3::new(100., 0., 0.) || position == Vec3::new(130., 10., 0.),
+                "{position}"
+            );
+        }
+    }
+}
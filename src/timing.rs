@@ -0,0 +1,174 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticMeasurement, DiagnosticPath, DiagnosticsStore},
+    prelude::*,
+    render::extract_resource::ExtractResource,
+    utils::{HashMap, Instant},
+};
+
+use crate::{render::ReadbackChannel, EffectAsset, ParticleEffect};
+
+/// GPU pass of an effect instance measured by the [`GpuTimingDiagnostics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum GpuTimingPass {
+    /// Compute pass initializing the newly spawned particles.
+    Init,
+    /// Compute pass updating the alive particles.
+    Update,
+    /// Render pass drawing the particles, summed over all views and groups.
+    Render,
+}
+
+impl GpuTimingPass {
+    /// All the measured passes, in execution order.
+    pub const ALL: [GpuTimingPass; 3] = [
+        GpuTimingPass::Init,
+        GpuTimingPass::Update,
+        GpuTimingPass::Render,
+    ];
+
+    /// Get the name of the pass, as used in diagnostic paths.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GpuTimingPass::Init => "init",
+            GpuTimingPass::Update => "update",
+            GpuTimingPass::Render => "render",
+        }
+    }
+}
+
+/// Configuration of the per-effect GPU timing diagnostics.
+///
+/// When enabled, the GPU time spent initializing, updating, and rendering the
+/// particles of each effect instance is measured with timestamp queries, and
+/// reported as a [`Diagnostic`] in the [`DiagnosticsStore`] of Bevy, in
+/// milliseconds. This helps finding which effect is blowing the frame budget,
+/// for example with the `LogDiagnosticsPlugin`.
+///
+/// The diagnostics are registered the first time an effect instance is
+/// measured, under the path returned by [`diagnostic_path()`], of the form
+/// `hanabi/gpu_time/<asset name>/<entity>/<pass>`. Passes which are not
+/// executed in a frame, like the init pass of an effect not spawning any
+/// particle, have no measurement for that frame.
+///
+/// Timing requires both the [`TIMESTAMP_QUERY`] and the
+/// [`TIMESTAMP_QUERY_INSIDE_PASSES`] features of the render device, which are
+/// generally available on native platforms but not on the Web. If they're not
+/// available, no measurement is made. Like other readbacks, the timestamps
+/// are read back asynchronously, so only every few frames are measured.
+/// Writing timestamps between each dispatch and draw call prevents some
+/// driver optimizations, so this is disabled by default.
+///
+/// [`diagnostic_path()`]: crate::GpuTimingDiagnostics::diagnostic_path
+/// [`TIMESTAMP_QUERY`]: bevy::render::render_resource::WgpuFeatures::TIMESTAMP_QUERY
+/// [`TIMESTAMP_QUERY_INSIDE_PASSES`]: bevy::render::render_resource::WgpuFeatures::TIMESTAMP_QUERY_INSIDE_PASSES
+#[derive(Debug, Default, Clone, Copy, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct GpuTimingDiagnostics {
+    /// Enable measuring the GPU time of each effect instance. Defaults to
+    /// `false`.
+    pub enabled: bool,
+}
+
+impl GpuTimingDiagnostics {
+    /// Get the path of the diagnostic measuring a pass of an effect instance.
+    ///
+    /// Any `/` in the asset name is replaced with a `_`, and an unnamed asset
+    /// is named `effect`.
+    pub fn diagnostic_path(
+        asset_name: &str,
+        entity: Entity,
+        pass: GpuTimingPass,
+    ) -> DiagnosticPath {
+        let asset_name = if asset_name.is_empty() {
+            "effect".to_string()
+        } else {
+            asset_name.replace('/', "_")
+        };
+        DiagnosticPath::new(format!(
+            "hanabi/gpu_time/{}/{:?}/{}",
+            asset_name,
+            entity,
+            pass.name()
+        ))
+    }
+}
+
+/// GPU time of a pass of a single effect instance read back from GPU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GpuTimingReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    pub entity: Entity,
+    /// Measured pass.
+    pub pass: GpuTimingPass,
+    /// GPU time of the pass, in milliseconds.
+    pub milliseconds: f64,
+}
+
+/// Add the GPU timing measurements read back from GPU to the
+/// [`DiagnosticsStore`], registering the diagnostics of newly measured effect
+/// instances.
+///
+/// See [`GpuTimingDiagnostics`] for details.
+pub(crate) fn report_gpu_timings(
+    channel: Res<ReadbackChannel<GpuTimingReport>>,
+    assets: Res<Assets<EffectAsset>>,
+    effects: Query<&ParticleEffect>,
+    store: Option<ResMut<DiagnosticsStore>>,
+    mut paths: Local<HashMap<(Entity, GpuTimingPass), DiagnosticPath>>,
+) {
+    let reports = channel.take();
+    let Some(mut store) = store else {
+        return;
+    };
+
+    let now = Instant::now();
+    for report in reports {
+        // The effect may have been despawned since the readback was issued
+        let Ok(effect) = effects.get(report.entity) else {
+            continue;
+        };
+        let path = paths
+            .entry((report.entity, report.pass))
+            .or_insert_with(|| {
+                let asset_name = assets
+                    .get(&effect.handle)
+                    .map(|asset| asset.name.as_str())
+                    .unwrap_or_default();
+                GpuTimingDiagnostics::diagnostic_path(asset_name, report.entity, report.pass)
+            });
+        if store.get(path).is_none() {
+            store.add(Diagnostic::new(path.clone()).with_suffix("ms"));
+        }
+        if let Some(diagnostic) = store.get_mut(path).filter(|diag| diag.is_enabled) {
+            diagnostic.add_measurement(DiagnosticMeasurement {
+                time: now,
+                value: report.milliseconds,
+            });
+        }
+    }
+
+    // Forget about despawned effects
+    paths.retain(|(entity, _), _| effects.contains(*entity));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_path() {
+        let entity = Entity::from_raw(42);
+        assert_eq!(
+            GpuTimingDiagnostics::diagnostic_path("sparks", entity, GpuTimingPass::Update).as_str(),
+            "hanabi/gpu_time/sparks/42v1/update"
+        );
+        assert_eq!(
+            GpuTimingDiagnostics::diagnostic_path("fx/fire", entity, GpuTimingPass::Init).as_str(),
+            "hanabi/gpu_time/fx_fire/42v1/init"
+        );
+        assert_eq!(
+            GpuTimingDiagnostics::diagnostic_path("", entity, GpuTimingPass::Render).as_str(),
+            "hanabi/gpu_time/effect/42v1/render"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use crate::{EffectAsset, Modifier};
+
+/// Component enabling or disabling individual modifiers of an effect instance
+/// at runtime.
+///
+/// Add this component to the same entity as a [`ParticleEffect`] to turn off
+/// some of the init and update modifiers of that instance only, for example to
+/// disable the turbulence of a smoke effect while it's underwater. Modifiers
+/// are designated either by their index in [`EffectAsset::modifiers()`], or by
+/// the name of their type, like `"AccelModifier"`, in which case all modifiers
+/// of that type are affected.
+///
+/// Toggling modifiers requires the [`EffectAsset`] to opt in with
+/// [`EffectAsset::with_modifier_toggles()`]. The code of each init and update
+/// modifier is then guarded by a check of a per-instance bitfield uploaded to
+/// GPU each frame, so toggling a modifier takes effect on the next frame
+/// without compiling any new shader, unlike the disabled modifiers of a
+/// [`LodTier`]. Only the first 32 modifiers of the asset can be toggled;
+/// render modifiers are always enabled. Effects simulated on CPU ignore the
+/// toggles.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn dive(mut query: Query<&mut ModifierToggles>) {
+///     for mut toggles in &mut query {
+///         toggles.set_enabled_by_name("AccelModifier", false);
+///     }
+/// }
+/// ```
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+/// [`EffectAsset::with_modifier_toggles()`]: crate::EffectAsset::with_modifier_toggles
+/// [`LodTier`]: crate::LodTier
+#[derive(Debug, Default, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct ModifierToggles {
+    /// Indices of the modifiers disabled, in [`EffectAsset::modifiers()`].
+    ///
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    disabled_indices: Vec<usize>,
+    /// Type names of the modifiers disabled.
+    disabled_names: Vec<String>,
+}
+
+impl ModifierToggles {
+    /// Create a new set of toggles with all modifiers enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disable the modifier at the given index in
+    /// [`EffectAsset::modifiers()`].
+    ///
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    pub fn with_disabled(mut self, index: usize) -> Self {
+        self.set_enabled(index, false);
+        self
+    }
+
+    /// Disable all the modifiers of the type with the given name.
+    pub fn with_disabled_by_name(mut self, name: impl Into<String>) -> Self {
+        self.set_enabled_by_name(name, false);
+        self
+    }
+
+    /// Enable or disable the modifier at the given index in
+    /// [`EffectAsset::modifiers()`].
+    ///
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.disabled_indices.retain(|i| *i != index);
+        if !enabled {
+            self.disabled_indices.push(index);
+        }
+    }
+
+    /// Enable or disable all the modifiers of the type with the given name.
+    ///
+    /// The name is either the short name of the type, like
+    /// `"AccelModifier"`, or its full type path, like
+    /// `"bevy_hanabi::modifier::accel::AccelModifier"`.
+    pub fn set_enabled_by_name(&mut self, name: impl Into<String>, enabled: bool) {
+        let name = name.into();
+        self.disabled_names.retain(|n| *n != name);
+        if !enabled {
+            self.disabled_names.push(name);
+        }
+    }
+
+    /// Enable all modifiers.
+    pub fn enable_all(&mut self) {
+        self.disabled_indices.clear();
+        self.disabled_names.clear();
+    }
+
+    /// Check if the modifier at the given index in
+    /// [`EffectAsset::modifiers()`] is enabled.
+    ///
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    pub fn is_enabled(&self, index: usize, modifier: &dyn Modifier) -> bool {
+        !self.disabled_indices.contains(&index)
+            && !self.disabled_names.iter().any(|name| {
+                name == modifier.reflect_short_type_path() || name == modifier.reflect_type_path()
+            })
+    }
+
+    /// Get the bitfield of the modifiers of an asset disabled by these
+    /// toggles, as uploaded to GPU.
+    ///
+    /// Bit #i is set if the modifier at index #i in
+    /// [`EffectAsset::modifiers()`] is disabled. This is always zero if the
+    /// asset doesn't allow toggling modifiers.
+    ///
+    /// [`EffectAsset::modifiers()`]: crate::EffectAsset::modifiers
+    pub(crate) fn disabled_mask(&self, asset: &EffectAsset) -> u32 {
+        if !asset.modifier_toggles {
+            return 0;
+        }
+        asset
+            .modifiers()
+            .take(32)
+            .enumerate()
+            .filter(|(index, modifier)| !self.is_enabled(*index, *modifier))
+            .fold(0, |mask, (index, _)| mask | (1 << index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccelModifier, Module, SetAttributeModifier, Spawner};
+
+    #[test]
+    fn disabled_mask() {
+        let mut module = Module::default();
+        let lifetime = module.lit(1.);
+        let accel = module.lit(Vec3::Y);
+        let asset = EffectAsset::new(vec![16], Spawner::once(1.0.into(), true), module)
+            .init(SetAttributeModifier::new(
+                crate::Attribute::LIFETIME,
+                lifetime,
+            ))
+            .update(AccelModifier::new(accel))
+            .update(AccelModifier::new(accel));
+
+        let mut toggles = ModifierToggles::new().with_disabled(0);
+        // Assets must opt in
+        assert_eq!(toggles.disabled_mask(&asset), 0);
+
+        let asset = asset.with_modifier_toggles(true);
+        assert_eq!(toggles.disabled_mask(&asset), 0b001);
+        toggles.set_enabled(0, true);
+        assert_eq!(toggles.disabled_mask(&asset), 0);
+        toggles.set_enabled_by_name("AccelModifier", false);
+        assert_eq!(toggles.disabled_mask(&asset), 0b110);
+        toggles.set_enabled(1, false);
+        toggles.set_enabled_by_name("AccelModifier", true);
+        assert_eq!(toggles.disabled_mask(&asset), 0b010);
+        toggles.enable_all();
+        assert_eq!(toggles.disabled_mask(&asset), 0);
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+
+use crate::{render::ReadbackChannel, CompiledParticleEffect};
+
+/// A single particle of a group tracked by a [`ParticleTracker`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TrackedParticle {
+    /// Index of the particle in the effect instance.
+    ///
+    /// This identifies the particle as long as it's alive; the index of a dead
+    /// particle is reused by the next particles spawned.
+    pub particle_index: u32,
+    /// Position of the particle, in world space.
+    pub position: Vec3,
+    /// Velocity of the particle, in world space, or zero if the effect has no
+    /// [`Attribute::VELOCITY`].
+    ///
+    /// [`Attribute::VELOCITY`]: crate::Attribute::VELOCITY
+    pub velocity: Vec3,
+}
+
+/// Component reading back the position and velocity of the particles of a
+/// small group of an effect instance, for gameplay.
+///
+/// Insert this component on the entity of a [`ParticleEffect`] to follow the
+/// alive particles of one of its groups, for example to attract homing
+/// pickups, apply damage around embers, or move audio emitters along with
+/// sparks. Only the [`Attribute::POSITION`] and [`Attribute::VELOCITY`] of the
+/// particles of that group are gathered on GPU into a small buffer at the end
+/// of each rendered frame, and read back asynchronously, so the cost doesn't
+/// depend on the other groups. Effects without a position attribute can't be
+/// tracked.
+///
+/// At most [`MAX_PARTICLES`] particles are read back; keep the capacity of the
+/// tracked group below this limit, typically by spawning the particles of
+/// interest into a dedicated group. Only one readback per effect is in flight
+/// at any time, so the particles lag a few frames behind the simulation, and
+/// are updated each time a readback completes.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn attract_pickups(trackers: Query<&ParticleTracker>, mut player: Query<&mut Transform>) {
+///     let mut player = player.single_mut();
+///     for tracker in &trackers {
+///         for particle in tracker.particles() {
+///             if particle.position.distance(player.translation) < 1. {
+///                 info!("Picked up particle #{}", particle.particle_index);
+///             }
+///         }
+///     }
+/// }
+/// ```
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`Attribute::POSITION`]: crate::Attribute::POSITION
+/// [`Attribute::VELOCITY`]: crate::Attribute::VELOCITY
+/// [`MAX_PARTICLES`]: ParticleTracker::MAX_PARTICLES
+#[derive(Debug, Default, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct ParticleTracker {
+    /// Index of the group whose particles are tracked.
+    pub group_index: u32,
+    /// Particles read back by the last completed readback.
+    #[reflect(ignore)]
+    particles: Vec<TrackedParticle>,
+}
+
+impl ParticleTracker {
+    /// Maximum number of particles read back per effect instance.
+    pub const MAX_PARTICLES: u32 = 512;
+
+    /// Track the particles of the given group.
+    pub fn new(group_index: u32) -> Self {
+        Self {
+            group_index,
+            particles: vec![],
+        }
+    }
+
+    /// Get the alive particles of the group read back by the last completed
+    /// readback, in no particular order.
+    pub fn particles(&self) -> &[TrackedParticle] {
+        &self.particles
+    }
+}
+
+/// Particles of the tracked group of an effect instance, read back from GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TrackReport {
+    /// Entity holding the [`ParticleEffect`] instance.
+    ///
+    /// [`ParticleEffect`]: crate::ParticleEffect
+    pub entity: Entity,
+    /// Index of the group tracked.
+    pub group_index: u32,
+    /// Particles of the group, in simulation space.
+    pub particles: Vec<TrackedParticle>,
+}
+
+/// Store the particles read back into the [`ParticleTracker`] of their effect
+/// instance, converted into world space.
+pub(crate) fn update_particle_trackers(
+    channel: Res<ReadbackChannel<TrackReport>>,
+    mut trackers: Query<(
+        &mut ParticleTracker,
+        &CompiledParticleEffect,
+        Option<&GlobalTransform>,
+    )>,
+) {
+    for report in channel.take() {
+        // The effect may have been despawned, or its tracker changed, since the
+        // readback was issued
+        let Ok((mut tracker, compiled, transform)) = trackers.get_mut(report.entity) else {
+            continue;
+        };
+        if tracker.group_index != report.group_index {
+            continue;
+        }
+        let simulation_to_world = compiled.simulation_to_world(transform);
+        tracker.particles = report
+            .particles
+            .into_iter()
+            .map(|particle| TrackedParticle {
+                particle_index: particle.particle_index,
+                position: simulation_to_world.transform_point3(particle.position),
+                velocity: simulation_to_world.transform_vector3(particle.velocity),
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulationSpace;
+
+    #[test]
+    fn update_trackers() {
+        let mut app = App::new();
+        app.init_resource::<ReadbackChannel<TrackReport>>()
+            .add_systems(Update, update_particle_trackers);
+
+        let entity = app
+            .world
+            .spawn((
+                ParticleTracker::new(1),
+                CompiledParticleEffect {
+                    simulation_space: SimulationSpace::Local,
+                    ..default()
+                },
+                GlobalTransform::from_xyz(0., 2., 0.),
+            ))
+            .id();
+        let particle = TrackedParticle {
+            particle_index: 3,
+            position: Vec3::X,
+            velocity: Vec3::Z,
+        };
+
+        // Reports of another group are ignored
+        let channel = app.world.resource::<ReadbackChannel<TrackReport>>().clone();
+        channel.send([TrackReport {
+            entity,
+            group_index: 0,
+            particles: vec![particle],
+        }]);
+        app.update();
+        assert!(app
+            .world
+            .get::<ParticleTracker>(entity)
+            .unwrap()
+            .particles()
+            .is_empty());
+
+        channel.send([TrackReport {
+            entity,
+            group_index: 1,
+            particles: vec![particle],
+        }]);
+        app.update();
+        let tracker = app.world.get::<ParticleTracker>(entity).unwrap();
+        assert_eq!(
+            tracker.particles(),
+            &[TrackedParticle {
+                particle_index: 3,
+                position: Vec3::new(1., 2., 0.),
+                velocity: Vec3::Z,
+            }]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use bevy::prelude::*;
+
+use crate::{gradient::Lerp, graph::Value, EffectProperties, EffectSimulation};
+
+/// Easing curve applied to the progress of a [`PropertyTween`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum TweenEasing {
+    /// Constant rate of change.
+    #[default]
+    Linear,
+    /// Quadratic curve, starting slowly and accelerating.
+    QuadraticIn,
+    /// Quadratic curve, starting fast and decelerating.
+    QuadraticOut,
+    /// Quadratic curve, accelerating then decelerating.
+    QuadraticInOut,
+    /// Cubic curve, starting slowly and accelerating.
+    CubicIn,
+    /// Cubic curve, starting fast and decelerating.
+    CubicOut,
+    /// Cubic curve, accelerating then decelerating.
+    CubicInOut,
+    /// Hermite smoothstep curve, like the WGSL `smoothstep()` function.
+    SmoothStep,
+}
+
+impl TweenEasing {
+    /// Apply the easing curve to a linear progress ratio in `[0:1]`.
+    #[allow(clippy::suboptimal_flops)]
+    pub fn ease(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            TweenEasing::Linear => t,
+            TweenEasing::QuadraticIn => t * t,
+            TweenEasing::QuadraticOut => t * (2. - t),
+            TweenEasing::QuadraticInOut => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(2) / 2.
+                }
+            }
+            TweenEasing::CubicIn => t * t * t,
+            TweenEasing::CubicOut => 1. - (1. - t).powi(3),
+            TweenEasing::CubicInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+            TweenEasing::SmoothStep => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// Interpolate between two values of the same type.
+///
+/// Floating-point scalars and vectors are interpolated linearly. Other types
+/// can't be interpolated, and switch from `start` to `end` once `t` reaches
+/// `1.0`.
+fn lerp_value(start: Value, end: Value, t: f32) -> Value {
+    fn lerp<T>(start: Value, end: Value, t: f32) -> Option<Value>
+    where
+        T: Lerp + Into<Value>,
+        Value: TryInto<T>,
+    {
+        let start: T = start.try_into().ok()?;
+        let end: T = end.try_into().ok()?;
+        Some(start.lerp(end, t).into())
+    }
+
+    lerp::<f32>(start, end, t)
+        .or_else(|| lerp::<Vec2>(start, end, t))
+        .or_else(|| lerp::<Vec3>(start, end, t))
+        .or_else(|| lerp::<Vec4>(start, end, t))
+        .unwrap_or(if t < 1. { start } else { end })
+}
+
+/// Animate a property of an effect instance over time.
+///
+/// Add this component to the same entity as the [`EffectProperties`] of an
+/// effect instance to interpolate the value of one of its properties from a
+/// `start` value to an `end` value over some duration, with some
+/// [`TweenEasing`]. The component is automatically removed once the tween
+/// completes, leaving the property at its `end` value. Inserting a new tween
+/// replaces any tween in progress on the same entity.
+///
+/// Floating-point scalar and vector properties are interpolated. Properties of
+/// other types keep their `start` value until the tween completes.
+///
+/// The tween advances with the [`Time<EffectSimulation>`] clock, so pauses and
+/// scales with the simulation of the effects.
+///
+/// # Example
+///
+/// Fade an effect out over 2 seconds by animating an `alpha` property:
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn fade_out(mut commands: Commands, query: Query<Entity, With<ParticleEffect>>) {
+///     for entity in &query {
+///         commands.entity(entity).insert(
+///             PropertyTween::new("alpha", 1_f32, 0_f32, 2.)
+///                 .with_easing(TweenEasing::QuadraticOut),
+///         );
+///     }
+/// }
+/// ```
+///
+/// [`Time<EffectSimulation>`]: crate::EffectSimulation
+#[derive(Debug, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct PropertyTween {
+    /// Name of the animated property.
+    pub name: String,
+    /// Value of the property at the start of the tween.
+    pub start: Value,
+    /// Value of the property at the end of the tween.
+    pub end: Value,
+    /// Duration of the tween, in seconds.
+    pub duration: f32,
+    /// Easing curve applied to the progress of the tween.
+    pub easing: TweenEasing,
+    /// Time elapsed since the start of the tween, in seconds.
+    elapsed: f32,
+}
+
+impl PropertyTween {
+    /// Create a new linear tween of a property.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` and `end` have different types.
+    pub fn new(
+        name: impl Into<String>,
+        start: impl Into<Value>,
+        end: impl Into<Value>,
+        duration: f32,
+    ) -> Self {
+        let name = name.into();
+        let start = start.into();
+        let end = end.into();
+        assert_eq!(
+            start.value_type(),
+            end.value_type(),
+            "Cannot tween property '{}' from a value of type {:?} to a value of type {:?}",
+            name,
+            start.value_type(),
+            end.value_type()
+        );
+        Self {
+            name,
+            start,
+            end,
+            duration: duration.max(0.),
+            easing: TweenEasing::Linear,
+            elapsed: 0.,
+        }
+    }
+
+    /// Set the easing curve of the tween.
+    pub fn with_easing(mut self, easing: TweenEasing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Get the time elapsed since the start of the tween, in seconds.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Get the linear progress of the tween, in `[0:1]`, before easing.
+    pub fn progress(&self) -> f32 {
+        if self.duration > 0. {
+            (self.elapsed / self.duration).min(1.)
+        } else {
+            1.
+        }
+    }
+
+    /// Check if the tween completed.
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.
+    }
+
+    /// Get the current value of the property.
+    pub fn value(&self) -> Value {
+        lerp_value(self.start, self.end, self.easing.ease(self.progress()))
+    }
+
+    /// Advance the tween by `dt` seconds, and return the new property value.
+    fn tick(&mut self, dt: f32) -> Value {
+        self.elapsed += dt.max(0.);
+        self.value()
+    }
+}
+
+/// Advance all [`PropertyTween`] and update the properties they animate.
+///
+/// Tweens whose property is stored with a different type in the
+/// [`EffectProperties`] are removed with a warning. Completed tweens are
+/// removed.
+///
+/// This system runs in the [`EffectSystems::TickSpawners`] set.
+///
+/// [`EffectSystems::TickSpawners`]: crate::EffectSystems::TickSpawners
+pub fn tick_property_tweens(
+    mut commands: Commands,
+    time: Res<Time<EffectSimulation>>,
+    mut query: Query<(Entity, &mut PropertyTween, &mut EffectProperties)>,
+) {
+    trace!("tick_property_tweens");
+
+    let dt = time.delta_seconds();
+    for (entity, mut tween, properties) in query.iter_mut() {
+        if let Some(current) = properties.get_stored(&tween.name) {
+            if current.value_type() != tween.start.value_type() {
+                warn!(
+                    "Cannot tween property '{}' of type {:?} with values of type {:?}.",
+                    tween.name,
+                    current.value_type(),
+                    tween.start.value_type()
+                );
+                commands.entity(entity).remove::<PropertyTween>();
+                continue;
+            }
+        }
+
+        let value = tween.tick(dt);
+        EffectProperties::set_if_changed(properties, &tween.name, value);
+        if tween.is_finished() {
+            commands.entity(entity).remove::<PropertyTween>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing() {
+        for easing in [
+            TweenEasing::Linear,
+            TweenEasing::QuadraticIn,
+            TweenEasing::QuadraticOut,
+            TweenEasing::QuadraticInOut,
+            TweenEasing::CubicIn,
+            TweenEasing::CubicOut,
+            TweenEasing::CubicInOut,
+            TweenEasing::SmoothStep,
+        ] {
+            assert_eq!(easing.ease(0.), 0.);
+            assert_eq!(easing.ease(1.), 1.);
+            assert_eq!(easing.ease(-1.), 0.);
+            assert_eq!(easing.ease(2.), 1.);
+        }
+        assert_eq!(TweenEasing::QuadraticIn.ease(0.5), 0.25);
+        assert_eq!(TweenEasing::QuadraticInOut.ease(0.5), 0.5);
+        assert_eq!(TweenEasing::SmoothStep.ease(0.5), 0.5);
+    }
+
+    #[test]
+    fn tween() {
+        let mut tween = PropertyTween::new("alpha", 1_f32, 0_f32, 2.);
+        assert_eq!(tween.value(), Value::from(1_f32));
+        assert_eq!(tween.tick(0.5), Value::from(0.75_f32));
+        assert!(!tween.is_finished());
+        assert_eq!(tween.tick(2.), Value::from(0_f32));
+        assert!(tween.is_finished());
+
+        let mut tween = PropertyTween::new("wind", Vec3::ZERO, Vec3::X, 1.)
+            .with_easing(TweenEasing::QuadraticIn);
+        assert_eq!(tween.tick(0.5), Value::from(Vec3::new(0.25, 0., 0.)));
+
+        // Non-interpolable types switch at the end
+        let mut tween = PropertyTween::new("count", 1_u32, 5_u32, 1.);
+        assert_eq!(tween.tick(0.9), Value::from(1_u32));
+        assert_eq!(tween.tick(1.), Value::from(5_u32));
+
+        // Zero duration completes immediately
+        let tween = PropertyTween::new("alpha", 1_f32, 0_f32, 0.);
+        assert!(tween.is_finished());
+        assert_eq!(tween.value(), Value::from(0_f32));
+    }
+
+    #[test]
+    #[should_panic]
+    fn tween_type_mismatch() {
+        let _ = PropertyTween::new("alpha", 1_f32, Vec3::ONE, 1.);
+    }
+}
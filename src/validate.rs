@@ -0,0 +1,327 @@
+use bevy::utils::thiserror::Error;
+
+use crate::{
+    graph::{expr::PropertyHandle, Expr},
+    Attribute, CloneModifier, CollideMeshModifier, CollidePlaneModifier, CollideStaticModifier,
+    CollideVoxelModifier, CollisionResponse, EffectAsset, ExprHandle, Modifier, Module,
+    SetAttributeModifier, ToWgslString, ValueType,
+};
+
+/// Severity of an [`EffectDiagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DiagnosticSeverity {
+    /// The effect works, but likely not as intended.
+    Warning,
+    /// The effect is invalid, and fails to compile or produces undefined
+    /// results.
+    Error,
+}
+
+/// Issue found in an [`EffectAsset`] by [`EffectAsset::validate()`].
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum EffectDiagnostic {
+    /// The effect doesn't have any particle group.
+    #[error("The effect doesn't have any particle group.")]
+    NoParticleGroup,
+    /// A particle group has a capacity of zero.
+    #[error("Particle group #{group_index} has a capacity of zero.")]
+    ZeroCapacity {
+        /// Index of the particle group.
+        group_index: u32,
+    },
+    /// A particle group never receives any particle, because no emitter nor
+    /// [`CloneModifier`] spawns into it.
+    #[error("Particle group #{group_index} is unreachable: no emitter nor clone modifier spawns into it.")]
+    UnreachableGroup {
+        /// Index of the particle group.
+        group_index: u32,
+    },
+    /// The particles never die, because the effect doesn't use an attribute
+    /// required to reap them.
+    #[error(
+        "The particles never die because the effect doesn't use the {} attribute.",
+        attribute.name()
+    )]
+    MissingAttribute {
+        /// The missing attribute.
+        attribute: Attribute,
+    },
+    /// A modifier references an expression which doesn't exist in the module
+    /// of the effect.
+    #[error("Modifier {modifier} references an expression {expr:?} which doesn't exist in the module of the effect.")]
+    InvalidExpression {
+        /// Type name of the modifier.
+        modifier: String,
+        /// The invalid expression.
+        expr: ExprHandle,
+    },
+    /// An expression references a property which doesn't exist in the module
+    /// of the effect.
+    #[error("Expression {expr:?} references a property {property:?} which doesn't exist in the module of the effect.")]
+    InvalidProperty {
+        /// The expression referencing the property.
+        expr: ExprHandle,
+        /// The invalid property.
+        property: PropertyHandle,
+    },
+    /// A modifier assigns to an attribute a value of a different type.
+    #[error(
+        "Modifier {modifier} assigns a value of type {} to the {} attribute of type {}.",
+        found.to_wgsl_string(),
+        attribute.name(),
+        attribute.value_type().to_wgsl_string()
+    )]
+    TypeMismatch {
+        /// Type name of the modifier.
+        modifier: String,
+        /// The assigned attribute.
+        attribute: Attribute,
+        /// Type of the assigned value.
+        found: ValueType,
+    },
+}
+
+impl EffectDiagnostic {
+    /// Get the severity of the diagnostic.
+    pub fn severity(&self) -> DiagnosticSeverity {
+        match self {
+            EffectDiagnostic::UnreachableGroup { .. }
+            | EffectDiagnostic::MissingAttribute { .. } => DiagnosticSeverity::Warning,
+            EffectDiagnostic::NoParticleGroup
+            | EffectDiagnostic::ZeroCapacity { .. }
+            | EffectDiagnostic::InvalidExpression { .. }
+            | EffectDiagnostic::InvalidProperty { .. }
+            | EffectDiagnostic::TypeMismatch { .. } => DiagnosticSeverity::Error,
+        }
+    }
+
+    /// Is this diagnostic an error?
+    pub fn is_error(&self) -> bool {
+        self.severity() == DiagnosticSeverity::Error
+    }
+}
+
+/// Get the value type of an expression, if it can be determined without
+/// evaluating the expression.
+fn expr_value_type(module: &Module, expr: &Expr) -> Option<ValueType> {
+    match expr {
+        Expr::Property(expr) => module
+            .get_property(expr.property())
+            .map(|property| property.value_type()),
+        expr => expr.value_type(),
+    }
+}
+
+impl EffectAsset {
+    /// Check the effect for common authoring mistakes.
+    ///
+    /// This returns the list of issues found in the effect, from mistakes
+    /// which make the effect invalid, like a particle group with a zero
+    /// capacity or a modifier referencing an invalid expression, to settings
+    /// which are likely not intended, like particles which never die. An empty
+    /// list means no issue was found.
+    ///
+    /// The validation is conservative, and only reports issues which can be
+    /// detected without compiling the effect. In particular, the type of
+    /// expressions involving operators is not checked.
+    ///
+    /// This is run automatically when an effect is loaded from a `.effect` or
+    /// `.effect_variant` file, and the issues found are logged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use bevy_hanabi::*;
+    /// let asset = EffectAsset::new(vec![0], Spawner::rate(5.0.into()), Module::default());
+    /// let diagnostics = asset.validate();
+    /// assert!(diagnostics.contains(&EffectDiagnostic::ZeroCapacity { group_index: 0 }));
+    /// ```
+    pub fn validate(&self) -> Vec<EffectDiagnostic> {
+        let mut diagnostics = vec![];
+        let module = self.module();
+
+        // Particle groups
+        if self.capacities().is_empty() {
+            diagnostics.push(EffectDiagnostic::NoParticleGroup);
+        }
+        for (group_index, capacity) in self.capacities().iter().enumerate() {
+            if *capacity == 0 {
+                diagnostics.push(EffectDiagnostic::ZeroCapacity {
+                    group_index: group_index as u32,
+                });
+            }
+        }
+        for group_index in 1..self.capacities().len() as u32 {
+            let emitted = self
+                .emitters()
+                .iter()
+                .any(|emitter| emitter.group_index == group_index);
+            let cloned = self
+                .modifiers()
+                .filter_map(|m| m.as_any().downcast_ref::<CloneModifier>())
+                .any(|clone| {
+                    let destination = clone
+                        .destination_group_name
+                        .as_deref()
+                        .and_then(|name| self.group_index(name))
+                        .unwrap_or(clone.destination_group);
+                    destination == group_index
+                });
+            let collided = self
+                .modifiers()
+                .filter_map(collision_response)
+                .filter_map(|response| response.sub_emitter)
+                .any(|sub_emitter| sub_emitter.destination_group == group_index);
+            if !emitted && !cloned && !collided {
+                diagnostics.push(EffectDiagnostic::UnreachableGroup { group_index });
+            }
+        }
+
+        // Reaping, which requires both the age and lifetime of the particles
+        let layout = self.particle_layout();
+        for attribute in [Attribute::AGE, Attribute::LIFETIME] {
+            if !layout.contains(attribute) {
+                diagnostics.push(EffectDiagnostic::MissingAttribute { attribute });
+            }
+        }
+
+        // Expressions
+        for modifier in self.modifiers() {
+            let Some(set_attr) = modifier.as_any().downcast_ref::<SetAttributeModifier>() else {
+                continue;
+            };
+            let modifier = modifier.reflect_short_type_path().to_string();
+            match module.get(set_attr.value) {
+                None => diagnostics.push(EffectDiagnostic::InvalidExpression {
+                    modifier,
+                    expr: set_attr.value,
+                }),
+                Some(expr) => {
+                    if let Some(found) = expr_value_type(module, expr) {
+                        if found != set_attr.attribute.value_type() {
+                            diagnostics.push(EffectDiagnostic::TypeMismatch {
+                                modifier,
+                                attribute: set_attr.attribute,
+                                found,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        for (handle, expr) in module.iter() {
+            if let Expr::Property(expr) = expr {
+                if module.get_property(expr.property()).is_none() {
+                    diagnostics.push(EffectDiagnostic::InvalidProperty {
+                        expr: handle,
+                        property: expr.property(),
+                    });
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// Get the response of a collision modifier, or `None` if the modifier doesn't
+/// handle collisions.
+fn collision_response(modifier: &dyn Modifier) -> Option<&CollisionResponse> {
+    let any = modifier.as_any();
+    any.downcast_ref::<CollidePlaneModifier>()
+        .map(|m| &m.response)
+        .or_else(|| {
+            any.downcast_ref::<CollideStaticModifier>()
+                .map(|m| &m.response)
+        })
+        .or_else(|| {
+            any.downcast_ref::<CollideVoxelModifier>()
+                .map(|m| &m.response)
+        })
+        .or_else(|| {
+            any.downcast_ref::<CollideMeshModifier>()
+                .map(|m| &m.response)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::{CollisionSubEmitter, ParticleGroupSet, Spawner};
+
+    #[test]
+    fn validate() {
+        let mut module = Module::default();
+        let age = module.lit(0.);
+        let lifetime = module.lit(2.);
+        let asset = EffectAsset::new(vec![32], Spawner::rate(5.0.into()), module.clone())
+            .init(SetAttributeModifier::new(Attribute::AGE, age))
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime));
+        assert!(asset.validate().is_empty());
+
+        // Missing lifetime
+        let asset = EffectAsset::new(vec![32], Spawner::rate(5.0.into()), module.clone())
+            .init(SetAttributeModifier::new(Attribute::AGE, age));
+        assert_eq!(
+            asset.validate(),
+            vec![EffectDiagnostic::MissingAttribute {
+                attribute: Attribute::LIFETIME
+            }]
+        );
+        assert_eq!(asset.validate()[0].severity(), DiagnosticSeverity::Warning);
+
+        // Zero capacity and unreachable group
+        let asset = EffectAsset::new(vec![32, 0, 16], Spawner::rate(5.0.into()), module.clone())
+            .with_emitter(1, Spawner::rate(5.0.into()))
+            .init_groups(
+                SetAttributeModifier::new(Attribute::AGE, age),
+                ParticleGroupSet::all(),
+            )
+            .init_groups(
+                SetAttributeModifier::new(Attribute::LIFETIME, lifetime),
+                ParticleGroupSet::all(),
+            );
+        assert_eq!(
+            asset.validate(),
+            vec![
+                EffectDiagnostic::ZeroCapacity { group_index: 1 },
+                EffectDiagnostic::UnreachableGroup { group_index: 2 },
+            ]
+        );
+
+        // Group only reached by a collision sub-emitter
+        let asset = EffectAsset::new(vec![32, 16], Spawner::rate(5.0.into()), module.clone())
+            .init_groups(
+                SetAttributeModifier::new(Attribute::AGE, age),
+                ParticleGroupSet::all(),
+            )
+            .init_groups(
+                SetAttributeModifier::new(Attribute::LIFETIME, lifetime),
+                ParticleGroupSet::all(),
+            )
+            .update_groups(
+                CollideStaticModifier::new().with_response(
+                    CollisionResponse::new().with_sub_emitter(CollisionSubEmitter::new(1, 4)),
+                ),
+                ParticleGroupSet::single(0),
+            );
+        assert!(asset.validate().is_empty());
+
+        // Type mismatch
+        let mut module = Module::default();
+        let age = module.lit(0.);
+        let lifetime = module.lit(Vec3::ONE);
+        let asset = EffectAsset::new(vec![32], Spawner::rate(5.0.into()), module)
+            .init(SetAttributeModifier::new(Attribute::AGE, age))
+            .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime));
+        let diagnostics = asset.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "Modifier SetAttributeModifier assigns a value of type vec3<f32> to the lifetime attribute of type f32."
+        );
+    }
+}